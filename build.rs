@@ -0,0 +1,15 @@
+fn main() {
+    // protox compiles the protos in Rust, so building does not need protoc installed
+    #[cfg(feature = "protobuf")]
+    {
+        println!("cargo:rerun-if-changed=proto/messages.proto");
+        let descriptors = protox::compile(["proto/messages.proto"], ["proto"]).expect("valid proto definitions");
+        prost_build::Config::new().compile_fds(descriptors).expect("generated message code");
+    }
+    #[cfg(feature = "service")]
+    {
+        println!("cargo:rerun-if-changed=proto/chain_service.proto");
+        let descriptors = protox::compile(["proto/chain_service.proto"], ["proto"]).expect("valid proto definitions");
+        tonic_prost_build::configure().build_client(false).compile_fds(descriptors).expect("generated service code");
+    }
+}
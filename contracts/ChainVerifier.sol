@@ -0,0 +1,47 @@
+// SPDX-License-Identifier: MIT
+pragma solidity ^0.8.20;
+
+/// Reference on-chain verifier for SHA-256 hash chains from fractal-hash-traversal.
+///
+/// A chain is enrolled by its anchor; each redemption discloses a later value, which must hash
+/// forward to the last accepted one. Calldata for both functions is built by the crate's `evm`
+/// module, and the redemption id it computes matches the one emitted here.
+contract ChainVerifier {
+    struct Record {
+        uint64 index;
+        bytes32 value;
+    }
+
+    mapping(bytes16 => Record) public records;
+    mapping(bytes16 => address) public owners;
+    /// The most positions one redemption may advance, bounding its gas cost.
+    uint64 public immutable maxGap;
+
+    event Enrolled(bytes16 indexed chainId, bytes32 anchor);
+    event Redeemed(bytes16 indexed chainId, bytes32 indexed redemptionId, uint64 index);
+
+    constructor(uint64 maxGap_) {
+        maxGap = maxGap_;
+    }
+
+    function enroll(bytes16 chainId, bytes32 anchor) external {
+        require(owners[chainId] == address(0), "already enrolled");
+        owners[chainId] = msg.sender;
+        records[chainId] = Record(0, anchor);
+        emit Enrolled(chainId, anchor);
+    }
+
+    function redeem(bytes16 chainId, uint64 index, bytes32 value) external {
+        require(owners[chainId] != address(0), "unknown chain");
+        Record memory record = records[chainId];
+        require(index > record.index, "replay");
+        require(index - record.index <= maxGap, "gap too large");
+        bytes32 walked = value;
+        for (uint64 i = record.index; i < index; i++) {
+            walked = sha256(abi.encodePacked(walked));
+        }
+        require(walked == record.value, "mismatch");
+        records[chainId] = Record(index, value);
+        emit Redeemed(chainId, keccak256(abi.encodePacked(chainId, index, value)), index);
+    }
+}
@@ -0,0 +1,180 @@
+//! Acknowledgment trails: the verifier keeps a hash chain of its own and binds every disclosure
+//! it accepts into an append-only [`chainlog`](crate::chainlog) on it, so the prover ends up
+//! with an ordered, tamper-evident record of what was acknowledged, e.g. to settle a dispute
+//! over metered usage or micropayments.
+//!
+//! Each [`AckChain::acknowledge`] appends a record whose data is the chain id (16 bytes), the
+//! index as u64 big endian and the accepted value, and hands it to the prover. An
+//! [`AckAuditor`] knowing only the anchor of the verifier's chain checks the records in order
+//! and yields each acknowledged disclosure once the record is authenticated, which is when the
+//! next record arrives or the verifier seals the trail. Unlike a signed receipt
+//! per disclosure the trail needs no signature scheme, and it shows dropped or reordered
+//! acknowledgments, not just forged ones.
+
+use crate::chainlog::{ChainLogError, LogAuditor, LogWriter, Record, Seal};
+use crate::{ChainId, HashChain};
+use digest::core_api::BlockSizeUser;
+use digest::generic_array::GenericArray;
+use digest::{Digest, FixedOutputReset, OutputSizeUser};
+use std::error::Error;
+use std::fmt::{self, Display};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum AckError {
+    /// The trail does not verify.
+    Log(ChainLogError),
+    /// The record's data is not an acknowledgment.
+    Malformed { index: u64 },
+}
+
+impl Display for AckError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            AckError::Log(error) => write!(f, "{}", error),
+            AckError::Malformed { index } => write!(f, "record {} is not an acknowledgment", index),
+        }
+    }
+}
+
+impl Error for AckError {}
+
+impl From<ChainLogError> for AckError {
+    fn from(error: ChainLogError) -> Self {
+        AckError::Log(error)
+    }
+}
+
+/// A disclosure the verifier acknowledged.
+pub struct Acknowledgment<H: OutputSizeUser> {
+    /// The position of its record in the trail, from 1.
+    pub position: u64,
+    pub chain_id: ChainId,
+    pub index: u64,
+    pub value: GenericArray<u8, H::OutputSize>,
+}
+
+// manual impls so that `H` itself need not be `Clone`/`PartialEq`/`Debug`
+impl<H: OutputSizeUser> Clone for Acknowledgment<H> {
+    fn clone(&self) -> Self {
+        Acknowledgment { position: self.position, chain_id: self.chain_id, index: self.index, value: self.value.clone() }
+    }
+}
+
+impl<H: OutputSizeUser> PartialEq for Acknowledgment<H> {
+    fn eq(&self, other: &Self) -> bool {
+        (self.position, self.chain_id, self.index) == (other.position, other.chain_id, other.index) && self.value == other.value
+    }
+}
+
+impl<H: OutputSizeUser> Eq for Acknowledgment<H> {}
+
+impl<H: OutputSizeUser> fmt::Debug for Acknowledgment<H> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Acknowledgment {{position: {}, chain_id: {}, index: {}, value: {}}}", self.position, self.chain_id, self.index, hex::encode(self.value.as_slice()))
+    }
+}
+
+fn ack_data(chain_id: &ChainId, index: u64, value: &[u8]) -> Vec<u8> {
+    let mut data = Vec::with_capacity(24 + value.len());
+    data.extend_from_slice(&chain_id.0);
+    data.extend_from_slice(&index.to_be_bytes());
+    data.extend_from_slice(value);
+    data
+}
+
+fn parse_ack<H: OutputSizeUser>(record: &Record<H>) -> Result<Acknowledgment<H>, AckError> {
+    let data = &record.data;
+    if data.len() != 24 + H::output_size() {
+        return Err(AckError::Malformed { index: record.index });
+    }
+    Ok(Acknowledgment {
+        position: record.index,
+        chain_id: ChainId(data[..16].try_into().expect("16 bytes")),
+        index: u64::from_be_bytes(data[16..24].try_into().expect("8 bytes")),
+        value: GenericArray::clone_from_slice(&data[24..]),
+    })
+}
+
+/// The verifier's side.
+pub struct AckChain<H: Digest + FixedOutputReset> {
+    log: LogWriter<H>,
+}
+
+impl<H: Digest + FixedOutputReset + BlockSizeUser> AckChain<H> {
+    pub fn new(chain: HashChain<H>) -> Self {
+        AckChain { log: LogWriter::new(chain) }
+    }
+
+    /// The anchor auditors start from.
+    pub fn anchor(&self) -> &GenericArray<u8, H::OutputSize> {
+        self.log.anchor()
+    }
+
+    /// Bind the accepted `value` at `index` of `chain_id` into the trail. Call it only after the
+    /// disclosure verified, and hand the record to the prover.
+    pub fn acknowledge(&mut self, chain_id: &ChainId, index: u64, value: &[u8]) -> Result<Record<H>, ChainLogError> {
+        self.log.append(&ack_data(chain_id, index, value))
+    }
+
+    /// Disclose the value authenticating the last record, e.g. when a settlement period ends.
+    pub fn seal(&self) -> Seal<H> {
+        self.log.seal()
+    }
+}
+
+/// The prover's side, checking the trail it was handed.
+pub struct AckAuditor<H: OutputSizeUser> {
+    log: LogAuditor<H>,
+    /// The last record, acknowledged once it is authenticated.
+    pending: Option<Acknowledgment<H>>,
+}
+
+impl<H: Digest + BlockSizeUser> AckAuditor<H> {
+    pub fn new(anchor: GenericArray<u8, H::OutputSize>) -> Self {
+        AckAuditor { log: LogAuditor::new(anchor), pending: None }
+    }
+
+    /// Take the next record, returning the acknowledgment of the one before it, now
+    /// authenticated.
+    pub fn audit(&mut self, record: &Record<H>) -> Result<Option<Acknowledgment<H>>, AckError> {
+        let acknowledgment = parse_ack(record)?;
+        self.log.audit(record)?;
+        Ok(self.pending.replace(acknowledgment))
+    }
+
+    /// Authenticate the last record from the verifier's seal, returning its acknowledgment.
+    pub fn seal(&mut self, seal: &Seal<H>) -> Result<Option<Acknowledgment<H>>, AckError> {
+        self.log.seal(seal)?;
+        Ok(self.pending.take())
+    }
+}
+
+#[test]
+fn test_acknowledgment_trail() {
+    use crate::registry::Registry;
+    use crate::store::MemoryStore;
+    use sha2::Sha256;
+
+    let registry = Registry::<Sha256, _>::new(MemoryStore::new());
+    let mut meter = HashChain::<Sha256>::new(16, 1).unwrap();
+    let id = registry.enroll(*meter.anchor(), 16).unwrap();
+    let mut acks = AckChain::new(HashChain::<Sha256>::new(16, 2).unwrap());
+    let mut records = Vec::new();
+    for (index, value) in meter.by_ref().take(3) {
+        registry.verify(&id, index, &value).unwrap();
+        records.push(acks.acknowledge(&id, index, &value).unwrap());
+    }
+
+    let mut auditor = AckAuditor::<Sha256>::new(*acks.anchor());
+    let mut acknowledged = Vec::new();
+    for record in &records {
+        acknowledged.extend(auditor.audit(record).unwrap());
+    }
+    acknowledged.extend(auditor.seal(&acks.seal()).unwrap());
+    assert_eq!(acknowledged.iter().map(|ack| (ack.position, ack.chain_id, ack.index)).collect::<Vec<_>>(), [(1, id, 1), (2, id, 2), (3, id, 3)]);
+
+    // the verifier cannot quietly drop an acknowledgment from the trail
+    let mut auditor = AckAuditor::<Sha256>::new(*acks.anchor());
+    auditor.audit(&records[0]).unwrap();
+    assert_eq!(auditor.audit(&records[2]), Err(AckError::Log(ChainLogError::OutOfOrder { expected: 2 })));
+}
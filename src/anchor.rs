@@ -0,0 +1,264 @@
+//! Anchor commitments: what a verifier needs to know about a chain before accepting values from
+//! it. Besides the anchor itself that is the chain length, the hash function, so a value cannot
+//! be checked with the wrong one, and the period in which the chain may be used.
+//!
+//! Commitments are exchanged in their canonical binary encoding or, to sit alongside X.509
+//! material in PEM bundles, armored as `HASH CHAIN ANCHOR` PEM blocks. In config files and on
+//! command lines they are written as base64 of the binary encoding, and parsed from that, hex or
+//! PEM.
+
+use crate::{ChainId, HashChain};
+use base64::engine::general_purpose::STANDARD;
+use base64::Engine;
+use digest::{Digest, FixedOutputReset};
+use std::error::Error;
+use std::fmt::{self, Display};
+use std::str::FromStr;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+#[derive(Debug)]
+pub struct CommitmentFormatError {
+    details: String,
+}
+
+impl CommitmentFormatError {
+    fn new(error_message: &str) -> CommitmentFormatError {
+        CommitmentFormatError { details: error_message.to_string() }
+    }
+}
+
+impl Display for CommitmentFormatError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.details)
+    }
+}
+
+impl Error for CommitmentFormatError {}
+
+const PEM_LABEL: &str = "HASH CHAIN ANCHOR";
+
+/// Armor `bytes` as a PEM block with `label`.
+pub(crate) fn to_pem(label: &str, bytes: &[u8]) -> String {
+    let encoded = STANDARD.encode(bytes);
+    let mut pem = format!("-----BEGIN {}-----\n", label);
+    for line in encoded.as_bytes().chunks(64) {
+        pem.push_str(std::str::from_utf8(line).expect("base64 is ASCII"));
+        pem.push('\n');
+    }
+    pem.push_str(&format!("-----END {}-----\n", label));
+    pem
+}
+
+/// The contents of the first PEM block with `label` in `text`, which may hold other blocks.
+pub(crate) fn from_pem(label: &str, text: &str) -> Result<Vec<u8>, CommitmentFormatError> {
+    let begin = format!("-----BEGIN {}-----", label);
+    let end = format!("-----END {}-----", label);
+    let start = text.find(&begin).ok_or_else(|| CommitmentFormatError::new("no PEM block with the expected label"))? + begin.len();
+    let stop = text[start..].find(&end).ok_or_else(|| CommitmentFormatError::new("unterminated PEM block"))? + start;
+    let encoded: String = text[start..stop].split_whitespace().collect();
+    STANDARD.decode(encoded).map_err(|_| CommitmentFormatError::new("invalid base64 in PEM block"))
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct AnchorCommitment {
+    pub anchor: Vec<u8>,
+    pub length: u64,
+    /// The hash function's name, e.g. `"sha256"`.
+    pub hash: String,
+    /// The validity window in seconds since the Unix epoch, both ends inclusive.
+    pub valid_from: u64,
+    pub valid_until: u64,
+    /// The digest of the chain's [kill value](crate::retire), if it can be retired early.
+    pub kill_commitment: Option<Vec<u8>>,
+    /// The chain's [personalization](HashChain::new_personalized), if it has one.
+    pub personalization: Option<Vec<u8>>,
+    /// The crypto module the chain was set up with, if recorded for audits, e.g. the
+    /// [`FipsProvider::name`](crate::fips::FipsProvider::name) in FIPS mode.
+    pub provider: Option<String>,
+}
+
+impl AnchorCommitment {
+    /// The commitment to `chain`, hashed with `hash`, for the given validity window.
+    pub fn for_chain<H: Digest + FixedOutputReset>(chain: &HashChain<H>, hash: &str, valid_from: u64, valid_until: u64) -> Self {
+        let personalization = (!chain.personalization().is_empty()).then(|| chain.personalization().to_vec());
+        AnchorCommitment { anchor: chain.anchor().to_vec(), length: chain.length(), hash: hash.to_string(), valid_from, valid_until, kill_commitment: None, personalization, provider: None }
+    }
+
+    /// Bind the kill value of the chain set up from `seed`, so it can be retired early.
+    pub fn with_kill_value<H: Digest>(self, seed: u64) -> Self {
+        let kill_commitment = crate::retire::kill_commitment::<H>(&crate::retire::kill_value::<H>(seed)).to_vec();
+        AnchorCommitment { kill_commitment: Some(kill_commitment), ..self }
+    }
+
+    /// Record the crypto module the chain was set up with.
+    pub fn with_provider(self, provider: &str) -> Self {
+        AnchorCommitment { provider: Some(provider.to_string()), ..self }
+    }
+
+    /// The identifier of the committed chain, the same as [`HashChain::chain_id`] gives.
+    pub fn chain_id(&self) -> ChainId {
+        ChainId::derive(&self.anchor, self.length)
+    }
+
+    /// Check that `value` is the committed chain's value at `index`, mixing in the
+    /// personalization as the chain did.
+    pub fn verify<H: Digest + FixedOutputReset>(&self, index: u64, value: &[u8]) -> bool {
+        let personalization = self.personalization.as_deref().unwrap_or_default();
+        let n = <H as Digest>::output_size();
+        self.anchor.len() == n && value.len() == n && index <= self.length
+            && crate::verify_personalized::<H>(personalization, 0, digest::generic_array::GenericArray::from_slice(&self.anchor), index, digest::generic_array::GenericArray::from_slice(value))
+    }
+
+    pub fn is_valid_at(&self, time: SystemTime) -> bool {
+        let seconds = time.duration_since(UNIX_EPOCH).map_or(0, |since| since.as_secs());
+        (self.valid_from..=self.valid_until).contains(&seconds)
+    }
+
+    /// The canonical encoding: the hash name and the anchor, each after a length byte, then
+    /// the chain length and the validity window as u64 big endian, then the kill value's digest,
+    /// the personalization and the provider, each after a length byte if there is one, with a
+    /// zero length byte standing in for a missing one before a present one. The name, the
+    /// anchor, the digest, the personalization and the provider must be shorter than 256 bytes.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = Vec::with_capacity(26 + self.hash.len() + self.anchor.len());
+        bytes.push(self.hash.len() as u8);
+        bytes.extend_from_slice(self.hash.as_bytes());
+        bytes.push(self.anchor.len() as u8);
+        bytes.extend_from_slice(&self.anchor);
+        bytes.extend_from_slice(&self.length.to_be_bytes());
+        bytes.extend_from_slice(&self.valid_from.to_be_bytes());
+        bytes.extend_from_slice(&self.valid_until.to_be_bytes());
+        let trailers = [self.kill_commitment.as_deref(), self.personalization.as_deref(), self.provider.as_deref().map(str::as_bytes)];
+        let written = trailers.iter().rposition(Option::is_some).map_or(0, |last| last + 1);
+        for trailer in &trailers[..written] {
+            let trailer = trailer.unwrap_or_default();
+            bytes.push(trailer.len() as u8);
+            bytes.extend_from_slice(trailer);
+        }
+        bytes
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, CommitmentFormatError> {
+        let truncated = || CommitmentFormatError::new("anchor commitment truncated");
+        let (&hash_len, rest) = bytes.split_first().ok_or_else(truncated)?;
+        let (hash, rest) = rest.split_at_checked(hash_len as usize).ok_or_else(truncated)?;
+        let hash = std::str::from_utf8(hash).map_err(|_| CommitmentFormatError::new("hash name is not UTF-8"))?;
+        let (&anchor_len, rest) = rest.split_first().ok_or_else(truncated)?;
+        let (anchor, rest) = rest.split_at_checked(anchor_len as usize).ok_or_else(truncated)?;
+        let (fields, rest) = rest.split_at_checked(24).ok_or_else(truncated)?;
+        let trailer = |rest: &mut &[u8]| match rest.split_first() {
+            None => Ok(None),
+            Some((&len, tail)) => {
+                let (field, tail) = tail.split_at_checked(len as usize).ok_or_else(|| CommitmentFormatError::new("wrong length for an anchor commitment"))?;
+                *rest = tail;
+                Ok((len > 0).then(|| field.to_vec()))
+            }
+        };
+        let (mut rest, trailed) = (rest, !rest.is_empty());
+        let kill_commitment = trailer(&mut rest)?;
+        let personalization = trailer(&mut rest)?;
+        let provider = trailer(&mut rest)?.map(String::from_utf8).transpose().map_err(|_| CommitmentFormatError::new("provider name is not UTF-8"))?;
+        // a missing field is only written out ahead of a present one
+        if !rest.is_empty() || (trailed && kill_commitment.is_none() && personalization.is_none() && provider.is_none()) {
+            return Err(CommitmentFormatError::new("wrong length for an anchor commitment"));
+        }
+        let field = |i: usize| u64::from_be_bytes(fields[8 * i..8 * i + 8].try_into().expect("8 bytes"));
+        Ok(AnchorCommitment { anchor: anchor.to_vec(), length: field(0), hash: hash.to_string(), valid_from: field(1), valid_until: field(2), kill_commitment, personalization, provider })
+    }
+
+    /// The canonical encoding as a `HASH CHAIN ANCHOR` PEM block.
+    pub fn to_pem(&self) -> String {
+        to_pem(PEM_LABEL, &self.to_bytes())
+    }
+
+    /// Read the first `HASH CHAIN ANCHOR` block in `text`.
+    pub fn from_pem(text: &str) -> Result<Self, CommitmentFormatError> {
+        Self::from_bytes(&from_pem(PEM_LABEL, text)?)
+    }
+}
+
+/// Standard base64 of the canonical encoding.
+impl Display for AnchorCommitment {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&STANDARD.encode(self.to_bytes()))
+    }
+}
+
+/// A `HASH CHAIN ANCHOR` PEM block, or the canonical encoding in base64 or hex.
+impl FromStr for AnchorCommitment {
+    type Err = CommitmentFormatError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let text = s.trim();
+        if text.starts_with("-----BEGIN") {
+            return Self::from_pem(text);
+        }
+        // hex digits are base64 characters too, so fall back to base64 when hex does not parse
+        let hex = text.strip_prefix("0x").unwrap_or(text);
+        if let Some(commitment) = hex::decode(hex).ok().and_then(|bytes| Self::from_bytes(&bytes).ok()) {
+            return Ok(commitment);
+        }
+        let bytes = STANDARD.decode(text).map_err(|_| CommitmentFormatError::new("expected an anchor commitment as a PEM block, base64 or hex"))?;
+        Self::from_bytes(&bytes)
+    }
+}
+
+impl crate::version::Versioned for AnchorCommitment {
+    type Error = CommitmentFormatError;
+
+    fn encode_body(&self, _version: u8) -> Vec<u8> {
+        self.to_bytes()
+    }
+
+    fn decode_body(_version: u8, body: &[u8]) -> Result<Self, CommitmentFormatError> {
+        Self::from_bytes(body)
+    }
+}
+
+#[test]
+fn test_anchor_commitment_roundtrip() {
+    use sha2::Sha256;
+    use std::time::Duration;
+
+    let chain = HashChain::<Sha256>::new(64, 1).unwrap();
+    let commitment = AnchorCommitment::for_chain(&chain, "sha256", 1_000, 2_000);
+    assert_eq!(AnchorCommitment::from_bytes(&commitment.to_bytes()).unwrap(), commitment);
+    assert_eq!(commitment.chain_id(), chain.chain_id());
+    assert!(AnchorCommitment::from_bytes(&commitment.to_bytes()[1..]).is_err());
+    let bundle = format!("-----BEGIN CERTIFICATE-----\nAAAA\n-----END CERTIFICATE-----\n{}", commitment.to_pem());
+    assert_eq!(AnchorCommitment::from_pem(&bundle).unwrap(), commitment);
+    assert!(commitment.is_valid_at(UNIX_EPOCH + Duration::from_secs(2_000)));
+    assert!(!commitment.is_valid_at(UNIX_EPOCH + Duration::from_secs(2_001)));
+    let retirable = commitment.clone().with_kill_value::<Sha256>(1);
+    assert_eq!(AnchorCommitment::from_bytes(&retirable.to_bytes()).unwrap(), retirable);
+    assert!(AnchorCommitment::from_bytes(&retirable.to_bytes()[..retirable.to_bytes().len() - 1]).is_err());
+
+    let mut personalized = HashChain::<Sha256>::new_personalized(64, 1, b"acme-meterd v2").unwrap();
+    let commitment = AnchorCommitment::for_chain(&personalized, "sha256", 1_000, 2_000);
+    assert_eq!(commitment.personalization.as_deref(), Some(&b"acme-meterd v2"[..]));
+    assert_eq!(AnchorCommitment::from_bytes(&commitment.to_bytes()).unwrap(), commitment);
+    let retirable = commitment.clone().with_kill_value::<Sha256>(1);
+    assert_eq!(AnchorCommitment::from_bytes(&retirable.to_bytes()).unwrap(), retirable);
+    let (index, value) = personalized.nth(2).unwrap();
+    assert!(commitment.verify::<Sha256>(index, &value));
+    assert!(!AnchorCommitment { personalization: Some(b"acme-meterd v3".to_vec()), ..commitment.clone() }.verify::<Sha256>(index, &value));
+
+    // a recorded provider follows zero lengths standing in for the fields before it
+    let recorded = AnchorCommitment::for_chain(&chain, "sha256", 1_000, 2_000).with_provider("openssl-fips");
+    let bytes = recorded.to_bytes();
+    assert_eq!(bytes[bytes.len() - 15..], *b"\0\0\x0copenssl-fips");
+    assert_eq!(AnchorCommitment::from_bytes(&bytes).unwrap(), recorded);
+}
+
+#[test]
+fn test_anchor_commitment_from_str() {
+    use sha2::Sha256;
+
+    let commitment = AnchorCommitment::for_chain(&HashChain::<Sha256>::new(16, 2).unwrap(), "sha256", 0, 10).with_kill_value::<Sha256>(2);
+    assert_eq!(commitment.to_string().parse::<AnchorCommitment>().unwrap(), commitment);
+    assert_eq!(hex::encode(commitment.to_bytes()).parse::<AnchorCommitment>().unwrap(), commitment);
+    assert_eq!(commitment.to_pem().parse::<AnchorCommitment>().unwrap(), commitment);
+    let error = "not a commitment!".parse::<AnchorCommitment>().unwrap_err();
+    assert_eq!(error.to_string(), "expected an anchor commitment as a PEM block, base64 or hex");
+    assert_eq!("AAAA".parse::<AnchorCommitment>().unwrap_err().to_string(), "anchor commitment truncated");
+}
@@ -0,0 +1,105 @@
+//! Chains whose values are plain `[u8; N]` arrays, for code that holds disclosed values without
+//! depending on `digest`'s `GenericArray` and type-level sizes.
+//!
+//! [`ArrayChain<H, N>`] wraps a [`HashChain<H>`] and checks when compiling that `N` is the output
+//! size of `H`. [`HashChain32`], with the `sha2` feature, is the SHA-256 chain. The arrays are
+//! copies and, unlike a [`ChainValue`](crate::value::ChainValue), are not wiped when dropped.
+
+use crate::{ChainInitError, HashChain};
+use digest::generic_array::typenum::Unsigned;
+use digest::generic_array::GenericArray;
+use digest::{Digest, FixedOutputReset};
+#[cfg(any(feature = "sha2", test))]
+use sha2::Sha256;
+
+/// A SHA-256 chain with `[u8; 32]` values.
+#[cfg(feature = "sha2")]
+pub type HashChain32 = ArrayChain<Sha256, 32>;
+
+fn to_array<const N: usize>(value: &[u8]) -> [u8; N] {
+    let mut array = [0; N];
+    array.copy_from_slice(value);
+    array
+}
+
+pub struct ArrayChain<H: Digest + FixedOutputReset, const N: usize> {
+    chain: HashChain<H>,
+}
+
+impl<H: Digest + FixedOutputReset, const N: usize> Clone for ArrayChain<H, N> {
+    fn clone(&self) -> Self {
+        ArrayChain { chain: self.chain.clone() }
+    }
+}
+
+impl<H: Digest + FixedOutputReset, const N: usize> ArrayChain<H, N> {
+    const SIZE_MATCHES: () = assert!(N == H::OutputSize::USIZE, "N must be the output size of the hash");
+
+    pub fn new(length: usize, seed: u64) -> Result<Self, ChainInitError> {
+        HashChain::new(length, seed).map(Self::from_chain)
+    }
+
+    pub fn from_chain(chain: HashChain<H>) -> Self {
+        #[allow(clippy::let_unit_value)]
+        let () = Self::SIZE_MATCHES;
+        ArrayChain { chain }
+    }
+
+    /// The public commitment at position 0.
+    pub fn anchor(&self) -> [u8; N] {
+        to_array(self.chain.anchor())
+    }
+
+    pub fn length(&self) -> u64 {
+        self.chain.length()
+    }
+
+    pub fn position(&self) -> u64 {
+        self.chain.position()
+    }
+
+    pub fn remaining(&self) -> u64 {
+        self.chain.remaining()
+    }
+
+    /// Disclose the next value, see [`HashChain::disclose`].
+    pub fn disclose(&mut self) -> Option<(u64, [u8; N])> {
+        self.chain.disclose().map(|(index, value)| (index, to_array(&value)))
+    }
+
+    pub fn hash_chain(&self) -> &HashChain<H> {
+        &self.chain
+    }
+
+    pub fn into_inner(self) -> HashChain<H> {
+        self.chain
+    }
+}
+
+impl<H: Digest + FixedOutputReset, const N: usize> Iterator for ArrayChain<H, N> {
+    type Item = (u64, [u8; N]);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.disclose()
+    }
+}
+
+/// [`crate::verify`] for array values. Fails when compiling if `N` is not the output size of `H`.
+pub fn verify_array<H: Digest + FixedOutputReset, const N: usize>(known_index: u64, known_value: &[u8; N], index: u64, value: &[u8; N]) -> bool {
+    #[allow(clippy::let_unit_value)]
+    let () = ArrayChain::<H, N>::SIZE_MATCHES;
+    crate::verify::<H>(known_index, GenericArray::from_slice(known_value), index, GenericArray::from_slice(value))
+}
+
+#[test]
+fn test_array_chain() {
+    let mut chain = ArrayChain::<Sha256, 32>::new(16, 7).unwrap();
+    let expected = crate::create_hash_chain_nopebble::<Sha256>(16, 7);
+    let anchor: [u8; 32] = chain.anchor();
+    assert_eq!(anchor[..], chain.hash_chain().anchor()[..]);
+    let (index, value) = chain.nth(4).unwrap();
+    assert_eq!((index, &value[..]), (5, &expected[11][..]));
+    assert!(verify_array::<Sha256, 32>(0, &anchor, index, &value));
+    assert!(!verify_array::<Sha256, 32>(0, &anchor, index + 1, &value));
+    assert_eq!(chain.remaining(), 11);
+}
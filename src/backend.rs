@@ -0,0 +1,169 @@
+//! The chain step computed by another crypto module than the pure-Rust `sha2`, for deployments
+//! that must use a particular one, e.g. a FIPS-validated OpenSSL.
+//!
+//! With the `ring` feature [`RingSha256`], [`RingSha384`] and [`RingSha512`] hash with
+//! [ring](https://docs.rs/ring), and with the `openssl` feature [`OpensslSha256`],
+//! [`OpensslSha384`] and [`OpensslSha512`] hash through OpenSSL's EVP interface, so the
+//! providers OpenSSL is configured with, the FIPS provider among them, are the ones used. Each
+//! is a digest like `sha2::Sha256`, so a `HashChain<RingSha256>` traverses, verifies and
+//! registers as any other chain does, and its values are the same as those of the `sha2` chain
+//! with the same seed: a chain set up with one backend is verified with any other. Whether the
+//! module linked in is a validated one is up to the build, not this crate.
+//!
+//! The `fips` feature keeps the module, so that enabling it breaks no other crate in the build
+//! using these digests; FIPS mode is enforced where chains are set up, see `fips`.
+
+use digest::consts::{U128, U32, U48, U64};
+use digest::core_api::BlockSizeUser;
+use digest::{FixedOutput, FixedOutputReset, HashMarker, Output, OutputSizeUser, Reset, Update};
+
+#[cfg(feature = "ring")]
+macro_rules! ring_digest {
+    ($name:ident, $algorithm:ident, $output:ty, $block:ty) => {
+        #[doc = concat!("`", stringify!($algorithm), "` computed by ring.")]
+        #[derive(Clone)]
+        pub struct $name(ring::digest::Context);
+
+        impl Default for $name {
+            fn default() -> Self {
+                $name(ring::digest::Context::new(&ring::digest::$algorithm))
+            }
+        }
+
+        impl Update for $name {
+            fn update(&mut self, data: &[u8]) {
+                self.0.update(data);
+            }
+        }
+
+        impl OutputSizeUser for $name {
+            type OutputSize = $output;
+        }
+
+        impl BlockSizeUser for $name {
+            type BlockSize = $block;
+        }
+
+        impl FixedOutput for $name {
+            fn finalize_into(self, out: &mut Output<Self>) {
+                out.copy_from_slice(self.0.finish().as_ref());
+            }
+        }
+
+        impl Reset for $name {
+            fn reset(&mut self) {
+                *self = Self::default();
+            }
+        }
+
+        impl FixedOutputReset for $name {
+            fn finalize_into_reset(&mut self, out: &mut Output<Self>) {
+                core::mem::take(self).finalize_into(out);
+            }
+        }
+
+        impl HashMarker for $name {}
+    };
+}
+
+#[cfg(feature = "ring")]
+ring_digest!(RingSha256, SHA256, U32, U64);
+#[cfg(feature = "ring")]
+ring_digest!(RingSha384, SHA384, U48, U128);
+#[cfg(feature = "ring")]
+ring_digest!(RingSha512, SHA512, U64, U128);
+
+/// OpenSSL reports errors for a digest only when it cannot allocate or the algorithm is not
+/// available from its providers, neither of which a chain could carry on from.
+#[cfg(feature = "openssl")]
+const OPENSSL_FAILED: &str = "OpenSSL digest failed";
+
+#[cfg(feature = "openssl")]
+macro_rules! openssl_digest {
+    ($name:ident, $algorithm:ident, $output:ty, $block:ty) => {
+        #[doc = concat!("`", stringify!($algorithm), "` computed through OpenSSL's EVP interface. Panics if")]
+        /// OpenSSL fails, e.g. because no configured provider offers the algorithm.
+        #[derive(Clone)]
+        pub struct $name(openssl::hash::Hasher);
+
+        impl Default for $name {
+            fn default() -> Self {
+                $name(openssl::hash::Hasher::new(openssl::hash::MessageDigest::$algorithm()).expect(OPENSSL_FAILED))
+            }
+        }
+
+        impl Update for $name {
+            fn update(&mut self, data: &[u8]) {
+                self.0.update(data).expect(OPENSSL_FAILED);
+            }
+        }
+
+        impl OutputSizeUser for $name {
+            type OutputSize = $output;
+        }
+
+        impl BlockSizeUser for $name {
+            type BlockSize = $block;
+        }
+
+        impl FixedOutput for $name {
+            fn finalize_into(mut self, out: &mut Output<Self>) {
+                self.finalize_into_reset(out);
+            }
+        }
+
+        impl Reset for $name {
+            fn reset(&mut self) {
+                self.0.finish().expect(OPENSSL_FAILED);
+            }
+        }
+
+        /// OpenSSL starts over after every digest.
+        impl FixedOutputReset for $name {
+            fn finalize_into_reset(&mut self, out: &mut Output<Self>) {
+                out.copy_from_slice(&self.0.finish().expect(OPENSSL_FAILED));
+            }
+        }
+
+        impl HashMarker for $name {}
+    };
+}
+
+#[cfg(feature = "openssl")]
+openssl_digest!(OpensslSha256, sha256, U32, U64);
+#[cfg(feature = "openssl")]
+openssl_digest!(OpensslSha384, sha384, U48, U128);
+#[cfg(feature = "openssl")]
+openssl_digest!(OpensslSha512, sha512, U64, U128);
+
+/// A chain set up with `B` has the values of the `sha2` chain `H` with the same seed.
+#[cfg(test)]
+fn assert_matches_sha2<B: digest::Digest + FixedOutputReset, H: digest::Digest + FixedOutputReset>() {
+    use crate::{verify, HashChain};
+
+    let backend = HashChain::<B>::new(64, 3).unwrap();
+    let reference = HashChain::<H>::new(64, 3).unwrap();
+    assert_eq!(backend.anchor().as_slice(), reference.anchor().as_slice());
+    let anchor = reference.anchor().clone();
+    for ((index, value), (_, expected)) in backend.zip(reference) {
+        assert_eq!(value.as_bytes(), expected.as_bytes());
+        // and the backend's values verify with sha2
+        assert!(verify::<H>(0, &anchor, index, digest::generic_array::GenericArray::from_slice(value.as_bytes())));
+    }
+}
+
+#[cfg(feature = "ring")]
+#[test]
+fn test_ring_backend() {
+    assert_matches_sha2::<RingSha256, sha2::Sha256>();
+    assert_matches_sha2::<RingSha384, sha2::Sha384>();
+    assert_matches_sha2::<RingSha512, sha2::Sha512>();
+}
+
+#[cfg(feature = "openssl")]
+#[test]
+fn test_openssl_backend() {
+    assert_matches_sha2::<OpensslSha256, sha2::Sha256>();
+    assert_matches_sha2::<OpensslSha384, sha2::Sha384>();
+    assert_matches_sha2::<OpensslSha512, sha2::Sha512>();
+}
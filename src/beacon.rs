@@ -0,0 +1,166 @@
+//! A randomness beacon. The operator publishes a chain anchor ahead of time and then one chain
+//! value per round. Since each round's value is fixed by the anchor, the operator cannot choose
+//! outputs after the fact, and nobody can predict a round before it is published without
+//! inverting the hash.
+//!
+//! Raw chain values are related to each other through the hash, so the public randomness of a
+//! round is a whitened [`output`] that mixes in the round number.
+
+use crate::verify::DEFAULT_MAX_GAP;
+use crate::{hash_forward, verify, HashChain};
+use digest::generic_array::GenericArray;
+use digest::{Digest, FixedOutputReset, OutputSizeUser};
+use std::error::Error;
+use std::fmt::{self, Display};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum BeaconError {
+    /// The round is not newer than the last verified one.
+    Stale,
+    /// The round skips more rounds past the last verified one than the verifier allows.
+    TooFarAhead,
+    /// The value does not extend the chain.
+    Invalid,
+}
+
+impl Display for BeaconError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            BeaconError::Stale => write!(f, "round already verified"),
+            BeaconError::TooFarAhead => write!(f, "round too far ahead of the last verified one"),
+            BeaconError::Invalid => write!(f, "round value does not verify against the chain"),
+        }
+    }
+}
+
+impl Error for BeaconError {}
+
+/// A published round.
+pub struct Round<H: OutputSizeUser> {
+    pub number: u64,
+    pub value: GenericArray<u8, H::OutputSize>,
+}
+
+impl<H: OutputSizeUser> Clone for Round<H> {
+    fn clone(&self) -> Self {
+        Round { number: self.number, value: self.value.clone() }
+    }
+}
+
+/// The public randomness of round `number` with chain value `value`.
+pub fn output<H: Digest>(number: u64, value: &[u8]) -> GenericArray<u8, H::OutputSize> {
+    let mut hasher = H::new();
+    hasher.update(b"beacon output");
+    hasher.update(number.to_be_bytes());
+    hasher.update(value);
+    hasher.finalize()
+}
+
+impl<H: Digest> Round<H> {
+    pub fn output(&self) -> GenericArray<u8, H::OutputSize> {
+        output::<H>(self.number, &self.value)
+    }
+}
+
+/// The operator side.
+pub struct Beacon<H: Digest + FixedOutputReset> {
+    chain: HashChain<H>,
+}
+
+impl<H: Digest + FixedOutputReset> Beacon<H> {
+    pub fn new(chain: HashChain<H>) -> Self {
+        Beacon { chain }
+    }
+
+    /// The anchor to publish before the first round.
+    pub fn anchor(&self) -> &GenericArray<u8, H::OutputSize> {
+        self.chain.anchor()
+    }
+
+    /// Publish the next round, or `None` once the chain is used up.
+    pub fn publish(&mut self) -> Option<Round<H>> {
+        self.chain.disclose().map(|(number, value)| Round { number, value: value.to_array() })
+    }
+}
+
+/// Follows a beacon from its anchor.
+pub struct BeaconVerifier<H: OutputSizeUser> {
+    round: u64,
+    value: GenericArray<u8, H::OutputSize>,
+    max_gap: u64,
+}
+
+impl<H: Digest + FixedOutputReset> BeaconVerifier<H> {
+    pub fn new(anchor: GenericArray<u8, H::OutputSize>) -> Self {
+        BeaconVerifier { round: 0, value: anchor, max_gap: DEFAULT_MAX_GAP }
+    }
+
+    /// Refuse rounds more than `max_gap` past the last verified one with
+    /// [`BeaconError::TooFarAhead`] before hashing, instead of [`DEFAULT_MAX_GAP`]. This also
+    /// bounds the outputs [`BeaconVerifier::catch_up`] collects.
+    pub fn with_max_gap(self, max_gap: u64) -> Self {
+        BeaconVerifier { max_gap, ..self }
+    }
+
+    /// The last verified round, 0 before the first.
+    pub fn round(&self) -> u64 {
+        self.round
+    }
+
+    /// Verify `round` and return its output. Rounds in between may be skipped; see
+    /// [`BeaconVerifier::catch_up`] to recover their outputs.
+    pub fn verify(&mut self, round: &Round<H>) -> Result<GenericArray<u8, H::OutputSize>, BeaconError> {
+        if round.number <= self.round {
+            return Err(BeaconError::Stale);
+        }
+        if round.number - self.round > self.max_gap {
+            return Err(BeaconError::TooFarAhead);
+        }
+        if !verify::<H>(self.round, &self.value, round.number, &round.value) {
+            return Err(BeaconError::Invalid);
+        }
+        self.round = round.number;
+        self.value = round.value.clone();
+        Ok(round.output())
+    }
+
+    /// Verify `round` and return the outputs of every round since the last verified one, oldest
+    /// first, reconstructing the missed values from `round` by hashing.
+    pub fn catch_up(&mut self, round: &Round<H>) -> Result<Vec<(u64, digest::Output<H>)>, BeaconError> {
+        let from = self.round + 1;
+        self.verify(round)?;
+        let mut value = round.value.clone();
+        let mut outputs = Vec::new();
+        for number in (from..=round.number).rev() {
+            outputs.push((number, output::<H>(number, &value)));
+            value = hash_forward::<H>(&value, 1);
+        }
+        outputs.reverse();
+        Ok(outputs)
+    }
+}
+
+#[test]
+fn test_beacon_rounds() {
+    use sha2::Sha256;
+
+    let mut beacon = Beacon::new(HashChain::<Sha256>::new(8, 21).unwrap());
+    let mut live = BeaconVerifier::<Sha256>::new(*beacon.anchor());
+    let mut late = BeaconVerifier::<Sha256>::new(*beacon.anchor());
+    let rounds: Vec<_> = std::iter::from_fn(|| beacon.publish()).take(4).collect();
+    let outputs: Vec<_> = rounds.iter().map(|round| live.verify(round).unwrap()).collect();
+    assert_ne!(outputs[0], outputs[1]);
+    assert_eq!(live.verify(&rounds[3]), Err(BeaconError::Stale));
+
+    let caught_up = late.catch_up(&rounds[3]).unwrap();
+    assert_eq!(caught_up, (1..=4).zip(outputs).collect::<Vec<_>>());
+
+    let mut forged = beacon.publish().unwrap();
+    let far = Round { number: u64::MAX, value: forged.value };
+    assert_eq!(late.catch_up(&far), Err(BeaconError::TooFarAhead));
+    let mut strict = BeaconVerifier::<Sha256>::new(*beacon.anchor()).with_max_gap(3);
+    assert_eq!(strict.catch_up(&rounds[3]), Err(BeaconError::TooFarAhead));
+    assert_eq!(strict.catch_up(&rounds[2]).unwrap().len(), 3);
+    forged.value[0] ^= 1;
+    assert_eq!(late.verify(&forged), Err(BeaconError::Invalid));
+}
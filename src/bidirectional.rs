@@ -0,0 +1,203 @@
+//! A pair of chains committed to together, so a running count can move both up and down, e.g.
+//! micropayments that can be refunded.
+//!
+//! The forward chain counts upward moves and the backward chain downward ones, each disclosed
+//! from its anchor as usual. A [`DualAnchor`] commits to both anchors, and the verifier's
+//! balance is the forward index less the backward index. The two chains may be held by
+//! different parties, say the payer's forward chain and the payee's refund chain, and
+//! [`BidirectionalChain`] holds both for a party that moves in either direction.
+//!
+//! The directions are not interchangeable, and misusing them gives values away:
+//!
+//! - The seeds must be independent. Chains from the same seed are the same chain, and every
+//!   backward value then discloses the forward value at the same index.
+//! - Every disclosed value stays public. Moving down and then up again never reuses a forward
+//!   value; it takes a fresh one, so a chain of `n` values covers `n` moves in its direction over
+//!   its whole lifetime, not a balance of `n`.
+//! - A downward move is only worth as much as the trust placed in whoever holds the backward
+//!   chain. A payer holding both chains can refund themselves.
+
+use crate::verify::DEFAULT_MAX_GAP;
+use crate::{verify, ChainInitError, HashChain};
+use core::error::Error;
+use core::fmt::{self, Display};
+use digest::{Digest, FixedOutputReset, Output, OutputSizeUser};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BidirectionalError {
+    /// The index is not above the last accepted one in its direction.
+    Replay,
+    /// The index skips further ahead of the last accepted one than the verifier allows.
+    GapTooLarge,
+    /// The value does not verify against the anchor of its direction.
+    Mismatch,
+    /// The move would take the balance below zero.
+    Overdrawn,
+}
+
+impl Display for BidirectionalError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            BidirectionalError::Replay => write!(f, "index already accepted"),
+            BidirectionalError::GapTooLarge => write!(f, "index too far ahead of the last accepted index"),
+            BidirectionalError::Mismatch => write!(f, "value does not verify"),
+            BidirectionalError::Overdrawn => write!(f, "balance would fall below zero"),
+        }
+    }
+}
+
+impl Error for BidirectionalError {}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Direction {
+    Up,
+    Down,
+}
+
+/// The anchors of both chains.
+pub struct DualAnchor<H: OutputSizeUser> {
+    pub forward: Output<H>,
+    pub backward: Output<H>,
+}
+
+impl<H: OutputSizeUser> Clone for DualAnchor<H> {
+    fn clone(&self) -> Self {
+        DualAnchor { forward: self.forward.clone(), backward: self.backward.clone() }
+    }
+}
+
+impl<H: Digest> DualAnchor<H> {
+    /// `H("dual anchor" || forward || backward)`, to publish or sign in place of the two anchors.
+    pub fn commitment(&self) -> Output<H> {
+        H::new_with_prefix(b"dual anchor").chain_update(&self.forward).chain_update(&self.backward).finalize()
+    }
+}
+
+/// A value of the chain for `direction`.
+pub struct Move<H: OutputSizeUser> {
+    pub direction: Direction,
+    pub index: u64,
+    pub value: Output<H>,
+}
+
+impl<H: OutputSizeUser> Clone for Move<H> {
+    fn clone(&self) -> Self {
+        Move { direction: self.direction, index: self.index, value: self.value.clone() }
+    }
+}
+
+pub struct BidirectionalChain<H: Digest + FixedOutputReset> {
+    forward: HashChain<H>,
+    backward: HashChain<H>,
+}
+
+impl<H: Digest + FixedOutputReset> BidirectionalChain<H> {
+    /// Two chains of `length` values; the seeds must differ and should be independent.
+    pub fn new(length: usize, forward_seed: u64, backward_seed: u64) -> Result<Self, ChainInitError> {
+        if forward_seed == backward_seed {
+            return Err(ChainInitError::new("forward and backward seeds must differ"));
+        }
+        Ok(BidirectionalChain { forward: HashChain::new(length, forward_seed)?, backward: HashChain::new(length, backward_seed)? })
+    }
+
+    pub fn anchor(&self) -> DualAnchor<H> {
+        DualAnchor { forward: self.forward.anchor().clone(), backward: self.backward.anchor().clone() }
+    }
+
+    /// The forward index less the backward one.
+    pub fn balance(&self) -> u64 {
+        HashChain::position(&self.forward) - HashChain::position(&self.backward)
+    }
+
+    /// Move up one, `None` once the forward chain is exhausted.
+    pub fn up(&mut self) -> Option<Move<H>> {
+        let (index, value) = self.forward.disclose()?;
+        Some(Move { direction: Direction::Up, index, value: value.to_array() })
+    }
+
+    /// Move down one, `None` at a balance of zero or once the backward chain is exhausted.
+    pub fn down(&mut self) -> Option<Move<H>> {
+        if self.balance() == 0 {
+            return None;
+        }
+        let (index, value) = self.backward.disclose()?;
+        Some(Move { direction: Direction::Down, index, value: value.to_array() })
+    }
+}
+
+pub struct BidirectionalVerifier<H: OutputSizeUser> {
+    /// The last accepted index and value of each chain, the anchors at index 0.
+    forward: (u64, Output<H>),
+    backward: (u64, Output<H>),
+    max_gap: u64,
+}
+
+impl<H: Digest + FixedOutputReset> BidirectionalVerifier<H> {
+    pub fn new(anchor: DualAnchor<H>) -> Self {
+        BidirectionalVerifier { forward: (0, anchor.forward), backward: (0, anchor.backward), max_gap: DEFAULT_MAX_GAP }
+    }
+
+    /// Refuse moves skipping more than `max_gap` values in their direction with
+    /// [`BidirectionalError::GapTooLarge`] before hashing, instead of [`DEFAULT_MAX_GAP`].
+    pub fn with_max_gap(self, max_gap: u64) -> Self {
+        BidirectionalVerifier { max_gap, ..self }
+    }
+
+    pub fn balance(&self) -> u64 {
+        self.forward.0 - self.backward.0
+    }
+
+    /// Accept a move, which may skip values in its direction, and return the new balance.
+    pub fn accept(&mut self, step: &Move<H>) -> Result<u64, BidirectionalError> {
+        let known = match step.direction {
+            Direction::Up => &self.forward,
+            Direction::Down => &self.backward,
+        };
+        if step.index <= known.0 {
+            return Err(BidirectionalError::Replay);
+        }
+        if step.index - known.0 > self.max_gap {
+            return Err(BidirectionalError::GapTooLarge);
+        }
+        if step.direction == Direction::Down && step.index > self.forward.0 {
+            return Err(BidirectionalError::Overdrawn);
+        }
+        if !verify::<H>(known.0, &known.1, step.index, &step.value) {
+            return Err(BidirectionalError::Mismatch);
+        }
+        let known = match step.direction {
+            Direction::Up => &mut self.forward,
+            Direction::Down => &mut self.backward,
+        };
+        *known = (step.index, step.value.clone());
+        Ok(self.balance())
+    }
+}
+
+#[test]
+fn test_bidirectional_chain() {
+    use sha2::Sha256;
+
+    assert!(BidirectionalChain::<Sha256>::new(8, 1, 1).is_err());
+    let mut chain = BidirectionalChain::<Sha256>::new(8, 1, 2).unwrap();
+    let mut verifier = BidirectionalVerifier::new(chain.anchor());
+    assert!(chain.down().is_none());
+    let (first, second) = (chain.up().unwrap(), chain.up().unwrap());
+    let refund = chain.down().unwrap();
+    assert_eq!(verifier.accept(&refund), Err(BidirectionalError::Overdrawn));
+    assert_eq!(verifier.accept(&second), Ok(2));
+    assert_eq!(verifier.accept(&first), Err(BidirectionalError::Replay));
+    assert_eq!(verifier.accept(&refund), Ok(1));
+    assert_eq!(chain.balance(), verifier.balance());
+
+    // a forward value does not pass for a backward one
+    let mut forged = second.clone();
+    forged.direction = Direction::Down;
+    assert_eq!(verifier.accept(&forged), Err(BidirectionalError::Mismatch));
+    let far = Move { index: u64::MAX, ..second.clone() };
+    assert_eq!(verifier.accept(&far), Err(BidirectionalError::GapTooLarge));
+    let mut strict = BidirectionalVerifier::new(chain.anchor()).with_max_gap(1);
+    assert_eq!(strict.accept(&second), Err(BidirectionalError::GapTooLarge));
+    assert_eq!(strict.accept(&first), Ok(1));
+    assert_ne!(chain.anchor().commitment(), BidirectionalChain::<Sha256>::new(8, 2, 1).unwrap().anchor().commitment());
+}
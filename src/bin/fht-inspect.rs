@@ -0,0 +1,123 @@
+//! `fht-inspect`, a terminal UI for watching the pebbles of a saved SHA-256 chain move.
+//!
+//! Usage: `fht-inspect <state file>`, the file written by `fht init` or `fht next`. Every pebble
+//! is drawn on a track from the anchor to the end of the chain, with its position (`●`) and
+//! destination (`◆`), next to the hash cost of each step taken.
+//!
+//! Keys: `n` or space steps once, `j` steps 16 times, `q` quits. Stepping only happens in
+//! memory: the state file is never written, so no value is consumed.
+
+use fractal_hash_traversal::HashChain;
+use ratatui::crossterm::event::{self, Event, KeyCode, KeyEventKind};
+use ratatui::layout::{Constraint, Layout};
+use ratatui::text::Line;
+use ratatui::widgets::{Block, Paragraph, Sparkline};
+use ratatui::Frame;
+use sha2::Sha256;
+use std::process::ExitCode;
+
+struct Inspector {
+    chain: HashChain<Sha256>,
+    /// The hash cost of every step taken so far.
+    costs: Vec<u64>,
+}
+
+impl Inspector {
+    fn step(&mut self) {
+        let cost = self.chain.step_cost();
+        if self.chain.disclose().is_some() {
+            self.costs.push(cost);
+        }
+    }
+
+    /// Draw `position` and `destination` as marks on a track `width` characters wide.
+    fn track(&self, position: u64, destination: u64, width: usize) -> String {
+        let column = |at: u64| (at.min(self.chain.length()) as u128 * (width as u128 - 1) / self.chain.length() as u128) as usize;
+        let mut track = vec!['·'; width];
+        track[column(self.chain.position())] = '|';
+        track[column(destination)] = '◆';
+        track[column(position)] = '●';
+        track.into_iter().collect()
+    }
+
+    fn draw(&self, frame: &mut Frame) {
+        let [header, pebbles, costs] = Layout::vertical([Constraint::Length(3), Constraint::Min(3), Constraint::Length(6)]).areas(frame.area());
+
+        let summary = format!(
+            "position {} of {}   next step {} hashes   total {} hashes",
+            HashChain::position(&self.chain),
+            self.chain.length(),
+            self.chain.step_cost(),
+            self.costs.iter().sum::<u64>(),
+        );
+        frame.render_widget(Paragraph::new(summary).block(Block::bordered().title(" fht-inspect: n step, j 16 steps, q quit ")), header);
+
+        let width = (pebbles.width as usize).saturating_sub(34).max(8);
+        let lines: Vec<Line> = self.chain.state().pebbles.iter().map(|p| {
+            let state = if p.moving { "move" } else { "rest" };
+            Line::from(format!("{:>2} {} {:>10} → {:>10} {}", p.level, state, p.position, p.destination, self.track(p.position, p.destination, width)))
+        }).collect();
+        frame.render_widget(Paragraph::new(lines).block(Block::bordered().title(" pebbles: level, position → destination ")), pebbles);
+
+        let recent = &self.costs[self.costs.len().saturating_sub(costs.width as usize)..];
+        frame.render_widget(Sparkline::default().data(recent).block(Block::bordered().title(" hashes per step ")), costs);
+    }
+}
+
+fn main() -> ExitCode {
+    let Some(path) = std::env::args().nth(1) else {
+        eprintln!("usage: fht-inspect <state file>");
+        return ExitCode::FAILURE;
+    };
+    let chain = match std::fs::read(&path).map_err(|e| e.to_string()).and_then(|bytes| HashChain::import_state(&bytes).map_err(|e| e.to_string())) {
+        Ok(chain) => chain,
+        Err(error) => {
+            eprintln!("fht-inspect: loading {}: {}", path, error);
+            return ExitCode::FAILURE;
+        }
+    };
+    let mut inspector = Inspector { chain, costs: Vec::new() };
+
+    let mut terminal = ratatui::init();
+    let result = loop {
+        if let Err(error) = terminal.draw(|frame| inspector.draw(frame)) {
+            break Err(error);
+        }
+        match event::read() {
+            Ok(Event::Key(key)) if key.kind == KeyEventKind::Press => match key.code {
+                KeyCode::Char('q') | KeyCode::Esc => break Ok(()),
+                KeyCode::Char('n') | KeyCode::Char(' ') => inspector.step(),
+                KeyCode::Char('j') => (0..16).for_each(|_| inspector.step()),
+                _ => {}
+            },
+            Ok(_) => {}
+            Err(error) => break Err(error),
+        }
+    };
+    ratatui::restore();
+    match result {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(error) => {
+            eprintln!("fht-inspect: {}", error);
+            ExitCode::FAILURE
+        }
+    }
+}
+
+#[test]
+fn test_inspector_draws_pebbles() {
+    use ratatui::backend::TestBackend;
+    use ratatui::Terminal;
+
+    let mut inspector = Inspector { chain: HashChain::new(16, 1).unwrap(), costs: Vec::new() };
+    for _ in 0..4 {
+        inspector.step();
+    }
+    assert_eq!(inspector.costs.len(), 4);
+
+    let mut terminal = Terminal::new(TestBackend::new(80, 16)).unwrap();
+    terminal.draw(|frame| inspector.draw(frame)).unwrap();
+    let screen: String = terminal.backend().buffer().content().iter().map(|cell| cell.symbol()).collect();
+    assert!(screen.contains("position 4 of 16"));
+    assert!(screen.contains("move"));
+}
@@ -0,0 +1,27 @@
+//! A ready-to-run verification endpoint serving [`fractal_hash_traversal::rest`] for SHA-256
+//! chains.
+//!
+//! Usage: `fht-server [<listen address> [<max gap>]]`, listening on `127.0.0.1:8080` by default
+//! and refusing disclosures more than 1024 positions past the last accepted one. Enrolled
+//! chains are kept in memory and lost on restart; embed [`fractal_hash_traversal::rest::router`]
+//! with a persistent [`StateStore`](fractal_hash_traversal::store::StateStore) to keep them.
+
+use fractal_hash_traversal::registry::Registry;
+use fractal_hash_traversal::rest;
+use fractal_hash_traversal::store::MemoryStore;
+use sha2::Sha256;
+use std::sync::Arc;
+
+#[tokio::main]
+async fn main() -> std::io::Result<()> {
+    let mut args = std::env::args().skip(1);
+    let address = args.next().unwrap_or_else(|| "127.0.0.1:8080".to_string());
+    let max_gap = match args.next() {
+        Some(max_gap) => max_gap.parse().map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidInput, format!("invalid max gap: {}", e)))?,
+        None => 1024,
+    };
+    let registry = Arc::new(Registry::<Sha256, _>::new(MemoryStore::new()).with_max_gap(max_gap));
+    let listener = tokio::net::TcpListener::bind(&address).await?;
+    eprintln!("listening on {}", listener.local_addr()?);
+    axum::serve(listener, rest::router(registry)).await
+}
@@ -0,0 +1,286 @@
+//! `fht`, a command-line tool for SHA-256 hash chains.
+//!
+//! - `fht init --length <n> --state <file>` creates a chain, saves its state and prints the
+//!   anchor.
+//! - `fht next --state <file>` discloses the next value, printing `<index> <value>`, and saves
+//!   the advanced state.
+//! - `fht status --state <file>` prints the position, length and anchor.
+//! - `fht provision --state <file> --label <label>` prints a provisioning URI for enrolling a
+//!   client, e.g. rendered as a QR code with `qrencode`. With `--include-state` the URI hands the
+//!   chain itself over and must be kept as secret as the state file.
+//! - `fht verify --anchor <hex> <index> <value>` checks a value against the anchor, or against
+//!   a later known value with `--known-index`, exiting with status 1 if it does not verify.
+//!   Without `<index> <value>` it reads the values following the known one from stdin, one hex
+//!   value per line, oldest first unless `--order newest-first` is given, and reports the first
+//!   that fails.
+//!
+//! Values and anchors are printed in lowercase hex unless `--format upper-hex` or
+//! `--format base58`, `--prefix` (for `0x`) or `--truncate <bytes>` say otherwise. Values read by
+//! `verify` are hex, with or without `0x`.
+//!
+//! The state file holds the traversal pebbles and must be guarded like a secret key.
+
+use clap::{Args, Parser, Subcommand, ValueEnum};
+use fractal_hash_traversal::provisioning::ProvisioningUri;
+use fractal_hash_traversal::seed::Seed;
+use fractal_hash_traversal::value::{ChainValue, DisplayOptions, Encoding};
+use fractal_hash_traversal::{verify, HashChain};
+use sha2::Sha256;
+use std::fs;
+use std::io::{self, BufRead};
+use std::path::{Path, PathBuf};
+use std::process::ExitCode;
+
+#[derive(Parser)]
+#[command(name = "fht", about = "Create, advance and verify SHA-256 hash chains")]
+struct Cli {
+    #[command(flatten)]
+    display: DisplayArgs,
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Args)]
+struct DisplayArgs {
+    /// How to print values and anchors
+    #[arg(long, global = true, value_enum, default_value_t = Format::Hex)]
+    format: Format,
+    /// Put 0x in front of hex
+    #[arg(long, global = true)]
+    prefix: bool,
+    /// Print only the first this many bytes
+    #[arg(long, global = true)]
+    truncate: Option<usize>,
+}
+
+impl DisplayArgs {
+    fn options(&self) -> DisplayOptions {
+        let encoding = match self.format {
+            Format::Hex => Encoding::Hex,
+            Format::UpperHex => Encoding::UpperHex,
+            Format::Base58 => Encoding::Base58,
+        };
+        DisplayOptions { encoding, prefix: self.prefix, truncate: self.truncate }
+    }
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum Format {
+    Hex,
+    UpperHex,
+    Base58,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Create a chain, save its state and print the anchor
+    Init {
+        #[arg(long)]
+        length: usize,
+        /// The seed; a random one is drawn if omitted
+        #[arg(long)]
+        seed: Option<u64>,
+        #[arg(long)]
+        state: PathBuf,
+    },
+    /// Disclose the next value and save the advanced state
+    Next {
+        #[arg(long)]
+        state: PathBuf,
+    },
+    /// Print the position, length and anchor of a saved chain
+    Status {
+        #[arg(long)]
+        state: PathBuf,
+    },
+    /// Print a provisioning URI for the chain, e.g. to render as a QR code
+    Provision {
+        #[arg(long)]
+        state: PathBuf,
+        /// What the client shows for the chain, e.g. Example:alice
+        #[arg(long)]
+        label: String,
+        #[arg(long)]
+        issuer: Option<String>,
+        /// Hand the chain over instead of its anchor only
+        #[arg(long)]
+        include_state: bool,
+    },
+    /// Check a disclosed value against the anchor or a known later value
+    Verify {
+        /// The known value in hex, the anchor unless --known-index is given
+        #[arg(long)]
+        anchor: String,
+        #[arg(long, default_value_t = 0)]
+        known_index: u64,
+        /// The order of the values read from stdin
+        #[arg(long, value_enum, default_value_t = Order::OldestFirst)]
+        order: Order,
+        /// The index of the value to check; read values from stdin if omitted
+        #[arg(requires = "value")]
+        index: Option<u64>,
+        value: Option<String>,
+    },
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum Order {
+    OldestFirst,
+    NewestFirst,
+}
+
+type Value = digest::Output<Sha256>;
+
+fn parse_value(text: &str) -> Result<Value, String> {
+    let value = ChainValue::<Sha256>::from_hex(text).map_err(|e| format!("invalid value: {}", e))?;
+    Ok(value.to_array())
+}
+
+fn load(path: &Path) -> Result<HashChain<Sha256>, String> {
+    let bytes = fs::read(path).map_err(|e| format!("reading {}: {}", path.display(), e))?;
+    HashChain::import_state(&bytes).map_err(|e| format!("loading {}: {}", path.display(), e))
+}
+
+/// Write the state next to `path` and rename it into place, so a crash leaves either the old
+/// or the new state.
+fn save(path: &Path, chain: &HashChain<Sha256>) -> Result<(), String> {
+    let temporary = path.with_extension("tmp");
+    fs::write(&temporary, chain.export_state()).map_err(|e| format!("writing {}: {}", temporary.display(), e))?;
+    fs::rename(&temporary, path).map_err(|e| format!("writing {}: {}", path.display(), e))
+}
+
+/// Check the values following `known` at `known_index`, one per line of `input`. Oldest first
+/// lines are checked as they arrive; newest first lines are read to the end, since their indices
+/// count back from the last line.
+fn verify_lines(known_index: u64, known: Value, order: Order, input: &mut dyn BufRead) -> Result<String, String> {
+    let mut lines = Vec::new();
+    let (mut index, mut known) = (known_index, known);
+    for line in input.lines() {
+        let line = line.map_err(|e| format!("reading stdin: {}", e))?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        match order {
+            Order::OldestFirst => check_next(&mut index, &mut known, &line)?,
+            Order::NewestFirst => lines.push(line),
+        }
+    }
+    if let Order::NewestFirst = order {
+        for line in lines.iter().rev() {
+            check_next(&mut index, &mut known, line)?;
+        }
+    }
+    Ok(format!("ok, {} values", index - known_index))
+}
+
+/// Accept `line` as the value after `known` at `index`.
+fn check_next(index: &mut u64, known: &mut Value, line: &str) -> Result<(), String> {
+    let value = parse_value(line).map_err(|e| format!("value {}: {}", *index + 1, e))?;
+    if !verify::<Sha256>(*index, known, *index + 1, &value) {
+        return Err(format!("value {} does not verify", *index + 1));
+    }
+    *index += 1;
+    *known = value;
+    Ok(())
+}
+
+fn run(command: Command, display: DisplayOptions, stdin: &mut dyn BufRead) -> Result<String, String> {
+    match command {
+        Command::Init { length, seed, state } => {
+            if state.exists() {
+                return Err(format!("{} already exists", state.display()));
+            }
+            let seed = match seed {
+                Some(seed) => seed,
+                None => Seed::generate().map_err(|e| format!("drawing a seed: {}", e))?.expose(),
+            };
+            let chain = HashChain::<Sha256>::new(length, seed).map_err(|e| e.to_string())?;
+            save(&state, &chain)?;
+            Ok(display.format(chain.anchor()))
+        }
+        Command::Next { state } => {
+            let mut chain = load(&state)?;
+            let (index, value) = chain.disclose().ok_or("chain exhausted")?;
+            // persist before printing, so a value is never disclosed twice
+            save(&state, &chain)?;
+            Ok(format!("{} {}", index, value.display(display)))
+        }
+        Command::Status { state } => {
+            let chain = load(&state)?;
+            Ok(format!("position {} of {}\nanchor {}", HashChain::position(&chain), chain.length(), display.format(chain.anchor())))
+        }
+        Command::Provision { state, label, issuer, include_state } => {
+            let chain = load(&state)?;
+            let mut uri = ProvisioningUri::for_chain(&chain, "sha256", &label);
+            if let Some(issuer) = issuer {
+                uri = uri.with_issuer(&issuer);
+            }
+            if include_state {
+                uri = uri.with_state(&chain);
+            }
+            Ok(uri.to_string())
+        }
+        Command::Verify { anchor, known_index, order, index, value } => {
+            let known = parse_value(&anchor)?;
+            let (Some(index), Some(value)) = (index, value) else {
+                return verify_lines(known_index, known, order, stdin);
+            };
+            if verify::<Sha256>(known_index, &known, index, &parse_value(&value)?) {
+                Ok("ok".to_string())
+            } else {
+                Err(format!("value {} does not verify", index))
+            }
+        }
+    }
+}
+
+fn main() -> ExitCode {
+    let cli = Cli::parse();
+    match run(cli.command, cli.display.options(), &mut io::stdin().lock()) {
+        Ok(output) => {
+            println!("{}", output);
+            ExitCode::SUCCESS
+        }
+        Err(error) => {
+            eprintln!("fht: {}", error);
+            ExitCode::FAILURE
+        }
+    }
+}
+
+#[test]
+fn test_cli_workflow() {
+    let mut stdin = io::empty();
+    let mut run = |command| run(command, DisplayOptions::new(), &mut stdin);
+    let state = std::env::temp_dir().join(format!("fht-test-{}.state", std::process::id()));
+    let _ = fs::remove_file(&state);
+    let anchor = run(Command::Init { length: 4, seed: Some(1), state: state.clone() }).unwrap();
+    assert!(run(Command::Init { length: 4, seed: None, state: state.clone() }).is_err());
+
+    let disclosed = run(Command::Next { state: state.clone() }).unwrap();
+    let (index, value) = disclosed.split_once(' ').unwrap();
+    assert_eq!(index, "1");
+    assert!(run(Command::Status { state: state.clone() }).unwrap().starts_with("position 1 of 4"));
+    let uri = run(Command::Provision { state: state.clone(), label: "alice".to_string(), issuer: None, include_state: false }).unwrap();
+    assert_eq!(uri.parse::<ProvisioningUri>().unwrap().anchor, hex::decode(&anchor).unwrap());
+    let verify = |index| Command::Verify { anchor: anchor.clone(), known_index: 0, order: Order::OldestFirst, index: Some(index), value: Some(value.to_string()) };
+    assert!(run(verify(1)).is_ok());
+    assert!(run(verify(2)).is_err());
+    let prefixed = Command::Verify { anchor: format!("0x{}", anchor), known_index: 0, order: Order::OldestFirst, index: Some(1), value: Some(value.to_string()) };
+    assert!(run(prefixed).is_ok());
+    fs::remove_file(&state).unwrap();
+}
+
+#[test]
+fn test_cli_verify_stdin() {
+    let mut chain = HashChain::<Sha256>::new(8, 2).unwrap();
+    let anchor = *chain.anchor();
+    let mut values: Vec<String> = chain.by_ref().take(5).map(|(_, value)| hex::encode(value)).collect();
+    let verify = |order, lines: &[String]| verify_lines(0, anchor, order, &mut lines.join("\n").as_bytes());
+
+    assert_eq!(verify(Order::OldestFirst, &values), Ok("ok, 5 values".to_string()));
+    values[3] = hex::encode([0; 32]);
+    assert_eq!(verify(Order::OldestFirst, &values), Err("value 4 does not verify".to_string()));
+    values.reverse();
+    assert_eq!(verify(Order::NewestFirst, &values), Err("value 4 does not verify".to_string()));
+}
@@ -0,0 +1,123 @@
+//! Ed25519 certificates over [`AnchorCommitment`]s, to distribute anchors over untrusted
+//! channels. The signature covers a domain separation label and the commitment's canonical
+//! encoding. Certificates are stored in their binary encoding or as
+//! `HASH CHAIN ANCHOR CERTIFICATE` PEM blocks.
+
+use crate::anchor::{self, AnchorCommitment, CommitmentFormatError};
+use ed25519_dalek::{Signature, Signer, SigningKey, Verifier, VerifyingKey};
+use std::error::Error;
+use std::fmt::{self, Display};
+use std::time::SystemTime;
+
+const LABEL: &[u8] = b"fractal-hash-traversal anchor certificate";
+const PEM_LABEL: &str = "HASH CHAIN ANCHOR CERTIFICATE";
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum CertificateError {
+    BadSignature,
+    /// The commitment is not valid at the given time.
+    OutsideValidity,
+    Malformed(String),
+}
+
+impl Display for CertificateError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            CertificateError::BadSignature => write!(f, "certificate signature does not verify"),
+            CertificateError::OutsideValidity => write!(f, "anchor commitment not valid at this time"),
+            CertificateError::Malformed(details) => write!(f, "malformed certificate: {}", details),
+        }
+    }
+}
+
+impl Error for CertificateError {}
+
+impl From<CommitmentFormatError> for CertificateError {
+    fn from(error: CommitmentFormatError) -> Self {
+        CertificateError::Malformed(error.to_string())
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AnchorCertificate {
+    pub commitment: AnchorCommitment,
+    pub signature: Signature,
+}
+
+fn signed_bytes(commitment: &AnchorCommitment) -> Vec<u8> {
+    [LABEL, &commitment.to_bytes()].concat()
+}
+
+/// Sign `commitment` with `key`.
+pub fn certify(commitment: AnchorCommitment, key: &SigningKey) -> AnchorCertificate {
+    let signature = key.sign(&signed_bytes(&commitment));
+    AnchorCertificate { commitment, signature }
+}
+
+impl AnchorCertificate {
+    /// Check the signature under `key` and that the commitment is valid at `now`.
+    pub fn verify(&self, key: &VerifyingKey, now: SystemTime) -> Result<&AnchorCommitment, CertificateError> {
+        key.verify(&signed_bytes(&self.commitment), &self.signature).map_err(|_| CertificateError::BadSignature)?;
+        if !self.commitment.is_valid_at(now) {
+            return Err(CertificateError::OutsideValidity);
+        }
+        Ok(&self.commitment)
+    }
+
+    /// The commitment's encoding followed by the 64 byte signature.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        [&self.commitment.to_bytes()[..], &self.signature.to_bytes()].concat()
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, CertificateError> {
+        let Some(split) = bytes.len().checked_sub(Signature::BYTE_SIZE) else {
+            return Err(CertificateError::Malformed("certificate truncated".to_string()));
+        };
+        let commitment = AnchorCommitment::from_bytes(&bytes[..split])?;
+        let signature = Signature::from_bytes(bytes[split..].try_into().expect("64 bytes"));
+        Ok(AnchorCertificate { commitment, signature })
+    }
+
+    pub fn to_pem(&self) -> String {
+        anchor::to_pem(PEM_LABEL, &self.to_bytes())
+    }
+
+    /// Read the first `HASH CHAIN ANCHOR CERTIFICATE` block in `text`.
+    pub fn from_pem(text: &str) -> Result<Self, CertificateError> {
+        Self::from_bytes(&anchor::from_pem(PEM_LABEL, text)?)
+    }
+}
+
+impl crate::version::Versioned for AnchorCertificate {
+    type Error = CertificateError;
+
+    fn encode_body(&self, _version: u8) -> Vec<u8> {
+        self.to_bytes()
+    }
+
+    fn decode_body(_version: u8, body: &[u8]) -> Result<Self, CertificateError> {
+        Self::from_bytes(body)
+    }
+}
+
+#[test]
+fn test_certify_anchor() {
+    use crate::HashChain;
+    use sha2::Sha256;
+    use std::time::{Duration, UNIX_EPOCH};
+
+    let key = SigningKey::from_bytes(&[7u8; 32]);
+    let chain = HashChain::<Sha256>::new(64, 1).unwrap();
+    let certificate = certify(AnchorCommitment::for_chain(&chain, "sha256", 1_000, 2_000), &key);
+    let certificate = AnchorCertificate::from_bytes(&certificate.to_bytes()).unwrap();
+    assert_eq!(AnchorCertificate::from_pem(&certificate.to_pem()).unwrap(), certificate);
+
+    let now = UNIX_EPOCH + Duration::from_secs(1_500);
+    assert_eq!(certificate.verify(&key.verifying_key(), now).unwrap().anchor, chain.anchor().to_vec());
+    assert_eq!(certificate.verify(&key.verifying_key(), now + Duration::from_secs(1_000)), Err(CertificateError::OutsideValidity));
+    assert_eq!(certificate.verify(&SigningKey::from_bytes(&[8u8; 32]).verifying_key(), now), Err(CertificateError::BadSignature));
+
+    let mut forged = certificate.clone();
+    forged.commitment.length = 128;
+    assert_eq!(forged.verify(&key.verifying_key(), now), Err(CertificateError::BadSignature));
+}
@@ -0,0 +1,275 @@
+//! Chain setup, auditing a chain against its seed, and chain identifiers.
+
+use crate::error::{ChainAuditError, ChainInitError};
+use crate::pebble::{create_powers, log_2, Pebble};
+use alloc::vec::Vec;
+use core::fmt::{self, Display};
+#[cfg(feature = "hex")]
+use core::str::FromStr;
+use digest::generic_array::GenericArray;
+use digest::{Digest, FixedOutputReset};
+#[cfg(any(feature = "sha2", test))]
+use sha2::Sha256;
+
+/// Creates the initial hash chain and outputs the pebbles which can be used to traverse the chain.
+///
+/// Positions are numbered from the anchor: the value at position `length` is the hash of the
+/// seed, the value at position `i - 1` is the hash of the value at position `i`, and the anchor
+/// sits at position 0. Pebble `j` starts at position `2^j`; they are returned in that order.
+pub fn create_hash_chain<H: Digest + FixedOutputReset>(length: usize, seed: u64) -> Result<Vec<Pebble<H>>, ChainInitError>
+where
+    {
+    setup_chain::<H, _>(length, seed, |_, _| {}).map(|(pebbles, _)| pebbles)
+}
+
+/// Computes the pebbles of a chain together with its anchor in a single pass from the seed,
+/// calling `observe(position, value)` for every position from `length` down to 1.
+pub(crate) fn setup_chain<H: Digest + FixedOutputReset, F: FnMut(u64, &digest::Output<H>)>(length: usize, seed: u64, observe: F) -> Result<(Vec<Pebble<H>>, digest::Output<H>), ChainInitError> {
+    setup_chain_personalized::<H, F>(length, seed, &[], observe)
+}
+
+/// [`setup_chain`] with `personalization` mixed into every step, see
+/// [`HashChain::new_personalized`](crate::HashChain::new_personalized).
+pub(crate) fn setup_chain_personalized<H: Digest + FixedOutputReset, F: FnMut(u64, &digest::Output<H>)>(length: usize, seed: u64, personalization: &[u8], mut observe: F) -> Result<(Vec<Pebble<H>>, digest::Output<H>), ChainInitError> {
+    #[cfg(feature = "tracing")]
+    let _span = tracing::debug_span!("chain_setup", length).entered();
+    let mut setup = Setup::<H>::new_personalized(length, seed, personalization)?;
+    while setup.step(&mut observe) {}
+    #[cfg(feature = "tracing")]
+    tracing::debug!("chain set up");
+    Ok(setup.finish())
+}
+
+/// The pass of [`setup_chain`] one position at a time, so it can be interleaved with other work.
+pub(crate) struct Setup<H: Digest + FixedOutputReset> {
+    /// The position the next step handles, 0 once done.
+    next: u64,
+    powers: Vec<u64>,
+    pebbles: Vec<Pebble<H>>,
+    personalization: Vec<u8>,
+    hasher: H,
+    output: digest::Output<H>,
+}
+
+impl<H: Digest + FixedOutputReset> Setup<H> {
+    pub(crate) fn new(length: usize, seed: u64) -> Result<Self, ChainInitError> {
+        Self::new_personalized(length, seed, &[])
+    }
+
+    pub(crate) fn new_personalized(length: usize, seed: u64, personalization: &[u8]) -> Result<Self, ChainInitError> {
+        crate::params::check_chain_length(length as u64).map_err(ChainInitError::new)?;
+
+        // the number of pebbles is log_2(length)
+        let num_pebbles = log_2(length.try_into().unwrap());
+
+        // initialize list of powers so we dont need to compute each time
+        let powers = create_powers(num_pebbles);
+
+        let mut hasher = H::new();
+        crate::verify::personalize(&mut hasher, personalization);
+        digest::Digest::update(&mut hasher, seed.to_le_bytes());
+        let output = hasher.finalize_reset();
+        Ok(Setup { next: length as u64, powers, pebbles: Vec::with_capacity(num_pebbles as usize), personalization: personalization.to_vec(), hasher, output })
+    }
+
+    /// Handle the next position, walking from the seed end (position `length`) down to the
+    /// anchor. Returns whether there was a position left.
+    pub(crate) fn step<F: FnMut(u64, &digest::Output<H>)>(&mut self, observe: &mut F) -> bool {
+        let i = self.next;
+        if i == 0 {
+            return false;
+        }
+        observe(i, &self.output);
+        if i >= 2 && i.eq(self.powers.get(log_2(i) as usize - 1).unwrap()) {
+            self.pebbles.push(Pebble{
+                start_incr: 3*i,
+                dest_incr: 2u64*i,
+                position: i,
+                destination: i,
+                value: self.output.clone(),
+            });
+
+        }
+        crate::verify::personalize(&mut self.hasher, &self.personalization);
+        digest::Digest::update(&mut self.hasher, self.output.as_ref());
+        self.output = self.hasher.finalize_reset();
+        #[cfg(feature = "telemetry")]
+        crate::telemetry::record(crate::telemetry::Phase::Setup);
+        self.next -= 1;
+        true
+    }
+
+    /// The pebbles, pebble `j` at position `2^j` first, and the anchor.
+    pub(crate) fn finish(mut self) -> (Vec<Pebble<H>>, digest::Output<H>) {
+        self.pebbles.reverse();
+        (self.pebbles, self.output)
+    }
+}
+
+/// Recompute the chain from `seed` in constant memory and check that it ends in `anchor`.
+pub fn audit_chain<H: Digest + FixedOutputReset>(seed: u64, length: usize, anchor: &GenericArray<u8, H::OutputSize>) -> Result<(), ChainAuditError> {
+    audit_chain_with_pebbles::<H>(seed, length, anchor, &[])
+}
+
+/// Like [`audit_chain`], also checking that every pebble holds the chain value at its current
+/// position, e.g. the pebbles saved from [`create_hash_chain`] or a [`HashChain`](crate::HashChain) in progress.
+pub fn audit_chain_with_pebbles<H: Digest + FixedOutputReset>(seed: u64, length: usize, anchor: &GenericArray<u8, H::OutputSize>, pebbles: &[Pebble<H>]) -> Result<(), ChainAuditError> {
+    audit_personalized::<H>(seed, length, anchor, pebbles, &[])
+}
+
+pub(crate) fn audit_personalized<H: Digest + FixedOutputReset>(seed: u64, length: usize, anchor: &GenericArray<u8, H::OutputSize>, pebbles: &[Pebble<H>], personalization: &[u8]) -> Result<(), ChainAuditError> {
+    let mut by_position: Vec<&Pebble<H>> = pebbles.iter().collect();
+    by_position.sort_by_key(|pebble| core::cmp::Reverse(pebble.position));
+    let mut expected = by_position.into_iter().peekable();
+    let mut mismatch = None;
+    let (_, computed) = setup_chain_personalized::<H, _>(length, seed, personalization, |position, value| {
+        while let Some(pebble) = expected.next_if(|pebble| pebble.position == position) {
+            if pebble.value != *value && mismatch.is_none() {
+                mismatch = Some(position);
+            }
+        }
+    }).map_err(ChainAuditError::Length)?;
+    if let Some(position) = mismatch.or(expected.next().map(|pebble| pebble.position)) {
+        return Err(ChainAuditError::PebbleMismatch { position });
+    }
+    if computed != *anchor {
+        return Err(ChainAuditError::AnchorMismatch);
+    }
+    Ok(())
+}
+
+/// Create hash chain without using pebbles. Warning: the resulting array will be very large,
+/// specifically the length specified. Element `k` is the value at position `length - k`.
+pub fn create_hash_chain_nopebble<H: Digest + FixedOutputReset>(length: usize, seed: u64) -> Vec<GenericArray<u8, H::OutputSize>> {
+    forward_iter::<H>(seed).take(length).collect()
+}
+
+/// The values derived from `seed` in the order they are computed: `H(seed)`, `H(H(seed))`, and
+/// so on without end. For a chain of `length` values the `k`-th one (counting from 1) sits at
+/// position `length - k + 1`, so `forward_iter(seed).take(length)` runs from the seed end of the
+/// chain to position 1 in constant memory.
+pub fn forward_iter<H: Digest + FixedOutputReset>(seed: u64) -> ForwardIter<H> {
+    ForwardIter { hasher: H::new_with_prefix(seed.to_le_bytes()), next: None, steps: 0 }
+}
+
+/// The iterator from [`forward_iter`].
+pub struct ForwardIter<H: Digest + FixedOutputReset> {
+    hasher: H,
+    /// The value to hash next, `None` before the first.
+    next: Option<GenericArray<u8, H::OutputSize>>,
+    steps: u64,
+}
+
+impl<H: Digest + FixedOutputReset> ForwardIter<H> {
+    /// The number of values yielded so far.
+    pub fn steps(&self) -> u64 {
+        self.steps
+    }
+}
+
+impl<H: Digest + FixedOutputReset> Iterator for ForwardIter<H> {
+    type Item = GenericArray<u8, H::OutputSize>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if let Some(value) = &self.next {
+            digest::Digest::update(&mut self.hasher, value.as_slice());
+        }
+        let output = self.hasher.finalize_reset();
+        self.next = Some(output.clone());
+        self.steps += 1;
+        Some(output)
+    }
+}
+
+/// An opaque 16 byte identifier naming a chain in registries, stores and tokens.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct ChainId(pub [u8; 16]);
+
+impl Display for ChainId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:x}", crate::value::Hex(&self.0))
+    }
+}
+
+#[cfg(feature = "hex")]
+impl FromStr for ChainId {
+    type Err = hex::FromHexError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut id = [0u8; 16];
+        hex::decode_to_slice(s, &mut id)?;
+        Ok(ChainId(id))
+    }
+}
+
+impl ChainId {
+    /// The identifier of the chain with `anchor` and `length`: the first 16 bytes of SHA-256
+    /// over a domain tag, the anchor after a length byte and the length as u64 big endian.
+    ///
+    /// The inputs are public, so the registry, wire messages and stores can all name a chain by
+    /// its commitment without agreeing on a naming scheme. The hash is fixed rather than the
+    /// chain's own so identifiers keep their meaning across hash functions.
+    #[cfg(feature = "sha2")]
+    pub fn derive(anchor: &[u8], length: u64) -> ChainId {
+        debug_assert!(anchor.len() < 256, "anchors are shorter than 256 bytes");
+        let digest = Sha256::new_with_prefix(b"fractal-hash-traversal chain id")
+            .chain_update([anchor.len() as u8])
+            .chain_update(anchor)
+            .chain_update(length.to_be_bytes())
+            .finalize();
+        ChainId(digest[..16].try_into().expect("SHA-256 output is 32 bytes"))
+    }
+}
+
+#[test]
+fn test_chain_init() {
+    let len = 128;
+    let pebbles = create_hash_chain::<Sha256>(len, 0).unwrap();
+    assert_eq!(pebbles.len(), usize::try_from(log_2(len.try_into().unwrap())).unwrap());
+}
+
+#[cfg(all(feature = "sha2", feature = "hex"))]
+#[test]
+fn test_chain_id_derive() {
+    use crate::HashChain;
+
+    let chain = HashChain::<Sha256>::new(64, 1).unwrap();
+    let id = chain.chain_id();
+    assert_eq!(id, ChainId::derive(chain.anchor(), 64));
+    assert_ne!(id, ChainId::derive(chain.anchor(), 32));
+    assert_ne!(id, HashChain::<Sha256>::new(64, 2).unwrap().chain_id());
+    assert_eq!(id.to_string().parse::<ChainId>().unwrap(), id);
+}
+
+#[test]
+fn test_create_chain_small() {
+    let len = 128;
+    let chain = create_hash_chain_nopebble::<Sha256>(len, 0);
+    assert_eq!(len, chain.len());
+}
+
+#[test]
+fn test_audit_chain() {
+    use crate::HashChain;
+
+    let mut chain = HashChain::<Sha256>::new(64, 12).unwrap();
+    assert!(audit_chain::<Sha256>(12, 64, chain.anchor()).is_ok());
+    assert!(matches!(audit_chain::<Sha256>(13, 64, chain.anchor()), Err(ChainAuditError::AnchorMismatch)));
+    chain.nth(20);
+    assert!(chain.audit(12).is_ok());
+
+    let mut pebbles = create_hash_chain::<Sha256>(64, 12).unwrap();
+    assert!(audit_chain_with_pebbles::<Sha256>(12, 64, chain.anchor(), &pebbles).is_ok());
+    pebbles[2].value[0] ^= 1;
+    assert!(matches!(audit_chain_with_pebbles::<Sha256>(12, 64, chain.anchor(), &pebbles), Err(ChainAuditError::PebbleMismatch { position: 8 })));
+}
+
+#[test]
+fn test_forward_iter() {
+    use crate::HashChain;
+
+    let mut values = forward_iter::<Sha256>(9);
+    assert_eq!(values.by_ref().take(64).collect::<Vec<_>>(), create_hash_chain_nopebble::<Sha256>(64, 9));
+    assert_eq!(values.steps(), 64);
+    assert_eq!(values.next().unwrap(), *HashChain::<Sha256>::new(64, 9).unwrap().anchor());
+}
@@ -0,0 +1,230 @@
+//! An append-only log anchored in a hash chain.
+//!
+//! Record `i` is MACed with chain value `i` over its data and the [`Mmr`] root of the records
+//! before it, and carries chain value `i - 1`, which authenticates the previous record. The
+//! auditor knows only the anchor: it checks each disclosed value against the last one, the
+//! previous record's tag against the disclosed value, and the root against the history it has
+//! replayed into [`Peaks`], so its memory does not grow with the log. [`LogWriter::seal`]
+//! discloses the value for the last record.
+//!
+//! A record cannot be forged before its value is disclosed, and after that it is fixed by the
+//! roots the auditor has already seen. Taking over the writer gains nothing either: the chain
+//! values for past records are public already, and the roots pin down the history. As with
+//! [`seclog`](crate::seclog), records cut off the end go unnoticed unless the auditor learns
+//! the expected length some other way.
+
+use crate::mmr::{Mmr, Peaks};
+use crate::HashChain;
+use digest::core_api::BlockSizeUser;
+use digest::generic_array::GenericArray;
+use digest::{Digest, FixedOutputReset, Mac, OutputSizeUser};
+use hmac::SimpleHmac;
+use std::error::Error;
+use std::fmt::{self, Display};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ChainLogError {
+    /// The chain has no values left.
+    Exhausted,
+    /// The record does not have the next index.
+    OutOfOrder { expected: u64 },
+    /// The record was made over a different history.
+    RootMismatch { index: u64 },
+    /// The disclosed chain value does not hash to the last one.
+    BadKey { index: u64 },
+    /// The record's tag does not verify.
+    Forged { index: u64 },
+}
+
+impl Display for ChainLogError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ChainLogError::Exhausted => write!(f, "hash chain exhausted"),
+            ChainLogError::OutOfOrder { expected } => write!(f, "expected record {}", expected),
+            ChainLogError::RootMismatch { index } => write!(f, "record {} has an unexpected history root", index),
+            ChainLogError::BadKey { index } => write!(f, "record {} discloses an invalid chain value", index),
+            ChainLogError::Forged { index } => write!(f, "record {} failed authentication", index),
+        }
+    }
+}
+
+impl Error for ChainLogError {}
+
+pub struct Record<H: OutputSizeUser> {
+    /// The chain position the record is MACed with, from 1.
+    pub index: u64,
+    pub data: Vec<u8>,
+    /// The root over all earlier records.
+    pub root: GenericArray<u8, H::OutputSize>,
+    pub tag: Vec<u8>,
+    /// Chain value `index - 1`; the anchor for the first record.
+    pub disclosed: GenericArray<u8, H::OutputSize>,
+}
+
+impl<H: OutputSizeUser> Clone for Record<H> {
+    fn clone(&self) -> Self {
+        Record { index: self.index, data: self.data.clone(), root: self.root.clone(), tag: self.tag.clone(), disclosed: self.disclosed.clone() }
+    }
+}
+
+/// The disclosure of the value for the last record.
+pub struct Seal<H: OutputSizeUser> {
+    pub index: u64,
+    pub value: GenericArray<u8, H::OutputSize>,
+}
+
+fn record_tag<H: Digest + BlockSizeUser>(key: &[u8], index: u64, root: &[u8], data: &[u8]) -> SimpleHmac<H> {
+    let mut mac = <SimpleHmac<H> as Mac>::new_from_slice(key).expect("HMAC accepts any key length");
+    mac.update(b"chainlog record");
+    mac.update(&index.to_be_bytes());
+    mac.update(root);
+    mac.update(data);
+    mac
+}
+
+/// What the accumulator commits to for a record.
+fn leaf_bytes<H: OutputSizeUser>(record: &Record<H>) -> Vec<u8> {
+    let mut bytes = Vec::with_capacity(12 + record.data.len() + record.tag.len());
+    bytes.extend_from_slice(&record.index.to_be_bytes());
+    bytes.extend_from_slice(&(record.data.len() as u32).to_be_bytes());
+    bytes.extend_from_slice(&record.data);
+    bytes.extend_from_slice(&record.tag);
+    bytes
+}
+
+pub struct LogWriter<H: Digest + FixedOutputReset> {
+    chain: HashChain<H>,
+    history: Mmr<H>,
+    last: GenericArray<u8, H::OutputSize>,
+}
+
+impl<H: Digest + FixedOutputReset + BlockSizeUser> LogWriter<H> {
+    pub fn new(chain: HashChain<H>) -> Self {
+        let last = chain.anchor().clone();
+        LogWriter { chain, history: Mmr::new(), last }
+    }
+
+    pub fn anchor(&self) -> &GenericArray<u8, H::OutputSize> {
+        self.chain.anchor()
+    }
+
+    /// The root over all records written so far.
+    pub fn root(&self) -> GenericArray<u8, H::OutputSize> {
+        self.history.root()
+    }
+
+    /// The full history, to prove single records to third parties.
+    pub fn history(&self) -> &Mmr<H> {
+        &self.history
+    }
+
+    pub fn append(&mut self, data: &[u8]) -> Result<Record<H>, ChainLogError> {
+        let (index, value) = self.chain.next().ok_or(ChainLogError::Exhausted)?;
+        let root = self.history.root();
+        let tag = record_tag::<H>(&value, index, &root, data).finalize().into_bytes().to_vec();
+        let disclosed = std::mem::replace(&mut self.last, value.to_array());
+        let record = Record { index, data: data.to_vec(), root, tag, disclosed };
+        self.history.append(&leaf_bytes(&record));
+        Ok(record)
+    }
+
+    /// Disclose the value for the last record, so that it can be verified too.
+    pub fn seal(&self) -> Seal<H> {
+        Seal { index: HashChain::position(&self.chain), value: self.last.clone() }
+    }
+}
+
+pub struct LogAuditor<H: OutputSizeUser> {
+    /// The last verified chain value and its position.
+    key_index: u64,
+    key: GenericArray<u8, H::OutputSize>,
+    history: Peaks<H>,
+    /// The last record, waiting for its value.
+    pending: Option<Record<H>>,
+}
+
+impl<H: Digest + BlockSizeUser> LogAuditor<H> {
+    pub fn new(anchor: GenericArray<u8, H::OutputSize>) -> Self {
+        LogAuditor { key_index: 0, key: anchor, history: Peaks::new(), pending: None }
+    }
+
+    /// The number of records verified.
+    pub fn verified(&self) -> u64 {
+        self.key_index
+    }
+
+    /// The root over all records received, verified or not.
+    pub fn root(&self) -> GenericArray<u8, H::OutputSize> {
+        self.history.root()
+    }
+
+    /// Check the disclosed value for the pending record and then the record's tag.
+    fn release(&mut self, index: u64, value: &GenericArray<u8, H::OutputSize>) -> Result<(), ChainLogError> {
+        let Some(pending) = &self.pending else {
+            return if *value == self.key { Ok(()) } else { Err(ChainLogError::BadKey { index }) };
+        };
+        if H::digest(value) != self.key {
+            return Err(ChainLogError::BadKey { index });
+        }
+        if record_tag::<H>(value, pending.index, &pending.root, &pending.data).verify_slice(&pending.tag).is_err() {
+            return Err(ChainLogError::Forged { index: pending.index });
+        }
+        self.key_index = pending.index;
+        self.key = value.clone();
+        self.pending = None;
+        Ok(())
+    }
+
+    /// Take the next record, verifying the one before it.
+    pub fn audit(&mut self, record: &Record<H>) -> Result<(), ChainLogError> {
+        let expected = self.history.leaves() + 1;
+        if record.index != expected {
+            return Err(ChainLogError::OutOfOrder { expected });
+        }
+        if record.root != self.history.root() {
+            return Err(ChainLogError::RootMismatch { index: record.index });
+        }
+        self.release(record.index, &record.disclosed)?;
+        self.history.append(&leaf_bytes(record));
+        self.pending = Some(record.clone());
+        Ok(())
+    }
+
+    /// Verify the last record from the writer's seal.
+    pub fn seal(&mut self, seal: &Seal<H>) -> Result<(), ChainLogError> {
+        if seal.index != self.history.leaves() {
+            return Err(ChainLogError::OutOfOrder { expected: self.history.leaves() });
+        }
+        self.release(seal.index, &seal.value)
+    }
+}
+
+#[test]
+fn test_chainlog_audit() {
+    use sha2::Sha256;
+
+    let mut writer = LogWriter::new(HashChain::<Sha256>::new(16, 3).unwrap());
+    let records: Vec<_> = ["boot", "login", "shutdown"].iter().map(|line| writer.append(line.as_bytes()).unwrap()).collect();
+
+    let mut auditor = LogAuditor::<Sha256>::new(*writer.anchor());
+    for record in &records {
+        auditor.audit(record).unwrap();
+    }
+    assert_eq!(auditor.verified(), 2);
+    auditor.seal(&writer.seal()).unwrap();
+    assert_eq!(auditor.verified(), 3);
+    assert_eq!(auditor.root(), writer.root());
+    assert!(writer.history().prove(1).unwrap().verify(&writer.root(), &leaf_bytes(&records[1])));
+
+    // the next record was made over the history with the original data
+    let mut auditor = LogAuditor::<Sha256>::new(*writer.anchor());
+    let mut altered = records[0].clone();
+    altered.data = b"reboot".to_vec();
+    auditor.audit(&altered).unwrap();
+    assert_eq!(auditor.audit(&records[1]), Err(ChainLogError::RootMismatch { index: 2 }));
+
+    // a dropped record shows up in the index and the root
+    let mut auditor = LogAuditor::<Sha256>::new(*writer.anchor());
+    auditor.audit(&records[0]).unwrap();
+    assert_eq!(auditor.audit(&records[2]), Err(ChainLogError::OutOfOrder { expected: 2 }));
+}
@@ -0,0 +1,387 @@
+//! Many provers behind one handle, for servers that hold a chain per user.
+//!
+//! A [`ChainSet`] keeps the traversal state of each chain in a [`StateStore`] under its
+//! [`ChainId`], as exported by [`HashChain::export_state`], and caches the chains it has
+//! touched in memory. Each disclosure is saved before its value is returned, so a restarted
+//! server loads every chain where it stopped. Calls on different chains run in parallel; calls
+//! on the same chain take turns.
+//!
+//! Chains are replaced before they run out by rotating them: the set provisions a successor and
+//! spends one value of the old chain on a [`Link`] vouching for the successor's anchor, which
+//! verifiers apply like any other disclosure. The old chain is then retired, and its id reports
+//! [`ChainSetError::Retired`] from then on. Rotation happens on demand with [`ChainSet::rotate`]
+//! or whenever a chain's [`RotationPolicy`] says so in [`ChainSet::rotate_due`].
+
+use crate::registry::{ChainRecord, Registry, VerifyError};
+use crate::store::StateStore;
+use crate::{verify, ChainId, HashChain};
+use crate::value::ChainValue;
+use digest::{Digest, FixedOutputReset, Output};
+use hkdf::Hkdf;
+use sha2::Sha256;
+use std::collections::HashMap;
+use std::error::Error;
+use std::fmt::{self, Display};
+use std::sync::{Arc, Mutex, RwLock};
+use std::time::{Duration, SystemTime};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ChainSetError {
+    /// No chain is stored under the id.
+    UnknownChain,
+    /// A chain is already stored under the id.
+    Duplicate,
+    /// Every value of the chain has been disclosed.
+    Exhausted,
+    /// The chain has been rotated out.
+    Retired,
+    /// The requested chain cannot be set up.
+    InvalidLength(String),
+    /// The stored state does not decode.
+    InvalidState(String),
+    Store(String),
+}
+
+impl Display for ChainSetError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ChainSetError::UnknownChain => write!(f, "unknown chain"),
+            ChainSetError::Duplicate => write!(f, "chain already exists"),
+            ChainSetError::Exhausted => write!(f, "chain is exhausted"),
+            ChainSetError::Retired => write!(f, "chain has been retired"),
+            ChainSetError::InvalidLength(details) => write!(f, "invalid chain length: {}", details),
+            ChainSetError::InvalidState(details) => write!(f, "invalid stored state: {}", details),
+            ChainSetError::Store(details) => write!(f, "store failed: {}", details),
+        }
+    }
+}
+
+impl Error for ChainSetError {}
+
+/// The position of one chain in a set.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ChainStatus {
+    pub chain_id: ChainId,
+    pub position: u64,
+    pub remaining: u64,
+}
+
+/// The seed of `user_id`'s chain under `master_seed`: the first 8 bytes, little endian, that
+/// HKDF-SHA-256 expands from `master_seed` with the user id as info. Whoever holds the master
+/// seed can recompute any user's chain from it.
+pub fn derive_seed(master_seed: &[u8], user_id: &ChainId) -> u64 {
+    let mut seed = [0; 8];
+    Hkdf::<Sha256>::new(Some(b"fractal-hash-traversal provisioning"), master_seed)
+        .expand(&user_id.0, &mut seed)
+        .expect("8 bytes is a valid output length");
+    u64::from_le_bytes(seed)
+}
+
+/// When to replace a chain. Policies are configuration rather than state: they live in memory
+/// and are set again when a server starts.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RotationPolicy {
+    /// Rotate once this percentage of the values has been disclosed.
+    pub max_consumption: Option<u8>,
+    /// Rotate once a chain has been in use this long.
+    pub lifetime: Option<Duration>,
+    /// The length of successor chains.
+    pub successor_length: usize,
+}
+
+impl RotationPolicy {
+    fn is_due<H: Digest + FixedOutputReset>(&self, chain: &HashChain<H>, expires: Option<SystemTime>, now: SystemTime) -> bool {
+        let consumed = self.max_consumption.is_some_and(|percent| HashChain::position(chain) * 100 >= chain.length() * percent as u64);
+        consumed || expires.is_some_and(|expires| now >= expires)
+    }
+}
+
+struct Rotation {
+    policy: RotationPolicy,
+    expires: Option<SystemTime>,
+}
+
+/// The successor of a rotated chain, vouched for by a value of its predecessor.
+pub struct Link<H: Digest + FixedOutputReset> {
+    pub predecessor: ChainId,
+    pub index: u64,
+    pub value: Output<H>,
+    pub successor: ChainId,
+    pub anchor: Output<H>,
+}
+
+impl<H: Digest + FixedOutputReset> Link<H> {
+    /// Accept the predecessor's value and enroll the successor under its anchor, in the
+    /// predecessor's domain if it was registered under one. Like any disclosure the value is
+    /// accepted only once, so of two links spending it the first wins.
+    pub fn apply<S>(&self, registry: &Registry<H, S>) -> Result<(), VerifyError>
+    where
+        S: StateStore<Key = ChainId, State = ChainRecord<H>>,
+    {
+        let domain = registry.record(&self.predecessor)?.domain;
+        registry.verify(&self.predecessor, self.index, &self.value)?;
+        registry.register_with(self.successor, self.anchor.clone(), domain.as_deref())
+    }
+}
+
+pub struct ChainSet<H: Digest + FixedOutputReset, S> {
+    store: S,
+    chains: RwLock<HashMap<ChainId, Arc<Mutex<HashChain<H>>>>>,
+    rotations: Mutex<HashMap<ChainId, Rotation>>,
+}
+
+impl<H, S> ChainSet<H, S>
+where
+    H: Digest + FixedOutputReset,
+    S: StateStore<Key = ChainId, State = Vec<u8>>,
+{
+    pub fn new(store: S) -> Self {
+        ChainSet { store, chains: RwLock::new(HashMap::new()), rotations: Mutex::new(HashMap::new()) }
+    }
+
+    fn store_error(e: S::Error) -> ChainSetError {
+        ChainSetError::Store(e.to_string())
+    }
+
+    /// Set up a chain of `length` values from `seed` under `chain_id`, returning its anchor.
+    pub fn create(&self, chain_id: ChainId, length: usize, seed: u64) -> Result<Output<H>, ChainSetError> {
+        let chain = HashChain::<H>::new(length, seed).map_err(|e| ChainSetError::InvalidLength(e.to_string()))?;
+        self.insert(chain_id, chain)
+    }
+
+    /// Add an existing chain under its derived [`HashChain::chain_id`], returning the identifier
+    /// and the anchor.
+    pub fn insert_derived(&self, chain: HashChain<H>) -> Result<(ChainId, Output<H>), ChainSetError> {
+        let chain_id = chain.chain_id();
+        Ok((chain_id, self.insert(chain_id, chain)?))
+    }
+
+    /// Add an existing chain under `chain_id`, returning its anchor.
+    pub fn insert(&self, chain_id: ChainId, chain: HashChain<H>) -> Result<Output<H>, ChainSetError> {
+        let anchor = chain.anchor().clone();
+        if !self.store.compare_and_swap(&chain_id, None, chain.export_state()).map_err(Self::store_error)? {
+            return Err(ChainSetError::Duplicate);
+        }
+        self.chains.write().unwrap().insert(chain_id, Arc::new(Mutex::new(chain)));
+        Ok(anchor)
+    }
+
+    /// The chain under `chain_id`, loaded from the store on first use.
+    fn chain(&self, chain_id: &ChainId) -> Result<Arc<Mutex<HashChain<H>>>, ChainSetError> {
+        if let Some(chain) = self.chains.read().unwrap().get(chain_id) {
+            return Ok(chain.clone());
+        }
+        let state = self.store.load(chain_id).map_err(Self::store_error)?.ok_or(ChainSetError::UnknownChain)?;
+        if state.is_empty() {
+            return Err(ChainSetError::Retired);
+        }
+        let chain = HashChain::import_state(&state).map_err(|e| ChainSetError::InvalidState(e.to_string()))?;
+        // another caller may have loaded it meanwhile; keep theirs, which may be further along
+        Ok(self.chains.write().unwrap().entry(*chain_id).or_insert_with(|| Arc::new(Mutex::new(chain))).clone())
+    }
+
+    /// Disclose the next value of a chain, after saving its advanced state.
+    pub fn next(&self, chain_id: &ChainId) -> Result<(u64, ChainValue<H>), ChainSetError> {
+        let chain = self.chain(chain_id)?;
+        let mut chain = chain.lock().unwrap();
+        let mut advanced = chain.clone();
+        let disclosed = advanced.disclose().ok_or(ChainSetError::Exhausted)?;
+        self.store.save(chain_id, advanced.export_state()).map_err(Self::store_error)?;
+        #[cfg(feature = "tracing")]
+        tracing::debug!(%chain_id, position = disclosed.0, "chain state saved");
+        *chain = advanced;
+        Ok(disclosed)
+    }
+
+    /// Whether `value` is the value of a chain at `index` and has already been disclosed.
+    pub fn verify(&self, chain_id: &ChainId, index: u64, value: &Output<H>) -> Result<bool, ChainSetError> {
+        let chain = self.chain(chain_id)?;
+        let chain = chain.lock().unwrap();
+        Ok(index <= HashChain::position(&chain) && verify::<H>(0, chain.anchor(), index, value))
+    }
+
+    pub fn anchor(&self, chain_id: &ChainId) -> Result<Output<H>, ChainSetError> {
+        Ok(self.chain(chain_id)?.lock().unwrap().anchor().clone())
+    }
+
+    /// Drop a chain from memory; it is loaded from the store again on next use.
+    pub fn evict(&self, chain_id: &ChainId) {
+        self.chains.write().unwrap().remove(chain_id);
+    }
+
+    /// The chains held in memory, in no particular order.
+    pub fn loaded(&self) -> Vec<ChainStatus> {
+        self.chains.read().unwrap().iter().map(|(chain_id, chain)| {
+            let chain = chain.lock().unwrap();
+            ChainStatus { chain_id: *chain_id, position: HashChain::position(&chain), remaining: chain.remaining() }
+        }).collect()
+    }
+
+    /// Disclose the next value of each chain, with one result per id in the same order.
+    pub fn next_many(&self, chain_ids: &[ChainId]) -> Vec<Result<(u64, ChainValue<H>), ChainSetError>> {
+        chain_ids.iter().map(|chain_id| self.next(chain_id)).collect()
+    }
+
+    /// Verify several `(chain id, index, value)` claims, with one result per claim.
+    pub fn verify_many(&self, claims: &[(ChainId, u64, Output<H>)]) -> Vec<Result<bool, ChainSetError>> {
+        claims.iter().map(|(chain_id, index, value)| self.verify(chain_id, *index, value)).collect()
+    }
+
+    /// Provision a chain of `length` values for each of `user_ids`, each from the seed
+    /// [`derive_seed`] gives for it under `master_seed`, and return the anchors to distribute in
+    /// the same order. Setup runs on all available cores. The ids are checked up front, but a
+    /// store failure part way leaves the chains before it provisioned.
+    pub fn provision_batch(&self, user_ids: &[ChainId], length: usize, master_seed: &[u8]) -> Result<Vec<(ChainId, Output<H>)>, ChainSetError>
+    where
+        H: Send,
+    {
+        for user_id in user_ids {
+            if self.store.load(user_id).map_err(Self::store_error)?.is_some() {
+                return Err(ChainSetError::Duplicate);
+            }
+        }
+        let workers = std::thread::available_parallelism().map_or(1, |n| n.get());
+        let chains: Vec<HashChain<H>> = std::thread::scope(|scope| {
+            let batches: Vec<_> = user_ids.chunks(user_ids.len().div_ceil(workers).max(1)).map(|batch| scope.spawn(move || {
+                batch.iter().map(|user_id| HashChain::<H>::new(length, derive_seed(master_seed, user_id))).collect::<Result<Vec<_>, _>>()
+            })).collect();
+            batches.into_iter().map(|batch| batch.join().expect("chain setup does not panic")).collect::<Result<Vec<_>, _>>()
+        }).map_err(|e| ChainSetError::InvalidLength(e.to_string()))?.into_iter().flatten().collect();
+        user_ids.iter().zip(chains).map(|(user_id, chain)| Ok((*user_id, self.insert(*user_id, chain)?))).collect()
+    }
+
+    /// Rotate `chain_id` according to `policy` from now on, its lifetime counting from `now`.
+    pub fn set_policy(&self, chain_id: ChainId, policy: RotationPolicy, now: SystemTime) {
+        let expires = policy.lifetime.map(|lifetime| now + lifetime);
+        self.rotations.lock().unwrap().insert(chain_id, Rotation { policy, expires });
+    }
+
+    /// Replace `chain_id` by a new chain of `length` values from `seed` under `successor`,
+    /// spending the old chain's next value on the link, and retire the old chain. A policy set
+    /// for the old chain carries over to the successor, with its lifetime counting from `now`.
+    pub fn rotate(&self, chain_id: &ChainId, successor: ChainId, length: usize, seed: u64, now: SystemTime) -> Result<Link<H>, ChainSetError> {
+        let chain = HashChain::<H>::new(length, seed).map_err(|e| ChainSetError::InvalidLength(e.to_string()))?;
+        if self.store.load(&successor).map_err(Self::store_error)?.is_some() {
+            return Err(ChainSetError::Duplicate);
+        }
+        let (index, value) = self.next(chain_id)?;
+        let anchor = self.insert(successor, chain)?;
+
+        // the tombstone is what keeps a restarted server from disclosing the old chain again
+        self.store.save(chain_id, Vec::new()).map_err(Self::store_error)?;
+        self.chains.write().unwrap().remove(chain_id);
+        let mut rotations = self.rotations.lock().unwrap();
+        if let Some(rotation) = rotations.remove(chain_id) {
+            let expires = rotation.policy.lifetime.map(|lifetime| now + lifetime);
+            rotations.insert(successor, Rotation { policy: rotation.policy, expires });
+        }
+        Ok(Link { predecessor: *chain_id, index, value: value.to_array(), successor, anchor })
+    }
+
+    /// Rotate every chain whose policy is due at `now`, taking the id and seed of each successor
+    /// from `successor`. Chains without a policy are left alone.
+    pub fn rotate_due(&self, now: SystemTime, mut successor: impl FnMut(&ChainId) -> (ChainId, u64)) -> Vec<Result<Link<H>, ChainSetError>> {
+        let due: Vec<(ChainId, usize)> = {
+            let rotations = self.rotations.lock().unwrap();
+            rotations.iter().filter_map(|(chain_id, rotation)| {
+                let due = self.chain(chain_id).map(|chain| rotation.policy.is_due(&chain.lock().unwrap(), rotation.expires, now)).unwrap_or(false);
+                due.then_some((*chain_id, rotation.policy.successor_length))
+            }).collect()
+        };
+        due.into_iter().map(|(chain_id, length)| {
+            let (next_id, seed) = successor(&chain_id);
+            self.rotate(&chain_id, next_id, length, seed, now)
+        }).collect()
+    }
+}
+
+#[test]
+fn test_chain_set() {
+    use crate::store::MemoryStore;
+
+    let store = Arc::new(MemoryStore::new());
+    let set = ChainSet::<Sha256, _>::new(store.clone());
+    let (alice, bob) = (ChainId([1; 16]), ChainId([2; 16]));
+    set.create(alice, 4, 1).unwrap();
+    let bob_anchor = set.create(bob, 8, 2).unwrap();
+    assert_eq!(set.create(alice, 4, 3), Err(ChainSetError::Duplicate));
+    assert_eq!(set.next(&ChainId([3; 16])), Err(ChainSetError::UnknownChain));
+    let (carol, carol_anchor) = set.insert_derived(HashChain::new(4, 4).unwrap()).unwrap();
+    assert_eq!(carol, ChainId::derive(&carol_anchor, 4));
+
+    let results = set.next_many(&[alice, bob, alice]);
+    assert_eq!(results.iter().map(|r| r.as_ref().unwrap().0).collect::<Vec<_>>(), vec![1, 1, 2]);
+    let (index, value) = results[1].clone().unwrap();
+    assert_eq!(set.verify(&bob, index, &value), Ok(true));
+    assert_eq!(set.verify(&alice, index, &value), Ok(false));
+
+    // a fresh set over the same store picks up where this one stopped
+    let restarted = ChainSet::<Sha256, _>::new(store);
+    assert_eq!(restarted.anchor(&bob), Ok(bob_anchor));
+    assert_eq!(restarted.next(&alice).unwrap().0, 3);
+    restarted.next(&alice).unwrap();
+    assert_eq!(restarted.next(&alice), Err(ChainSetError::Exhausted));
+    let mut loaded = restarted.loaded();
+    loaded.sort_by_key(|status| status.chain_id.0);
+    assert_eq!(loaded, vec![
+        ChainStatus { chain_id: alice, position: 4, remaining: 0 },
+        ChainStatus { chain_id: bob, position: 1, remaining: 7 },
+    ]);
+}
+
+#[test]
+fn test_chain_rotation() {
+    use crate::store::MemoryStore;
+
+    let start = SystemTime::UNIX_EPOCH + Duration::from_secs(1_000_000);
+    let set = ChainSet::<Sha256, _>::new(MemoryStore::new());
+    let registry = Registry::<Sha256, _>::new(MemoryStore::new());
+    let (worn, young, fresh) = (ChainId([1; 16]), ChainId([2; 16]), ChainId([3; 16]));
+    registry.register(worn, set.create(worn, 8, 1).unwrap()).unwrap();
+    registry.register(young, set.create(young, 8, 2).unwrap()).unwrap();
+    let policy = RotationPolicy { max_consumption: Some(75), lifetime: Some(Duration::from_secs(3600)), successor_length: 16 };
+    set.set_policy(worn, policy, start);
+    set.set_policy(young, policy, start);
+    for _ in 0..6 {
+        let (index, value) = set.next(&worn).unwrap();
+        registry.verify(&worn, index, &value).unwrap();
+    }
+
+    let successor_of = |id: &ChainId| (ChainId([id.0[0] + 10; 16]), id.0[0] as u64);
+    let links = set.rotate_due(start + Duration::from_secs(60), successor_of);
+    assert_eq!(links.len(), 1);
+    let link = links.into_iter().next().unwrap().unwrap();
+    assert_eq!((link.predecessor, link.index, link.successor), (worn, 7, ChainId([11; 16])));
+    link.apply(&registry).unwrap();
+    assert_eq!(set.next(&worn), Err(ChainSetError::Retired));
+    let (index, value) = set.next(&link.successor).unwrap();
+    registry.verify(&link.successor, index, &value).unwrap();
+
+    // after an hour the young chain expires, and so far the successor has not
+    let links = set.rotate_due(start + Duration::from_secs(3600), successor_of);
+    assert_eq!(links.iter().map(|link| link.as_ref().unwrap().predecessor).collect::<Vec<_>>(), vec![young]);
+    links[0].as_ref().unwrap().apply(&registry).unwrap();
+
+    // and on demand, a link cannot be spent twice
+    let link = set.rotate(&ChainId([12; 16]), fresh, 8, 3, start).unwrap();
+    link.apply(&registry).unwrap();
+    assert_eq!(link.apply(&registry), Err(VerifyError::Replay));
+}
+
+#[test]
+fn test_provision_batch() {
+    use crate::store::MemoryStore;
+
+    let set = ChainSet::<Sha256, _>::new(MemoryStore::new());
+    let users: Vec<ChainId> = (0..20u8).map(|i| ChainId([i; 16])).collect();
+    let manifest = set.provision_batch(&users, 16, b"master seed").unwrap();
+    assert_eq!(manifest.iter().map(|(user, _)| *user).collect::<Vec<_>>(), users);
+    for (user, anchor) in &manifest {
+        let expected = HashChain::<Sha256>::new(16, derive_seed(b"master seed", user)).unwrap();
+        assert_eq!(anchor, expected.anchor());
+    }
+    assert_ne!(derive_seed(b"master seed", &users[0]), derive_seed(b"master seed", &users[1]));
+    assert_eq!(set.next(&users[7]).unwrap().0, 1);
+    assert_eq!(set.provision_batch(&users[5..6], 16, b"other seed"), Err(ChainSetError::Duplicate));
+    assert!(matches!(set.provision_batch(&[ChainId([99; 16])], 15, b"master seed"), Err(ChainSetError::InvalidLength(_))));
+}
@@ -0,0 +1,153 @@
+//! Fault injection for testing recovery paths against realistic state corruption.
+//!
+//! [`Fault::apply`] damages a chain state in [`HashChain::export_state`](crate::HashChain::export_state)
+//! encoding the way storage does: a pebble lost to a bad record, a checkpoint cut short, a write
+//! torn half way between the old state and the new one, a flipped bit. [`ChaosStore`] wraps a
+//! [`StateStore`] and applies faults to chosen writes while reporting them as successful, so the
+//! damage only shows when the state is loaded again.
+//!
+//! Nothing here belongs in production builds; it is behind the `chaos` feature.
+
+use crate::store::StateStore;
+use digest::Digest;
+use std::collections::BTreeMap;
+use std::marker::PhantomData;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Mutex;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Fault {
+    /// Drop the pebble at this offset of the pebble list, keeping the encoding well formed.
+    LosePebble(usize),
+    /// Keep only this many bytes.
+    Truncate(usize),
+    /// Keep this many bytes of the new state followed by the rest of the previous one.
+    TornWrite(usize),
+    /// Flip bit `bit` of byte `byte`.
+    FlipBit { byte: usize, bit: u8 },
+}
+
+impl Fault {
+    /// The state `state` damaged by the fault, where `previous` is what it overwrites. Faults
+    /// that do not fit the state, e.g. losing a pebble it does not have, leave it unchanged.
+    pub fn apply<H: Digest>(&self, state: &[u8], previous: Option<&[u8]>) -> Vec<u8> {
+        let mut damaged = state.to_vec();
+        match *self {
+            Fault::LosePebble(pebble) => {
+                let n = <H as Digest>::output_size();
+                let Some(&count) = state.get(16 + n) else {
+                    return damaged;
+                };
+                if pebble < count as usize {
+                    let at = 17 + n + pebble * (32 + n);
+                    damaged.drain(at..(at + 32 + n).min(state.len()));
+                    damaged[16 + n] = count - 1;
+                }
+            }
+            Fault::Truncate(length) => damaged.truncate(length),
+            Fault::TornWrite(length) => {
+                damaged.truncate(length);
+                if let Some(rest) = previous.and_then(|previous| previous.get(length..)) {
+                    damaged.extend_from_slice(rest);
+                }
+            }
+            Fault::FlipBit { byte, bit } => {
+                if let Some(byte) = damaged.get_mut(byte) {
+                    *byte ^= 1 << (bit % 8);
+                }
+            }
+        }
+        damaged
+    }
+}
+
+/// A [`StateStore`] of encoded chain states that damages chosen writes. Writes, both saves and
+/// swaps that go ahead, are counted from 1.
+pub struct ChaosStore<H, S> {
+    inner: S,
+    writes: AtomicU64,
+    faults: Mutex<BTreeMap<u64, Fault>>,
+    _hash: PhantomData<H>,
+}
+
+impl<H: Digest, S: StateStore<State = Vec<u8>>> ChaosStore<H, S> {
+    pub fn new(inner: S) -> Self {
+        ChaosStore { inner, writes: AtomicU64::new(0), faults: Mutex::new(BTreeMap::new()), _hash: PhantomData }
+    }
+
+    /// Damage write number `write` with `fault`.
+    pub fn inject(&self, write: u64, fault: Fault) {
+        self.faults.lock().unwrap().insert(write, fault);
+    }
+
+    /// The number of writes so far.
+    pub fn writes(&self) -> u64 {
+        self.writes.load(Ordering::Acquire)
+    }
+
+    pub fn into_inner(self) -> S {
+        self.inner
+    }
+
+    fn damage(&self, key: &S::Key, state: Vec<u8>) -> Result<Vec<u8>, S::Error> {
+        let write = self.writes.fetch_add(1, Ordering::AcqRel) + 1;
+        let Some(fault) = self.faults.lock().unwrap().remove(&write) else {
+            return Ok(state);
+        };
+        let previous = self.inner.load(key)?;
+        Ok(fault.apply::<H>(&state, previous.as_deref()))
+    }
+}
+
+impl<H: Digest, S: StateStore<State = Vec<u8>>> StateStore for ChaosStore<H, S> {
+    type Key = S::Key;
+    type State = Vec<u8>;
+    type Error = S::Error;
+
+    fn load(&self, key: &S::Key) -> Result<Option<Vec<u8>>, S::Error> {
+        self.inner.load(key)
+    }
+
+    fn save(&self, key: &S::Key, state: Vec<u8>) -> Result<(), S::Error> {
+        let state = self.damage(key, state)?;
+        self.inner.save(key, state)
+    }
+
+    fn compare_and_swap(&self, key: &S::Key, expected: Option<&Vec<u8>>, new: Vec<u8>) -> Result<bool, S::Error> {
+        if self.inner.load(key)?.as_ref() != expected {
+            return Ok(false);
+        }
+        let new = self.damage(key, new)?;
+        self.inner.compare_and_swap(key, expected, new)
+    }
+}
+
+#[test]
+fn test_chaos_faults() {
+    use crate::chainset::{ChainSet, ChainSetError};
+    use crate::store::MemoryStore;
+    use crate::{ChainId, HashChain};
+    use sha2::Sha256;
+
+    let mut chain = HashChain::<Sha256>::new(16, 4).unwrap();
+    let state = chain.export_state();
+    let lost = Fault::LosePebble(1).apply::<Sha256>(&state, None);
+    assert_eq!(lost.len(), state.len() - 64);
+    assert!(HashChain::<Sha256>::import_state(&lost).is_err());
+    assert!(HashChain::<Sha256>::import_state(&Fault::Truncate(40).apply::<Sha256>(&state, None)).is_err());
+    chain.disclose();
+    let torn = Fault::TornWrite(12).apply::<Sha256>(&chain.export_state(), Some(&state));
+    assert_eq!((&torn[..12], &torn[12..]), (&chain.export_state()[..12], &state[12..]));
+
+    // the second write, the first disclosure's, is cut short and the restarted set notices
+    let store = ChaosStore::<Sha256, _>::new(MemoryStore::new());
+    store.inject(2, Fault::Truncate(20));
+    let store = std::sync::Arc::new(store);
+    let set = ChainSet::<Sha256, _>::new(store.clone());
+    let id = ChainId([1; 16]);
+    set.create(id, 16, 4).unwrap();
+    set.next(&id).unwrap();
+    assert_eq!(store.writes(), 2);
+    let restarted = ChainSet::<Sha256, _>::new(store);
+    assert!(matches!(restarted.next(&id), Err(ChainSetError::InvalidState(_))));
+}
@@ -0,0 +1,115 @@
+//! Verification against provisioned checkpoints. Besides the anchor the verifier stores a few
+//! chain values at known positions, so a disclosure far past the last accepted one is hashed
+//! down only to the nearest checkpoint below it. With checkpoints every `k` positions no
+//! verification takes more than `k` hashes: a disclosure more than `k` positions past the last
+//! checkpoint, or past the last accepted position once above it, is refused without hashing.
+
+use crate::registry::{VerifyError, DEFAULT_MAX_GAP};
+use crate::{hash_forward, setup_chain, ChainInitError};
+use digest::generic_array::GenericArray;
+use digest::{Digest, FixedOutputReset, OutputSizeUser};
+use std::collections::BTreeMap;
+
+/// The issuer's side: the values at every `every`-th position of the chain from `seed`,
+/// computed in one pass.
+pub fn checkpoints<H: Digest + FixedOutputReset>(length: usize, seed: u64, every: u64) -> Result<Vec<(u64, digest::Output<H>)>, ChainInitError> {
+    let mut checkpoints = Vec::new();
+    setup_chain::<H, _>(length, seed, |position, value| {
+        if every > 0 && position.is_multiple_of(every) {
+            checkpoints.push((position, value.clone()));
+        }
+    })?;
+    checkpoints.reverse();
+    Ok(checkpoints)
+}
+
+pub struct CheckpointVerifier<H: OutputSizeUser> {
+    last_index: u64,
+    last: GenericArray<u8, H::OutputSize>,
+    /// Checkpoints above the last accepted position.
+    checkpoints: BTreeMap<u64, GenericArray<u8, H::OutputSize>>,
+    /// The most hashes one disclosure may take.
+    max_steps: u64,
+}
+
+impl<H: Digest + FixedOutputReset> CheckpointVerifier<H> {
+    pub fn new(anchor: GenericArray<u8, H::OutputSize>) -> Self {
+        CheckpointVerifier { last_index: 0, last: anchor, checkpoints: BTreeMap::new(), max_steps: DEFAULT_MAX_GAP }
+    }
+
+    /// Add checkpoints. They are checked against the anchor and each other, which costs as
+    /// many hashes as the highest position, so an untrusted provisioning channel cannot plant
+    /// a wrong one. From then on a disclosure may take at most as many hashes as the widest
+    /// spacing between them, instead of [`DEFAULT_MAX_GAP`].
+    pub fn with_checkpoints<I>(mut self, checkpoints: I) -> Result<Self, VerifyError>
+    where
+        I: IntoIterator<Item = (u64, GenericArray<u8, H::OutputSize>)>,
+    {
+        self.checkpoints.extend(checkpoints.into_iter().filter(|(index, _)| *index > self.last_index));
+        let (mut known_index, mut known) = (self.last_index, &self.last);
+        let mut widest = None;
+        for (index, value) in &self.checkpoints {
+            if hash_forward::<H>(value, index - known_index) != *known {
+                return Err(VerifyError::Mismatch);
+            }
+            widest = widest.max(Some(index - known_index));
+            (known_index, known) = (*index, value);
+        }
+        self.max_steps = widest.unwrap_or(self.max_steps);
+        Ok(self)
+    }
+
+    /// The last accepted position.
+    pub fn last_index(&self) -> u64 {
+        self.last_index
+    }
+
+    /// Accept `value` at `index` if it hashes to the nearest known value below it, returning
+    /// the number of hashes that took. Indices too far past the nearest known value are refused
+    /// with [`VerifyError::GapTooLarge`] before hashing.
+    pub fn accept(&mut self, index: u64, value: &GenericArray<u8, H::OutputSize>) -> Result<u64, VerifyError> {
+        if index <= self.last_index {
+            return Err(VerifyError::Replay);
+        }
+        let (known_index, known) = self.checkpoints.range(..=index).next_back().unwrap_or((&self.last_index, &self.last));
+        let steps = index - known_index;
+        if steps > self.max_steps {
+            return Err(VerifyError::GapTooLarge);
+        }
+        if hash_forward::<H>(value, steps) != *known {
+            return Err(VerifyError::Mismatch);
+        }
+        self.checkpoints = self.checkpoints.split_off(&(index + 1));
+        self.last_index = index;
+        self.last = value.clone();
+        Ok(steps)
+    }
+}
+
+#[test]
+fn test_checkpoint_verification() {
+    use crate::HashChain;
+    use sha2::Sha256;
+
+    let mut chain = HashChain::<Sha256>::new(256, 1).unwrap();
+    let provisioned = checkpoints::<Sha256>(256, 1, 32).unwrap();
+    assert_eq!(provisioned.len(), 8);
+    let mut verifier = CheckpointVerifier::<Sha256>::new(*chain.anchor()).with_checkpoints(provisioned.clone()).unwrap();
+
+    let (index, value) = chain.nth(199).unwrap();
+    assert_eq!(verifier.accept(index, &value), Ok(8));
+    let (index, value) = chain.next().unwrap();
+    assert_eq!(verifier.accept(index, &value), Ok(1));
+    assert_eq!(verifier.accept(index, &value), Err(VerifyError::Replay));
+
+    // nothing is provisioned above 96, so 200 is more than 32 hashes from anything known
+    let partial = provisioned.iter().filter(|(index, _)| *index <= 96).cloned();
+    let mut verifier = CheckpointVerifier::<Sha256>::new(*chain.anchor()).with_checkpoints(partial).unwrap();
+    assert_eq!(verifier.accept(200, &value), Err(VerifyError::GapTooLarge));
+    assert_eq!(verifier.accept(u64::MAX, &value), Err(VerifyError::GapTooLarge));
+    assert_eq!(verifier.last_index(), 0);
+
+    let mut planted = provisioned;
+    planted[3].1[0] ^= 1;
+    assert!(CheckpointVerifier::<Sha256>::new(*chain.anchor()).with_checkpoints(planted).is_err());
+}
@@ -0,0 +1,223 @@
+//! [`tokio_util::codec`] implementations for the crate's wire formats, so protocol messages can
+//! be carried over any `AsyncRead`/`AsyncWrite` byte stream with `Framed`.
+
+use crate::registry::ChainToken;
+use crate::tesla::Packet;
+use crate::ChainId;
+use bytes::{Buf, BufMut, BytesMut};
+use digest::OutputSizeUser;
+use std::io;
+use std::marker::PhantomData;
+use tokio_util::codec::{Decoder, Encoder};
+
+/// The default limit on decoded payload sizes.
+pub const DEFAULT_MAX_PAYLOAD: usize = 64 * 1024;
+
+/// Frames TESLA [`Packet`]s using their self-delimiting wire encoding. Malformed input is
+/// reported as [`io::ErrorKind::InvalidData`].
+pub struct TeslaCodec<H> {
+    max_payload: usize,
+    _hash: PhantomData<H>,
+}
+
+impl<H> TeslaCodec<H> {
+    pub fn new() -> Self {
+        Self::with_max_payload(DEFAULT_MAX_PAYLOAD)
+    }
+
+    /// Reject packets whose payload exceeds `max_payload` bytes.
+    pub fn with_max_payload(max_payload: usize) -> Self {
+        TeslaCodec { max_payload, _hash: PhantomData }
+    }
+}
+
+impl<H> Default for TeslaCodec<H> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<H: OutputSizeUser> Decoder for TeslaCodec<H> {
+    type Item = Packet<H>;
+    type Error = io::Error;
+
+    fn decode(&mut self, src: &mut BytesMut) -> Result<Option<Self::Item>, Self::Error> {
+        match Packet::decode(src, self.max_payload) {
+            Ok(Some((packet, used))) => {
+                src.advance(used);
+                Ok(Some(packet))
+            }
+            Ok(None) => Ok(None),
+            Err(e) => Err(io::Error::new(io::ErrorKind::InvalidData, e)),
+        }
+    }
+}
+
+impl<H: OutputSizeUser> Encoder<Packet<H>> for TeslaCodec<H> {
+    type Error = io::Error;
+
+    fn encode(&mut self, packet: Packet<H>, dst: &mut BytesMut) -> Result<(), Self::Error> {
+        let mut encoded = Vec::new();
+        packet.encode(&mut encoded);
+        dst.extend_from_slice(&encoded);
+        Ok(())
+    }
+}
+
+/// A disclosure as carried by [`DisclosureCodec`]: the token and, optionally, a MAC or proof
+/// over it, opaque to the codec.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DisclosureFrame {
+    pub token: ChainToken,
+    pub proof: Option<Vec<u8>>,
+}
+
+/// Frames disclosures as a u32 big endian body length followed by the body: the 16 byte chain
+/// id, the index as u64 big endian, the value after a u16 big endian length, and a flag byte
+/// that, if 1, is followed by the proof filling the rest of the frame. Malformed input is
+/// reported as [`io::ErrorKind::InvalidData`].
+pub struct DisclosureCodec {
+    max_payload: usize,
+}
+
+impl DisclosureCodec {
+    pub fn new() -> Self {
+        Self::with_max_payload(DEFAULT_MAX_PAYLOAD)
+    }
+
+    /// Reject frames whose body exceeds `max_payload` bytes.
+    pub fn with_max_payload(max_payload: usize) -> Self {
+        DisclosureCodec { max_payload }
+    }
+}
+
+impl Default for DisclosureCodec {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+fn invalid(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+impl Decoder for DisclosureCodec {
+    type Item = DisclosureFrame;
+    type Error = io::Error;
+
+    fn decode(&mut self, src: &mut BytesMut) -> Result<Option<Self::Item>, Self::Error> {
+        let Some(header) = src.get(..4) else {
+            return Ok(None);
+        };
+        let length = u32::from_be_bytes(header.try_into().expect("4 bytes")) as usize;
+        if length > self.max_payload {
+            return Err(invalid("disclosure frame too large"));
+        }
+        if src.len() < 4 + length {
+            src.reserve(4 + length - src.len());
+            return Ok(None);
+        }
+        src.advance(4);
+        let mut body = src.split_to(length);
+        if body.len() < 27 {
+            return Err(invalid("disclosure frame truncated"));
+        }
+        let mut chain_id = [0u8; 16];
+        body.copy_to_slice(&mut chain_id);
+        let index = body.get_u64();
+        let value_length = body.get_u16() as usize;
+        if body.len() < value_length + 1 {
+            return Err(invalid("disclosure frame truncated"));
+        }
+        let value = body.split_to(value_length).to_vec();
+        let proof = match body.get_u8() {
+            0 if body.is_empty() => None,
+            1 => Some(body.to_vec()),
+            _ => return Err(invalid("malformed disclosure proof")),
+        };
+        Ok(Some(DisclosureFrame { token: ChainToken { chain_id: ChainId(chain_id), index, value }, proof }))
+    }
+}
+
+impl Encoder<DisclosureFrame> for DisclosureCodec {
+    type Error = io::Error;
+
+    fn encode(&mut self, frame: DisclosureFrame, dst: &mut BytesMut) -> Result<(), Self::Error> {
+        let value_length = u16::try_from(frame.token.value.len()).map_err(|_| invalid("disclosed value too long"))?;
+        let length = 27 + frame.token.value.len() + frame.proof.as_ref().map_or(0, Vec::len);
+        if length > self.max_payload {
+            return Err(invalid("disclosure frame too large"));
+        }
+        dst.reserve(4 + length);
+        dst.put_u32(length as u32);
+        dst.put_slice(&frame.token.chain_id.0);
+        dst.put_u64(frame.token.index);
+        dst.put_u16(value_length);
+        dst.put_slice(&frame.token.value);
+        match &frame.proof {
+            None => dst.put_u8(0),
+            Some(proof) => {
+                dst.put_u8(1);
+                dst.put_slice(proof);
+            }
+        }
+        Ok(())
+    }
+}
+
+#[test]
+fn test_tesla_codec_streaming() {
+    use crate::tesla::{Schedule, Sender};
+    use crate::HashChain;
+    use sha2::Sha256;
+    use std::time::Duration;
+
+    let schedule = Schedule { start: Duration::ZERO, interval: Duration::from_secs(1) };
+    let mut sender = Sender::new(HashChain::<Sha256>::new(8, 2).unwrap(), schedule, 1);
+    let mut codec = TeslaCodec::<Sha256>::new();
+    let mut wire = BytesMut::new();
+    codec.encode(sender.send(b"a", Duration::from_millis(10)).unwrap(), &mut wire).unwrap();
+    codec.encode(sender.send(b"b", Duration::from_millis(1010)).unwrap(), &mut wire).unwrap();
+
+    // feed the stream a few bytes at a time
+    let mut input = BytesMut::new();
+    let mut decoded = Vec::new();
+    for chunk in wire.chunks(7) {
+        input.extend_from_slice(chunk);
+        while let Some(packet) = codec.decode(&mut input).unwrap() {
+            decoded.push(packet.payload);
+        }
+    }
+    assert_eq!(decoded, vec![b"a".to_vec(), b"b".to_vec()]);
+    assert!(input.is_empty());
+}
+
+#[test]
+fn test_disclosure_codec() {
+    let mut codec = DisclosureCodec::new();
+    let plain = DisclosureFrame { token: ChainToken { chain_id: ChainId([4; 16]), index: 9, value: vec![1; 32] }, proof: None };
+    let proven = DisclosureFrame { proof: Some(vec![]), ..plain.clone() };
+    let mut wire = BytesMut::new();
+    codec.encode(plain.clone(), &mut wire).unwrap();
+    codec.encode(proven.clone(), &mut wire).unwrap();
+
+    let mut input = BytesMut::new();
+    let mut decoded = Vec::new();
+    for chunk in wire.chunks(5) {
+        input.extend_from_slice(chunk);
+        while let Some(frame) = codec.decode(&mut input).unwrap() {
+            decoded.push(frame);
+        }
+    }
+    assert_eq!(decoded, vec![plain.clone(), proven]);
+
+    let mut small = DisclosureCodec::with_max_payload(40);
+    assert!(small.encode(plain.clone(), &mut BytesMut::new()).is_err());
+    let mut oversized = BytesMut::new();
+    codec.encode(plain, &mut oversized).unwrap();
+    assert!(small.decode(&mut oversized).is_err());
+    let mut bad_flag = BytesMut::from(&[0, 0, 0, 27][..]);
+    bad_flag.extend_from_slice(&[0; 26]);
+    bad_flag.put_u8(2);
+    assert!(codec.decode(&mut bad_flag).is_err());
+}
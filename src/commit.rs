@@ -0,0 +1,197 @@
+//! Commitments bound to chain positions.
+//!
+//! A commitment to a value at position `p` hashes the value together with the chain value at
+//! `p`, which stays secret until the committer discloses it. Disclosing the chain value at `p`
+//! opens every commitment bound to `p` or an earlier position at once, because the earlier chain
+//! values follow from it by hashing.
+//!
+//! The API keeps the usual mistakes out of reach: committing to a position that is already
+//! disclosed (which would hide nothing) is refused, each commitment carries a serial number so
+//! equal values do not give equal commitments, and a [`Verifier`] only checks a value against a
+//! commitment once it holds a chain value verified against the anchor.
+
+use crate::{hash_forward, verify, ChainInitError, HashChain};
+use digest::generic_array::GenericArray;
+use digest::{Digest, FixedOutputReset, OutputSizeUser};
+use std::error::Error;
+use std::fmt::{self, Display};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum CommitError {
+    /// The position's chain value has already been disclosed.
+    AlreadyDisclosed,
+    /// The position lies outside the chain.
+    OutOfRange,
+    /// No accepted opening covers the commitment's position yet.
+    NotYetOpened,
+    /// The opening does not verify against the chain.
+    InvalidOpening,
+    /// The value does not match the commitment.
+    Mismatch,
+}
+
+impl Display for CommitError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            CommitError::AlreadyDisclosed => write!(f, "chain value already disclosed"),
+            CommitError::OutOfRange => write!(f, "position outside the chain"),
+            CommitError::NotYetOpened => write!(f, "commitment not opened yet"),
+            CommitError::InvalidOpening => write!(f, "opening does not verify against the chain"),
+            CommitError::Mismatch => write!(f, "value does not match the commitment"),
+        }
+    }
+}
+
+impl Error for CommitError {}
+
+pub struct Commitment<H: OutputSizeUser> {
+    pub position: u64,
+    pub serial: u64,
+    pub digest: GenericArray<u8, H::OutputSize>,
+}
+
+impl<H: OutputSizeUser> Clone for Commitment<H> {
+    fn clone(&self) -> Self {
+        Commitment { position: self.position, serial: self.serial, digest: self.digest.clone() }
+    }
+}
+
+/// A disclosed chain value, opening the commitments up to its position.
+pub struct Opening<H: OutputSizeUser> {
+    pub position: u64,
+    pub value: GenericArray<u8, H::OutputSize>,
+}
+
+fn commitment_digest<H: Digest>(position: u64, serial: u64, value: &[u8], key: &[u8]) -> GenericArray<u8, H::OutputSize> {
+    let mut hasher = H::new();
+    hasher.update(b"chain commitment");
+    hasher.update(position.to_be_bytes());
+    hasher.update(serial.to_be_bytes());
+    hasher.update(key);
+    hasher.update(value);
+    hasher.finalize()
+}
+
+pub struct Committer<H: Digest + FixedOutputReset> {
+    chain: HashChain<H>,
+    /// The chain value at position `length`, from which every position can be reached.
+    tail: GenericArray<u8, H::OutputSize>,
+    serial: u64,
+}
+
+impl<H: Digest + FixedOutputReset> Committer<H> {
+    /// Commit with the chain of `length` values generated from `seed`. Committing to position
+    /// `p` costs `length - p` hash evaluations.
+    pub fn new(length: usize, seed: u64) -> Result<Self, ChainInitError> {
+        let chain = HashChain::new(length, seed)?;
+        let tail = H::new_with_prefix(seed.to_le_bytes()).finalize();
+        Ok(Committer { chain, tail, serial: 0 })
+    }
+
+    pub fn anchor(&self) -> &GenericArray<u8, H::OutputSize> {
+        self.chain.anchor()
+    }
+
+    /// The last disclosed position; commitments must be bound to later ones.
+    pub fn disclosed(&self) -> u64 {
+        HashChain::position(&self.chain)
+    }
+
+    /// Commit to `value`, to be opened once position `position` is disclosed.
+    pub fn commit(&mut self, value: &[u8], position: u64) -> Result<Commitment<H>, CommitError> {
+        if position <= self.disclosed() {
+            return Err(CommitError::AlreadyDisclosed);
+        }
+        if position > self.chain.length() {
+            return Err(CommitError::OutOfRange);
+        }
+        let key = hash_forward::<H>(&self.tail, self.chain.length() - position);
+        let serial = self.serial;
+        self.serial += 1;
+        Ok(Commitment { position, serial, digest: commitment_digest::<H>(position, serial, value, &key) })
+    }
+
+    /// Disclose the chain up to `position`, opening every commitment bound to it or earlier.
+    pub fn open(&mut self, position: u64) -> Result<Opening<H>, CommitError> {
+        if position <= self.disclosed() {
+            return Err(CommitError::AlreadyDisclosed);
+        }
+        let skip = (position - self.disclosed() - 1) as usize;
+        let (position, value) = self.chain.nth(skip).ok_or(CommitError::OutOfRange)?;
+        Ok(Opening { position, value: value.to_array() })
+    }
+}
+
+pub struct Verifier<H: OutputSizeUser> {
+    position: u64,
+    value: GenericArray<u8, H::OutputSize>,
+}
+
+impl<H: Digest + FixedOutputReset> Verifier<H> {
+    pub fn new(anchor: GenericArray<u8, H::OutputSize>) -> Self {
+        Verifier { position: 0, value: anchor }
+    }
+
+    /// The highest opened position.
+    pub fn opened(&self) -> u64 {
+        self.position
+    }
+
+    /// Check `opening` against the chain and keep it if it is newer than what we have.
+    pub fn accept(&mut self, opening: &Opening<H>) -> Result<(), CommitError> {
+        if opening.position <= self.position {
+            let expected = hash_forward::<H>(&self.value, self.position - opening.position);
+            return if expected == opening.value { Ok(()) } else { Err(CommitError::InvalidOpening) };
+        }
+        if !verify::<H>(self.position, &self.value, opening.position, &opening.value) {
+            return Err(CommitError::InvalidOpening);
+        }
+        self.position = opening.position;
+        self.value = opening.value.clone();
+        Ok(())
+    }
+
+    /// Check that `commitment` was made to `value`.
+    pub fn reveal(&self, commitment: &Commitment<H>, value: &[u8]) -> Result<(), CommitError> {
+        if commitment.position > self.position {
+            return Err(CommitError::NotYetOpened);
+        }
+        let key = hash_forward::<H>(&self.value, self.position - commitment.position);
+        if commitment_digest::<H>(commitment.position, commitment.serial, value, &key) != commitment.digest {
+            return Err(CommitError::Mismatch);
+        }
+        Ok(())
+    }
+
+    /// Check a batch of commitments, reporting the first failure by its index in the batch.
+    pub fn reveal_all<'a, I>(&self, batch: I) -> Result<(), (usize, CommitError)>
+    where
+        I: IntoIterator<Item = (&'a Commitment<H>, &'a [u8])>,
+        H: 'a,
+    {
+        batch.into_iter().enumerate().try_for_each(|(i, (commitment, value))| self.reveal(commitment, value).map_err(|e| (i, e)))
+    }
+}
+
+#[test]
+fn test_commit_reveal() {
+    use sha2::Sha256;
+
+    let mut committer = Committer::<Sha256>::new(16, 4).unwrap();
+    let mut verifier = Verifier::<Sha256>::new(*committer.anchor());
+    let bids = [committer.commit(b"bid 10", 3).unwrap(), committer.commit(b"bid 10", 3).unwrap(), committer.commit(b"bid 7", 5).unwrap()];
+    assert_ne!(bids[0].digest, bids[1].digest);
+    assert_eq!(committer.commit(b"late", 17).err(), Some(CommitError::OutOfRange));
+    assert_eq!(verifier.reveal(&bids[0], b"bid 10"), Err(CommitError::NotYetOpened));
+
+    // a single opening at position 5 opens all three
+    let opening = committer.open(5).unwrap();
+    assert_eq!(committer.commit(b"too late", 4).err(), Some(CommitError::AlreadyDisclosed));
+    verifier.accept(&opening).unwrap();
+    let values: [&[u8]; 3] = [b"bid 10", b"bid 10", b"bid 7"];
+    assert_eq!(verifier.reveal_all(bids.iter().zip(values)), Ok(()));
+    assert_eq!(verifier.reveal(&bids[2], b"bid 8"), Err(CommitError::Mismatch));
+
+    let forged = Opening::<Sha256> { position: 6, value: opening.value };
+    assert_eq!(verifier.accept(&forged), Err(CommitError::InvalidOpening));
+}
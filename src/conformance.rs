@@ -0,0 +1,113 @@
+//! A conformance suite for custom hash backends.
+//!
+//! A backend, e.g. a hardware accelerator, implements [`ChainStep`] for the digest `H` it
+//! claims to compute. [`run`] checks it against `H` itself on fixed inputs, checks that its
+//! `walk` agrees with stepping one value at a time, and walks a whole chain disclosed by
+//! [`HashChain`] back to the anchor with it. Passing means the backend can stand in for `H`
+//! everywhere the crate hashes chain values.
+
+use crate::{ChainStep, HashChain};
+use core::error::Error;
+use core::fmt::{self, Display};
+use digest::generic_array::GenericArray;
+use digest::{Digest, FixedOutputReset};
+
+/// The length of the chain walked by [`run`].
+const CHAIN_LENGTH: usize = 64;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ConformanceError {
+    /// The backend's output for fixed input `vector` differs from the reference digest.
+    KnownAnswer { vector: usize },
+    /// Stepping the same value twice gave different outputs.
+    Nondeterministic,
+    /// `walk` over `steps` steps differs from stepping one value at a time.
+    Walk { steps: u64 },
+    /// The value disclosed at `index` does not step to the one before it.
+    Chain { index: u64 },
+}
+
+impl Display for ConformanceError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ConformanceError::KnownAnswer { vector } => write!(f, "wrong output for fixed input {}", vector),
+            ConformanceError::Nondeterministic => write!(f, "output differs between calls"),
+            ConformanceError::Walk { steps } => write!(f, "walk of {} steps differs from single steps", steps),
+            ConformanceError::Chain { index } => write!(f, "chain value {} does not step to its predecessor", index),
+        }
+    }
+}
+
+impl Error for ConformanceError {}
+
+/// The fixed inputs: all zeros, all ones, a byte counter and the hash of each of them.
+fn vectors<H: Digest>() -> impl Iterator<Item = GenericArray<u8, H::OutputSize>> {
+    let fill = |byte: fn(usize) -> u8| {
+        let mut value = GenericArray::<u8, H::OutputSize>::default();
+        value.iter_mut().enumerate().for_each(|(i, b)| *b = byte(i));
+        value
+    };
+    [fill(|_| 0), fill(|_| 0xff), fill(|i| i as u8)].into_iter().flat_map(|v| [H::digest(&v), v])
+}
+
+/// Run every check against `backend`, stopping at the first failure.
+pub fn run<H: Digest + FixedOutputReset>(backend: impl ChainStep<H>) -> Result<(), ConformanceError> {
+    for (vector, input) in vectors::<H>().enumerate() {
+        if backend.step(&input, vector as u64) != H::digest(&input) {
+            return Err(ConformanceError::KnownAnswer { vector });
+        }
+    }
+
+    let value = H::digest(b"conformance");
+    if backend.step(&value, 0) != backend.step(&value, 0) {
+        return Err(ConformanceError::Nondeterministic);
+    }
+    let mut stepped = value.clone();
+    for steps in 1..=33u64 {
+        stepped = backend.step(&stepped, steps - 1);
+        if backend.walk(&value, 0, steps) != stepped {
+            return Err(ConformanceError::Walk { steps });
+        }
+    }
+
+    let mut chain = HashChain::<H>::new(CHAIN_LENGTH, 0x636f_6e66).expect("valid length");
+    let mut previous = chain.anchor().clone();
+    while let Some((index, value)) = chain.disclose() {
+        if backend.step(&value, CHAIN_LENGTH as u64 - index) != previous {
+            return Err(ConformanceError::Chain { index });
+        }
+        previous = value.to_array();
+    }
+    Ok(())
+}
+
+#[test]
+fn test_conformance() {
+    use crate::PlainStep;
+    use sha2::{Sha256, Sha512};
+
+    struct Truncating;
+    impl ChainStep<Sha256> for Truncating {
+        fn step(&self, value: &digest::Output<Sha256>, _index: u64) -> digest::Output<Sha256> {
+            // a backend that only hashes the first 31 bytes
+            Sha256::digest(&value[..31])
+        }
+    }
+
+    struct BadWalk;
+    impl ChainStep<Sha256> for BadWalk {
+        fn step(&self, value: &digest::Output<Sha256>, _index: u64) -> digest::Output<Sha256> {
+            Sha256::digest(value)
+        }
+
+        fn walk(&self, value: &digest::Output<Sha256>, start: u64, steps: u64) -> digest::Output<Sha256> {
+            // a batched walk that silently stops after 32 steps
+            (start..start + steps.min(32)).fold(*value, |value, index| self.step(&value, index))
+        }
+    }
+
+    assert_eq!(run::<Sha256>(PlainStep::new()), Ok(()));
+    assert_eq!(run::<Sha512>(PlainStep::new()), Ok(()));
+    assert_eq!(run::<Sha256>(Truncating), Err(ConformanceError::KnownAnswer { vector: 0 }));
+    assert_eq!(run::<Sha256>(BadWalk), Err(ConformanceError::Walk { steps: 33 }));
+}
@@ -0,0 +1,82 @@
+//! Async helpers for cooperative schedulers such as Embassy, where a long run of hashing would
+//! starve other tasks. They hash in batches and await a caller-supplied pause between batches,
+//! e.g. `|| embassy_time::Timer::after_millis(1)` or a plain yield, so they depend on no
+//! particular executor.
+
+use crate::{ChainInitError, HashChain, Setup};
+use crate::value::ChainValue;
+use alloc::sync::Arc;
+use core::future::Future;
+use digest::{Digest, FixedOutputReset};
+
+/// Set up a chain like [`HashChain::new`], awaiting `pause()` after every `batch` hashes of the
+/// `length` the setup takes.
+pub async fn setup_with_yield<H, P, Fut>(length: usize, seed: u64, batch: u64, mut pause: P) -> Result<HashChain<H>, ChainInitError>
+where
+    H: Digest + FixedOutputReset,
+    P: FnMut() -> Fut,
+    Fut: Future<Output = ()>,
+{
+    let mut setup = Setup::<H>::new(length, seed)?;
+    'setup: loop {
+        for _ in 0..batch.max(1) {
+            if !setup.step(&mut |_, _| {}) {
+                break 'setup;
+            }
+        }
+        pause().await;
+    }
+    let (pebbles, anchor) = setup.finish();
+    Ok(HashChain { length: length as u64, current: 0, anchor, pebbles: Arc::new(pebbles.into()), personalization: None, hasher: H::new() })
+}
+
+/// Disclose the next `steps` values, awaiting `pause()` after every `batch` of them, and return
+/// the last one. Each disclosure costs up to about `log_2(length)` hashes.
+pub async fn advance_with_yield<H, P, Fut>(chain: &mut HashChain<H>, steps: u64, batch: u64, mut pause: P) -> Option<(u64, ChainValue<H>)>
+where
+    H: Digest + FixedOutputReset,
+    P: FnMut() -> Fut,
+    Fut: Future<Output = ()>,
+{
+    let mut last = None;
+    for step in 1..=steps {
+        last = Some(chain.disclose()?);
+        if step.is_multiple_of(batch.max(1)) && step < steps {
+            pause().await;
+        }
+    }
+    last
+}
+
+#[test]
+fn test_cooperative_setup_and_advance() {
+    use sha2::Sha256;
+    use std::pin::pin;
+    use std::task::{Context, Poll, Waker};
+
+    fn block_on<F: Future>(future: F) -> F::Output {
+        let mut future = pin!(future);
+        let mut context = Context::from_waker(Waker::noop());
+        loop {
+            if let Poll::Ready(output) = future.as_mut().poll(&mut context) {
+                return output;
+            }
+        }
+    }
+
+    let mut pauses = 0;
+    let mut chain = block_on(setup_with_yield::<Sha256, _, _>(64, 5, 16, || {
+        pauses += 1;
+        core::future::ready(())
+    })).unwrap();
+    assert_eq!(pauses, 4);
+    assert_eq!(chain.anchor(), HashChain::<Sha256>::new(64, 5).unwrap().anchor());
+
+    let mut pauses = 0;
+    let last = block_on(advance_with_yield(&mut chain, 10, 3, || {
+        pauses += 1;
+        core::future::ready(())
+    }));
+    assert_eq!(pauses, 3);
+    assert_eq!(last, HashChain::<Sha256>::new(64, 5).unwrap().nth(9));
+}
@@ -0,0 +1,62 @@
+//! [`defmt::Format`] implementations for logging over RTT and similar transports. Anything
+//! that could let a reader compute undisclosed chain values is left out: pebble values are
+//! redacted and chains only report their counters.
+
+use crate::fixed::{HashChainFixed, Position};
+use crate::merkle::PathFormatError;
+use crate::{ChainAuditError, ChainInitError, HashChain, Pebble};
+use defmt::{Format, Formatter};
+use digest::{Digest, FixedOutputReset, OutputSizeUser};
+
+impl<H: OutputSizeUser> Format for Pebble<H> {
+    fn format(&self, f: Formatter) {
+        defmt::write!(f, "Pebble {{position: {=u64}, destination: {=u64}, value: <redacted>}}", self.position, self.destination)
+    }
+}
+
+impl<H: Digest + FixedOutputReset> Format for HashChain<H> {
+    fn format(&self, f: Formatter) {
+        defmt::write!(f, "HashChain {{length: {=u64}, position: {=u64}, pebbles: {=usize}}}", self.length, self.current, self.pebbles.len())
+    }
+}
+
+impl<H: Digest + FixedOutputReset, const LOG_N: usize, P: Position> Format for HashChainFixed<H, LOG_N, P> {
+    fn format(&self, f: Formatter) {
+        defmt::write!(f, "HashChainFixed {{length: {=u64}, position: {=u64}}}", self.length(), self.position())
+    }
+}
+
+impl Format for ChainInitError {
+    fn format(&self, f: Formatter) {
+        defmt::write!(f, "{=str}", self.details.as_str())
+    }
+}
+
+impl Format for ChainAuditError {
+    fn format(&self, f: Formatter) {
+        match self {
+            ChainAuditError::Length(e) => defmt::write!(f, "invalid chain length: {}", e),
+            ChainAuditError::AnchorMismatch => defmt::write!(f, "seed does not lead to the anchor"),
+            ChainAuditError::PebbleMismatch { position } => defmt::write!(f, "pebble at position {=u64} does not match the chain", position),
+        }
+    }
+}
+
+impl Format for PathFormatError {
+    fn format(&self, f: Formatter) {
+        defmt::write!(f, "{=str}", self.details.as_str())
+    }
+}
+
+#[cfg(any(feature = "embedded-storage", feature = "embedded-storage-async"))]
+impl<E: Format> Format for crate::flash::FlashError<E> {
+    fn format(&self, f: Formatter) {
+        use crate::flash::FlashError;
+        match self {
+            FlashError::Flash(e) => defmt::write!(f, "flash error: {}", e),
+            FlashError::Layout => defmt::write!(f, "checkpoint slots not aligned to erase sectors"),
+            FlashError::TooLarge => defmt::write!(f, "state does not fit in a checkpoint slot"),
+            FlashError::BadState(e) => defmt::write!(f, "invalid chain state: {}", e),
+        }
+    }
+}
@@ -0,0 +1,243 @@
+//! A disclosed chain value as one message shape for every protocol: the chain, the position
+//! and the value, optionally with a MAC and a [`Proof`] of position.
+//!
+//! The canonical encoding is the chain id (16 bytes), the index as u64 big endian, the value, a
+//! flags byte (0x01 for a MAC, 0x02 for a proof), the MAC after a length byte if there is one,
+//! and the proof's hashes after their count as u32 big endian if there is one. The MAC covers
+//! the encoding of the disclosure without its MAC, and signatures cover
+//! `"chain disclosure" || encoding`, so a signed disclosure cannot be passed off as another kind
+//! of signed message.
+
+use crate::proof::Proof;
+use crate::ChainId;
+use alloc::vec::Vec;
+use core::error::Error;
+use core::fmt::{self, Display};
+use digest::generic_array::GenericArray;
+use digest::{Digest, FixedOutputReset, KeyInit, Mac, OutputSizeUser};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DisclosureError {
+    /// The encoding ends early or runs on past the disclosure.
+    WrongLength,
+    /// A reserved flag bit is set.
+    UnknownFlags(u8),
+    /// The value does not verify against the known one, or the proof does not lead to it.
+    Mismatch,
+    /// The disclosure carries no MAC.
+    MacMissing,
+    /// The MAC does not match the disclosure.
+    MacMismatch,
+    /// The signature does not match the disclosure.
+    SignatureMismatch,
+}
+
+impl Display for DisclosureError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            DisclosureError::WrongLength => write!(f, "wrong length for a disclosure"),
+            DisclosureError::UnknownFlags(flags) => write!(f, "unknown flags {:#04x}", flags),
+            DisclosureError::Mismatch => write!(f, "value does not verify against the chain"),
+            DisclosureError::MacMissing => write!(f, "disclosure carries no MAC"),
+            DisclosureError::MacMismatch => write!(f, "MAC does not match the disclosure"),
+            DisclosureError::SignatureMismatch => write!(f, "signature does not match the disclosure"),
+        }
+    }
+}
+
+impl Error for DisclosureError {}
+
+const FLAG_MAC: u8 = 0x01;
+const FLAG_PROOF: u8 = 0x02;
+
+pub struct Disclosure<H: OutputSizeUser> {
+    pub chain_id: ChainId,
+    pub index: u64,
+    pub value: GenericArray<u8, H::OutputSize>,
+    /// A MAC over the rest of the disclosure, see [`Disclosure::authenticate`].
+    pub mac: Option<Vec<u8>>,
+    /// The values between this one and one the verifier knows.
+    pub proof: Option<Proof<H>>,
+}
+
+impl<H: OutputSizeUser> Clone for Disclosure<H> {
+    fn clone(&self) -> Self {
+        Disclosure { chain_id: self.chain_id, index: self.index, value: self.value.clone(), mac: self.mac.clone(), proof: self.proof.clone() }
+    }
+}
+
+impl<H: Digest> Disclosure<H> {
+    pub fn new(chain_id: ChainId, index: u64, value: GenericArray<u8, H::OutputSize>) -> Self {
+        Disclosure { chain_id, index, value, mac: None, proof: None }
+    }
+
+    /// Attach the proof that the value lies `steps` positions above one the verifier knows.
+    pub fn with_proof(self, steps: u64) -> Self {
+        let proof = Proof::generate(self.value.clone(), steps);
+        Disclosure { proof: Some(proof), ..self }
+    }
+
+    /// Attach the MAC `M` computes under `key` over the disclosure, e.g. with a TESLA key or a
+    /// key shared with the verifier.
+    pub fn authenticate<M: Mac + KeyInit>(self, key: &[u8]) -> Result<Self, digest::InvalidLength> {
+        let mac = <M as Mac>::new_from_slice(key)?.chain_update(self.encode(false)).finalize().into_bytes().to_vec();
+        Ok(Disclosure { mac: Some(mac), ..self })
+    }
+
+    /// The canonical encoding.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        self.encode(true)
+    }
+
+    fn encode(&self, with_mac: bool) -> Vec<u8> {
+        let mac = self.mac.as_ref().filter(|_| with_mac);
+        let mut bytes = Vec::with_capacity(29 + self.value.len());
+        bytes.extend_from_slice(&self.chain_id.0);
+        bytes.extend_from_slice(&self.index.to_be_bytes());
+        bytes.extend_from_slice(&self.value);
+        bytes.push(if mac.is_some() { FLAG_MAC } else { 0 } | if self.proof.is_some() { FLAG_PROOF } else { 0 });
+        if let Some(mac) = mac {
+            bytes.push(mac.len() as u8);
+            bytes.extend_from_slice(mac);
+        }
+        if let Some(proof) = &self.proof {
+            bytes.extend_from_slice(&(proof.distance() as u32).to_be_bytes());
+            for steps in 1..=proof.distance() {
+                bytes.extend_from_slice(proof.intermediate(steps).expect("within the proof"));
+            }
+        }
+        bytes
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, DisclosureError> {
+        fn take<'a>(rest: &mut &'a [u8], len: usize) -> Result<&'a [u8], DisclosureError> {
+            let (taken, tail) = rest.split_at_checked(len).ok_or(DisclosureError::WrongLength)?;
+            *rest = tail;
+            Ok(taken)
+        }
+        let n = <H as Digest>::output_size();
+        let mut rest = bytes;
+        let chain_id = ChainId(take(&mut rest, 16)?.try_into().expect("16 bytes"));
+        let index = u64::from_be_bytes(take(&mut rest, 8)?.try_into().expect("8 bytes"));
+        let value = GenericArray::clone_from_slice(take(&mut rest, n)?);
+        let flags = take(&mut rest, 1)?[0];
+        if flags & !(FLAG_MAC | FLAG_PROOF) != 0 {
+            return Err(DisclosureError::UnknownFlags(flags));
+        }
+        let mac = if flags & FLAG_MAC != 0 {
+            let len = take(&mut rest, 1)?[0] as usize;
+            Some(take(&mut rest, len)?.to_vec())
+        } else {
+            None
+        };
+        let proof = if flags & FLAG_PROOF != 0 {
+            let count = u32::from_be_bytes(take(&mut rest, 4)?.try_into().expect("4 bytes")) as usize;
+            let hashes = take(&mut rest, count.checked_mul(n).ok_or(DisclosureError::WrongLength)?)?;
+            Some(Proof::new(value.clone(), hashes.chunks(n).map(GenericArray::clone_from_slice).collect()))
+        } else {
+            None
+        };
+        if !rest.is_empty() {
+            return Err(DisclosureError::WrongLength);
+        }
+        Ok(Disclosure { chain_id, index, value, mac, proof })
+    }
+
+    /// The bytes a signature on the disclosure covers.
+    pub fn signed_bytes(&self) -> Vec<u8> {
+        let mut bytes = b"chain disclosure".to_vec();
+        bytes.extend_from_slice(&self.to_bytes());
+        bytes
+    }
+
+    /// Sign the disclosure with `sign`, which maps [`Disclosure::signed_bytes`] to a signature.
+    pub fn sign<F: FnOnce(&[u8]) -> Vec<u8>>(&self, sign: F) -> Vec<u8> {
+        sign(&self.signed_bytes())
+    }
+
+    /// Check `signature` with `verify`, which takes the signed bytes and the signature.
+    pub fn verify_signature<F: FnOnce(&[u8], &[u8]) -> bool>(&self, signature: &[u8], verify: F) -> Result<(), DisclosureError> {
+        verify(&self.signed_bytes(), signature).then_some(()).ok_or(DisclosureError::SignatureMismatch)
+    }
+
+    /// Check the MAC [`Disclosure::authenticate`] attached under `key`.
+    pub fn verify_mac<M: Mac + KeyInit>(&self, key: &[u8]) -> Result<(), DisclosureError> {
+        let mac = self.mac.as_ref().ok_or(DisclosureError::MacMissing)?;
+        let expected = <M as Mac>::new_from_slice(key).map_err(|_| DisclosureError::MacMismatch)?;
+        expected.chain_update(self.encode(false)).verify_slice(mac).map_err(|_| DisclosureError::MacMismatch)
+    }
+}
+
+impl<H: Digest + FixedOutputReset> Disclosure<H> {
+    /// Check the value against the value at `known_index`, the anchor at index 0 or the last
+    /// accepted disclosure. With a proof every step it covers is checked on its own, see
+    /// [`Proof::verify`].
+    pub fn verify(&self, known_index: u64, known_value: &GenericArray<u8, H::OutputSize>) -> Result<(), DisclosureError> {
+        let valid = match &self.proof {
+            Some(proof) => *proof.value() == self.value && proof.index_above(known_index) == self.index && proof.verify(known_value),
+            None => crate::verify::<H>(known_index, known_value, self.index, &self.value),
+        };
+        valid.then_some(()).ok_or(DisclosureError::Mismatch)
+    }
+}
+
+#[cfg(feature = "std")]
+impl<H: OutputSizeUser> From<&Disclosure<H>> for crate::registry::ChainToken {
+    fn from(disclosure: &Disclosure<H>) -> Self {
+        crate::registry::ChainToken { chain_id: disclosure.chain_id, index: disclosure.index, value: disclosure.value.to_vec() }
+    }
+}
+
+#[cfg(feature = "std")]
+impl<H: OutputSizeUser> TryFrom<&crate::registry::ChainToken> for Disclosure<H> {
+    type Error = DisclosureError;
+
+    fn try_from(token: &crate::registry::ChainToken) -> Result<Self, DisclosureError> {
+        if token.value.len() != H::output_size() {
+            return Err(DisclosureError::WrongLength);
+        }
+        Ok(Disclosure { chain_id: token.chain_id, index: token.index, value: GenericArray::clone_from_slice(&token.value), mac: None, proof: None })
+    }
+}
+
+impl<H: Digest> crate::version::Versioned for Disclosure<H> {
+    type Error = DisclosureError;
+
+    fn encode_body(&self, _version: u8) -> Vec<u8> {
+        self.to_bytes()
+    }
+
+    fn decode_body(_version: u8, body: &[u8]) -> Result<Self, DisclosureError> {
+        Self::from_bytes(body)
+    }
+}
+
+#[test]
+fn test_disclosure_roundtrip() {
+    use crate::HashChain;
+    use hmac::SimpleHmac;
+    use sha2::Sha256;
+
+    let mut chain = HashChain::<Sha256>::new(16, 3).unwrap();
+    let (_, second) = chain.nth(1).unwrap();
+    let (index, value) = chain.nth(2).unwrap();
+    let plain = Disclosure::<Sha256>::new(crate::ChainId([3; 16]), index, value.to_array());
+    assert_eq!(plain.to_bytes().len(), 16 + 8 + 32 + 1);
+    assert!(plain.verify(0, chain.anchor()).is_ok());
+    assert_eq!(plain.verify(2, chain.anchor()), Err(DisclosureError::Mismatch));
+
+    let full = plain.clone().with_proof(3).authenticate::<SimpleHmac<Sha256>>(b"shared key").unwrap();
+    let decoded = Disclosure::<Sha256>::from_bytes(&full.to_bytes()).unwrap();
+    assert_eq!(decoded.to_bytes(), full.to_bytes());
+    assert!(decoded.verify(2, &second).is_ok());
+    assert!(decoded.verify_mac::<SimpleHmac<Sha256>>(b"shared key").is_ok());
+    assert_eq!(decoded.verify_mac::<SimpleHmac<Sha256>>(b"other key"), Err(DisclosureError::MacMismatch));
+    assert_eq!(plain.verify_mac::<SimpleHmac<Sha256>>(b"shared key"), Err(DisclosureError::MacMissing));
+    let bytes = full.to_bytes();
+    assert_eq!(Disclosure::<Sha256>::from_bytes(&bytes[..bytes.len() - 1]).err(), Some(DisclosureError::WrongLength));
+
+    // a stand-in signature scheme: the signed bytes' digest
+    let signature = full.sign(|bytes| Sha256::digest(bytes).to_vec());
+    assert!(decoded.verify_signature(&signature, |bytes, signature| Sha256::digest(bytes)[..] == *signature).is_ok());
+    assert!(plain.verify_signature(&signature, |bytes, signature| Sha256::digest(bytes)[..] == *signature).is_err());
+}
@@ -0,0 +1,203 @@
+//! Pre-committed message feeds over two chains: each step discloses the next value of the
+//! commitment chain together with a digest binding a message to the same index of the opening
+//! chain, and the opening chain's value, with the message, follows `lag` steps later.
+//!
+//! Until it is opened a message is hidden behind an opening chain value nobody else knows yet,
+//! and the commitment chain value shows the step came from the publisher, since nobody else could
+//! disclose it before the publisher did. A [`DualVerifier`] accepts each commitment before the
+//! opening that reveals it, so a publisher cannot choose a message after seeing what happened
+//! in the meantime.
+//!
+//! The publisher keeps the last `lag` opening values and their messages until they are due, the
+//! same memory as the keys a [`tesla`](crate::tesla) sender retains.
+
+use crate::{verify, ChainInitError, HashChain};
+use digest::generic_array::GenericArray;
+use digest::{Digest, FixedOutputReset, OutputSizeUser};
+use std::collections::VecDeque;
+use std::error::Error;
+use std::fmt::{self, Display};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DualChainError {
+    /// The feed has no steps left.
+    Exhausted,
+    /// The step is not newer than the last accepted one.
+    Stale,
+    /// The commitment chain value does not verify.
+    Forged,
+    /// The opening chain value does not verify.
+    BadOpening,
+    /// No accepted commitment is waiting for the opening.
+    NotCommitted,
+    /// The message does not match its commitment.
+    Mismatch,
+}
+
+impl Display for DualChainError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            DualChainError::Exhausted => write!(f, "feed exhausted"),
+            DualChainError::Stale => write!(f, "step already accepted"),
+            DualChainError::Forged => write!(f, "commitment chain value does not verify"),
+            DualChainError::BadOpening => write!(f, "opening chain value does not verify"),
+            DualChainError::NotCommitted => write!(f, "no commitment waiting for the opening"),
+            DualChainError::Mismatch => write!(f, "message does not match its commitment"),
+        }
+    }
+}
+
+impl Error for DualChainError {}
+
+fn digest<H: Digest>(index: u64, key: &[u8], message: &[u8]) -> GenericArray<u8, H::OutputSize> {
+    H::new_with_prefix(b"dual chain commitment").chain_update(index.to_be_bytes()).chain_update(key).chain_update(message).finalize()
+}
+
+/// The opening of the commitment at `index`: the opening chain value there and the message.
+pub struct DualOpening<H: OutputSizeUser> {
+    pub index: u64,
+    pub key: GenericArray<u8, H::OutputSize>,
+    pub message: Vec<u8>,
+}
+
+impl<H: OutputSizeUser> Clone for DualOpening<H> {
+    fn clone(&self) -> Self {
+        DualOpening { index: self.index, key: self.key.clone(), message: self.message.clone() }
+    }
+}
+
+/// One step of the feed.
+pub struct DualStep<H: OutputSizeUser> {
+    pub index: u64,
+    /// The commitment chain value at `index`.
+    pub value: GenericArray<u8, H::OutputSize>,
+    /// The commitment to this step's message.
+    pub digest: GenericArray<u8, H::OutputSize>,
+    /// The opening of the step `lag` back, once there is one.
+    pub opening: Option<DualOpening<H>>,
+}
+
+impl<H: OutputSizeUser> Clone for DualStep<H> {
+    fn clone(&self) -> Self {
+        DualStep { index: self.index, value: self.value.clone(), digest: self.digest.clone(), opening: self.opening.clone() }
+    }
+}
+
+/// The publisher, holding both chains.
+pub struct DualChain<H: Digest + FixedOutputReset> {
+    commitments: HashChain<H>,
+    openings: HashChain<H>,
+    lag: u64,
+    /// The openings not yet due, oldest first.
+    pending: VecDeque<DualOpening<H>>,
+}
+
+impl<H: Digest + FixedOutputReset> DualChain<H> {
+    /// A feed of `length` steps whose openings follow their commitments `lag` steps later. The
+    /// two seeds must be independent.
+    pub fn new(length: usize, commitment_seed: u64, opening_seed: u64, lag: u64) -> Result<Self, ChainInitError> {
+        if lag == 0 || lag >= length as u64 {
+            return Err(ChainInitError::new("lag must be at least 1 and below the length"));
+        }
+        Ok(DualChain { commitments: HashChain::new(length, commitment_seed)?, openings: HashChain::new(length, opening_seed)?, lag, pending: VecDeque::new() })
+    }
+
+    pub fn commitment_anchor(&self) -> &GenericArray<u8, H::OutputSize> {
+        self.commitments.anchor()
+    }
+
+    pub fn opening_anchor(&self) -> &GenericArray<u8, H::OutputSize> {
+        self.openings.anchor()
+    }
+
+    pub fn lag(&self) -> u64 {
+        self.lag
+    }
+
+    /// Commit to `message` in the next step, which also opens the step `lag` back.
+    pub fn publish(&mut self, message: &[u8]) -> Result<DualStep<H>, DualChainError> {
+        let (index, value) = self.commitments.disclose().ok_or(DualChainError::Exhausted)?;
+        let (_, key) = self.openings.disclose().expect("both chains have the same length");
+        let digest = digest::<H>(index, &key, message);
+        self.pending.push_back(DualOpening { index, key: key.to_array(), message: message.to_vec() });
+        let opening = if self.pending.len() as u64 > self.lag { self.pending.pop_front() } else { None };
+        Ok(DualStep { index, value: value.to_array(), digest, opening })
+    }
+
+    /// Open the commitments not yet due, e.g. when the feed ends.
+    pub fn finish(self) -> Vec<DualOpening<H>> {
+        self.pending.into()
+    }
+}
+
+pub struct DualVerifier<H: OutputSizeUser> {
+    commitment: (u64, GenericArray<u8, H::OutputSize>),
+    opening: (u64, GenericArray<u8, H::OutputSize>),
+    /// Accepted commitments waiting for their openings.
+    committed: VecDeque<(u64, GenericArray<u8, H::OutputSize>)>,
+}
+
+impl<H: Digest + FixedOutputReset> DualVerifier<H> {
+    pub fn new(commitment_anchor: GenericArray<u8, H::OutputSize>, opening_anchor: GenericArray<u8, H::OutputSize>) -> Self {
+        DualVerifier { commitment: (0, commitment_anchor), opening: (0, opening_anchor), committed: VecDeque::new() }
+    }
+
+    /// Accept a step's commitment, then its opening if it carries one, returning the opened
+    /// message with its index.
+    pub fn accept(&mut self, step: &DualStep<H>) -> Result<Option<(u64, Vec<u8>)>, DualChainError> {
+        let (known_index, known_value) = &self.commitment;
+        if step.index <= *known_index {
+            return Err(DualChainError::Stale);
+        }
+        if !verify::<H>(*known_index, known_value, step.index, &step.value) {
+            return Err(DualChainError::Forged);
+        }
+        self.commitment = (step.index, step.value.clone());
+        self.committed.push_back((step.index, step.digest.clone()));
+        step.opening.as_ref().map(|opening| self.open(opening).map(|message| (opening.index, message))).transpose()
+    }
+
+    /// Check an opening against its accepted commitment and return the message.
+    pub fn open(&mut self, opening: &DualOpening<H>) -> Result<Vec<u8>, DualChainError> {
+        let (known_index, known_value) = &self.opening;
+        if !verify::<H>(*known_index, known_value, opening.index, &opening.key) {
+            return Err(DualChainError::BadOpening);
+        }
+        let Some(position) = self.committed.iter().position(|(index, _)| *index == opening.index) else {
+            return Err(DualChainError::NotCommitted);
+        };
+        if digest::<H>(opening.index, &opening.key, &opening.message) != self.committed[position].1 {
+            return Err(DualChainError::Mismatch);
+        }
+        // commitments skipped over stay closed: their keys follow from this one, but without
+        // their messages there is nothing to open
+        self.committed.drain(..=position);
+        self.opening = (opening.index, opening.key.clone());
+        Ok(opening.message.clone())
+    }
+}
+
+#[test]
+fn test_dual_chain_feed() {
+    use sha2::Sha256;
+
+    assert!(DualChain::<Sha256>::new(8, 1, 2, 8).is_err());
+    let mut feed = DualChain::<Sha256>::new(8, 1, 2, 2).unwrap();
+    let mut verifier = DualVerifier::<Sha256>::new(*feed.commitment_anchor(), *feed.opening_anchor());
+    let first = feed.publish(b"first").unwrap();
+    assert!(first.opening.is_none());
+    assert_eq!(verifier.accept(&first), Ok(None));
+    assert_eq!(verifier.accept(&first), Err(DualChainError::Stale));
+    assert_eq!(verifier.accept(&feed.publish(b"second").unwrap()), Ok(None));
+
+    // the third step opens the first
+    let third = feed.publish(b"third").unwrap();
+    let mut tampered = third.clone();
+    tampered.opening.as_mut().unwrap().message = b"not first".to_vec();
+    assert_eq!(verifier.accept(&tampered), Err(DualChainError::Mismatch));
+    assert_eq!(verifier.open(third.opening.as_ref().unwrap()), Ok(b"first".to_vec()));
+    assert_eq!(verifier.open(third.opening.as_ref().unwrap()), Err(DualChainError::BadOpening));
+
+    let rest: Vec<_> = feed.finish().iter().map(|opening| verifier.open(opening)).collect();
+    assert_eq!(rest, [Ok(b"second".to_vec()), Ok(b"third".to_vec())]);
+}
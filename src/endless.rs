@@ -0,0 +1,237 @@
+//! An unbounded stream of values from a succession of chains, each handing over to the next.
+//!
+//! [`EndlessChain`] derives the seed of every chain, its generation, from one master seed. Once
+//! a chain enters its last [`EndlessPolicy::handover`] values the next chain is set up, and
+//! those values carry its anchor. Every one of them but the last also carries a tag on the
+//! anchor keyed by the value after it, `H("endless chain handover" || v_(i+1) || anchor)`, which
+//! the verifier checks once that value arrives. The first value of the new generation is then
+//! verified against the anchor so announced. As with TESLA, a tag only binds the anchor if it
+//! reaches the verifier before the value keying it is disclosed.
+
+use crate::{hash_forward, verify, ChainInitError, HashChain};
+use core::error::Error;
+use core::fmt::{self, Display};
+use digest::{Digest, FixedOutputReset, Output};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EndlessError {
+    /// The disclosure is neither of the verifier's generation nor of the next one.
+    WrongGeneration,
+    /// A new generation began before its anchor was handed over.
+    NotHandedOver,
+    /// A handover does not match its tag, or contradicts an earlier one. The disclosure itself
+    /// was accepted and the handover dropped.
+    HandoverMismatch,
+    /// The index is not above the last accepted one.
+    Replay,
+    /// The value does not verify against the generation's chain.
+    Mismatch,
+}
+
+impl Display for EndlessError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            EndlessError::WrongGeneration => write!(f, "disclosure for another generation"),
+            EndlessError::NotHandedOver => write!(f, "new generation without a handover"),
+            EndlessError::HandoverMismatch => write!(f, "handover does not match its tag"),
+            EndlessError::Replay => write!(f, "index already accepted"),
+            EndlessError::Mismatch => write!(f, "value does not verify against the chain"),
+        }
+    }
+}
+
+impl Error for EndlessError {}
+
+/// How long each chain is and how early it hands over to the next.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct EndlessPolicy {
+    pub length: usize,
+    /// The number of final values of each chain that carry the next chain's anchor, at least 2
+    /// so that one of them is tagged.
+    pub handover: u64,
+}
+
+/// The next chain's anchor, carried by the final values of a chain.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Handover<H: Digest> {
+    pub next_anchor: Output<H>,
+    /// Keyed by the value after this one, absent on the chain's last value.
+    pub tag: Option<Output<H>>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct EndlessDisclosure<H: Digest> {
+    pub generation: u64,
+    pub index: u64,
+    pub value: Output<H>,
+    pub handover: Option<Handover<H>>,
+}
+
+/// The seed of generation `generation`'s chain.
+fn generation_seed<H: Digest>(master_seed: u64, generation: u64) -> u64 {
+    let digest = H::new_with_prefix(b"endless chain seed").chain_update(master_seed.to_le_bytes()).chain_update(generation.to_le_bytes()).finalize();
+    u64::from_le_bytes(digest[..8].try_into().expect("digest output is at least 8 bytes"))
+}
+
+fn handover_tag<H: Digest>(key: &Output<H>, next_anchor: &Output<H>) -> Output<H> {
+    H::new_with_prefix(b"endless chain handover").chain_update(key).chain_update(next_anchor).finalize()
+}
+
+pub struct EndlessChain<H: Digest + FixedOutputReset> {
+    master_seed: u64,
+    policy: EndlessPolicy,
+    initial_anchor: Output<H>,
+    generation: u64,
+    chain: HashChain<H>,
+    /// Set up once the current chain enters its handover.
+    next: Option<HashChain<H>>,
+}
+
+impl<H: Digest + FixedOutputReset> EndlessChain<H> {
+    pub fn new(master_seed: u64, policy: EndlessPolicy) -> Result<Self, ChainInitError> {
+        let chain = HashChain::new(policy.length, generation_seed::<H>(master_seed, 0))?;
+        if policy.handover < 2 || policy.handover > chain.length() {
+            return Err(ChainInitError::new("handover must be between 2 and the chain length"));
+        }
+        Ok(EndlessChain { master_seed, policy, initial_anchor: chain.anchor().clone(), generation: 0, chain, next: None })
+    }
+
+    /// The anchor of the first generation, which verifiers start from.
+    pub fn initial_anchor(&self) -> &Output<H> {
+        &self.initial_anchor
+    }
+
+    pub fn generation(&self) -> u64 {
+        self.generation
+    }
+
+    /// The chain values are disclosed from.
+    pub fn current(&self) -> &HashChain<H> {
+        &self.chain
+    }
+
+    /// Disclose the next value, moving on to the next generation when the current chain is
+    /// exhausted.
+    pub fn disclose(&mut self) -> EndlessDisclosure<H> {
+        if self.chain.remaining() == 0 {
+            self.chain = self.next.take().unwrap_or_else(|| Self::setup(self.master_seed, self.policy, self.generation + 1));
+            self.generation += 1;
+        }
+        let (index, value) = self.chain.disclose().expect("the chain is not exhausted");
+        let length = self.chain.length();
+        let mut handover = None;
+        if index > length - self.policy.handover {
+            let generation = self.generation;
+            let next_anchor = self.next.get_or_insert_with(|| Self::setup(self.master_seed, self.policy, generation + 1)).anchor().clone();
+            // the value after this one, hashed down from the seed end of the chain
+            let tag = (index < length).then(|| {
+                let top = H::new_with_prefix(generation_seed::<H>(self.master_seed, generation).to_le_bytes()).finalize();
+                handover_tag::<H>(&hash_forward::<H>(&top, length - index - 1), &next_anchor)
+            });
+            handover = Some(Handover { next_anchor, tag });
+        }
+        EndlessDisclosure { generation: self.generation, index, value: value.to_array(), handover }
+    }
+
+    fn setup(master_seed: u64, policy: EndlessPolicy, generation: u64) -> HashChain<H> {
+        HashChain::new(policy.length, generation_seed::<H>(master_seed, generation)).expect("length validated on construction")
+    }
+}
+
+impl<H: Digest + FixedOutputReset> Iterator for EndlessChain<H> {
+    type Item = EndlessDisclosure<H>;
+
+    fn next(&mut self) -> Option<EndlessDisclosure<H>> {
+        Some(self.disclose())
+    }
+}
+
+pub struct EndlessVerifier<H: Digest + FixedOutputReset> {
+    generation: u64,
+    /// The last accepted index and value of the generation's chain, the anchor at index 0.
+    known: (u64, Output<H>),
+    /// The last tagged handover and its index, waiting for the value that keys its tag.
+    pending: Option<(u64, Output<H>, Output<H>)>,
+    /// The next generation's anchor, once a tag on it has verified.
+    next_anchor: Option<Output<H>>,
+}
+
+impl<H: Digest + FixedOutputReset> EndlessVerifier<H> {
+    pub fn new(initial_anchor: Output<H>) -> Self {
+        EndlessVerifier { generation: 0, known: (0, initial_anchor), pending: None, next_anchor: None }
+    }
+
+    pub fn generation(&self) -> u64 {
+        self.generation
+    }
+
+    /// Whether the next generation's anchor has been handed over.
+    pub fn handed_over(&self) -> bool {
+        self.next_anchor.is_some()
+    }
+
+    pub fn accept(&mut self, disclosure: &EndlessDisclosure<H>) -> Result<(), EndlessError> {
+        let (mut known, mut pending, mut next_anchor) = (self.known.clone(), self.pending.clone(), self.next_anchor.clone());
+        if disclosure.generation == self.generation + 1 {
+            known = (0, next_anchor.take().ok_or(EndlessError::NotHandedOver)?);
+            pending = None;
+        } else if disclosure.generation != self.generation {
+            return Err(EndlessError::WrongGeneration);
+        }
+        if disclosure.index <= known.0 {
+            return Err(EndlessError::Replay);
+        }
+        if !verify::<H>(known.0, &known.1, disclosure.index, &disclosure.value) {
+            return Err(EndlessError::Mismatch);
+        }
+        let mut result = Ok(());
+        if let Some((index, anchor, tag)) = pending.take() {
+            let key = hash_forward::<H>(&disclosure.value, disclosure.index - index - 1);
+            match &next_anchor {
+                _ if handover_tag::<H>(&key, &anchor) != tag => result = Err(EndlessError::HandoverMismatch),
+                Some(confirmed) if *confirmed != anchor => result = Err(EndlessError::HandoverMismatch),
+                _ => next_anchor = Some(anchor),
+            }
+        }
+        if let Some(handover) = &disclosure.handover {
+            match &handover.tag {
+                Some(tag) => pending = Some((disclosure.index, handover.next_anchor.clone(), tag.clone())),
+                None if next_anchor.as_ref().is_some_and(|confirmed| *confirmed != handover.next_anchor) => result = Err(EndlessError::HandoverMismatch),
+                None => {}
+            }
+        }
+        self.generation = disclosure.generation;
+        (self.known, self.pending, self.next_anchor) = ((disclosure.index, disclosure.value.clone()), pending, next_anchor);
+        result
+    }
+}
+
+#[test]
+fn test_endless_chain() {
+    use sha2::Sha256;
+
+    let policy = EndlessPolicy { length: 8, handover: 3 };
+    assert!(EndlessChain::<Sha256>::new(1, EndlessPolicy { handover: 1, ..policy }).is_err());
+    let mut prover = EndlessChain::<Sha256>::new(1, policy).unwrap();
+    let mut verifier = EndlessVerifier::<Sha256>::new(*prover.initial_anchor());
+    let disclosures: Vec<_> = prover.by_ref().take(20).collect();
+    for disclosure in &disclosures {
+        verifier.accept(disclosure).unwrap();
+    }
+    assert_eq!((prover.generation(), verifier.generation()), (2, 2));
+    assert_eq!(disclosures.iter().filter(|d| d.handover.is_some()).count(), 6);
+    assert_eq!(disclosures[8].index, 1);
+    assert_eq!(prover.initial_anchor(), EndlessChain::<Sha256>::new(1, policy).unwrap().current().anchor());
+
+    // a forged anchor is caught by the tag, and the forger's chain is then refused
+    let mut verifier = EndlessVerifier::<Sha256>::new(*prover.initial_anchor());
+    let mut forged = disclosures[5].clone();
+    forged.handover.as_mut().unwrap().next_anchor = *HashChain::<Sha256>::new(8, 9).unwrap().anchor();
+    for disclosure in &disclosures[..5] {
+        verifier.accept(disclosure).unwrap();
+    }
+    verifier.accept(&forged).unwrap();
+    assert_eq!(verifier.accept(&disclosures[7]), Err(EndlessError::HandoverMismatch));
+    assert!(!verifier.handed_over());
+    assert_eq!(verifier.accept(&disclosures[8]), Err(EndlessError::NotHandedOver));
+}
@@ -0,0 +1,93 @@
+//! Where the crate draws randomness from, so integration tests can replace the operating system
+//! with a reproducible source.
+//!
+//! Only [`Seed::generate`](crate::seed::Seed::generate) and, with the `vsss` feature,
+//! [`Seed::split`](crate::seed::Seed::split) draw randomness, from [`OsEntropy`], and both have a
+//! `_with` variant taking any [`EntropySource`]. Everything else is deterministic already, e.g.
+//! the WOTS+ masks are derived from the public seed. [`DeterministicEntropy`] expands a test seed
+//! into the same bytes on every run; it must never stand in for the operating system outside
+//! tests, since anyone knowing its seed knows every byte it yields.
+
+use core::error::Error;
+use core::fmt::{self, Display};
+#[cfg(feature = "sha2")]
+use {digest::Digest, sha2::Sha256};
+
+/// The entropy source failed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct EntropyError;
+
+impl Display for EntropyError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "random number generator failed")
+    }
+}
+
+impl Error for EntropyError {}
+
+pub trait EntropySource {
+    /// Fill `bytes` with random bytes.
+    fn fill(&mut self, bytes: &mut [u8]) -> Result<(), EntropyError>;
+}
+
+impl<E: EntropySource + ?Sized> EntropySource for &mut E {
+    fn fill(&mut self, bytes: &mut [u8]) -> Result<(), EntropyError> {
+        (**self).fill(bytes)
+    }
+}
+
+/// The operating system's random number generator.
+#[cfg(any(feature = "vsss", feature = "cli"))]
+#[derive(Debug, Clone, Copy, Default)]
+pub struct OsEntropy;
+
+#[cfg(any(feature = "vsss", feature = "cli"))]
+impl EntropySource for OsEntropy {
+    fn fill(&mut self, bytes: &mut [u8]) -> Result<(), EntropyError> {
+        getrandom::fill(bytes).map_err(|_| EntropyError)
+    }
+}
+
+#[cfg(feature = "sha2")]
+/// A reproducible source for tests: its `n`-th block of 32 bytes is `SHA-256("deterministic
+/// entropy" || seed || n)` with `n` as u64 big endian, and every fill starts a new block.
+#[derive(Debug, Clone)]
+pub struct DeterministicEntropy {
+    key: [u8; 32],
+    block: u64,
+}
+
+#[cfg(feature = "sha2")]
+impl DeterministicEntropy {
+    pub fn new(seed: &[u8]) -> Self {
+        DeterministicEntropy { key: Sha256::digest(seed).into(), block: 0 }
+    }
+}
+
+#[cfg(feature = "sha2")]
+impl EntropySource for DeterministicEntropy {
+    fn fill(&mut self, bytes: &mut [u8]) -> Result<(), EntropyError> {
+        for chunk in bytes.chunks_mut(32) {
+            let block = Sha256::new_with_prefix(b"deterministic entropy").chain_update(self.key).chain_update(self.block.to_be_bytes()).finalize();
+            chunk.copy_from_slice(&block[..chunk.len()]);
+            self.block += 1;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(feature = "sha2")]
+#[test]
+fn test_deterministic_entropy() {
+    let (mut first, mut second) = ([0u8; 40], [0u8; 40]);
+    DeterministicEntropy::new(b"test").fill(&mut first).unwrap();
+    DeterministicEntropy::new(b"test").fill(&mut second).unwrap();
+    assert_eq!(first, second);
+    DeterministicEntropy::new(b"other test").fill(&mut second).unwrap();
+    assert_ne!(first, second);
+    // a fill past one block moves on to the next
+    let mut source = DeterministicEntropy::new(b"test");
+    source.fill(&mut second[..32]).unwrap();
+    source.fill(&mut second[32..]).unwrap();
+    assert_eq!(first, second);
+}
@@ -0,0 +1,148 @@
+//! Wall-clock epochs, mapping times to chain indices for time-based disclosure.
+//!
+//! Epoch 1 starts at `start` and every epoch lasts `interval`, matching the interval numbering of
+//! [`tesla::Schedule`]. Readings of the local clock are shifted by a [`ClockOffset`] correction,
+//! and `max_drift` widens lookups to every epoch the reference clock could be in.
+//!
+//! [`EpochSchedule::current_index`] measures elapsed time with the monotonic clock from a
+//! reference reading taken at construction (or at the last [`EpochSchedule::resync`]), so steps
+//! of the system clock do not make indices jump or run backwards.
+
+use crate::tesla::{ClockOffset, Schedule};
+use crate::HashChain;
+use crate::value::ChainValue;
+use digest::{Digest, FixedOutputReset};
+use std::ops::RangeInclusive;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct EpochSchedule {
+    start: SystemTime,
+    interval: Duration,
+    correction: ClockOffset,
+    max_drift: Duration,
+    reference: (Instant, SystemTime),
+}
+
+fn since_unix_epoch(time: SystemTime) -> Duration {
+    time.duration_since(UNIX_EPOCH).unwrap_or(Duration::ZERO)
+}
+
+impl EpochSchedule {
+    pub fn new(start: SystemTime, interval: Duration) -> Self {
+        assert!(!interval.is_zero(), "epoch interval must be positive");
+        EpochSchedule { start, interval, correction: ClockOffset::default(), max_drift: Duration::ZERO, reference: (Instant::now(), SystemTime::now()) }
+    }
+
+    /// Correct local clock readings by `correction`, e.g. an offset measured against the sender.
+    pub fn with_correction(self, correction: ClockOffset) -> Self {
+        EpochSchedule { correction, ..self }
+    }
+
+    /// Tolerate the local clock being up to `max_drift` off in either direction.
+    pub fn with_max_drift(self, max_drift: Duration) -> Self {
+        EpochSchedule { max_drift, ..self }
+    }
+
+    pub fn start(&self) -> SystemTime {
+        self.start
+    }
+
+    pub fn interval(&self) -> Duration {
+        self.interval
+    }
+
+    /// The same schedule as a TESLA [`Schedule`] over durations since the Unix epoch.
+    pub fn tesla_schedule(&self) -> Schedule {
+        Schedule { start: since_unix_epoch(self.start), interval: self.interval }
+    }
+
+    /// The corrected time since the Unix epoch for a local clock reading, as TESLA expects it.
+    pub fn timestamp(&self, local: SystemTime) -> Duration {
+        self.correction.apply(since_unix_epoch(local))
+    }
+
+    /// The epoch containing the local time `local`, or `None` before the first one.
+    pub fn index_at(&self, local: SystemTime) -> Option<u64> {
+        self.tesla_schedule().interval_at(self.timestamp(local))
+    }
+
+    /// The epoch containing the monotonic instant `instant`, measured from the reference reading.
+    pub fn index_at_instant(&self, instant: Instant) -> Option<u64> {
+        let (reference_instant, reference_time) = self.reference;
+        let local = match instant.checked_duration_since(reference_instant) {
+            Some(elapsed) => reference_time + elapsed,
+            None => reference_time - reference_instant.duration_since(instant),
+        };
+        self.index_at(local)
+    }
+
+    pub fn current_index(&self) -> Option<u64> {
+        self.index_at_instant(Instant::now())
+    }
+
+    /// Take a new reference reading of the system clock, picking up any adjustment made to it.
+    pub fn resync(&mut self) {
+        self.reference = (Instant::now(), SystemTime::now());
+    }
+
+    /// Every epoch the reference clock may be in when ours reads `local`, given `max_drift`.
+    /// Empty before the first epoch can have started.
+    pub fn possible_indices(&self, local: SystemTime) -> RangeInclusive<u64> {
+        let schedule = self.tesla_schedule();
+        let time = self.timestamp(local);
+        let last = schedule.interval_at(time + self.max_drift).unwrap_or(0);
+        let first = schedule.interval_at(time.saturating_sub(self.max_drift)).unwrap_or(1);
+        first..=last
+    }
+
+    /// When epoch `index` begins, or `None` if that lies beyond what `SystemTime` can represent.
+    pub fn start_of(&self, index: u64) -> Option<SystemTime> {
+        let nanos = self.interval.as_nanos().checked_mul(u128::from(index.saturating_sub(1)))?;
+        let offset = Duration::new(u64::try_from(nanos / 1_000_000_000).ok()?, (nanos % 1_000_000_000) as u32);
+        self.start.checked_add(offset)
+    }
+
+    /// Advance `chain` to the current epoch and return that epoch's value. Returns `None` before
+    /// the first epoch, once the chain is exhausted, or if the chain has already gone past the
+    /// current epoch.
+    pub fn key_for_now<H: Digest + FixedOutputReset>(&self, chain: &mut HashChain<H>) -> Option<(u64, ChainValue<H>)> {
+        let index = self.current_index()?;
+        let position = HashChain::position(chain);
+        if index <= position {
+            return None;
+        }
+        chain.nth((index - position - 1) as usize)
+    }
+}
+
+#[test]
+fn test_epoch_indices() {
+    let start = UNIX_EPOCH + Duration::from_secs(1_000);
+    let schedule = EpochSchedule::new(start, Duration::from_secs(10));
+    assert_eq!(schedule.index_at(start - Duration::from_secs(1)), None);
+    assert_eq!(schedule.index_at(start), Some(1));
+    assert_eq!(schedule.index_at(start + Duration::from_secs(25)), Some(3));
+    assert_eq!(schedule.start_of(3), Some(start + Duration::from_secs(20)));
+    assert_eq!(schedule.start_of(1 << 32), Some(start + Duration::from_secs(10 * ((1 << 32) - 1))));
+    assert_eq!(schedule.start_of(u64::MAX), None);
+    assert_eq!(schedule.tesla_schedule().interval_at(Duration::from_secs(1_025)), Some(3));
+
+    let skewed = schedule.with_correction(ClockOffset::ahead(Duration::from_secs(10))).with_max_drift(Duration::from_secs(2));
+    assert_eq!(skewed.index_at(start + Duration::from_secs(25)), Some(4));
+    assert_eq!(skewed.possible_indices(start + Duration::from_secs(29)), 4..=5);
+    assert!(schedule.possible_indices(start - Duration::from_secs(5)).is_empty());
+}
+
+#[test]
+fn test_key_for_now() {
+    use sha2::Sha256;
+
+    let schedule = EpochSchedule::new(SystemTime::now() - Duration::from_secs(35), Duration::from_secs(10));
+    let mut chain = HashChain::<Sha256>::new(16, 3).unwrap();
+    let expected = crate::create_hash_chain_nopebble::<Sha256>(16, 3);
+    let (index, key) = schedule.key_for_now(&mut chain).unwrap();
+    assert_eq!(index, 4);
+    assert_eq!(key, expected[16 - 4]);
+    assert!(schedule.key_for_now(&mut chain).is_none());
+}
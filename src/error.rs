@@ -0,0 +1,51 @@
+//! The errors of chain setup and auditing.
+
+use alloc::string::{String, ToString};
+use core::error::Error;
+use core::fmt::{self, Display};
+
+#[derive(Debug, Clone)]
+pub struct ChainInitError {
+    pub(crate) details: String,
+}
+
+impl ChainInitError {
+    pub(crate) fn new(error_message: &str) -> ChainInitError {
+        ChainInitError { details: error_message.to_string() }
+    }
+}
+
+impl Display for ChainInitError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.details)
+    }
+}
+
+impl Error for ChainInitError {
+    fn description(&self) -> &str {
+        &self.details
+    }
+}
+
+/// Why [`audit_chain`](crate::audit_chain) rejected a chain.
+#[derive(Debug, Clone)]
+pub enum ChainAuditError {
+    /// The length is not valid for a chain.
+    Length(ChainInitError),
+    /// The seed does not lead to the anchor.
+    AnchorMismatch,
+    /// The pebble at this position does not hold the chain value there.
+    PebbleMismatch { position: u64 },
+}
+
+impl Display for ChainAuditError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ChainAuditError::Length(e) => write!(f, "invalid chain length: {}", e),
+            ChainAuditError::AnchorMismatch => write!(f, "seed does not lead to the anchor"),
+            ChainAuditError::PebbleMismatch { position } => write!(f, "pebble at position {} does not match the chain", position),
+        }
+    }
+}
+
+impl Error for ChainAuditError {}
@@ -0,0 +1,91 @@
+//! Encodings for redeeming SHA-256 chain values on the EVM through the reference verifier in
+//! `contracts/ChainVerifier.sol`.
+//!
+//! The contract hashes values with the `sha256` precompile, so chains built with
+//! `HashChain<Sha256>` verify on-chain unchanged. [`encode_packed`] is the exact
+//! `abi.encodePacked(chainId, index, value)` string its redemption id is the Keccak-256 of, and
+//! [`enroll_calldata`] and [`redeem_calldata`] build transactions calling it.
+
+use crate::ChainId;
+use alloc::vec::Vec;
+use digest::Digest;
+use sha3::Keccak256;
+
+/// A SHA-256 chain value.
+pub type Value = digest::Output<sha2::Sha256>;
+
+/// The 4-byte selector of the function with the canonical `signature`, e.g.
+/// `"redeem(bytes16,uint64,bytes32)"`.
+pub fn selector(signature: &str) -> [u8; 4] {
+    let hash = Keccak256::digest(signature.as_bytes());
+    [hash[0], hash[1], hash[2], hash[3]]
+}
+
+/// `abi.encodePacked(bytes16 chainId, uint64 index, bytes32 value)`: 56 bytes, with the index
+/// big-endian.
+pub fn encode_packed(chain_id: &ChainId, index: u64, value: &Value) -> Vec<u8> {
+    let mut packed = Vec::with_capacity(56);
+    packed.extend_from_slice(&chain_id.0);
+    packed.extend_from_slice(&index.to_be_bytes());
+    packed.extend_from_slice(value);
+    packed
+}
+
+/// The id the contract emits for a redemption, `keccak256(abi.encodePacked(...))`.
+pub fn redemption_id(chain_id: &ChainId, index: u64, value: &Value) -> [u8; 32] {
+    Keccak256::digest(encode_packed(chain_id, index, value)).into()
+}
+
+/// A 32-byte ABI word holding a `bytes16`, left-aligned.
+fn bytes16_word(bytes: &[u8; 16]) -> [u8; 32] {
+    let mut word = [0; 32];
+    word[..16].copy_from_slice(bytes);
+    word
+}
+
+/// A 32-byte ABI word holding a `uint64`, right-aligned.
+fn uint_word(value: u64) -> [u8; 32] {
+    let mut word = [0; 32];
+    word[24..].copy_from_slice(&value.to_be_bytes());
+    word
+}
+
+fn calldata(signature: &str, words: &[[u8; 32]]) -> Vec<u8> {
+    let mut data = Vec::with_capacity(4 + 32 * words.len());
+    data.extend_from_slice(&selector(signature));
+    words.iter().for_each(|word| data.extend_from_slice(word));
+    data
+}
+
+/// Calldata for `enroll(bytes16 chainId, bytes32 anchor)`.
+pub fn enroll_calldata(chain_id: &ChainId, anchor: &Value) -> Vec<u8> {
+    calldata("enroll(bytes16,bytes32)", &[bytes16_word(&chain_id.0), (*anchor).into()])
+}
+
+/// Calldata for `redeem(bytes16 chainId, uint64 index, bytes32 value)`.
+pub fn redeem_calldata(chain_id: &ChainId, index: u64, value: &Value) -> Vec<u8> {
+    calldata("redeem(bytes16,uint64,bytes32)", &[bytes16_word(&chain_id.0), uint_word(index), (*value).into()])
+}
+
+#[test]
+fn test_evm_encodings() {
+    use crate::HashChain;
+    use sha2::Sha256;
+
+    assert_eq!(selector("transfer(address,uint256)"), [0xa9, 0x05, 0x9c, 0xbb]);
+
+    let mut chain = HashChain::<Sha256>::new(16, 1).unwrap();
+    let (index, value) = chain.disclose().unwrap();
+    let id = ChainId([0xab; 16]);
+    let packed = encode_packed(&id, index, &value);
+    assert_eq!(packed.len(), 56);
+    assert_eq!(&packed[16..24], &[0, 0, 0, 0, 0, 0, 0, 1]);
+
+    let data = redeem_calldata(&id, index, &value);
+    assert_eq!(data.len(), 4 + 3 * 32);
+    assert_eq!(&data[..4], &selector("redeem(bytes16,uint64,bytes32)"));
+    assert_eq!((&data[4..20], &data[20..36]), (&[0xab; 16][..], &[0; 16][..]));
+    assert_eq!(data[67], 1);
+    assert_eq!(&data[68..], value.as_slice());
+    assert_eq!(enroll_calldata(&id, chain.anchor()).len(), 4 + 2 * 32);
+}
@@ -0,0 +1,141 @@
+//! Forward-secure symmetric keys.
+//!
+//! An [`EvolvingKey`] holds the current value of a key chain and replaces it with its hash on
+//! every [`EvolvingKey::evolve`], wiping the old value. Whoever obtains the key at epoch `i` can
+//! compute every later key but none of the earlier ones, so data protected under earlier keys
+//! stays safe after a compromise.
+
+use digest::core_api::BlockSizeUser;
+use digest::generic_array::GenericArray;
+use digest::{Digest, Mac, OutputSizeUser};
+use hmac::SimpleHmac;
+use std::error::Error;
+use std::fmt::{self, Debug, Display};
+use zeroize::Zeroize;
+
+#[derive(Debug, Clone)]
+pub struct KeyFormatError {
+    details: String,
+}
+
+impl KeyFormatError {
+    fn new(error_message: &str) -> KeyFormatError {
+        KeyFormatError { details: error_message.to_string() }
+    }
+}
+
+impl Display for KeyFormatError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.details)
+    }
+}
+
+impl Error for KeyFormatError {}
+
+/// A key that can only move forward. The value is wiped on evolution and on drop, and is
+/// deliberately neither `Clone` nor shown by `Debug`.
+pub struct EvolvingKey<H: OutputSizeUser> {
+    epoch: u64,
+    key: GenericArray<u8, H::OutputSize>,
+}
+
+impl<H: Digest> EvolvingKey<H> {
+    /// Start at epoch 0 with `initial`.
+    pub fn new(initial: GenericArray<u8, H::OutputSize>) -> Self {
+        EvolvingKey { epoch: 0, key: initial }
+    }
+
+    pub fn epoch(&self) -> u64 {
+        self.epoch
+    }
+
+    pub fn key(&self) -> &GenericArray<u8, H::OutputSize> {
+        &self.key
+    }
+
+    /// Advance to the next epoch, overwriting the current key.
+    pub fn evolve(&mut self) {
+        let mut next = H::digest(self.key.as_slice());
+        self.key.copy_from_slice(&next);
+        next.as_mut_slice().zeroize();
+        self.epoch += 1;
+    }
+
+    /// Advance to `epoch`, which must not lie in the past.
+    pub fn evolve_to(&mut self, epoch: u64) -> Result<(), KeyFormatError> {
+        if epoch < self.epoch {
+            return Err(KeyFormatError::new("cannot evolve to an earlier epoch"));
+        }
+        while self.epoch < epoch {
+            self.evolve();
+        }
+        Ok(())
+    }
+
+    /// Serialize as the epoch (u64 big-endian) followed by the key. The result holds the key and
+    /// should be handled, and wiped, accordingly.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = self.epoch.to_be_bytes().to_vec();
+        bytes.extend_from_slice(&self.key);
+        bytes
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, KeyFormatError> {
+        if bytes.len() != 8 + <H as Digest>::output_size() {
+            return Err(KeyFormatError::new("wrong length for an evolving key"));
+        }
+        let (epoch, key) = bytes.split_at(8);
+        Ok(EvolvingKey { epoch: u64::from_be_bytes(epoch.try_into().expect("8 bytes")), key: GenericArray::clone_from_slice(key) })
+    }
+}
+
+impl<H: Digest + BlockSizeUser> EvolvingKey<H> {
+    /// A subkey for `purpose` in the current epoch, so one chain can key several primitives.
+    pub fn derive(&self, purpose: &[u8]) -> Vec<u8> {
+        let mut prf = <SimpleHmac<H> as Mac>::new_from_slice(&self.key).expect("HMAC accepts any key length");
+        prf.update(purpose);
+        prf.finalize().into_bytes().to_vec()
+    }
+}
+
+impl<H: OutputSizeUser> Drop for EvolvingKey<H> {
+    fn drop(&mut self) {
+        self.key.as_mut_slice().zeroize();
+    }
+}
+
+impl<H: OutputSizeUser> Debug for EvolvingKey<H> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("EvolvingKey").field("epoch", &self.epoch).finish_non_exhaustive()
+    }
+}
+
+impl<H: Digest> crate::version::Versioned for EvolvingKey<H> {
+    type Error = KeyFormatError;
+
+    fn encode_body(&self, _version: u8) -> Vec<u8> {
+        self.to_bytes()
+    }
+
+    fn decode_body(_version: u8, body: &[u8]) -> Result<Self, KeyFormatError> {
+        Self::from_bytes(body)
+    }
+}
+
+#[test]
+fn test_evolving_key() {
+    use sha2::Sha256;
+
+    let initial = Sha256::digest(b"initial");
+    let mut key = EvolvingKey::<Sha256>::new(initial);
+    let encryption = key.derive(b"encryption");
+    key.evolve();
+    assert_eq!(*key.key(), Sha256::digest(initial));
+    assert_ne!(key.derive(b"encryption"), encryption);
+
+    key.evolve_to(5).unwrap();
+    assert!(key.evolve_to(4).is_err());
+    let restored = EvolvingKey::<Sha256>::from_bytes(&key.to_bytes()).unwrap();
+    assert_eq!((restored.epoch(), restored.key()), (5, &crate::hash_forward::<Sha256>(&initial, 5)));
+    assert!(EvolvingKey::<Sha256>::from_bytes(&[0; 8]).is_err());
+}
@@ -0,0 +1,139 @@
+//! An [axum](https://docs.rs/axum) extractor for chain-token authentication.
+//!
+//! Handlers taking a [`ChainAuth`] argument only run for requests carrying a valid, unspent
+//! [`ChainToken`]. The router state must provide a [`ChainAuthState`] through `FromRef`. If the
+//! request already passed through a [`ChainTokenLayer`](crate::middleware::ChainTokenLayer), the
+//! token it verified is reused instead of spending another value.
+
+use crate::middleware::VerifiedToken;
+use crate::registry::{ChainToken, TokenVerifier, VerifyError};
+use crate::ChainId;
+use axum_core::extract::{FromRef, FromRequestParts};
+use axum_core::response::{IntoResponse, Response};
+use http::request::Parts;
+use http::{header, HeaderName, StatusCode};
+use std::error::Error;
+use std::fmt::{self, Display};
+use std::marker::PhantomData;
+use std::sync::Arc;
+
+/// The verifier and header used by [`ChainAuth`].
+#[derive(Clone)]
+pub struct ChainAuthState {
+    verifier: Arc<dyn TokenVerifier>,
+    header: HeaderName,
+}
+
+impl ChainAuthState {
+    pub fn new(verifier: Arc<dyn TokenVerifier>) -> Self {
+        ChainAuthState { verifier, header: header::AUTHORIZATION }
+    }
+
+    /// Read tokens from `header` instead of `Authorization`.
+    pub fn with_header(mut self, header: HeaderName) -> Self {
+        self.header = header;
+        self
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ChainAuthError {
+    /// The request carries no token header.
+    Missing,
+    /// The token header could not be parsed.
+    Malformed,
+    /// The token was parsed but not accepted.
+    Rejected(VerifyError),
+}
+
+impl Display for ChainAuthError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ChainAuthError::Missing => write!(f, "missing chain token"),
+            ChainAuthError::Malformed => write!(f, "malformed chain token"),
+            ChainAuthError::Rejected(e) => write!(f, "chain token rejected: {}", e),
+        }
+    }
+}
+
+impl Error for ChainAuthError {}
+
+/// The default rejection: `401 Unauthorized` with the error message as body.
+#[derive(Debug)]
+pub struct ChainAuthRejection(pub ChainAuthError);
+
+impl From<ChainAuthError> for ChainAuthRejection {
+    fn from(error: ChainAuthError) -> Self {
+        ChainAuthRejection(error)
+    }
+}
+
+impl IntoResponse for ChainAuthRejection {
+    fn into_response(self) -> Response {
+        (StatusCode::UNAUTHORIZED, self.0.to_string()).into_response()
+    }
+}
+
+/// The verified chain and index of the current request. `R` is the rejection returned when
+/// authentication fails and can be any type built from a [`ChainAuthError`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ChainAuth<R = ChainAuthRejection> {
+    pub chain_id: ChainId,
+    pub index: u64,
+    _rejection: PhantomData<fn() -> R>,
+}
+
+impl<S, R> FromRequestParts<S> for ChainAuth<R>
+where
+    ChainAuthState: FromRef<S>,
+    S: Send + Sync,
+    R: From<ChainAuthError> + IntoResponse,
+{
+    type Rejection = R;
+
+    async fn from_request_parts(parts: &mut Parts, state: &S) -> Result<Self, Self::Rejection> {
+        if let Some(verified) = parts.extensions.get::<VerifiedToken>() {
+            return Ok(ChainAuth { chain_id: verified.chain_id, index: verified.index, _rejection: PhantomData });
+        }
+        let auth = ChainAuthState::from_ref(state);
+        let value = parts.headers.get(&auth.header).ok_or(ChainAuthError::Missing)?;
+        let value = value.to_str().map_err(|_| ChainAuthError::Malformed)?;
+        let token: ChainToken = value.strip_prefix("Bearer ").unwrap_or(value).parse()
+            .map_err(|_| ChainAuthError::Malformed)?;
+        auth.verifier.verify_token(&token).map_err(ChainAuthError::Rejected)?;
+        Ok(ChainAuth { chain_id: token.chain_id, index: token.index, _rejection: PhantomData })
+    }
+}
+
+#[test]
+fn test_extractor_verifies_and_rejects() {
+    use crate::registry::Registry;
+    use crate::store::MemoryStore;
+    use crate::{create_hash_chain_nopebble, hash_forward};
+    use sha2::Sha256;
+    use std::future::Future;
+    use std::task::{Context, Poll};
+
+    fn extract(state: &ChainAuthState, token: &str) -> Result<ChainAuth, ChainAuthRejection> {
+        let request = http::Request::builder().header(header::AUTHORIZATION, token).body(()).unwrap();
+        let (mut parts, _) = request.into_parts();
+        let mut future = std::pin::pin!(ChainAuth::<ChainAuthRejection>::from_request_parts(&mut parts, state));
+        let Poll::Ready(result) = future.as_mut().poll(&mut Context::from_waker(std::task::Waker::noop())) else {
+            unreachable!()
+        };
+        result
+    }
+
+    let chain = create_hash_chain_nopebble::<Sha256>(4, 5);
+    let id = ChainId([3; 16]);
+    let registry = Registry::<Sha256, _>::new(MemoryStore::new());
+    registry.register(id, hash_forward::<Sha256>(&chain[3], 1)).unwrap();
+    let state = ChainAuthState::new(Arc::new(registry));
+
+    let token = ChainToken { chain_id: id, index: 2, value: chain[2].to_vec() }.to_string();
+    let auth = extract(&state, &format!("Bearer {}", token)).unwrap();
+    assert_eq!((auth.chain_id, auth.index), (id, 2));
+    let rejection = extract(&state, &token).unwrap_err();
+    assert_eq!(rejection.0, ChainAuthError::Rejected(VerifyError::Replay));
+    assert_eq!(extract(&state, "Bearer nonsense").unwrap_err().0, ChainAuthError::Malformed);
+}
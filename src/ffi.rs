@@ -0,0 +1,165 @@
+//! A C API over SHA-256 chains, laid out for cbindgen (see `cbindgen.toml`). Chains are opaque
+//! handles created with [`fht_chain_new`] or [`fht_chain_deserialize`] and released with
+//! [`fht_chain_free`]. Functions returning `int` report [`FHT_OK`] or one of the negative
+//! `FHT_ERR_*` codes. Link the crate as a static library, e.g. with
+//! `cargo rustc --release --features ffi --crate-type staticlib`.
+
+use crate::{verify, HashChain};
+use digest::generic_array::GenericArray;
+use sha2::Sha256;
+use std::os::raw::c_int;
+use std::ptr;
+use std::slice;
+
+/// The size of chain values.
+pub const FHT_VALUE_SIZE: usize = 32;
+
+pub const FHT_OK: c_int = 0;
+/// A required pointer was null.
+pub const FHT_ERR_NULL: c_int = -1;
+/// The chain has no values left.
+pub const FHT_ERR_EXHAUSTED: c_int = -2;
+/// The output buffer is too small; the required size has been written.
+pub const FHT_ERR_BUFFER: c_int = -3;
+
+/// An opaque chain handle.
+pub struct FhtChain(HashChain<Sha256>);
+
+fn into_handle(chain: HashChain<Sha256>) -> *mut FhtChain {
+    Box::into_raw(Box::new(FhtChain(chain)))
+}
+
+/// Set up a chain of `length` values from `seed`, or return null if `length` is not a power of
+/// two of at least 2.
+#[no_mangle]
+pub extern "C" fn fht_chain_new(length: u64, seed: u64) -> *mut FhtChain {
+    match HashChain::new(length as usize, seed) {
+        Ok(chain) => into_handle(chain),
+        Err(_) => ptr::null_mut(),
+    }
+}
+
+/// Release a chain. Null is ignored.
+///
+/// # Safety
+///
+/// `chain` must be null or a handle from this library that has not been freed.
+#[no_mangle]
+pub unsafe extern "C" fn fht_chain_free(chain: *mut FhtChain) {
+    if !chain.is_null() {
+        drop(Box::from_raw(chain));
+    }
+}
+
+/// Write the anchor to `out`.
+///
+/// # Safety
+///
+/// `chain` must be a live handle and `out` must point to [`FHT_VALUE_SIZE`] writable bytes.
+#[no_mangle]
+pub unsafe extern "C" fn fht_chain_anchor(chain: *const FhtChain, out: *mut u8) -> c_int {
+    let (Some(chain), false) = (chain.as_ref(), out.is_null()) else {
+        return FHT_ERR_NULL;
+    };
+    ptr::copy_nonoverlapping(chain.0.anchor().as_ptr(), out, FHT_VALUE_SIZE);
+    FHT_OK
+}
+
+/// Disclose the next value, writing its index to `index` and the value to `out`.
+///
+/// # Safety
+///
+/// `chain` must be a live handle not used concurrently, `index` must point to a writable
+/// `uint64_t` and `out` to [`FHT_VALUE_SIZE`] writable bytes.
+#[no_mangle]
+pub unsafe extern "C" fn fht_chain_next(chain: *mut FhtChain, index: *mut u64, out: *mut u8) -> c_int {
+    let (Some(chain), false, false) = (chain.as_mut(), index.is_null(), out.is_null()) else {
+        return FHT_ERR_NULL;
+    };
+    let Some((position, value)) = chain.0.disclose() else {
+        return FHT_ERR_EXHAUSTED;
+    };
+    *index = position;
+    ptr::copy_nonoverlapping(value.as_ptr(), out, FHT_VALUE_SIZE);
+    FHT_OK
+}
+
+/// Return 1 if `value` at `index` hashes forward to `known` at `known_index`, 0 otherwise.
+///
+/// # Safety
+///
+/// `known` and `value` must each point to [`FHT_VALUE_SIZE`] readable bytes.
+#[no_mangle]
+pub unsafe extern "C" fn fht_verify(known_index: u64, known: *const u8, index: u64, value: *const u8) -> c_int {
+    if known.is_null() || value.is_null() {
+        return 0;
+    }
+    let known = GenericArray::from_slice(slice::from_raw_parts(known, FHT_VALUE_SIZE));
+    let value = GenericArray::from_slice(slice::from_raw_parts(value, FHT_VALUE_SIZE));
+    verify::<Sha256>(known_index, known, index, value) as c_int
+}
+
+/// Write the chain's state (see [`HashChain::export_state`]) to `out`, which has room for
+/// `capacity` bytes, and its size to `written`. If `out` is too small, only the size is written
+/// and [`FHT_ERR_BUFFER`] returned; `out` may then be null.
+///
+/// # Safety
+///
+/// `chain` must be a live handle, `written` must point to a writable `size_t` and `out`, unless
+/// null, to `capacity` writable bytes.
+#[no_mangle]
+pub unsafe extern "C" fn fht_chain_serialize(chain: *const FhtChain, out: *mut u8, capacity: usize, written: *mut usize) -> c_int {
+    let (Some(chain), false) = (chain.as_ref(), written.is_null()) else {
+        return FHT_ERR_NULL;
+    };
+    let state = chain.0.export_state();
+    *written = state.len();
+    if out.is_null() || capacity < state.len() {
+        return FHT_ERR_BUFFER;
+    }
+    ptr::copy_nonoverlapping(state.as_ptr(), out, state.len());
+    FHT_OK
+}
+
+/// Restore a chain from `len` bytes written by [`fht_chain_serialize`], or return null if they
+/// are not a valid state.
+///
+/// # Safety
+///
+/// `bytes` must point to `len` readable bytes.
+#[no_mangle]
+pub unsafe extern "C" fn fht_chain_deserialize(bytes: *const u8, len: usize) -> *mut FhtChain {
+    if bytes.is_null() {
+        return ptr::null_mut();
+    }
+    match HashChain::import_state(slice::from_raw_parts(bytes, len)) {
+        Ok(chain) => into_handle(chain),
+        Err(_) => ptr::null_mut(),
+    }
+}
+
+#[test]
+fn test_ffi_roundtrip() {
+    unsafe {
+        let chain = fht_chain_new(16, 4);
+        assert!(fht_chain_new(12, 4).is_null());
+        let (mut anchor, mut value, mut index) = ([0u8; 32], [0u8; 32], 0u64);
+        assert_eq!(fht_chain_anchor(chain, anchor.as_mut_ptr()), FHT_OK);
+        assert_eq!(fht_chain_next(chain, &mut index, value.as_mut_ptr()), FHT_OK);
+        assert_eq!(fht_verify(0, anchor.as_ptr(), index, value.as_ptr()), 1);
+
+        let mut size = 0;
+        assert_eq!(fht_chain_serialize(chain, ptr::null_mut(), 0, &mut size), FHT_ERR_BUFFER);
+        let mut state = vec![0u8; size];
+        assert_eq!(fht_chain_serialize(chain, state.as_mut_ptr(), state.len(), &mut size), FHT_OK);
+        let restored = fht_chain_deserialize(state.as_ptr(), state.len());
+        let (mut next, mut restored_next) = ([0u8; 32], [0u8; 32]);
+        fht_chain_next(chain, &mut index, next.as_mut_ptr());
+        assert_eq!(fht_chain_next(restored, &mut index, restored_next.as_mut_ptr()), FHT_OK);
+        assert_eq!(next, restored_next);
+        assert_eq!(fht_verify(1, value.as_ptr(), 2, next.as_ptr()), 1);
+
+        fht_chain_free(chain);
+        fht_chain_free(restored);
+    }
+}
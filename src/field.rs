@@ -0,0 +1,106 @@
+//! Chains whose values are elements of a prime field, for use as public inputs in zk circuits
+//! built with [arkworks](https://arkworks.rs).
+//!
+//! [`FieldHash`] wraps a digest and maps every output into the field `F`: the digest is read as
+//! a little-endian integer and reduced modulo the field's order, and the element is written
+//! back in its canonical little-endian encoding. Since it is a digest itself, a
+//! `HashChain<FieldHash<F, H>>` traverses, verifies and registers like any other chain, and
+//! every value it discloses, the anchor included, decodes with [`to_field`] to the element a
+//! circuit recomputes as `F::from_le_bytes_mod_order(H(value))`.
+//!
+//! The field must fit the digest, i.e. its elements must take at most as many bytes as `H`
+//! outputs.
+
+use ark_ff::{BigInteger, PrimeField};
+use core::marker::PhantomData;
+use digest::{FixedOutput, FixedOutputReset, HashMarker, Output, OutputSizeUser, Reset, Update};
+
+/// The digest `H` followed by reduction into the field `F`.
+pub struct FieldHash<F, H> {
+    hasher: H,
+    _field: PhantomData<F>,
+}
+
+impl<F, H: Default> Default for FieldHash<F, H> {
+    fn default() -> Self {
+        FieldHash { hasher: H::default(), _field: PhantomData }
+    }
+}
+
+impl<F, H: Clone> Clone for FieldHash<F, H> {
+    fn clone(&self) -> Self {
+        FieldHash { hasher: self.hasher.clone(), _field: PhantomData }
+    }
+}
+
+impl<F, H: Update> Update for FieldHash<F, H> {
+    fn update(&mut self, data: &[u8]) {
+        self.hasher.update(data);
+    }
+}
+
+impl<F, H: OutputSizeUser> OutputSizeUser for FieldHash<F, H> {
+    type OutputSize = H::OutputSize;
+}
+
+/// Write the canonical encoding of `H(...) mod p` into `out`. The element is below both the
+/// modulus and `2^(8 * output size)`, so its encoding never needs more bytes than `out` has.
+fn reduce_into<F: PrimeField, H: OutputSizeUser>(digest: &Output<H>, out: &mut Output<H>) {
+    let bytes = F::from_le_bytes_mod_order(digest).into_bigint().to_bytes_le();
+    out.iter_mut().for_each(|b| *b = 0);
+    for (out, byte) in out.iter_mut().zip(&bytes) {
+        *out = *byte;
+    }
+}
+
+impl<F: PrimeField, H: FixedOutput> FixedOutput for FieldHash<F, H> {
+    fn finalize_into(self, out: &mut Output<Self>) {
+        reduce_into::<F, H>(&self.hasher.finalize_fixed(), out);
+    }
+}
+
+impl<F, H: Reset> Reset for FieldHash<F, H> {
+    fn reset(&mut self) {
+        self.hasher.reset();
+    }
+}
+
+impl<F: PrimeField, H: FixedOutputReset> FixedOutputReset for FieldHash<F, H> {
+    fn finalize_into_reset(&mut self, out: &mut Output<Self>) {
+        reduce_into::<F, H>(&self.hasher.finalize_fixed_reset(), out);
+    }
+}
+
+impl<F, H: HashMarker> HashMarker for FieldHash<F, H> {}
+
+/// The field element a value of a `FieldHash` chain encodes.
+pub fn to_field<F: PrimeField>(value: &[u8]) -> F {
+    F::from_le_bytes_mod_order(value)
+}
+
+#[test]
+#[allow(unexpected_cfgs)] // the MontConfig derive checks arkworks' own `asm` feature
+fn test_field_chain() {
+    use crate::{verify, HashChain};
+    use ark_ff::{Fp256, MontBackend, MontConfig};
+    use digest::Digest;
+    use sha2::Sha256;
+
+    // the BN254 scalar field
+    #[derive(MontConfig)]
+    #[modulus = "21888242871839275222246405745257275088548364400416034343698204186575808495617"]
+    #[generator = "5"]
+    struct FrConfig;
+    type Fr = Fp256<MontBackend<FrConfig, 4>>;
+
+    type Hash = FieldHash<Fr, Sha256>;
+    let mut chain = HashChain::<Hash>::new(16, 4).unwrap();
+    let anchor = *chain.anchor();
+    let (index, value) = chain.disclose().unwrap();
+    assert!(verify::<Hash>(0, &anchor, index, &value));
+
+    // what a circuit checks: the anchor is the reduced hash of the value's bytes
+    let element = to_field::<Fr>(&value);
+    assert_eq!(element.into_bigint().to_bytes_le(), value.to_vec());
+    assert_eq!(to_field::<Fr>(&anchor), Fr::from_le_bytes_mod_order(&Sha256::digest(value)));
+}
@@ -0,0 +1,205 @@
+//! FIPS mode: chains hashed only by OpenSSL's FIPS provider, with the provider recorded in their
+//! commitments for audits.
+//!
+//! In FIPS mode the chain step is [`FipsSha256`], [`FipsSha384`] or [`FipsSha512`], the only
+//! [`FipsDigest`]s, which [`FipsProvider::new_chain`] and [`FipsProvider::commitment`] require.
+//! The feature is additive: the digests of `backend` and `sha2` stay compiled for code not set
+//! up through the provider, and FIPS mode is enforced where chains are created instead. The
+//! FIPS digests fetch their algorithm from OpenSSL 3 with the property query `fips=yes`, so no
+//! provider but the FIPS one can supply it, and [`FipsProvider::load`] must have loaded that
+//! provider before the first of them is created. [`check`] and [`FipsProvider::accept`] refuse
+//! every hash function not in [`APPROVED`], e.g. a commitment naming `md5`, and
+//! [`FipsProvider::commitment`] writes the provider into the [`AnchorCommitment`], so an
+//! auditor can tell which module set a chain up.
+//!
+//! AWS-LC's FIPS build would serve as well, but it needs CMake and Go to build; OpenSSL's FIPS
+//! provider is configured at run time instead, through `fipsmodule.cnf`. The `sha2` hashing the
+//! crate does besides the chain step, e.g. for chain ids and fingerprints, is not affected.
+
+use crate::anchor::AnchorCommitment;
+use crate::{ChainInitError, HashChain};
+use digest::consts::{U128, U32, U48, U64};
+use digest::core_api::BlockSizeUser;
+use digest::{FixedOutput, FixedOutputReset, HashMarker, Output, OutputSizeUser, Reset, Update};
+use openssl::md::Md;
+use openssl::md_ctx::MdCtx;
+use openssl::provider::Provider;
+use std::error::Error;
+use std::fmt::{self, Display};
+use std::sync::OnceLock;
+
+/// The hash functions chains may use in FIPS mode, by their commitment names.
+pub const APPROVED: &[&str] = &["sha256", "sha384", "sha512"];
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum FipsError {
+    /// The FIPS provider could not be loaded or does not offer an approved algorithm.
+    Unavailable(String),
+    /// The hash function is not approved.
+    NotApproved(String),
+}
+
+impl Display for FipsError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            FipsError::Unavailable(details) => write!(f, "FIPS provider unavailable: {}", details),
+            FipsError::NotApproved(hash) => write!(f, "{} is not approved in FIPS mode", hash),
+        }
+    }
+}
+
+impl Error for FipsError {}
+
+struct Loaded {
+    name: String,
+    /// The approved digests in the order of [`APPROVED`].
+    digests: [Md; 3],
+}
+
+static LOADED: OnceLock<Loaded> = OnceLock::new();
+
+/// The loaded FIPS provider.
+#[derive(Clone, Copy)]
+pub struct FipsProvider(&'static Loaded);
+
+impl FipsProvider {
+    /// Load OpenSSL's FIPS provider for the rest of the process and fetch the approved digests
+    /// from it. Fails if the provider is not installed or did not pass its self-tests.
+    pub fn load() -> Result<FipsProvider, FipsError> {
+        if let Some(loaded) = LOADED.get() {
+            return Ok(FipsProvider(loaded));
+        }
+        let unavailable = |error: openssl::error::ErrorStack| FipsError::Unavailable(error.to_string());
+        // never unloaded, since digests fetched from it may be in use at any time
+        core::mem::forget(Provider::load(None, "fips").map_err(unavailable)?);
+        let fetch = |name| Md::fetch(None, name, Some("fips=yes")).map_err(unavailable);
+        let digests = [fetch("SHA2-256")?, fetch("SHA2-384")?, fetch("SHA2-512")?];
+        let name = format!("openssl-fips ({})", openssl::version::version());
+        Ok(FipsProvider(LOADED.get_or_init(|| Loaded { name, digests })))
+    }
+
+    /// The provider as recorded in commitments: `openssl-fips` and the OpenSSL version.
+    pub fn name(&self) -> &str {
+        &self.0.name
+    }
+
+    /// Set up a chain hashed by the provider.
+    pub fn new_chain<H: FipsDigest>(&self, length: usize, seed: u64) -> Result<HashChain<H>, ChainInitError> {
+        HashChain::new(length, seed)
+    }
+
+    /// The commitment to `chain`, naming its hash function and recording the provider.
+    pub fn commitment<H: FipsDigest>(&self, chain: &HashChain<H>, valid_from: u64, valid_until: u64) -> AnchorCommitment {
+        AnchorCommitment::for_chain(chain, H::NAME, valid_from, valid_until).with_provider(self.name())
+    }
+
+    /// Check that a commitment from elsewhere names an approved hash function.
+    pub fn accept(&self, commitment: &AnchorCommitment) -> Result<(), FipsError> {
+        check(&commitment.hash)
+    }
+}
+
+/// Check that `hash` is approved.
+pub fn check(hash: &str) -> Result<(), FipsError> {
+    if !APPROVED.contains(&hash) {
+        return Err(FipsError::NotApproved(hash.to_string()));
+    }
+    Ok(())
+}
+
+/// A chain step computed by the FIPS provider. Only the digests here implement it.
+pub trait FipsDigest: digest::Digest + FixedOutputReset + sealed::Sealed {
+    /// The hash function's name in [`APPROVED`].
+    const NAME: &'static str;
+}
+
+mod sealed {
+    pub trait Sealed {}
+}
+
+macro_rules! fips_digest {
+    ($name:ident, $slot:expr, $doc:literal, $output:ty, $block:ty) => {
+        #[doc = concat!($doc, " computed by the FIPS provider. Panics if created before")]
+        /// [`FipsProvider::load`] succeeded, or if OpenSSL fails.
+        pub struct $name(MdCtx);
+
+        impl $name {
+            fn init(context: &mut MdCtx) {
+                let loaded = LOADED.get().expect("FipsProvider::load must succeed first");
+                context.digest_init(&loaded.digests[$slot]).expect("FIPS digest failed");
+            }
+        }
+
+        impl Default for $name {
+            fn default() -> Self {
+                let mut context = MdCtx::new().expect("FIPS digest failed");
+                Self::init(&mut context);
+                $name(context)
+            }
+        }
+
+        impl Update for $name {
+            fn update(&mut self, data: &[u8]) {
+                self.0.digest_update(data).expect("FIPS digest failed");
+            }
+        }
+
+        impl OutputSizeUser for $name {
+            type OutputSize = $output;
+        }
+
+        impl BlockSizeUser for $name {
+            type BlockSize = $block;
+        }
+
+        impl FixedOutput for $name {
+            fn finalize_into(mut self, out: &mut Output<Self>) {
+                self.0.digest_final(out).expect("FIPS digest failed");
+            }
+        }
+
+        impl Reset for $name {
+            fn reset(&mut self) {
+                Self::init(&mut self.0);
+            }
+        }
+
+        impl FixedOutputReset for $name {
+            fn finalize_into_reset(&mut self, out: &mut Output<Self>) {
+                self.0.digest_final(out).expect("FIPS digest failed");
+                Self::init(&mut self.0);
+            }
+        }
+
+        impl HashMarker for $name {}
+
+        impl sealed::Sealed for $name {}
+
+        impl FipsDigest for $name {
+            const NAME: &'static str = APPROVED[$slot];
+        }
+    };
+}
+
+fips_digest!(FipsSha256, 0, "SHA-256", U32, U64);
+fips_digest!(FipsSha384, 1, "SHA-384", U48, U128);
+fips_digest!(FipsSha512, 2, "SHA-512", U64, U128);
+
+#[test]
+fn test_fips_mode() {
+    use sha2::Sha256;
+
+    assert_eq!(check("md5"), Err(FipsError::NotApproved("md5".to_string())));
+    assert!(APPROVED.iter().all(|hash| check(hash).is_ok()));
+    // without an installed FIPS provider loading fails cleanly, and nothing else can be tried
+    let Ok(provider) = FipsProvider::load() else {
+        assert!(matches!(FipsProvider::load(), Err(FipsError::Unavailable(_))));
+        return;
+    };
+    let chain = provider.new_chain::<FipsSha256>(64, 3).unwrap();
+    assert_eq!(chain.anchor().as_slice(), HashChain::<Sha256>::new(64, 3).unwrap().anchor().as_slice());
+    let commitment = provider.commitment(&chain, 0, 10);
+    assert_eq!((commitment.hash.as_str(), commitment.provider.as_deref()), ("sha256", Some(provider.name())));
+    assert!(provider.accept(&commitment).is_ok());
+    assert!(provider.accept(&AnchorCommitment { hash: "sha1".to_string(), ..commitment }).is_err());
+}
@@ -0,0 +1,193 @@
+//! A heapless [`HashChain`](crate::HashChain) for devices without an allocator. The chain has
+//! `2^LOG_N` values and its `LOG_N` pebbles live in a fixed-size array, so the whole traversal
+//! state has a size known at compile time: for a 2^20 chain and a 32 byte hash it is under two
+//! kilobytes, and it can sit in a `static`.
+//!
+//! Positions are stored as `u64` by default. With `u32` positions, e.g.
+//! `HashChainFixed<H, LOG_N, u32>`, the counters take half the space, which matters on 16 and
+//! 32-bit MCUs, and chains longer than `2^29` values are rejected when compiling.
+
+use crate::log_2;
+use crate::value::ChainValue;
+use digest::generic_array::GenericArray;
+use digest::{Digest, FixedOutputReset, OutputSizeUser};
+
+mod private {
+    pub trait Sealed {}
+    impl Sealed for u32 {}
+    impl Sealed for u64 {}
+}
+
+/// The integer type a [`HashChainFixed`] stores positions in: `u32` or `u64`.
+pub trait Position: Copy + Ord + private::Sealed {
+    /// The largest `LOG_N` whose positions fit. Pebbles move up to four times the chain length.
+    const MAX_LOG_N: usize;
+
+    fn from_u64(value: u64) -> Self;
+
+    fn to_u64(self) -> u64;
+}
+
+impl Position for u32 {
+    const MAX_LOG_N: usize = 29;
+
+    fn from_u64(value: u64) -> Self {
+        value as u32
+    }
+
+    fn to_u64(self) -> u64 {
+        self as u64
+    }
+}
+
+impl Position for u64 {
+    const MAX_LOG_N: usize = 61;
+
+    fn from_u64(value: u64) -> Self {
+        value
+    }
+
+    fn to_u64(self) -> u64 {
+        self
+    }
+}
+
+/// A [`Pebble`](crate::Pebble) with its counters stored as `P`.
+struct Slot<H: OutputSizeUser, P> {
+    start_incr: P,
+    dest_incr: P,
+    position: P,
+    destination: P,
+    value: GenericArray<u8, H::OutputSize>,
+}
+
+pub struct HashChainFixed<H: Digest + FixedOutputReset, const LOG_N: usize, P: Position = u64> {
+    current: P,
+    anchor: GenericArray<u8, H::OutputSize>,
+    pebbles: [Slot<H, P>; LOG_N],
+    /// The number of pebbles still in use, at the front of `pebbles`.
+    live: usize,
+    hasher: H,
+}
+
+impl<H: Digest + FixedOutputReset, const LOG_N: usize, P: Position> HashChainFixed<H, LOG_N, P> {
+    const LENGTH: u64 = {
+        assert!(LOG_N >= 1 && LOG_N <= P::MAX_LOG_N, "LOG_N out of range for the position type");
+        1 << LOG_N
+    };
+
+    /// Set up the chain from `seed`, with the same values as `HashChain::new(2^LOG_N, seed)`.
+    pub fn new(seed: u64) -> Self {
+        let zero = P::from_u64(0);
+        let mut pebbles = core::array::from_fn(|_| Slot { start_incr: zero, dest_incr: zero, position: zero, destination: zero, value: GenericArray::default() });
+        let mut hasher = H::new_with_prefix(seed.to_le_bytes());
+        let mut output = hasher.finalize_reset();
+        for i in (1..=Self::LENGTH).rev() {
+            if i >= 2 && i.is_power_of_two() {
+                let (start_incr, dest_incr, position) = (P::from_u64(3 * i), P::from_u64(2 * i), P::from_u64(i));
+                pebbles[log_2(i) as usize - 1] = Slot { start_incr, dest_incr, position, destination: position, value: output.clone() };
+            }
+            digest::Digest::update(&mut hasher, output.as_ref());
+            output = hasher.finalize_reset();
+        }
+        HashChainFixed { current: zero, anchor: output, pebbles, live: LOG_N, hasher }
+    }
+
+    /// The public commitment at position 0.
+    pub fn anchor(&self) -> &GenericArray<u8, H::OutputSize> {
+        &self.anchor
+    }
+
+    /// The number of disclosable values, `2^LOG_N`.
+    pub fn length(&self) -> u64 {
+        Self::LENGTH
+    }
+
+    /// The position of the last disclosed value, 0 before the first disclosure.
+    pub fn position(&self) -> u64 {
+        self.current.to_u64()
+    }
+
+    /// The number of values left to disclose.
+    pub fn remaining(&self) -> u64 {
+        Self::LENGTH - self.current.to_u64()
+    }
+
+    fn hash(&mut self, value: &GenericArray<u8, H::OutputSize>) -> GenericArray<u8, H::OutputSize> {
+        digest::Digest::update(&mut self.hasher, value.as_slice());
+        self.hasher.finalize_reset()
+    }
+
+    /// Disclose the next value, exactly like [`HashChain::disclose`](crate::HashChain::disclose).
+    pub fn disclose(&mut self) -> Option<(u64, ChainValue<H>)> {
+        let current = self.current.to_u64();
+        if current == Self::LENGTH {
+            return None;
+        }
+        let current = current + 1;
+        self.current = P::from_u64(current);
+
+        let output = if current % 2 == 1 {
+            let value = self.pebbles[0].value.clone();
+            self.hash(&value)
+        } else {
+            let output = self.pebbles[0].value.clone();
+            let pebble = &mut self.pebbles[0];
+            pebble.position = P::from_u64(pebble.position.to_u64() + pebble.start_incr.to_u64());
+            pebble.destination = P::from_u64(pebble.destination.to_u64() + pebble.dest_incr.to_u64());
+            if pebble.destination.to_u64() > Self::LENGTH {
+                // retire the pebble by moving it behind the live ones
+                self.pebbles[..self.live].rotate_left(1);
+                self.live -= 1;
+            } else {
+                let position = pebble.position;
+                let value = self.pebbles[1..self.live].iter().find(|p| p.position == position && p.destination == p.position)
+                    .expect("relocated pebble must land on a resting pebble").value.clone();
+                self.pebbles[0].value = value;
+                self.pebbles[..self.live].sort_unstable_by_key(|p| p.destination);
+            }
+            output
+        };
+
+        for j in 0..self.live {
+            if self.pebbles[j].position != self.pebbles[j].destination {
+                let value = self.pebbles[j].value.clone();
+                let once = self.hash(&value);
+                self.pebbles[j].value = self.hash(&once);
+                self.pebbles[j].position = P::from_u64(self.pebbles[j].position.to_u64() - 2);
+            }
+        }
+
+        Some((current, ChainValue::new(output)))
+    }
+}
+
+impl<H: Digest + FixedOutputReset, const LOG_N: usize, P: Position> Iterator for HashChainFixed<H, LOG_N, P> {
+    type Item = (u64, ChainValue<H>);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.disclose()
+    }
+}
+
+#[test]
+fn test_fixed_chain_matches_hash_chain() {
+    use crate::HashChain;
+    use sha2::Sha256;
+
+    let fixed = HashChainFixed::<Sha256, 10>::new(3);
+    let chain = HashChain::<Sha256>::new(1024, 3).unwrap();
+    assert_eq!(fixed.anchor(), chain.anchor());
+    assert!(fixed.eq(chain));
+    assert!(core::mem::size_of::<HashChainFixed<Sha256, 20>>() < 2048);
+}
+
+#[test]
+fn test_fixed_chain_with_u32_positions() {
+    use crate::HashChain;
+    use sha2::Sha256;
+
+    let fixed = HashChainFixed::<Sha256, 8, u32>::new(9);
+    assert!(fixed.eq(HashChain::<Sha256>::new(256, 9).unwrap()));
+    assert!(core::mem::size_of::<HashChainFixed<Sha256, 20, u32>>() < core::mem::size_of::<HashChainFixed<Sha256, 20>>());
+}
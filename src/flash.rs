@@ -0,0 +1,296 @@
+//! Checkpoints of chain state in NOR flash, over the `embedded-storage` traits, so firmware
+//! keeps its chain position across power loss.
+//!
+//! Two slots of whole erase sectors take turns: every save erases and writes the slot not
+//! holding the latest checkpoint, so an interrupted save leaves the previous checkpoint intact
+//! and each slot is erased only on every other save. A record is a header of magic, sequence
+//! number, length and a truncated SHA-256 checksum, followed by the state; loading picks the
+//! valid record with the newer sequence number.
+
+use crate::{ChainInitError, HashChain};
+use alloc::vec;
+use alloc::vec::Vec;
+use core::error::Error;
+use core::fmt::{self, Debug, Display};
+use digest::{Digest, FixedOutputReset};
+#[cfg(feature = "embedded-storage")]
+use embedded_storage::nor_flash::NorFlash;
+#[cfg(feature = "embedded-storage-async")]
+use embedded_storage_async::nor_flash::NorFlash as AsyncNorFlash;
+use sha2::Sha256;
+
+const MAGIC: [u8; 4] = *b"FHT1";
+const HEADER: usize = 16;
+
+#[derive(Debug)]
+pub enum FlashError<E> {
+    Flash(E),
+    /// The slots are not made of whole erase sectors, or lie outside the flash.
+    Layout,
+    /// The state does not fit in a slot.
+    TooLarge,
+    /// The checkpoint holds no valid chain state.
+    BadState(ChainInitError),
+}
+
+impl<E: Debug> Display for FlashError<E> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            FlashError::Flash(e) => write!(f, "flash error: {:?}", e),
+            FlashError::Layout => write!(f, "checkpoint slots not aligned to erase sectors"),
+            FlashError::TooLarge => write!(f, "state does not fit in a checkpoint slot"),
+            FlashError::BadState(e) => write!(f, "invalid chain state: {}", e),
+        }
+    }
+}
+
+impl<E: Debug> Error for FlashError<E> {}
+
+fn checksum(sequence: u32, state: &[u8]) -> [u8; 4] {
+    let digest = Sha256::new().chain_update(MAGIC).chain_update(sequence.to_be_bytes()).chain_update((state.len() as u32).to_be_bytes()).chain_update(state).finalize();
+    [digest[0], digest[1], digest[2], digest[3]]
+}
+
+fn round_up(n: usize, to: usize) -> usize {
+    n.div_ceil(to) * to
+}
+
+/// The record for `state`, padded with erased bytes to a multiple of `write_size`.
+fn encode(sequence: u32, state: &[u8], write_size: usize) -> Vec<u8> {
+    let mut record = Vec::with_capacity(round_up(HEADER + state.len(), write_size));
+    record.extend_from_slice(&MAGIC);
+    record.extend_from_slice(&sequence.to_be_bytes());
+    record.extend_from_slice(&(state.len() as u32).to_be_bytes());
+    record.extend_from_slice(&checksum(sequence, state));
+    record.extend_from_slice(state);
+    record.resize(record.capacity(), 0xff);
+    record
+}
+
+/// The state length a header announces, if it is a header at all.
+fn announced(header: &[u8]) -> Option<usize> {
+    (header[..4] == MAGIC).then(|| u32::from_be_bytes(header[8..12].try_into().expect("4 bytes")) as usize)
+}
+
+fn decode(record: &[u8], len: usize) -> Option<(u32, &[u8])> {
+    let sequence = u32::from_be_bytes(record[4..8].try_into().expect("4 bytes"));
+    let state = &record[HEADER..HEADER + len];
+    (record[12..16] == checksum(sequence, state)).then_some((sequence, state))
+}
+
+/// Whether sequence number `a` comes after `b`, allowing for wrap-around.
+fn newer(a: u32, b: u32) -> bool {
+    (a.wrapping_sub(b) as i32) > 0
+}
+
+/// Two checkpoint slots of `slot_size` bytes each, starting at `offset` in the flash.
+pub struct FlashCheckpoint<F> {
+    flash: F,
+    offset: u32,
+    slot_size: u32,
+    /// The slot and sequence number of the latest checkpoint, once known.
+    latest: Option<Option<(u32, u32)>>,
+}
+
+impl<F> FlashCheckpoint<F> {
+    fn slot_offset(&self, slot: u32) -> u32 {
+        self.offset + slot * self.slot_size
+    }
+
+    fn check_layout(offset: u32, slot_size: u32, erase_size: usize, capacity: usize) -> bool {
+        let fits = (offset as usize).checked_add(2 * slot_size as usize).is_some_and(|end| end <= capacity);
+        fits && slot_size as usize > HEADER && (offset as usize).is_multiple_of(erase_size) && (slot_size as usize).is_multiple_of(erase_size)
+    }
+
+    /// The slot and sequence number for the next save.
+    fn next_slot(&self) -> (u32, u32) {
+        match self.latest.flatten() {
+            Some((slot, sequence)) => (1 - slot, sequence.wrapping_add(1)),
+            None => (0, 0),
+        }
+    }
+
+    /// Give back the flash.
+    pub fn release(self) -> F {
+        self.flash
+    }
+}
+
+#[cfg(feature = "embedded-storage")]
+impl<F: NorFlash> FlashCheckpoint<F> {
+    pub fn new(flash: F, offset: u32, slot_size: u32) -> Result<Self, FlashError<F::Error>> {
+        if !Self::check_layout(offset, slot_size, F::ERASE_SIZE, flash.capacity()) {
+            return Err(FlashError::Layout);
+        }
+        Ok(FlashCheckpoint { flash, offset, slot_size, latest: None })
+    }
+
+    fn read_slot(&mut self, slot: u32) -> Result<Option<(u32, Vec<u8>)>, F::Error> {
+        let at = self.slot_offset(slot);
+        let mut header = vec![0; round_up(HEADER, F::READ_SIZE)];
+        self.flash.read(at, &mut header)?;
+        let Some(len) = announced(&header).filter(|len| HEADER + len <= self.slot_size as usize) else {
+            return Ok(None);
+        };
+        let mut record = vec![0; round_up(HEADER + len, F::READ_SIZE).min(self.slot_size as usize)];
+        self.flash.read(at, &mut record)?;
+        Ok(decode(&record, len).map(|(sequence, state)| (sequence, state.to_vec())))
+    }
+
+    /// The latest saved state, if any slot holds a valid one.
+    pub fn load(&mut self) -> Result<Option<Vec<u8>>, FlashError<F::Error>> {
+        let mut best: Option<(u32, u32, Vec<u8>)> = None;
+        for slot in 0..2 {
+            if let Some((sequence, state)) = self.read_slot(slot).map_err(FlashError::Flash)? {
+                if best.as_ref().is_none_or(|(_, latest, _)| newer(sequence, *latest)) {
+                    best = Some((slot, sequence, state));
+                }
+            }
+        }
+        self.latest = Some(best.as_ref().map(|(slot, sequence, _)| (*slot, *sequence)));
+        Ok(best.map(|(_, _, state)| state))
+    }
+
+    /// Save `state` to the slot not holding the latest checkpoint.
+    pub fn save(&mut self, state: &[u8]) -> Result<(), FlashError<F::Error>> {
+        if self.latest.is_none() {
+            self.load()?;
+        }
+        let (slot, sequence) = self.next_slot();
+        let record = encode(sequence, state, F::WRITE_SIZE);
+        if record.len() > self.slot_size as usize {
+            return Err(FlashError::TooLarge);
+        }
+        let at = self.slot_offset(slot);
+        self.flash.erase(at, at + self.slot_size).map_err(FlashError::Flash)?;
+        self.flash.write(at, &record).map_err(FlashError::Flash)?;
+        self.latest = Some(Some((slot, sequence)));
+        Ok(())
+    }
+
+    pub fn save_chain<H: Digest + FixedOutputReset>(&mut self, chain: &HashChain<H>) -> Result<(), FlashError<F::Error>> {
+        self.save(&chain.export_state())
+    }
+
+    pub fn load_chain<H: Digest + FixedOutputReset>(&mut self) -> Result<Option<HashChain<H>>, FlashError<F::Error>> {
+        self.load()?.map(|state| HashChain::import_state(&state).map_err(FlashError::BadState)).transpose()
+    }
+}
+
+#[cfg(feature = "embedded-storage-async")]
+impl<F: AsyncNorFlash> FlashCheckpoint<F> {
+    pub fn new_async(flash: F, offset: u32, slot_size: u32) -> Result<Self, FlashError<F::Error>> {
+        if !Self::check_layout(offset, slot_size, F::ERASE_SIZE, flash.capacity()) {
+            return Err(FlashError::Layout);
+        }
+        Ok(FlashCheckpoint { flash, offset, slot_size, latest: None })
+    }
+
+    async fn read_slot_async(&mut self, slot: u32) -> Result<Option<(u32, Vec<u8>)>, F::Error> {
+        let at = self.slot_offset(slot);
+        let mut header = vec![0; round_up(HEADER, F::READ_SIZE)];
+        self.flash.read(at, &mut header).await?;
+        let Some(len) = announced(&header).filter(|len| HEADER + len <= self.slot_size as usize) else {
+            return Ok(None);
+        };
+        let mut record = vec![0; round_up(HEADER + len, F::READ_SIZE).min(self.slot_size as usize)];
+        self.flash.read(at, &mut record).await?;
+        Ok(decode(&record, len).map(|(sequence, state)| (sequence, state.to_vec())))
+    }
+
+    /// Like the blocking `load`.
+    pub async fn load_async(&mut self) -> Result<Option<Vec<u8>>, FlashError<F::Error>> {
+        let mut best: Option<(u32, u32, Vec<u8>)> = None;
+        for slot in 0..2 {
+            if let Some((sequence, state)) = self.read_slot_async(slot).await.map_err(FlashError::Flash)? {
+                if best.as_ref().is_none_or(|(_, latest, _)| newer(sequence, *latest)) {
+                    best = Some((slot, sequence, state));
+                }
+            }
+        }
+        self.latest = Some(best.as_ref().map(|(slot, sequence, _)| (*slot, *sequence)));
+        Ok(best.map(|(_, _, state)| state))
+    }
+
+    /// Like the blocking `save`.
+    pub async fn save_async(&mut self, state: &[u8]) -> Result<(), FlashError<F::Error>> {
+        if self.latest.is_none() {
+            self.load_async().await?;
+        }
+        let (slot, sequence) = self.next_slot();
+        let record = encode(sequence, state, F::WRITE_SIZE);
+        if record.len() > self.slot_size as usize {
+            return Err(FlashError::TooLarge);
+        }
+        let at = self.slot_offset(slot);
+        self.flash.erase(at, at + self.slot_size).await.map_err(FlashError::Flash)?;
+        self.flash.write(at, &record).await.map_err(FlashError::Flash)?;
+        self.latest = Some(Some((slot, sequence)));
+        Ok(())
+    }
+
+    pub async fn save_chain_async<H: Digest + FixedOutputReset>(&mut self, chain: &HashChain<H>) -> Result<(), FlashError<F::Error>> {
+        self.save_async(&chain.export_state()).await
+    }
+
+    pub async fn load_chain_async<H: Digest + FixedOutputReset>(&mut self) -> Result<Option<HashChain<H>>, FlashError<F::Error>> {
+        self.load_async().await?.map(|state| HashChain::import_state(&state).map_err(FlashError::BadState)).transpose()
+    }
+}
+
+#[cfg(feature = "embedded-storage")]
+#[test]
+fn test_flash_checkpoint_survives_torn_write() {
+    use embedded_storage::nor_flash::{ErrorType, NorFlashErrorKind, ReadNorFlash};
+    use sha2::Sha256;
+
+    struct Ram(Vec<u8>);
+
+    impl ErrorType for Ram {
+        type Error = NorFlashErrorKind;
+    }
+
+    impl ReadNorFlash for Ram {
+        const READ_SIZE: usize = 1;
+
+        fn read(&mut self, offset: u32, bytes: &mut [u8]) -> Result<(), Self::Error> {
+            bytes.copy_from_slice(&self.0[offset as usize..offset as usize + bytes.len()]);
+            Ok(())
+        }
+
+        fn capacity(&self) -> usize {
+            self.0.len()
+        }
+    }
+
+    impl NorFlash for Ram {
+        const WRITE_SIZE: usize = 4;
+        const ERASE_SIZE: usize = 256;
+
+        fn erase(&mut self, from: u32, to: u32) -> Result<(), Self::Error> {
+            self.0[from as usize..to as usize].fill(0xff);
+            Ok(())
+        }
+
+        fn write(&mut self, offset: u32, bytes: &[u8]) -> Result<(), Self::Error> {
+            self.0[offset as usize..offset as usize + bytes.len()].copy_from_slice(bytes);
+            Ok(())
+        }
+    }
+
+    let mut chain = HashChain::<Sha256>::new(64, 3).unwrap();
+    let mut checkpoint = FlashCheckpoint::new(Ram(vec![0xff; 2048]), 512, 512).unwrap();
+    assert!(checkpoint.load().unwrap().is_none());
+    chain.nth(4);
+    checkpoint.save_chain(&chain).unwrap();
+    chain.nth(4);
+    checkpoint.save_chain(&chain).unwrap();
+
+    // a torn third save into slot 0 leaves the second checkpoint in slot 1
+    let mut flash = checkpoint.release();
+    flash.0[512 + HEADER] ^= 0xff;
+    let mut checkpoint = FlashCheckpoint::new(flash, 512, 512).unwrap();
+    let restored = checkpoint.load_chain::<Sha256>().unwrap().unwrap();
+    assert_eq!(HashChain::position(&restored), 10);
+    assert!(matches!(FlashCheckpoint::new(Ram(vec![0xff; 2048]), 100, 512), Err(FlashError::Layout)));
+}
@@ -0,0 +1,103 @@
+//! [`Arbitrary`] implementations for property testing and fuzzing integrations with
+//! structurally valid inputs, behind the `arbitrary` feature.
+//!
+//! Arbitrary chains are short, at most 2^10 values, so a fuzzer spends its time in the code
+//! under test rather than in chain setup. States and messages are built from real chains, so
+//! they parse and verify; fuzzers that want malformed input can mutate the bytes from there.
+
+use crate::anchor::AnchorCommitment;
+use crate::registry::ChainToken;
+use crate::{ChainId, HashChain};
+use arbitrary::{Arbitrary, Result, Unstructured};
+use digest::{Digest, FixedOutputReset};
+
+/// The parameters of a chain: a valid length and a seed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ChainParams {
+    pub length: usize,
+    pub seed: u64,
+}
+
+impl ChainParams {
+    pub fn build<H: Digest + FixedOutputReset>(&self) -> HashChain<H> {
+        HashChain::new(self.length, self.seed).expect("arbitrary lengths are valid")
+    }
+}
+
+impl<'a> Arbitrary<'a> for ChainParams {
+    fn arbitrary(u: &mut Unstructured<'a>) -> Result<Self> {
+        Ok(ChainParams { length: 1 << u.int_in_range(1..=10)?, seed: u.arbitrary()? })
+    }
+}
+
+/// A chain part of the way through its traversal.
+impl<'a, H: Digest + FixedOutputReset> Arbitrary<'a> for HashChain<H> {
+    fn arbitrary(u: &mut Unstructured<'a>) -> Result<Self> {
+        let mut chain = ChainParams::arbitrary(u)?.build::<H>();
+        let steps = u.int_in_range(0..=chain.length())?;
+        for _ in 0..steps {
+            chain.disclose();
+        }
+        Ok(chain)
+    }
+}
+
+/// A state in [`HashChain::export_state`] encoding that imports.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ChainState(pub Vec<u8>);
+
+impl ChainState {
+    pub fn arbitrary_for<H: Digest + FixedOutputReset>(u: &mut Unstructured<'_>) -> Result<Self> {
+        Ok(ChainState(HashChain::<H>::arbitrary(u)?.export_state()))
+    }
+}
+
+impl<'a> Arbitrary<'a> for ChainId {
+    fn arbitrary(u: &mut Unstructured<'a>) -> Result<Self> {
+        Ok(ChainId(u.arbitrary()?))
+    }
+}
+
+/// A disclosure of a SHA-256 chain that verifies against its [`HashChain::chain_id`] and anchor.
+/// Use [`disclosure_for`] for other hash functions.
+impl<'a> Arbitrary<'a> for ChainToken {
+    fn arbitrary(u: &mut Unstructured<'a>) -> Result<Self> {
+        Ok(disclosure_for::<sha2::Sha256>(u)?.1)
+    }
+}
+
+/// An arbitrary chain's anchor and one of its disclosures.
+pub fn disclosure_for<H: Digest + FixedOutputReset>(u: &mut Unstructured<'_>) -> Result<(digest::Output<H>, ChainToken)> {
+    let mut chain = ChainParams::arbitrary(u)?.build::<H>();
+    let index = u.int_in_range(1..=chain.length())?;
+    let (_, value) = chain.nth(index as usize - 1).expect("index within the chain");
+    Ok((chain.anchor().clone(), ChainToken { chain_id: chain.chain_id(), index, value: value.to_vec() }))
+}
+
+/// The commitment to an arbitrary SHA-256 chain with a non-empty validity window.
+impl<'a> Arbitrary<'a> for AnchorCommitment {
+    fn arbitrary(u: &mut Unstructured<'a>) -> Result<Self> {
+        let chain = ChainParams::arbitrary(u)?.build::<sha2::Sha256>();
+        let valid_from = u.int_in_range(0..=u64::MAX / 2)?;
+        let valid_until = u.int_in_range(valid_from..=u64::MAX)?;
+        Ok(AnchorCommitment::for_chain(&chain, "sha256", valid_from, valid_until))
+    }
+}
+
+#[test]
+fn test_arbitrary_inputs_are_valid() {
+    use crate::verify;
+    use digest::generic_array::GenericArray;
+    use sha2::Sha256;
+
+    let entropy: Vec<u8> = (0..4096u32).map(|i| (i.wrapping_mul(2654435761) >> 13) as u8).collect();
+    let mut u = Unstructured::new(&entropy);
+    for _ in 0..16 {
+        let state = ChainState::arbitrary_for::<Sha256>(&mut u).unwrap();
+        assert!(HashChain::<Sha256>::import_state(&state.0).is_ok());
+        let (anchor, token) = disclosure_for::<Sha256>(&mut u).unwrap();
+        assert!(verify::<Sha256>(0, &anchor, token.index, GenericArray::from_slice(&token.value)));
+        let commitment = AnchorCommitment::arbitrary(&mut u).unwrap();
+        assert_eq!(AnchorCommitment::from_bytes(&commitment.to_bytes()).unwrap(), commitment);
+    }
+}
@@ -0,0 +1,164 @@
+//! The Guy Fawkes protocol (Anderson, Bergadano, Crispo, Lee and Manifavas).
+//!
+//! Every packet reveals a message together with the key that opens the commitment made to it in
+//! the previous packet, and commits to the next message under the next key. The keys are
+//! successive chain values, so a revealed key is also checked against the chain. Once the first
+//! commitment has been delivered authentically (say, signed once), every later packet is
+//! authenticated with nothing but hashing, and without any timing assumptions. The price is
+//! that the sender has to know each message one packet ahead.
+
+use crate::{verify, HashChain};
+use crate::value::ChainValue;
+use digest::generic_array::GenericArray;
+use digest::{Digest, FixedOutputReset, OutputSizeUser};
+use std::error::Error;
+use std::fmt::{self, Display};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum GuyFawkesError {
+    /// The chain has no key left for another message.
+    Exhausted,
+    /// The stream has been closed by its last packet.
+    Finished,
+    /// The packet is not the next one in the stream.
+    OutOfOrder { expected: u64 },
+    /// The revealed key does not belong to the chain.
+    BadKey,
+    /// The message or key does not open the previous commitment.
+    BadCommitment,
+}
+
+impl Display for GuyFawkesError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            GuyFawkesError::Exhausted => write!(f, "no key left for another message"),
+            GuyFawkesError::Finished => write!(f, "stream already finished"),
+            GuyFawkesError::OutOfOrder { expected } => write!(f, "expected packet {}", expected),
+            GuyFawkesError::BadKey => write!(f, "revealed key does not verify against the chain"),
+            GuyFawkesError::BadCommitment => write!(f, "packet does not open the previous commitment"),
+        }
+    }
+}
+
+impl Error for GuyFawkesError {}
+
+fn commit<H: Digest>(index: u64, message: &[u8], key: &[u8]) -> GenericArray<u8, H::OutputSize> {
+    let mut hasher = H::new();
+    hasher.update(b"guy fawkes commitment");
+    hasher.update(index.to_be_bytes());
+    hasher.update((message.len() as u64).to_be_bytes());
+    hasher.update(message);
+    hasher.update(key);
+    hasher.finalize()
+}
+
+/// What a receiver must obtain authentically before the first packet.
+pub struct Bootstrap<H: OutputSizeUser> {
+    pub anchor: GenericArray<u8, H::OutputSize>,
+    /// The commitment to the first message.
+    pub commitment: GenericArray<u8, H::OutputSize>,
+}
+
+pub struct Packet<H: OutputSizeUser> {
+    pub index: u64,
+    pub message: Vec<u8>,
+    /// The chain value opening the commitment to this packet.
+    pub key: GenericArray<u8, H::OutputSize>,
+    /// The commitment to the next packet, or `None` if this is the last one.
+    pub next: Option<GenericArray<u8, H::OutputSize>>,
+}
+
+pub struct Sender<H: Digest + FixedOutputReset> {
+    chain: HashChain<H>,
+    /// The next message to send, with its index and key.
+    pending: Option<(u64, ChainValue<H>, Vec<u8>)>,
+}
+
+impl<H: Digest + FixedOutputReset> Sender<H> {
+    /// Send messages keyed by `chain`, starting with `first`.
+    pub fn new(mut chain: HashChain<H>, first: &[u8]) -> Result<Self, GuyFawkesError> {
+        let (index, key) = chain.disclose().ok_or(GuyFawkesError::Exhausted)?;
+        Ok(Sender { chain, pending: Some((index, key, first.to_vec())) })
+    }
+
+    /// The anchor and first commitment, to be delivered authentically. `None` once finished.
+    pub fn bootstrap(&self) -> Option<Bootstrap<H>> {
+        let (index, key, message) = self.pending.as_ref()?;
+        Some(Bootstrap { anchor: self.chain.anchor().clone(), commitment: commit::<H>(*index, message, key) })
+    }
+
+    /// Send the pending message, committing to `next` as the one after it.
+    pub fn send(&mut self, next: &[u8]) -> Result<Packet<H>, GuyFawkesError> {
+        if self.pending.is_none() {
+            return Err(GuyFawkesError::Finished);
+        }
+        let (next_index, next_key) = self.chain.disclose().ok_or(GuyFawkesError::Exhausted)?;
+        let commitment = commit::<H>(next_index, next, &next_key);
+        let (index, key, message) = self.pending.replace((next_index, next_key, next.to_vec())).expect("checked above");
+        Ok(Packet { index, message, key: key.to_array(), next: Some(commitment) })
+    }
+
+    /// Send the pending message as the last one.
+    pub fn finish(&mut self) -> Result<Packet<H>, GuyFawkesError> {
+        let (index, key, message) = self.pending.take().ok_or(GuyFawkesError::Finished)?;
+        Ok(Packet { index, message, key: key.to_array(), next: None })
+    }
+}
+
+pub struct Receiver<H: OutputSizeUser> {
+    /// The last verified key and its index.
+    key_index: u64,
+    key: GenericArray<u8, H::OutputSize>,
+    commitment: Option<GenericArray<u8, H::OutputSize>>,
+}
+
+impl<H: Digest + FixedOutputReset> Receiver<H> {
+    pub fn new(bootstrap: Bootstrap<H>) -> Self {
+        Receiver { key_index: 0, key: bootstrap.anchor, commitment: Some(bootstrap.commitment) }
+    }
+
+    /// Whether the last packet has been received.
+    pub fn is_finished(&self) -> bool {
+        self.commitment.is_none()
+    }
+
+    /// Check `packet` against the previous commitment and return its message.
+    pub fn receive(&mut self, packet: &Packet<H>) -> Result<Vec<u8>, GuyFawkesError> {
+        let commitment = self.commitment.as_ref().ok_or(GuyFawkesError::Finished)?;
+        if packet.index != self.key_index + 1 {
+            return Err(GuyFawkesError::OutOfOrder { expected: self.key_index + 1 });
+        }
+        if !verify::<H>(self.key_index, &self.key, packet.index, &packet.key) {
+            return Err(GuyFawkesError::BadKey);
+        }
+        if commit::<H>(packet.index, &packet.message, &packet.key) != *commitment {
+            return Err(GuyFawkesError::BadCommitment);
+        }
+        self.key_index = packet.index;
+        self.key = packet.key.clone();
+        self.commitment = packet.next.clone();
+        Ok(packet.message.clone())
+    }
+}
+
+#[test]
+fn test_guy_fawkes() {
+    use sha2::Sha256;
+
+    let mut sender = Sender::new(HashChain::<Sha256>::new(4, 9).unwrap(), b"one").unwrap();
+    let mut receiver = Receiver::new(sender.bootstrap().unwrap());
+    let first = sender.send(b"two").unwrap();
+    let second = sender.send(b"three").unwrap();
+    let forged = Packet::<Sha256> { message: b"twp".to_vec(), ..second };
+
+    assert_eq!(receiver.receive(&forged).err(), Some(GuyFawkesError::OutOfOrder { expected: 1 }));
+    assert_eq!(receiver.receive(&first).unwrap(), b"one");
+    assert_eq!(receiver.receive(&forged).err(), Some(GuyFawkesError::BadCommitment));
+    let second = Packet { message: b"two".to_vec(), ..forged };
+    assert_eq!(receiver.receive(&second).unwrap(), b"two");
+
+    let last = sender.finish().unwrap();
+    assert!(sender.send(b"four").is_err());
+    assert_eq!(receiver.receive(&last).unwrap(), b"three");
+    assert!(receiver.is_finished());
+}
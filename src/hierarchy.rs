@@ -0,0 +1,222 @@
+//! Two-level chains: a long-lived master chain whose values seed short sub-chains, one per
+//! epoch (say a day).
+//!
+//! Sub-chain `k` is set up from master value `k`, so the prover only ever holds the master
+//! traversal and one sub-chain. Master value `k` stays secret while sub-chain `k` is in use and
+//! is disclosed when the prover rolls over to epoch `k + 1`. The verifier then checks it against
+//! the master anchor and recomputes sub-anchor `k` from it, which binds the sub-chain it has been
+//! verifying against to the master chain. Values of the current epoch verify against the
+//! sub-anchor announced when the epoch began; they are bound to the master chain once the epoch
+//! closes, as with the delayed authentication of TESLA.
+
+use crate::{verify, ChainInitError, HashChain};
+use crate::value::ChainValue;
+use digest::{Digest, FixedOutputReset, Output};
+use std::error::Error;
+use std::fmt::{self, Display};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HierarchyError {
+    /// The disclosure is for another epoch than the verifier's current one.
+    WrongEpoch,
+    /// A new epoch began without a rollover announcing its sub-anchor.
+    MissingRollover,
+    /// The closing master value does not verify against the master chain.
+    MasterMismatch,
+    /// The closing master value does not lead to the sub-anchor used during its epoch.
+    BindingMismatch,
+    /// The index is not above the last accepted one.
+    Replay,
+    /// The value does not verify against the sub-chain.
+    Mismatch,
+}
+
+impl Display for HierarchyError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            HierarchyError::WrongEpoch => write!(f, "disclosure for another epoch"),
+            HierarchyError::MissingRollover => write!(f, "new epoch without a rollover"),
+            HierarchyError::MasterMismatch => write!(f, "master value does not verify"),
+            HierarchyError::BindingMismatch => write!(f, "sub-anchor not bound to the master value"),
+            HierarchyError::Replay => write!(f, "index already accepted"),
+            HierarchyError::Mismatch => write!(f, "value does not verify against the sub-chain"),
+        }
+    }
+}
+
+impl Error for HierarchyError {}
+
+/// The seed of the sub-chain set up from `master_value`.
+fn sub_seed<H: Digest>(master_value: &Output<H>) -> u64 {
+    let digest = H::new_with_prefix(b"two-level sub-chain seed").chain_update(master_value).finalize();
+    u64::from_le_bytes(digest[..8].try_into().expect("digest output is at least 8 bytes"))
+}
+
+fn sub_chain<H: Digest + FixedOutputReset>(master_value: &Output<H>, sub_length: usize) -> HashChain<H> {
+    HashChain::new(sub_length, sub_seed::<H>(master_value)).expect("sub-chain length validated on construction")
+}
+
+/// Sent with the first disclosure of every epoch.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Rollover<H: Digest> {
+    pub sub_anchor: Output<H>,
+    /// The master value of the epoch that just ended, absent for the first epoch.
+    pub closed: Option<Output<H>>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SubDisclosure<H: Digest> {
+    pub epoch: u64,
+    /// The index in the epoch's sub-chain.
+    pub index: u64,
+    pub value: Output<H>,
+    pub rollover: Option<Rollover<H>>,
+}
+
+pub struct TwoLevelChain<H: Digest + FixedOutputReset> {
+    master: HashChain<H>,
+    sub_length: usize,
+    /// The current epoch's number, master value and sub-chain, `None` before the first epoch.
+    current: Option<(u64, ChainValue<H>, HashChain<H>)>,
+    /// Whether the next disclosure begins a new epoch.
+    rolling: bool,
+}
+
+impl<H: Digest + FixedOutputReset> TwoLevelChain<H> {
+    /// A master chain of `master_length` epochs, each with a sub-chain of `sub_length` values.
+    pub fn new(master_length: usize, master_seed: u64, sub_length: usize) -> Result<Self, ChainInitError> {
+        HashChain::<H>::new(sub_length, 0)?;
+        Ok(TwoLevelChain { master: HashChain::new(master_length, master_seed)?, sub_length, current: None, rolling: true })
+    }
+
+    pub fn master_anchor(&self) -> &Output<H> {
+        self.master.anchor()
+    }
+
+    pub fn sub_length(&self) -> usize {
+        self.sub_length
+    }
+
+    /// The current epoch, 0 before the first disclosure.
+    pub fn epoch(&self) -> u64 {
+        self.current.as_ref().map_or(0, |(epoch, _, _)| *epoch)
+    }
+
+    /// End the current epoch early, e.g. at midnight; the next disclosure begins the next one.
+    pub fn roll(&mut self) {
+        self.rolling = true;
+    }
+
+    /// Disclose the next value, moving on to the next sub-chain when the current one is
+    /// exhausted or [`roll`](TwoLevelChain::roll) was called. `None` once the master chain is
+    /// exhausted as well.
+    pub fn disclose(&mut self) -> Option<SubDisclosure<H>> {
+        let exhausted = self.current.as_ref().is_none_or(|(_, _, sub)| sub.remaining() == 0);
+        let mut rollover = None;
+        if self.rolling || exhausted {
+            let (epoch, master_value) = self.master.disclose()?;
+            let sub = sub_chain::<H>(&master_value, self.sub_length);
+            let closed = self.current.take().map(|(_, value, _)| value.to_array());
+            rollover = Some(Rollover { sub_anchor: sub.anchor().clone(), closed });
+            self.current = Some((epoch, master_value, sub));
+            self.rolling = false;
+        }
+        let (epoch, _, sub) = self.current.as_mut().expect("an epoch is in progress");
+        let (index, value) = sub.disclose().expect("a fresh or unexhausted sub-chain");
+        Some(SubDisclosure { epoch: *epoch, index, value: value.to_array(), rollover })
+    }
+}
+
+pub struct TwoLevelVerifier<H: Digest + FixedOutputReset> {
+    sub_length: usize,
+    /// The last disclosed master value and its index, the anchor at index 0.
+    master: (u64, Output<H>),
+    /// The current epoch's number and sub-anchor, and the last accepted index and value.
+    epoch: Option<(u64, Output<H>, u64, Output<H>)>,
+}
+
+impl<H: Digest + FixedOutputReset> TwoLevelVerifier<H> {
+    pub fn new(master_anchor: Output<H>, sub_length: usize) -> Self {
+        TwoLevelVerifier { sub_length, master: (0, master_anchor), epoch: None }
+    }
+
+    /// The highest epoch whose sub-anchor has been bound to the master chain.
+    pub fn bound_epochs(&self) -> u64 {
+        self.master.0
+    }
+
+    pub fn accept(&mut self, disclosure: &SubDisclosure<H>) -> Result<(), HierarchyError> {
+        let current = self.epoch.as_ref().map(|(epoch, ..)| *epoch);
+        let mut next = self.epoch.clone();
+        let mut master = self.master.clone();
+        match &disclosure.rollover {
+            Some(rollover) => {
+                if disclosure.epoch <= current.unwrap_or(0) {
+                    return Err(HierarchyError::WrongEpoch);
+                }
+                if let Some((epoch, sub_anchor, ..)) = &self.epoch {
+                    let closed = rollover.closed.as_ref().ok_or(HierarchyError::MissingRollover)?;
+                    if !verify::<H>(master.0, &master.1, *epoch, closed) {
+                        return Err(HierarchyError::MasterMismatch);
+                    }
+                    if sub_chain::<H>(closed, self.sub_length).anchor() != sub_anchor {
+                        return Err(HierarchyError::BindingMismatch);
+                    }
+                    master = (*epoch, closed.clone());
+                }
+                // the sub-anchor of the new epoch is only bound once that epoch closes
+                next = Some((disclosure.epoch, rollover.sub_anchor.clone(), 0, rollover.sub_anchor.clone()));
+            }
+            None if current.is_none_or(|epoch| disclosure.epoch > epoch) => return Err(HierarchyError::MissingRollover),
+            None if current != Some(disclosure.epoch) => return Err(HierarchyError::WrongEpoch),
+            None => {}
+        }
+        let (epoch, sub_anchor, last_index, last_value) = next.expect("an epoch is in progress");
+        if disclosure.index <= last_index {
+            return Err(HierarchyError::Replay);
+        }
+        if !verify::<H>(last_index, &last_value, disclosure.index, &disclosure.value) {
+            return Err(HierarchyError::Mismatch);
+        }
+        self.master = master;
+        self.epoch = Some((epoch, sub_anchor, disclosure.index, disclosure.value.clone()));
+        Ok(())
+    }
+}
+
+#[test]
+fn test_two_level_chain() {
+    use sha2::Sha256;
+
+    let mut prover = TwoLevelChain::<Sha256>::new(8, 1, 4).unwrap();
+    let mut verifier = TwoLevelVerifier::<Sha256>::new(*prover.master_anchor(), 4);
+    let mut disclosed = Vec::new();
+    for step in 0..10 {
+        if step == 6 {
+            prover.roll();
+        }
+        let disclosure = prover.disclose().unwrap();
+        verifier.accept(&disclosure).unwrap();
+        let replayed = if disclosure.rollover.is_some() { HierarchyError::WrongEpoch } else { HierarchyError::Replay };
+        assert_eq!(verifier.accept(&disclosure), Err(replayed));
+        disclosed.push(disclosure);
+    }
+    // epochs 1 and 2 run out, epoch 3 is rolled early after two values
+    assert_eq!(disclosed.iter().map(|d| (d.epoch, d.index)).collect::<Vec<_>>(),
+        vec![(1, 1), (1, 2), (1, 3), (1, 4), (2, 1), (2, 2), (3, 1), (3, 2), (3, 3), (3, 4)]);
+    assert_eq!(verifier.bound_epochs(), 2);
+
+    // rollovers must close the previous epoch with its master value
+    let mut forged = prover.disclose().unwrap();
+    let mut fresh = TwoLevelVerifier::<Sha256>::new(*prover.master_anchor(), 4);
+    for disclosure in &disclosed[..6] {
+        fresh.accept(disclosure).unwrap();
+    }
+    let mut late = disclosed[6].clone();
+    late.rollover.as_mut().unwrap().closed = forged.rollover.take().unwrap().closed;
+    assert_eq!(fresh.accept(&late), Err(HierarchyError::MasterMismatch));
+    let mut skipped = disclosed[6].clone();
+    skipped.rollover = None;
+    assert_eq!(fresh.accept(&skipped), Err(HierarchyError::MissingRollover));
+    assert_eq!(verifier.accept(&forged), Err(HierarchyError::MissingRollover));
+}
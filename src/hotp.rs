@@ -0,0 +1,142 @@
+//! Chain tokens behind the counter-based interface of HOTP
+//! ([RFC 4226](https://www.rfc-editor.org/rfc/rfc4226)), so validation loops written for HOTP
+//! can switch to hash chains with few changes.
+//!
+//! Counter `c` is the chain value at index `c + 1`, and the code for it is that value in hex.
+//! The [`ChainGenerator`] walks its chain forward to whatever counter is asked for, and the
+//! [`ChainValidator`] accepts a code within a look-ahead window past its counter and moves its
+//! counter past the code, as an HOTP server resynchronizes.
+//!
+//! Unlike HOTP the validator holds no secret, only the last accepted value, so a leaked validator
+//! database lets nobody mint codes. The price is that codes are whole hash values: a chain value
+//! cut down to six digits can no longer be hashed forward to the last accepted one. The
+//! look-ahead window only bounds the hashing a code costs, since codes cannot be guessed anyway.
+
+use crate::value::{ChainValue, ValueParseError};
+use crate::HashChain;
+use digest::generic_array::GenericArray;
+use digest::{Digest, FixedOutputReset};
+use std::error::Error;
+use std::fmt::{self, Display};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HotpError {
+    /// The counter lies before the generator's, so its code was handed out or skipped already.
+    Spent { counter: u64 },
+    /// The chain has no value for the counter.
+    Exhausted,
+    /// The code is not a chain value.
+    Malformed(ValueParseError),
+    /// The code is not the value at any counter in the window.
+    Rejected,
+}
+
+impl Display for HotpError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            HotpError::Spent { counter } => write!(f, "counter {} already used", counter),
+            HotpError::Exhausted => write!(f, "chain exhausted"),
+            HotpError::Malformed(error) => write!(f, "malformed code: {}", error),
+            HotpError::Rejected => write!(f, "code rejected"),
+        }
+    }
+}
+
+impl Error for HotpError {}
+
+/// The token side, generating the code for a counter.
+pub struct ChainGenerator<H: Digest + FixedOutputReset> {
+    chain: HashChain<H>,
+}
+
+impl<H: Digest + FixedOutputReset> ChainGenerator<H> {
+    pub fn new(chain: HashChain<H>) -> Self {
+        ChainGenerator { chain }
+    }
+
+    /// The next counter a code can be generated for.
+    pub fn counter(&self) -> u64 {
+        self.chain.position()
+    }
+
+    /// The code for `counter`. Counters skipped on the way can no longer be generated.
+    pub fn generate(&mut self, counter: u64) -> Result<String, HotpError> {
+        if counter < self.counter() {
+            return Err(HotpError::Spent { counter });
+        }
+        let skip = (counter - self.counter()) as usize;
+        let (_, value) = self.chain.nth(skip).ok_or(HotpError::Exhausted)?;
+        Ok(value.to_hex())
+    }
+
+    /// The code for the next counter, with the counter.
+    pub fn generate_next(&mut self) -> Result<(u64, String), HotpError> {
+        let counter = self.counter();
+        Ok((counter, self.generate(counter)?))
+    }
+
+    pub fn chain(&self) -> &HashChain<H> {
+        &self.chain
+    }
+}
+
+/// The server side, holding the value at its counter: the anchor until a code is accepted.
+pub struct ChainValidator<H: Digest + FixedOutputReset> {
+    counter: u64,
+    known: GenericArray<u8, H::OutputSize>,
+    look_ahead: u64,
+}
+
+impl<H: Digest + FixedOutputReset> ChainValidator<H> {
+    /// A validator for the chain with `anchor`, accepting codes up to `look_ahead` counters
+    /// past the expected one.
+    pub fn new(anchor: GenericArray<u8, H::OutputSize>, look_ahead: u64) -> Self {
+        ChainValidator { counter: 0, known: anchor, look_ahead }
+    }
+
+    /// The counter the next code is expected for.
+    pub fn counter(&self) -> u64 {
+        self.counter
+    }
+
+    /// Accept `code` if it is the code for a counter from [`ChainValidator::counter`] to
+    /// `look_ahead` past it, returning that counter and moving the expected counter past it.
+    pub fn validate(&mut self, code: &str) -> Result<u64, HotpError> {
+        let value = ChainValue::<H>::from_hex(code).map_err(HotpError::Malformed)?;
+        let mut hasher = H::new();
+        let mut current = value.clone();
+        for steps in 1..=self.look_ahead + 1 {
+            digest::Digest::update(&mut hasher, current.as_bytes());
+            current = ChainValue::new(hasher.finalize_reset());
+            if current == self.known {
+                let counter = self.counter + steps - 1;
+                self.counter = counter + 1;
+                self.known = value.to_array();
+                return Ok(counter);
+            }
+        }
+        Err(HotpError::Rejected)
+    }
+}
+
+#[test]
+fn test_hotp_adapter() {
+    use sha2::Sha256;
+
+    let chain = HashChain::<Sha256>::new(16, 4).unwrap();
+    let mut validator = ChainValidator::<Sha256>::new(*chain.anchor(), 2);
+    let mut generator = ChainGenerator::new(chain);
+    let (counter, code) = generator.generate_next().unwrap();
+    assert_eq!(counter, 0);
+    assert_eq!(validator.validate(&code), Ok(0));
+    assert_eq!(validator.validate(&code), Err(HotpError::Rejected));
+
+    // codes generated but never presented are skipped, as long as the next one is in the window
+    let code = generator.generate(3).unwrap();
+    assert_eq!(generator.generate(2), Err(HotpError::Spent { counter: 2 }));
+    assert_eq!(validator.validate(&generator.generate(5).unwrap()), Err(HotpError::Rejected));
+    assert_eq!(validator.validate(&code), Ok(3));
+    assert_eq!(validator.counter(), 4);
+    assert_eq!(generator.generate(16), Err(HotpError::Exhausted));
+    assert!(matches!(validator.validate("not hex"), Err(HotpError::Malformed(_))));
+}
@@ -0,0 +1,205 @@
+//! Hypertrees: layers of [`xmss`](crate::xmss) trees in which every tree's leaves sign the roots
+//! of the trees on the layer below, and the bottom layer signs messages. With `layers` layers of
+//! height `height` a key makes `2^(layers * height)` signatures, while only one tree per layer is
+//! held in memory and key generation costs a single tree. When a bottom tree runs out, the next
+//! one is generated and signed by its parent, rolling over upper layers the same way.
+//!
+//! The signing index is guarded through a [`StateStore`] exactly as for [`XmssSigner`].
+//!
+//! [`XmssSigner`]: crate::xmss::XmssSigner
+
+use crate::ots::winternitz::MaskedStep;
+use crate::store::StateStore;
+use crate::xmss::{load_index, reserve_index, signed_root, Tree, XmssError, XmssParams, XmssSignature};
+use digest::generic_array::GenericArray;
+use digest::{Digest, OutputSizeUser};
+
+pub struct HypertreePublicKey<H: OutputSizeUser> {
+    /// The parameters of every tree.
+    pub params: XmssParams,
+    pub layers: u32,
+    pub root: GenericArray<u8, H::OutputSize>,
+    pub public_seed: Vec<u8>,
+}
+
+/// One signature per layer, bottom first: the message signature, then the signatures of each
+/// tree's root by its parent.
+pub struct HypertreeSignature<H: OutputSizeUser> {
+    pub index: u64,
+    pub layers: Vec<XmssSignature<H>>,
+}
+
+/// Identifies tree `tree` on layer `layer` in key derivation and signed digests.
+fn context(layer: u32, tree: u64) -> Vec<u8> {
+    let mut context = layer.to_be_bytes().to_vec();
+    context.extend_from_slice(&tree.to_be_bytes());
+    context
+}
+
+fn tree_seed<H: Digest>(secret_seed: &[u8], layer: u32, tree: u64) -> GenericArray<u8, H::OutputSize> {
+    let mut hasher = H::new_with_prefix(b"hypertree tree seed");
+    hasher.update((secret_seed.len() as u64).to_be_bytes());
+    hasher.update(secret_seed);
+    hasher.update(context(layer, tree));
+    hasher.finalize()
+}
+
+pub struct HypertreeSigner<H: Digest, S: StateStore<State = u64>> {
+    secret_seed: Vec<u8>,
+    /// The current tree on every layer, with its index on that layer.
+    trees: Vec<(u64, Tree<H>)>,
+    /// The signature on the root of `trees[l]` made by `trees[l + 1]`.
+    root_signatures: Vec<XmssSignature<H>>,
+    public_key: HypertreePublicKey<H>,
+    store: S,
+    key: S::Key,
+    index: u64,
+}
+
+impl<H, S> HypertreeSigner<H, S>
+where
+    H: Digest + Send + 'static,
+    S: StateStore<State = u64>,
+{
+    /// Set up the key for the seeds with `layers` layers of trees with `params`, resuming from the
+    /// index in `store`. `layers * params.height` must stay below 64.
+    pub fn new(params: XmssParams, layers: u32, secret_seed: &[u8], public_seed: &[u8], store: S, key: S::Key) -> Result<Self, XmssError> {
+        if layers == 0 || layers * params.height >= 64 {
+            return Err(XmssError::BadParams);
+        }
+        let index = load_index(&store, &key)?;
+        let mut signer = HypertreeSigner {
+            secret_seed: secret_seed.to_vec(),
+            trees: Vec::new(),
+            root_signatures: Vec::new(),
+            public_key: HypertreePublicKey { params, layers, root: GenericArray::default(), public_seed: public_seed.to_vec() },
+            store,
+            key,
+            index,
+        };
+        // Build from the top, each tree positioned at the leaf signing the current tree below.
+        // Signing that root again on resumption repeats the earlier signature exactly, since
+        // WOTS+ signing is deterministic, so nothing more is revealed.
+        for layer in (0..layers).rev() {
+            let tree_index = signer.tree_index(layer, index);
+            let tree = Tree::new(params, &tree_seed::<H>(secret_seed, layer, tree_index), public_seed, signer.leaf_index(layer, index))?;
+            if layer == layers - 1 {
+                signer.public_key.root = tree.root().clone();
+            }
+            signer.trees.insert(0, (tree_index, tree));
+            if layer + 1 < layers {
+                let signature = signer.sign_root(layer)?;
+                signer.root_signatures.insert(0, signature);
+            }
+        }
+        Ok(signer)
+    }
+
+    fn tree_index(&self, layer: u32, index: u64) -> u64 {
+        index >> (self.public_key.params.height * (layer + 1))
+    }
+
+    fn leaf_index(&self, layer: u32, index: u64) -> u64 {
+        (index >> (self.public_key.params.height * layer)) & ((1 << self.public_key.params.height) - 1)
+    }
+
+    /// Have the parent of the current tree on `layer` sign its root.
+    fn sign_root(&mut self, layer: u32) -> Result<XmssSignature<H>, XmssError> {
+        let root = self.trees[layer as usize].1.root().clone();
+        let parent_context = context(layer + 1, self.trees[layer as usize + 1].0);
+        let public_root = self.public_key.root.clone();
+        self.trees[layer as usize + 1].1.sign(&public_root, &parent_context, &root)
+    }
+
+    /// Replace the exhausted tree on `layer` by the next one, rolling over its parent as needed.
+    fn roll_over(&mut self, layer: u32) -> Result<(), XmssError> {
+        let params = self.public_key.params;
+        let tree_index = self.trees[layer as usize].0 + 1;
+        let tree = Tree::new(params, &tree_seed::<H>(&self.secret_seed, layer, tree_index), &self.public_key.public_seed, 0)?;
+        self.trees[layer as usize] = (tree_index, tree);
+        if self.trees[layer as usize + 1].1.is_exhausted() {
+            self.roll_over(layer + 1)?;
+        }
+        self.root_signatures[layer as usize] = self.sign_root(layer)?;
+        Ok(())
+    }
+
+    pub fn public_key(&self) -> &HypertreePublicKey<H> {
+        &self.public_key
+    }
+
+    pub fn remaining(&self) -> u64 {
+        (1 << (self.public_key.layers * self.public_key.params.height)) - self.index
+    }
+
+    pub fn sign(&mut self, message: &[u8]) -> Result<HypertreeSignature<H>, XmssError> {
+        if self.remaining() == 0 {
+            return Err(XmssError::Exhausted);
+        }
+        reserve_index(&self.store, &self.key, self.index)?;
+        let index = self.index;
+        self.index += 1;
+        let bottom_context = context(0, self.trees[0].0);
+        let public_root = self.public_key.root.clone();
+        let bottom = self.trees[0].1.sign(&public_root, &bottom_context, message)?;
+        let mut layers = vec![bottom];
+        layers.extend(self.root_signatures.iter().cloned());
+        if self.trees[0].1.is_exhausted() && self.remaining() > 0 {
+            self.roll_over(0)?;
+        }
+        Ok(HypertreeSignature { index, layers })
+    }
+}
+
+impl<H: Digest> HypertreePublicKey<H> {
+    pub fn verify(&self, message: &[u8], signature: &HypertreeSignature<H>) -> bool {
+        let height = self.params.height;
+        if signature.layers.len() != self.layers as usize || signature.index >> (height * self.layers) != 0 {
+            return false;
+        }
+        let step = MaskedStep::<H>::new(self.params.wots, &self.public_seed);
+        let mut signed = message.to_vec();
+        for (layer, layer_signature) in signature.layers.iter().enumerate() {
+            let layer = layer as u32;
+            let leaf = (signature.index >> (height * layer)) & ((1 << height) - 1);
+            let tree = signature.index >> (height * (layer + 1));
+            if layer_signature.index != leaf {
+                return false;
+            }
+            match signed_root(self.params, &step, &self.root, &context(layer, tree), &signed, layer_signature) {
+                Some(root) => signed = root.to_vec(),
+                None => return false,
+            }
+        }
+        signed == self.root.as_slice()
+    }
+}
+
+#[test]
+fn test_hypertree_rollover() {
+    use crate::ots::winternitz::Params;
+    use crate::store::MemoryStore;
+    use sha2::Sha256;
+    use std::sync::Arc;
+
+    let params = XmssParams { height: 2, wots: Params::new(16).unwrap(), bds_k: 0 };
+    let store = Arc::new(MemoryStore::<&str, u64>::new());
+    let mut signer = HypertreeSigner::<Sha256, _>::new(params, 2, b"secret", b"public", store.clone(), "key").unwrap();
+    assert_eq!(signer.remaining(), 16);
+    let signatures: Vec<_> = (0..6u8).map(|i| signer.sign(&[i]).unwrap()).collect();
+    for (i, signature) in signatures.iter().enumerate() {
+        assert_eq!(signature.index, i as u64);
+        assert!(signer.public_key().verify(&[i as u8], signature), "signature {}", i);
+        assert!(!signer.public_key().verify(&[i as u8 + 1], signature));
+    }
+
+    // resuming in the middle of the second bottom tree gives the same key and goes on signing
+    let mut restored = HypertreeSigner::<Sha256, _>::new(params, 2, b"secret", b"public", store.clone(), "key").unwrap();
+    assert_eq!(restored.public_key().root, signer.public_key().root);
+    for i in 6..16u8 {
+        let signature = restored.sign(&[i]).unwrap();
+        assert!(signer.public_key().verify(&[i], &signature), "signature {}", i);
+    }
+    assert_eq!(restored.sign(b"none left").err(), Some(XmssError::Exhausted));
+    assert_eq!(signer.sign(b"stale").err(), Some(XmssError::IndexReused));
+}
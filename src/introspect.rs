@@ -0,0 +1,163 @@
+//! A read-only snapshot of a traversal for monitoring dashboards and the `fht-inspect` TUI.
+//!
+//! [`HashChain::state`] copies out everything but the pebble values into plain structs, so a
+//! snapshot can be kept, sent or displayed without holding on to anything secret.
+//! [`HashChain::dump`] is the fuller picture for debugging, every counter of every pebble and,
+//! only if asked for, the values; with the `serde` feature it serializes as well.
+
+use crate::value::Hex;
+use crate::HashChain;
+use alloc::format;
+use alloc::string::String;
+use alloc::vec::Vec;
+use core::fmt::{self, Display};
+use digest::{Digest, FixedOutputReset};
+
+/// What the next disclosure does.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Phase {
+    /// Hash the first pebble's value, at odd positions.
+    Derive,
+    /// Hand out the first pebble's own value and send the pebble up the chain, at even positions.
+    Release,
+    /// Every value has been disclosed.
+    Exhausted,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PebbleState {
+    /// The pebble rests on odd multiples of `2^level`.
+    pub level: u32,
+    pub position: u64,
+    pub destination: u64,
+    pub moving: bool,
+    /// Disclosures since the pebble was last sent up the chain, or since setup.
+    pub age: u64,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TraversalState {
+    pub length: u64,
+    pub position: u64,
+    pub remaining: u64,
+    pub phase: Phase,
+    /// The hash evaluations the next disclosure takes.
+    pub next_step_cost: u64,
+    /// Ordered by destination.
+    pub pebbles: Vec<PebbleState>,
+}
+
+impl<H: Digest + FixedOutputReset> HashChain<H> {
+    pub fn state(&self) -> TraversalState {
+        let phase = match self.current {
+            current if current == self.length => Phase::Exhausted,
+            current if current % 2 == 0 => Phase::Derive,
+            _ => Phase::Release,
+        };
+        let pebbles = self.pebbles.iter().map(|pebble| {
+            let base = pebble.dest_incr / 2;
+            // the first destination is `base`, every later one was reached by a jump
+            let sent_at = if pebble.destination > base { pebble.destination - pebble.dest_incr } else { 0 };
+            PebbleState {
+                level: base.trailing_zeros(),
+                position: pebble.position,
+                destination: pebble.destination,
+                moving: pebble.position != pebble.destination,
+                age: self.current.saturating_sub(sent_at),
+            }
+        }).collect();
+        TraversalState { length: self.length, position: self.current, remaining: self.remaining(), phase, next_step_cost: self.step_cost(), pebbles }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct PebbleDump {
+    pub start_incr: u64,
+    pub dest_incr: u64,
+    pub position: u64,
+    pub destination: u64,
+    /// The value in hex, `None` when redacted.
+    pub value: Option<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct ChainStateDump {
+    pub length: u64,
+    pub position: u64,
+    pub output_size: usize,
+    /// The anchor in hex; it is public.
+    pub anchor: String,
+    pub next_step_cost: u64,
+    /// Ordered by destination.
+    pub pebbles: Vec<PebbleDump>,
+}
+
+impl Display for ChainStateDump {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "chain of {} at position {}, next step {} hashes, anchor {}", self.length, self.position, self.next_step_cost, self.anchor)?;
+        for (offset, pebble) in self.pebbles.iter().enumerate() {
+            write!(f, "  {:>2}: {} -> {} (+{}/+{}) ", offset, pebble.position, pebble.destination, pebble.start_incr, pebble.dest_incr)?;
+            writeln!(f, "{}", pebble.value.as_deref().unwrap_or("<redacted>"))?;
+        }
+        Ok(())
+    }
+}
+
+impl<H: Digest + FixedOutputReset> HashChain<H> {
+    /// Everything about the traversal, for debugging. Unless `redact` is false the pebble
+    /// values are left out; a dump with values reveals every undisclosed value of the chain.
+    pub fn dump(&self, redact: bool) -> ChainStateDump {
+        let pebbles = self.pebbles.iter().map(|pebble| PebbleDump {
+            start_incr: pebble.start_incr,
+            dest_incr: pebble.dest_incr,
+            position: pebble.position,
+            destination: pebble.destination,
+            value: (!redact).then(|| format!("{:x}", Hex(&pebble.value))),
+        }).collect();
+        ChainStateDump {
+            length: self.length,
+            position: self.current,
+            output_size: <H as Digest>::output_size(),
+            anchor: format!("{:x}", Hex(&self.anchor)),
+            next_step_cost: self.step_cost(),
+            pebbles,
+        }
+    }
+}
+
+#[test]
+fn test_traversal_state() {
+    use sha2::Sha256;
+
+    let mut chain = HashChain::<Sha256>::new(32, 1).unwrap();
+    assert_eq!(chain.state().phase, Phase::Derive);
+    chain.by_ref().take(4).for_each(drop);
+    let state = chain.state();
+    assert_eq!((state.position, state.remaining, state.phase), (4, 28, Phase::Derive));
+    assert_eq!(state.next_step_cost, chain.step_cost());
+    // the level 2 pebble left position 4 for 16 on the last step and has moved down to 14
+    assert_eq!(state.pebbles[2], PebbleState { level: 2, position: 14, destination: 12, moving: true, age: 0 });
+    assert_eq!(state.pebbles[0], PebbleState { level: 1, position: 6, destination: 6, moving: false, age: 2 });
+    chain.disclose();
+    assert_eq!(chain.state().phase, Phase::Release);
+    chain.by_ref().for_each(drop);
+    assert_eq!(chain.state().phase, Phase::Exhausted);
+    assert!(chain.state().pebbles.is_empty());
+}
+
+#[test]
+fn test_chain_state_dump() {
+    use alloc::string::ToString;
+    use sha2::Sha256;
+
+    let mut chain = HashChain::<Sha256>::new(8, 2).unwrap();
+    chain.disclose();
+    let dump = chain.dump(true);
+    assert_eq!((dump.length, dump.position, dump.output_size, dump.pebbles.len()), (8, 1, 32, 3));
+    assert!(dump.pebbles.iter().all(|pebble| pebble.value.is_none()));
+    assert!(dump.to_string().contains("<redacted>"));
+    let revealed = chain.dump(false);
+    assert_eq!(revealed.pebbles[0].value.as_deref(), Some(hex::encode(chain.pebbles()[0].value).as_str()));
+}
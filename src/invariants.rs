@@ -0,0 +1,145 @@
+//! Structural checks of a traversal's pebbles, for states that come from outside the process.
+//!
+//! Pebble `j` (for `j` from 1 to `log2(length)`) rests on odd multiples of `2^j`: it starts at
+//! `2^j`, and each time its value is used it jumps `3 * 2^j` up and is hashed down, two positions
+//! per disclosure, to a destination `2^(j+1)` above the last one. It is dropped once that
+//! destination would lie past the end of the chain. [`HashChain::check_invariants`] checks every
+//! pebble against this, and that exactly the pebbles still needed are there.
+
+use crate::HashChain;
+use alloc::vec::Vec;
+use core::fmt::{self, Display};
+use digest::{Digest, FixedOutputReset};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Violation {
+    /// The pebble at this offset does not have a higher destination than the one before it.
+    Unordered { offset: usize },
+    /// The pebble's increments are not `3 * 2^j` and `2 * 2^j` for some `j` of at least 1.
+    Increments { offset: usize },
+    /// The pebble's destination is not an odd multiple of `2^j`.
+    Misaligned { offset: usize },
+    /// The pebble is not an even distance of at most `2^j` above its destination, or lies past
+    /// the end of the chain.
+    Position { offset: usize },
+    /// The pebble's destination has already been disclosed.
+    Passed { offset: usize },
+    /// No pebble for level `j`, which is still needed.
+    Missing { level: u32 },
+    /// A pebble for level `j` that is duplicated or should have been dropped.
+    Extra { level: u32 },
+    /// No resting pebble holds one of the next two values.
+    NoNextValue,
+}
+
+impl Display for Violation {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Violation::Unordered { offset } => write!(f, "pebble {} out of destination order", offset),
+            Violation::Increments { offset } => write!(f, "pebble {} has invalid increments", offset),
+            Violation::Misaligned { offset } => write!(f, "pebble {} has a misaligned destination", offset),
+            Violation::Position { offset } => write!(f, "pebble {} is not on its way to its destination", offset),
+            Violation::Passed { offset } => write!(f, "pebble {} is behind the current position", offset),
+            Violation::Missing { level } => write!(f, "pebble for level {} missing", level),
+            Violation::Extra { level } => write!(f, "unexpected pebble for level {}", level),
+            Violation::NoNextValue => write!(f, "no pebble holds the next value"),
+        }
+    }
+}
+
+/// Every invariant a traversal state violates, empty if it is consistent.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct InvariantReport {
+    pub violations: Vec<Violation>,
+}
+
+impl InvariantReport {
+    pub fn is_ok(&self) -> bool {
+        self.violations.is_empty()
+    }
+}
+
+impl Display for InvariantReport {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.is_ok() {
+            return write!(f, "consistent");
+        }
+        for (i, violation) in self.violations.iter().enumerate() {
+            write!(f, "{}{}", if i == 0 { "" } else { "; " }, violation)?;
+        }
+        Ok(())
+    }
+}
+
+impl<H: Digest + FixedOutputReset> HashChain<H> {
+    /// Check the pebbles against the layout the traversal maintains. The length itself is
+    /// assumed valid, a power of two of at least 2.
+    pub fn check_invariants(&self) -> InvariantReport {
+        let mut violations = Vec::new();
+        let levels = self.length.trailing_zeros();
+        let mut per_level = Vec::from_iter(core::iter::repeat_n(0u32, levels as usize + 1));
+        for (offset, pebble) in self.pebbles.iter().enumerate() {
+            if offset > 0 && pebble.destination <= self.pebbles[offset - 1].destination {
+                violations.push(Violation::Unordered { offset });
+            }
+            let base = pebble.dest_incr / 2;
+            if base < 2 || !base.is_power_of_two() || base > self.length || pebble.start_incr != 3 * base {
+                violations.push(Violation::Increments { offset });
+                continue;
+            }
+            per_level[base.trailing_zeros() as usize] += 1;
+            if pebble.destination % pebble.dest_incr != base {
+                violations.push(Violation::Misaligned { offset });
+            }
+            let distance = pebble.position.checked_sub(pebble.destination);
+            if distance.is_none_or(|distance| distance % 2 == 1 || distance > base) || pebble.position > self.length {
+                violations.push(Violation::Position { offset });
+            }
+            if pebble.destination <= self.current {
+                violations.push(Violation::Passed { offset });
+            }
+        }
+        for level in 1..=levels {
+            let base = 1u64 << level;
+            let last = if level == levels { self.length } else { self.length - base };
+            match (per_level[level as usize], self.current < last) {
+                (0, true) => violations.push(Violation::Missing { level }),
+                (1, true) | (0, false) => {}
+                _ => violations.push(Violation::Extra { level }),
+            }
+        }
+        let next = self.current + 1..=self.current + 2;
+        if self.current < self.length && !self.pebbles.iter().any(|p| p.position == p.destination && next.contains(&p.position)) {
+            violations.push(Violation::NoNextValue);
+        }
+        InvariantReport { violations }
+    }
+}
+
+#[test]
+fn test_invariants_hold_along_the_chain() {
+    use sha2::Sha256;
+
+    for length in [2, 4, 64] {
+        let mut chain = HashChain::<Sha256>::new(length, 3).unwrap();
+        loop {
+            assert_eq!(chain.check_invariants(), InvariantReport::default(), "length {} at {}", length, HashChain::position(&chain));
+            if chain.disclose().is_none() {
+                break;
+            }
+        }
+    }
+
+    let mut chain = HashChain::<Sha256>::new(16, 3).unwrap();
+    alloc::sync::Arc::make_mut(&mut chain.pebbles).remove(1);
+    assert_eq!(chain.check_invariants().violations, vec![Violation::Missing { level: 2 }]);
+    let pebbles = alloc::sync::Arc::make_mut(&mut chain.pebbles);
+    pebbles[0].position = 3;
+    pebbles.swap(1, 2);
+    assert_eq!(chain.check_invariants().violations, vec![
+        Violation::Position { offset: 0 },
+        Violation::Unordered { offset: 2 },
+        Violation::Missing { level: 2 },
+        Violation::NoNextValue,
+    ]);
+}
@@ -0,0 +1,100 @@
+//! The bookkeeping of delayed key disclosure, shared by [`tesla`](crate::tesla),
+//! [`mutesla`](crate::mutesla) and any other protocol that keys interval `i` with the chain value
+//! at index `i` and discloses it `lag` intervals later.
+//!
+//! A [`KeySchedule`] maps times to intervals through a [`Schedule`], intervals to the chain
+//! indices keying them, and each interval to the key index disclosed in it. Its
+//! [`KeySchedule::is_safe`] is the security condition: something authenticated with an
+//! interval's key may only be accepted while the party holding the chain cannot have reached the
+//! interval disclosing that key.
+
+use crate::tesla::Schedule;
+use std::error::Error;
+use std::fmt::{self, Display};
+use std::time::Duration;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum KeyScheduleError {
+    /// The interval lies after the latest one the key holder can be in.
+    FromTheFuture,
+    /// The interval's key may already have been disclosed.
+    Disclosed,
+}
+
+impl Display for KeyScheduleError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            KeyScheduleError::FromTheFuture => write!(f, "interval lies in the future"),
+            KeyScheduleError::Disclosed => write!(f, "interval key may already be disclosed"),
+        }
+    }
+}
+
+impl Error for KeyScheduleError {}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct KeySchedule {
+    pub schedule: Schedule,
+    /// The intervals between using a key and disclosing it.
+    pub lag: u64,
+}
+
+impl KeySchedule {
+    pub fn new(schedule: Schedule, lag: u64) -> Self {
+        KeySchedule { schedule, lag }
+    }
+
+    /// The interval containing `time`, or `None` before the start.
+    pub fn interval_at(&self, time: Duration) -> Option<u64> {
+        self.schedule.interval_at(time)
+    }
+
+    /// The chain index keying `interval`.
+    pub fn key_index(&self, interval: u64) -> u64 {
+        interval
+    }
+
+    /// The interval the key at chain index `index` is disclosed in.
+    pub fn disclosure_interval(&self, index: u64) -> u64 {
+        index + self.lag
+    }
+
+    /// The chain index disclosed in `interval`, or `None` while no key is due.
+    pub fn disclosed_in(&self, interval: u64) -> Option<u64> {
+        interval.checked_sub(self.lag).filter(|&index| index > 0).map(|index| self.key_index(index))
+    }
+
+    /// How many keys the key holder has to keep around: the current one and the `lag` before it.
+    pub fn retained(&self) -> usize {
+        self.lag as usize + 1
+    }
+
+    /// Whether something claiming `interval` can still be accepted when the key holder's clock
+    /// shows at most `latest_time`.
+    pub fn is_safe(&self, interval: u64, latest_time: Duration) -> Result<(), KeyScheduleError> {
+        let latest = self.interval_at(latest_time).unwrap_or(0);
+        if interval > latest {
+            return Err(KeyScheduleError::FromTheFuture);
+        }
+        if latest >= self.disclosure_interval(self.key_index(interval)) {
+            return Err(KeyScheduleError::Disclosed);
+        }
+        Ok(())
+    }
+}
+
+#[test]
+fn test_key_schedule() {
+    let keys = KeySchedule::new(Schedule { start: Duration::from_secs(10), interval: Duration::from_secs(2) }, 3);
+    assert_eq!(keys.interval_at(Duration::from_secs(9)), None);
+    assert_eq!(keys.interval_at(Duration::from_secs(15)), Some(3));
+    assert_eq!(keys.disclosed_in(3), None);
+    assert_eq!(keys.disclosed_in(5), Some(2));
+    assert_eq!(keys.disclosure_interval(keys.key_index(2)), 5);
+    assert_eq!(keys.retained(), 4);
+
+    // interval 2's key goes out in interval 5, which starts at 18s
+    assert_eq!(keys.is_safe(2, Duration::from_secs(17)), Ok(()));
+    assert_eq!(keys.is_safe(2, Duration::from_secs(18)), Err(KeyScheduleError::Disclosed));
+    assert_eq!(keys.is_safe(4, Duration::from_secs(13)), Err(KeyScheduleError::FromTheFuture));
+}
@@ -0,0 +1,37 @@
+//! **Insecure** compatibility with historical S/KEY and RFC 2289 OTP servers.
+//!
+//! MD4 and MD5 are broken hash functions and a 64-bit folded output is far too short for a modern
+//! chain. This module exists only to interoperate with deployed legacy systems, is compiled only
+//! with the `insecure-legacy` feature, and nothing outside of it depends on these types.
+
+use crate::otp;
+use crate::sixword::Folded;
+
+/// The RFC 2289 MD4 step: MD4 followed by folding to 64 bits.
+pub type Md4Fold = Folded<md4::Md4>;
+
+/// The RFC 2289 MD5 step: MD5 followed by folding to 64 bits.
+pub type Md5Fold = Folded<md5::Md5>;
+
+/// Compute the S/KEY one-time password for `count` using folded MD4.
+pub fn skey_md4(seed: &str, passphrase: &str, count: u64) -> u64 {
+    otp::compute::<md4::Md4>(seed, passphrase, count)
+}
+
+/// Compute the S/KEY one-time password for `count` using folded MD5.
+pub fn skey_md5(seed: &str, passphrase: &str, count: u64) -> u64 {
+    otp::compute::<md5::Md5>(seed, passphrase, count)
+}
+
+#[test]
+fn test_rfc2289_md4_vectors() {
+    assert_eq!(skey_md4("TeSt", "This is a test.", 0), 0xD1854218EBBB0B51);
+    assert_eq!(skey_md4("alpha1", "AbCdEfGhIjK", 99), 0xD150C82CCE6F62D1);
+}
+
+#[test]
+fn test_rfc2289_md5_vectors() {
+    assert_eq!(skey_md5("TeSt", "This is a test.", 0), 0x9E876134D90499DD);
+    assert_eq!(skey_md5("TeSt", "This is a test.", 1), 0x7965E05436F5029F);
+    assert_eq!(crate::sixword::encode(skey_md5("correct", "OTP's are good", 99)), "LONG IVY JULY AJAR BOND LEE");
+}
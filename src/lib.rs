@@ -1,140 +1,235 @@
-use digest::{Digest, generic_array::GenericArray, FixedOutputReset, OutputSizeUser};
-use sha2::Sha256;
-use std::fmt::{self, Display, Debug};
-use std::error::Error;
+#![cfg_attr(not(any(feature = "std", test)), no_std)]
 
-#[derive(Debug, Clone)]
-struct ChainInitError {
-    details: String,
-}
-
-impl ChainInitError {
-    fn new(error_message: &str) -> ChainInitError {
-        ChainInitError { details: error_message.to_string() }
-    }
-}
-
-impl Display for ChainInitError {
-    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        write!(f, "{}", self.details)
-    }
-}
-
-impl Error for ChainInitError {
-    fn description(&self) -> &str {
-        &self.details
-    }
-}
-
-#[derive(Clone)]
-struct Pebble<H: OutputSizeUser> {
-    start_incr: u64,
-    dest_incr: u64,
-    position: u64,
-    destination: u64,
-    value: GenericArray<u8, H::OutputSize>,
-}
-
-impl<H: OutputSizeUser> Display for Pebble<H> {
-    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        let value_bytes = self.value.as_slice();
-        write!(f, "Pebble {{start_incr: {}, dest_incr: {}, position: {}, destination: {}, value: {}}}", self.start_incr, self.dest_incr, self.position, self.destination, hex::encode(value_bytes))
-    }
-}
-
-impl<H: OutputSizeUser> Debug for Pebble<H> {
-    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        let value_bytes = self.value.as_slice();
-        write!(f, "Pebble {{start_incr: {}, dest_incr: {}, position: {}, destination: {}, value: {}}}", self.start_incr, self.dest_incr, self.position, self.destination, hex::encode(value_bytes))
-    }
-}
-
-const fn num_bits<T>() -> usize { std::mem::size_of::<T>() * 8 }
+extern crate alloc;
 
-fn log_2(x: u64) -> u32 {
-    assert!(x > 0);
-    num_bits::<u64>() as u32 - x.leading_zeros() - 1
-}
-
-/// Return a mutable list of powers of two
-fn create_powers(how_many: u32) -> Vec<u64> {
-    let mut powers = Vec::<u64>::new();
-    for p in 0..how_many {
-        powers.push(2u64.pow(p+1));
-    }
-    powers
-}
-
-/// Creates the initial hash chain and outputs the pebbles which can be used to traverse the chain.
-fn create_hash_chain<H: Digest + FixedOutputReset>(length: usize, seed: u64) -> Result<Vec<Pebble<H>>, ChainInitError>
-where
-    {
-    // is length a power of two? Also catches zero
-    if length == 0 || (length & (length - 1)) != 0 {
-        return Err(ChainInitError::new("length not a power of two"));
-    }
-
-    // the number of pebbles is log_2(length)
-    let num_pebbles = log_2(length.try_into().unwrap());
-
-    // initialize the pebble list
-    let mut pebbles = Vec::<Pebble<H>>::new();
-
-    // initialize list of powers so we dont need to compute each time
-    let powers = create_powers(num_pebbles);
-
-    let mut hasher = H::new_with_prefix(seed.to_le_bytes());
-    let mut output = hasher.finalize_reset();
-    for i in 2u64..=length as u64 {
-        digest::Digest::update(&mut hasher, output.as_ref());
-        output = hasher.finalize_reset();
-        if i.eq(powers.get(log_2(i) as usize - 1).unwrap()) {
-            pebbles.push(Pebble{
-                start_incr: 3*i,
-                dest_incr: 2u64*i,
-                position: i,
-                destination: i,
-                value: output.clone(),
-            });
+#[cfg(feature = "uniffi")]
+uniffi::setup_scaffolding!();
 
+/// Check [`HashChain::check_invariants`] in debug builds.
+macro_rules! debug_assert_invariants {
+    ($chain:expr) => {
+        #[cfg(debug_assertions)]
+        {
+            let report = $chain.check_invariants();
+            debug_assert!(report.is_ok(), "traversal invariants violated: {}", report);
         }
-    }
-
-    Ok(pebbles)
+    };
 }
 
-/// Create hash chain without using pebbles. Warning: the resulting array will be very large,
-/// specifically the length specified.
-fn create_hash_chain_nopebble<H: Digest + FixedOutputReset>(length: usize, seed: u64) -> Vec<GenericArray<u8, H::OutputSize>> {
-    let mut chain = Vec::<GenericArray<u8, H::OutputSize>>::new();
-    let mut hasher = H::new_with_prefix(seed.to_le_bytes());
-    let mut output = hasher.finalize_reset();
-    chain.push(output.clone());
-    for _ in 2u64..=length as u64 {
-        digest::Digest::update(&mut hasher, output.as_ref());
-        output = hasher.finalize_reset();
-        chain.push(output.clone());
-    }
-    chain
+mod error;
+mod pebble;
+mod chain;
+mod traverse;
+mod serialize;
+pub mod verify;
+
+pub use chain::{audit_chain, audit_chain_with_pebbles, create_hash_chain, forward_iter, ChainId, ForwardIter};
+#[doc(hidden)]
+pub use chain::create_hash_chain_nopebble;
+pub use error::{ChainAuditError, ChainInitError};
+pub use pebble::{Pebble, Revealed};
+pub use traverse::{HashChain, Reservation};
+pub use verify::{hash_forward, hash_forward_personalized, verify, verify_batch, verify_personalized, ChainStep, MacStep, PersonalizedStep, PlainStep};
+pub(crate) use chain::{setup_chain, Setup};
+pub(crate) use pebble::log_2;
+
+/// The types and functions most users need: `use fractal_hash_traversal::prelude::*;`.
+pub mod prelude {
+    pub use crate::value::ChainValue;
+    pub use crate::{hash_forward, verify, verify_batch, ChainId, ChainInitError, HashChain};
 }
 
-#[test]
-fn test_chain_init() {
-    let len = 128;
-    let pebbles = create_hash_chain::<Sha256>(len, 0).unwrap();
-    println!("Here are the pebbles: {:?}", pebbles);
-    assert_eq!(pebbles.len(), log_2(len.try_into().unwrap()).try_into().unwrap());
-}
-
-#[test]
-fn test_create_powers_small() {
-    let powers = create_powers(3);
-    assert_eq!(powers, vec![2, 4, 8]);
-}
+#[cfg(feature = "std")]
+pub mod sixword;
+#[cfg(feature = "std")]
+pub mod store;
+#[cfg(feature = "std")]
+pub mod otp;
+#[cfg(feature = "std")]
+pub mod hotp;
+#[cfg(feature = "std")]
+pub mod provisioning;
+#[cfg(feature = "std")]
+pub mod registry;
+#[cfg(feature = "std")]
+pub mod shared;
+#[cfg(feature = "std")]
+pub mod chainset;
+#[cfg(feature = "std")]
+pub mod sharded;
+#[cfg(feature = "std")]
+pub mod segmented;
+#[cfg(feature = "std")]
+pub mod tokens;
+#[cfg(feature = "std")]
+pub mod ratelimit;
+#[cfg(feature = "std")]
+pub mod metering;
+#[cfg(feature = "std")]
+pub mod payword;
+#[cfg(feature = "sha2")]
+pub mod shachain;
+#[cfg(feature = "std")]
+pub mod puzzle;
+#[cfg(feature = "std")]
+pub mod timelock;
+#[cfg(feature = "std")]
+pub mod tesla;
+#[cfg(feature = "std")]
+pub mod keyschedule;
+#[cfg(feature = "std")]
+pub mod mutesla;
+#[cfg(feature = "std")]
+pub mod multilevel;
+#[cfg(feature = "std")]
+pub mod epoch;
+#[cfg(feature = "std")]
+pub mod hierarchy;
+#[cfg(feature = "std")]
+pub mod release;
+#[cfg(feature = "std")]
+pub mod mutual_auth;
+#[cfg(feature = "std")]
+pub mod seclog;
+#[cfg(feature = "std")]
+pub mod evolving;
+#[cfg(feature = "std")]
+pub mod guy_fawkes;
+#[cfg(feature = "std")]
+pub mod commit;
+#[cfg(feature = "std")]
+pub mod dualchain;
+#[cfg(feature = "std")]
+pub mod beacon;
+#[cfg(feature = "std")]
+pub mod sequential_beacon;
+#[cfg(feature = "std")]
+pub mod lottery;
+#[cfg(feature = "std")]
+pub mod ots;
+pub mod merkle;
+pub mod value;
+pub mod array;
+#[cfg(feature = "std")]
+pub mod xmss;
+#[cfg(feature = "std")]
+pub mod hypertree;
+#[cfg(feature = "std")]
+pub mod sparse;
+#[cfg(feature = "std")]
+pub mod mmr;
+#[cfg(feature = "std")]
+pub mod chainlog;
+#[cfg(feature = "std")]
+pub mod ack;
+#[cfg(feature = "std")]
+pub mod anchor;
+pub mod proof;
+pub mod disclosure;
+pub mod version;
+pub mod fixed;
+pub mod params;
+pub mod plan;
+pub mod seed;
+pub mod entropy;
+#[cfg(feature = "mlock")]
+pub mod locked;
+pub mod observer;
+pub mod invariants;
+pub mod introspect;
+pub mod cooperative;
+pub mod conformance;
+pub mod endless;
+pub mod rotation;
+pub mod skipchain;
+pub mod bidirectional;
+pub mod retire;
+pub mod payload;
+pub mod timestamp;
+pub mod posw;
+pub mod policy;
+pub mod window;
+pub mod table;
+#[cfg(feature = "arkworks")]
+pub mod field;
+#[cfg(feature = "evm")]
+pub mod evm;
+#[cfg(any(feature = "ring", feature = "openssl"))]
+pub mod backend;
+#[cfg(feature = "fips")]
+pub mod fips;
+#[cfg(any(feature = "embedded-storage", feature = "embedded-storage-async"))]
+pub mod flash;
+#[cfg(feature = "defmt")]
+mod defmt_impls;
+#[cfg(feature = "telemetry")]
+pub mod telemetry;
+#[cfg(feature = "std")]
+pub mod measure;
+#[cfg(feature = "std")]
+pub mod checkpoint;
+#[cfg(feature = "std")]
+pub mod replay;
+#[cfg(feature = "chaos")]
+pub mod chaos;
+#[cfg(feature = "arbitrary")]
+pub mod fuzzing;
+#[cfg(feature = "vectors")]
+pub mod vectors;
+#[cfg(feature = "multiformats")]
+pub mod multiformat;
+#[cfg(feature = "tower")]
+pub mod middleware;
+#[cfg(feature = "axum")]
+pub mod extract;
+#[cfg(feature = "tokio")]
+pub mod codec;
+#[cfg(feature = "tokio")]
+pub mod stream;
+#[cfg(feature = "signature")]
+mod signature_traits;
+#[cfg(feature = "ed25519")]
+pub mod certificate;
+#[cfg(feature = "ed25519")]
+pub mod receipt;
+#[cfg(feature = "wasm")]
+pub mod wasm;
+#[cfg(feature = "ffi")]
+pub mod ffi;
+#[cfg(feature = "python")]
+pub mod python;
+#[cfg(feature = "node")]
+pub mod node;
+#[cfg(feature = "uniffi")]
+pub mod mobile;
+#[cfg(feature = "service")]
+pub mod service;
+#[cfg(feature = "server")]
+pub mod rest;
+#[cfg(feature = "protobuf")]
+pub mod wire;
+#[cfg(feature = "insecure-legacy")]
+pub mod legacy;
+
+/// An upper bound in bytes on the stack that chain setup, disclosure and verification use. None
+/// of them recurse, and their frames hold a fixed number of hash states and values, so the bound
+/// does not depend on the chain length. The tests run them on a thread with just this much stack,
+/// using SHA-256 in an unoptimized build; hash functions with larger states need more.
+pub const MAX_STACK_USAGE: usize = 32 * 1024;
 
 #[test]
-fn test_create_chain_small() {
-    let len = 128;
-    let chain = create_hash_chain_nopebble::<Sha256>(len, 0);
-    assert_eq!(len, chain.len());
+fn test_bounded_stack() {
+    use sha2::Sha256;
+
+    let run = || {
+        let mut chain = HashChain::<Sha256>::new(1 << 12, 3).unwrap();
+        let anchor = *chain.anchor();
+        let batch: Vec<_> = chain.by_ref().take(8).map(|(i, v)| (i, v.to_array())).collect();
+        assert!(verify_batch::<Sha256>(0, &anchor, &batch));
+        let (index, value) = chain.last().unwrap();
+        assert!(verify::<Sha256>(0, &anchor, index, &value));
+        let mut fixed = fixed::HashChainFixed::<Sha256, 12>::new(3);
+        assert_eq!(fixed.nth(4095).map(|(i, _)| i), Some(index));
+    };
+    std::thread::Builder::new().stack_size(MAX_STACK_USAGE).spawn(run).unwrap().join().unwrap();
 }
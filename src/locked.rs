@@ -0,0 +1,137 @@
+//! Keeping chain secrets out of swap, with the `mlock` feature.
+//!
+//! With the feature on, the pebbles of every [`HashChain`](crate::HashChain) sit in memory pages
+//! locked into RAM, and a [`Locked`] box holds any other secret the same way, e.g. a
+//! [`Seed`](crate::seed::Seed). The heap shares pages between allocations, so the locks taken
+//! here are counted per page and a page is unlocked only once nothing locked on it is left.
+//! Locking fails once the process has used up its allowance of locked memory (`RLIMIT_MEMLOCK`
+//! on Unix); the memory is then used unlocked, as without the feature, which
+//! [`Locked::is_locked`] and [`HashChain::memory_locked`](crate::HashChain::memory_locked)
+//! report. Either way it is zeroed before it is freed. Values on their way through the stack
+//! and registers while a disclosure is computed are not covered.
+
+use std::collections::BTreeMap;
+use std::ops::{Deref, DerefMut};
+use std::sync::Mutex;
+use zeroize::Zeroize;
+
+/// The number of locks taken on every locked page, by page address.
+static PAGES: Mutex<BTreeMap<usize, usize>> = Mutex::new(BTreeMap::new());
+
+/// The addresses of the pages `len` bytes from `address` span.
+fn pages(address: *const u8, len: usize) -> impl Iterator<Item = usize> {
+    let size = region::page::size();
+    let first = address as usize / size * size;
+    (first..address as usize + len).step_by(size)
+}
+
+/// Lock the pages `len` bytes from `address` span into RAM, returning whether they are locked.
+pub(crate) fn lock(address: *const u8, len: usize) -> bool {
+    if len == 0 {
+        return true;
+    }
+    let mut locked = PAGES.lock().unwrap();
+    match region::lock(address, len) {
+        Ok(guard) => core::mem::forget(guard),
+        Err(_error) => {
+            #[cfg(feature = "tracing")]
+            tracing::warn!(len, "memory locking failed, continuing unlocked");
+            return false;
+        }
+    }
+    for page in pages(address, len) {
+        *locked.entry(page).or_insert(0) += 1;
+    }
+    true
+}
+
+/// Release a successful [`lock`] of the same range.
+pub(crate) fn unlock(address: *const u8, len: usize) {
+    if len == 0 {
+        return;
+    }
+    let mut locked = PAGES.lock().unwrap();
+    for page in pages(address, len) {
+        let count = locked.get_mut(&page).expect("page was locked");
+        *count -= 1;
+        if *count == 0 {
+            locked.remove(&page);
+            // SAFETY: every lock taken on the page through this module has been released, and
+            // pages are only ever locked here
+            let _ = unsafe { region::unlock(page as *const u8, region::page::size()) };
+        }
+    }
+}
+
+/// The number of pages currently locked.
+pub fn locked_pages() -> usize {
+    PAGES.lock().unwrap().len()
+}
+
+/// A secret on the heap, locked into RAM if the operating system allows, and zeroed when
+/// dropped.
+pub struct Locked<T: Zeroize> {
+    value: Box<T>,
+    locked: bool,
+}
+
+impl<T: Zeroize> Locked<T> {
+    /// Move `value` into locked memory. The copy it is moved from, e.g. on the stack, is not
+    /// wiped.
+    pub fn new(value: T) -> Self {
+        let value = Box::new(value);
+        let locked = lock((&*value as *const T).cast(), core::mem::size_of::<T>());
+        Locked { value, locked }
+    }
+
+    /// Whether the memory is locked, or the lock failed and it can be swapped out.
+    pub fn is_locked(&self) -> bool {
+        self.locked
+    }
+}
+
+impl<T: Zeroize> Deref for Locked<T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        &self.value
+    }
+}
+
+impl<T: Zeroize> DerefMut for Locked<T> {
+    fn deref_mut(&mut self) -> &mut T {
+        &mut self.value
+    }
+}
+
+impl<T: Zeroize> Drop for Locked<T> {
+    fn drop(&mut self) {
+        self.value.zeroize();
+        if self.locked {
+            unlock((&*self.value as *const T).cast(), core::mem::size_of::<T>());
+        }
+    }
+}
+
+#[test]
+fn test_locked_memory() {
+    use crate::seed::Seed;
+    use sha2::Sha256;
+
+    let seed = Locked::new(Seed::new(7));
+    assert_eq!(seed.expose(), 7);
+    let chain = crate::HashChain::<Sha256>::new(1024, seed.expose()).unwrap();
+    if seed.is_locked() && chain.memory_locked() {
+        assert!(locked_pages() >= 1);
+    }
+    assert!(chain.clone().eq(crate::HashChain::<Sha256>::new(1024, 7).unwrap()));
+
+    // two locks on one page: releasing one leaves the page locked
+    let buffer = [0u8; 2];
+    let page = pages(buffer.as_ptr(), 1).next().unwrap();
+    if lock(buffer.as_ptr(), 1) && lock(buffer.as_ptr(), 2) {
+        unlock(buffer.as_ptr(), 2);
+        assert!(PAGES.lock().unwrap().contains_key(&page));
+        unlock(buffer.as_ptr(), 1);
+    }
+}
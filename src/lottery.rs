@@ -0,0 +1,131 @@
+//! Verifiable draws from [`beacon`](crate::beacon) rounds.
+//!
+//! A draw is a deterministic function of a round's output and the participant list, so anyone
+//! holding the anchor and the published rounds can re-run it with [`audit`]. The participant
+//! list has to be fixed before the round is published, otherwise whoever assembles it can try
+//! out lists until one wins.
+
+use crate::beacon::{BeaconVerifier, Round};
+use digest::generic_array::GenericArray;
+use digest::{Digest, FixedOutputReset};
+use std::error::Error;
+use std::fmt::{self, Display};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum AuditError {
+    /// A published round does not verify against the anchor.
+    InvalidRound(u64),
+    /// The round the draw used was not among the published ones.
+    MissingRound(u64),
+    /// The claimed result differs from the recomputed one.
+    Mismatch,
+}
+
+impl Display for AuditError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            AuditError::InvalidRound(round) => write!(f, "round {} does not verify", round),
+            AuditError::MissingRound(round) => write!(f, "round {} not published", round),
+            AuditError::Mismatch => write!(f, "claimed result does not match the draw"),
+        }
+    }
+}
+
+impl Error for AuditError {}
+
+/// Uniform numbers derived from a round output and a participant list, in counter mode.
+struct DrawStream<H: Digest> {
+    seed: GenericArray<u8, H::OutputSize>,
+    counter: u64,
+}
+
+impl<H: Digest> DrawStream<H> {
+    fn new<P: AsRef<[u8]>>(round: &Round<H>, participants: &[P]) -> Self {
+        let mut hasher = H::new();
+        hasher.update(b"lottery draw");
+        hasher.update(round.output());
+        hasher.update((participants.len() as u64).to_be_bytes());
+        for participant in participants {
+            hasher.update((participant.as_ref().len() as u64).to_be_bytes());
+            hasher.update(participant.as_ref());
+        }
+        DrawStream { seed: hasher.finalize(), counter: 0 }
+    }
+
+    fn next_u64(&mut self) -> u64 {
+        let block = H::new().chain_update(&self.seed).chain_update(self.counter.to_be_bytes()).finalize();
+        self.counter += 1;
+        u64::from_be_bytes(block[..8].try_into().expect("digests are at least 8 bytes"))
+    }
+
+    /// A uniform number below `bound`, by rejection so there is no modulo bias.
+    fn below(&mut self, bound: u64) -> u64 {
+        let zone = u64::MAX - u64::MAX % bound;
+        loop {
+            let x = self.next_u64();
+            if x < zone {
+                return x % bound;
+            }
+        }
+    }
+}
+
+/// The index of the winner among `participants`, or `None` if there are none. This is the
+/// first place in the [`shuffle`].
+pub fn winner<H: Digest, P: AsRef<[u8]>>(round: &Round<H>, participants: &[P]) -> Option<usize> {
+    shuffle(round, participants).first().copied()
+}
+
+/// A permutation of the indices of `participants`, e.g. a ranking of all of them.
+pub fn shuffle<H: Digest, P: AsRef<[u8]>>(round: &Round<H>, participants: &[P]) -> Vec<usize> {
+    let mut stream = DrawStream::new(round, participants);
+    let mut order: Vec<usize> = (0..participants.len()).collect();
+    for i in (1..order.len()).rev() {
+        let j = stream.below(i as u64 + 1) as usize;
+        order.swap(i, j);
+    }
+    order
+}
+
+/// Check a claimed result from the anchor, the published rounds and the participant list: the
+/// rounds must verify and include `round`, and `claimed` must be a prefix of the shuffle drawn
+/// from that round. A single winner is checked as a prefix of length one.
+pub fn audit<H, P>(anchor: GenericArray<u8, H::OutputSize>, published: &[Round<H>], round: u64, participants: &[P], claimed: &[usize]) -> Result<(), AuditError>
+where
+    H: Digest + FixedOutputReset,
+    P: AsRef<[u8]>,
+{
+    let mut verifier = BeaconVerifier::<H>::new(anchor);
+    for published in published {
+        verifier.verify(published).map_err(|_| AuditError::InvalidRound(published.number))?;
+    }
+    let draw_round = published.iter().find(|r| r.number == round).ok_or(AuditError::MissingRound(round))?;
+    if !shuffle(draw_round, participants).starts_with(claimed) {
+        return Err(AuditError::Mismatch);
+    }
+    Ok(())
+}
+
+#[test]
+fn test_lottery_draw_and_audit() {
+    use crate::beacon::Beacon;
+    use crate::HashChain;
+    use sha2::Sha256;
+
+    let mut beacon = Beacon::new(HashChain::<Sha256>::new(8, 5).unwrap());
+    let anchor = *beacon.anchor();
+    let participants = ["alice", "bob", "carol", "dave", "erin"];
+    let rounds: Vec<_> = std::iter::from_fn(|| beacon.publish()).take(3).collect();
+
+    let won = winner(&rounds[2], &participants).unwrap();
+    let mut order = shuffle(&rounds[2], &participants);
+    assert_eq!(order[0], won);
+    assert_eq!(order.iter().copied().fold(0, |acc, i| acc | 1 << i), 0b11111);
+    assert_ne!(winner(&rounds[2], &participants[..4]), None);
+
+    assert_eq!(audit::<Sha256, _>(anchor, &rounds, 3, &participants, &[won]), Ok(()));
+    assert_eq!(audit::<Sha256, _>(anchor, &rounds, 3, &participants, &order), Ok(()));
+    assert_eq!(audit::<Sha256, _>(anchor, &rounds, 4, &participants, &order), Err(AuditError::MissingRound(4)));
+    order.swap(0, 1);
+    assert_eq!(audit::<Sha256, _>(anchor, &rounds, 3, &participants, &order), Err(AuditError::Mismatch));
+}
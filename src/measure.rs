@@ -0,0 +1,272 @@
+//! Measuring what a traversal costs on the hardware at hand.
+//!
+//! [`traverse_cost`] sets up a real chain and discloses values from it, recording for every
+//! disclosure the hash evaluations it took and the wall time it took, and sums both up in a
+//! [`CostReport`]. The hash counts depend only on the chain length, so they are the same on every
+//! machine and bound what the times can look like: at most `log2(length)` hashes a disclosure,
+//! and about half that on average. The times include everything a disclosure does, so they also
+//! show what the pebble bookkeeping costs next to the hashing.
+//!
+//! In production [`Measured`] wraps a chain and records the same two costs of every disclosure
+//! into [`Histogram`]s that can be queried at any time, so a latency-sensitive discloser, e.g. a
+//! TESLA broadcaster, can watch its tail latencies. A histogram keeps counts in buckets of
+//! relative width at most 1/8 instead of the samples, HDR style: values below 16 exactly, larger
+//! ones to three significant bits, in 4 KB whatever the number of samples.
+
+use crate::value::ChainValue;
+use crate::{ChainInitError, HashChain};
+use digest::{Digest, FixedOutputReset};
+use std::fmt::{self, Display};
+use std::time::{Duration, Instant};
+
+/// The chain to measure.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TraversalParams {
+    /// A power of two.
+    pub length: usize,
+    pub seed: u64,
+}
+
+/// The distribution of a per-disclosure cost.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Distribution {
+    pub mean: f64,
+    pub max: u64,
+    pub p50: u64,
+    pub p90: u64,
+    pub p99: u64,
+}
+
+impl Distribution {
+    /// Nearest-rank percentiles of `samples`, all zero if there are none.
+    fn of(mut samples: Vec<u64>) -> Self {
+        if samples.is_empty() {
+            return Distribution { mean: 0.0, max: 0, p50: 0, p90: 0, p99: 0 };
+        }
+        samples.sort_unstable();
+        let percentile = |p: usize| samples[(samples.len() * p).div_ceil(100).max(1) - 1];
+        Distribution {
+            mean: samples.iter().sum::<u64>() as f64 / samples.len() as f64,
+            max: *samples.last().expect("not empty"),
+            p50: percentile(50),
+            p90: percentile(90),
+            p99: percentile(99),
+        }
+    }
+}
+
+/// Buckets below 16 hold one value each, every power of two above is split into 8.
+const EXACT: u64 = 16;
+const BUCKETS: usize = 16 + 60 * 8;
+
+fn bucket(value: u64) -> usize {
+    if value < EXACT {
+        return value as usize;
+    }
+    let magnitude = 63 - value.leading_zeros();
+    16 + (magnitude as usize - 4) * 8 + ((value >> (magnitude - 3)) - 8) as usize
+}
+
+/// The largest value in `bucket`.
+fn bucket_high(bucket: usize) -> u64 {
+    if bucket < 16 {
+        return bucket as u64;
+    }
+    let (magnitude, sub) = ((bucket - 16) / 8 + 4, (bucket - 16) % 8 + 8);
+    ((sub as u64 + 1) << (magnitude - 3)).wrapping_sub(1)
+}
+
+/// Counts of recorded values in buckets of bounded relative width.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Histogram {
+    counts: Vec<u64>,
+    count: u64,
+    sum: u128,
+    max: u64,
+}
+
+impl Default for Histogram {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Histogram {
+    pub fn new() -> Self {
+        Histogram { counts: vec![0; BUCKETS], count: 0, sum: 0, max: 0 }
+    }
+
+    pub fn record(&mut self, value: u64) {
+        self.counts[bucket(value)] += 1;
+        self.count += 1;
+        self.sum += value as u128;
+        self.max = self.max.max(value);
+    }
+
+    pub fn count(&self) -> u64 {
+        self.count
+    }
+
+    /// The exact largest value recorded, 0 if there is none.
+    pub fn max(&self) -> u64 {
+        self.max
+    }
+
+    /// The exact mean of the recorded values, 0 if there are none.
+    pub fn mean(&self) -> f64 {
+        if self.count == 0 { 0.0 } else { self.sum as f64 / self.count as f64 }
+    }
+
+    /// The value `percent` percent of the recorded ones are at or below, as the upper end of its
+    /// bucket but at most the largest value, 0 if there are none.
+    pub fn percentile(&self, percent: f64) -> u64 {
+        let rank = ((self.count as f64 * percent / 100.0).ceil() as u64).clamp(1, self.count.max(1));
+        let mut seen = 0;
+        for (bucket, &count) in self.counts.iter().enumerate() {
+            seen += count;
+            if seen >= rank {
+                return bucket_high(bucket).min(self.max);
+            }
+        }
+        0
+    }
+
+    pub fn distribution(&self) -> Distribution {
+        Distribution { mean: self.mean(), max: self.max, p50: self.percentile(50.0), p90: self.percentile(90.0), p99: self.percentile(99.0) }
+    }
+
+    pub fn reset(&mut self) {
+        *self = Self::new();
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct CostReport {
+    pub params: TraversalParams,
+    /// The disclosures measured.
+    pub steps: u64,
+    /// The wall time of setting the chain up, `length` hashes.
+    pub setup: Duration,
+    /// Hash evaluations per disclosure.
+    pub hashes: Distribution,
+    /// Wall time per disclosure, in nanoseconds.
+    pub nanos: Distribution,
+}
+
+impl Display for CostReport {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "length {}, {} disclosures, setup {:?}", self.params.length, self.steps, self.setup)?;
+        for (name, cost) in [("hashes", &self.hashes), ("ns", &self.nanos)] {
+            writeln!(f, "{:>6}: mean {:.1}, p50 {}, p90 {}, p99 {}, max {}", name, cost.mean, cost.p50, cost.p90, cost.p99, cost.max)?;
+        }
+        Ok(())
+    }
+}
+
+/// Set up the chain `params` describes and measure its first `steps` disclosures, or all of
+/// them if it has fewer.
+pub fn traverse_cost<H: Digest + FixedOutputReset>(params: TraversalParams, steps: u64) -> Result<CostReport, ChainInitError> {
+    let start = Instant::now();
+    let mut chain = HashChain::<H>::new(params.length, params.seed)?;
+    let setup = start.elapsed();
+    let steps = steps.min(chain.remaining());
+    let (mut hashes, mut nanos) = (Vec::with_capacity(steps as usize), Vec::with_capacity(steps as usize));
+    for _ in 0..steps {
+        hashes.push(chain.step_cost());
+        let start = Instant::now();
+        std::hint::black_box(chain.disclose());
+        nanos.push(start.elapsed().as_nanos() as u64);
+    }
+    Ok(CostReport { params, steps, setup, hashes: Distribution::of(hashes), nanos: Distribution::of(nanos) })
+}
+
+/// A chain recording the hashes and wall time of every disclosure.
+pub struct Measured<H: Digest + FixedOutputReset> {
+    chain: HashChain<H>,
+    hashes: Histogram,
+    nanos: Histogram,
+}
+
+impl<H: Digest + FixedOutputReset> Measured<H> {
+    pub fn new(chain: HashChain<H>) -> Self {
+        Measured { chain, hashes: Histogram::new(), nanos: Histogram::new() }
+    }
+
+    /// Disclose the next value, recording its cost, or `None` once exhausted.
+    pub fn disclose(&mut self) -> Option<(u64, ChainValue<H>)> {
+        let hashes = self.chain.step_cost();
+        let start = Instant::now();
+        let disclosed = self.chain.disclose()?;
+        self.nanos.record(start.elapsed().as_nanos() as u64);
+        self.hashes.record(hashes);
+        Some(disclosed)
+    }
+
+    /// Hash evaluations per disclosure.
+    pub fn hashes(&self) -> &Histogram {
+        &self.hashes
+    }
+
+    /// Wall time per disclosure, in nanoseconds.
+    pub fn nanos(&self) -> &Histogram {
+        &self.nanos
+    }
+
+    /// Start both histograms over, e.g. after each reporting interval.
+    pub fn reset(&mut self) {
+        self.hashes.reset();
+        self.nanos.reset();
+    }
+
+    pub fn chain(&self) -> &HashChain<H> {
+        &self.chain
+    }
+
+    pub fn into_inner(self) -> HashChain<H> {
+        self.chain
+    }
+}
+
+impl<H: Digest + FixedOutputReset> Iterator for Measured<H> {
+    type Item = (u64, ChainValue<H>);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.disclose()
+    }
+}
+
+#[test]
+fn test_histogram() {
+    let mut histogram = Histogram::new();
+    assert_eq!((histogram.percentile(99.0), histogram.mean()), (0, 0.0));
+    for value in 1..=1000 {
+        histogram.record(value);
+    }
+    assert_eq!((histogram.count(), histogram.max(), histogram.mean()), (1000, 1000, 500.5));
+    assert_eq!(histogram.percentile(1.0), 10);
+    // 500 lands in the bucket 480..=511
+    assert_eq!(histogram.percentile(50.0), 511);
+    assert_eq!(histogram.percentile(100.0), 1000);
+    histogram.record(u64::MAX);
+    assert_eq!(histogram.percentile(100.0), u64::MAX);
+    assert!((0..BUCKETS).all(|b| bucket(bucket_high(b)) == b));
+}
+
+#[test]
+fn test_traverse_cost() {
+    use sha2::Sha256;
+
+    let report = traverse_cost::<Sha256>(TraversalParams { length: 1024, seed: 1 }, 4096).unwrap();
+    assert_eq!(report.steps, 1024);
+    assert!(report.hashes.max <= 10 && report.hashes.p50 <= report.hashes.p90);
+    assert!(report.hashes.mean > 1.0 && report.hashes.mean <= 5.0);
+    assert!(report.to_string().starts_with("length 1024, 1024 disclosures"));
+    assert!(traverse_cost::<Sha256>(TraversalParams { length: 1000, seed: 1 }, 1).is_err());
+
+    let mut measured = Measured::new(HashChain::<Sha256>::new(1024, 1).unwrap());
+    assert_eq!(measured.by_ref().count(), 1024);
+    assert_eq!(measured.hashes().distribution(), report.hashes);
+    assert_eq!(measured.nanos().count(), 1024);
+    measured.reset();
+    assert_eq!(measured.hashes().count(), 0);
+}
@@ -0,0 +1,602 @@
+//! Merkle trees over hash outputs, with leaves and inner nodes hashed under distinct prefixes
+//! so that an inner node can never pass for a leaf.
+
+use crate::{hash_forward, ChainInitError, HashChain};
+use digest::generic_array::GenericArray;
+use digest::{Digest, FixedOutputReset, OutputSizeUser};
+use alloc::string::{String, ToString};
+use alloc::vec;
+use alloc::vec::Vec;
+use core::error::Error;
+use core::fmt::{self, Display};
+
+#[derive(Debug, Clone)]
+pub struct PathFormatError {
+    pub(crate) details: String,
+}
+
+impl PathFormatError {
+    fn new(error_message: &str) -> PathFormatError {
+        PathFormatError { details: error_message.to_string() }
+    }
+}
+
+impl Display for PathFormatError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.details)
+    }
+}
+
+impl Error for PathFormatError {}
+
+/// The leaf hash of `data`.
+pub fn leaf<H: Digest>(data: &[u8]) -> GenericArray<u8, H::OutputSize> {
+    H::new_with_prefix([0u8]).chain_update(data).finalize()
+}
+
+/// The parent of two nodes.
+pub fn node<H: Digest>(left: &[u8], right: &[u8]) -> GenericArray<u8, H::OutputSize> {
+    H::new_with_prefix([1u8]).chain_update(left).chain_update(right).finalize()
+}
+
+/// All levels of the tree over `leaves`, from the leaves up to the root. The number of leaves
+/// must be a power of two.
+pub(crate) fn levels<H: Digest>(leaves: &[GenericArray<u8, H::OutputSize>]) -> Vec<Vec<GenericArray<u8, H::OutputSize>>> {
+    assert!(leaves.len().is_power_of_two(), "number of leaves must be a power of two");
+    let mut levels = vec![leaves.to_vec()];
+    while levels.last().expect("at least one level").len() > 1 {
+        let next = levels.last().expect("at least one level").chunks(2).map(|pair| node::<H>(&pair[0], &pair[1])).collect();
+        levels.push(next);
+    }
+    levels
+}
+
+/// The root of the tree over `leaves`, whose number must be a power of two.
+pub fn root<H: Digest>(leaves: &[GenericArray<u8, H::OutputSize>]) -> GenericArray<u8, H::OutputSize> {
+    assert!(leaves.len().is_power_of_two(), "number of leaves must be a power of two");
+    TreeHash::<H>::from_leaves(leaves.len().trailing_zeros(), leaves.iter().cloned()).expect("leaf count matches the height")
+}
+
+/// The treehash algorithm: computes the root of a tree of height `height` from its leaves,
+/// given one at a time from left to right, keeping at most `height + 1` nodes.
+pub struct TreeHash<H: OutputSizeUser> {
+    height: u32,
+    leaves: u64,
+    /// The nodes still waiting for a right sibling, with their heights, lowest last.
+    stack: Vec<(u32, GenericArray<u8, H::OutputSize>)>,
+}
+
+impl<H: Digest> TreeHash<H> {
+    pub fn new(height: u32) -> Self {
+        TreeHash { height, leaves: 0, stack: Vec::with_capacity(height as usize + 1) }
+    }
+
+    /// The root over `leaves`, or `None` unless there are exactly `2^height` of them.
+    pub fn from_leaves<I: IntoIterator<Item = GenericArray<u8, H::OutputSize>>>(height: u32, leaves: I) -> Option<GenericArray<u8, H::OutputSize>> {
+        let mut treehash = Self::new(height);
+        for leaf in leaves {
+            if treehash.is_complete() {
+                return None;
+            }
+            treehash.push(leaf);
+        }
+        treehash.root().cloned()
+    }
+
+    pub fn height(&self) -> u32 {
+        self.height
+    }
+
+    /// The number of leaves pushed so far.
+    pub fn leaves(&self) -> u64 {
+        self.leaves
+    }
+
+    pub fn is_complete(&self) -> bool {
+        self.leaves == 1 << self.height
+    }
+
+    /// The height of the lowest node on the stack, `None` if it is empty or the root is done.
+    pub fn lowest_height(&self) -> Option<u32> {
+        if self.is_complete() {
+            return None;
+        }
+        self.stack.last().map(|(height, _)| *height)
+    }
+
+    /// Push the next leaf. Panics if the tree is already complete.
+    pub fn push(&mut self, leaf: GenericArray<u8, H::OutputSize>) {
+        self.push_observed(leaf, |_, _, _| {});
+    }
+
+    /// Push the next leaf, calling `observe(height, index, node)` for the leaf and for every
+    /// node it completes, `index` counting the nodes of that height from the left.
+    pub fn push_observed<F: FnMut(u32, u64, &GenericArray<u8, H::OutputSize>)>(&mut self, leaf: GenericArray<u8, H::OutputSize>, mut observe: F) {
+        assert!(!self.is_complete(), "tree already complete");
+        let index = self.leaves;
+        self.leaves += 1;
+        observe(0, index, &leaf);
+        let (mut height, mut current) = (0, leaf);
+        while let Some((top, _)) = self.stack.last() {
+            if *top != height {
+                break;
+            }
+            let (_, left) = self.stack.pop().expect("checked above");
+            current = node::<H>(&left, &current);
+            height += 1;
+            observe(height, index >> height, &current);
+        }
+        self.stack.push((height, current));
+    }
+
+    /// The root, once all leaves have been pushed.
+    pub fn root(&self) -> Option<&GenericArray<u8, H::OutputSize>> {
+        if !self.is_complete() {
+            return None;
+        }
+        self.stack.last().map(|(_, root)| root)
+    }
+}
+
+/// The siblings on the way from a leaf up to the root.
+pub struct AuthPath<H: OutputSizeUser> {
+    pub index: u64,
+    pub siblings: Vec<GenericArray<u8, H::OutputSize>>,
+}
+
+impl<H: OutputSizeUser> Clone for AuthPath<H> {
+    fn clone(&self) -> Self {
+        AuthPath { index: self.index, siblings: self.siblings.clone() }
+    }
+}
+
+/// The authentication path of leaf `index` in the tree over `leaves`.
+pub fn auth_path<H: Digest>(leaves: &[GenericArray<u8, H::OutputSize>], index: u64) -> AuthPath<H> {
+    path_in(&levels::<H>(leaves), index)
+}
+
+/// The authentication path of leaf `index`, read off precomputed [`levels`].
+pub(crate) fn path_in<H: OutputSizeUser>(levels: &[Vec<GenericArray<u8, H::OutputSize>>], index: u64) -> AuthPath<H> {
+    let siblings = levels[..levels.len() - 1].iter().enumerate().map(|(height, level)| level[((index >> height) ^ 1) as usize].clone()).collect();
+    AuthPath { index, siblings }
+}
+
+impl<H: OutputSizeUser> AuthPath<H> {
+    /// The tree height the path is for.
+    pub fn height(&self) -> u32 {
+        self.siblings.len() as u32
+    }
+
+    /// Serialize as the leaf index (u64 big-endian), the number of siblings (u8), and the
+    /// siblings from the leaf upwards.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = self.index.to_be_bytes().to_vec();
+        bytes.push(self.siblings.len() as u8);
+        for sibling in &self.siblings {
+            bytes.extend_from_slice(sibling);
+        }
+        bytes
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, PathFormatError> {
+        let n = H::output_size();
+        let (Some(index), Some(&count)) = (bytes.get(..8), bytes.get(8)) else {
+            return Err(PathFormatError::new("authentication path truncated"));
+        };
+        let index = u64::from_be_bytes(index.try_into().expect("8 bytes"));
+        if bytes.len() != 9 + count as usize * n {
+            return Err(PathFormatError::new("wrong length for an authentication path"));
+        }
+        if count < 64 && index >> count != 0 {
+            return Err(PathFormatError::new("leaf index outside the tree"));
+        }
+        let siblings = bytes[9..].chunks(n).map(GenericArray::clone_from_slice).collect();
+        Ok(AuthPath { index, siblings })
+    }
+}
+
+impl<H: Digest> AuthPath<H> {
+    /// Check that `leaf` sits at this path's index in the tree with root `root`.
+    pub fn verify(&self, root: &GenericArray<u8, H::OutputSize>, leaf: &GenericArray<u8, H::OutputSize>) -> bool {
+        (self.height() >= 64 || self.index >> self.height() == 0) && self.root(leaf) == *root
+    }
+
+    /// The root reached from `leaf` along this path.
+    pub fn root(&self, leaf: &GenericArray<u8, H::OutputSize>) -> GenericArray<u8, H::OutputSize> {
+        self.siblings.iter().enumerate().fold(leaf.clone(), |current, (height, sibling)| {
+            if (self.index >> height) & 1 == 0 {
+                node::<H>(&current, sibling)
+            } else {
+                node::<H>(sibling, &current)
+            }
+        })
+    }
+}
+
+/// A treehash instance computing the node of height `treehash.height()` whose leftmost leaf is
+/// `start`.
+struct Pending<H: OutputSizeUser> {
+    start: u64,
+    treehash: TreeHash<H>,
+}
+
+/// Szydlo's Merkle tree traversal: produces the authentication paths of all leaves in order,
+/// storing about `3 * height` nodes and computing `2 * height - 1` leaves per path.
+///
+/// For every height the traverser keeps the current authentication node and a treehash
+/// instance building the next one. After each path it spends its budget of leaf computations
+/// on the instance whose lowest node is lowest, which is enough to finish every node in time.
+pub struct Traverser<H: OutputSizeUser, F> {
+    height: u32,
+    leaf: F,
+    next: u64,
+    auth: Vec<GenericArray<u8, H::OutputSize>>,
+    pending: Vec<Option<Pending<H>>>,
+    root: GenericArray<u8, H::OutputSize>,
+}
+
+impl<H: Digest, F: FnMut(u64) -> GenericArray<u8, H::OutputSize>> Traverser<H, F> {
+    /// Set up the traversal of the tree of height `height` whose leaf `i` is `leaf(i)`. This
+    /// computes every leaf once. A tree of height 0 is its one leaf, with an empty path.
+    pub fn new(height: u32, mut leaf: F) -> Self {
+        let mut auth = vec![GenericArray::default(); height as usize];
+        let mut first = vec![GenericArray::default(); height as usize];
+        let mut treehash = TreeHash::<H>::new(height);
+        for i in 0..1u64 << height {
+            treehash.push_observed(leaf(i), |h, index, node| {
+                match (h < height, index) {
+                    (true, 0) => first[h as usize] = node.clone(),
+                    (true, 1) => auth[h as usize] = node.clone(),
+                    _ => {}
+                }
+            });
+        }
+        let root = treehash.root().expect("all leaves pushed").clone();
+        // the first node needed at every height is the leftmost, which is already done
+        let pending = first.into_iter().enumerate().map(|(h, node)| Some(Pending { start: 0, treehash: TreeHash { height: h as u32, leaves: 1 << h, stack: vec![(h as u32, node)] } })).collect();
+        Traverser { height, leaf, next: 0, auth, pending, root }
+    }
+
+    pub fn root(&self) -> &GenericArray<u8, H::OutputSize> {
+        &self.root
+    }
+
+    /// The leaf whose path comes next.
+    pub fn position(&self) -> u64 {
+        self.next
+    }
+
+    fn refresh(&mut self, leaf: u64) {
+        for h in 0..self.height {
+            if !(leaf + 1).is_multiple_of(1 << h) {
+                continue;
+            }
+            if let Some(done) = self.pending[h as usize].take() {
+                self.auth[h as usize] = done.treehash.root().expect("treehash finished in time").clone();
+            }
+            let start = (leaf + 1 + (1 << h)) ^ (1 << h);
+            self.pending[h as usize] = (start < 1 << self.height).then(|| Pending { start, treehash: TreeHash::new(h) });
+        }
+    }
+
+    fn build(&mut self) {
+        for _ in 0..(2 * self.height).saturating_sub(1) {
+            let lowest = self.pending.iter().enumerate().filter_map(|(h, pending)| {
+                let pending = pending.as_ref()?;
+                (!pending.treehash.is_complete()).then(|| (pending.treehash.lowest_height().unwrap_or(h as u32), h))
+            });
+            let Some((_, focus)) = lowest.min() else { return };
+            let pending = self.pending[focus].as_mut().expect("selected above");
+            let leaf = (self.leaf)(pending.start + pending.treehash.leaves());
+            pending.treehash.push(leaf);
+        }
+    }
+}
+
+impl<H: Digest, F: FnMut(u64) -> GenericArray<u8, H::OutputSize>> Iterator for Traverser<H, F> {
+    type Item = AuthPath<H>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.next >= 1 << self.height {
+            return None;
+        }
+        let path = AuthPath { index: self.next, siblings: self.auth.clone() };
+        self.refresh(self.next);
+        self.build();
+        self.next += 1;
+        Some(path)
+    }
+}
+
+/// A Merkle traversal strategy: an iterator over the authentication paths of all leaves in
+/// order, as produced by [`Traverser`] or [`BdsTraverser`].
+pub trait TreeTraversal<H: OutputSizeUser>: Iterator<Item = AuthPath<H>> {
+    fn root(&self) -> &GenericArray<u8, H::OutputSize>;
+
+    /// The leaf whose path comes next.
+    fn position(&self) -> u64;
+}
+
+impl<H: Digest, F: FnMut(u64) -> GenericArray<u8, H::OutputSize>> TreeTraversal<H> for Traverser<H, F> {
+    fn root(&self) -> &GenericArray<u8, H::OutputSize> {
+        Traverser::root(self)
+    }
+
+    fn position(&self) -> u64 {
+        Traverser::position(self)
+    }
+}
+
+/// The traversal of Buchmann, Dahmen and Schneider, as used by XMSS. Nodes on the top `k`
+/// levels that will be needed again are kept from setup, which saves leaf computations at the
+/// price of about `2^k` stored nodes; the levels below are built by treehash instances with a
+/// budget of `(height - k) / 2` leaf computations per path.
+pub struct BdsTraverser<H: OutputSizeUser, F> {
+    height: u32,
+    k: u32,
+    leaf: F,
+    next: u64,
+    auth: Vec<GenericArray<u8, H::OutputSize>>,
+    keep: Vec<GenericArray<u8, H::OutputSize>>,
+    treehash: Vec<Pending<H>>,
+    /// The right nodes with index 3, 5, ... on every level from `height - k` to `height - 2`,
+    /// highest level first.
+    retain: Vec<GenericArray<u8, H::OutputSize>>,
+    root: GenericArray<u8, H::OutputSize>,
+}
+
+impl<H: Digest, F: FnMut(u64) -> GenericArray<u8, H::OutputSize>> BdsTraverser<H, F> {
+    /// Set up the traversal of the tree of height `height` whose leaf `i` is `leaf(i)`, keeping
+    /// the top `k` levels. `height - k` must be even. This computes every leaf once.
+    pub fn new(height: u32, k: u32, mut leaf: F) -> Option<Self> {
+        if height == 0 || k > height || !(height - k).is_multiple_of(2) {
+            return None;
+        }
+        let low = height - k;
+        let mut auth = vec![GenericArray::default(); height as usize];
+        let mut third = vec![GenericArray::default(); low as usize];
+        let mut retain = vec![GenericArray::default(); (1usize << k).saturating_sub(k as usize + 1)];
+        let mut treehash = TreeHash::<H>::new(height);
+        for i in 0..1u64 << height {
+            treehash.push_observed(leaf(i), |h, index, node| {
+                if h == height {
+                    return;
+                }
+                if index == 1 {
+                    auth[h as usize] = node.clone();
+                } else if h < low && index == 3 {
+                    third[h as usize] = node.clone();
+                } else if h >= low && index >= 3 && index % 2 == 1 {
+                    let offset = (1usize << (height - 1 - h)) + h as usize - height as usize;
+                    retain[offset + ((index as usize - 3) >> 1)] = node.clone();
+                }
+            });
+        }
+        let root = treehash.root().expect("all leaves pushed").clone();
+        let treehash = third.into_iter().enumerate().map(|(h, node)| Pending { start: 3 << h, treehash: TreeHash { height: h as u32, leaves: 1 << h, stack: vec![(h as u32, node)] } }).collect();
+        let keep = vec![GenericArray::default(); height as usize / 2 + 1];
+        Some(BdsTraverser { height, k, leaf, next: 0, auth, keep, treehash, retain, root })
+    }
+
+    /// Prepare the path of the leaf after `leaf`.
+    fn round(&mut self, leaf: u64) {
+        let height = self.height;
+        let tau = (0..height).find(|i| (leaf >> i) & 1 == 0).unwrap_or(height);
+        let parent_inputs = (tau > 0).then(|| (self.auth[tau as usize - 1].clone(), self.keep[(tau as usize - 1) >> 1].clone()));
+        if (leaf >> (tau + 1)) & 1 == 0 && tau + 1 < height {
+            self.keep[tau as usize >> 1] = self.auth[tau as usize].clone();
+        }
+        let Some((left, right)) = parent_inputs else {
+            self.auth[0] = (self.leaf)(leaf);
+            return;
+        };
+        self.auth[tau as usize] = node::<H>(&left, &right);
+        let low = height - self.k;
+        for i in 0..tau {
+            self.auth[i as usize] = if i < low {
+                self.treehash[i as usize].treehash.root().expect("treehash finished in time").clone()
+            } else {
+                let offset = (1usize << (height - 1 - i)) + i as usize - height as usize;
+                self.retain[offset + ((((leaf >> i) - 1) >> 1) as usize)].clone()
+            };
+        }
+        for i in 0..tau.min(low) {
+            let start = leaf + 1 + (3 << i);
+            if start < 1 << height {
+                self.treehash[i as usize] = Pending { start, treehash: TreeHash::new(i) };
+            }
+        }
+    }
+
+    fn update(&mut self) {
+        for _ in 0..(self.height - self.k) / 2 {
+            let lowest = self.treehash.iter().enumerate().filter(|(_, pending)| !pending.treehash.is_complete()).map(|(h, pending)| (pending.treehash.lowest_height().unwrap_or(h as u32), h));
+            let Some((_, focus)) = lowest.min() else { return };
+            let pending = &mut self.treehash[focus];
+            let leaf = (self.leaf)(pending.start + pending.treehash.leaves());
+            pending.treehash.push(leaf);
+        }
+    }
+}
+
+impl<H: Digest, F: FnMut(u64) -> GenericArray<u8, H::OutputSize>> Iterator for BdsTraverser<H, F> {
+    type Item = AuthPath<H>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.next >= 1 << self.height {
+            return None;
+        }
+        let path = AuthPath { index: self.next, siblings: self.auth.clone() };
+        if self.next + 1 < 1 << self.height {
+            self.round(self.next);
+            self.update();
+        }
+        self.next += 1;
+        Some(path)
+    }
+}
+
+impl<H: Digest, F: FnMut(u64) -> GenericArray<u8, H::OutputSize>> TreeTraversal<H> for BdsTraverser<H, F> {
+    fn root(&self) -> &GenericArray<u8, H::OutputSize> {
+        &self.root
+    }
+
+    fn position(&self) -> u64 {
+        self.next
+    }
+}
+
+/// The root of the Merkle tree over every value of the chain of `length` values from `seed`,
+/// computed in one pass with logarithmic memory. The leaves are in setup order: leaf `i` is the
+/// value at position `length - i`.
+pub fn chain_root<H: Digest + FixedOutputReset>(length: usize, seed: u64) -> Result<GenericArray<u8, H::OutputSize>, ChainInitError> {
+    HashChain::<H>::new_with_merkle_root(length, seed).map(|(_, root)| root)
+}
+
+/// Evidence that `value` is the chain value at `position`, checked against a [`chain_root`]
+/// without hashing down to the anchor.
+pub struct ChainProof<H: OutputSizeUser> {
+    pub position: u64,
+    pub value: GenericArray<u8, H::OutputSize>,
+    pub path: AuthPath<H>,
+}
+
+impl<H: Digest> ChainProof<H> {
+    /// Check the proof for a chain of `length` values with Merkle root `root`.
+    pub fn verify(&self, root: &GenericArray<u8, H::OutputSize>, length: u64) -> bool {
+        (1..=length).contains(&self.position) && self.path.index == length - self.position && self.path.height() == length.trailing_zeros() && self.path.verify(root, &leaf::<H>(&self.value))
+    }
+}
+
+/// Prove the value at `position` of the chain of `length` values from `seed`. This walks the
+/// whole chain again, but only keeps logarithmically many nodes.
+pub fn chain_proof<H: Digest + FixedOutputReset>(length: usize, seed: u64, position: u64) -> Option<ChainProof<H>> {
+    if !length.is_power_of_two() || !(1..=length as u64).contains(&position) {
+        return None;
+    }
+    let index = length as u64 - position;
+    let height = length.trailing_zeros();
+    let mut siblings = vec![GenericArray::default(); height as usize];
+    let mut tree = TreeHash::<H>::new(height);
+    let mut value = GenericArray::default();
+    let mut chain_value = H::new_with_prefix(seed.to_le_bytes()).finalize();
+    for i in 0..length as u64 {
+        if i == index {
+            value = chain_value.clone();
+        }
+        tree.push_observed(leaf::<H>(&chain_value), |h, node_index, node| {
+            if h < height && node_index == (index >> h) ^ 1 {
+                siblings[h as usize] = node.clone();
+            }
+        });
+        chain_value = hash_forward::<H>(&chain_value, 1);
+    }
+    Some(ChainProof { position, value, path: AuthPath { index, siblings } })
+}
+
+impl<H: OutputSizeUser> crate::version::Versioned for AuthPath<H> {
+    type Error = PathFormatError;
+
+    fn encode_body(&self, _version: u8) -> Vec<u8> {
+        self.to_bytes()
+    }
+
+    fn decode_body(_version: u8, body: &[u8]) -> Result<Self, PathFormatError> {
+        Self::from_bytes(body)
+    }
+}
+
+#[test]
+fn test_merkle_paths() {
+    use sha2::Sha256;
+
+    let leaves: Vec<_> = (0u8..8).map(|i| leaf::<Sha256>(&[i])).collect();
+    let root = root::<Sha256>(&leaves);
+    assert_eq!(root, node::<Sha256>(&node::<Sha256>(&node::<Sha256>(&leaves[0], &leaves[1]), &node::<Sha256>(&leaves[2], &leaves[3])), &node::<Sha256>(&node::<Sha256>(&leaves[4], &leaves[5]), &node::<Sha256>(&leaves[6], &leaves[7]))));
+    for index in 0..8 {
+        let path = auth_path::<Sha256>(&leaves, index);
+        assert_eq!(path.height(), 3);
+        assert!(path.verify(&root, &leaves[index as usize]));
+        assert!(!path.verify(&root, &leaves[(index as usize + 1) % 8]));
+
+        let bytes = path.to_bytes();
+        assert_eq!(bytes.len(), 9 + 3 * 32);
+        let decoded = AuthPath::<Sha256>::from_bytes(&bytes).unwrap();
+        assert_eq!((decoded.index, &decoded.siblings), (index, &path.siblings));
+        assert!(AuthPath::<Sha256>::from_bytes(&bytes[..bytes.len() - 1]).is_err());
+    }
+    let outside = AuthPath::<Sha256> { index: 8, siblings: auth_path::<Sha256>(&leaves, 0).siblings };
+    assert!(!outside.verify(&root, &leaves[0]));
+}
+
+#[test]
+fn test_treehash() {
+    use sha2::Sha256;
+
+    let leaves: Vec<_> = (0u8..16).map(|i| leaf::<Sha256>(&[i])).collect();
+    let levels = levels::<Sha256>(&leaves);
+    let mut treehash = TreeHash::<Sha256>::new(4);
+    let mut observed = 0;
+    for (i, leaf) in leaves.iter().enumerate() {
+        assert!(treehash.root().is_none());
+        treehash.push_observed(*leaf, |height, index, node| {
+            assert_eq!(*node, levels[height as usize][index as usize]);
+            observed += 1;
+        });
+        assert!(treehash.stack.len() <= 5, "after {} leaves", i);
+    }
+    assert_eq!(observed, 31);
+    assert_eq!(treehash.root(), Some(&levels[4][0]));
+    assert_eq!(TreeHash::<Sha256>::from_leaves(3, leaves.iter().copied()), None);
+}
+
+#[test]
+fn test_traverser_matches_paths() {
+    use sha2::Sha256;
+
+    for height in 0..=7 {
+        let leaves: Vec<_> = (0..1u32 << height).map(|i| leaf::<Sha256>(&i.to_be_bytes())).collect();
+        let mut traverser = Traverser::<Sha256, _>::new(height, |i| leaves[i as usize]);
+        assert_eq!(*traverser.root(), root::<Sha256>(&leaves));
+        for index in 0..1u64 << height {
+            let path = traverser.next().unwrap();
+            assert_eq!(path.siblings, auth_path::<Sha256>(&leaves, index).siblings, "height {} leaf {}", height, index);
+        }
+        assert!(traverser.next().is_none());
+    }
+}
+
+#[test]
+fn test_bds_traverser_matches_paths() {
+    use sha2::Sha256;
+
+    assert!(BdsTraverser::<Sha256, _>::new(5, 2, |_| GenericArray::default()).is_none());
+    for height in 1..=7u32 {
+        let leaves: Vec<_> = (0..1u32 << height).map(|i| leaf::<Sha256>(&i.to_be_bytes())).collect();
+        for k in (height % 2..=height).step_by(2) {
+            let mut traverser = BdsTraverser::<Sha256, _>::new(height, k, |i| leaves[i as usize]).unwrap();
+            assert_eq!(*traverser.root(), root::<Sha256>(&leaves));
+            for index in 0..1u64 << height {
+                let path = traverser.next().unwrap();
+                assert_eq!(path.siblings, auth_path::<Sha256>(&leaves, index).siblings, "height {} k {} leaf {}", height, k, index);
+            }
+            assert!(traverser.next().is_none());
+        }
+    }
+}
+
+#[test]
+fn test_chain_random_access_proofs() {
+    use sha2::Sha256;
+
+    let (chain, root) = HashChain::<Sha256>::new_with_merkle_root(16, 8).unwrap();
+    assert_eq!(chain_root::<Sha256>(16, 8).unwrap(), root);
+    let expected: Vec<_> = chain.collect();
+    for (position, value) in &expected {
+        let proof = chain_proof::<Sha256>(16, 8, *position).unwrap();
+        assert_eq!(*value, proof.value);
+        assert!(proof.verify(&root, 16));
+        assert!(!ChainProof::<Sha256> { position: position % 16 + 1, ..proof }.verify(&root, 16));
+    }
+    assert!(chain_proof::<Sha256>(16, 8, 17).is_none());
+}
@@ -0,0 +1,203 @@
+//! Prepaid-credit metering where every chain value is worth one unit.
+//!
+//! The issuer sells a chain and publishes its anchor. The client's [`Wallet`] pays `n` units by
+//! disclosing the value `n` positions past the previous payment, and the merchant's [`Meter`]
+//! credits as many units as it takes hashes to reach the previously redeemed value. Merchants
+//! need not settle every payment: [`Meter::redeem`] batches all unsettled units into a single
+//! token that the [`Issuer`] settles in one verification, and [`Meter::settled`] marks them
+//! redeemed once it has. An issuer settles at most [`DEFAULT_MAX_GAP`] units per token unless
+//! [`Issuer::with_max_gap`] allows more, so merchants redeem at least that often.
+
+use crate::registry::{ChainRecord, ChainToken, Registry, VerifyError, DEFAULT_MAX_GAP};
+use crate::store::StateStore;
+use crate::{hash_forward, ChainId, HashChain};
+use digest::generic_array::GenericArray;
+use digest::{Digest, FixedOutputReset};
+
+/// The client side: spends the values of a prepaid chain.
+pub struct Wallet<H: Digest + FixedOutputReset> {
+    chain_id: ChainId,
+    chain: HashChain<H>,
+}
+
+impl<H: Digest + FixedOutputReset> Wallet<H> {
+    pub fn new(chain_id: ChainId, chain: HashChain<H>) -> Self {
+        Wallet { chain_id, chain }
+    }
+
+    /// Pay `units` units, or return `None` (spending nothing) if the balance is insufficient.
+    pub fn pay(&mut self, units: u64) -> Option<ChainToken> {
+        if units == 0 || units > self.chain.remaining() {
+            return None;
+        }
+        let (index, value) = self.chain.by_ref().nth(units as usize - 1)?;
+        Some(ChainToken { chain_id: self.chain_id, index, value: value.to_vec() })
+    }
+
+    /// The number of units left.
+    pub fn balance(&self) -> u64 {
+        self.chain.remaining()
+    }
+}
+
+/// The merchant side: accepts payments on one chain and batches them for redemption.
+pub struct Meter<H: Digest + FixedOutputReset> {
+    chain_id: ChainId,
+    last: ChainRecord<H>,
+    redeemed_index: u64,
+    max_gap: u64,
+}
+
+impl<H: Digest + FixedOutputReset> Meter<H> {
+    /// Start metering a chain from its anchor.
+    pub fn new(chain_id: ChainId, anchor: GenericArray<u8, H::OutputSize>) -> Self {
+        Meter { chain_id, last: ChainRecord { index: 0, value: anchor, domain: None }, redeemed_index: 0, max_gap: DEFAULT_MAX_GAP }
+    }
+
+    /// Refuse payments of more than `max_gap` units at once instead of [`DEFAULT_MAX_GAP`]. The
+    /// units are counted by hashing, so the limit bounds the work a bogus payment costs.
+    pub fn with_max_gap(self, max_gap: u64) -> Self {
+        Meter { max_gap, ..self }
+    }
+
+    /// Verify a payment, returning the number of units it is worth.
+    pub fn accept(&mut self, payment: &ChainToken) -> Result<u64, VerifyError> {
+        if payment.chain_id != self.chain_id {
+            return Err(VerifyError::UnknownChain);
+        }
+        if payment.index <= self.last.index {
+            return Err(VerifyError::Replay);
+        }
+        let units = payment.index - self.last.index;
+        if units > self.max_gap {
+            return Err(VerifyError::GapTooLarge);
+        }
+        if payment.value.len() != self.last.value.len() {
+            return Err(VerifyError::Mismatch);
+        }
+        let value = GenericArray::clone_from_slice(&payment.value);
+        if hash_forward::<H>(&value, units) != self.last.value {
+            return Err(VerifyError::Mismatch);
+        }
+        self.last = ChainRecord { index: payment.index, value, domain: None };
+        Ok(units)
+    }
+
+    /// Units accepted but not yet settled.
+    pub fn pending_units(&self) -> u64 {
+        self.last.index - self.redeemed_index
+    }
+
+    /// Collect every unsettled unit into one token for the issuer, or `None` if there is nothing
+    /// to redeem. The units stay pending until [`Meter::settled`] confirms the settlement.
+    pub fn redeem(&self) -> Option<ChainToken> {
+        if self.pending_units() == 0 {
+            return None;
+        }
+        Some(ChainToken { chain_id: self.chain_id, index: self.last.index, value: self.last.value.to_vec() })
+    }
+
+    /// Mark the units of `redemption`, a token from [`Meter::redeem`], as settled once
+    /// [`Issuer::settle`] accepted it.
+    pub fn settled(&mut self, redemption: &ChainToken) {
+        if redemption.chain_id == self.chain_id && redemption.index <= self.last.index {
+            self.redeemed_index = self.redeemed_index.max(redemption.index);
+        }
+    }
+}
+
+/// The issuer side: knows every sold chain's anchor and settles merchant redemptions.
+pub struct Issuer<H, S> {
+    registry: Registry<H, S>,
+}
+
+impl<H, S> Issuer<H, S>
+where
+    H: Digest + FixedOutputReset,
+    S: StateStore<Key = ChainId, State = ChainRecord<H>>,
+{
+    pub fn new(store: S) -> Self {
+        Issuer { registry: Registry::new(store) }
+    }
+
+    /// Settle up to `max_gap` units per redemption instead of [`DEFAULT_MAX_GAP`], see
+    /// [`Registry::with_max_gap`].
+    pub fn with_max_gap(self, max_gap: u64) -> Self {
+        Issuer { registry: self.registry.with_max_gap(max_gap) }
+    }
+
+    /// Consult `policy` on how many units one redemption may cover, see
+    /// [`GapPolicy`](crate::registry::GapPolicy).
+    pub fn with_gap_policy(self, policy: crate::registry::GapPolicy) -> Self {
+        Issuer { registry: self.registry.with_gap_policy(policy) }
+    }
+
+    /// Record a sold chain by its anchor.
+    pub fn issue(&self, chain_id: ChainId, anchor: GenericArray<u8, H::OutputSize>) -> Result<(), VerifyError> {
+        self.registry.register(chain_id, anchor)
+    }
+
+    /// Settle a redemption, returning the number of units it covers. Each unit can be settled
+    /// only once.
+    pub fn settle(&self, redemption: &ChainToken) -> Result<u64, VerifyError> {
+        self.registry.advance(&redemption.chain_id, redemption.index, &redemption.value)
+    }
+}
+
+#[test]
+fn test_batched_redemption() {
+    use crate::store::MemoryStore;
+    use sha2::Sha256;
+
+    let id = ChainId([8; 16]);
+    let mut wallet = Wallet::new(id, HashChain::<Sha256>::new(16, 99).unwrap());
+    let issuer = Issuer::<Sha256, _>::new(MemoryStore::new());
+    issuer.issue(id, *wallet.chain.anchor()).unwrap();
+    let mut meter = Meter::<Sha256>::new(id, *wallet.chain.anchor());
+
+    assert_eq!(meter.accept(&wallet.pay(3).unwrap()), Ok(3));
+    let payment = wallet.pay(1).unwrap();
+    assert_eq!(meter.accept(&payment), Ok(1));
+    assert_eq!(meter.accept(&payment), Err(VerifyError::Replay));
+    let huge = ChainToken { index: u64::MAX, ..payment.clone() };
+    assert_eq!(meter.accept(&huge), Err(VerifyError::GapTooLarge));
+    assert_eq!(meter.pending_units(), 4);
+    assert!(wallet.pay(13).is_none());
+    assert_eq!(wallet.balance(), 12);
+
+    let redemption = meter.redeem().unwrap();
+    assert_eq!(meter.pending_units(), 4);
+    assert_eq!(issuer.settle(&redemption), Ok(4));
+    meter.settled(&redemption);
+    assert!(meter.redeem().is_none());
+    assert_eq!(issuer.settle(&redemption), Err(VerifyError::Replay));
+
+    assert_eq!(meter.accept(&wallet.pay(12).unwrap()), Ok(12));
+    assert_eq!(issuer.settle(&meter.redeem().unwrap()), Ok(12));
+}
+
+#[test]
+fn test_redemption_above_max_gap() {
+    use crate::store::MemoryStore;
+    use sha2::Sha256;
+
+    let id = ChainId([9; 16]);
+    let units = DEFAULT_MAX_GAP + 10;
+    let mut wallet = Wallet::new(id, HashChain::<Sha256>::new(2 * DEFAULT_MAX_GAP as usize, 5).unwrap());
+    let mut meter = Meter::<Sha256>::new(id, *wallet.chain.anchor());
+    assert_eq!(meter.accept(&wallet.pay(units / 2).unwrap()), Ok(units / 2));
+    assert_eq!(meter.accept(&wallet.pay(units - units / 2).unwrap()), Ok(units - units / 2));
+
+    // a default issuer refuses the batch, and the units stay pending
+    let strict = Issuer::<Sha256, _>::new(MemoryStore::new());
+    strict.issue(id, *wallet.chain.anchor()).unwrap();
+    let redemption = meter.redeem().unwrap();
+    assert_eq!(strict.settle(&redemption), Err(VerifyError::GapTooLarge));
+    assert_eq!(meter.pending_units(), units);
+
+    let issuer = Issuer::<Sha256, _>::new(MemoryStore::new()).with_max_gap(units);
+    issuer.issue(id, *wallet.chain.anchor()).unwrap();
+    assert_eq!(issuer.settle(&redemption), Ok(units));
+    meter.settled(&redemption);
+    assert_eq!(meter.pending_units(), 0);
+}
@@ -0,0 +1,195 @@
+//! A [`tower`](https://docs.rs/tower) layer guarding HTTP services with chain tokens.
+//!
+//! Each request must carry a [`ChainToken`] in a header (by default
+//! `Authorization: Bearer <chain id>.<index>.<value>`). The token is verified against a shared
+//! [`Registry`], which only accepts strictly increasing indices, so every request spends one
+//! chain value and replays are answered with `401 Unauthorized` without reaching the inner
+//! service. Accepted tokens are inserted into the request extensions as a [`VerifiedToken`].
+
+use crate::registry::{ChainRecord, ChainToken, Registry};
+use crate::store::StateStore;
+use crate::ChainId;
+use digest::{Digest, FixedOutputReset};
+use http::{header, HeaderName, Request, Response, StatusCode};
+use pin_project_lite::pin_project;
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::Arc;
+use std::task::{Context, Poll};
+use tower_layer::Layer;
+use tower_service::Service;
+
+/// The position of a token accepted by [`ChainTokenService`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct VerifiedToken {
+    pub chain_id: ChainId,
+    pub index: u64,
+}
+
+/// A [`Layer`] wrapping services in [`ChainTokenService`].
+pub struct ChainTokenLayer<H, S> {
+    registry: Arc<Registry<H, S>>,
+    header: HeaderName,
+}
+
+impl<H, S> ChainTokenLayer<H, S> {
+    pub fn new(registry: Arc<Registry<H, S>>) -> Self {
+        ChainTokenLayer { registry, header: header::AUTHORIZATION }
+    }
+
+    /// Read tokens from `header` instead of `Authorization`.
+    pub fn with_header(mut self, header: HeaderName) -> Self {
+        self.header = header;
+        self
+    }
+}
+
+impl<H, S> Clone for ChainTokenLayer<H, S> {
+    fn clone(&self) -> Self {
+        ChainTokenLayer { registry: self.registry.clone(), header: self.header.clone() }
+    }
+}
+
+impl<H, S, T> Layer<T> for ChainTokenLayer<H, S> {
+    type Service = ChainTokenService<H, S, T>;
+
+    fn layer(&self, inner: T) -> Self::Service {
+        ChainTokenService { inner, registry: self.registry.clone(), header: self.header.clone() }
+    }
+}
+
+/// Middleware verifying the chain token of every request before calling the inner service.
+pub struct ChainTokenService<H, S, T> {
+    inner: T,
+    registry: Arc<Registry<H, S>>,
+    header: HeaderName,
+}
+
+impl<H, S, T: Clone> Clone for ChainTokenService<H, S, T> {
+    fn clone(&self) -> Self {
+        ChainTokenService { inner: self.inner.clone(), registry: self.registry.clone(), header: self.header.clone() }
+    }
+}
+
+impl<H, S, T> ChainTokenService<H, S, T>
+where
+    H: Digest + FixedOutputReset,
+    S: StateStore<Key = ChainId, State = ChainRecord<H>>,
+{
+    fn authorize<B>(&self, request: &Request<B>) -> Option<VerifiedToken> {
+        let value = request.headers().get(&self.header)?.to_str().ok()?;
+        let value = value.strip_prefix("Bearer ").unwrap_or(value);
+        let token: ChainToken = value.parse().ok()?;
+        self.registry.verify_token(&token).ok()?;
+        Some(VerifiedToken { chain_id: token.chain_id, index: token.index })
+    }
+}
+
+impl<H, S, T, ReqBody, ResBody> Service<Request<ReqBody>> for ChainTokenService<H, S, T>
+where
+    H: Digest + FixedOutputReset,
+    S: StateStore<Key = ChainId, State = ChainRecord<H>>,
+    T: Service<Request<ReqBody>, Response = Response<ResBody>>,
+    ResBody: Default,
+{
+    type Response = Response<ResBody>;
+    type Error = T::Error;
+    type Future = ResponseFuture<T::Future, ResBody>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, mut request: Request<ReqBody>) -> Self::Future {
+        match self.authorize(&request) {
+            Some(verified) => {
+                request.extensions_mut().insert(verified);
+                ResponseFuture { state: ResponseState::Inner { future: self.inner.call(request) } }
+            }
+            None => {
+                let mut response = Response::new(ResBody::default());
+                *response.status_mut() = StatusCode::UNAUTHORIZED;
+                ResponseFuture { state: ResponseState::Rejected { response: Some(response) } }
+            }
+        }
+    }
+}
+
+pin_project! {
+    /// The response future of [`ChainTokenService`].
+    pub struct ResponseFuture<F, B> {
+        #[pin]
+        state: ResponseState<F, B>,
+    }
+}
+
+pin_project! {
+    #[project = ResponseStateProj]
+    enum ResponseState<F, B> {
+        Inner { #[pin] future: F },
+        Rejected { response: Option<Response<B>> },
+    }
+}
+
+impl<F, B, E> Future for ResponseFuture<F, B>
+where
+    F: Future<Output = Result<Response<B>, E>>,
+{
+    type Output = Result<Response<B>, E>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        match self.project().state.project() {
+            ResponseStateProj::Inner { future } => future.poll(cx),
+            ResponseStateProj::Rejected { response } => Poll::Ready(Ok(response.take().expect("polled after completion"))),
+        }
+    }
+}
+
+#[test]
+fn test_layer_rejects_missing_and_replayed_tokens() {
+    use crate::store::MemoryStore;
+    use crate::{create_hash_chain_nopebble, hash_forward};
+    use sha2::Sha256;
+    use std::convert::Infallible;
+    use std::future::Ready;
+
+    struct Echo;
+    impl Service<Request<()>> for Echo {
+        type Response = Response<String>;
+        type Error = Infallible;
+        type Future = Ready<Result<Response<String>, Infallible>>;
+        fn poll_ready(&mut self, _: &mut Context<'_>) -> Poll<Result<(), Infallible>> {
+            Poll::Ready(Ok(()))
+        }
+        fn call(&mut self, request: Request<()>) -> Self::Future {
+            let index = request.extensions().get::<VerifiedToken>().unwrap().index;
+            std::future::ready(Ok(Response::new(index.to_string())))
+        }
+    }
+
+    fn send(service: &mut ChainTokenService<Sha256, MemoryStore<ChainId, ChainRecord<Sha256>>, Echo>, token: Option<String>) -> Response<String> {
+        let mut request = Request::builder();
+        if let Some(token) = token {
+            request = request.header(header::AUTHORIZATION, format!("Bearer {}", token));
+        }
+        let future = service.call(request.body(()).unwrap());
+        let mut cx = Context::from_waker(std::task::Waker::noop());
+        match std::pin::pin!(future).poll(&mut cx) {
+            Poll::Ready(result) => result.unwrap(),
+            Poll::Pending => unreachable!(),
+        }
+    }
+
+    let chain = create_hash_chain_nopebble::<Sha256>(4, 3);
+    let id = ChainId([9; 16]);
+    let registry = Arc::new(Registry::<Sha256, _>::new(MemoryStore::new()));
+    registry.register(id, hash_forward::<Sha256>(&chain[3], 1)).unwrap();
+    let mut service = ChainTokenLayer::new(registry).layer(Echo);
+
+    let token = ChainToken { chain_id: id, index: 1, value: chain[3].to_vec() }.to_string();
+    let response = send(&mut service, Some(token.clone()));
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_eq!(response.body(), "1");
+    assert_eq!(send(&mut service, Some(token)).status(), StatusCode::UNAUTHORIZED);
+    assert_eq!(send(&mut service, None).status(), StatusCode::UNAUTHORIZED);
+}
@@ -0,0 +1,175 @@
+//! A Merkle Mountain Range: an append-only accumulator over disclosed values.
+//!
+//! The prover appends every value it discloses and publishes the root after each append. The
+//! leaves form a row of perfect trees, the mountains, of strictly decreasing height, one per set
+//! bit of the leaf count; the root commits to the count and the mountain peaks. An auditor
+//! who replays the disclosures into [`Peaks`] keeps only those peaks, one per mountain, and can
+//! check every published root, while [`MmrProof`]s show single values to be in the history
+//! without replaying it.
+
+use crate::merkle::{self, AuthPath};
+use digest::generic_array::GenericArray;
+use digest::{Digest, OutputSizeUser};
+
+fn bag<H: Digest>(leaves: u64, peaks: &[GenericArray<u8, H::OutputSize>]) -> GenericArray<u8, H::OutputSize> {
+    let mut hasher = H::new_with_prefix([2u8]);
+    hasher.update(leaves.to_be_bytes());
+    for peak in peaks {
+        hasher.update(peak);
+    }
+    hasher.finalize()
+}
+
+/// The mountains of a range with `leaves` leaves as `(height, first leaf)`, tallest first.
+fn mountains(leaves: u64) -> impl Iterator<Item = (u32, u64)> {
+    (0..u64::BITS).rev().filter(move |height| leaves >> height & 1 == 1).scan(0, |start, height| {
+        let first = *start;
+        *start += 1 << height;
+        Some((height, first))
+    })
+}
+
+/// The compact accumulator: only the peaks, enough to append and compute roots.
+pub struct Peaks<H: OutputSizeUser> {
+    leaves: u64,
+    /// `(height, node)`, tallest first.
+    peaks: Vec<(u32, GenericArray<u8, H::OutputSize>)>,
+}
+
+impl<H: Digest> Peaks<H> {
+    pub fn new() -> Self {
+        Peaks { leaves: 0, peaks: Vec::new() }
+    }
+
+    pub fn leaves(&self) -> u64 {
+        self.leaves
+    }
+
+    /// Append `data`, returning its leaf index.
+    pub fn append(&mut self, data: &[u8]) -> u64 {
+        let mut height = 0;
+        let mut current = merkle::leaf::<H>(data);
+        while let Some((_, left)) = self.peaks.pop_if(|(peak, _)| *peak == height) {
+            current = merkle::node::<H>(&left, &current);
+            height += 1;
+        }
+        self.peaks.push((height, current));
+        self.leaves += 1;
+        self.leaves - 1
+    }
+
+    pub fn root(&self) -> GenericArray<u8, H::OutputSize> {
+        bag::<H>(self.leaves, &self.peaks.iter().map(|(_, peak)| peak.clone()).collect::<Vec<_>>())
+    }
+}
+
+impl<H: Digest> Default for Peaks<H> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Shows one leaf to be in the range as it stood with `leaves` leaves.
+pub struct MmrProof<H: OutputSizeUser> {
+    pub leaves: u64,
+    /// The path from the leaf to the peak of its mountain.
+    pub path: AuthPath<H>,
+    /// The other mountains' peaks, tallest first.
+    pub peaks: Vec<GenericArray<u8, H::OutputSize>>,
+}
+
+impl<H: Digest> MmrProof<H> {
+    /// Check that `data` is the leaf at `self.path.index` under `root`.
+    pub fn verify(&self, root: &GenericArray<u8, H::OutputSize>, data: &[u8]) -> bool {
+        let index = self.path.index;
+        let Some(position) = mountains(self.leaves).position(|(height, first)| (first..first + (1 << height)).contains(&index)) else {
+            return false;
+        };
+        let (height, _) = mountains(self.leaves).nth(position).expect("mountain exists");
+        if self.path.height() != height || self.peaks.len() + 1 != self.leaves.count_ones() as usize {
+            return false;
+        }
+        let mut peaks = self.peaks.clone();
+        peaks.insert(position, self.path.root(&merkle::leaf::<H>(data)));
+        bag::<H>(self.leaves, &peaks) == *root
+    }
+}
+
+/// The full accumulator, which also keeps every node to prove inclusion.
+pub struct Mmr<H: OutputSizeUser> {
+    /// Level `h` holds the roots of the aligned perfect subtrees of `2^h` leaves, left to right.
+    levels: Vec<Vec<GenericArray<u8, H::OutputSize>>>,
+}
+
+impl<H: Digest> Mmr<H> {
+    pub fn new() -> Self {
+        Mmr { levels: vec![Vec::new()] }
+    }
+
+    pub fn leaves(&self) -> u64 {
+        self.levels[0].len() as u64
+    }
+
+    /// Append `data`, returning its leaf index.
+    pub fn append(&mut self, data: &[u8]) -> u64 {
+        self.levels[0].push(merkle::leaf::<H>(data));
+        let mut height = 0;
+        while self.levels[height].len().is_multiple_of(2) {
+            let level = &self.levels[height];
+            let parent = merkle::node::<H>(&level[level.len() - 2], &level[level.len() - 1]);
+            if self.levels.len() == height + 1 {
+                self.levels.push(Vec::new());
+            }
+            self.levels[height + 1].push(parent);
+            height += 1;
+        }
+        self.leaves() - 1
+    }
+
+    fn peaks(&self) -> Vec<GenericArray<u8, H::OutputSize>> {
+        let leaves = self.leaves();
+        mountains(leaves).map(|(height, _)| self.levels[height as usize][((leaves >> height) - 1) as usize].clone()).collect()
+    }
+
+    pub fn root(&self) -> GenericArray<u8, H::OutputSize> {
+        bag::<H>(self.leaves(), &self.peaks())
+    }
+
+    /// A proof for leaf `index` under the current root.
+    pub fn prove(&self, index: u64) -> Option<MmrProof<H>> {
+        let leaves = self.leaves();
+        let position = mountains(leaves).position(|(height, first)| (first..first + (1 << height)).contains(&index))?;
+        let (height, _) = mountains(leaves).nth(position).expect("mountain exists");
+        let siblings = (0..height as usize).map(|level| self.levels[level][((index >> level) ^ 1) as usize].clone()).collect();
+        let mut peaks = self.peaks();
+        peaks.remove(position);
+        Some(MmrProof { leaves, path: AuthPath { index, siblings }, peaks })
+    }
+}
+
+impl<H: Digest> Default for Mmr<H> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[test]
+fn test_mmr_roots_and_proofs() {
+    use sha2::Sha256;
+
+    let mut mmr = Mmr::<Sha256>::new();
+    let mut peaks = Peaks::<Sha256>::new();
+    assert_eq!(mmr.root(), peaks.root());
+    for n in 0..37u64 {
+        assert_eq!(mmr.append(&n.to_be_bytes()), n);
+        peaks.append(&n.to_be_bytes());
+        let root = mmr.root();
+        assert_eq!(root, peaks.root());
+        for i in 0..=n {
+            let proof = mmr.prove(i).unwrap();
+            assert!(proof.verify(&root, &i.to_be_bytes()));
+            assert!(!proof.verify(&root, &(i + 1).to_be_bytes()));
+        }
+        assert!(mmr.prove(n + 1).is_none());
+    }
+}
@@ -0,0 +1,117 @@
+//! Self-describing encodings of chain values and anchors, so a value exchanged between systems
+//! carries the identity of the hash that produced it.
+//!
+//! A [multihash](https://multiformats.io/multihash/) prefixes the value with the hash's
+//! multicodec code and the value's length; a [multibase](https://multiformats.io/multibase/)
+//! string prefixes its text encoding with a character naming the base, e.g.
+//! `z` for base58btc. Decoding checks both, so a SHA-512 anchor cannot be mistaken for a pair of
+//! SHA-256 values.
+
+use digest::generic_array::GenericArray;
+use digest::OutputSizeUser;
+pub use multibase::Base;
+use multihash::Multihash;
+use std::error::Error;
+use std::fmt::{self, Display};
+
+/// A hash with a multicodec code.
+pub trait HashCode: OutputSizeUser {
+    const CODE: u64;
+}
+
+impl HashCode for sha2::Sha224 {
+    const CODE: u64 = 0x1013;
+}
+
+impl HashCode for sha2::Sha256 {
+    const CODE: u64 = 0x12;
+}
+
+impl HashCode for sha2::Sha384 {
+    const CODE: u64 = 0x20;
+}
+
+impl HashCode for sha2::Sha512 {
+    const CODE: u64 = 0x13;
+}
+
+#[cfg(feature = "insecure-legacy")]
+impl HashCode for md4::Md4 {
+    const CODE: u64 = 0xd4;
+}
+
+#[cfg(feature = "insecure-legacy")]
+impl HashCode for md5::Md5 {
+    const CODE: u64 = 0xd5;
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum MultiformatError {
+    /// The input is not a valid multihash or multibase string.
+    Malformed(String),
+    /// The value was produced by a different hash.
+    WrongHash { expected: u64, found: u64 },
+    /// The digest has the wrong length for the hash.
+    WrongLength,
+}
+
+impl Display for MultiformatError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            MultiformatError::Malformed(details) => write!(f, "malformed encoding: {}", details),
+            MultiformatError::WrongHash { expected, found } => write!(f, "expected hash code {:#x}, found {:#x}", expected, found),
+            MultiformatError::WrongLength => write!(f, "digest has the wrong length"),
+        }
+    }
+}
+
+impl Error for MultiformatError {}
+
+/// The multihash of `value`: the code of `H`, the length and the value.
+pub fn to_multihash<H: HashCode>(value: &GenericArray<u8, H::OutputSize>) -> Vec<u8> {
+    Multihash::<64>::wrap(H::CODE, value).expect("digests are at most 64 bytes").to_bytes()
+}
+
+/// Parse a multihash, requiring it to be a full-length value of `H`.
+pub fn from_multihash<H: HashCode>(bytes: &[u8]) -> Result<GenericArray<u8, H::OutputSize>, MultiformatError> {
+    let multihash = Multihash::<64>::from_bytes(bytes).map_err(|e| MultiformatError::Malformed(e.to_string()))?;
+    if multihash.code() != H::CODE {
+        return Err(MultiformatError::WrongHash { expected: H::CODE, found: multihash.code() });
+    }
+    if multihash.digest().len() != H::output_size() {
+        return Err(MultiformatError::WrongLength);
+    }
+    Ok(GenericArray::clone_from_slice(multihash.digest()))
+}
+
+/// The multihash of `value` as a multibase string in `base`.
+pub fn to_multibase<H: HashCode>(value: &GenericArray<u8, H::OutputSize>, base: Base) -> String {
+    multibase::encode(base, to_multihash::<H>(value))
+}
+
+/// Parse a multibase string in any base holding a multihash of `H`.
+pub fn from_multibase<H: HashCode>(text: &str) -> Result<GenericArray<u8, H::OutputSize>, MultiformatError> {
+    let (_, bytes) = multibase::decode(text).map_err(|e| MultiformatError::Malformed(e.to_string()))?;
+    from_multihash::<H>(&bytes)
+}
+
+#[test]
+fn test_multiformat_roundtrip() {
+    use crate::HashChain;
+    use sha2::{Sha256, Sha512};
+
+    let chain = HashChain::<Sha256>::new(16, 2).unwrap();
+    let bytes = to_multihash::<Sha256>(chain.anchor());
+    assert_eq!(&bytes[..2], &[0x12, 32]);
+    assert_eq!(from_multihash::<Sha256>(&bytes).unwrap(), *chain.anchor());
+
+    let text = to_multibase::<Sha256>(chain.anchor(), Base::Base58Btc);
+    assert!(text.starts_with("zQm"));
+    assert_eq!(from_multibase::<Sha256>(&text).unwrap(), *chain.anchor());
+    assert_eq!(from_multibase::<Sha256>(&to_multibase::<Sha256>(chain.anchor(), Base::Base32Lower)).unwrap(), *chain.anchor());
+
+    let anchor = *HashChain::<Sha512>::new(16, 2).unwrap().anchor();
+    let text = to_multibase::<Sha512>(&anchor, Base::Base64);
+    assert_eq!(from_multibase::<Sha256>(&text), Err(MultiformatError::WrongHash { expected: 0x12, found: 0x13 }));
+    assert!(from_multibase::<Sha256>("not multibase").is_err());
+}
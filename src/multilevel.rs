@@ -0,0 +1,197 @@
+//! Multi-level µTESLA (Liu and Ning).
+//!
+//! A long-lived high-level chain keys one TESLA stream whose intervals are long (say a day) and
+//! whose only payloads are commitment distribution messages ([`Cdm`]s). The CDM broadcast in
+//! high-level interval `i` carries the anchor of the short low-level chain used during interval
+//! `i + 2`, so a receiver has authenticated each low-level anchor before that chain comes into
+//! use. Data packets travel on the low-level chains, which only need as many values as there are
+//! low-level intervals in one high-level interval.
+//!
+//! The CDM of interval `i` also discloses the last key of low-level chain `i - 1` once that key
+//! is no longer protecting anything, so packets from the end of an interval are not stranded.
+
+use crate::tesla::{self, Authenticated, Packet, Schedule, TeslaError};
+use crate::HashChain;
+use digest::core_api::BlockSizeUser;
+use digest::generic_array::GenericArray;
+use digest::{Digest, FixedOutputReset, OutputSizeUser};
+use std::collections::BTreeMap;
+use std::time::Duration;
+
+/// A commitment distribution message.
+pub struct Cdm<H: OutputSizeUser> {
+    /// High-level packet whose payload is the anchor of the low-level chain two intervals ahead.
+    pub packet: Packet<H>,
+    /// The final key of the previous interval's low-level chain, once it may be disclosed.
+    pub low_tail: Option<(u64, GenericArray<u8, H::OutputSize>)>,
+}
+
+/// A data packet on the low-level chain of `high_interval`.
+pub struct LowPacket<H: OutputSizeUser> {
+    pub high_interval: u64,
+    pub packet: Packet<H>,
+}
+
+/// The seed of the low-level chain used during high-level interval `interval`.
+fn low_seed<H: Digest>(master_seed: u64, interval: u64) -> u64 {
+    let digest = H::new_with_prefix(b"multilevel low seed")
+        .chain_update(master_seed.to_le_bytes())
+        .chain_update(interval.to_le_bytes())
+        .finalize();
+    u64::from_le_bytes(digest[..8].try_into().expect("digest output is at least 8 bytes"))
+}
+
+/// The low-level schedule dividing high-level interval `interval` into `low_length` parts.
+fn low_schedule(high: &Schedule, interval: u64, low_length: u64) -> Schedule {
+    let offset = Duration::from_nanos((high.interval.as_nanos() * u128::from(interval - 1)) as u64);
+    Schedule { start: high.start + offset, interval: high.interval / low_length as u32 }
+}
+
+/// The broadcasting side.
+pub struct MultiLevelSender<H: Digest + FixedOutputReset> {
+    high: tesla::Sender<H>,
+    schedule: Schedule,
+    low_length: usize,
+    low_lag: u64,
+    master_seed: u64,
+    low: Option<(u64, tesla::Sender<H>)>,
+}
+
+impl<H: Digest + FixedOutputReset + BlockSizeUser> MultiLevelSender<H> {
+    /// Broadcast over `high` with one high-level interval per `schedule` interval, each split into
+    /// `low_length` low-level intervals whose keys are disclosed `low_lag` intervals later.
+    /// Low-level chains are derived from `master_seed`.
+    pub fn new(high: HashChain<H>, schedule: Schedule, low_length: usize, low_lag: u64, master_seed: u64) -> Result<Self, crate::ChainInitError> {
+        // validate the low-level length once, so deriving chains later cannot fail
+        HashChain::<H>::new(low_length, 0)?;
+        Ok(MultiLevelSender { high: tesla::Sender::new(high, schedule, 1), schedule, low_length, low_lag, master_seed, low: None })
+    }
+
+    fn low_chain(&self, interval: u64) -> HashChain<H> {
+        HashChain::new(self.low_length, low_seed::<H>(self.master_seed, interval)).expect("length validated in new")
+    }
+
+    /// What receivers must be bootstrapped with: the high-level anchor and the anchors of the
+    /// low-level chains for intervals 1 and 2, which no CDM commits to.
+    pub fn bootstrap(&self) -> (digest::Output<H>, [digest::Output<H>; 2]) {
+        (self.high.anchor().clone(), [self.low_chain(1).anchor().clone(), self.low_chain(2).anchor().clone()])
+    }
+
+    /// The CDM to broadcast at time `now`.
+    pub fn cdm(&mut self, now: Duration) -> Result<Cdm<H>, TeslaError> {
+        let interval = self.schedule.interval_at(now).ok_or(TeslaError::NotStarted)?;
+        let packet = self.high.send(self.low_chain(interval + 2).anchor(), now)?;
+        let low = low_schedule(&self.schedule, interval, self.low_length as u64);
+        let low_interval = low.interval_at(now).unwrap_or(0);
+        // the last key of the previous chain protects its final interval until `low_lag`
+        // low-level intervals into the current one
+        let low_tail = (interval > 1 && low_interval > self.low_lag).then(|| {
+            let seed = low_seed::<H>(self.master_seed, interval - 1);
+            (interval - 1, H::digest(seed.to_le_bytes()))
+        });
+        Ok(Cdm { packet, low_tail })
+    }
+
+    /// Authenticate a data packet for the low-level interval containing `now`.
+    pub fn send(&mut self, payload: &[u8], now: Duration) -> Result<LowPacket<H>, TeslaError> {
+        let interval = self.schedule.interval_at(now).ok_or(TeslaError::NotStarted)?;
+        if self.low.as_ref().map(|(i, _)| *i) != Some(interval) {
+            let schedule = low_schedule(&self.schedule, interval, self.low_length as u64);
+            self.low = Some((interval, tesla::Sender::new(self.low_chain(interval), schedule, self.low_lag)));
+        }
+        let (_, low) = self.low.as_mut().expect("low-level sender set above");
+        Ok(LowPacket { high_interval: interval, packet: low.send(payload, now)? })
+    }
+}
+
+/// The receiving side.
+pub struct MultiLevelReceiver<H: Digest + FixedOutputReset> {
+    high: tesla::Receiver<H>,
+    schedule: Schedule,
+    low_length: u64,
+    low_lag: u64,
+    max_offset: Duration,
+    low: BTreeMap<u64, tesla::Receiver<H>>,
+}
+
+impl<H: Digest + FixedOutputReset + BlockSizeUser> MultiLevelReceiver<H> {
+    /// Bootstrap from the values returned by [`MultiLevelSender::bootstrap`].
+    pub fn new(high_anchor: GenericArray<u8, H::OutputSize>, low_anchors: [GenericArray<u8, H::OutputSize>; 2], schedule: Schedule, low_length: usize, low_lag: u64, max_offset: Duration) -> Self {
+        let mut receiver = MultiLevelReceiver {
+            high: tesla::Receiver::new(high_anchor, schedule, 1, max_offset),
+            schedule,
+            low_length: low_length as u64,
+            low_lag,
+            max_offset,
+            low: BTreeMap::new(),
+        };
+        let [first, second] = low_anchors;
+        receiver.add_low(1, first);
+        receiver.add_low(2, second);
+        receiver
+    }
+
+    fn add_low(&mut self, interval: u64, anchor: GenericArray<u8, H::OutputSize>) {
+        let schedule = low_schedule(&self.schedule, interval, self.low_length);
+        self.low.insert(interval, tesla::Receiver::new(anchor, schedule, self.low_lag, self.max_offset));
+    }
+
+    /// The high-level intervals whose low-level anchor is known.
+    pub fn known_intervals(&self) -> Vec<u64> {
+        self.low.keys().copied().collect()
+    }
+
+    /// Process a CDM received at `now`, returning low-level packets released by its tail key.
+    pub fn receive_cdm(&mut self, cdm: Cdm<H>, now: Duration) -> Result<Vec<(u64, Authenticated)>, TeslaError> {
+        for commitment in self.high.receive(cdm.packet, now)? {
+            if commitment.payload.len() == <H as Digest>::output_size() {
+                self.add_low(commitment.interval + 2, GenericArray::clone_from_slice(&commitment.payload));
+            }
+        }
+        let mut released = Vec::new();
+        if let Some((interval, key)) = cdm.low_tail {
+            if let Some(low) = self.low.get_mut(&interval) {
+                released.extend(low.receive_key(self.low_length, &key, now)?.into_iter().map(|p| (interval, p)));
+            }
+            // chains before the tail's are finished
+            self.low.retain(|i, _| *i >= interval);
+        }
+        Ok(released)
+    }
+
+    /// Process a data packet received at `now`.
+    pub fn receive(&mut self, packet: LowPacket<H>, now: Duration) -> Result<Vec<(u64, Authenticated)>, TeslaError> {
+        let low = self.low.get_mut(&packet.high_interval).ok_or(TeslaError::InvalidKey)?;
+        Ok(low.receive(packet.packet, now)?.into_iter().map(|p| (packet.high_interval, p)).collect())
+    }
+}
+
+#[test]
+fn test_multilevel_commitments() {
+    use sha2::Sha256;
+
+    // high-level intervals of 8 seconds, each split into 8 one-second low-level intervals
+    let schedule = Schedule { start: Duration::ZERO, interval: Duration::from_secs(8) };
+    let mut sender = MultiLevelSender::new(HashChain::<Sha256>::new(8, 1).unwrap(), schedule, 8, 1, 42).unwrap();
+    let (high_anchor, low_anchors) = sender.bootstrap();
+    let mut receiver = MultiLevelReceiver::<Sha256>::new(high_anchor, low_anchors, schedule, 8, 1, Duration::from_millis(10));
+    let at = |ms: u64| Duration::from_millis(ms);
+
+    // the CDM of interval 1 commits to chain 3 and is authenticated by the CDM of interval 2
+    receiver.receive_cdm(sender.cdm(at(100)).unwrap(), at(150)).unwrap();
+    assert_eq!(receiver.known_intervals(), vec![1, 2]);
+
+    // data in the last low-level interval of high interval 1
+    let late = sender.send(b"late", at(7500)).unwrap();
+    assert_eq!(receiver.receive(late, at(7550)).unwrap(), vec![]);
+
+    let released = receiver.receive_cdm(sender.cdm(at(9100)).unwrap(), at(9150)).unwrap();
+    assert_eq!(receiver.known_intervals(), vec![1, 2, 3]);
+    let payloads: Vec<_> = released.into_iter().map(|(i, p)| (i, p.payload)).collect();
+    assert_eq!(payloads, vec![(1, b"late".to_vec())]);
+
+    // a packet on the freshly committed chain 3 is accepted for buffering
+    let packet = sender.send(b"three", at(16_100)).unwrap();
+    assert_eq!(packet.high_interval, 3);
+    assert_eq!(receiver.receive(packet, at(16_150)).unwrap(), vec![]);
+}
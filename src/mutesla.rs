@@ -0,0 +1,234 @@
+//! µTESLA, the TESLA variant for sensor networks (Perrig et al., SPINS).
+//!
+//! Compared to [`tesla`](crate::tesla), data packets carry no key: the base station discloses
+//! one key per interval in a separate [`KeyDisclosure`] broadcast. Nodes are bootstrapped over a
+//! unicast exchange authenticated with the key they share with the base station, and buffer at
+//! most a fixed number of packets, evicting according to an [`EvictionPolicy`] so the memory
+//! footprint is known when the firmware is built.
+
+use crate::keyschedule::KeySchedule;
+use crate::tesla::{packet_mac, Authenticated, Packet, Schedule, TeslaError};
+use crate::{hash_forward, HashChain};
+use crate::value::ChainValue;
+use digest::core_api::BlockSizeUser;
+use digest::generic_array::GenericArray;
+use digest::{Digest, FixedOutputReset, Mac, OutputSizeUser};
+use hmac::SimpleHmac;
+use std::collections::VecDeque;
+use std::time::Duration;
+
+/// The base station's reply to a node's bootstrap request.
+pub struct Bootstrap<H: OutputSizeUser> {
+    /// A verified chain position: the anchor, or the most recently disclosed key.
+    pub key_index: u64,
+    pub key: GenericArray<u8, H::OutputSize>,
+    pub schedule: Schedule,
+    pub lag: u64,
+    /// MAC under the node's master key over the node's nonce and the fields above.
+    pub mac: Vec<u8>,
+}
+
+fn bootstrap_mac<H: Digest + BlockSizeUser>(master_key: &[u8], nonce: &[u8], key_index: u64, key: &[u8], schedule: &Schedule, lag: u64) -> SimpleHmac<H> {
+    let mut mac = <SimpleHmac<H> as Mac>::new_from_slice(master_key).expect("HMAC accepts any key length");
+    mac.update(b"mutesla bootstrap");
+    mac.update(&(nonce.len() as u32).to_be_bytes());
+    mac.update(nonce);
+    mac.update(&key_index.to_be_bytes());
+    mac.update(key);
+    mac.update(&(schedule.start.as_nanos() as u64).to_be_bytes());
+    mac.update(&(schedule.interval.as_nanos() as u64).to_be_bytes());
+    mac.update(&lag.to_be_bytes());
+    mac
+}
+
+/// A key broadcast by the base station once per interval.
+pub struct KeyDisclosure<H: OutputSizeUser> {
+    pub index: u64,
+    pub key: GenericArray<u8, H::OutputSize>,
+}
+
+/// The base station.
+pub struct BaseStation<H: Digest + FixedOutputReset> {
+    chain: HashChain<H>,
+    keys: KeySchedule,
+    recent: VecDeque<(u64, ChainValue<H>)>,
+}
+
+impl<H: Digest + FixedOutputReset + BlockSizeUser> BaseStation<H> {
+    pub fn new(chain: HashChain<H>, schedule: Schedule, lag: u64) -> Self {
+        BaseStation { chain, keys: KeySchedule::new(schedule, lag), recent: VecDeque::new() }
+    }
+
+    fn advance(&mut self, now: Duration) -> Result<u64, TeslaError> {
+        let interval = self.keys.interval_at(now).ok_or(TeslaError::NotStarted)?;
+        let index = self.keys.key_index(interval);
+        if index > self.chain.length() {
+            return Err(TeslaError::Exhausted);
+        }
+        while self.chain.position() < index {
+            let key = self.chain.next().ok_or(TeslaError::Exhausted)?;
+            self.recent.push_back(key);
+            if self.recent.len() > self.keys.retained() {
+                self.recent.pop_front();
+            }
+        }
+        Ok(interval)
+    }
+
+    /// The retained key disclosed in `interval`, if one is due.
+    fn due(&self, interval: u64) -> Option<&(u64, ChainValue<H>)> {
+        let index = self.keys.disclosed_in(interval)?;
+        self.recent.iter().find(|(i, _)| *i == index)
+    }
+
+    /// Answer a node's bootstrap request carrying `nonce`, authenticated with the master key
+    /// shared with that node.
+    pub fn bootstrap(&mut self, master_key: &[u8], nonce: &[u8], now: Duration) -> Result<Bootstrap<H>, TeslaError> {
+        let interval = self.advance(now)?;
+        let (key_index, key) = match self.due(interval) {
+            Some((i, key)) => (*i, key.to_array()),
+            // nothing disclosed yet: hand out the anchor
+            None => (0, self.chain.anchor().clone()),
+        };
+        let KeySchedule { schedule, lag } = self.keys;
+        let mac = bootstrap_mac::<H>(master_key, nonce, key_index, &key, &schedule, lag).finalize().into_bytes().to_vec();
+        Ok(Bootstrap { key_index, key, schedule, lag, mac })
+    }
+
+    /// Authenticate a data packet for the interval containing `now`.
+    pub fn send(&mut self, payload: &[u8], now: Duration) -> Result<Packet<H>, TeslaError> {
+        let interval = self.advance(now)?;
+        let (_, key) = self.recent.back().expect("current key present");
+        let mac = packet_mac::<H>(key, interval, payload).finalize().into_bytes().to_vec();
+        Ok(Packet { interval, payload: payload.to_vec(), mac, disclosed: None })
+    }
+
+    /// The key disclosure to broadcast in the interval containing `now`, if any key is due.
+    pub fn disclose(&mut self, now: Duration) -> Result<Option<KeyDisclosure<H>>, TeslaError> {
+        let interval = self.advance(now)?;
+        Ok(self.due(interval)
+            .map(|(index, key)| KeyDisclosure { index: *index, key: key.to_array() }))
+    }
+}
+
+/// What to do when a packet arrives at a full buffer.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EvictionPolicy {
+    /// Keep the buffered packets and reject the new one.
+    DropNewest,
+    /// Evict the oldest buffered packet to make room.
+    DropOldest,
+}
+
+/// A sensor node's receiver.
+pub struct Node<H: Digest + FixedOutputReset> {
+    keys: KeySchedule,
+    max_offset: Duration,
+    key_index: u64,
+    key: GenericArray<u8, H::OutputSize>,
+    buffer: VecDeque<Packet<H>>,
+    capacity: usize,
+    policy: EvictionPolicy,
+}
+
+impl<H: Digest + FixedOutputReset + BlockSizeUser> Node<H> {
+    /// Complete the bootstrap started with `nonce`, buffering at most `capacity` packets.
+    pub fn bootstrap(master_key: &[u8], nonce: &[u8], reply: Bootstrap<H>, max_offset: Duration, capacity: usize, policy: EvictionPolicy) -> Result<Self, TeslaError> {
+        bootstrap_mac::<H>(master_key, nonce, reply.key_index, &reply.key, &reply.schedule, reply.lag)
+            .verify_slice(&reply.mac)
+            .map_err(|_| TeslaError::BadBootstrap)?;
+        Ok(Node {
+            keys: KeySchedule::new(reply.schedule, reply.lag),
+            max_offset,
+            key_index: reply.key_index,
+            key: reply.key,
+            buffer: VecDeque::with_capacity(capacity),
+            capacity,
+            policy,
+        })
+    }
+
+    /// The highest interval whose key has been verified.
+    pub fn key_index(&self) -> u64 {
+        self.key_index
+    }
+
+    /// Buffer a data packet received at `now` if it passes the security condition.
+    pub fn receive(&mut self, packet: Packet<H>, now: Duration) -> Result<(), TeslaError> {
+        self.keys.is_safe(packet.interval, now + self.max_offset)?;
+        if self.keys.key_index(packet.interval) <= self.key_index {
+            return Err(TeslaError::Unsafe);
+        }
+        if self.buffer.len() >= self.capacity {
+            match self.policy {
+                EvictionPolicy::DropNewest => return Err(TeslaError::BufferFull),
+                EvictionPolicy::DropOldest => {
+                    self.buffer.pop_front();
+                }
+            }
+        }
+        if self.capacity > 0 {
+            self.buffer.push_back(packet);
+        }
+        Ok(())
+    }
+
+    /// Process a key disclosure received at `now`, returning the buffered packets it
+    /// authenticates. Keys the base station cannot have disclosed yet are refused before hashing.
+    pub fn receive_key(&mut self, disclosure: &KeyDisclosure<H>, now: Duration) -> Result<Vec<Authenticated>, TeslaError> {
+        if disclosure.index <= self.key_index {
+            return Ok(Vec::new());
+        }
+        let latest = self.keys.interval_at(now + self.max_offset).and_then(|interval| self.keys.disclosed_in(interval));
+        if latest.is_none_or(|latest| disclosure.index > latest) {
+            return Err(TeslaError::FromTheFuture);
+        }
+        if hash_forward::<H>(&disclosure.key, disclosure.index - self.key_index) != self.key {
+            return Err(TeslaError::InvalidKey);
+        }
+        self.key_index = disclosure.index;
+        self.key = disclosure.key.clone();
+        let mut released = Vec::new();
+        let mut waiting = VecDeque::with_capacity(self.capacity);
+        for packet in self.buffer.drain(..) {
+            let index = self.keys.key_index(packet.interval);
+            if index > self.key_index {
+                waiting.push_back(packet);
+                continue;
+            }
+            let key = hash_forward::<H>(&self.key, self.key_index - index);
+            if packet_mac::<H>(&key, packet.interval, &packet.payload).verify_slice(&packet.mac).is_ok() {
+                released.push(Authenticated { interval: packet.interval, payload: packet.payload });
+            }
+        }
+        self.buffer = waiting;
+        Ok(released)
+    }
+}
+
+#[test]
+fn test_mutesla_bootstrap_and_eviction() {
+    use sha2::Sha256;
+
+    let schedule = Schedule { start: Duration::ZERO, interval: Duration::from_secs(1) };
+    let at = |ms: u64| Duration::from_millis(ms);
+    let mut base = BaseStation::new(HashChain::<Sha256>::new(32, 8).unwrap(), schedule, 1);
+
+    let reply = base.bootstrap(b"node-7 key", b"nonce-1", at(500)).unwrap();
+    assert!(Node::bootstrap(b"wrong key", b"nonce-1", base.bootstrap(b"node-7 key", b"nonce-1", at(500)).unwrap(), at(50), 2, EvictionPolicy::DropOldest).is_err());
+    let mut node = Node::bootstrap(b"node-7 key", b"nonce-1", reply, at(50), 2, EvictionPolicy::DropOldest).unwrap();
+
+    // three packets in interval 2 overflow the two-slot buffer, evicting the first
+    for payload in [&b"a"[..], b"b", b"c"] {
+        node.receive(base.send(payload, at(1200)).unwrap(), at(1300)).unwrap();
+    }
+    assert!(base.disclose(at(1500)).unwrap().unwrap().index == 1);
+    let disclosure = base.disclose(at(2100)).unwrap().unwrap();
+    let forged = KeyDisclosure::<Sha256> { index: u64::MAX, key: disclosure.key };
+    assert_eq!(node.receive_key(&forged, at(2150)), Err(TeslaError::FromTheFuture));
+    assert_eq!(node.receive_key(&disclosure, at(1000)), Err(TeslaError::FromTheFuture));
+    let released = node.receive_key(&disclosure, at(2150)).unwrap();
+    let payloads: Vec<_> = released.into_iter().map(|p| p.payload).collect();
+    assert_eq!(payloads, vec![b"b".to_vec(), b"c".to_vec()]);
+    assert_eq!(node.key_index(), 2);
+}
@@ -0,0 +1,235 @@
+//! Lightweight mutual authentication for constrained devices such as RFID tags and IoT nodes.
+//!
+//! Device and server each hold a hash chain and know the other's anchor from onboarding, along
+//! with a key shared between the two. A session takes three messages:
+//!
+//! 1. the server sends a [`Challenge`] carrying a fresh nonce,
+//! 2. the device answers with its next chain value and a tag binding it to the nonce,
+//! 3. the server checks the value against the last one it accepted from the device and answers
+//!    with its own next value, bound to the nonce and the device's index.
+//!
+//! The tags are keyed with the shared key, not with the chain value they travel with, so whoever
+//! relays a proof cannot move its value to another nonce. Only hashing is needed on the device.
+//! Each side accepts any chain value up to [`MAX_SKIP`] past the last one from its peer, so a
+//! session broken off after the device has answered costs a chain value but does not lock the
+//! device out, while a forged index costs no more than that many hashes.
+
+use crate::{verify, HashChain};
+use digest::core_api::BlockSizeUser;
+use digest::generic_array::GenericArray;
+use digest::{Digest, FixedOutputReset, Mac, OutputSizeUser};
+use hmac::SimpleHmac;
+use std::error::Error;
+use std::fmt::{self, Display};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum MutualAuthError {
+    /// The message does not fit the current state of the exchange.
+    UnexpectedMessage,
+    /// Our chain has no value left to disclose.
+    Exhausted,
+    /// The peer's value or tag did not verify.
+    Rejected,
+}
+
+impl Display for MutualAuthError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            MutualAuthError::UnexpectedMessage => write!(f, "unexpected message"),
+            MutualAuthError::Exhausted => write!(f, "chain exhausted"),
+            MutualAuthError::Rejected => write!(f, "peer failed authentication"),
+        }
+    }
+}
+
+impl Error for MutualAuthError {}
+
+/// Where a role stands in the current exchange.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum State {
+    /// No exchange in progress.
+    Idle,
+    /// Waiting for the peer's proof.
+    Pending,
+    /// The last exchange authenticated the peer.
+    Authenticated,
+    /// The last exchange failed; a new one may be started.
+    Failed,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Challenge {
+    pub nonce: Vec<u8>,
+}
+
+/// A chain value disclosed during an exchange, with its tag.
+pub struct Proof<H: OutputSizeUser> {
+    pub index: u64,
+    pub value: GenericArray<u8, H::OutputSize>,
+    pub tag: Vec<u8>,
+}
+
+/// How many chain values past the last accepted one a peer may skip.
+pub const MAX_SKIP: u64 = 64;
+
+fn tag<H: Digest + BlockSizeUser>(key: &[u8], label: &[u8], value: &[u8], nonce: &[u8], bound_index: u64) -> SimpleHmac<H> {
+    let mut mac = <SimpleHmac<H> as Mac>::new_from_slice(key).expect("HMAC accepts any key length");
+    mac.update(label);
+    mac.update(value);
+    mac.update(&(nonce.len() as u32).to_be_bytes());
+    mac.update(nonce);
+    mac.update(&bound_index.to_be_bytes());
+    mac
+}
+
+const DEVICE_LABEL: &[u8] = b"mutual auth device";
+const SERVER_LABEL: &[u8] = b"mutual auth server";
+
+/// One side's view: its own chain and the last value it accepted from the peer.
+struct Party<H: Digest + FixedOutputReset> {
+    chain: HashChain<H>,
+    key: Vec<u8>,
+    peer_index: u64,
+    peer_value: GenericArray<u8, H::OutputSize>,
+    state: State,
+}
+
+impl<H: Digest + FixedOutputReset + BlockSizeUser> Party<H> {
+    fn prove(&mut self, label: &[u8], nonce: &[u8], bound_index: u64) -> Result<Proof<H>, MutualAuthError> {
+        let (index, value) = self.chain.disclose().ok_or(MutualAuthError::Exhausted)?;
+        let tag = tag::<H>(&self.key, label, &value, nonce, bound_index).finalize().into_bytes().to_vec();
+        Ok(Proof { index, value: value.to_array(), tag })
+    }
+
+    fn check(&mut self, label: &[u8], nonce: &[u8], bound_index: u64, proof: &Proof<H>) -> Result<(), MutualAuthError> {
+        let tag_ok = tag::<H>(&self.key, label, &proof.value, nonce, bound_index).verify_slice(&proof.tag).is_ok();
+        let in_window = proof.index.saturating_sub(self.peer_index) <= MAX_SKIP;
+        if !tag_ok || !in_window || !verify::<H>(self.peer_index, &self.peer_value, proof.index, &proof.value) {
+            self.state = State::Failed;
+            return Err(MutualAuthError::Rejected);
+        }
+        self.peer_index = proof.index;
+        self.peer_value = proof.value.clone();
+        Ok(())
+    }
+}
+
+/// The device role.
+pub struct Device<H: Digest + FixedOutputReset> {
+    party: Party<H>,
+    pending: Option<(Vec<u8>, u64)>,
+}
+
+impl<H: Digest + FixedOutputReset + BlockSizeUser> Device<H> {
+    /// A device disclosing from `chain` that trusts `server_anchor`, tagging with the key it
+    /// shares with the server.
+    pub fn new(chain: HashChain<H>, server_anchor: GenericArray<u8, H::OutputSize>, key: &[u8]) -> Self {
+        Device { party: Party { chain, key: key.to_vec(), peer_index: 0, peer_value: server_anchor, state: State::Idle }, pending: None }
+    }
+
+    /// The anchor to register with the server.
+    pub fn anchor(&self) -> &GenericArray<u8, H::OutputSize> {
+        self.party.chain.anchor()
+    }
+
+    pub fn state(&self) -> State {
+        self.party.state
+    }
+
+    /// Answer a server's challenge. Any exchange still pending is abandoned.
+    pub fn respond(&mut self, challenge: &Challenge) -> Result<Proof<H>, MutualAuthError> {
+        let proof = self.party.prove(DEVICE_LABEL, &challenge.nonce, 0)?;
+        self.pending = Some((challenge.nonce.clone(), proof.index));
+        self.party.state = State::Pending;
+        Ok(proof)
+    }
+
+    /// Check the server's answer, completing the exchange.
+    pub fn confirm(&mut self, proof: &Proof<H>) -> Result<(), MutualAuthError> {
+        let (nonce, index) = self.pending.take().ok_or(MutualAuthError::UnexpectedMessage)?;
+        self.party.check(SERVER_LABEL, &nonce, index, proof)?;
+        self.party.state = State::Authenticated;
+        Ok(())
+    }
+}
+
+/// The server role for one device.
+pub struct Server<H: Digest + FixedOutputReset> {
+    party: Party<H>,
+    pending: Option<Vec<u8>>,
+}
+
+impl<H: Digest + FixedOutputReset + BlockSizeUser> Server<H> {
+    /// A server disclosing from `chain` for the device enrolled with `device_anchor` and the
+    /// key shared with it.
+    pub fn new(chain: HashChain<H>, device_anchor: GenericArray<u8, H::OutputSize>, key: &[u8]) -> Self {
+        Server { party: Party { chain, key: key.to_vec(), peer_index: 0, peer_value: device_anchor, state: State::Idle }, pending: None }
+    }
+
+    /// The anchor to provision on the device.
+    pub fn anchor(&self) -> &GenericArray<u8, H::OutputSize> {
+        self.party.chain.anchor()
+    }
+
+    pub fn state(&self) -> State {
+        self.party.state
+    }
+
+    /// Start an exchange with a fresh, unpredictable `nonce`.
+    pub fn challenge(&mut self, nonce: &[u8]) -> Challenge {
+        self.pending = Some(nonce.to_vec());
+        self.party.state = State::Pending;
+        Challenge { nonce: nonce.to_vec() }
+    }
+
+    /// Check the device's answer and prove ourselves in return.
+    pub fn respond(&mut self, proof: &Proof<H>) -> Result<Proof<H>, MutualAuthError> {
+        let nonce = self.pending.take().ok_or(MutualAuthError::UnexpectedMessage)?;
+        self.party.check(DEVICE_LABEL, &nonce, 0, proof)?;
+        let reply = self.party.prove(SERVER_LABEL, &nonce, proof.index)?;
+        self.party.state = State::Authenticated;
+        Ok(reply)
+    }
+}
+
+#[test]
+fn test_mutual_auth() {
+    use sha2::Sha256;
+
+    let device_chain = HashChain::<Sha256>::new(128, 1).unwrap();
+    let server_chain = HashChain::<Sha256>::new(16, 2).unwrap();
+    let (device_anchor, server_anchor) = (*device_chain.anchor(), *server_chain.anchor());
+    let mut device = Device::new(device_chain.clone(), server_anchor, b"device 7 key");
+    let mut server = Server::new(server_chain, device_anchor, b"device 7 key");
+
+    let answer = device.respond(&server.challenge(b"nonce 1")).unwrap();
+    assert_eq!(device.state(), State::Pending);
+    let reply = server.respond(&answer).unwrap();
+    device.confirm(&reply).unwrap();
+    assert_eq!((device.state(), server.state()), (State::Authenticated, State::Authenticated));
+    assert_eq!(device.confirm(&reply).err(), Some(MutualAuthError::UnexpectedMessage));
+
+    // a lost answer is skipped over; a replayed one is bound to the old nonce
+    device.respond(&server.challenge(b"nonce 2")).unwrap();
+    device.respond(&Challenge { nonce: b"nonce 2".to_vec() }).unwrap();
+    let answer = device.respond(&server.challenge(b"nonce 3")).unwrap();
+    server.challenge(b"nonce 4");
+    assert_eq!(server.respond(&answer).err(), Some(MutualAuthError::Rejected));
+    assert_eq!(server.state(), State::Failed);
+    let answer = device.respond(&server.challenge(b"nonce 5")).unwrap();
+    assert_eq!(answer.index, 5);
+    device.confirm(&server.respond(&answer).unwrap()).unwrap();
+
+    // an eavesdropper holding the disclosed value cannot tag it for another nonce
+    let (index, value) = device.party.chain.disclose().unwrap();
+    let forged_tag = tag::<Sha256>(&value, DEVICE_LABEL, &value, b"nonce 6", 0).finalize().into_bytes().to_vec();
+    server.challenge(b"nonce 6");
+    assert_eq!(server.respond(&Proof { index, value: value.to_array(), tag: forged_tag }).err(), Some(MutualAuthError::Rejected));
+
+    // an index far past the last accepted one is refused before hashing
+    let mut far = device_chain;
+    let (index, value) = far.nth(MAX_SKIP as usize + 5).unwrap();
+    let far_tag = tag::<Sha256>(b"device 7 key", DEVICE_LABEL, &value, b"nonce 7", 0).finalize().into_bytes().to_vec();
+    server.challenge(b"nonce 7");
+    assert_eq!(server.respond(&Proof { index, value: value.to_array(), tag: far_tag }).err(), Some(MutualAuthError::Rejected));
+}
@@ -0,0 +1,85 @@
+//! Node.js bindings built with napi-rs, for SHA-256 chains. Values and chain state cross the
+//! boundary as `Buffer`s, indices as numbers. Build the addon with
+//! `cargo rustc --release --features node --crate-type cdylib` and load the library renamed to
+//! `fractal_hash_traversal.node`.
+
+use crate::{verify, HashChain};
+use digest::generic_array::GenericArray;
+use napi::bindgen_prelude::{BigInt, Buffer};
+use napi_derive::napi;
+use sha2::Sha256;
+
+fn invalid<E: std::fmt::Display>(error: E) -> napi::Error {
+    napi::Error::from_reason(error.to_string())
+}
+
+fn index(value: i64) -> napi::Result<u64> {
+    u64::try_from(value).map_err(|_| napi::Error::from_reason("index must not be negative"))
+}
+
+#[napi(js_name = "HashChain")]
+pub struct NodeHashChain {
+    chain: HashChain<Sha256>,
+}
+
+#[napi]
+impl NodeHashChain {
+    /// Set up a chain of `length` values from `seed`.
+    #[napi(constructor)]
+    pub fn new(length: u32, seed: BigInt) -> napi::Result<Self> {
+        let (_, seed, lossless) = seed.get_u64();
+        if !lossless {
+            return Err(napi::Error::from_reason("seed must fit in 64 bits"));
+        }
+        Ok(NodeHashChain { chain: HashChain::new(length as usize, seed).map_err(invalid)? })
+    }
+
+    #[napi(getter)]
+    pub fn anchor(&self) -> Buffer {
+        self.chain.anchor().to_vec().into()
+    }
+
+    /// The index of the last disclosed value.
+    #[napi(getter)]
+    pub fn position(&self) -> i64 {
+        self.chain.position() as i64
+    }
+
+    /// The next value, or `null` once the chain is exhausted; its index is the new position.
+    #[napi]
+    #[allow(clippy::should_implement_trait)]
+    pub fn next(&mut self) -> Option<Buffer> {
+        self.chain.disclose().map(|(_, value)| value.to_vec().into())
+    }
+
+    /// The traversal state, see `HashChain::export_state`. Guard it like a secret key.
+    #[napi]
+    pub fn export_state(&self) -> Buffer {
+        self.chain.export_state().into()
+    }
+
+    #[napi(factory)]
+    pub fn import_state(state: Buffer) -> napi::Result<Self> {
+        Ok(NodeHashChain { chain: HashChain::import_state(&state).map_err(invalid)? })
+    }
+}
+
+/// Check `value` at `index` against `known` at `knownIndex`, the anchor being at 0.
+#[napi(js_name = "verify")]
+pub fn verify_value(known_index: i64, known: Buffer, index_: i64, value: Buffer) -> napi::Result<bool> {
+    if known.len() != 32 || value.len() != 32 {
+        return Ok(false);
+    }
+    Ok(verify::<Sha256>(index(known_index)?, GenericArray::from_slice(&known), index(index_)?, GenericArray::from_slice(&value)))
+}
+
+#[test]
+fn test_node_chain_state_roundtrip() {
+    let mut chain = NodeHashChain::new(16, BigInt::from(5u64)).unwrap_or_else(|_| unreachable!());
+    let first = chain.next().unwrap();
+    assert!(verify_value(0, chain.anchor(), 1, first.to_vec().into()).unwrap_or(false));
+
+    let mut restored = NodeHashChain::import_state(chain.export_state()).unwrap_or_else(|_| unreachable!());
+    assert_eq!(restored.position(), 1);
+    assert_eq!(restored.next().unwrap().to_vec(), chain.next().unwrap().to_vec());
+}
@@ -0,0 +1,239 @@
+//! Callbacks on a chain's progress, for alerts, rotation or billing reconciliation without
+//! polling the chain.
+//!
+//! [`Observed`] wraps a [`HashChain`] and calls its [`ChainObserver`] after every disclosure,
+//! the first time the consumed share of the chain reaches each configured percentage, and once
+//! when the last value has been disclosed.
+//!
+//! With [`Observed::with_forecast`] it also keeps the times of the last few disclosures in a
+//! [`RateTracker`] and forecasts from their rate when the chain will run out, so a successor can
+//! be rotated in on actual consumption. Times are durations since any fixed epoch, e.g. the Unix
+//! epoch or boot, passed to [`Observed::disclose_at`]; once the forecast exhaustion is within the
+//! configured horizon the observer hears of it, once.
+
+use crate::HashChain;
+use crate::value::ChainValue;
+use alloc::collections::VecDeque;
+use alloc::vec::Vec;
+use core::time::Duration;
+use digest::{Digest, FixedOutputReset};
+
+/// Every method does nothing by default, so observers implement only what they need.
+pub trait ChainObserver {
+    fn on_disclosed(&mut self, _index: u64) {}
+
+    /// At least `percent` percent of the chain has been disclosed.
+    fn on_threshold(&mut self, _percent: u8) {}
+
+    /// The last value has been disclosed.
+    fn on_exhausted(&mut self) {}
+
+    /// At the recent disclosure rate the chain runs out at `at`, within the forecast horizon.
+    fn on_exhaustion_forecast(&mut self, _at: Duration) {}
+}
+
+/// The disclosure rate over the last `window` disclosures.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RateTracker {
+    window: usize,
+    /// Oldest first.
+    times: VecDeque<Duration>,
+}
+
+impl RateTracker {
+    /// Track the last `window` disclosures, at least 2.
+    pub fn new(window: usize) -> Self {
+        let window = window.max(2);
+        RateTracker { window, times: VecDeque::with_capacity(window) }
+    }
+
+    pub fn record(&mut self, at: Duration) {
+        if self.times.len() == self.window {
+            self.times.pop_front();
+        }
+        self.times.push_back(at);
+    }
+
+    /// Disclosures per second, `None` until two disclosures came at different times.
+    pub fn rate(&self) -> Option<f64> {
+        let (first, last) = (self.times.front()?, self.times.back()?);
+        let span = last.checked_sub(*first).filter(|span| !span.is_zero())?;
+        Some((self.times.len() - 1) as f64 / span.as_secs_f64())
+    }
+
+    /// When `remaining` more disclosures will have been made at the current rate.
+    pub fn forecast(&self, remaining: u64) -> Option<Duration> {
+        let ahead = Duration::try_from_secs_f64(remaining as f64 / self.rate()?).ok()?;
+        self.times.back()?.checked_add(ahead)
+    }
+}
+
+struct Forecast {
+    rate: RateTracker,
+    horizon: Duration,
+    alerted: bool,
+}
+
+pub struct Observed<H: Digest + FixedOutputReset, O> {
+    chain: HashChain<H>,
+    observer: O,
+    /// Ascending, with the ones already reported removed.
+    thresholds: Vec<u8>,
+    forecast: Option<Forecast>,
+}
+
+impl<H: Digest + FixedOutputReset, O: ChainObserver> Observed<H, O> {
+    pub fn new(chain: HashChain<H>, observer: O) -> Self {
+        Observed { chain, observer, thresholds: Vec::new(), forecast: None }
+    }
+
+    /// Report when each of `thresholds` percent of the chain has been disclosed. Thresholds the
+    /// chain is already past are not reported.
+    pub fn with_thresholds(mut self, thresholds: &[u8]) -> Self {
+        let consumed = self.consumed();
+        self.thresholds = thresholds.iter().copied().filter(|&percent| percent as u64 > consumed).collect();
+        self.thresholds.sort_unstable();
+        self.thresholds.dedup();
+        self
+    }
+
+    /// Forecast exhaustion from the rate of the last `window` disclosures, and tell the observer
+    /// once the forecast falls within `horizon` of the latest disclosure.
+    pub fn with_forecast(mut self, window: usize, horizon: Duration) -> Self {
+        self.forecast = Some(Forecast { rate: RateTracker::new(window), horizon, alerted: false });
+        self
+    }
+
+    /// The disclosed share of the chain in whole percent, rounded down.
+    fn consumed(&self) -> u64 {
+        HashChain::position(&self.chain) * 100 / self.chain.length()
+    }
+
+    /// Disclose the next value, notifying the observer, or `None` once exhausted.
+    pub fn disclose(&mut self) -> Option<(u64, ChainValue<H>)> {
+        let disclosed = self.chain.disclose()?;
+        self.observer.on_disclosed(disclosed.0);
+        let consumed = self.consumed();
+        let reached = self.thresholds.iter().take_while(|&&percent| percent as u64 <= consumed).count();
+        for percent in self.thresholds.drain(..reached) {
+            self.observer.on_threshold(percent);
+        }
+        if self.chain.remaining() == 0 {
+            self.observer.on_exhausted();
+        }
+        Some(disclosed)
+    }
+
+    /// Like [`Observed::disclose`], recording that the value was disclosed at `now` for the
+    /// forecast.
+    pub fn disclose_at(&mut self, now: Duration) -> Option<(u64, ChainValue<H>)> {
+        let disclosed = self.disclose()?;
+        if let Some(forecast) = &mut self.forecast {
+            forecast.rate.record(now);
+            let at = forecast.rate.forecast(self.chain.remaining());
+            if let Some(at) = at.filter(|at| !forecast.alerted && at.saturating_sub(now) <= forecast.horizon) {
+                forecast.alerted = true;
+                self.observer.on_exhaustion_forecast(at);
+            }
+        }
+        Some(disclosed)
+    }
+
+    /// Disclosures per second over the forecast window, `None` without a forecast or before it
+    /// has a rate.
+    pub fn disclosure_rate(&self) -> Option<f64> {
+        self.forecast.as_ref()?.rate.rate()
+    }
+
+    /// When the chain runs out at the recent rate, in the epoch of [`Observed::disclose_at`].
+    pub fn estimated_exhaustion_time(&self) -> Option<Duration> {
+        self.forecast.as_ref()?.rate.forecast(self.chain.remaining())
+    }
+
+    pub fn chain(&self) -> &HashChain<H> {
+        &self.chain
+    }
+
+    pub fn observer(&self) -> &O {
+        &self.observer
+    }
+
+    pub fn observer_mut(&mut self) -> &mut O {
+        &mut self.observer
+    }
+
+    pub fn into_parts(self) -> (HashChain<H>, O) {
+        (self.chain, self.observer)
+    }
+}
+
+impl<H: Digest + FixedOutputReset, O: ChainObserver> Iterator for Observed<H, O> {
+    type Item = (u64, ChainValue<H>);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.disclose()
+    }
+}
+
+#[test]
+fn test_observed_chain() {
+    use alloc::string::{String, ToString};
+    use alloc::{format, vec};
+    use sha2::Sha256;
+
+    #[derive(Default)]
+    struct Log(Vec<String>);
+
+    impl ChainObserver for Log {
+        fn on_threshold(&mut self, percent: u8) {
+            self.0.push(format!("{}%", percent));
+        }
+
+        fn on_exhausted(&mut self) {
+            self.0.push("exhausted".to_string());
+        }
+    }
+
+    let mut chain = HashChain::<Sha256>::new(8, 1).unwrap();
+    chain.disclose();
+    // 12% is already past after one of eight values
+    let mut observed = Observed::new(chain, Log::default()).with_thresholds(&[90, 50, 12, 50]);
+    assert_eq!(observed.by_ref().take(3).count(), 3);
+    assert_eq!(observed.observer().0, vec!["50%"]);
+    assert_eq!(observed.by_ref().count(), 4);
+    assert_eq!(observed.disclose(), None);
+    assert_eq!(observed.into_parts().1 .0, vec!["50%", "90%", "exhausted"]);
+}
+
+#[test]
+fn test_exhaustion_forecast() {
+    use sha2::Sha256;
+
+    #[derive(Default)]
+    struct Alerts(Vec<Duration>);
+
+    impl ChainObserver for Alerts {
+        fn on_exhaustion_forecast(&mut self, at: Duration) {
+            self.0.push(at);
+        }
+    }
+
+    let chain = HashChain::<Sha256>::new(64, 1).unwrap();
+    let mut observed = Observed::new(chain, Alerts::default()).with_forecast(4, Duration::from_secs(60));
+    let start = Duration::from_secs(1_000);
+    observed.disclose_at(start).unwrap();
+    assert_eq!(observed.estimated_exhaustion_time(), None);
+    // one disclosure every 10 seconds: 62 more take 620 seconds
+    observed.disclose_at(start + Duration::from_secs(10)).unwrap();
+    assert_eq!(observed.disclosure_rate(), Some(0.1));
+    assert_eq!(observed.estimated_exhaustion_time(), Some(start + Duration::from_secs(630)));
+    assert!(observed.observer().0.is_empty());
+    // a burst of one a second brings exhaustion within the minute's horizon
+    for second in 11..=13 {
+        observed.disclose_at(start + Duration::from_secs(second)).unwrap();
+    }
+    assert_eq!(observed.disclosure_rate(), Some(1.0));
+    assert_eq!(observed.observer().0, [start + Duration::from_secs(72)]);
+    observed.disclose_at(start + Duration::from_secs(14)).unwrap();
+    assert_eq!(observed.observer().0.len(), 1);
+}
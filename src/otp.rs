@@ -0,0 +1,195 @@
+//! One-time password authentication in the style of S/KEY and
+//! [RFC 2289](https://www.rfc-editor.org/rfc/rfc2289).
+//!
+//! The user's client computes `count` folded hash steps from the seed and passphrase, and the
+//! [`Server`] only remembers the last accepted value and its sequence number. Each login asks
+//! for the value one step closer to the passphrase, which the server checks with a single hash.
+
+use crate::sixword::{self, Folded};
+use crate::store::StateStore;
+use digest::{Digest, FixedOutputReset, HashMarker, Update};
+use std::error::Error;
+use std::fmt::{self, Display};
+use std::marker::PhantomData;
+use std::str::FromStr;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum OtpError {
+    /// No state is stored for the user.
+    UnknownUser,
+    /// The user's sequence has reached zero and must be re-initialized.
+    Exhausted,
+    /// The challenge or response could not be parsed.
+    Malformed(String),
+    /// The response does not hash to the last accepted value.
+    Rejected,
+    /// The state changed while the response was being verified, e.g. the same response was
+    /// submitted twice concurrently.
+    Conflict,
+    /// The state store failed.
+    Store(String),
+}
+
+impl Display for OtpError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            OtpError::UnknownUser => write!(f, "unknown user"),
+            OtpError::Exhausted => write!(f, "sequence exhausted"),
+            OtpError::Malformed(details) => write!(f, "malformed input: {}", details),
+            OtpError::Rejected => write!(f, "one-time password rejected"),
+            OtpError::Conflict => write!(f, "state changed during verification"),
+            OtpError::Store(details) => write!(f, "state store error: {}", details),
+        }
+    }
+}
+
+impl Error for OtpError {}
+
+/// Per-user server state: the sequence number of the last accepted value, the seed and the value
+/// itself.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct OtpState {
+    pub sequence: u64,
+    pub seed: String,
+    pub last: u64,
+}
+
+/// A challenge such as `otp-sha256 97 dog7841`, asking for the value at `sequence`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Challenge {
+    pub algorithm: String,
+    pub sequence: u64,
+    pub seed: String,
+}
+
+impl Display for Challenge {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "otp-{} {} {}", self.algorithm, self.sequence, self.seed)
+    }
+}
+
+impl FromStr for Challenge {
+    type Err = OtpError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut parts = s.split_whitespace();
+        let (Some(algorithm), Some(sequence), Some(seed), None) = (parts.next(), parts.next(), parts.next(), parts.next()) else {
+            return Err(OtpError::Malformed("expected `otp-<algorithm> <sequence> <seed>`".to_string()));
+        };
+        let algorithm = algorithm.strip_prefix("otp-")
+            .ok_or_else(|| OtpError::Malformed("challenge must start with `otp-`".to_string()))?;
+        let sequence = sequence.parse()
+            .map_err(|_| OtpError::Malformed(format!("invalid sequence number: {}", sequence)))?;
+        Ok(Challenge { algorithm: algorithm.to_string(), sequence, seed: seed.to_string() })
+    }
+}
+
+/// Compute the one-time password for `count`: the folded hash of the lowercased seed followed by
+/// the passphrase, then `count` further folded hash steps.
+pub fn compute<H: Update + FixedOutputReset + Default + HashMarker>(seed: &str, passphrase: &str, count: u64) -> u64 {
+    let mut hasher = Folded::<H>::new_with_prefix(seed.to_lowercase().as_bytes());
+    digest::Digest::update(&mut hasher, passphrase.as_bytes());
+    let mut output = hasher.finalize_reset();
+    for _ in 0..count {
+        digest::Digest::update(&mut hasher, output.as_slice());
+        output = hasher.finalize_reset();
+    }
+    u64::from_be_bytes(output.into())
+}
+
+/// Parse a response given either as 16 hex digits (whitespace allowed) or as six dictionary
+/// words.
+pub fn parse_response(response: &str) -> Result<u64, OtpError> {
+    let compact: String = response.split_whitespace().collect();
+    if compact.len() == 16 && compact.chars().all(|c| c.is_ascii_hexdigit()) {
+        return u64::from_str_radix(&compact, 16).map_err(|e| OtpError::Malformed(e.to_string()));
+    }
+    sixword::decode(response).map_err(|e| OtpError::Malformed(e.to_string()))
+}
+
+/// An OTP authentication server over a [`StateStore`] keyed by user name.
+pub struct Server<H, S> {
+    algorithm: String,
+    store: S,
+    _hash: PhantomData<H>,
+}
+
+impl<H, S> Server<H, S>
+where
+    H: Update + FixedOutputReset + Default + HashMarker,
+    S: StateStore<Key = String, State = OtpState>,
+{
+    /// Create a server advertising `algorithm` (e.g. `"sha256"`) in its challenges.
+    pub fn new(algorithm: &str, store: S) -> Self {
+        Server { algorithm: algorithm.to_string(), store, _hash: PhantomData }
+    }
+
+    /// (Re-)initialize a user with the value the client computed for `sequence`.
+    pub fn enroll(&self, user: &str, seed: &str, sequence: u64, value: u64) -> Result<(), OtpError> {
+        let state = OtpState { sequence, seed: seed.to_string(), last: value };
+        self.store.save(&user.to_string(), state).map_err(|e| OtpError::Store(e.to_string()))
+    }
+
+    /// Issue the challenge for the user's next login.
+    pub fn challenge(&self, user: &str) -> Result<Challenge, OtpError> {
+        let state = self.load(user)?;
+        if state.sequence == 0 {
+            return Err(OtpError::Exhausted);
+        }
+        Ok(Challenge { algorithm: self.algorithm.clone(), sequence: state.sequence - 1, seed: state.seed })
+    }
+
+    /// Verify a response to the current challenge, advancing the user's state on success.
+    pub fn verify(&self, user: &str, response: &str) -> Result<(), OtpError> {
+        let value = parse_response(response)?;
+        let state = self.load(user)?;
+        if state.sequence == 0 {
+            return Err(OtpError::Exhausted);
+        }
+        let next: [u8; 8] = Folded::<H>::digest(value.to_be_bytes()).into();
+        if u64::from_be_bytes(next) != state.last {
+            return Err(OtpError::Rejected);
+        }
+        let advanced = OtpState { sequence: state.sequence - 1, seed: state.seed.clone(), last: value };
+        match self.store.compare_and_swap(&user.to_string(), Some(&state), advanced) {
+            Ok(true) => Ok(()),
+            Ok(false) => Err(OtpError::Conflict),
+            Err(e) => Err(OtpError::Store(e.to_string())),
+        }
+    }
+
+    fn load(&self, user: &str) -> Result<OtpState, OtpError> {
+        self.store.load(&user.to_string())
+            .map_err(|e| OtpError::Store(e.to_string()))?
+            .ok_or(OtpError::UnknownUser)
+    }
+}
+
+#[test]
+fn test_challenge_roundtrip() {
+    let challenge: Challenge = "otp-sha256 97 dog7841".parse().unwrap();
+    assert_eq!(challenge, Challenge { algorithm: "sha256".to_string(), sequence: 97, seed: "dog7841".to_string() });
+    assert_eq!(challenge.to_string(), "otp-sha256 97 dog7841");
+    assert!("sha256 97 dog7841".parse::<Challenge>().is_err());
+}
+
+#[test]
+fn test_server_login_flow() {
+    use crate::store::MemoryStore;
+    use sha2::Sha256;
+
+    let server = Server::<Sha256, _>::new("sha256", MemoryStore::new());
+    server.enroll("alice", "dog7841", 98, compute::<Sha256>("dog7841", "correct horse", 98)).unwrap();
+
+    let challenge = server.challenge("alice").unwrap();
+    assert_eq!(challenge.to_string(), "otp-sha256 97 dog7841");
+    let response = compute::<Sha256>(&challenge.seed, "correct horse", challenge.sequence);
+    server.verify("alice", &sixword::encode(response)).unwrap();
+
+    // the same response cannot be replayed, and the next challenge moves down the sequence
+    assert_eq!(server.verify("alice", &format!("{:016x}", response)), Err(OtpError::Rejected));
+    assert_eq!(server.challenge("alice").unwrap().sequence, 96);
+    let next = compute::<Sha256>("dog7841", "correct horse", 96);
+    server.verify("alice", &format!("{:016X}", next)).unwrap();
+    assert_eq!(server.challenge("bob"), Err(OtpError::UnknownUser));
+}
@@ -0,0 +1,125 @@
+//! HORS and HORST few-time signatures (Reyzin and Reyzin; Bernstein et al.).
+//!
+//! The secret key is `t = 2^tau` random values. A message digest is cut into `k` indices of
+//! `tau` bits each, and the signature reveals the secrets at those indices. In HORST the public
+//! key is the Merkle root over the hashed secrets, and each revealed secret comes with its
+//! authentication path. Every signature reveals `k` more secrets, so security drops with each
+//! use; a key is meant for a few signatures, which [`SigningKey::signatures`] helps keep track of.
+
+use crate::merkle::{self, AuthPath};
+use digest::generic_array::GenericArray;
+use digest::{Digest, OutputSizeUser};
+
+/// `k` revealed secrets out of `2^tau`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Params {
+    pub k: u32,
+    pub tau: u32,
+}
+
+impl Params {
+    /// Parameters for `n`-byte digests: `tau` between 1 and 20, and `k` indices that fit into the
+    /// digest.
+    pub fn new(k: u32, tau: u32, n: usize) -> Option<Self> {
+        ((1..=20).contains(&tau) && k >= 1 && k.checked_mul(tau).is_some_and(|bits| bits as usize <= 8 * n)).then_some(Params { k, tau })
+    }
+
+    /// The indices revealed when signing `digest`.
+    pub fn indices(&self, digest: &[u8]) -> Vec<u64> {
+        let bit = |i: usize| ((digest[i / 8] >> (7 - i % 8)) & 1) as u64;
+        (0..self.k as usize).map(|j| (0..self.tau as usize).fold(0, |index, b| (index << 1) | bit(j * self.tau as usize + b))).collect()
+    }
+}
+
+/// The secret at `index`. Each is derived from the seed on its own: secrets at different indices
+/// must not follow from one another, which rules out taking them as values of a single chain.
+fn secret<H: Digest>(seed: &[u8], index: u64) -> GenericArray<u8, H::OutputSize> {
+    let mut hasher = H::new_with_prefix(b"horst secret");
+    hasher.update((seed.len() as u64).to_be_bytes());
+    hasher.update(seed);
+    hasher.update(index.to_be_bytes());
+    hasher.finalize()
+}
+
+pub struct SigningKey<H: OutputSizeUser> {
+    params: Params,
+    secrets: Vec<GenericArray<u8, H::OutputSize>>,
+    /// Every level of the tree over the hashed secrets, leaves first.
+    tree: Vec<Vec<GenericArray<u8, H::OutputSize>>>,
+    signatures: u64,
+}
+
+/// A revealed secret with the path from its leaf to the public key.
+pub struct Revealed<H: OutputSizeUser> {
+    pub secret: GenericArray<u8, H::OutputSize>,
+    pub path: AuthPath<H>,
+}
+
+pub struct Signature<H: OutputSizeUser> {
+    pub revealed: Vec<Revealed<H>>,
+}
+
+/// The HORST public key, a Merkle root.
+pub struct VerifyingKey<H: OutputSizeUser> {
+    params: Params,
+    pub root: GenericArray<u8, H::OutputSize>,
+}
+
+/// Generate a key pair from the secret `seed`. Takes `2^tau` hashes for the secrets and as many
+/// again for the tree.
+pub fn generate<H: Digest>(params: Params, seed: &[u8]) -> (SigningKey<H>, VerifyingKey<H>) {
+    let secrets: Vec<_> = (0..1u64 << params.tau).map(|i| secret::<H>(seed, i)).collect();
+    let leaves: Vec<_> = secrets.iter().map(|s| merkle::leaf::<H>(s)).collect();
+    let tree = merkle::levels::<H>(&leaves);
+    let root = tree[tree.len() - 1][0].clone();
+    (SigningKey { params, secrets, tree, signatures: 0 }, VerifyingKey { params, root })
+}
+
+impl<H: Digest> SigningKey<H> {
+    /// The number of signatures made so far.
+    pub fn signatures(&self) -> u64 {
+        self.signatures
+    }
+
+    pub fn sign(&mut self, digest: &GenericArray<u8, H::OutputSize>) -> Signature<H> {
+        self.signatures += 1;
+        let revealed = self.params.indices(digest).into_iter().map(|index| Revealed { secret: self.secrets[index as usize].clone(), path: merkle::path_in(&self.tree, index) }).collect();
+        Signature { revealed }
+    }
+
+    pub fn sign_message(&mut self, message: &[u8]) -> Signature<H> {
+        self.sign(&H::digest(message))
+    }
+}
+
+impl<H: Digest> VerifyingKey<H> {
+    pub fn verify(&self, digest: &GenericArray<u8, H::OutputSize>, signature: &Signature<H>) -> bool {
+        let indices = self.params.indices(digest);
+        indices.len() == signature.revealed.len()
+            && indices.iter().zip(&signature.revealed).all(|(&index, revealed)| {
+                revealed.path.index == index && revealed.path.height() == self.params.tau && revealed.path.verify(&self.root, &merkle::leaf::<H>(&revealed.secret))
+            })
+    }
+
+    pub fn verify_message(&self, message: &[u8], signature: &Signature<H>) -> bool {
+        self.verify(&H::digest(message), signature)
+    }
+}
+
+#[test]
+fn test_horst_sign_verify() {
+    use sha2::Sha256;
+
+    let params = Params::new(16, 10, 32).unwrap();
+    assert!(Params::new(32, 10, 32).is_none());
+    assert!(Params::new(u32::MAX, 20, 32).is_none());
+    assert_eq!(params.indices(&[0xff; 32])[0], 1023);
+
+    let (mut signing, verifying) = generate::<Sha256>(params, b"horst seed");
+    let first = signing.sign_message(b"first");
+    let second = signing.sign_message(b"second");
+    assert_eq!(signing.signatures(), 2);
+    assert!(verifying.verify_message(b"first", &first));
+    assert!(verifying.verify_message(b"second", &second));
+    assert!(!verifying.verify_message(b"second", &first));
+}
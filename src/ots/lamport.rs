@@ -0,0 +1,98 @@
+//! Lamport one-time signatures over `n`-bit message digests, `n` being the hash output size.
+//!
+//! The secret key holds two random values per digest bit and the public key their hashes. A
+//! signature reveals, for every bit, the secret matching its value.
+
+use digest::generic_array::GenericArray;
+use digest::{Digest, OutputSizeUser};
+
+/// Derive the secret for bit `bit` taking value `value` from `seed`.
+fn secret<H: Digest>(seed: &[u8], bit: usize, value: u8) -> GenericArray<u8, H::OutputSize> {
+    let mut hasher = H::new_with_prefix(b"lamport secret");
+    hasher.update((seed.len() as u64).to_be_bytes());
+    hasher.update(seed);
+    hasher.update((bit as u64).to_be_bytes());
+    hasher.update([value]);
+    hasher.finalize()
+}
+
+fn bits<H: OutputSizeUser>(digest: &GenericArray<u8, H::OutputSize>) -> impl Iterator<Item = u8> + '_ {
+    digest.iter().flat_map(|byte| (0..8).rev().map(move |i| (byte >> i) & 1))
+}
+
+/// A one-time signing key. It is consumed by signing, so it cannot sign twice.
+pub struct SigningKey<H: OutputSizeUser> {
+    /// The secrets for bit `i` taking values 0 and 1 at `2i` and `2i + 1`.
+    secrets: Vec<GenericArray<u8, H::OutputSize>>,
+}
+
+pub struct VerifyingKey<H: OutputSizeUser> {
+    hashes: Vec<GenericArray<u8, H::OutputSize>>,
+}
+
+impl<H: OutputSizeUser> Clone for VerifyingKey<H> {
+    fn clone(&self) -> Self {
+        VerifyingKey { hashes: self.hashes.clone() }
+    }
+}
+
+pub struct Signature<H: OutputSizeUser> {
+    pub revealed: Vec<GenericArray<u8, H::OutputSize>>,
+}
+
+/// Generate a key pair from `seed`, which must be secret and unique to this key. The same seed
+/// always gives the same key pair.
+pub fn generate<H: Digest>(seed: &[u8]) -> (SigningKey<H>, VerifyingKey<H>) {
+    let n = <H as Digest>::output_size() * 8;
+    let secrets: Vec<_> = (0..2 * n).map(|i| secret::<H>(seed, i / 2, (i % 2) as u8)).collect();
+    let hashes = secrets.iter().map(|s| H::digest(s)).collect();
+    (SigningKey { secrets }, VerifyingKey { hashes })
+}
+
+impl<H: Digest> SigningKey<H> {
+    /// Sign a message digest, using up the key.
+    pub fn sign(self, digest: &GenericArray<u8, H::OutputSize>) -> Signature<H> {
+        let revealed = bits::<H>(digest).enumerate().map(|(i, bit)| self.secrets[2 * i + bit as usize].clone()).collect();
+        Signature { revealed }
+    }
+
+    /// Hash `message` with `H` and sign the digest.
+    pub fn sign_message(self, message: &[u8]) -> Signature<H> {
+        self.sign(&H::digest(message))
+    }
+}
+
+impl<H: Digest> VerifyingKey<H> {
+    pub fn verify(&self, digest: &GenericArray<u8, H::OutputSize>, signature: &Signature<H>) -> bool {
+        signature.revealed.len() * 2 == self.hashes.len()
+            && bits::<H>(digest).zip(&signature.revealed).enumerate().all(|(i, (bit, revealed))| H::digest(revealed) == self.hashes[2 * i + bit as usize])
+    }
+
+    pub fn verify_message(&self, message: &[u8], signature: &Signature<H>) -> bool {
+        self.verify(&H::digest(message), signature)
+    }
+
+    /// A single hash standing for the whole key, e.g. as a Merkle tree leaf.
+    pub fn fingerprint(&self) -> GenericArray<u8, H::OutputSize> {
+        let mut hasher = H::new_with_prefix(b"lamport public key");
+        for hash in &self.hashes {
+            hasher.update(hash);
+        }
+        hasher.finalize()
+    }
+}
+
+#[test]
+fn test_lamport_sign_verify() {
+    use sha2::Sha256;
+
+    let (signing, verifying) = generate::<Sha256>(b"lamport test seed");
+    let signature = signing.sign_message(b"hello");
+    assert_eq!(signature.revealed.len(), 256);
+    assert!(verifying.verify_message(b"hello", &signature));
+    assert!(!verifying.verify_message(b"hellp", &signature));
+
+    let (_, again) = generate::<Sha256>(b"lamport test seed");
+    assert_eq!(again.fingerprint(), verifying.fingerprint());
+    assert_ne!(generate::<Sha256>(b"other seed").1.fingerprint(), verifying.fingerprint());
+}
@@ -0,0 +1,8 @@
+//! Hash-based one-time signatures, the building blocks for Merkle signature schemes.
+//!
+//! A one-time key must never sign twice: every signature reveals secret material, and two
+//! signatures under the same key let anyone forge others. Signing therefore consumes the key.
+
+pub mod lamport;
+pub mod winternitz;
+pub mod horst;
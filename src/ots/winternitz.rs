@@ -0,0 +1,279 @@
+//! Winternitz one-time signatures (W-OTS).
+//!
+//! The message digest is split into base-`w` digits, followed by checksum digits that keep an
+//! attacker from increasing any digit. Each digit gets a chain of `w - 1` [`ChainStep`]s: the
+//! secret key holds the chain starts and the public key their ends, and a signature reveals the
+//! value `d` steps along for digit `d`. The verifier walks the remaining `w - 1 - d` steps. A
+//! larger `w` gives shorter signatures at the cost of more hashing.
+//!
+//! [`MaskedStep`] turns this into WOTS+ (Hülsing, 2013), the variant used by XMSS and SPHINCS+:
+//! every step XORs a per-position mask into the value before hashing it under a key, both
+//! derived from a public seed. Its security then rests on second-preimage resistance rather
+//! than collision resistance, which allows shorter hashes.
+
+use crate::{ChainStep, PlainStep};
+use digest::generic_array::GenericArray;
+use digest::{Digest, OutputSizeUser};
+
+/// The Winternitz parameter `w`: 2, 4, 16 or 256.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Params {
+    log_w: u32,
+}
+
+impl Params {
+    pub fn new(w: u32) -> Option<Self> {
+        matches!(w, 2 | 4 | 16 | 256).then(|| Params { log_w: w.trailing_zeros() })
+    }
+
+    pub fn w(&self) -> u32 {
+        1 << self.log_w
+    }
+
+    /// The number of message digits and checksum digits for an `n`-byte digest.
+    pub fn lengths(&self, n: usize) -> (usize, usize) {
+        let len1 = (8 * n).div_ceil(self.log_w as usize);
+        let max_checksum = len1 as u64 * (self.w() as u64 - 1);
+        let len2 = (u64::BITS - max_checksum.leading_zeros() - 1) / self.log_w + 1;
+        (len1, len2 as usize)
+    }
+
+    /// The number of chains, and of values in a key or signature, for an `n`-byte digest.
+    pub fn chains(&self, n: usize) -> usize {
+        let (len1, len2) = self.lengths(n);
+        len1 + len2
+    }
+
+    fn base_w(&self, bytes: &[u8], digits: usize) -> Vec<u32> {
+        let (mut total, mut bits) = (0u32, 0u32);
+        let mut bytes = bytes.iter();
+        (0..digits)
+            .map(|_| {
+                if bits == 0 {
+                    total = *bytes.next().unwrap_or(&0) as u32;
+                    bits = 8;
+                }
+                bits -= self.log_w;
+                (total >> bits) & (self.w() - 1)
+            })
+            .collect()
+    }
+
+    /// The digits of `digest` followed by its checksum digits, as in RFC 8391.
+    pub fn digits(&self, digest: &[u8]) -> Vec<u32> {
+        let (len1, len2) = self.lengths(digest.len());
+        let mut digits = self.base_w(digest, len1);
+        let checksum: u64 = digits.iter().map(|&d| (self.w() - 1 - d) as u64).sum();
+        let checksum_bits = len2 * self.log_w as usize;
+        let shifted = checksum << ((8 - checksum_bits % 8) % 8);
+        let checksum_bytes = shifted.to_be_bytes();
+        digits.extend(self.base_w(&checksum_bytes[8 - checksum_bits.div_ceil(8)..], len2));
+        digits
+    }
+}
+
+/// The WOTS+ chain step `H(key || (value ^ mask_i))`, with the key and the `w - 1` masks derived
+/// from a public seed that is part of the public key.
+pub struct MaskedStep<H: OutputSizeUser> {
+    public_seed: Vec<u8>,
+    key: GenericArray<u8, H::OutputSize>,
+    masks: Vec<GenericArray<u8, H::OutputSize>>,
+}
+
+impl<H: OutputSizeUser> Clone for MaskedStep<H> {
+    fn clone(&self) -> Self {
+        MaskedStep { public_seed: self.public_seed.clone(), key: self.key.clone(), masks: self.masks.clone() }
+    }
+}
+
+impl<H: Digest> MaskedStep<H> {
+    pub fn new(params: Params, public_seed: &[u8]) -> Self {
+        let derive = |label: &[u8], index: u64| {
+            let mut hasher = H::new_with_prefix(label);
+            hasher.update((public_seed.len() as u64).to_be_bytes());
+            hasher.update(public_seed);
+            hasher.update(index.to_be_bytes());
+            hasher.finalize()
+        };
+        let masks = (1..params.w() as u64).map(|i| derive(b"wots+ mask", i)).collect();
+        MaskedStep { public_seed: public_seed.to_vec(), key: derive(b"wots+ key", 0), masks }
+    }
+
+    pub fn public_seed(&self) -> &[u8] {
+        &self.public_seed
+    }
+}
+
+impl<H: Digest> ChainStep<H> for MaskedStep<H> {
+    /// Panics if `index` is not below `w - 1`.
+    fn step(&self, value: &GenericArray<u8, H::OutputSize>, index: u64) -> GenericArray<u8, H::OutputSize> {
+        let mut masked = value.clone();
+        masked.iter_mut().zip(&self.masks[index as usize]).for_each(|(byte, mask)| *byte ^= mask);
+        H::new_with_prefix(&self.key).chain_update(masked).finalize()
+    }
+}
+
+fn secret<H: Digest>(seed: &[u8], chain: usize) -> GenericArray<u8, H::OutputSize> {
+    let mut hasher = H::new_with_prefix(b"winternitz secret");
+    hasher.update((seed.len() as u64).to_be_bytes());
+    hasher.update(seed);
+    hasher.update((chain as u64).to_be_bytes());
+    hasher.finalize()
+}
+
+/// A one-time signing key, consumed by signing.
+pub struct SigningKey<H: OutputSizeUser, S = PlainStep<H>> {
+    params: Params,
+    step: S,
+    secrets: Vec<GenericArray<u8, H::OutputSize>>,
+}
+
+pub struct VerifyingKey<H: OutputSizeUser, S = PlainStep<H>> {
+    params: Params,
+    step: S,
+    /// The chain ends, one per digit.
+    pub ends: Vec<GenericArray<u8, H::OutputSize>>,
+}
+
+pub struct Signature<H: OutputSizeUser> {
+    pub values: Vec<GenericArray<u8, H::OutputSize>>,
+}
+
+impl<H: OutputSizeUser> Clone for Signature<H> {
+    fn clone(&self) -> Self {
+        Signature { values: self.values.clone() }
+    }
+}
+
+/// Generate a key pair with plain hash steps from the secret `seed`.
+pub fn generate<H: Digest>(params: Params, seed: &[u8]) -> (SigningKey<H>, VerifyingKey<H>) {
+    generate_with(params, PlainStep::new(), seed)
+}
+
+/// Generate a key pair whose chains use `step`.
+pub fn generate_with<H: Digest, S: ChainStep<H> + Clone>(params: Params, step: S, seed: &[u8]) -> (SigningKey<H, S>, VerifyingKey<H, S>) {
+    let last = params.w() as u64 - 1;
+    let secrets: Vec<_> = (0..params.chains(<H as Digest>::output_size())).map(|i| secret::<H>(seed, i)).collect();
+    let ends = secrets.iter().map(|s| step.walk(s, 0, last)).collect();
+    (SigningKey { params, step: step.clone(), secrets }, VerifyingKey { params, step, ends })
+}
+
+/// Generate a WOTS+ key pair: [`MaskedStep`]s from `public_seed`, secrets from `seed`.
+pub fn generate_plus<H: Digest>(params: Params, public_seed: &[u8], seed: &[u8]) -> (SigningKey<H, MaskedStep<H>>, VerifyingKey<H, MaskedStep<H>>) {
+    generate_with(params, MaskedStep::new(params, public_seed), seed)
+}
+
+/// The chain ends a signature on `digest` points to, which match the public key exactly when
+/// the signature is valid. Merkle schemes compare these against a leaf instead of a stored key.
+pub fn recover<H: Digest, S: ChainStep<H>>(params: Params, step: &S, digest: &[u8], signature: &Signature<H>) -> Option<Vec<GenericArray<u8, H::OutputSize>>> {
+    let digits = params.digits(digest);
+    if signature.values.len() != digits.len() {
+        return None;
+    }
+    let last = params.w() as u64 - 1;
+    Some(digits.iter().zip(&signature.values).map(|(&d, value)| step.walk(value, d as u64, last - d as u64)).collect())
+}
+
+impl<H: Digest, S: ChainStep<H>> SigningKey<H, S> {
+    pub fn params(&self) -> Params {
+        self.params
+    }
+
+    /// Sign a message digest, using up the key.
+    pub fn sign(self, digest: &GenericArray<u8, H::OutputSize>) -> Signature<H> {
+        let digits = self.params.digits(digest);
+        let values = digits.iter().zip(&self.secrets).map(|(&d, secret)| self.step.walk(secret, 0, d as u64)).collect();
+        Signature { values }
+    }
+
+    pub fn sign_message(self, message: &[u8]) -> Signature<H> {
+        self.sign(&H::digest(message))
+    }
+}
+
+impl<H: Digest, S: ChainStep<H>> VerifyingKey<H, S> {
+    pub fn params(&self) -> Params {
+        self.params
+    }
+
+    pub fn step(&self) -> &S {
+        &self.step
+    }
+
+    pub fn verify(&self, digest: &GenericArray<u8, H::OutputSize>, signature: &Signature<H>) -> bool {
+        recover(self.params, &self.step, digest, signature).is_some_and(|ends| ends == self.ends)
+    }
+
+    pub fn verify_message(&self, message: &[u8], signature: &Signature<H>) -> bool {
+        self.verify(&H::digest(message), signature)
+    }
+
+    /// A single hash standing for the whole key, e.g. as a Merkle tree leaf.
+    pub fn fingerprint(&self) -> GenericArray<u8, H::OutputSize> {
+        fingerprint::<H>(&self.ends)
+    }
+}
+
+/// Compress chain ends, as returned by [`recover`], into a key fingerprint.
+pub fn fingerprint<H: Digest>(ends: &[GenericArray<u8, H::OutputSize>]) -> GenericArray<u8, H::OutputSize> {
+    let mut hasher = H::new_with_prefix(b"winternitz public key");
+    for end in ends {
+        hasher.update(end);
+    }
+    hasher.finalize()
+}
+
+#[test]
+fn test_winternitz_params() {
+    // the W-OTS+ lengths of RFC 8391 for n = 32
+    assert_eq!(Params::new(16).unwrap().lengths(32), (64, 3));
+    assert_eq!(Params::new(4).unwrap().lengths(32), (128, 5));
+    assert_eq!(Params::new(256).unwrap().lengths(32), (32, 2));
+    assert!(Params::new(8).is_none());
+
+    let params = Params::new(16).unwrap();
+    let digits = params.digits(&[0xff; 32]);
+    assert_eq!(&digits[64..], &[0, 0, 0]);
+    let digits = params.digits(&[0; 32]);
+    assert_eq!(&digits[64..], &[3, 12, 0]);
+}
+
+#[test]
+fn test_winternitz_sign_verify() {
+    use sha2::Sha256;
+
+    for w in [4, 16, 256] {
+        let params = Params::new(w).unwrap();
+        let (signing, verifying) = generate::<Sha256>(params, b"winternitz seed");
+        let signature = signing.sign_message(b"message");
+        assert_eq!(signature.values.len(), params.chains(32));
+        assert!(verifying.verify_message(b"message", &signature));
+        assert!(!verifying.verify_message(b"messagf", &signature));
+        let ends = recover(params, &PlainStep::<Sha256>::new(), &Sha256::digest(b"message"), &signature).unwrap();
+        assert_eq!(fingerprint::<Sha256>(&ends), verifying.fingerprint());
+    }
+}
+
+#[test]
+fn test_wots_plus() {
+    use sha2::Sha256;
+
+    let params = Params::new(16).unwrap();
+    let (signing, verifying) = generate_plus::<Sha256>(params, b"public seed", b"secret seed");
+    let (_, plain) = generate::<Sha256>(params, b"secret seed");
+    assert_ne!(verifying.ends, plain.ends);
+
+    let signature = signing.sign_message(b"message");
+    assert!(verifying.verify_message(b"message", &signature));
+    assert!(!plain.verify_message(b"message", &signature));
+    let other = MaskedStep::<Sha256>::new(params, b"other public seed");
+    let ends = recover(params, &other, &Sha256::digest(b"message"), &signature).unwrap();
+    assert_ne!(ends, verifying.ends);
+
+    // HMAC steps through the same machinery
+    let step = crate::MacStep::<hmac::SimpleHmac<Sha256>>::new_from_slice(b"chain key").unwrap();
+    let (signing, verifying) = generate_with::<Sha256, _>(params, step, b"secret seed");
+    let signature = signing.sign_message(b"message");
+    assert!(verifying.verify_message(b"message", &signature));
+    assert!(!plain.verify_message(b"message", &signature));
+}
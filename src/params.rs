@@ -0,0 +1,81 @@
+//! Chain parameters checked at compile time, for firmware that fixes them when it is built.
+//!
+//! The `const fn`s here make the same checks as [`HashChain::new`](crate::HashChain::new) and
+//! fail with the same messages, and [`assert_valid_chain_params!`](crate::assert_valid_chain_params)
+//! turns a failure into a compile error, so a bad length never makes it to a device to fail
+//! with a [`ChainInitError`](crate::ChainInitError) there. A chain of `length` values takes
+//! `log2(length)` pebbles, which is also the `LOG_N` of a
+//! [`HashChainFixed`](crate::fixed::HashChainFixed).
+
+/// The longest personalization [`HashChain::new_personalized`](crate::HashChain::new_personalized)
+/// accepts.
+pub const MAX_PERSONALIZATION: usize = 255;
+
+/// Check a chain length: a power of two, at least 2.
+pub const fn check_chain_length(length: u64) -> Result<(), &'static str> {
+    if !length.is_power_of_two() {
+        return Err("length not a power of two");
+    }
+    if length < 2 {
+        return Err("length must be at least 2");
+    }
+    Ok(())
+}
+
+/// The number of pebbles a chain of `length` values takes, for a valid length.
+pub const fn pebbles_for(length: u64) -> u32 {
+    length.trailing_zeros()
+}
+
+/// Check a chain length together with the number of pebbles it is meant to take, e.g. the size
+/// of a statically allocated pebble array.
+pub const fn check_chain_params(length: u64, pebbles: usize) -> Result<(), &'static str> {
+    if let Err(message) = check_chain_length(length) {
+        return Err(message);
+    }
+    if pebbles != pebbles_for(length) as usize {
+        return Err("pebble count is not log2 of the length");
+    }
+    Ok(())
+}
+
+/// Check the length of a personalization string.
+pub const fn check_personalization(len: usize) -> Result<(), &'static str> {
+    if len > MAX_PERSONALIZATION {
+        return Err("personalization must be shorter than 256 bytes");
+    }
+    Ok(())
+}
+
+/// Fail compilation unless a chain of `$length` values takes `$pebbles` pebbles, e.g.
+/// `assert_valid_chain_params!(1024, 10);` at item level. With just a length only the length is
+/// checked.
+#[macro_export]
+macro_rules! assert_valid_chain_params {
+    ($length:expr) => {
+        const _: () = if let Err(message) = $crate::params::check_chain_length($length as u64) {
+            panic!("{}", message)
+        };
+    };
+    ($length:expr, $pebbles:expr) => {
+        const _: () = if let Err(message) = $crate::params::check_chain_params($length as u64, $pebbles as usize) {
+            panic!("{}", message)
+        };
+    };
+}
+
+#[test]
+fn test_chain_params() {
+    const LENGTH: usize = 1 << 20;
+    crate::assert_valid_chain_params!(LENGTH, 20);
+    crate::assert_valid_chain_params!(2);
+
+    assert_eq!(check_chain_length(1000), Err("length not a power of two"));
+    assert_eq!(check_chain_length(1), Err("length must be at least 2"));
+    assert_eq!(check_chain_params(1024, 9), Err("pebble count is not log2 of the length"));
+    assert_eq!(check_personalization(256), Err("personalization must be shorter than 256 bytes"));
+    // the same verdicts as at runtime
+    for length in [0, 1, 2, 3, 16, 1000, 1024] {
+        assert_eq!(check_chain_length(length as u64).is_ok(), crate::HashChain::<sha2::Sha256>::new(length, 0).is_ok());
+    }
+}
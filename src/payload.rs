@@ -0,0 +1,169 @@
+//! Chains whose links commit to a schedule of messages, e.g. announcements published ahead of
+//! time and released one by one.
+//!
+//! The step down to position `i - 1` hashes the value at `i` together with the digest of message
+//! `i`, `v_(i-1) = H(v_i || H(m_i))`, so the anchor commits to every message of the schedule and
+//! disclosing the value at `i` with message `i` authenticates the message. A receiver that missed
+//! disclosures needs the digests of the messages in between, which the disclosure carries; the
+//! messages themselves stay undisclosed until their turn.
+//!
+//! The prover keeps every value and digest, so the schedule has to fit in memory.
+
+use alloc::vec::Vec;
+use core::error::Error;
+use core::fmt::{self, Display};
+use digest::{Digest, Output, OutputSizeUser};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PayloadError {
+    /// The index is not above the last accepted one.
+    Replay,
+    /// The disclosure does not carry a digest for every skipped position.
+    MissingDigests,
+    /// The value and message do not hash to the last accepted value.
+    Mismatch,
+}
+
+impl Display for PayloadError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            PayloadError::Replay => write!(f, "index already accepted"),
+            PayloadError::MissingDigests => write!(f, "digests of skipped messages missing"),
+            PayloadError::Mismatch => write!(f, "value and message do not match the chain"),
+        }
+    }
+}
+
+impl Error for PayloadError {}
+
+/// `H(value || digest)`, the step from position `i` to `i - 1` with message `i`'s digest.
+fn step<H: Digest>(value: &Output<H>, digest: &Output<H>) -> Output<H> {
+    H::new().chain_update(value).chain_update(digest).finalize()
+}
+
+pub struct PayloadDisclosure<H: OutputSizeUser> {
+    pub index: u64,
+    pub value: Output<H>,
+    pub message: Vec<u8>,
+    /// The digests of the messages after the receiver's index and before `index`, in order.
+    pub skipped: Vec<Output<H>>,
+}
+
+impl<H: OutputSizeUser> Clone for PayloadDisclosure<H> {
+    fn clone(&self) -> Self {
+        PayloadDisclosure { index: self.index, value: self.value.clone(), message: self.message.clone(), skipped: self.skipped.clone() }
+    }
+}
+
+pub struct PayloadChain<H: Digest> {
+    messages: Vec<Vec<u8>>,
+    /// The digest of message `i` at offset `i - 1`.
+    digests: Vec<Output<H>>,
+    /// The value at position `i` at offset `i`, the anchor's first.
+    values: Vec<Output<H>>,
+    position: u64,
+}
+
+impl<H: Digest> PayloadChain<H> {
+    /// A chain over `messages`, message `i` at position `i` counting from 1, from `seed`.
+    pub fn new<M: AsRef<[u8]>>(messages: &[M], seed: u64) -> Self {
+        let messages: Vec<Vec<u8>> = messages.iter().map(|message| message.as_ref().to_vec()).collect();
+        let digests: Vec<Output<H>> = messages.iter().map(|message| H::digest(message)).collect();
+        let mut values = Vec::with_capacity(messages.len() + 1);
+        values.push(H::digest(seed.to_le_bytes()));
+        for digest in digests.iter().rev() {
+            values.push(step::<H>(values.last().expect("the seed's hash is there"), digest));
+        }
+        values.reverse();
+        PayloadChain { messages, digests, values, position: 0 }
+    }
+
+    pub fn anchor(&self) -> &Output<H> {
+        &self.values[0]
+    }
+
+    pub fn length(&self) -> u64 {
+        self.messages.len() as u64
+    }
+
+    pub fn position(&self) -> u64 {
+        self.position
+    }
+
+    /// Disclose the next value and message to a receiver that last accepted index `known`, 0
+    /// if it only has the anchor. `None` once the schedule is done, or if `known` has not been
+    /// disclosed.
+    pub fn disclose(&mut self, known: u64) -> Option<PayloadDisclosure<H>> {
+        let index = self.position + 1;
+        if index > self.length() || known > self.position {
+            return None;
+        }
+        self.position = index;
+        Some(PayloadDisclosure {
+            index,
+            value: self.values[index as usize].clone(),
+            message: self.messages[index as usize - 1].clone(),
+            skipped: self.digests[known as usize..index as usize - 1].to_vec(),
+        })
+    }
+}
+
+pub struct PayloadVerifier<H: OutputSizeUser> {
+    index: u64,
+    value: Output<H>,
+}
+
+impl<H: Digest> PayloadVerifier<H> {
+    pub fn new(anchor: Output<H>) -> Self {
+        PayloadVerifier { index: 0, value: anchor }
+    }
+
+    /// The last accepted index, the one to pass to [`PayloadChain::disclose`].
+    pub fn index(&self) -> u64 {
+        self.index
+    }
+
+    /// Check a disclosure and return its message, now authenticated.
+    pub fn accept<'a>(&mut self, disclosure: &'a PayloadDisclosure<H>) -> Result<&'a [u8], PayloadError> {
+        if disclosure.index <= self.index {
+            return Err(PayloadError::Replay);
+        }
+        if disclosure.skipped.len() as u64 != disclosure.index - self.index - 1 {
+            return Err(PayloadError::MissingDigests);
+        }
+        let below = step::<H>(&disclosure.value, &H::digest(&disclosure.message));
+        let reached = disclosure.skipped.iter().rev().fold(below, |value, digest| step::<H>(&value, digest));
+        if reached != self.value {
+            return Err(PayloadError::Mismatch);
+        }
+        (self.index, self.value) = (disclosure.index, disclosure.value.clone());
+        Ok(&disclosure.message)
+    }
+}
+
+#[test]
+fn test_payload_chain() {
+    use sha2::Sha256;
+
+    let schedule = ["monday", "tuesday", "wednesday", "thursday", "friday"];
+    let mut prover = PayloadChain::<Sha256>::new(&schedule, 3);
+    let mut verifier = PayloadVerifier::new(*prover.anchor());
+    let first = prover.disclose(verifier.index()).unwrap();
+    assert_eq!(verifier.accept(&first), Ok(&b"monday"[..]));
+    assert_eq!(verifier.accept(&first), Err(PayloadError::Replay));
+
+    // a receiver that missed two announcements catches up from the digests
+    prover.disclose(1).unwrap();
+    prover.disclose(2).unwrap();
+    let late = prover.disclose(verifier.index()).unwrap();
+    assert_eq!(late.skipped.len(), 2);
+    let mut forged = late.clone();
+    forged.message = b"saturday".to_vec();
+    assert_eq!(verifier.accept(&forged), Err(PayloadError::Mismatch));
+    let mut short = late.clone();
+    short.skipped.pop();
+    assert_eq!(verifier.accept(&short), Err(PayloadError::MissingDigests));
+    assert_eq!(verifier.accept(&late), Ok(&b"thursday"[..]));
+    prover.disclose(4).unwrap();
+    assert!(prover.disclose(5).is_none());
+}
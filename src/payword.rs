@@ -0,0 +1,239 @@
+//! The PayWord micropayment scheme of Rivest and Shamir.
+//!
+//! The payer signs a [`Commitment`] naming the vendor and the chain anchor, then pays one unit
+//! per chain value disclosed to that vendor. The vendor checks each payment with a few hashes
+//! and, at the end of the day, hands the broker a [`Redemption`]: the signed commitment plus the
+//! last payment, which proves the total amount owed.
+//!
+//! Signing is left to the caller through closures over the canonical commitment bytes, so any
+//! signature scheme (and any key storage) can be used.
+
+use crate::{verify, HashChain};
+use digest::generic_array::GenericArray;
+use digest::{Digest, FixedOutputReset, OutputSizeUser};
+use std::error::Error;
+use std::fmt::{self, Display};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PayWordError {
+    /// The commitment signature did not verify.
+    BadSignature,
+    /// The commitment names a different vendor.
+    WrongVendor,
+    /// The commitment anchor has the wrong length for the hash.
+    MalformedCommitment,
+    /// The payment does not extend the previous one.
+    InvalidPayment,
+    /// The payment goes past the committed chain length.
+    Overspent,
+}
+
+impl Display for PayWordError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            PayWordError::BadSignature => write!(f, "commitment signature invalid"),
+            PayWordError::WrongVendor => write!(f, "commitment is for another vendor"),
+            PayWordError::MalformedCommitment => write!(f, "malformed commitment"),
+            PayWordError::InvalidPayment => write!(f, "payment does not extend the chain"),
+            PayWordError::Overspent => write!(f, "payment exceeds the committed chain length"),
+        }
+    }
+}
+
+impl Error for PayWordError {}
+
+/// The payer's commitment to a chain for one vendor.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Commitment {
+    pub vendor: String,
+    /// The payer's certificate as issued by the broker.
+    pub certificate: Vec<u8>,
+    pub anchor: Vec<u8>,
+    /// The number of payable units.
+    pub length: u64,
+    /// Seconds since the Unix epoch; commitments are valid for the day they are made.
+    pub date: u64,
+    pub info: Vec<u8>,
+}
+
+impl Commitment {
+    /// The canonical encoding that is signed: a domain tag followed by every field, byte
+    /// strings prefixed by their 32-bit big-endian length.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        fn put(out: &mut Vec<u8>, bytes: &[u8]) {
+            out.extend_from_slice(&(bytes.len() as u32).to_be_bytes());
+            out.extend_from_slice(bytes);
+        }
+        let mut out = Vec::new();
+        put(&mut out, b"payword-commitment-v1");
+        put(&mut out, self.vendor.as_bytes());
+        put(&mut out, &self.certificate);
+        put(&mut out, &self.anchor);
+        out.extend_from_slice(&self.length.to_be_bytes());
+        out.extend_from_slice(&self.date.to_be_bytes());
+        put(&mut out, &self.info);
+        out
+    }
+}
+
+/// A commitment together with the payer's signature over [`Commitment::to_bytes`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SignedCommitment {
+    pub commitment: Commitment,
+    pub signature: Vec<u8>,
+}
+
+impl SignedCommitment {
+    /// Check the signature with `verify_signature(message, signature)`.
+    pub fn verify<F: Fn(&[u8], &[u8]) -> bool>(&self, verify_signature: F) -> Result<(), PayWordError> {
+        if verify_signature(&self.commitment.to_bytes(), &self.signature) {
+            Ok(())
+        } else {
+            Err(PayWordError::BadSignature)
+        }
+    }
+}
+
+/// A payment: the chain value at `index`, paying `index` units in total.
+pub struct Payment<H: OutputSizeUser> {
+    pub index: u64,
+    pub value: GenericArray<u8, H::OutputSize>,
+}
+
+impl<H: OutputSizeUser> Clone for Payment<H> {
+    fn clone(&self) -> Self {
+        Payment { index: self.index, value: self.value.clone() }
+    }
+}
+
+/// The payer side.
+pub struct Payer<H: Digest + FixedOutputReset> {
+    chain: HashChain<H>,
+    commitment: SignedCommitment,
+}
+
+impl<H: Digest + FixedOutputReset> Payer<H> {
+    /// Commit to `chain` for `vendor`, signing the commitment with `sign`.
+    pub fn new<F: FnOnce(&[u8]) -> Vec<u8>>(chain: HashChain<H>, vendor: &str, certificate: Vec<u8>, date: u64, info: Vec<u8>, sign: F) -> Self {
+        let commitment = Commitment {
+            vendor: vendor.to_string(),
+            certificate,
+            anchor: chain.anchor().to_vec(),
+            length: chain.length(),
+            date,
+            info,
+        };
+        let signature = sign(&commitment.to_bytes());
+        Payer { chain, commitment: SignedCommitment { commitment, signature } }
+    }
+
+    /// The signed commitment to send to the vendor before paying.
+    pub fn commitment(&self) -> &SignedCommitment {
+        &self.commitment
+    }
+
+    /// Pay `units` more units, or `None` if the chain does not have that many left.
+    pub fn pay(&mut self, units: u64) -> Option<Payment<H>> {
+        if units == 0 || units > self.chain.remaining() {
+            return None;
+        }
+        let (index, value) = self.chain.by_ref().nth(units as usize - 1)?;
+        Some(Payment { index, value: value.to_array() })
+    }
+}
+
+/// The vendor side: accepts payments under one commitment.
+pub struct Vendor<H: Digest + FixedOutputReset> {
+    commitment: SignedCommitment,
+    last: Payment<H>,
+}
+
+impl<H: Digest + FixedOutputReset> Vendor<H> {
+    /// Accept a commitment addressed to `vendor` whose signature passes `verify_signature`.
+    pub fn new<F: Fn(&[u8], &[u8]) -> bool>(vendor: &str, commitment: SignedCommitment, verify_signature: F) -> Result<Self, PayWordError> {
+        commitment.verify(verify_signature)?;
+        if commitment.commitment.vendor != vendor {
+            return Err(PayWordError::WrongVendor);
+        }
+        if commitment.commitment.anchor.len() != <H as Digest>::output_size() {
+            return Err(PayWordError::MalformedCommitment);
+        }
+        let anchor = GenericArray::clone_from_slice(&commitment.commitment.anchor);
+        Ok(Vendor { commitment, last: Payment { index: 0, value: anchor } })
+    }
+
+    /// Verify a payment, returning the number of new units it pays.
+    pub fn receive(&mut self, payment: &Payment<H>) -> Result<u64, PayWordError> {
+        if payment.index > self.commitment.commitment.length {
+            return Err(PayWordError::Overspent);
+        }
+        if !verify::<H>(self.last.index, &self.last.value, payment.index, &payment.value) {
+            return Err(PayWordError::InvalidPayment);
+        }
+        let units = payment.index - self.last.index;
+        self.last = payment.clone();
+        Ok(units)
+    }
+
+    /// The total received so far.
+    pub fn total(&self) -> u64 {
+        self.last.index
+    }
+
+    /// The proof to hand the broker, or `None` if nothing was paid.
+    pub fn redemption(&self) -> Option<Redemption<H>> {
+        if self.last.index == 0 {
+            return None;
+        }
+        Some(Redemption { commitment: self.commitment.clone(), payment: self.last.clone() })
+    }
+}
+
+/// What a vendor redeems with the broker.
+pub struct Redemption<H: OutputSizeUser> {
+    pub commitment: SignedCommitment,
+    pub payment: Payment<H>,
+}
+
+impl<H: Digest + FixedOutputReset> Redemption<H> {
+    /// The broker's check: returns the amount owed to the vendor.
+    pub fn verify<F: Fn(&[u8], &[u8]) -> bool>(&self, verify_signature: F) -> Result<u64, PayWordError> {
+        self.commitment.verify(verify_signature)?;
+        let commitment = &self.commitment.commitment;
+        if commitment.anchor.len() != <H as Digest>::output_size() {
+            return Err(PayWordError::MalformedCommitment);
+        }
+        if self.payment.index > commitment.length {
+            return Err(PayWordError::Overspent);
+        }
+        let anchor = GenericArray::clone_from_slice(&commitment.anchor);
+        if !verify::<H>(0, &anchor, self.payment.index, &self.payment.value) {
+            return Err(PayWordError::InvalidPayment);
+        }
+        Ok(self.payment.index)
+    }
+}
+
+#[test]
+fn test_payword_flow() {
+    use sha2::Sha256;
+
+    // a stand-in signature scheme: the "signature" is a keyed hash of the message
+    let sign = |message: &[u8]| Sha256::new_with_prefix(b"payer key").chain_update(message).finalize().to_vec();
+    let check = |message: &[u8], signature: &[u8]| sign(message) == signature;
+
+    let mut payer = Payer::new(HashChain::<Sha256>::new(8, 5).unwrap(), "shop", b"cert".to_vec(), 1_700_000_000, vec![], sign);
+    assert_eq!(Vendor::<Sha256>::new("other", payer.commitment().clone(), check).err(), Some(PayWordError::WrongVendor));
+    let mut vendor = Vendor::<Sha256>::new("shop", payer.commitment().clone(), check).unwrap();
+
+    assert_eq!(vendor.receive(&payer.pay(2).unwrap()), Ok(2));
+    let payment = payer.pay(3).unwrap();
+    assert_eq!(vendor.receive(&payment), Ok(3));
+    assert_eq!(vendor.receive(&payment), Err(PayWordError::InvalidPayment));
+
+    let redemption = vendor.redemption().unwrap();
+    assert_eq!(redemption.verify(check), Ok(5));
+    let mut tampered = vendor.redemption().unwrap();
+    tampered.commitment.commitment.length = 1000;
+    assert_eq!(tampered.verify(check), Err(PayWordError::BadSignature));
+}
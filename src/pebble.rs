@@ -0,0 +1,231 @@
+//! Pebbles, the stored chain values a traversal moves along the chain.
+
+use alloc::vec::Vec;
+use core::fmt::{self, Debug, Display};
+use crate::value::Hex;
+use digest::generic_array::GenericArray;
+use digest::OutputSizeUser;
+#[cfg(feature = "sha2")]
+use {digest::Digest, sha2::Sha256};
+
+pub struct Pebble<H: OutputSizeUser> {
+    pub(crate) start_incr: u64,
+    pub(crate) dest_incr: u64,
+    pub(crate) position: u64,
+    pub(crate) destination: u64,
+    pub(crate) value: GenericArray<u8, H::OutputSize>,
+}
+
+impl<H: OutputSizeUser> Clone for Pebble<H> {
+    fn clone(&self) -> Self {
+        Pebble { value: self.value.clone(), ..*self }
+    }
+}
+
+impl<H: OutputSizeUser> PartialEq for Pebble<H> {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == core::cmp::Ordering::Equal
+    }
+}
+
+impl<H: OutputSizeUser> Eq for Pebble<H> {}
+
+/// Pebbles are ordered by position, then by the rest of their state, so that sorting a
+/// traversal's pebbles lines them up along the chain.
+impl<H: OutputSizeUser> Ord for Pebble<H> {
+    fn cmp(&self, other: &Self) -> core::cmp::Ordering {
+        (self.position, self.destination, self.start_incr, self.dest_incr, &self.value)
+            .cmp(&(other.position, other.destination, other.start_incr, other.dest_incr, &other.value))
+    }
+}
+
+impl<H: OutputSizeUser> PartialOrd for Pebble<H> {
+    fn partial_cmp(&self, other: &Self) -> Option<core::cmp::Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl<H: OutputSizeUser> core::hash::Hash for Pebble<H> {
+    fn hash<S: core::hash::Hasher>(&self, state: &mut S) {
+        (self.position, self.destination, self.start_incr, self.dest_incr, &self.value).hash(state);
+    }
+}
+
+impl<H: OutputSizeUser> Pebble<H> {
+    /// The position whose value the pebble currently holds.
+    pub fn position(&self) -> u64 {
+        self.position
+    }
+
+    /// The position the pebble is moving down to; it rests once it gets there.
+    pub fn destination(&self) -> u64 {
+        self.destination
+    }
+}
+
+impl<H: OutputSizeUser> Pebble<H> {
+    /// A short identifier of the value: the first 4 bytes of its SHA-256 hash. It tells values
+    /// apart in logs without revealing them.
+    #[cfg(feature = "sha2")]
+    pub fn fingerprint(&self) -> [u8; 4] {
+        let digest = Sha256::new_with_prefix(b"pebble fingerprint").chain_update(&self.value).finalize();
+        digest[..4].try_into().expect("SHA-256 output is 32 bytes")
+    }
+
+    /// The pebble with its full value for `Display` and `Debug`. A pebble's value is a future
+    /// chain value, so the output must not end up anywhere an attacker could read it.
+    pub fn danger_reveal(&self) -> Revealed<'_, H> {
+        Revealed(self)
+    }
+
+    fn fmt_with(&self, f: &mut fmt::Formatter<'_>, value: &dyn Display) -> fmt::Result {
+        write!(f, "Pebble {{start_incr: {}, dest_incr: {}, position: {}, destination: {}, value: {}}}", self.start_incr, self.dest_incr, self.position, self.destination, value)
+    }
+}
+
+/// Formats the value as a [`Pebble::fingerprint`] only, or not at all without `sha2`.
+impl<H: OutputSizeUser> Display for Pebble<H> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        #[cfg(feature = "sha2")]
+        return self.fmt_with(f, &format_args!("<redacted {:x}>", Hex(&self.fingerprint())));
+        #[cfg(not(feature = "sha2"))]
+        self.fmt_with(f, &"<redacted>")
+    }
+}
+
+impl<H: OutputSizeUser> Debug for Pebble<H> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        Display::fmt(self, f)
+    }
+}
+
+/// A [`Pebble`] formatted with its full value, from [`Pebble::danger_reveal`].
+pub struct Revealed<'a, H: OutputSizeUser>(&'a Pebble<H>);
+
+impl<H: OutputSizeUser> Display for Revealed<'_, H> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.0.fmt_with(f, &format_args!("{:x}", Hex(&self.0.value)))
+    }
+}
+
+impl<H: OutputSizeUser> Debug for Revealed<'_, H> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        Display::fmt(self, f)
+    }
+}
+
+/// The pebbles of a traversal. With the `mlock` feature their memory is locked into RAM and
+/// zeroed when dropped, see [`locked`](crate::locked).
+pub(crate) struct PebbleBuf<H: OutputSizeUser> {
+    pebbles: Vec<Pebble<H>>,
+    /// The range locked, if locking succeeded.
+    #[cfg(feature = "mlock")]
+    locked: Option<(usize, usize)>,
+}
+
+impl<H: OutputSizeUser> PebbleBuf<H> {
+    #[cfg(feature = "mlock")]
+    pub(crate) fn is_locked(&self) -> bool {
+        self.locked.is_some()
+    }
+}
+
+impl<H: OutputSizeUser> From<Vec<Pebble<H>>> for PebbleBuf<H> {
+    fn from(pebbles: Vec<Pebble<H>>) -> Self {
+        #[cfg(feature = "mlock")]
+        {
+            let len = pebbles.capacity() * core::mem::size_of::<Pebble<H>>();
+            let locked = crate::locked::lock(pebbles.as_ptr().cast(), len).then_some((pebbles.as_ptr() as usize, len));
+            PebbleBuf { pebbles, locked }
+        }
+        #[cfg(not(feature = "mlock"))]
+        PebbleBuf { pebbles }
+    }
+}
+
+impl<H: OutputSizeUser> Clone for PebbleBuf<H> {
+    fn clone(&self) -> Self {
+        Self::from(self.pebbles.clone())
+    }
+}
+
+impl<H: OutputSizeUser> core::ops::Deref for PebbleBuf<H> {
+    type Target = Vec<Pebble<H>>;
+
+    fn deref(&self) -> &Vec<Pebble<H>> {
+        &self.pebbles
+    }
+}
+
+impl<H: OutputSizeUser> core::ops::DerefMut for PebbleBuf<H> {
+    fn deref_mut(&mut self) -> &mut Vec<Pebble<H>> {
+        &mut self.pebbles
+    }
+}
+
+#[cfg(feature = "mlock")]
+impl<H: OutputSizeUser> Drop for PebbleBuf<H> {
+    fn drop(&mut self) {
+        use zeroize::Zeroize;
+        // the slots past the end hold copies of pebbles removed before
+        self.pebbles.clear();
+        self.pebbles.spare_capacity_mut().zeroize();
+        if let Some((address, len)) = self.locked {
+            crate::locked::unlock(address as *const u8, len);
+        }
+    }
+}
+
+const fn num_bits<T>() -> usize { core::mem::size_of::<T>() * 8 }
+
+pub(crate) fn log_2(x: u64) -> u32 {
+    assert!(x > 0);
+    num_bits::<u64>() as u32 - x.leading_zeros() - 1
+}
+
+/// Return a mutable list of powers of two
+pub(crate) fn create_powers(how_many: u32) -> Vec<u64> {
+    let mut powers = Vec::<u64>::new();
+    for p in 0..how_many {
+        powers.push(2u64.pow(p+1));
+    }
+    powers
+}
+
+#[cfg(feature = "sha2")]
+#[test]
+fn test_pebble_formatting_redacts() {
+    use crate::HashChain;
+    use alloc::format;
+
+    let chain = HashChain::<Sha256>::new(8, 3).unwrap();
+    let pebble = &chain.pebbles()[0];
+    let value = hex::encode(pebble.value.as_slice());
+    for shown in [format!("{}", pebble), format!("{:?}", pebble), format!("{:?}", chain.pebbles())] {
+        assert!(!shown.contains(&value));
+        assert!(shown.contains(&hex::encode(pebble.fingerprint())));
+    }
+    assert!(format!("{}", pebble.danger_reveal()).contains(&value));
+}
+
+#[test]
+fn test_pebble_order() {
+    use crate::HashChain;
+    use alloc::collections::BTreeSet;
+    use sha2::Sha256;
+
+    let chain = HashChain::<Sha256>::new(16, 3).unwrap();
+    let mut pebbles = chain.pebbles().to_vec();
+    pebbles.reverse();
+    pebbles.sort();
+    assert_eq!(pebbles.iter().map(Pebble::position).collect::<Vec<_>>(), vec![2, 4, 8, 16]);
+    let set: BTreeSet<_> = chain.pebbles().iter().chain(&pebbles).cloned().collect();
+    assert_eq!(set.len(), 4);
+    assert!(chain.pebbles()[0] < chain.pebbles()[1]);
+}
+
+#[test]
+fn test_create_powers_small() {
+    let powers = create_powers(3);
+    assert_eq!(powers, vec![2, 4, 8]);
+}
@@ -0,0 +1,143 @@
+//! Sizing a chain before setting it up: how long it must be to last, and what that costs.
+//!
+//! [`chain_length`] takes the expected disclosure rate, the lifetime the chain must cover and a
+//! safety margin on top, and rounds the disclosures that takes up to the next length a
+//! [`HashChain`](crate::HashChain) supports, a power of two. [`lifetime_for`] goes the other way.
+//! Both describe the chain in a [`Plan`]: the lifetime it actually covers, the pebbles it keeps
+//! and their memory, and the hashes of setup and of every disclosure, which depend only on the
+//! length. A traversal of `2^k` values takes `(k - 2) * 2^(k-1) + 2` hashes in all and at most
+//! `k - 1` for one disclosure.
+
+use crate::pebble::Pebble;
+use core::error::Error;
+use core::fmt::{self, Display};
+use core::time::Duration;
+use digest::OutputSizeUser;
+
+/// The longest chain planned.
+pub const MAX_LENGTH: u64 = 1 << 63;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PlanError {
+    /// The disclosure rate is not a positive, finite number per second.
+    InvalidRate,
+    /// The safety margin is negative or not finite.
+    InvalidMargin,
+    /// The chain would be longer than [`MAX_LENGTH`], or its lifetime too long for a `Duration`.
+    TooLong,
+    /// The length is not one a chain can have.
+    InvalidLength(&'static str),
+}
+
+impl Display for PlanError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            PlanError::InvalidRate => write!(f, "disclosure rate must be positive and finite"),
+            PlanError::InvalidMargin => write!(f, "safety margin must be non-negative and finite"),
+            PlanError::TooLong => write!(f, "chain too long to plan"),
+            PlanError::InvalidLength(message) => write!(f, "{}", message),
+        }
+    }
+}
+
+impl Error for PlanError {}
+
+/// A chain of a supported length and what it costs.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Plan {
+    pub length: u64,
+    /// How long the chain lasts at the planned rate.
+    pub lifetime: Duration,
+    pub pebbles: u32,
+    /// The bytes the pebbles take.
+    pub memory: usize,
+    /// Hash evaluations to set the chain up, one per value.
+    pub setup_hashes: u64,
+    /// Hash evaluations of all disclosures together.
+    pub traversal_hashes: u128,
+    /// The most hash evaluations one disclosure takes.
+    pub max_step_hashes: u64,
+    pub mean_step_hashes: f64,
+}
+
+impl Plan {
+    fn new<H: OutputSizeUser>(length: u64, rate: f64) -> Result<Self, PlanError> {
+        let lifetime = Duration::try_from_secs_f64(length as f64 / rate).map_err(|_| PlanError::TooLong)?;
+        let k = length.trailing_zeros() as u64;
+        // (k - 2) * 2^(k-1) + 2, written for k = 1 as well
+        let traversal_hashes = k as u128 * (length as u128 / 2) + 2 - length as u128;
+        Ok(Plan {
+            length,
+            lifetime,
+            pebbles: k as u32,
+            memory: k as usize * core::mem::size_of::<Pebble<H>>(),
+            setup_hashes: length,
+            traversal_hashes,
+            max_step_hashes: k.saturating_sub(1).max(1),
+            mean_step_hashes: traversal_hashes as f64 / length as f64,
+        })
+    }
+}
+
+fn check_rate(rate: f64) -> Result<(), PlanError> {
+    if !(rate.is_finite() && rate > 0.0) {
+        return Err(PlanError::InvalidRate);
+    }
+    Ok(())
+}
+
+/// The shortest chain lasting `lifetime` at `disclosure_rate` disclosures a second with
+/// `safety_margin` to spare, e.g. `0.25` for a quarter more disclosures than expected.
+pub fn chain_length<H: OutputSizeUser>(disclosure_rate: f64, lifetime: Duration, safety_margin: f64) -> Result<Plan, PlanError> {
+    check_rate(disclosure_rate)?;
+    if !(safety_margin.is_finite() && safety_margin >= 0.0) {
+        return Err(PlanError::InvalidMargin);
+    }
+    let disclosures = disclosure_rate * lifetime.as_secs_f64() * (1.0 + safety_margin);
+    if disclosures > MAX_LENGTH as f64 {
+        return Err(PlanError::TooLong);
+    }
+    // rounded up by hand, `f64::ceil` needs std
+    let whole = disclosures as u64;
+    let length = (whole + ((whole as f64) < disclosures) as u64).max(2).next_power_of_two();
+    Plan::new::<H>(length, disclosure_rate)
+}
+
+/// How long a chain of `length` values lasts at `disclosure_rate` disclosures a second.
+pub fn lifetime_for<H: OutputSizeUser>(length: u64, disclosure_rate: f64) -> Result<Plan, PlanError> {
+    check_rate(disclosure_rate)?;
+    crate::params::check_chain_length(length).map_err(PlanError::InvalidLength)?;
+    Plan::new::<H>(length, disclosure_rate)
+}
+
+#[test]
+fn test_chain_plan() {
+    use sha2::Sha256;
+
+    // one disclosure a minute for a year, with 10% to spare: 578,160 values
+    let year = Duration::from_secs(365 * 24 * 3600);
+    let plan = chain_length::<Sha256>(1.0 / 60.0, year, 0.1).unwrap();
+    assert_eq!((plan.length, plan.pebbles, plan.memory), (1 << 20, 20, 20 * 64));
+    assert!(plan.lifetime >= year + year / 10);
+    assert_eq!(lifetime_for::<Sha256>(1 << 20, 1.0 / 60.0).unwrap(), plan);
+    assert_eq!(chain_length::<Sha256>(1.0, Duration::ZERO, 0.0).unwrap().length, 2);
+    assert_eq!(chain_length::<Sha256>(0.0, year, 0.1), Err(PlanError::InvalidRate));
+    assert_eq!(chain_length::<Sha256>(1.0, year, -0.5), Err(PlanError::InvalidMargin));
+    assert_eq!(chain_length::<Sha256>(1e12, year, 0.0), Err(PlanError::TooLong));
+    assert_eq!(lifetime_for::<Sha256>(MAX_LENGTH, 1.0).unwrap().traversal_hashes, 61 << 62 | 2);
+    assert_eq!(lifetime_for::<Sha256>(1000, 1.0), Err(PlanError::InvalidLength("length not a power of two")));
+
+    // the costs are those of an actual traversal
+    for length in [2, 4, 256] {
+        let plan = lifetime_for::<Sha256>(length, 1.0).unwrap();
+        let mut chain = crate::HashChain::<Sha256>::new(length as usize, 1).unwrap();
+        assert_eq!(chain.pebbles().len() as u32, plan.pebbles);
+        let (mut total, mut max) = (0, 0);
+        while chain.remaining() > 0 {
+            total += chain.step_cost() as u128;
+            max = max.max(chain.step_cost());
+            chain.disclose();
+        }
+        assert_eq!((total, max), (plan.traversal_hashes, plan.max_step_hashes));
+    }
+}
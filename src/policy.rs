@@ -0,0 +1,174 @@
+//! Per-position disclosure policies, so operators can embargo parts of a chain, e.g. keep the
+//! last values in reserve for emergency revocation messages.
+//!
+//! A [`DisclosurePolicy`] marks ranges of indices with a [`Rule`] and [`Policed`] enforces it on
+//! a [`HashChain`]: [`Policed::try_next`] stops in front of reserved indices, walks past skipped
+//! ones without handing them out, and asks its authorizer before disclosing the others it
+//! marks. Reserved values only come out through [`Policed::release_reserved`].
+//!
+//! Disclosing a value gives away every value before it, so a reserve is only a reserve while
+//! nothing after it has been disclosed: put it at the end of the chain, or accept that walking
+//! past it forfeits it.
+
+use crate::HashChain;
+use crate::value::ChainValue;
+use alloc::vec::Vec;
+use core::error::Error;
+use core::fmt::{self, Display};
+use core::ops::RangeInclusive;
+use digest::{Digest, FixedOutputReset};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PolicyError {
+    /// The next index is reserved.
+    Reserved { index: u64 },
+    /// The authorizer refused the next index.
+    Denied { index: u64 },
+    /// The next index is not reserved, for [`Policed::release_reserved`].
+    NotReserved { index: u64 },
+    /// The chain has nothing left to disclose.
+    Exhausted,
+}
+
+impl Display for PolicyError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            PolicyError::Reserved { index } => write!(f, "index {} is reserved", index),
+            PolicyError::Denied { index } => write!(f, "disclosure of index {} not authorized", index),
+            PolicyError::NotReserved { index } => write!(f, "index {} is not reserved", index),
+            PolicyError::Exhausted => write!(f, "chain exhausted"),
+        }
+    }
+}
+
+impl Error for PolicyError {}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Rule {
+    /// Never disclosed in order; see [`Policed::release_reserved`].
+    Reserved,
+    /// Walked past without being disclosed.
+    Skipped,
+    /// Disclosed only if the authorizer agrees.
+    Authorized,
+}
+
+/// Rules for ranges of indices. Where ranges overlap, the rule added first wins.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct DisclosurePolicy {
+    rules: Vec<(RangeInclusive<u64>, Rule)>,
+}
+
+impl DisclosurePolicy {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with_rule(mut self, indices: RangeInclusive<u64>, rule: Rule) -> Self {
+        self.rules.push((indices, rule));
+        self
+    }
+
+    pub fn reserve(self, indices: RangeInclusive<u64>) -> Self {
+        self.with_rule(indices, Rule::Reserved)
+    }
+
+    pub fn skip(self, indices: RangeInclusive<u64>) -> Self {
+        self.with_rule(indices, Rule::Skipped)
+    }
+
+    pub fn require_authorization(self, indices: RangeInclusive<u64>) -> Self {
+        self.with_rule(indices, Rule::Authorized)
+    }
+
+    /// The rule for `index`, `None` if it is disclosed freely.
+    pub fn rule(&self, index: u64) -> Option<Rule> {
+        self.rules.iter().find(|(indices, _)| indices.contains(&index)).map(|&(_, rule)| rule)
+    }
+}
+
+/// A chain that only discloses what its [`DisclosurePolicy`] allows. The authorizer is asked
+/// with the index about to be disclosed.
+pub struct Policed<H: Digest + FixedOutputReset, A> {
+    chain: HashChain<H>,
+    policy: DisclosurePolicy,
+    authorize: A,
+}
+
+impl<H: Digest + FixedOutputReset, A: FnMut(u64) -> bool> Policed<H, A> {
+    pub fn new(chain: HashChain<H>, policy: DisclosurePolicy, authorize: A) -> Self {
+        Policed { chain, policy, authorize }
+    }
+
+    /// Disclose the next value the policy allows, walking past skipped indices.
+    pub fn try_next(&mut self) -> Result<(u64, ChainValue<H>), PolicyError> {
+        loop {
+            let index = self.chain.position() + 1;
+            match self.policy.rule(index) {
+                Some(Rule::Reserved) => return Err(PolicyError::Reserved { index }),
+                Some(Rule::Skipped) => {
+                    self.chain.disclose().ok_or(PolicyError::Exhausted)?;
+                    continue;
+                }
+                Some(Rule::Authorized) if !(self.authorize)(index) => return Err(PolicyError::Denied { index }),
+                Some(Rule::Authorized) | None => {}
+            }
+            return self.chain.disclose().ok_or(PolicyError::Exhausted);
+        }
+    }
+
+    /// Disclose the next value, which has to be reserved, e.g. to send an emergency message.
+    pub fn release_reserved(&mut self) -> Result<(u64, ChainValue<H>), PolicyError> {
+        let index = self.chain.position() + 1;
+        if self.policy.rule(index) != Some(Rule::Reserved) {
+            return Err(PolicyError::NotReserved { index });
+        }
+        self.chain.disclose().ok_or(PolicyError::Exhausted)
+    }
+
+    pub fn chain(&self) -> &HashChain<H> {
+        &self.chain
+    }
+
+    pub fn policy(&self) -> &DisclosurePolicy {
+        &self.policy
+    }
+
+    pub fn into_inner(self) -> HashChain<H> {
+        self.chain
+    }
+}
+
+impl<H: Digest + FixedOutputReset, A: FnMut(u64) -> bool> Iterator for Policed<H, A> {
+    type Item = (u64, ChainValue<H>);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.try_next().ok()
+    }
+}
+
+#[test]
+fn test_disclosure_policy() {
+    use core::cell::Cell;
+    use sha2::Sha256;
+
+    let policy = DisclosurePolicy::new().skip(2..=3).require_authorization(5..=6).reserve(7..=8);
+    let approved = Cell::new(false);
+    let mut chain = Policed::new(HashChain::<Sha256>::new(8, 4).unwrap(), policy, |_| approved.replace(false));
+    assert_eq!(chain.try_next().unwrap().0, 1);
+    assert_eq!(chain.release_reserved(), Err(PolicyError::NotReserved { index: 2 }));
+    assert_eq!(chain.try_next().unwrap().0, 4);
+    assert_eq!(chain.try_next(), Err(PolicyError::Denied { index: 5 }));
+    approved.set(true);
+    assert_eq!(chain.try_next().unwrap().0, 5);
+    assert_eq!(chain.try_next(), Err(PolicyError::Denied { index: 6 }));
+    approved.set(true);
+    assert_eq!(chain.try_next().unwrap().0, 6);
+
+    // the reserve only comes out on purpose
+    assert_eq!(chain.try_next(), Err(PolicyError::Reserved { index: 7 }));
+    assert_eq!(chain.next(), None);
+    assert_eq!(chain.release_reserved().unwrap().0, 7);
+    assert_eq!(chain.release_reserved().unwrap().0, 8);
+    assert_eq!(chain.try_next(), Err(PolicyError::Exhausted));
+}
@@ -0,0 +1,196 @@
+//! A simple proof of sequential work: `steps` hashes in a row from a challenge, checked by
+//! spot checks, e.g. to rate-limit requests or to show that time has passed.
+//!
+//! The walk starts at `x_0 = H("posw" || challenge)` and every step hashes the last value. The
+//! prover keeps every `stride`-th value as a checkpoint and commits to the checkpoints `x_stride,
+//! x_(2 stride), ..., x_steps` in a Merkle tree. The queries are derived from the challenge and
+//! the root, so the prover cannot pick them; each one names a segment, and its answer opens the
+//! checkpoints at both ends, which the verifier checks against the root and against each other
+//! by walking the `stride` steps in between. A prover that skipped a fraction `f` of the
+//! segments survives `q` queries with probability `(1 - f)^q`, and every segment has to be
+//! walked after the one before it.
+
+use crate::merkle::{auth_path, leaf, AuthPath};
+use crate::{hash_forward, ChainInitError};
+use alloc::vec::Vec;
+use core::error::Error;
+use core::fmt::{self, Display};
+use digest::{Digest, FixedOutputReset, Output, OutputSizeUser};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PoswError {
+    /// The proof does not answer the queries derived from its root.
+    WrongQueries,
+    /// A checkpoint does not sit under the root at its segment's position.
+    BadOpening,
+    /// Walking a segment from its start does not reach its end.
+    Unwalked { segment: u64 },
+}
+
+impl Display for PoswError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            PoswError::WrongQueries => write!(f, "proof answers the wrong queries"),
+            PoswError::BadOpening => write!(f, "checkpoint not under the committed root"),
+            PoswError::Unwalked { segment } => write!(f, "segment {} was not walked", segment),
+        }
+    }
+}
+
+impl Error for PoswError {}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PoswParams {
+    pub steps: u64,
+    /// The steps between checkpoints, which is also what a verifier walks per query.
+    pub stride: u64,
+    pub queries: usize,
+}
+
+impl PoswParams {
+    fn segments(&self) -> u64 {
+        self.steps / self.stride
+    }
+
+    /// The stride must divide the steps into a power of two of segments.
+    pub(crate) fn check(&self) -> Result<(), ChainInitError> {
+        if self.stride == 0 || !self.steps.is_multiple_of(self.stride) || !self.segments().is_power_of_two() {
+            return Err(ChainInitError::new("steps must be a power of two multiple of the stride"));
+        }
+        Ok(())
+    }
+}
+
+/// The checkpoint at the end of one queried segment, with its path. The start is the previous
+/// segment's end, or `x_0` for segment 0.
+pub struct Opening<H: OutputSizeUser> {
+    pub segment: u64,
+    pub start: Option<(Output<H>, AuthPath<H>)>,
+    pub end: (Output<H>, AuthPath<H>),
+}
+
+impl<H: OutputSizeUser> Clone for Opening<H> {
+    fn clone(&self) -> Self {
+        Opening { segment: self.segment, start: self.start.clone(), end: self.end.clone() }
+    }
+}
+
+pub struct PoswProof<H: OutputSizeUser> {
+    pub root: Output<H>,
+    pub openings: Vec<Opening<H>>,
+}
+
+impl<H: OutputSizeUser> Clone for PoswProof<H> {
+    fn clone(&self) -> Self {
+        PoswProof { root: self.root.clone(), openings: self.openings.clone() }
+    }
+}
+
+fn start<H: Digest>(challenge: &[u8]) -> Output<H> {
+    H::new_with_prefix(b"posw").chain_update(challenge).finalize()
+}
+
+/// The segments the proof over `root` has to open.
+pub fn queries<H: Digest>(challenge: &[u8], params: &PoswParams, root: &Output<H>) -> Vec<u64> {
+    (0..params.queries as u64).map(|query| {
+        let digest = H::new_with_prefix(b"posw query").chain_update(challenge).chain_update(root).chain_update(query.to_le_bytes()).finalize();
+        u64::from_le_bytes(digest[..8].try_into().expect("digest output is at least 8 bytes")) % params.segments()
+    }).collect()
+}
+
+pub struct PoswProver<H: OutputSizeUser> {
+    /// The leaf hashes of the checkpoints `x_stride`, ..., `x_steps`.
+    leaves: Vec<Output<H>>,
+    checkpoints: Vec<Output<H>>,
+    root: Output<H>,
+}
+
+impl<H: Digest + FixedOutputReset> PoswProver<H> {
+    /// Walk the `params.steps` hashes from `challenge`. The stride must divide the steps into
+    /// a power of two of segments.
+    pub fn compute(challenge: &[u8], params: &PoswParams) -> Result<Self, ChainInitError> {
+        params.check()?;
+        let mut checkpoints = Vec::with_capacity(params.segments() as usize);
+        let mut value = start::<H>(challenge);
+        for _ in 0..params.segments() {
+            value = hash_forward::<H>(&value, params.stride);
+            checkpoints.push(value.clone());
+        }
+        let leaves: Vec<Output<H>> = checkpoints.iter().map(|checkpoint| leaf::<H>(checkpoint)).collect();
+        let root = crate::merkle::root::<H>(&leaves);
+        Ok(PoswProver { leaves, checkpoints, root })
+    }
+
+    pub fn root(&self) -> &Output<H> {
+        &self.root
+    }
+
+    /// The last value of the walk.
+    pub fn output(&self) -> &Output<H> {
+        self.checkpoints.last().expect("at least one segment")
+    }
+
+    /// The path of the last checkpoint, tying [`PoswProver::output`] to the root.
+    pub fn output_path(&self) -> AuthPath<H> {
+        auth_path::<H>(&self.leaves, self.leaves.len() as u64 - 1)
+    }
+
+    /// Open the segments `queries` names, e.g. those a verifier sent.
+    pub fn answer(&self, queries: &[u64]) -> PoswProof<H> {
+        let open = |index: u64| (self.checkpoints[index as usize].clone(), auth_path::<H>(&self.leaves, index));
+        let openings = queries.iter().map(|&segment| Opening { segment, start: segment.checked_sub(1).map(open), end: open(segment) }).collect();
+        PoswProof { root: self.root.clone(), openings }
+    }
+
+    /// The non-interactive proof, answering the queries [`queries`] derives.
+    pub fn prove(&self, challenge: &[u8], params: &PoswParams) -> PoswProof<H> {
+        self.answer(&queries::<H>(challenge, params, &self.root))
+    }
+}
+
+/// Check a non-interactive proof, walking `params.stride` steps per query.
+pub fn verify<H: Digest + FixedOutputReset>(challenge: &[u8], params: &PoswParams, proof: &PoswProof<H>) -> Result<(), PoswError> {
+    let expected = queries::<H>(challenge, params, &proof.root);
+    if proof.openings.iter().map(|opening| opening.segment).ne(expected) {
+        return Err(PoswError::WrongQueries);
+    }
+    let height = params.segments().trailing_zeros();
+    let opened = |index: u64, (value, path): &(Output<H>, AuthPath<H>)| path.index == index && path.height() == height && path.verify(&proof.root, &leaf::<H>(value));
+    for opening in &proof.openings {
+        let first = match (&opening.start, opening.segment) {
+            (None, 0) => start::<H>(challenge),
+            (Some(start), segment) if segment > 0 && opened(segment - 1, start) => start.0.clone(),
+            _ => return Err(PoswError::BadOpening),
+        };
+        if !opened(opening.segment, &opening.end) {
+            return Err(PoswError::BadOpening);
+        }
+        if hash_forward::<H>(&first, params.stride) != opening.end.0 {
+            return Err(PoswError::Unwalked { segment: opening.segment });
+        }
+    }
+    Ok(())
+}
+
+#[test]
+fn test_sequential_work() {
+    use sha2::Sha256;
+
+    let params = PoswParams { steps: 1 << 12, stride: 64, queries: 8 };
+    assert!(PoswProver::<Sha256>::compute(b"challenge", &PoswParams { stride: 100, ..params }).is_err());
+    let prover = PoswProver::<Sha256>::compute(b"challenge", &params).unwrap();
+    assert_eq!(*prover.output(), hash_forward::<Sha256>(&start::<Sha256>(b"challenge"), 1 << 12));
+    let proof = prover.prove(b"challenge", &params);
+    assert_eq!(verify::<Sha256>(b"challenge", &params, &proof), Ok(()));
+    assert_eq!(verify::<Sha256>(b"other challenge", &params, &proof), Err(PoswError::WrongQueries));
+
+    // a prover that makes up the checkpoints instead of walking is caught on the first query
+    let checkpoints: Vec<_> = (0..64u64).map(|i| Sha256::digest(i.to_le_bytes())).collect();
+    let leaves: Vec<_> = checkpoints.iter().map(|checkpoint| leaf::<Sha256>(checkpoint)).collect();
+    let shortcut = PoswProver::<Sha256> { root: crate::merkle::root::<Sha256>(&leaves), leaves, checkpoints };
+    let proof = shortcut.prove(b"challenge", &params);
+    assert_eq!(verify::<Sha256>(b"challenge", &params, &proof), Err(PoswError::Unwalked { segment: proof.openings[0].segment }));
+    let mut moved = prover.prove(b"challenge", &params);
+    moved.openings[0].end = moved.openings[1].end.clone();
+    assert_eq!(verify::<Sha256>(b"challenge", &params, &moved), Err(PoswError::BadOpening));
+}
@@ -0,0 +1,100 @@
+//! Proofs of position: a value together with the values between it and a value the verifier
+//! already knows, the anchor or an earlier disclosure.
+//!
+//! The verifier still hashes once per step, but every step is checked on its own, so a proof
+//! whose end does not match is rejected without hashing at all, the links can be checked in
+//! parallel, and afterwards the verifier holds every value in between. To check a position in
+//! a logarithmic number of hashes, commit to a [`chain_root`](crate::merkle::chain_root) and
+//! use [`ChainProof`](crate::merkle::ChainProof)s instead.
+
+use crate::{ChainStep, PlainStep};
+use alloc::vec::Vec;
+use digest::generic_array::GenericArray;
+use digest::{Digest, OutputSizeUser};
+
+pub struct Proof<H: OutputSizeUser> {
+    value: GenericArray<u8, H::OutputSize>,
+    /// `H(value)`, `H(H(value))`, ... down to and including the known value.
+    hashes: Vec<GenericArray<u8, H::OutputSize>>,
+}
+
+impl<H: OutputSizeUser> Clone for Proof<H> {
+    fn clone(&self) -> Self {
+        Proof { value: self.value.clone(), hashes: self.hashes.clone() }
+    }
+}
+
+impl<H: Digest> Proof<H> {
+    /// A proof from `value` at index `i` and the values at `i - 1`, ..., `j` as `hashes`, the
+    /// value at `j` being the one the verifier knows.
+    pub fn new(value: GenericArray<u8, H::OutputSize>, hashes: Vec<GenericArray<u8, H::OutputSize>>) -> Self {
+        Proof { value, hashes }
+    }
+
+    /// The proof that `value` lies `steps` positions above the value it hashes to.
+    pub fn generate(value: GenericArray<u8, H::OutputSize>, steps: u64) -> Self {
+        let step = PlainStep::<H>::new();
+        let hashes = (0..steps).scan(value.clone(), |current, index| {
+            *current = step.step(current, index);
+            Some(current.clone())
+        }).collect();
+        Proof { value, hashes }
+    }
+
+    pub fn value(&self) -> &GenericArray<u8, H::OutputSize> {
+        &self.value
+    }
+
+    /// The number of positions between the proven value and the known one.
+    pub fn distance(&self) -> u64 {
+        self.hashes.len() as u64
+    }
+
+    /// The proven value's index, given the index of the known value.
+    pub fn index_above(&self, known_index: u64) -> u64 {
+        known_index + self.distance()
+    }
+
+    /// The value `steps` positions below the proven one, if the proof covers it.
+    pub fn intermediate(&self, steps: u64) -> Option<&GenericArray<u8, H::OutputSize>> {
+        match steps {
+            0 => Some(&self.value),
+            steps => self.hashes.get(steps as usize - 1),
+        }
+    }
+
+    /// Check the proof against `known`, the anchor or an earlier value.
+    pub fn verify(&self, known: &GenericArray<u8, H::OutputSize>) -> bool {
+        if self.hashes.last().unwrap_or(&self.value) != known {
+            return false;
+        }
+        let mut upper = &self.value;
+        for lower in &self.hashes {
+            if H::digest(upper) != *lower {
+                return false;
+            }
+            upper = lower;
+        }
+        true
+    }
+}
+
+#[test]
+fn test_proof_of_position() {
+    use crate::HashChain;
+    use sha2::Sha256;
+
+    let mut chain = HashChain::<Sha256>::new(16, 5).unwrap();
+    let (_, third) = chain.nth(2).unwrap();
+    let (index, seventh) = chain.nth(3).unwrap();
+
+    let proof = Proof::<Sha256>::generate(*seventh, 4);
+    assert!(proof.verify(&third));
+    assert_eq!(proof.index_above(3), index);
+    assert_eq!(Proof::<Sha256>::generate(*seventh, 7).intermediate(7), Some(chain.anchor()));
+
+    let mut hashes: Vec<_> = (1..=4).map(|i| *proof.intermediate(i).unwrap()).collect();
+    hashes[1][0] ^= 1;
+    assert!(!Proof::<Sha256>::new(*seventh, hashes).verify(&third));
+    assert!(!proof.verify(chain.anchor()));
+}
@@ -0,0 +1,182 @@
+//! Provisioning URIs in the manner of `otpauth://`, for enrolling mobile clients by scanning a
+//! QR code of the URI:
+//!
+//! ```text
+//! fhtauth://chain/Example:alice?id=<chain id>&hash=sha256&anchor=<hex>&index=0&length=1024&issuer=Example
+//! ```
+//!
+//! `anchor` is the chain value at `index`, the anchor itself at index 0, and is all a client
+//! needs to verify the values that follow. `length`, `issuer` and `state` are optional. A URI
+//! with `state` carries the traversal state (in URL-safe base64 without padding) and lets the
+//! client disclose values itself; like an `otpauth` secret it must only be shown where nobody
+//! else can see it.
+
+use crate::{ChainId, HashChain};
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+use base64::Engine;
+use digest::{Digest, FixedOutputReset};
+use std::error::Error;
+use std::fmt::{self, Display};
+use std::str::FromStr;
+
+const SCHEME: &str = "fhtauth://chain/";
+
+#[derive(Debug)]
+pub struct UriParseError {
+    details: String,
+}
+
+impl UriParseError {
+    fn new(error_message: &str) -> UriParseError {
+        UriParseError { details: error_message.to_string() }
+    }
+}
+
+impl Display for UriParseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.details)
+    }
+}
+
+impl Error for UriParseError {}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ProvisioningUri {
+    /// What the client shows for the chain, e.g. `"Example:alice"`.
+    pub label: String,
+    pub issuer: Option<String>,
+    pub chain_id: ChainId,
+    /// The hash function's name, e.g. `"sha256"`.
+    pub hash: String,
+    /// The chain value at `index`.
+    pub anchor: Vec<u8>,
+    pub index: u64,
+    pub length: Option<u64>,
+    /// The traversal state, see [`HashChain::export_state`].
+    pub state: Option<Vec<u8>>,
+}
+
+impl ProvisioningUri {
+    /// The URI for verifying `chain` from its anchor, hashed with `hash`.
+    pub fn for_chain<H: Digest + FixedOutputReset>(chain: &HashChain<H>, hash: &str, label: &str) -> Self {
+        ProvisioningUri {
+            label: label.to_string(),
+            issuer: None,
+            chain_id: chain.chain_id(),
+            hash: hash.to_string(),
+            anchor: chain.anchor().to_vec(),
+            index: 0,
+            length: Some(chain.length()),
+            state: None,
+        }
+    }
+
+    pub fn with_issuer(self, issuer: &str) -> Self {
+        ProvisioningUri { issuer: Some(issuer.to_string()), ..self }
+    }
+
+    /// Hand `chain` itself over, so the client discloses values instead of verifying them.
+    pub fn with_state<H: Digest + FixedOutputReset>(self, chain: &HashChain<H>) -> Self {
+        ProvisioningUri { state: Some(chain.export_state()), ..self }
+    }
+
+    /// The chain handed over with [`ProvisioningUri::with_state`], if any.
+    pub fn chain<H: Digest + FixedOutputReset>(&self) -> Option<Result<HashChain<H>, crate::ChainInitError>> {
+        self.state.as_deref().map(HashChain::import_state)
+    }
+}
+
+impl Display for ProvisioningUri {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}{}?id={}&hash={}&anchor={}&index={}", SCHEME, encode(&self.label), self.chain_id, encode(&self.hash), hex::encode(&self.anchor), self.index)?;
+        if let Some(length) = self.length {
+            write!(f, "&length={}", length)?;
+        }
+        if let Some(issuer) = &self.issuer {
+            write!(f, "&issuer={}", encode(issuer))?;
+        }
+        if let Some(state) = &self.state {
+            write!(f, "&state={}", URL_SAFE_NO_PAD.encode(state))?;
+        }
+        Ok(())
+    }
+}
+
+impl FromStr for ProvisioningUri {
+    type Err = UriParseError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let rest = s.trim().strip_prefix(SCHEME).ok_or_else(|| UriParseError::new("expected a fhtauth://chain/ URI"))?;
+        let (label, query) = rest.split_once('?').ok_or_else(|| UriParseError::new("missing parameters"))?;
+        let (mut id, mut hash, mut anchor, mut index, mut length, mut issuer, mut state) = (None, None, None, None, None, None, None);
+        for parameter in query.split('&') {
+            let (key, value) = parameter.split_once('=').ok_or_else(|| UriParseError::new("parameter without a value"))?;
+            match key {
+                "id" => id = Some(value.parse::<ChainId>().map_err(|_| UriParseError::new("invalid chain id"))?),
+                "hash" => hash = Some(decode(value)?),
+                "anchor" => anchor = Some(hex::decode(value).map_err(|_| UriParseError::new("invalid anchor"))?),
+                "index" => index = Some(value.parse().map_err(|_| UriParseError::new("invalid index"))?),
+                "length" => length = Some(value.parse().map_err(|_| UriParseError::new("invalid length"))?),
+                "issuer" => issuer = Some(decode(value)?),
+                "state" => state = Some(URL_SAFE_NO_PAD.decode(value).map_err(|_| UriParseError::new("invalid state"))?),
+                // unknown parameters are left for newer clients
+                _ => {}
+            }
+        }
+        let missing = |name: &str| UriParseError::new(&format!("missing parameter {}", name));
+        Ok(ProvisioningUri {
+            label: decode(label)?,
+            issuer,
+            chain_id: id.ok_or_else(|| missing("id"))?,
+            hash: hash.ok_or_else(|| missing("hash"))?,
+            anchor: anchor.ok_or_else(|| missing("anchor"))?,
+            index: index.ok_or_else(|| missing("index"))?,
+            length,
+            state,
+        })
+    }
+}
+
+/// Percent-encode everything but the unreserved characters and `:`, which labels use.
+fn encode(text: &str) -> String {
+    text.bytes().map(|byte| match byte {
+        b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'~' | b':' => (byte as char).to_string(),
+        _ => format!("%{:02X}", byte),
+    }).collect()
+}
+
+fn decode(text: &str) -> Result<String, UriParseError> {
+    let invalid = || UriParseError::new("invalid percent-encoding");
+    let mut bytes = Vec::with_capacity(text.len());
+    let mut rest = text.as_bytes();
+    while let Some((&byte, tail)) = rest.split_first() {
+        if byte == b'%' {
+            let digits = tail.get(..2).ok_or_else(invalid)?;
+            let digits = std::str::from_utf8(digits).map_err(|_| invalid())?;
+            bytes.push(u8::from_str_radix(digits, 16).map_err(|_| invalid())?);
+            rest = &tail[2..];
+        } else {
+            bytes.push(byte);
+            rest = tail;
+        }
+    }
+    String::from_utf8(bytes).map_err(|_| invalid())
+}
+
+#[test]
+fn test_provisioning_uri_roundtrip() {
+    use sha2::Sha256;
+
+    let mut chain = HashChain::<Sha256>::new(16, 3).unwrap();
+    let uri = ProvisioningUri::for_chain(&chain, "sha256", "Example:alice smith").with_issuer("Example & Co");
+    let text = uri.to_string();
+    assert!(text.starts_with("fhtauth://chain/Example:alice%20smith?id="));
+    assert!(text.ends_with("&index=0&length=16&issuer=Example%20%26%20Co"));
+    assert_eq!(text.parse::<ProvisioningUri>().unwrap(), uri);
+
+    let handed_over = uri.with_state(&chain).to_string().parse::<ProvisioningUri>().unwrap();
+    let mut restored = handed_over.chain::<Sha256>().unwrap().unwrap();
+    assert_eq!(restored.next(), chain.next());
+    assert!(text.replace("&hash=sha256", "").parse::<ProvisioningUri>().is_err());
+    assert!("otpauth://hotp/alice?secret=AAAA".parse::<ProvisioningUri>().is_err());
+}
@@ -0,0 +1,182 @@
+//! Client puzzles (hashcash stamps) minted from short hash chains.
+//!
+//! The server hands out a [`Puzzle`] and only serves clients that return a nonce whose chain of
+//! `steps` hashes, started from the challenge and nonce, ends in a value with `difficulty`
+//! leading zero bits. Finding one costs about `steps * 2^difficulty` hashes, checking it costs
+//! `steps`. The server keeps no state: the challenge is a keyed hash of the puzzle parameters
+//! and a caller-chosen context (such as the client address), so it is simply recomputed.
+//!
+//! A solution can be presented again until the puzzle expires; bind the context to whatever the
+//! solution should pay for and keep expiry short.
+
+use crate::hash_forward;
+use digest::{Digest, FixedOutputReset};
+use std::error::Error;
+use std::fmt::{self, Display};
+use std::marker::PhantomData;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PuzzleError {
+    /// The puzzle expired before the solution arrived.
+    Expired,
+    /// The puzzle was not issued by this server for this context.
+    Forged,
+    /// The nonce does not meet the difficulty.
+    Unsolved,
+}
+
+impl Display for PuzzleError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            PuzzleError::Expired => write!(f, "puzzle expired"),
+            PuzzleError::Forged => write!(f, "puzzle not issued by this server"),
+            PuzzleError::Unsolved => write!(f, "solution does not meet the difficulty"),
+        }
+    }
+}
+
+impl Error for PuzzleError {}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Puzzle {
+    /// Expiry time in seconds since the Unix epoch.
+    pub expires: u64,
+    /// Required number of leading zero bits.
+    pub difficulty: u32,
+    /// Number of chained hashes per attempt.
+    pub steps: u32,
+    pub challenge: Vec<u8>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Solution {
+    pub puzzle: Puzzle,
+    pub nonce: u64,
+}
+
+fn leading_zero_bits(bytes: &[u8]) -> u32 {
+    let mut bits = 0;
+    for byte in bytes {
+        bits += byte.leading_zeros();
+        if *byte != 0 {
+            break;
+        }
+    }
+    bits
+}
+
+/// The end of the chain for one attempt.
+fn attempt<H: Digest + FixedOutputReset>(puzzle: &Puzzle, nonce: u64) -> digest::Output<H> {
+    let start = H::new_with_prefix(&puzzle.challenge).chain_update(nonce.to_le_bytes()).finalize();
+    hash_forward::<H>(&start, u64::from(puzzle.steps.saturating_sub(1)))
+}
+
+/// Solve a puzzle by trying nonces in order.
+pub fn solve<H: Digest + FixedOutputReset>(puzzle: &Puzzle) -> Solution {
+    let nonce = (0u64..)
+        .find(|nonce| leading_zero_bits(&attempt::<H>(puzzle, *nonce)) >= puzzle.difficulty)
+        .expect("nonce space exhausted");
+    Solution { puzzle: puzzle.clone(), nonce }
+}
+
+/// The difficulty whose expected solving cost is closest to, without exceeding, `target_hashes`
+/// for chains of `steps` hashes.
+pub fn difficulty_for(target_hashes: u64, steps: u32) -> u32 {
+    let attempts = target_hashes / u64::from(steps.max(1));
+    if attempts == 0 {
+        0
+    } else {
+        63 - attempts.leading_zeros()
+    }
+}
+
+/// Issues and verifies puzzles under a server secret.
+pub struct PuzzleIssuer<H> {
+    secret: Vec<u8>,
+    difficulty: u32,
+    steps: u32,
+    lifetime: u64,
+    _hash: PhantomData<H>,
+}
+
+impl<H: Digest + FixedOutputReset> PuzzleIssuer<H> {
+    /// Create an issuer whose puzzles live for `lifetime` seconds.
+    pub fn new(secret: &[u8], difficulty: u32, steps: u32, lifetime: u64) -> Self {
+        PuzzleIssuer { secret: secret.to_vec(), difficulty, steps: steps.max(1), lifetime, _hash: PhantomData }
+    }
+
+    /// Change the difficulty of newly issued puzzles, e.g. as load rises or falls. Outstanding
+    /// puzzles stay valid at the difficulty they were issued with.
+    pub fn set_difficulty(&mut self, difficulty: u32) {
+        self.difficulty = difficulty;
+    }
+
+    pub fn difficulty(&self) -> u32 {
+        self.difficulty
+    }
+
+    fn challenge(&self, context: &[u8], expires: u64, difficulty: u32, steps: u32) -> Vec<u8> {
+        H::new_with_prefix(b"fht-puzzle-v1")
+            .chain_update((self.secret.len() as u32).to_be_bytes())
+            .chain_update(&self.secret)
+            .chain_update((context.len() as u32).to_be_bytes())
+            .chain_update(context)
+            .chain_update(expires.to_be_bytes())
+            .chain_update(difficulty.to_be_bytes())
+            .chain_update(steps.to_be_bytes())
+            .finalize()
+            .to_vec()
+    }
+
+    /// Issue a puzzle bound to `context` at time `now` (seconds since the Unix epoch).
+    pub fn issue(&self, context: &[u8], now: u64) -> Puzzle {
+        let expires = now.saturating_add(self.lifetime);
+        let challenge = self.challenge(context, expires, self.difficulty, self.steps);
+        Puzzle { expires, difficulty: self.difficulty, steps: self.steps, challenge }
+    }
+
+    /// Check a solution for `context` at time `now`.
+    pub fn verify(&self, context: &[u8], solution: &Solution, now: u64) -> Result<(), PuzzleError> {
+        let puzzle = &solution.puzzle;
+        if now > puzzle.expires {
+            return Err(PuzzleError::Expired);
+        }
+        if self.challenge(context, puzzle.expires, puzzle.difficulty, puzzle.steps) != puzzle.challenge {
+            return Err(PuzzleError::Forged);
+        }
+        if leading_zero_bits(&attempt::<H>(puzzle, solution.nonce)) < puzzle.difficulty {
+            return Err(PuzzleError::Unsolved);
+        }
+        Ok(())
+    }
+}
+
+#[test]
+fn test_puzzle_roundtrip() {
+    use sha2::Sha256;
+
+    let mut issuer = PuzzleIssuer::<Sha256>::new(b"server secret", 8, 4, 60);
+    let puzzle = issuer.issue(b"10.0.0.1", 1000);
+    let solution = solve::<Sha256>(&puzzle);
+    assert_eq!(issuer.verify(b"10.0.0.1", &solution, 1030), Ok(()));
+    assert_eq!(issuer.verify(b"10.0.0.2", &solution, 1030), Err(PuzzleError::Forged));
+    assert_eq!(issuer.verify(b"10.0.0.1", &solution, 1061), Err(PuzzleError::Expired));
+
+    // lowering the advertised difficulty invalidates the challenge
+    let mut cheap = solution.clone();
+    cheap.puzzle.difficulty = 0;
+    assert_eq!(issuer.verify(b"10.0.0.1", &cheap, 1030), Err(PuzzleError::Forged));
+
+    issuer.set_difficulty(16);
+    let hard = issuer.issue(b"10.0.0.1", 1000);
+    let nonce = (0u64..).find(|n| leading_zero_bits(&attempt::<Sha256>(&hard, *n)) < 16).unwrap();
+    let wrong = Solution { puzzle: hard, nonce };
+    assert_eq!(issuer.verify(b"10.0.0.1", &wrong, 1030), Err(PuzzleError::Unsolved));
+}
+
+#[test]
+fn test_difficulty_for() {
+    assert_eq!(difficulty_for(1 << 20, 16), 16);
+    assert_eq!(difficulty_for(1000, 1), 9);
+    assert_eq!(difficulty_for(3, 4), 0);
+}
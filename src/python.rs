@@ -0,0 +1,150 @@
+//! Python bindings built with PyO3, for SHA-256 chains. The `fractal_hash_traversal` module
+//! holds `HashChain`, `Verifier` and the OTP helpers; errors are raised as `ValueError`. Build
+//! the extension with
+//! `cargo rustc --release --features python,pyo3/extension-module --crate-type cdylib`
+//! and install the library as `fractal_hash_traversal.so`.
+
+use crate::registry::{self, ChainRecord};
+use crate::store::MemoryStore;
+use crate::{otp, sixword, HashChain};
+use digest::generic_array::GenericArray;
+use pyo3::exceptions::PyValueError;
+use pyo3::prelude::*;
+use pyo3::types::PyBytes;
+use sha2::Sha256;
+
+fn value_error<E: std::fmt::Display>(error: E) -> PyErr {
+    PyValueError::new_err(error.to_string())
+}
+
+#[pyclass(name = "HashChain", module = "fractal_hash_traversal")]
+pub struct PyHashChain {
+    chain: HashChain<Sha256>,
+}
+
+#[pymethods]
+impl PyHashChain {
+    #[new]
+    fn new(length: usize, seed: u64) -> PyResult<Self> {
+        Ok(PyHashChain { chain: HashChain::new(length, seed).map_err(value_error)? })
+    }
+
+    #[getter]
+    fn anchor<'py>(&self, py: Python<'py>) -> Bound<'py, PyBytes> {
+        PyBytes::new(py, self.chain.anchor())
+    }
+
+    #[getter]
+    fn position(&self) -> u64 {
+        self.chain.position()
+    }
+
+    #[getter]
+    fn remaining(&self) -> u64 {
+        self.chain.remaining()
+    }
+
+    /// The next `(index, value)`, or `None` once the chain is exhausted.
+    fn disclose<'py>(&mut self, py: Python<'py>) -> Option<(u64, Bound<'py, PyBytes>)> {
+        self.chain.disclose().map(|(index, value)| (index, PyBytes::new(py, &value)))
+    }
+
+    fn __iter__(slf: PyRef<'_, Self>) -> PyRef<'_, Self> {
+        slf
+    }
+
+    fn __next__<'py>(&mut self, py: Python<'py>) -> Option<(u64, Bound<'py, PyBytes>)> {
+        self.disclose(py)
+    }
+
+    fn export_state<'py>(&self, py: Python<'py>) -> Bound<'py, PyBytes> {
+        PyBytes::new(py, &self.chain.export_state())
+    }
+
+    #[staticmethod]
+    fn import_state(state: &[u8]) -> PyResult<Self> {
+        Ok(PyHashChain { chain: HashChain::import_state(state).map_err(value_error)? })
+    }
+}
+
+/// An in-memory verifier for one chain.
+#[pyclass(name = "Verifier", module = "fractal_hash_traversal")]
+pub struct PyVerifier {
+    verifier: registry::Verifier<Sha256, MemoryStore<(), ChainRecord<Sha256>>>,
+}
+
+#[pymethods]
+impl PyVerifier {
+    #[new]
+    #[pyo3(signature = (anchor, max_gap = None))]
+    fn new(anchor: &[u8], max_gap: Option<u64>) -> PyResult<Self> {
+        if anchor.len() != 32 {
+            return Err(PyValueError::new_err("anchor must be 32 bytes"));
+        }
+        let verifier = registry::Verifier::new(MemoryStore::new(), (), GenericArray::clone_from_slice(anchor)).map_err(value_error)?;
+        let verifier = match max_gap {
+            Some(max_gap) => verifier.with_max_gap(max_gap),
+            None => verifier,
+        };
+        Ok(PyVerifier { verifier })
+    }
+
+    /// Accept `value` at `index`, returning how many positions the chain advanced.
+    fn accept(&self, index: u64, value: &[u8]) -> PyResult<u64> {
+        self.verifier.accept(index, value).map_err(value_error)
+    }
+
+    #[getter]
+    fn last_index(&self) -> PyResult<u64> {
+        Ok(self.verifier.last().map_err(value_error)?.index)
+    }
+}
+
+/// The SHA-256 one-time password for `count`, see `otp::compute`.
+#[pyfunction]
+fn otp_compute(seed: &str, passphrase: &str, count: u64) -> u64 {
+    otp::compute::<Sha256>(seed, passphrase, count)
+}
+
+/// Parse an OTP response given as hex or six words.
+#[pyfunction]
+fn otp_parse_response(response: &str) -> PyResult<u64> {
+    otp::parse_response(response).map_err(value_error)
+}
+
+#[pyfunction]
+fn six_word_encode(value: u64) -> String {
+    sixword::encode(value)
+}
+
+#[pyfunction]
+fn six_word_decode(words: &str) -> PyResult<u64> {
+    sixword::decode(words).map_err(value_error)
+}
+
+#[pymodule]
+fn fractal_hash_traversal(module: &Bound<'_, PyModule>) -> PyResult<()> {
+    module.add_class::<PyHashChain>()?;
+    module.add_class::<PyVerifier>()?;
+    module.add_function(wrap_pyfunction!(otp_compute, module)?)?;
+    module.add_function(wrap_pyfunction!(otp_parse_response, module)?)?;
+    module.add_function(wrap_pyfunction!(six_word_encode, module)?)?;
+    module.add_function(wrap_pyfunction!(six_word_decode, module)?)?;
+    Ok(())
+}
+
+#[test]
+fn test_python_bindings() {
+    Python::initialize();
+    Python::attach(|py| {
+        let chain = Bound::new(py, PyHashChain::new(16, 1).unwrap()).unwrap();
+        let anchor = chain.borrow().anchor(py);
+        let verifier = Bound::new(py, PyVerifier::new(anchor.as_bytes(), Some(4)).unwrap()).unwrap();
+        let (index, value) = chain.borrow_mut().disclose(py).unwrap();
+        assert_eq!(verifier.borrow().accept(index, value.as_bytes()).unwrap(), 1);
+        assert!(verifier.borrow().accept(index, value.as_bytes()).is_err());
+
+        let value = otp_compute("alpha1", "secret", 3);
+        assert_eq!(otp_parse_response(&six_word_encode(value)).unwrap(), value);
+    });
+}
@@ -0,0 +1,134 @@
+//! Rate limiting of disclosures.
+//!
+//! [`RateLimited`] wraps any traverser (anything iterating over disclosures, such as
+//! [`HashChain`](crate::HashChain)) and refuses to disclose faster than a token bucket allows or
+//! sooner than a minimum interval after the previous disclosure. Compromised or buggy application
+//! code can then only burn through the chain at a bounded rate.
+
+use std::error::Error;
+use std::fmt::{self, Display};
+use std::time::{Duration, Instant};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RateLimitError {
+    /// The previous disclosure was too recent.
+    TooSoon { retry_after: Duration },
+    /// The token bucket is empty.
+    RateExceeded { retry_after: Duration },
+    /// The wrapped traverser has nothing left to disclose.
+    Exhausted,
+}
+
+impl Display for RateLimitError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            RateLimitError::TooSoon { retry_after } => write!(f, "minimum disclosure interval not elapsed, retry after {:?}", retry_after),
+            RateLimitError::RateExceeded { retry_after } => write!(f, "disclosure rate exceeded, retry after {:?}", retry_after),
+            RateLimitError::Exhausted => write!(f, "chain exhausted"),
+        }
+    }
+}
+
+impl Error for RateLimitError {}
+
+/// Limits on how fast disclosures may happen.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RateLimit {
+    /// The number of disclosures that may happen in a burst.
+    pub burst: u32,
+    /// The time it takes to regain one disclosure in the bucket.
+    pub refill_interval: Duration,
+    /// The minimum time between two disclosures, regardless of the bucket.
+    pub min_interval: Duration,
+}
+
+/// A traverser that only discloses within a [`RateLimit`].
+pub struct RateLimited<T> {
+    inner: T,
+    limit: RateLimit,
+    tokens: u32,
+    refilled_at: Instant,
+    last_disclosure: Option<Instant>,
+}
+
+impl<T: Iterator> RateLimited<T> {
+    /// Wrap `inner`, starting with a full bucket.
+    pub fn new(inner: T, limit: RateLimit) -> Self {
+        Self::new_at(inner, limit, Instant::now())
+    }
+
+    /// Like [`RateLimited::new`], with the bucket considered full as of `now`.
+    pub fn new_at(inner: T, limit: RateLimit, now: Instant) -> Self {
+        RateLimited { inner, limit, tokens: limit.burst, refilled_at: now, last_disclosure: None }
+    }
+
+    /// Disclose the next value if the limits allow it right now.
+    pub fn try_next(&mut self) -> Result<T::Item, RateLimitError> {
+        self.try_next_at(Instant::now())
+    }
+
+    /// Disclose the next value if the limits allow it at time `now`.
+    pub fn try_next_at(&mut self, now: Instant) -> Result<T::Item, RateLimitError> {
+        if let Some(last) = self.last_disclosure {
+            let elapsed = now.saturating_duration_since(last);
+            if elapsed < self.limit.min_interval {
+                return Err(RateLimitError::TooSoon { retry_after: self.limit.min_interval - elapsed });
+            }
+        }
+        self.refill(now);
+        if self.tokens == 0 {
+            let elapsed = now.saturating_duration_since(self.refilled_at);
+            return Err(RateLimitError::RateExceeded { retry_after: self.limit.refill_interval.saturating_sub(elapsed) });
+        }
+        let item = self.inner.next().ok_or(RateLimitError::Exhausted)?;
+        self.tokens -= 1;
+        self.last_disclosure = Some(now);
+        Ok(item)
+    }
+
+    fn refill(&mut self, now: Instant) {
+        if self.tokens >= self.limit.burst {
+            self.refilled_at = now;
+            return;
+        }
+        if self.limit.refill_interval.is_zero() {
+            self.tokens = self.limit.burst;
+            return;
+        }
+        let elapsed = now.saturating_duration_since(self.refilled_at);
+        let earned = elapsed.as_nanos() / self.limit.refill_interval.as_nanos();
+        if earned > 0 {
+            let earned = earned.min(u128::from(self.limit.burst - self.tokens)) as u32;
+            self.tokens += earned;
+            self.refilled_at += self.limit.refill_interval * earned;
+        }
+    }
+
+    /// The wrapped traverser.
+    pub fn get_ref(&self) -> &T {
+        &self.inner
+    }
+
+    /// Unwrap the traverser, dropping the limits.
+    pub fn into_inner(self) -> T {
+        self.inner
+    }
+}
+
+#[test]
+fn test_rate_limited_chain() {
+    use crate::HashChain;
+    use sha2::Sha256;
+
+    let start = Instant::now();
+    let limit = RateLimit { burst: 2, refill_interval: Duration::from_secs(10), min_interval: Duration::from_secs(1) };
+    let mut chain = RateLimited::new_at(HashChain::<Sha256>::new(4, 0).unwrap(), limit, start);
+
+    assert_eq!(chain.try_next_at(start).unwrap().0, 1);
+    assert_eq!(chain.try_next_at(start + Duration::from_millis(500)), Err(RateLimitError::TooSoon { retry_after: Duration::from_millis(500) }));
+    assert_eq!(chain.try_next_at(start + Duration::from_secs(1)).unwrap().0, 2);
+    assert_eq!(chain.try_next_at(start + Duration::from_secs(2)), Err(RateLimitError::RateExceeded { retry_after: Duration::from_secs(8) }));
+    assert_eq!(chain.try_next_at(start + Duration::from_secs(10)).unwrap().0, 3);
+    assert_eq!(chain.try_next_at(start + Duration::from_secs(30)).unwrap().0, 4);
+    assert_eq!(chain.try_next_at(start + Duration::from_secs(40)), Err(RateLimitError::Exhausted));
+}
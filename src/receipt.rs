@@ -0,0 +1,89 @@
+//! Signed receipts for accepted disclosures. A verifier that accepts a value signs a receipt
+//! naming the chain, the index, a digest of the value and the time, which the party that
+//! disclosed it can keep as proof that the value was redeemed, e.g. to settle a dispute over a
+//! micropayment.
+
+use crate::registry::{ChainRecord, Registry, VerifyError};
+use crate::store::StateStore;
+use crate::ChainId;
+use digest::{Digest, FixedOutputReset};
+use ed25519_dalek::{Signature, Signer, SigningKey, Verifier, VerifyingKey};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+const LABEL: &[u8] = b"fractal-hash-traversal disclosure receipt";
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Receipt {
+    pub chain_id: ChainId,
+    pub index: u64,
+    /// The hash of the accepted value.
+    pub value_digest: Vec<u8>,
+    /// When the value was accepted, in seconds since the Unix epoch.
+    pub timestamp: u64,
+    pub signature: Signature,
+}
+
+fn signed_bytes(chain_id: &ChainId, index: u64, value_digest: &[u8], timestamp: u64) -> Vec<u8> {
+    let mut bytes = LABEL.to_vec();
+    bytes.extend_from_slice(&chain_id.0);
+    bytes.extend_from_slice(&index.to_be_bytes());
+    bytes.push(value_digest.len() as u8);
+    bytes.extend_from_slice(value_digest);
+    bytes.extend_from_slice(&timestamp.to_be_bytes());
+    bytes
+}
+
+/// Sign a receipt for `value` at `index` of `chain_id`, accepted at `time`.
+pub fn issue<H: Digest>(key: &SigningKey, chain_id: ChainId, index: u64, value: &[u8], time: SystemTime) -> Receipt {
+    let value_digest = H::digest(value).to_vec();
+    let timestamp = time.duration_since(UNIX_EPOCH).map_or(0, |since| since.as_secs());
+    let signature = key.sign(&signed_bytes(&chain_id, index, &value_digest, timestamp));
+    Receipt { chain_id, index, value_digest, timestamp, signature }
+}
+
+impl Receipt {
+    /// Check the signature under the verifier's `key`.
+    pub fn verify(&self, key: &VerifyingKey) -> bool {
+        key.verify(&signed_bytes(&self.chain_id, self.index, &self.value_digest, self.timestamp), &self.signature).is_ok()
+    }
+
+    /// Whether the receipt is for `value`.
+    pub fn covers<H: Digest>(&self, value: &[u8]) -> bool {
+        H::digest(value).as_slice() == self.value_digest
+    }
+}
+
+impl<H, S> Registry<H, S>
+where
+    H: Digest + FixedOutputReset,
+    S: StateStore<Key = ChainId, State = ChainRecord<H>>,
+{
+    /// Like [`Registry::verify`], signing a receipt with `key` if the disclosure is accepted.
+    pub fn verify_with_receipt(&self, chain_id: &ChainId, index: u64, value: &[u8], key: &SigningKey, now: SystemTime) -> Result<Receipt, VerifyError> {
+        self.verify(chain_id, index, value)?;
+        Ok(issue::<H>(key, *chain_id, index, value, now))
+    }
+}
+
+#[test]
+fn test_receipts() {
+    use crate::store::MemoryStore;
+    use crate::HashChain;
+    use sha2::Sha256;
+
+    let mut chain = HashChain::<Sha256>::new(16, 2).unwrap();
+    let registry = Registry::<Sha256, _>::new(MemoryStore::new());
+    let id = ChainId([4; 16]);
+    registry.register(id, *chain.anchor()).unwrap();
+    let key = SigningKey::from_bytes(&[9u8; 32]);
+
+    let (index, value) = chain.nth(2).unwrap();
+    let receipt = registry.verify_with_receipt(&id, index, &value, &key, SystemTime::now()).unwrap();
+    assert!(receipt.verify(&key.verifying_key()));
+    assert!(receipt.covers::<Sha256>(&value));
+    assert_eq!(registry.verify_with_receipt(&id, index, &value, &key, SystemTime::now()), Err(VerifyError::Replay));
+
+    let mut altered = receipt.clone();
+    altered.index += 1;
+    assert!(!altered.verify(&key.verifying_key()));
+}
@@ -0,0 +1,948 @@
+//! Server-side registry of enrolled chains.
+//!
+//! For every chain the registry remembers only the last accepted `(index, value)` pair, starting
+//! from the anchor at index 0. A disclosure is accepted when its index is strictly greater and it
+//! hashes forward to the stored value, so each value can be redeemed at most once.
+//!
+//! A deployment serving several systems from one store can configure an allow-list of domain
+//! tags, e.g. `"otp"` and `"metering"`, with [`Registry::with_domains`]. Every chain must then be
+//! registered under one of them, and [`Registry::verify_in`] only accepts a disclosure for the
+//! domain its chain was registered under, so a metering chain is never taken for an OTP one.
+
+use crate::proof::Proof;
+use crate::store::{AsyncStateStore, StateStore, StoreError};
+use crate::{verify, verify_batch, ChainId};
+use digest::generic_array::GenericArray;
+use digest::{Digest, FixedOutputReset, OutputSizeUser};
+use std::error::Error;
+use std::fmt::{self, Debug, Display};
+use std::marker::PhantomData;
+use std::str::FromStr;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum VerifyError {
+    /// The chain was never enrolled.
+    UnknownChain,
+    /// The index is not greater than the last accepted index.
+    Replay,
+    /// The index is not the one right after the last accepted index, where every value must be
+    /// presented in turn.
+    NotNext,
+    /// The index skips further ahead of the last accepted index than the [`GapPolicy`] allows,
+    /// or its penalty callback refused the gap.
+    GapTooLarge,
+    /// The index skips far enough ahead that the [`GapPolicy`] requires a proof of position.
+    ProofRequired,
+    /// The value does not hash forward to the last accepted value.
+    Mismatch,
+    /// Another disclosure for the same chain was accepted concurrently.
+    Conflict,
+    /// The chain is already enrolled, and enrolling it again would reset its position.
+    AlreadyEnrolled,
+    /// The chain was retired with its kill value.
+    Retired,
+    /// The chain declares a domain tag not on the allow-list, or none where one is required.
+    DomainNotAllowed(String),
+    /// The chain was registered under another domain tag than the one it is verified for.
+    WrongDomain,
+    /// The state store failed.
+    Store(String),
+}
+
+impl Display for VerifyError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            VerifyError::UnknownChain => write!(f, "unknown chain"),
+            VerifyError::Replay => write!(f, "index already used"),
+            VerifyError::NotNext => write!(f, "index is not the next one"),
+            VerifyError::GapTooLarge => write!(f, "index too far ahead of the last accepted index"),
+            VerifyError::ProofRequired => write!(f, "gap too large to accept without a proof"),
+            VerifyError::Mismatch => write!(f, "value does not match the chain"),
+            VerifyError::Conflict => write!(f, "chain state changed during verification"),
+            VerifyError::AlreadyEnrolled => write!(f, "chain already enrolled"),
+            VerifyError::Retired => write!(f, "chain has been retired"),
+            VerifyError::DomainNotAllowed(domain) if domain.is_empty() => write!(f, "chain declares no domain tag"),
+            VerifyError::DomainNotAllowed(domain) => write!(f, "domain tag {:?} is not allowed", domain),
+            VerifyError::WrongDomain => write!(f, "chain belongs to another domain"),
+            VerifyError::Store(details) => write!(f, "state store error: {}", details),
+        }
+    }
+}
+
+impl Error for VerifyError {}
+
+/// The last accepted position of a chain.
+pub struct ChainRecord<H: OutputSizeUser> {
+    pub index: u64,
+    pub value: GenericArray<u8, H::OutputSize>,
+    /// The domain tag the chain was registered under, if any, shared between the records of
+    /// the chain so that advancing one copies no string.
+    pub domain: Option<Arc<str>>,
+}
+
+/// The index of a retired chain's record, past any index of a chain.
+const RETIRED: u64 = u64::MAX;
+
+impl<H: OutputSizeUser> ChainRecord<H> {
+    pub fn is_retired(&self) -> bool {
+        self.index == RETIRED
+    }
+
+    /// The index as u64 big endian and the value, followed by the domain tag's length as u32
+    /// big endian and the tag if there is one.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = self.index.to_be_bytes().to_vec();
+        bytes.extend_from_slice(&self.value);
+        if let Some(domain) = &self.domain {
+            bytes.extend_from_slice(&(domain.len() as u32).to_be_bytes());
+            bytes.extend_from_slice(domain.as_bytes());
+        }
+        bytes
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, StoreError> {
+        let n = H::output_size();
+        let (index, rest) = bytes.split_first_chunk::<8>().ok_or(StoreError::new("chain record too short"))?;
+        let (value, rest) = rest.split_at_checked(n).ok_or(StoreError::new("chain record too short"))?;
+        let domain = match rest.split_first_chunk::<4>() {
+            None if rest.is_empty() => None,
+            Some((len, tag)) if u32::from_be_bytes(*len) as usize == tag.len() => {
+                Some(Arc::from(std::str::from_utf8(tag).map_err(|_| StoreError::new("domain tag not UTF-8"))?))
+            }
+            _ => return Err(StoreError::new("wrong length for a chain record")),
+        };
+        Ok(ChainRecord { index: u64::from_be_bytes(*index), value: GenericArray::clone_from_slice(value), domain })
+    }
+}
+
+impl<H: OutputSizeUser> crate::version::Versioned for ChainRecord<H> {
+    type Error = StoreError;
+
+    fn encode_body(&self, _version: u8) -> Vec<u8> {
+        self.to_bytes()
+    }
+
+    fn decode_body(_version: u8, body: &[u8]) -> Result<Self, StoreError> {
+        Self::from_bytes(body)
+    }
+}
+
+// manual impls so that `H` itself need not be `Clone`/`PartialEq`
+impl<H: OutputSizeUser> Clone for ChainRecord<H> {
+    fn clone(&self) -> Self {
+        ChainRecord { index: self.index, value: self.value.clone(), domain: self.domain.clone() }
+    }
+}
+
+impl<H: OutputSizeUser> PartialEq for ChainRecord<H> {
+    fn eq(&self, other: &Self) -> bool {
+        self.index == other.index && self.value == other.value && self.domain == other.domain
+    }
+}
+
+impl<H: OutputSizeUser> Eq for ChainRecord<H> {}
+
+impl<H: OutputSizeUser> Debug for ChainRecord<H> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "ChainRecord {{index: {}, value: {}, domain: {:?}}}", self.index, hex::encode(self.value.as_slice()), self.domain)
+    }
+}
+
+/// A disclosure presented by a client: `<chain id hex>.<index>.<value hex>`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ChainToken {
+    pub chain_id: ChainId,
+    pub index: u64,
+    pub value: Vec<u8>,
+}
+
+impl Display for ChainToken {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}.{}.{}", self.chain_id, self.index, hex::encode(&self.value))
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TokenParseError {
+    details: String,
+}
+
+impl TokenParseError {
+    fn new(error_message: &str) -> TokenParseError {
+        TokenParseError { details: error_message.to_string() }
+    }
+}
+
+impl Display for TokenParseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.details)
+    }
+}
+
+impl Error for TokenParseError {}
+
+impl FromStr for ChainToken {
+    type Err = TokenParseError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut parts = s.trim().split('.');
+        let (Some(chain_id), Some(index), Some(value), None) = (parts.next(), parts.next(), parts.next(), parts.next()) else {
+            return Err(TokenParseError::new("expected `<chain id>.<index>.<value>`"));
+        };
+        Ok(ChainToken {
+            chain_id: chain_id.parse().map_err(|_| TokenParseError::new("invalid chain id"))?,
+            index: index.parse().map_err(|_| TokenParseError::new("invalid index"))?,
+            value: hex::decode(value).map_err(|_| TokenParseError::new("invalid value"))?,
+        })
+    }
+}
+
+/// How far past the last accepted position a disclosure may skip, so strict deployments, e.g.
+/// OTP logins, and lossy ones, e.g. broadcast receivers, configure one verifier differently.
+///
+/// A gap is the number of positions a disclosure advances the chain, 1 for the next value. The
+/// policy refuses gaps above its maximum before any hashing, requires a [`Proof`] of position for
+/// gaps above its proof threshold, and asks its penalty callback about every otherwise valid
+/// disclosure with a gap above 1, e.g. to charge the skipped values to the client or rate limit
+/// it. The default allows gaps up to [`DEFAULT_MAX_GAP`]; [`GapPolicy::unbounded`] lifts the
+/// limit for verifiers whose clients are trusted not to present huge indices.
+#[derive(Clone)]
+pub struct GapPolicy {
+    max_gap: Option<u64>,
+    proof_over: Option<u64>,
+    penalty: Option<Arc<dyn Fn(u64) -> bool + Send + Sync>>,
+}
+
+impl Debug for GapPolicy {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("GapPolicy")
+            .field("max_gap", &self.max_gap)
+            .field("proof_over", &self.proof_over)
+            .field("penalty", &self.penalty.is_some())
+            .finish()
+    }
+}
+
+/// The largest gap a [`GapPolicy`] allows unless configured otherwise.
+pub use crate::verify::DEFAULT_MAX_GAP;
+
+impl Default for GapPolicy {
+    fn default() -> Self {
+        GapPolicy { max_gap: Some(DEFAULT_MAX_GAP), proof_over: None, penalty: None }
+    }
+}
+
+impl GapPolicy {
+    /// Any gap, without a proof or a penalty. Every disclosure may then cost up to `u64::MAX`
+    /// hashes.
+    pub fn unbounded() -> Self {
+        GapPolicy { max_gap: None, ..Self::default() }
+    }
+
+    /// Only the next value, as a login that must see every one-time password in turn.
+    pub fn strict() -> Self {
+        Self::default().with_max_gap(1)
+    }
+
+    /// Refuse gaps above `max_gap` with [`VerifyError::GapTooLarge`].
+    pub fn with_max_gap(self, max_gap: u64) -> Self {
+        GapPolicy { max_gap: Some(max_gap), ..self }
+    }
+
+    /// Refuse gaps above `threshold` with [`VerifyError::ProofRequired`] unless they come with a
+    /// proof, so a client cannot make the verifier hash an unbounded distance for free.
+    pub fn require_proof_over(self, threshold: u64) -> Self {
+        GapPolicy { proof_over: Some(threshold), ..self }
+    }
+
+    /// Call `penalty` with the gap of every otherwise valid disclosure skipping values, refusing
+    /// the disclosure with [`VerifyError::GapTooLarge`] if it returns `false`.
+    pub fn with_penalty<F: Fn(u64) -> bool + Send + Sync + 'static>(self, penalty: F) -> Self {
+        GapPolicy { penalty: Some(Arc::new(penalty)), ..self }
+    }
+
+    pub fn max_gap(&self) -> Option<u64> {
+        self.max_gap
+    }
+
+    /// Check `gap` before hashing, `proven` if it comes with a proof.
+    fn admit(&self, gap: u64, proven: bool) -> Result<(), VerifyError> {
+        if self.max_gap.is_some_and(|max_gap| gap > max_gap) {
+            return Err(VerifyError::GapTooLarge);
+        }
+        if !proven && self.proof_over.is_some_and(|threshold| gap > threshold) {
+            return Err(VerifyError::ProofRequired);
+        }
+        Ok(())
+    }
+
+    /// Charge `gap` to the penalty callback once the disclosure verified.
+    fn charge(&self, gap: u64) -> Result<(), VerifyError> {
+        match &self.penalty {
+            Some(penalty) if gap > 1 && !penalty(gap) => Err(VerifyError::GapTooLarge),
+            _ => Ok(()),
+        }
+    }
+}
+
+/// Verify a disclosure against `record`, with the proof of its position if it comes with one,
+/// returning how many positions it advances the chain and the record to swap in.
+fn check_disclosure<H>(policy: &GapPolicy, record: &ChainRecord<H>, index: u64, value: &[u8], proof: Option<&Proof<H>>) -> Result<(u64, ChainRecord<H>), VerifyError>
+where
+    H: Digest + FixedOutputReset,
+{
+    if record.is_retired() {
+        return Err(VerifyError::Retired);
+    }
+    if index <= record.index {
+        return Err(VerifyError::Replay);
+    }
+    let gap = index - record.index;
+    policy.admit(gap, proof.is_some())?;
+    if value.len() != record.value.len() {
+        return Err(VerifyError::Mismatch);
+    }
+    let value = GenericArray::clone_from_slice(value);
+    let valid = match proof {
+        Some(proof) => *proof.value() == value && proof.index_above(record.index) == index && proof.verify(&record.value),
+        None => verify::<H>(record.index, &record.value, index, &value),
+    };
+    if !valid {
+        return Err(VerifyError::Mismatch);
+    }
+    policy.charge(gap)?;
+    Ok((gap, ChainRecord { index, value, domain: record.domain.clone() }))
+}
+
+fn swap_result<E: Error>(swapped: Result<bool, E>, steps: u64) -> Result<u64, VerifyError> {
+    match swapped {
+        Ok(true) => Ok(steps),
+        Ok(false) => Err(VerifyError::Conflict),
+        Err(e) => Err(VerifyError::Store(e.to_string())),
+    }
+}
+
+/// Report whether a disclosure was accepted; the value itself is never recorded.
+#[cfg(feature = "tracing")]
+fn trace_outcome(chain_id: &ChainId, index: u64, outcome: &Result<u64, VerifyError>) {
+    match outcome {
+        Ok(advanced) => tracing::debug!(%chain_id, index, advanced, "disclosure accepted"),
+        Err(error) => tracing::debug!(%chain_id, index, %error, "disclosure rejected"),
+    }
+}
+
+/// Verify a disclosure against `record`, the state stored under `key`, and swap it in.
+fn advance_record<H, S>(store: &S, key: &S::Key, policy: &GapPolicy, record: ChainRecord<H>, index: u64, value: &[u8], proof: Option<&Proof<H>>) -> Result<u64, VerifyError>
+where
+    H: Digest + FixedOutputReset,
+    S: StateStore<State = ChainRecord<H>>,
+{
+    let (steps, new) = check_disclosure(policy, &record, index, value, proof)?;
+    swap_result(store.compare_and_swap(key, Some(&record), new), steps)
+}
+
+/// Retire the chain stored under `key` if `kill_value` matches `kill_commitment`.
+fn retire_record<H, S>(store: &S, key: &S::Key, kill_commitment: &[u8], kill_value: &[u8]) -> Result<(), VerifyError>
+where
+    H: Digest,
+    S: StateStore<State = ChainRecord<H>>,
+{
+    if !crate::retire::is_kill_value::<H>(kill_commitment, kill_value) {
+        return Err(VerifyError::Mismatch);
+    }
+    let record = store.load(key).map_err(|e| VerifyError::Store(e.to_string()))?.ok_or(VerifyError::UnknownChain)?;
+    store.save(key, ChainRecord { index: RETIRED, ..record }).map_err(|e| VerifyError::Store(e.to_string()))
+}
+
+/// Enrolled chains and their last accepted positions, kept in a [`StateStore`].
+pub struct Registry<H, S> {
+    store: S,
+    gap: GapPolicy,
+    /// The allowed domain tags, if chains must declare one.
+    domains: Option<Vec<String>>,
+    _hash: PhantomData<H>,
+}
+
+impl<H: OutputSizeUser, S> Registry<H, S> {
+    /// The record a chain starts from, refused if its domain tag is not on the allow-list.
+    fn first_record(&self, anchor: GenericArray<u8, H::OutputSize>, domain: Option<&str>) -> Result<ChainRecord<H>, VerifyError> {
+        if let Some(domains) = &self.domains {
+            if !domain.is_some_and(|domain| domains.iter().any(|allowed| allowed == domain)) {
+                return Err(VerifyError::DomainNotAllowed(domain.unwrap_or_default().to_string()));
+            }
+        }
+        Ok(ChainRecord { index: 0, value: anchor, domain: domain.map(Arc::from) })
+    }
+}
+
+impl<H, S> Registry<H, S>
+where
+    H: Digest + FixedOutputReset,
+    S: StateStore<Key = ChainId, State = ChainRecord<H>>,
+{
+    pub fn new(store: S) -> Self {
+        Registry { store, gap: GapPolicy::default(), domains: None, _hash: PhantomData }
+    }
+
+    /// Reject disclosures more than `max_gap` positions past the last accepted one with
+    /// [`VerifyError::GapTooLarge`] before hashing, instead of [`DEFAULT_MAX_GAP`], bounding the
+    /// work a bogus disclosure costs. The registry does not know chain lengths, so a client can
+    /// otherwise make it hash up to `u64::MAX` steps.
+    pub fn with_max_gap(self, max_gap: u64) -> Self {
+        Registry { gap: self.gap.with_max_gap(max_gap), ..self }
+    }
+
+    /// Consult `policy` on how far disclosures may skip ahead, replacing any maximum gap set.
+    pub fn with_gap_policy(self, policy: GapPolicy) -> Self {
+        Registry { gap: policy, ..self }
+    }
+
+    /// Require every chain to be registered under one of `domains` with
+    /// [`Registry::register_in`]; [`Registry::register`] is refused from then on.
+    pub fn with_domains(self, domains: &[&str]) -> Self {
+        Registry { domains: Some(domains.iter().map(|domain| domain.to_string()).collect()), ..self }
+    }
+
+    /// Enroll a chain by its anchor, replacing any previous state.
+    pub fn register(&self, chain_id: ChainId, anchor: GenericArray<u8, H::OutputSize>) -> Result<(), VerifyError> {
+        self.register_with(chain_id, anchor, None)
+    }
+
+    /// Enroll a chain under the domain tag `domain`, see [`Registry::with_domains`].
+    pub fn register_in(&self, chain_id: ChainId, anchor: GenericArray<u8, H::OutputSize>, domain: &str) -> Result<(), VerifyError> {
+        self.register_with(chain_id, anchor, Some(domain))
+    }
+
+    pub(crate) fn register_with(&self, chain_id: ChainId, anchor: GenericArray<u8, H::OutputSize>, domain: Option<&str>) -> Result<(), VerifyError> {
+        let record = self.first_record(anchor, domain)?;
+        #[cfg(feature = "tracing")]
+        tracing::debug!(%chain_id, domain, "chain enrolled");
+        self.store.save(&chain_id, record)
+            .map_err(|e| VerifyError::Store(e.to_string()))
+    }
+
+    /// Enroll a chain under the identifier derived from its anchor and length, returning it.
+    /// Unlike [`Registry::register`] this never touches a chain already enrolled, refusing it
+    /// with [`VerifyError::AlreadyEnrolled`], so it is safe to expose to untrusted callers.
+    pub fn enroll(&self, anchor: GenericArray<u8, H::OutputSize>, length: u64) -> Result<ChainId, VerifyError> {
+        self.enroll_with(anchor, length, None)
+    }
+
+    fn enroll_with(&self, anchor: GenericArray<u8, H::OutputSize>, length: u64, domain: Option<&str>) -> Result<ChainId, VerifyError> {
+        let chain_id = ChainId::derive(&anchor, length);
+        let record = self.first_record(anchor, domain)?;
+        match self.store.compare_and_swap(&chain_id, None, record) {
+            Ok(true) => {
+                #[cfg(feature = "tracing")]
+                tracing::debug!(%chain_id, domain, "chain enrolled");
+                Ok(chain_id)
+            }
+            Ok(false) => Err(VerifyError::AlreadyEnrolled),
+            Err(e) => Err(VerifyError::Store(e.to_string())),
+        }
+    }
+
+    /// The last accepted position of a chain.
+    pub fn record(&self, chain_id: &ChainId) -> Result<ChainRecord<H>, VerifyError> {
+        self.store.load(chain_id)
+            .map_err(|e| VerifyError::Store(e.to_string()))?
+            .ok_or(VerifyError::UnknownChain)
+    }
+
+    /// Like [`Registry::enroll`], under the domain tag `domain`.
+    pub fn enroll_in(&self, anchor: GenericArray<u8, H::OutputSize>, length: u64, domain: &str) -> Result<ChainId, VerifyError> {
+        self.enroll_with(anchor, length, Some(domain))
+    }
+
+    /// Verify a disclosure and, if it is valid and newer than the last accepted one, record it.
+    /// The chain's domain tag is not checked, see [`Registry::verify_in`].
+    pub fn verify(&self, chain_id: &ChainId, index: u64, value: &[u8]) -> Result<(), VerifyError> {
+        self.advance(chain_id, index, value).map(|_| ())
+    }
+
+    /// Like [`Registry::verify`], returning how many positions the chain advanced.
+    pub fn advance(&self, chain_id: &ChainId, index: u64, value: &[u8]) -> Result<u64, VerifyError> {
+        let outcome = self.record(chain_id).and_then(|record| advance_record(&self.store, chain_id, &self.gap, record, index, value, None));
+        #[cfg(feature = "tracing")]
+        trace_outcome(chain_id, index, &outcome);
+        outcome
+    }
+
+    /// Like [`Registry::verify`] for the system behind the domain tag `domain`, refusing chains
+    /// registered under another tag or none with [`VerifyError::WrongDomain`].
+    pub fn verify_in(&self, chain_id: &ChainId, domain: &str, index: u64, value: &[u8]) -> Result<(), VerifyError> {
+        self.advance_in(chain_id, domain, index, value).map(|_| ())
+    }
+
+    /// Like [`Registry::advance`], checking the domain tag as [`Registry::verify_in`] does.
+    pub fn advance_in(&self, chain_id: &ChainId, domain: &str, index: u64, value: &[u8]) -> Result<u64, VerifyError> {
+        let outcome = self.record(chain_id).and_then(|record| match record.domain.as_deref() {
+            Some(declared) if declared == domain => advance_record(&self.store, chain_id, &self.gap, record, index, value, None),
+            _ => Err(VerifyError::WrongDomain),
+        });
+        #[cfg(feature = "tracing")]
+        trace_outcome(chain_id, index, &outcome);
+        outcome
+    }
+
+    /// Verify several consecutive disclosures with [`verify_batch`] and record the highest,
+    /// returning how many positions the chain advanced.
+    pub fn verify_batch(&self, chain_id: &ChainId, batch: &[(u64, GenericArray<u8, H::OutputSize>)]) -> Result<u64, VerifyError> {
+        let record = self.record(chain_id)?;
+        if record.is_retired() {
+            return Err(VerifyError::Retired);
+        }
+        let Some((index, value)) = batch.last() else {
+            return Ok(0);
+        };
+        if batch[0].0 <= record.index {
+            return Err(VerifyError::Replay);
+        }
+        // a batch of every value up to its last proves the last value's position
+        let steps = index - record.index;
+        self.gap.admit(steps, batch.len() as u64 == steps)?;
+        if !verify_batch::<H>(record.index, &record.value, batch) {
+            return Err(VerifyError::Mismatch);
+        }
+        self.gap.charge(steps)?;
+        let new = ChainRecord { index: *index, value: value.clone(), domain: record.domain.clone() };
+        swap_result(self.store.compare_and_swap(chain_id, Some(&record), new), steps)
+    }
+
+    /// Retire a chain with the kill value its commitment's `kill_commitment` binds. Every
+    /// disclosure from it is refused with [`VerifyError::Retired`] from then on, and enrolling
+    /// it again is the only way back.
+    pub fn retire(&self, chain_id: &ChainId, kill_commitment: &[u8], kill_value: &[u8]) -> Result<(), VerifyError> {
+        #[cfg(feature = "tracing")]
+        tracing::debug!(%chain_id, "chain retired");
+        retire_record(&self.store, chain_id, kill_commitment, kill_value)
+    }
+
+    /// Verify a parsed [`ChainToken`].
+    pub fn verify_token(&self, token: &ChainToken) -> Result<(), VerifyError> {
+        self.verify(&token.chain_id, token.index, &token.value)
+    }
+
+    /// Verify a [`Disclosure`](crate::disclosure::Disclosure) of an enrolled chain, with its
+    /// proof if it has one, which must then lead from the last accepted position. Its MAC, if
+    /// any, is left to the caller, since the registry knows no keys.
+    pub fn verify_disclosure(&self, disclosure: &crate::disclosure::Disclosure<H>) -> Result<(), VerifyError> {
+        let (chain_id, index) = (&disclosure.chain_id, disclosure.index);
+        let outcome = self.record(chain_id)
+            .and_then(|record| advance_record(&self.store, chain_id, &self.gap, record, index, &disclosure.value, disclosure.proof.as_ref()));
+        #[cfg(feature = "tracing")]
+        trace_outcome(chain_id, index, &outcome);
+        outcome.map(|_| ())
+    }
+}
+
+#[cfg(feature = "rayon")]
+impl<H, S> Registry<H, S>
+where
+    H: Digest + FixedOutputReset + Send + Sync,
+    S: StateStore<Key = ChainId, State = ChainRecord<H>> + Sync,
+{
+    /// Verify many tokens across the rayon thread pool, e.g. when settling micropayments, with
+    /// one result per token in the same order. Tokens for the same chain are verified one after
+    /// the other in the order given, so they do not race each other in the store.
+    pub fn verify_many_par(&self, tokens: &[ChainToken]) -> Vec<Result<(), VerifyError>> {
+        use rayon::prelude::*;
+        use std::collections::HashMap;
+
+        let mut by_chain: HashMap<ChainId, Vec<usize>> = HashMap::new();
+        for (i, token) in tokens.iter().enumerate() {
+            by_chain.entry(token.chain_id).or_default().push(i);
+        }
+        let verified: Vec<(usize, Result<(), VerifyError>)> = by_chain.into_par_iter()
+            .flat_map_iter(|(_, indices)| indices.into_iter().map(|i| (i, self.verify_token(&tokens[i]))))
+            .collect();
+        let mut results = vec![Ok(()); tokens.len()];
+        for (i, result) in verified {
+            results[i] = result;
+        }
+        results
+    }
+}
+
+/// The same operations against an [`AsyncStateStore`], for async servers whose state lives in a
+/// network store. Hashing still happens inline; only the store is awaited.
+impl<H, S> Registry<H, S>
+where
+    H: Digest + FixedOutputReset,
+    S: AsyncStateStore<Key = ChainId, State = ChainRecord<H>>,
+{
+    pub async fn register_async(&self, chain_id: ChainId, anchor: GenericArray<u8, H::OutputSize>) -> Result<(), VerifyError> {
+        self.store.save(&chain_id, self.first_record(anchor, None)?).await
+            .map_err(|e| VerifyError::Store(e.to_string()))
+    }
+
+    pub async fn record_async(&self, chain_id: &ChainId) -> Result<ChainRecord<H>, VerifyError> {
+        self.store.load(chain_id).await
+            .map_err(|e| VerifyError::Store(e.to_string()))?
+            .ok_or(VerifyError::UnknownChain)
+    }
+
+    pub async fn verify_async(&self, chain_id: &ChainId, index: u64, value: &[u8]) -> Result<(), VerifyError> {
+        self.advance_async(chain_id, index, value).await.map(|_| ())
+    }
+
+    pub async fn advance_async(&self, chain_id: &ChainId, index: u64, value: &[u8]) -> Result<u64, VerifyError> {
+        let outcome = async {
+            let record = self.record_async(chain_id).await?;
+            let (steps, new) = check_disclosure(&self.gap, &record, index, value, None)?;
+            swap_result(self.store.compare_and_swap(chain_id, Some(&record), new).await, steps)
+        }.await;
+        #[cfg(feature = "tracing")]
+        trace_outcome(chain_id, index, &outcome);
+        outcome
+    }
+}
+
+/// The verifier for a single chain, keeping its last accepted position under one key of a
+/// [`StateStore`]. Each accepted value is persisted before [`Verifier::accept`] returns, so a
+/// restarted verifier picks up where it left off and a value accepted before the crash is a
+/// replay afterwards.
+///
+/// This holds as long as the store's swaps are durable once they report success, as a
+/// [`FileStore`](crate::store::FileStore)'s are. An accept that fails with
+/// [`VerifyError::Store`] may or may not have been persisted; [`Verifier::confirm`] tells which
+/// before the disclosure is acted on or refused, and either way it cannot verify twice.
+pub struct Verifier<H, S: StateStore> {
+    store: S,
+    key: S::Key,
+    gap: GapPolicy,
+    resync_bound: u64,
+    /// The values skipped by resynchronizations since the verifier was started.
+    missed: AtomicU64,
+    _hash: PhantomData<H>,
+}
+
+/// How far [`Verifier::resync`] hashes forward unless configured otherwise.
+pub const DEFAULT_RESYNC_BOUND: u64 = 1024;
+
+/// The outcome of a [`Verifier::resync`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Resync {
+    /// The new last accepted index.
+    pub index: u64,
+    /// The values between the previous last accepted one and this one that never arrived.
+    pub missed: u64,
+}
+
+impl<H, S> Verifier<H, S>
+where
+    H: Digest + FixedOutputReset,
+    S: StateStore<State = ChainRecord<H>>,
+{
+    /// Resume the verifier stored under `key`, or start one at `anchor` if there is none.
+    pub fn new(store: S, key: S::Key, anchor: GenericArray<u8, H::OutputSize>) -> Result<Self, VerifyError> {
+        let stored = store.load(&key).map_err(|e| VerifyError::Store(e.to_string()))?;
+        if stored.is_none() {
+            // a concurrent start may have won; its record is as good as ours
+            store.compare_and_swap(&key, None, ChainRecord { index: 0, value: anchor, domain: None })
+                .map_err(|e| VerifyError::Store(e.to_string()))?;
+        }
+        Ok(Verifier { store, key, gap: GapPolicy::default(), resync_bound: DEFAULT_RESYNC_BOUND, missed: AtomicU64::new(0), _hash: PhantomData })
+    }
+
+    /// Bound the work per disclosure instead of by [`DEFAULT_MAX_GAP`], see
+    /// [`Registry::with_max_gap`].
+    pub fn with_max_gap(self, max_gap: u64) -> Self {
+        Verifier { gap: self.gap.with_max_gap(max_gap), ..self }
+    }
+
+    /// Consult `policy` on how far disclosures may skip ahead, see [`GapPolicy`].
+    pub fn with_gap_policy(self, policy: GapPolicy) -> Self {
+        Verifier { gap: policy, ..self }
+    }
+
+    /// Let [`Verifier::resync`] hash forward over at most `bound` positions instead of
+    /// [`DEFAULT_RESYNC_BOUND`].
+    pub fn with_resync_bound(self, bound: u64) -> Self {
+        Verifier { resync_bound: bound, ..self }
+    }
+
+    /// The last accepted position.
+    pub fn last(&self) -> Result<ChainRecord<H>, VerifyError> {
+        self.store.load(&self.key)
+            .map_err(|e| VerifyError::Store(e.to_string()))?
+            .ok_or(VerifyError::UnknownChain)
+    }
+
+    /// Accept `value` at `index` if it hashes forward to the last accepted value, persisting it
+    /// as the new last value. Returns how many positions the chain advanced.
+    pub fn accept(&self, index: u64, value: &[u8]) -> Result<u64, VerifyError> {
+        advance_record(&self.store, &self.key, &self.gap, self.last()?, index, value, None)
+    }
+
+    /// Accept the value `proof` leads to from the last accepted one, at `index`, which the gap
+    /// policy may require for large gaps.
+    pub fn accept_with_proof(&self, index: u64, proof: &Proof<H>) -> Result<u64, VerifyError> {
+        advance_record(&self.store, &self.key, &self.gap, self.last()?, index, proof.value(), Some(proof))
+    }
+
+    /// Accept `value` at `candidate_index` after disclosures were lost, e.g. dropped broadcast
+    /// packets. Unlike [`Verifier::accept`] the gap may exceed the maximum gap, up to the resync
+    /// bound, and the values skipped are reported and added to [`Verifier::missed`]. The rest of
+    /// the gap policy still applies.
+    pub fn resync(&self, candidate_index: u64, value: &[u8]) -> Result<Resync, VerifyError> {
+        let policy = self.gap.clone().with_max_gap(self.resync_bound);
+        let steps = advance_record(&self.store, &self.key, &policy, self.last()?, candidate_index, value, None)?;
+        self.missed.fetch_add(steps - 1, Ordering::Relaxed);
+        Ok(Resync { index: candidate_index, missed: steps - 1 })
+    }
+
+    /// Whether `value` at `index` was persisted, for an accept whose outcome is unknown because
+    /// the store failed or the process crashed while it was under way. `false` means it was
+    /// not: the disclosure may be accepted again.
+    pub fn confirm(&self, index: u64, value: &[u8]) -> Result<bool, VerifyError> {
+        let record = self.last()?;
+        Ok(record.index == index && record.value.as_slice() == value)
+    }
+
+    /// The values skipped by resynchronizations since the verifier was started.
+    pub fn missed(&self) -> u64 {
+        self.missed.load(Ordering::Relaxed)
+    }
+
+    /// Retire the chain, see [`Registry::retire`].
+    pub fn retire(&self, kill_commitment: &[u8], kill_value: &[u8]) -> Result<(), VerifyError> {
+        retire_record(&self.store, &self.key, kill_commitment, kill_value)
+    }
+}
+
+/// Object-safe access to token verification, so that a registry can be shared behind
+/// `Arc<dyn TokenVerifier>` without naming its hash and store types.
+pub trait TokenVerifier: Send + Sync {
+    fn verify_token(&self, token: &ChainToken) -> Result<(), VerifyError>;
+}
+
+impl<H, S> TokenVerifier for Registry<H, S>
+where
+    H: Digest + FixedOutputReset + Send + Sync,
+    S: StateStore<Key = ChainId, State = ChainRecord<H>> + Send + Sync,
+{
+    fn verify_token(&self, token: &ChainToken) -> Result<(), VerifyError> {
+        Registry::verify_token(self, token)
+    }
+}
+
+#[test]
+fn test_token_roundtrip() {
+    let token = ChainToken { chain_id: ChainId([0xab; 16]), index: 42, value: vec![1, 2, 3] };
+    let encoded = token.to_string();
+    assert_eq!(encoded, "abababababababababababababababab.42.010203");
+    assert_eq!(encoded.parse::<ChainToken>(), Ok(token));
+    assert!("abab.42".parse::<ChainToken>().is_err());
+}
+
+#[test]
+fn test_registry_rejects_replays() {
+    use crate::store::MemoryStore;
+    use crate::{create_hash_chain_nopebble, hash_forward};
+    use sha2::Sha256;
+
+    let chain = create_hash_chain_nopebble::<Sha256>(8, 1);
+    let value_at = |i: usize| chain[8 - i];
+    let registry = Registry::<Sha256, _>::new(MemoryStore::new());
+    let id = ChainId([1; 16]);
+    registry.register(id, hash_forward::<Sha256>(&value_at(1), 1)).unwrap();
+
+    registry.verify(&id, 1, &value_at(1)).unwrap();
+    assert_eq!(registry.verify(&id, 1, &value_at(1)), Err(VerifyError::Replay));
+    // skipping ahead is allowed, going back is not
+    registry.verify(&id, 4, &value_at(4)).unwrap();
+    assert_eq!(registry.verify(&id, 3, &value_at(3)), Err(VerifyError::Replay));
+    assert_eq!(registry.verify(&id, 6, &value_at(5)), Err(VerifyError::Mismatch));
+    assert_eq!(registry.verify(&ChainId([2; 16]), 1, &value_at(1)), Err(VerifyError::UnknownChain));
+    assert_eq!(registry.record(&id).unwrap().index, 4);
+
+    let bounded = Registry::<Sha256, _>::new(MemoryStore::new()).with_max_gap(2);
+    bounded.register(id, hash_forward::<Sha256>(&value_at(1), 1)).unwrap();
+    assert_eq!(bounded.verify(&id, 3, &value_at(3)), Err(VerifyError::GapTooLarge));
+    bounded.verify(&id, 2, &value_at(2)).unwrap();
+    bounded.verify(&id, 4, &value_at(4)).unwrap();
+    // without a limit set, a huge index is refused before hashing
+    assert_eq!(registry.verify(&id, u64::MAX, &value_at(5)), Err(VerifyError::GapTooLarge));
+    assert_eq!(GapPolicy::unbounded().max_gap(), None);
+
+    let derived = registry.enroll(hash_forward::<Sha256>(&value_at(1), 1), 8).unwrap();
+    assert_eq!(derived, ChainId::derive(&hash_forward::<Sha256>(&value_at(1), 1), 8));
+    registry.verify(&derived, 1, &value_at(1)).unwrap();
+    let disclosure = crate::disclosure::Disclosure::<Sha256>::new(derived, 2, value_at(2));
+    registry.verify_disclosure(&disclosure).unwrap();
+    assert_eq!(registry.verify_disclosure(&disclosure), Err(VerifyError::Replay));
+}
+
+#[test]
+fn test_registry_retires_chains() {
+    use crate::retire::{kill_commitment, kill_value};
+    use crate::store::MemoryStore;
+    use crate::HashChain;
+    use sha2::Sha256;
+
+    let mut chain = HashChain::<Sha256>::new(8, 1).unwrap();
+    let registry = Registry::<Sha256, _>::new(MemoryStore::new());
+    let id = registry.enroll(*chain.anchor(), 8).unwrap();
+    let commitment = kill_commitment::<Sha256>(&kill_value::<Sha256>(1));
+    let (index, value) = chain.disclose().unwrap();
+    registry.verify(&id, index, &value).unwrap();
+    assert_eq!(registry.retire(&id, &commitment, &kill_value::<Sha256>(2)), Err(VerifyError::Mismatch));
+    registry.retire(&id, &commitment, &kill_value::<Sha256>(1)).unwrap();
+    assert!(registry.record(&id).unwrap().is_retired());
+    // enrolling it again cannot revive it
+    assert_eq!(registry.enroll(*chain.anchor(), 8), Err(VerifyError::AlreadyEnrolled));
+    let (index, value) = chain.disclose().unwrap();
+    assert_eq!(registry.verify(&id, index, &value), Err(VerifyError::Retired));
+    assert_eq!(registry.verify_batch(&id, &[(index, value.to_array())]), Err(VerifyError::Retired));
+}
+
+#[test]
+fn test_registry_domains() {
+    use crate::store::MemoryStore;
+    use crate::HashChain;
+    use sha2::Sha256;
+    use std::sync::Arc;
+
+    let store = Arc::new(MemoryStore::new());
+    let otp = Registry::<Sha256, _>::new(store.clone()).with_domains(&["otp", "metering"]);
+    let (mut token, mut meter) = (HashChain::<Sha256>::new(8, 1).unwrap(), HashChain::<Sha256>::new(8, 2).unwrap());
+    assert_eq!(otp.enroll(*token.anchor(), 8), Err(VerifyError::DomainNotAllowed(String::new())));
+    assert_eq!(otp.enroll_in(*token.anchor(), 8, "billing"), Err(VerifyError::DomainNotAllowed("billing".to_string())));
+    let token_id = otp.enroll_in(*token.anchor(), 8, "otp").unwrap();
+    let meter_id = otp.enroll_in(*meter.anchor(), 8, "metering").unwrap();
+
+    let (index, value) = token.disclose().unwrap();
+    otp.verify_in(&token_id, "otp", index, &value).unwrap();
+    let (index, value) = meter.disclose().unwrap();
+    assert_eq!(otp.verify_in(&meter_id, "otp", index, &value), Err(VerifyError::WrongDomain));
+
+    // the tag is kept in the store, so another registry over it checks it too
+    let metering = Registry::<Sha256, _>::new(store);
+    assert_eq!(metering.advance_in(&meter_id, "metering", index, &value), Ok(1));
+    assert_eq!(metering.record(&meter_id).unwrap().domain.as_deref(), Some("metering"));
+}
+
+#[test]
+fn test_registry_verifies_without_allocating() {
+    use crate::store::MemoryStore;
+    use crate::verify::count_allocations;
+    use crate::HashChain;
+    use sha2::Sha256;
+
+    let registry = Registry::<Sha256, _>::new(MemoryStore::new()).with_max_gap(16);
+    let mut chain = HashChain::<Sha256>::new(64, 3).unwrap();
+    let id = registry.enroll_in(*chain.anchor(), 64, "otp").unwrap();
+    let (index, value) = chain.nth(2).unwrap();
+    let value = value.to_array();
+    assert_eq!(count_allocations(|| registry.advance_in(&id, "otp", index, &value)), (Ok(3), 0));
+    assert_eq!(count_allocations(|| registry.verify(&id, index, &value)), (Err(VerifyError::Replay), 0));
+    let (index, value) = chain.next().unwrap();
+    assert_eq!(count_allocations(|| registry.verify(&id, index, value.as_bytes())), (Ok(()), 0));
+}
+
+#[test]
+fn test_gap_policy() {
+    use crate::disclosure::Disclosure;
+    use crate::store::MemoryStore;
+    use crate::HashChain;
+    use sha2::Sha256;
+    use std::sync::atomic::AtomicU64;
+
+    let skipped = Arc::new(AtomicU64::new(0));
+    let charged = skipped.clone();
+    let policy = GapPolicy::default().with_max_gap(8).require_proof_over(2).with_penalty(move |gap| charged.fetch_add(gap - 1, Ordering::Relaxed) < 3);
+    let registry = Registry::<Sha256, _>::new(MemoryStore::new()).with_gap_policy(policy);
+    let mut chain = HashChain::<Sha256>::new(32, 5).unwrap();
+    let id = registry.enroll(*chain.anchor(), 32).unwrap();
+    let values: Vec<_> = chain.by_ref().map(|(_, value)| value.to_array()).collect();
+
+    assert_eq!(registry.advance(&id, 2, &values[1]), Ok(2));
+    assert_eq!(registry.advance(&id, 5, &values[4]), Err(VerifyError::ProofRequired));
+    let proven = Disclosure::<Sha256>::new(id, 5, values[4]).with_proof(3);
+    registry.verify_disclosure(&proven).unwrap();
+    assert_eq!(registry.advance(&id, 14, &values[13]), Err(VerifyError::GapTooLarge));
+    // the penalty refuses once four skipped values have been charged
+    assert_eq!(registry.advance(&id, 7, &values[6]), Err(VerifyError::GapTooLarge));
+    assert_eq!(skipped.load(Ordering::Relaxed), 4);
+    assert_eq!(registry.advance(&id, 6, &values[5]), Ok(1));
+    assert_eq!(format!("{:?}", GapPolicy::strict()), "GapPolicy { max_gap: Some(1), proof_over: None, penalty: false }");
+}
+
+#[cfg(feature = "rayon")]
+#[test]
+fn test_verify_many_par() {
+    use crate::store::MemoryStore;
+    use crate::HashChain;
+    use sha2::Sha256;
+
+    let registry = Registry::<Sha256, _>::new(MemoryStore::new());
+    let mut tokens = Vec::new();
+    for n in 0..8u8 {
+        let id = ChainId([n; 16]);
+        let mut chain = HashChain::<Sha256>::new(16, n as u64).unwrap();
+        registry.register(id, *chain.anchor()).unwrap();
+        for (index, value) in chain.by_ref().take(3) {
+            tokens.push(ChainToken { chain_id: id, index, value: value.to_vec() });
+        }
+    }
+    tokens.push(tokens[4].clone());
+    let results = registry.verify_many_par(&tokens);
+    assert!(results[..24].iter().all(Result::is_ok));
+    assert_eq!(results[24], Err(VerifyError::Replay));
+}
+
+#[test]
+fn test_verifier_survives_restart() {
+    use crate::store::MemoryStore;
+    use crate::HashChain;
+    use sha2::Sha256;
+    use std::sync::Arc;
+
+    let mut chain = HashChain::<Sha256>::new(16, 9).unwrap();
+    let store = Arc::new(MemoryStore::new());
+    let verifier = Verifier::<Sha256, _>::new(store.clone(), "meter", *chain.anchor()).unwrap();
+    let (index, value) = chain.nth(1).unwrap();
+    assert_eq!(verifier.accept(index, &value), Ok(2));
+
+    // a verifier restarted from the store treats the value as spent
+    let restarted = Verifier::<Sha256, _>::new(store, "meter", *chain.anchor()).unwrap();
+    assert_eq!(restarted.accept(index, &value), Err(VerifyError::Replay));
+    let (index, value) = chain.next().unwrap();
+    assert_eq!(restarted.accept(index, &value), Ok(1));
+    assert_eq!(restarted.last().unwrap().index, 3);
+
+    // after lost disclosures the gap is beyond the maximum for accept but within the resync bound
+    let restarted = restarted.with_max_gap(1).with_resync_bound(4);
+    let (index, value) = chain.nth(2).unwrap();
+    assert_eq!(restarted.accept(index, &value), Err(VerifyError::GapTooLarge));
+    assert_eq!(restarted.resync(index, &value), Ok(Resync { index: 6, missed: 2 }));
+    let (index, value) = chain.nth(4).unwrap();
+    assert_eq!(restarted.resync(index, &value), Err(VerifyError::GapTooLarge));
+    assert_eq!(restarted.missed(), 2);
+}
+
+#[test]
+fn test_registry_async() {
+    use crate::store::MemoryStore;
+    use crate::HashChain;
+    use sha2::Sha256;
+
+    let mut chain = HashChain::<Sha256>::new(8, 3).unwrap();
+    let registry = Registry::<Sha256, _>::new(MemoryStore::new());
+    let id = ChainId([3; 16]);
+    let runtime = tokio::runtime::Builder::new_current_thread().build().unwrap();
+    runtime.block_on(async {
+        registry.register_async(id, *chain.anchor()).await.unwrap();
+        let (index, value) = chain.disclose().unwrap();
+        assert_eq!(registry.advance_async(&id, index, &value).await, Ok(1));
+        assert_eq!(registry.verify_async(&id, index, &value).await, Err(VerifyError::Replay));
+        let (index, value) = chain.disclose().unwrap();
+        assert_eq!(registry.verify_async(&ChainId([4; 16]), index, &value).await, Err(VerifyError::UnknownChain));
+        registry.verify_async(&id, index, &value).await.unwrap();
+    });
+    // both faces share the store
+    assert_eq!(registry.record(&id).unwrap().index, 2);
+}
@@ -0,0 +1,162 @@
+//! Timed release: a publisher commits to a chain anchor and an [`EpochSchedule`], then discloses
+//! the chain value of each epoch once it begins. Subscribers check that every value extends the
+//! last one they accepted, so a value proves the publisher took part in that epoch. That is
+//! enough for scheduled reveals (anything keyed by the value of epoch `i` stays sealed until
+//! epoch `i`) and dead man's switches (a publisher that stops disclosing becomes overdue).
+
+use crate::epoch::EpochSchedule;
+use crate::{verify, HashChain};
+use digest::generic_array::GenericArray;
+use digest::{Digest, FixedOutputReset, OutputSizeUser};
+use std::error::Error;
+use std::fmt::{self, Display};
+use std::time::SystemTime;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ReleaseError {
+    /// The value belongs to an epoch that has not started yet.
+    Early,
+    /// The value is not newer than the last accepted one.
+    Stale,
+    /// The value does not extend the last accepted one.
+    Invalid,
+}
+
+impl Display for ReleaseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ReleaseError::Early => write!(f, "release for an epoch that has not started"),
+            ReleaseError::Stale => write!(f, "release is not newer than the last one"),
+            ReleaseError::Invalid => write!(f, "release does not extend the chain"),
+        }
+    }
+}
+
+impl Error for ReleaseError {}
+
+/// The chain value disclosed for an epoch.
+pub struct Release<H: OutputSizeUser> {
+    pub epoch: u64,
+    pub value: GenericArray<u8, H::OutputSize>,
+}
+
+impl<H: OutputSizeUser> Clone for Release<H> {
+    fn clone(&self) -> Self {
+        Release { epoch: self.epoch, value: self.value.clone() }
+    }
+}
+
+pub struct Publisher<H: Digest + FixedOutputReset> {
+    chain: HashChain<H>,
+    schedule: EpochSchedule,
+}
+
+impl<H: Digest + FixedOutputReset> Publisher<H> {
+    pub fn new(chain: HashChain<H>, schedule: EpochSchedule) -> Self {
+        Publisher { chain, schedule }
+    }
+
+    /// The value subscribers start from.
+    pub fn anchor(&self) -> &GenericArray<u8, H::OutputSize> {
+        self.chain.anchor()
+    }
+
+    pub fn schedule(&self) -> &EpochSchedule {
+        &self.schedule
+    }
+
+    /// The release due at `now`, if a new epoch has begun since the last one. Epochs missed in
+    /// between are skipped; the latest value covers them.
+    pub fn due(&mut self, now: SystemTime) -> Option<Release<H>> {
+        let epoch = self.schedule.index_at(now)?.min(self.chain.length());
+        let position = HashChain::position(&self.chain);
+        if epoch <= position {
+            return None;
+        }
+        let (epoch, value) = self.chain.nth((epoch - position - 1) as usize)?;
+        Some(Release { epoch, value: value.to_array() })
+    }
+
+    /// When the next release falls due, or `None` once the chain is used up or if that time is
+    /// out of range. A daemon can sleep until then and call [`Publisher::due`].
+    pub fn next_release_at(&self) -> Option<SystemTime> {
+        (self.chain.remaining() > 0).then(|| self.schedule.start_of(HashChain::position(&self.chain) + 1)).flatten()
+    }
+}
+
+pub struct Subscriber<H: Digest + FixedOutputReset> {
+    schedule: EpochSchedule,
+    epoch: u64,
+    value: GenericArray<u8, H::OutputSize>,
+}
+
+impl<H: Digest + FixedOutputReset> Subscriber<H> {
+    pub fn new(anchor: GenericArray<u8, H::OutputSize>, schedule: EpochSchedule) -> Self {
+        Subscriber { schedule, epoch: 0, value: anchor }
+    }
+
+    /// The epoch of the last accepted release, 0 before the first.
+    pub fn epoch(&self) -> u64 {
+        self.epoch
+    }
+
+    /// The last accepted value, which is the anchor before the first release.
+    pub fn latest(&self) -> &GenericArray<u8, H::OutputSize> {
+        &self.value
+    }
+
+    /// Accept `release` if its epoch may have begun at local time `now` and it extends the last
+    /// accepted value.
+    pub fn accept(&mut self, release: &Release<H>, now: SystemTime) -> Result<(), ReleaseError> {
+        if release.epoch > *self.schedule.possible_indices(now).end() {
+            return Err(ReleaseError::Early);
+        }
+        if release.epoch <= self.epoch {
+            return Err(ReleaseError::Stale);
+        }
+        if !verify::<H>(self.epoch, &self.value, release.epoch, &release.value) {
+            return Err(ReleaseError::Invalid);
+        }
+        self.epoch = release.epoch;
+        self.value = release.value.clone();
+        Ok(())
+    }
+
+    /// How many epochs the publisher is behind at local time `now`: 0 while the current epoch's
+    /// value has been accepted. A dead man's switch fires once this passes its grace period.
+    pub fn overdue(&self, now: SystemTime) -> u64 {
+        let current = *self.schedule.possible_indices(now).start();
+        current.saturating_sub(self.epoch)
+    }
+}
+
+#[test]
+fn test_timed_release() {
+    use sha2::Sha256;
+    use std::time::{Duration, UNIX_EPOCH};
+
+    let start = UNIX_EPOCH + Duration::from_secs(3_600);
+    let schedule = EpochSchedule::new(start, Duration::from_secs(60));
+    let mut publisher = Publisher::new(HashChain::<Sha256>::new(8, 11).unwrap(), schedule);
+    let mut subscriber = Subscriber::<Sha256>::new(*publisher.anchor(), schedule);
+    assert!(publisher.due(start - Duration::from_secs(1)).is_none());
+    assert_eq!(publisher.next_release_at(), Some(start));
+
+    let first = publisher.due(start + Duration::from_secs(5)).unwrap();
+    assert!(publisher.due(start + Duration::from_secs(6)).is_none());
+    assert_eq!(subscriber.accept(&first, start - Duration::from_secs(1)), Err(ReleaseError::Early));
+    subscriber.accept(&first, start + Duration::from_secs(5)).unwrap();
+    assert_eq!(subscriber.accept(&first, start + Duration::from_secs(5)), Err(ReleaseError::Stale));
+
+    // the publisher skips two epochs, then the subscriber notices it is overdue
+    let now = start + Duration::from_secs(185);
+    assert_eq!(subscriber.overdue(now), 3);
+    let fourth = publisher.due(now).unwrap();
+    assert_eq!(fourth.epoch, 4);
+    let mut forged = fourth.clone();
+    forged.value[0] ^= 1;
+    assert_eq!(subscriber.accept(&forged, now), Err(ReleaseError::Invalid));
+    subscriber.accept(&fourth, now).unwrap();
+    assert_eq!(subscriber.overdue(now), 0);
+    assert_eq!(publisher.next_release_at(), Some(start + Duration::from_secs(240)));
+}
@@ -0,0 +1,183 @@
+//! An append-only record of disclosures, for recovery from backups.
+//!
+//! A chain restored from a backup is behind the live one by whatever was disclosed since the
+//! backup was taken, and disclosing those values again would give an observer two uses of the
+//! same credential. Appending every disclosure to a [`DisclosureLog`] beside the backup lets
+//! the operator [`diff`](DisclosureLog::diff) the log against the restored position and
+//! [`replay`](DisclosureLog::replay) the chain past everything already disclosed.
+//!
+//! Each entry is 32 bytes: a counter that increases by one per entry, the index, and the first
+//! 16 bytes of SHA-256 over the value, which identifies it without keeping the value around.
+//! The counter exposes entries lost from the middle of the log.
+
+use crate::HashChain;
+use digest::{Digest, FixedOutputReset};
+use sha2::Sha256;
+use std::error::Error;
+use std::fmt::{self, Display};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ReplayLogError {
+    /// The index is not above the last logged one.
+    OutOfOrder { last: u64 },
+    /// The encoding ends part of the way through an entry, e.g. after a torn write.
+    Truncated,
+    /// An entry's counter does not follow the one before it.
+    CounterGap { expected: u64 },
+    /// The chain's value at `index` is not the logged one, so the log is for another chain.
+    Mismatch { index: u64 },
+}
+
+impl Display for ReplayLogError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ReplayLogError::OutOfOrder { last } => write!(f, "index not above the last logged index {}", last),
+            ReplayLogError::Truncated => write!(f, "disclosure log truncated"),
+            ReplayLogError::CounterGap { expected } => write!(f, "disclosure log entry {} missing", expected),
+            ReplayLogError::Mismatch { index } => write!(f, "chain value at index {} does not match the log", index),
+        }
+    }
+}
+
+impl Error for ReplayLogError {}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct LogEntry {
+    pub counter: u64,
+    pub index: u64,
+    pub digest: [u8; 16],
+}
+
+impl LogEntry {
+    /// The counter and index as u64 big endian, then the digest.
+    pub fn to_bytes(&self) -> [u8; 32] {
+        let mut bytes = [0u8; 32];
+        bytes[..8].copy_from_slice(&self.counter.to_be_bytes());
+        bytes[8..16].copy_from_slice(&self.index.to_be_bytes());
+        bytes[16..].copy_from_slice(&self.digest);
+        bytes
+    }
+
+    pub fn from_bytes(bytes: &[u8; 32]) -> Self {
+        let field = |at: usize| u64::from_be_bytes(bytes[at..at + 8].try_into().expect("8 bytes"));
+        LogEntry { counter: field(0), index: field(8), digest: bytes[16..].try_into().expect("16 bytes") }
+    }
+}
+
+fn value_digest(value: &[u8]) -> [u8; 16] {
+    Sha256::digest(value)[..16].try_into().expect("SHA-256 output is 32 bytes")
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct DisclosureLog {
+    entries: Vec<LogEntry>,
+}
+
+impl DisclosureLog {
+    pub fn new() -> Self {
+        DisclosureLog::default()
+    }
+
+    /// Record the disclosure of `value` at `index`, returning the entry to persist.
+    pub fn append(&mut self, index: u64, value: &[u8]) -> Result<LogEntry, ReplayLogError> {
+        let (counter, last) = self.entries.last().map_or((1, 0), |entry| (entry.counter + 1, entry.index));
+        if index <= last {
+            return Err(ReplayLogError::OutOfOrder { last });
+        }
+        let entry = LogEntry { counter, index, digest: value_digest(value) };
+        self.entries.push(entry);
+        Ok(entry)
+    }
+
+    pub fn entries(&self) -> &[LogEntry] {
+        &self.entries
+    }
+
+    /// The highest logged index, 0 for an empty log.
+    pub fn last_index(&self) -> u64 {
+        self.entries.last().map_or(0, |entry| entry.index)
+    }
+
+    /// The entries disclosed beyond `position`, e.g. the position of a restored chain. If any
+    /// are returned, the restored chain must not disclose until it has been replayed.
+    pub fn diff(&self, position: u64) -> &[LogEntry] {
+        &self.entries[self.entries.partition_point(|entry| entry.index <= position)..]
+    }
+
+    /// Move `chain` past every logged disclosure beyond its position, checking the logged values
+    /// on the way, and return how many entries were replayed.
+    pub fn replay<H: Digest + FixedOutputReset>(&self, chain: &mut HashChain<H>) -> Result<usize, ReplayLogError> {
+        let pending = self.diff(HashChain::position(chain));
+        for entry in pending {
+            while HashChain::position(chain) + 1 < entry.index {
+                chain.disclose();
+            }
+            match chain.disclose() {
+                Some((_, value)) if value_digest(&value) == entry.digest => {}
+                _ => return Err(ReplayLogError::Mismatch { index: entry.index }),
+            }
+        }
+        Ok(pending.len())
+    }
+
+    /// The concatenated entries, ready to be written as a whole or appended entry by entry.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        self.entries.iter().flat_map(LogEntry::to_bytes).collect()
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, ReplayLogError> {
+        let (chunks, rest) = bytes.as_chunks::<32>();
+        if !rest.is_empty() {
+            return Err(ReplayLogError::Truncated);
+        }
+        let mut log = DisclosureLog::new();
+        for entry in chunks.iter().map(LogEntry::from_bytes) {
+            let expected = log.entries.last().map_or(1, |last| last.counter + 1);
+            if entry.counter != expected {
+                return Err(ReplayLogError::CounterGap { expected });
+            }
+            if entry.index <= log.last_index() {
+                return Err(ReplayLogError::OutOfOrder { last: log.last_index() });
+            }
+            log.entries.push(entry);
+        }
+        Ok(log)
+    }
+}
+
+impl crate::version::Versioned for DisclosureLog {
+    type Error = ReplayLogError;
+
+    fn encode_body(&self, _version: u8) -> Vec<u8> {
+        self.to_bytes()
+    }
+
+    fn decode_body(_version: u8, body: &[u8]) -> Result<Self, ReplayLogError> {
+        Self::from_bytes(body)
+    }
+}
+
+#[test]
+fn test_disclosure_log_recovery() {
+    let mut chain = HashChain::<Sha256>::new(16, 2).unwrap();
+    chain.disclose();
+    let backup = chain.export_state();
+
+    let mut log = DisclosureLog::new();
+    for (index, value) in chain.by_ref().take(5).step_by(2) {
+        log.append(index, &value).unwrap();
+    }
+    assert_eq!(log.append(3, &[0; 32]), Err(ReplayLogError::OutOfOrder { last: 6 }));
+    let log = DisclosureLog::from_bytes(&log.to_bytes()).unwrap();
+    assert!(DisclosureLog::from_bytes(&log.to_bytes()[..40]).is_err());
+    assert_eq!(DisclosureLog::from_bytes(&log.to_bytes()[32..]), Err(ReplayLogError::CounterGap { expected: 1 }));
+
+    let mut restored = HashChain::<Sha256>::import_state(&backup).unwrap();
+    assert_eq!(log.diff(HashChain::position(&restored)).iter().map(|entry| entry.index).collect::<Vec<_>>(), vec![2, 4, 6]);
+    assert_eq!(log.replay(&mut restored), Ok(3));
+    assert_eq!(restored.disclose(), chain.disclose());
+    assert!(log.diff(HashChain::position(&restored)).is_empty());
+
+    let mut other = HashChain::<Sha256>::new(16, 3).unwrap();
+    assert_eq!(log.replay(&mut other), Err(ReplayLogError::Mismatch { index: 2 }));
+}
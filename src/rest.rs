@@ -0,0 +1,169 @@
+//! A JSON-over-HTTP verification API built with [axum](https://docs.rs/axum), served by the
+//! `fht-server` binary.
+//!
+//! - `POST /enroll` with `{"anchor", "length"}` registers a chain under its derived id and
+//!   answers `{"chain_id"}`. A request may name the id as well, which must then match. Chains
+//!   already enrolled are refused with 409, so nobody can reset another's chain.
+//! - `POST /verify` with `{"chain_id", "index", "value"}` verifies a disclosure and answers
+//!   `{"advanced"}`. The hashing runs on tokio's blocking pool, bounded by the registry's
+//!   [`GapPolicy`](crate::registry::GapPolicy).
+//! - `GET /status/{chain_id}` answers the last accepted `{"index", "value"}`.
+//!
+//! Chain ids and values are hex. Errors are answered as `{"error"}` with a matching status code.
+
+use crate::registry::{ChainRecord, Registry, VerifyError};
+use crate::store::StateStore;
+use crate::value::ChainValue;
+use crate::ChainId;
+use axum::extract::{Path, State};
+use axum::http::StatusCode;
+use axum::response::{IntoResponse, Response};
+use axum::routing::{get, post};
+use axum::{Json, Router};
+use digest::{Digest, FixedOutputReset};
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+
+#[derive(Deserialize)]
+struct EnrollRequest {
+    chain_id: Option<String>,
+    anchor: String,
+    length: u64,
+}
+
+#[derive(Serialize)]
+struct EnrollResponse {
+    chain_id: String,
+}
+
+#[derive(Deserialize)]
+struct VerifyRequest {
+    chain_id: String,
+    index: u64,
+    value: String,
+}
+
+#[derive(Serialize)]
+struct VerifyResponse {
+    advanced: u64,
+}
+
+#[derive(Serialize)]
+struct StatusResponse {
+    index: u64,
+    value: String,
+}
+
+/// A rejected request, answered as `{"error": ...}`.
+struct ApiError(StatusCode, String);
+
+impl IntoResponse for ApiError {
+    fn into_response(self) -> Response {
+        (self.0, Json(serde_json::json!({ "error": self.1 }))).into_response()
+    }
+}
+
+impl From<VerifyError> for ApiError {
+    fn from(error: VerifyError) -> Self {
+        let status = match error {
+            VerifyError::UnknownChain => StatusCode::NOT_FOUND,
+            VerifyError::Replay | VerifyError::NotNext | VerifyError::GapTooLarge | VerifyError::Mismatch | VerifyError::WrongDomain => StatusCode::FORBIDDEN,
+            VerifyError::Conflict | VerifyError::AlreadyEnrolled => StatusCode::CONFLICT,
+            VerifyError::Retired => StatusCode::GONE,
+            VerifyError::ProofRequired => StatusCode::PRECONDITION_REQUIRED,
+            VerifyError::DomainNotAllowed(_) => StatusCode::BAD_REQUEST,
+            VerifyError::Store(_) => StatusCode::SERVICE_UNAVAILABLE,
+        };
+        ApiError(status, error.to_string())
+    }
+}
+
+fn bad_request<E: ToString>(error: E) -> ApiError {
+    ApiError(StatusCode::BAD_REQUEST, error.to_string())
+}
+
+/// The routes above, over `registry`.
+pub fn router<H, S>(registry: Arc<Registry<H, S>>) -> Router
+where
+    H: Digest + FixedOutputReset + Send + Sync + 'static,
+    S: StateStore<Key = ChainId, State = ChainRecord<H>> + Send + Sync + 'static,
+{
+    Router::new()
+        .route("/enroll", post(enroll::<H, S>))
+        .route("/verify", post(verify::<H, S>))
+        .route("/status/{chain_id}", get(status::<H, S>))
+        .with_state(registry)
+}
+
+async fn enroll<H, S>(State(registry): State<Arc<Registry<H, S>>>, Json(request): Json<EnrollRequest>) -> Result<Json<EnrollResponse>, ApiError>
+where
+    H: Digest + FixedOutputReset,
+    S: StateStore<Key = ChainId, State = ChainRecord<H>>,
+{
+    let anchor = ChainValue::<H>::from_hex(&request.anchor).map_err(|e| bad_request(format!("invalid anchor: {}", e)))?;
+    let named = request.chain_id.map(|id| id.parse::<ChainId>()).transpose().map_err(bad_request)?;
+    if named.is_some_and(|named| named != ChainId::derive(&anchor, request.length)) {
+        return Err(bad_request("chain id does not match the anchor"));
+    }
+    let chain_id = registry.enroll(anchor.to_array(), request.length)?;
+    Ok(Json(EnrollResponse { chain_id: chain_id.to_string() }))
+}
+
+async fn verify<H, S>(State(registry): State<Arc<Registry<H, S>>>, Json(request): Json<VerifyRequest>) -> Result<Json<VerifyResponse>, ApiError>
+where
+    H: Digest + FixedOutputReset + Send + Sync + 'static,
+    S: StateStore<Key = ChainId, State = ChainRecord<H>> + Send + Sync + 'static,
+{
+    let chain_id = request.chain_id.parse().map_err(bad_request)?;
+    let value = hex::decode(&request.value).map_err(bad_request)?;
+    let advanced = tokio::task::spawn_blocking(move || registry.advance(&chain_id, request.index, &value))
+        .await
+        .map_err(|e| ApiError(StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))??;
+    Ok(Json(VerifyResponse { advanced }))
+}
+
+async fn status<H, S>(State(registry): State<Arc<Registry<H, S>>>, Path(chain_id): Path<String>) -> Result<Json<StatusResponse>, ApiError>
+where
+    H: Digest + FixedOutputReset,
+    S: StateStore<Key = ChainId, State = ChainRecord<H>>,
+{
+    let record = registry.record(&chain_id.parse().map_err(bad_request)?)?;
+    Ok(Json(StatusResponse { index: record.index, value: hex::encode(record.value) }))
+}
+
+#[test]
+fn test_rest_api() {
+    use crate::store::MemoryStore;
+    use crate::HashChain;
+    use axum::body::Body;
+    use axum::http::Request;
+    use sha2::Sha256;
+    use tower_service::Service;
+
+    let runtime = tokio::runtime::Builder::new_current_thread().build().unwrap();
+    let mut app = router(Arc::new(Registry::<Sha256, _>::new(MemoryStore::new()).with_max_gap(4)));
+    let mut call = |request: Request<Body>| runtime.block_on(app.call(request)).unwrap().status();
+    let post = |uri: &str, body: serde_json::Value| Request::post(uri).header("content-type", "application/json").body(Body::from(body.to_string())).unwrap();
+
+    let mut chain = HashChain::<Sha256>::new(16, 3).unwrap();
+    let arbitrary = serde_json::json!({ "chain_id": "000102030405060708090a0b0c0d0e0f", "anchor": hex::encode(chain.anchor()) });
+    assert_eq!(call(post("/enroll", arbitrary)), StatusCode::UNPROCESSABLE_ENTITY);
+    let mismatched = serde_json::json!({ "chain_id": "000102030405060708090a0b0c0d0e0f", "anchor": hex::encode(chain.anchor()), "length": 16 });
+    assert_eq!(call(post("/enroll", mismatched)), StatusCode::BAD_REQUEST);
+    assert_eq!(call(post("/enroll", serde_json::json!({ "anchor": hex::encode(chain.anchor()), "length": 16 }))), StatusCode::OK);
+    let id = chain.chain_id().to_string();
+    assert_eq!(call(Request::get(format!("/status/{}", id)).body(Body::empty()).unwrap()), StatusCode::OK);
+    let (index, value) = chain.disclose().unwrap();
+    let disclosure = serde_json::json!({ "chain_id": id, "index": index, "value": hex::encode(&value) });
+    assert_eq!(call(post("/verify", disclosure.clone())), StatusCode::OK);
+    assert_eq!(call(post("/verify", disclosure)), StatusCode::FORBIDDEN);
+    let far = serde_json::json!({ "chain_id": id, "index": u64::MAX, "value": hex::encode(&value) });
+    assert_eq!(call(post("/verify", far)), StatusCode::FORBIDDEN);
+    assert_eq!(ApiError::from(VerifyError::ProofRequired).0, StatusCode::PRECONDITION_REQUIRED);
+    assert_eq!(call(Request::get(format!("/status/{}", id)).body(Body::empty()).unwrap()), StatusCode::OK);
+    assert_eq!(call(Request::get("/status/not-hex").body(Body::empty()).unwrap()), StatusCode::BAD_REQUEST);
+
+    // enrolling the live chain again would reset it to the anchor
+    let again = serde_json::json!({ "chain_id": id, "anchor": hex::encode(chain.anchor()), "length": 16 });
+    assert_eq!(call(post("/enroll", again)), StatusCode::CONFLICT);
+}
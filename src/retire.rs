@@ -0,0 +1,36 @@
+//! Retiring a chain before it runs out, e.g. when the device holding it is lost.
+//!
+//! Every chain has a kill value, derived from its seed and so known only to whoever set the
+//! chain up, and the commitment to a chain can carry the kill value's digest. Disclosing the
+//! kill value proves the chain's owner wants it retired, and verifiers refuse every disclosure
+//! from it afterwards. The device only needs its traversal state, so the seed, and with it the
+//! kill value, can stay with the owner.
+//!
+//! The kill value is `H("chain kill value" || seed)` with the seed as u64 little endian, and its
+//! digest is `H("chain kill commitment" || kill value)`.
+
+use digest::{Digest, Output};
+
+pub fn kill_value<H: Digest>(seed: u64) -> Output<H> {
+    H::new_with_prefix(b"chain kill value").chain_update(seed.to_le_bytes()).finalize()
+}
+
+/// The digest of `kill_value` to bind into the chain's commitment.
+pub fn kill_commitment<H: Digest>(kill_value: &[u8]) -> Output<H> {
+    H::new_with_prefix(b"chain kill commitment").chain_update(kill_value).finalize()
+}
+
+/// Whether `candidate` is the kill value `commitment` binds.
+pub fn is_kill_value<H: Digest>(commitment: &[u8], candidate: &[u8]) -> bool {
+    kill_commitment::<H>(candidate).as_slice() == commitment
+}
+
+#[test]
+fn test_kill_value() {
+    use sha2::Sha256;
+
+    let commitment = kill_commitment::<Sha256>(&kill_value::<Sha256>(7));
+    assert!(is_kill_value::<Sha256>(&commitment, &kill_value::<Sha256>(7)));
+    assert!(!is_kill_value::<Sha256>(&commitment, &kill_value::<Sha256>(8)));
+    assert!(!is_kill_value::<Sha256>(&commitment[..16], &kill_value::<Sha256>(7)));
+}
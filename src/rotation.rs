@@ -0,0 +1,374 @@
+//! Rotating to an independently set up chain without bootstrapping verifiers again.
+//!
+//! [`RotatingChain::rotate`] takes the successor and a number of final values of the retiring
+//! chain that link to it. Each of them carries the link digest `H("chain link" || next_anchor)`,
+//! and every one but the last a tag on the digest keyed by the value after it,
+//! `H("chain link tag" || v_(i+1) || digest)`, which the verifier checks once that value arrives.
+//! The last linked value reveals the successor's anchor, which the verifier takes only if it
+//! hashes to a digest whose tag verified, and the successor's values are then verified against
+//! it. As with TESLA, a tag only binds the digest if it reaches the verifier before the value
+//! keying it is disclosed. The values of the retiring chain past the link are never disclosed.
+//!
+//! Unlike an [`EndlessChain`](crate::endless::EndlessChain) the successor can be any chain, set
+//! up whenever the rotation is decided, and its anchor stays unknown until the rotation is done.
+//!
+//! By default the verifier refuses the retiring chain as soon as the successor's first value
+//! arrives. A client still holding the retiring chain's state, e.g. a second device that missed
+//! the rotation, would be locked out, so [`RotatingVerifier::with_overlap`] keeps accepting the
+//! retiring chain for a while as an [`Overlap`] allows. Its values past the link were never
+//! disclosed, so they still authenticate the client; each is accepted once, like any other.
+
+use crate::{hash_forward, verify, ChainInitError, HashChain};
+use core::error::Error;
+use core::fmt::{self, Display};
+use digest::{Digest, FixedOutputReset, Output};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RotationError {
+    /// The disclosure is neither of the verifier's generation nor of the next one, nor of the
+    /// previous one while it overlaps.
+    WrongGeneration,
+    /// A new generation began before the link to it was followed.
+    NotLinked,
+    /// A link does not match its tag or the digest confirmed before. The disclosure itself was
+    /// accepted and the link dropped.
+    LinkMismatch,
+    /// The index is not above the last accepted one.
+    Replay,
+    /// The value does not verify against the generation's chain.
+    Mismatch,
+}
+
+impl Display for RotationError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            RotationError::WrongGeneration => write!(f, "disclosure for another generation"),
+            RotationError::NotLinked => write!(f, "new generation without a link"),
+            RotationError::LinkMismatch => write!(f, "link does not match its tag"),
+            RotationError::Replay => write!(f, "index already accepted"),
+            RotationError::Mismatch => write!(f, "value does not verify against the chain"),
+        }
+    }
+}
+
+impl Error for RotationError {}
+
+/// The link to the successor, carried by the final values of a retiring chain.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AnchorLink<H: Digest> {
+    /// `H("chain link" || next_anchor)`.
+    pub digest: Output<H>,
+    /// Keyed by the value after this one, absent on the last linked value.
+    pub tag: Option<Output<H>>,
+    /// The successor's anchor, revealed by the last linked value only.
+    pub next_anchor: Option<Output<H>>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RotatingDisclosure<H: Digest> {
+    pub generation: u64,
+    pub index: u64,
+    pub value: Output<H>,
+    pub link: Option<AnchorLink<H>>,
+}
+
+/// The digest a retiring chain commits to its successor's anchor with.
+pub fn link_digest<H: Digest>(next_anchor: &[u8]) -> Output<H> {
+    H::new_with_prefix(b"chain link").chain_update(next_anchor).finalize()
+}
+
+fn link_tag<H: Digest>(key: &Output<H>, digest: &Output<H>) -> Output<H> {
+    H::new_with_prefix(b"chain link tag").chain_update(key).chain_update(digest).finalize()
+}
+
+struct Rotation<H: Digest + FixedOutputReset> {
+    successor: HashChain<H>,
+    digest: Output<H>,
+    /// The linked values still to disclose, the one in `ahead` included.
+    left: u64,
+    /// The next value of the retiring chain, taken early to key the tag before it.
+    ahead: (u64, Output<H>),
+}
+
+pub struct RotatingChain<H: Digest + FixedOutputReset> {
+    generation: u64,
+    chain: HashChain<H>,
+    rotation: Option<Rotation<H>>,
+}
+
+impl<H: Digest + FixedOutputReset> RotatingChain<H> {
+    pub fn new(chain: HashChain<H>) -> Self {
+        RotatingChain { generation: 0, chain, rotation: None }
+    }
+
+    pub fn generation(&self) -> u64 {
+        self.generation
+    }
+
+    /// The chain values are disclosed from, the retiring one until the rotation is done.
+    pub fn current(&self) -> &HashChain<H> {
+        &self.chain
+    }
+
+    /// Whether a rotation is under way.
+    pub fn rotating(&self) -> bool {
+        self.rotation.is_some()
+    }
+
+    /// Rotate to `successor`, linking to it from the next `handover` values of the current
+    /// chain, at least 2 so that one of them is tagged.
+    pub fn rotate(&mut self, successor: HashChain<H>, handover: u64) -> Result<(), ChainInitError> {
+        if self.rotation.is_some() {
+            return Err(ChainInitError::new("a rotation is already under way"));
+        }
+        if handover < 2 || handover > self.chain.remaining() {
+            return Err(ChainInitError::new("handover must be between 2 and the values left"));
+        }
+        let (index, value) = self.chain.disclose().expect("values left checked above");
+        let digest = link_digest::<H>(successor.anchor());
+        self.rotation = Some(Rotation { successor, digest, left: handover, ahead: (index, value.to_array()) });
+        Ok(())
+    }
+
+    /// Disclose the next value, moving on to the successor once the link to it is complete.
+    /// `None` once the chain is exhausted without a rotation.
+    pub fn disclose(&mut self) -> Option<RotatingDisclosure<H>> {
+        let Some(mut rotation) = self.rotation.take() else {
+            let (index, value) = self.chain.disclose()?;
+            return Some(RotatingDisclosure { generation: self.generation, index, value: value.to_array(), link: None });
+        };
+        let generation = self.generation;
+        let (index, value) = rotation.ahead.clone();
+        rotation.left -= 1;
+        let link = if rotation.left > 0 {
+            let (next_index, next_value) = self.chain.disclose().expect("handover checked against the values left");
+            let tag = link_tag::<H>(&next_value, &rotation.digest);
+            let link = AnchorLink { digest: rotation.digest.clone(), tag: Some(tag), next_anchor: None };
+            rotation.ahead = (next_index, next_value.to_array());
+            self.rotation = Some(rotation);
+            link
+        } else {
+            let link = AnchorLink { digest: rotation.digest, tag: None, next_anchor: Some(rotation.successor.anchor().clone()) };
+            self.chain = rotation.successor;
+            self.generation += 1;
+            link
+        };
+        Some(RotatingDisclosure { generation, index, value, link: Some(link) })
+    }
+}
+
+impl<H: Digest + FixedOutputReset> Iterator for RotatingChain<H> {
+    type Item = RotatingDisclosure<H>;
+
+    fn next(&mut self) -> Option<RotatingDisclosure<H>> {
+        self.disclose()
+    }
+}
+
+/// How long a verifier keeps accepting a retired generation next to its successor.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Overlap {
+    /// Not once the successor's first value has been accepted.
+    #[default]
+    None,
+    /// Until this many of the successor's values have been accepted.
+    Disclosures(u64),
+    /// Until [`RotatingVerifier::end_overlap`] is called.
+    Manual,
+}
+
+/// A retired generation still accepted while it overlaps with its successor.
+struct Retiring<H: Digest> {
+    known: (u64, Output<H>),
+    /// The successor's values still to accept before the overlap ends, `None` for a manual end.
+    left: Option<u64>,
+}
+
+pub struct RotatingVerifier<H: Digest + FixedOutputReset> {
+    overlap: Overlap,
+    /// The previous generation while it overlaps with this one.
+    retiring: Option<Retiring<H>>,
+    generation: u64,
+    /// The last accepted index and value of the generation's chain, the anchor at index 0.
+    known: (u64, Output<H>),
+    /// The last tagged link digest and its index, waiting for the value that keys its tag.
+    pending: Option<(u64, Output<H>, Output<H>)>,
+    /// The link digest, once a tag on it has verified.
+    confirmed: Option<Output<H>>,
+    /// The successor's anchor, once revealed and matching the confirmed digest.
+    next_anchor: Option<Output<H>>,
+}
+
+impl<H: Digest + FixedOutputReset> RotatingVerifier<H> {
+    pub fn new(anchor: Output<H>) -> Self {
+        RotatingVerifier { overlap: Overlap::None, retiring: None, generation: 0, known: (0, anchor), pending: None, confirmed: None, next_anchor: None }
+    }
+
+    /// Keep accepting each retired generation as `overlap` allows.
+    pub fn with_overlap(self, overlap: Overlap) -> Self {
+        RotatingVerifier { overlap, ..self }
+    }
+
+    /// Whether the previous generation is still accepted.
+    pub fn overlapping(&self) -> bool {
+        self.retiring.is_some()
+    }
+
+    /// How many more of the current generation's values end the overlap, `None` if there is
+    /// none or it ends manually.
+    pub fn overlap_left(&self) -> Option<u64> {
+        self.retiring.as_ref().and_then(|retiring| retiring.left)
+    }
+
+    /// Stop accepting the previous generation, e.g. once every client is known to have rotated.
+    pub fn end_overlap(&mut self) {
+        self.retiring = None;
+    }
+
+    /// Accept a disclosure of the retiring generation, ignoring any link it carries.
+    fn accept_retiring(&mut self, disclosure: &RotatingDisclosure<H>) -> Result<(), RotationError> {
+        let retiring = self.retiring.as_mut().ok_or(RotationError::WrongGeneration)?;
+        if disclosure.index <= retiring.known.0 {
+            return Err(RotationError::Replay);
+        }
+        if !verify::<H>(retiring.known.0, &retiring.known.1, disclosure.index, &disclosure.value) {
+            return Err(RotationError::Mismatch);
+        }
+        retiring.known = (disclosure.index, disclosure.value.clone());
+        Ok(())
+    }
+
+    pub fn generation(&self) -> u64 {
+        self.generation
+    }
+
+    /// The successor's anchor, once the link to it has been followed.
+    pub fn next_anchor(&self) -> Option<&Output<H>> {
+        self.next_anchor.as_ref()
+    }
+
+    pub fn accept(&mut self, disclosure: &RotatingDisclosure<H>) -> Result<(), RotationError> {
+        if self.generation.checked_sub(1) == Some(disclosure.generation) {
+            return self.accept_retiring(disclosure);
+        }
+        let (mut known, mut pending, mut confirmed, mut next_anchor) = (self.known.clone(), self.pending.clone(), self.confirmed.clone(), self.next_anchor.clone());
+        if self.generation.checked_add(1) == Some(disclosure.generation) {
+            known = (0, next_anchor.take().ok_or(RotationError::NotLinked)?);
+            (pending, confirmed) = (None, None);
+        } else if disclosure.generation != self.generation {
+            return Err(RotationError::WrongGeneration);
+        }
+        if disclosure.index <= known.0 {
+            return Err(RotationError::Replay);
+        }
+        if !verify::<H>(known.0, &known.1, disclosure.index, &disclosure.value) {
+            return Err(RotationError::Mismatch);
+        }
+        let mut result = Ok(());
+        if let Some((index, digest, tag)) = pending.take() {
+            let key = hash_forward::<H>(&disclosure.value, disclosure.index - index - 1);
+            match &confirmed {
+                _ if link_tag::<H>(&key, &digest) != tag => result = Err(RotationError::LinkMismatch),
+                Some(earlier) if *earlier != digest => result = Err(RotationError::LinkMismatch),
+                _ => confirmed = Some(digest),
+            }
+        }
+        if let Some(link) = &disclosure.link {
+            if let Some(tag) = &link.tag {
+                pending = Some((disclosure.index, link.digest.clone(), tag.clone()));
+            }
+            if let Some(anchor) = &link.next_anchor {
+                match &confirmed {
+                    Some(digest) if link_digest::<H>(anchor) == *digest => next_anchor = Some(anchor.clone()),
+                    _ => result = Err(RotationError::LinkMismatch),
+                }
+            }
+        }
+        if disclosure.generation != self.generation {
+            let left = match self.overlap {
+                Overlap::None => Some(0),
+                Overlap::Disclosures(count) => Some(count),
+                Overlap::Manual => None,
+            };
+            self.retiring = Some(Retiring { known: self.known.clone(), left });
+        }
+        if let Some(retiring) = &mut self.retiring {
+            retiring.left = retiring.left.map(|left| left.saturating_sub(1));
+            if retiring.left == Some(0) {
+                self.retiring = None;
+            }
+        }
+        self.generation = disclosure.generation;
+        (self.known, self.pending, self.confirmed, self.next_anchor) = ((disclosure.index, disclosure.value.clone()), pending, confirmed, next_anchor);
+        result
+    }
+}
+
+#[test]
+fn test_rotating_chain() {
+    use sha2::Sha256;
+
+    let mut prover = RotatingChain::new(HashChain::<Sha256>::new(8, 1).unwrap());
+    let mut verifier = RotatingVerifier::<Sha256>::new(*prover.current().anchor());
+    assert!(prover.rotate(HashChain::new(8, 2).unwrap(), 1).is_err());
+    let mut disclosures: Vec<_> = prover.by_ref().take(2).collect();
+    prover.rotate(HashChain::new(8, 2).unwrap(), 3).unwrap();
+    assert!(prover.rotate(HashChain::new(8, 3).unwrap(), 2).is_err());
+    disclosures.extend(prover.by_ref().take(5));
+    assert_eq!(disclosures.iter().map(|d| (d.generation, d.index)).collect::<Vec<_>>(), [(0, 1), (0, 2), (0, 3), (0, 4), (0, 5), (1, 1), (1, 2)]);
+    for disclosure in &disclosures {
+        verifier.accept(disclosure).unwrap();
+    }
+    assert_eq!(verifier.generation(), 1);
+    assert_eq!(verifier.next_anchor(), None);
+
+    // a swapped anchor does not match the digest, and the impostor's chain is then refused
+    let mut verifier = RotatingVerifier::<Sha256>::new(*HashChain::<Sha256>::new(8, 1).unwrap().anchor());
+    let mut forged = disclosures[4].clone();
+    forged.link.as_mut().unwrap().next_anchor = Some(*HashChain::<Sha256>::new(8, 9).unwrap().anchor());
+    for disclosure in &disclosures[..4] {
+        verifier.accept(disclosure).unwrap();
+    }
+    assert_eq!(verifier.accept(&forged), Err(RotationError::LinkMismatch));
+    assert_eq!(verifier.accept(&disclosures[5]), Err(RotationError::NotLinked));
+    let far = RotatingDisclosure { generation: u64::MAX, ..disclosures[4].clone() };
+    assert_eq!(verifier.accept(&far), Err(RotationError::WrongGeneration));
+}
+
+#[test]
+fn test_rotation_overlap() {
+    use sha2::Sha256;
+
+    let retiring = HashChain::<Sha256>::new(16, 1).unwrap();
+    // a second device holding the retiring chain's state, which never hears of the rotation
+    let mut stale = retiring.clone();
+    let mut prover = RotatingChain::new(retiring.clone());
+    prover.rotate(HashChain::new(8, 2).unwrap(), 2).unwrap();
+    let disclosures: Vec<_> = prover.by_ref().take(5).collect();
+    stale.nth(1).unwrap();
+    let late = |stale: &mut HashChain<Sha256>| {
+        let (index, value) = stale.disclose().unwrap();
+        RotatingDisclosure { generation: 0, index, value: value.to_array(), link: None }
+    };
+
+    let mut verifier = RotatingVerifier::<Sha256>::new(*retiring.anchor()).with_overlap(Overlap::Disclosures(2));
+    for disclosure in &disclosures[..3] {
+        verifier.accept(disclosure).unwrap();
+    }
+    assert_eq!((verifier.overlapping(), verifier.overlap_left()), (true, Some(1)));
+    let stale_value = late(&mut stale);
+    verifier.accept(&stale_value).unwrap();
+    assert_eq!(verifier.accept(&stale_value), Err(RotationError::Replay));
+    verifier.accept(&disclosures[3]).unwrap();
+    assert!(!verifier.overlapping());
+    assert_eq!(verifier.accept(&late(&mut stale)), Err(RotationError::WrongGeneration));
+
+    let mut verifier = RotatingVerifier::<Sha256>::new(*retiring.anchor()).with_overlap(Overlap::Manual);
+    for disclosure in &disclosures {
+        verifier.accept(disclosure).unwrap();
+    }
+    assert_eq!((verifier.overlapping(), verifier.overlap_left()), (true, None));
+    verifier.accept(&late(&mut stale)).unwrap();
+    verifier.end_overlap();
+    assert_eq!(verifier.accept(&late(&mut stale)), Err(RotationError::WrongGeneration));
+}
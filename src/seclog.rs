@@ -0,0 +1,164 @@
+//! Forward-secure audit logging (Schneier and Kelsey; Bellare and Yee).
+//!
+//! The logger starts from a secret initial key shared with the verifier. Each entry is MACed
+//! with a key derived from the current key and the previous entry's tag, then the key is
+//! replaced by its hash. Since the hash cannot be inverted, an attacker who takes over the
+//! logger learns nothing that lets them forge, alter or reorder earlier entries. The verifier
+//! replays the key chain from the initial key.
+//!
+//! Dropping entries from the end of the log cannot be detected from the log alone; the verifier
+//! has to learn the expected length some other way, e.g. from a periodically published
+//! [`Logger::len`].
+
+use crate::evolving::EvolvingKey;
+use digest::core_api::BlockSizeUser;
+use digest::generic_array::GenericArray;
+use digest::{Digest, FixedOutputReset, Mac, OutputSizeUser};
+use hmac::SimpleHmac;
+use std::error::Error;
+use std::fmt::{self, Display};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SecLogError {
+    /// The entry does not have the next index.
+    OutOfOrder { expected: u64 },
+    /// The entry's tag does not verify.
+    Forged { index: u64 },
+}
+
+impl Display for SecLogError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            SecLogError::OutOfOrder { expected } => write!(f, "expected entry {}", expected),
+            SecLogError::Forged { index } => write!(f, "entry {} failed authentication", index),
+        }
+    }
+}
+
+impl Error for SecLogError {}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Entry {
+    pub index: u64,
+    pub data: Vec<u8>,
+    pub tag: Vec<u8>,
+}
+
+fn entry_tag<H: Digest + BlockSizeUser>(key: &[u8], previous: &[u8], index: u64, data: &[u8]) -> SimpleHmac<H> {
+    let mut mac = <SimpleHmac<H> as Mac>::new_from_slice(key).expect("HMAC accepts any key length");
+    mac.update(b"seclog entry");
+    mac.update(&(previous.len() as u32).to_be_bytes());
+    mac.update(previous);
+    mac.update(&index.to_be_bytes());
+    mac.update(data);
+    mac
+}
+
+/// The state shared by both sides: the key for the next entry and the chaining value.
+struct KeyState<H: OutputSizeUser> {
+    key: EvolvingKey<H>,
+    previous: Vec<u8>,
+}
+
+impl<H: Digest + BlockSizeUser> KeyState<H> {
+    fn tag(&self, data: &[u8]) -> SimpleHmac<H> {
+        entry_tag::<H>(self.key.key(), &self.previous, self.key.epoch(), data)
+    }
+
+    fn index(&self) -> u64 {
+        self.key.epoch()
+    }
+
+    fn advance(&mut self, tag: Vec<u8>) {
+        self.key.evolve();
+        self.previous = tag;
+    }
+}
+
+/// Writes log entries.
+pub struct Logger<H: OutputSizeUser> {
+    state: KeyState<H>,
+}
+
+impl<H: Digest + FixedOutputReset + BlockSizeUser> Logger<H> {
+    pub fn new(initial_key: GenericArray<u8, H::OutputSize>) -> Self {
+        Logger { state: KeyState { key: EvolvingKey::new(initial_key), previous: Vec::new() } }
+    }
+
+    /// The number of entries written.
+    pub fn len(&self) -> u64 {
+        self.state.index()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.state.index() == 0
+    }
+
+    /// Authenticate `data` as the next entry and evolve the key.
+    pub fn append(&mut self, data: &[u8]) -> Entry {
+        let tag = self.state.tag(data).finalize().into_bytes().to_vec();
+        let entry = Entry { index: self.state.index(), data: data.to_vec(), tag: tag.clone() };
+        self.state.advance(tag);
+        entry
+    }
+}
+
+/// Checks log entries in order.
+pub struct Verifier<H: OutputSizeUser> {
+    state: KeyState<H>,
+}
+
+impl<H: Digest + FixedOutputReset + BlockSizeUser> Verifier<H> {
+    pub fn new(initial_key: GenericArray<u8, H::OutputSize>) -> Self {
+        Verifier { state: KeyState { key: EvolvingKey::new(initial_key), previous: Vec::new() } }
+    }
+
+    /// The number of entries verified.
+    pub fn verified(&self) -> u64 {
+        self.state.index()
+    }
+
+    pub fn verify(&mut self, entry: &Entry) -> Result<(), SecLogError> {
+        if entry.index != self.state.index() {
+            return Err(SecLogError::OutOfOrder { expected: self.state.index() });
+        }
+        if self.state.tag(&entry.data).verify_slice(&entry.tag).is_err() {
+            return Err(SecLogError::Forged { index: entry.index });
+        }
+        self.state.advance(entry.tag.clone());
+        Ok(())
+    }
+}
+
+/// Verify a whole log from the initial key.
+pub fn verify_log<'a, H, I>(initial_key: GenericArray<u8, H::OutputSize>, entries: I) -> Result<u64, SecLogError>
+where
+    H: Digest + FixedOutputReset + BlockSizeUser,
+    I: IntoIterator<Item = &'a Entry>,
+{
+    let mut verifier = Verifier::<H>::new(initial_key);
+    for entry in entries {
+        verifier.verify(entry)?;
+    }
+    Ok(verifier.verified())
+}
+
+#[test]
+fn test_seclog() {
+    use sha2::Sha256;
+
+    let initial = Sha256::digest(b"audit key");
+    let mut logger = Logger::<Sha256>::new(initial);
+    let mut log: Vec<Entry> = ["boot", "login root", "config changed"].iter().map(|line| logger.append(line.as_bytes())).collect();
+    assert_eq!(verify_log::<Sha256, _>(initial, &log), Ok(3));
+
+    let mut tampered = log.clone();
+    tampered[1].data = b"login guest".to_vec();
+    assert_eq!(verify_log::<Sha256, _>(initial, &tampered), Err(SecLogError::Forged { index: 1 }));
+    log.remove(1);
+    assert_eq!(verify_log::<Sha256, _>(initial, &log), Err(SecLogError::OutOfOrder { expected: 1 }));
+
+    // renumbering after a deletion breaks the chaining of tags
+    log[1].index = 1;
+    assert_eq!(verify_log::<Sha256, _>(initial, &log), Err(SecLogError::Forged { index: 1 }));
+}
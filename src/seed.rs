@@ -0,0 +1,455 @@
+//! Chain seeds as a type of their own, so they are wiped when dropped and never printed.
+//!
+//! With the `vsss` feature a seed can be split into shares for escrow with several custodians
+//! using Shamir's scheme over GF(2^8), one polynomial per seed byte: any `t` of the `n` shares
+//! recover the seed, fewer reveal nothing about it. Every share also carries a fingerprint of
+//! the seed, so combining shares from different splits, or too few shares, is detected rather
+//! than yielding a wrong seed.
+//!
+//! A seed can also be contributed to by several parties, e.g. so that no single operator picks
+//! the values of a beacon. Each party hands a secret [`Contribution`] to whoever sets up the
+//! chain, who combines them with [`Seed::combine_contributions`] and publishes the
+//! [`SeedTranscript`] of their commitments along with the anchor. Each party checks its
+//! commitment is in the transcript; once the chain is used up the contributions are revealed,
+//! and anyone can [`SeedTranscript::open`] the transcript to the seed and audit the chain
+//! against it. The seed is unpredictable as long as one party kept its contribution secret
+//! until then.
+//!
+//! The KDF is SHA-256. A commitment is `SHA-256("seed contribution" || len || party || secret)`,
+//! with `len` the party name's length as u64 big endian, and the seed is the first 8 bytes, read
+//! little endian, of `SHA-256("multi-party seed" || transcript digest || secret_1 || ...)`, the
+//! transcript digest being `SHA-256("seed transcript" || count || commitment_1 || ...)` with the
+//! count as u64 big endian and everything in transcript order.
+
+#[cfg(any(feature = "vsss", feature = "cli"))]
+use crate::entropy::OsEntropy;
+use crate::entropy::{EntropyError, EntropySource};
+#[cfg(feature = "sha2")]
+use {alloc::string::String, alloc::vec::Vec, core::error::Error, core::fmt::Display, digest::Digest, sha2::Sha256};
+use core::fmt::{self, Debug};
+use zeroize::Zeroize;
+
+pub struct Seed(u64);
+
+impl Seed {
+    pub fn new(seed: u64) -> Self {
+        Seed(seed)
+    }
+
+    /// A fresh seed from the operating system's random number generator.
+    #[cfg(any(feature = "vsss", feature = "cli"))]
+    pub fn generate() -> Result<Seed, EntropyError> {
+        Self::generate_with(OsEntropy)
+    }
+
+    /// A fresh seed from `source`, e.g. a [`DeterministicEntropy`](crate::entropy::DeterministicEntropy)
+    /// in tests.
+    pub fn generate_with<E: EntropySource>(mut source: E) -> Result<Seed, EntropyError> {
+        let mut bytes = [0; 8];
+        source.fill(&mut bytes)?;
+        let seed = Seed(u64::from_le_bytes(bytes));
+        bytes.zeroize();
+        Ok(seed)
+    }
+
+    /// The raw seed, e.g. for [`HashChain::new`](crate::HashChain::new).
+    pub fn expose(&self) -> u64 {
+        self.0
+    }
+
+    /// Hash everything `reader` yields up to its end into a seed, e.g. a file of dice rolls, and
+    /// fail with [`UnexpectedEof`](std::io::ErrorKind::UnexpectedEof) if that is less than
+    /// `min_bytes`. For a device that never ends, such as a hardware RNG, pass
+    /// `device.take(n)`.
+    ///
+    /// The seed is the first 8 bytes, read little endian, of `SHA-256("seed from reader" ||
+    /// input || n)` with `n` the input length as u64 big endian.
+    #[cfg(feature = "std")]
+    pub fn from_reader<R: std::io::Read>(reader: R, min_bytes: u64) -> std::io::Result<Seed> {
+        Self::from_reader_mixed(reader, min_bytes, &[])
+    }
+
+    /// Like [`Seed::from_reader`], also mixing in `local` entropy, e.g. from the operating
+    /// system, so the seed stays unpredictable if either source is good. `local` is appended
+    /// after the input length, followed by its own length as u64 big endian.
+    #[cfg(feature = "std")]
+    pub fn from_reader_mixed<R: std::io::Read>(mut reader: R, min_bytes: u64, local: &[u8]) -> std::io::Result<Seed> {
+        let mut hasher = Sha256::new_with_prefix(b"seed from reader");
+        let mut buffer = [0u8; 4096];
+        let mut total = 0u64;
+        loop {
+            let read = match reader.read(&mut buffer) {
+                Ok(0) => break,
+                Ok(read) => read,
+                Err(e) if e.kind() == std::io::ErrorKind::Interrupted => continue,
+                Err(e) => {
+                    buffer.zeroize();
+                    return Err(e);
+                }
+            };
+            hasher.update(&buffer[..read]);
+            total += read as u64;
+        }
+        buffer.zeroize();
+        if total < min_bytes {
+            return Err(std::io::Error::new(std::io::ErrorKind::UnexpectedEof, "not enough seed material"));
+        }
+        let mut digest = hasher.chain_update(total.to_be_bytes()).chain_update(local).chain_update((local.len() as u64).to_be_bytes()).finalize();
+        let seed = Seed(u64::from_le_bytes(digest[..8].try_into().expect("8 bytes")));
+        digest.zeroize();
+        Ok(seed)
+    }
+}
+
+impl Zeroize for Seed {
+    fn zeroize(&mut self) {
+        self.0.zeroize();
+    }
+}
+
+impl Drop for Seed {
+    fn drop(&mut self) {
+        self.zeroize();
+    }
+}
+
+impl Debug for Seed {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Seed(..)")
+    }
+}
+
+#[cfg(feature = "sha2")]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum TranscriptError {
+    /// The number of contributions differs from the number of commitments.
+    WrongCount,
+    /// The contribution of `party` does not open its commitment.
+    WrongContribution { party: String },
+}
+
+#[cfg(feature = "sha2")]
+impl Display for TranscriptError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            TranscriptError::WrongCount => write!(f, "one contribution per commitment expected"),
+            TranscriptError::WrongContribution { party } => write!(f, "contribution of {} does not match its commitment", party),
+        }
+    }
+}
+
+#[cfg(feature = "sha2")]
+impl Error for TranscriptError {}
+
+#[cfg(feature = "sha2")]
+/// One party's secret input to a multi-party seed.
+pub struct Contribution {
+    pub party: String,
+    pub secret: [u8; 32],
+}
+
+#[cfg(feature = "sha2")]
+impl Drop for Contribution {
+    fn drop(&mut self) {
+        self.secret.zeroize();
+    }
+}
+
+#[cfg(feature = "sha2")]
+impl Contribution {
+    pub fn commitment(&self) -> [u8; 32] {
+        Sha256::new_with_prefix(b"seed contribution")
+            .chain_update((self.party.len() as u64).to_be_bytes())
+            .chain_update(self.party.as_bytes())
+            .chain_update(self.secret)
+            .finalize()
+            .into()
+    }
+}
+
+#[cfg(feature = "sha2")]
+/// The public record of who contributed to a seed.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SeedTranscript {
+    /// Each party and its commitment, in the order the contributions are combined.
+    pub commitments: Vec<(String, [u8; 32])>,
+}
+
+#[cfg(feature = "sha2")]
+impl SeedTranscript {
+    pub fn digest(&self) -> [u8; 32] {
+        self.commitments.iter()
+            .fold(Sha256::new_with_prefix(b"seed transcript").chain_update((self.commitments.len() as u64).to_be_bytes()), |hasher, (_, commitment)| hasher.chain_update(commitment))
+            .finalize()
+            .into()
+    }
+
+    /// Whether `contribution` is part of the transcript.
+    pub fn includes(&self, contribution: &Contribution) -> bool {
+        let commitment = contribution.commitment();
+        self.commitments.iter().any(|(party, c)| *party == contribution.party && *c == commitment)
+    }
+
+    /// The seed, from the revealed contributions in transcript order.
+    pub fn open(&self, contributions: &[Contribution]) -> Result<Seed, TranscriptError> {
+        if contributions.len() != self.commitments.len() {
+            return Err(TranscriptError::WrongCount);
+        }
+        for ((party, commitment), contribution) in self.commitments.iter().zip(contributions) {
+            if contribution.party != *party || contribution.commitment() != *commitment {
+                return Err(TranscriptError::WrongContribution { party: party.clone() });
+            }
+        }
+        Ok(self.derive(contributions))
+    }
+
+    fn derive(&self, contributions: &[Contribution]) -> Seed {
+        let mut digest = contributions.iter()
+            .fold(Sha256::new_with_prefix(b"multi-party seed").chain_update(self.digest()), |hasher, c| hasher.chain_update(c.secret))
+            .finalize();
+        let seed = Seed(u64::from_le_bytes(digest[..8].try_into().expect("8 bytes")));
+        digest.zeroize();
+        seed
+    }
+}
+
+#[cfg(feature = "sha2")]
+impl Seed {
+    /// The seed combining every contribution, and the transcript to publish with the anchor.
+    pub fn combine_contributions(contributions: &[Contribution]) -> (Seed, SeedTranscript) {
+        let transcript = SeedTranscript { commitments: contributions.iter().map(|c| (c.party.clone(), c.commitment())).collect() };
+        (transcript.derive(contributions), transcript)
+    }
+}
+
+#[cfg(feature = "vsss")]
+pub use sharing::{SeedShare, SharingError};
+
+#[cfg(feature = "vsss")]
+mod sharing {
+    use super::Seed;
+    use crate::entropy::{EntropySource, OsEntropy};
+    use alloc::vec::Vec;
+    use core::error::Error;
+    use core::fmt::{self, Display};
+    use digest::Digest;
+    use sha2::Sha256;
+    use zeroize::Zeroize;
+
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    pub enum SharingError {
+        /// The threshold is zero or larger than the number of shares.
+        InvalidThreshold,
+        /// The random number generator failed.
+        Randomness,
+        /// Two shares have the same index.
+        DuplicateShare,
+        /// The shares come from different splits, or there are fewer than the threshold.
+        Mismatch,
+    }
+
+    impl Display for SharingError {
+        fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+            match self {
+                SharingError::InvalidThreshold => write!(f, "threshold must be between 1 and the number of shares"),
+                SharingError::Randomness => write!(f, "random number generator failed"),
+                SharingError::DuplicateShare => write!(f, "two shares have the same index"),
+                SharingError::Mismatch => write!(f, "shares do not combine to their seed"),
+            }
+        }
+    }
+
+    impl Error for SharingError {}
+
+    /// One custodian's share of a seed.
+    #[derive(Clone, PartialEq, Eq)]
+    pub struct SeedShare {
+        /// The point the share polynomials are evaluated at, from 1.
+        pub index: u8,
+        pub value: [u8; 8],
+        /// The first bytes of a hash of the seed, the same in every share of a split.
+        pub fingerprint: [u8; 8],
+    }
+
+    impl fmt::Debug for SeedShare {
+        fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            write!(f, "SeedShare {{index: {}, ..}}", self.index)
+        }
+    }
+
+    impl Drop for SeedShare {
+        fn drop(&mut self) {
+            self.value.zeroize();
+        }
+    }
+
+    impl SeedShare {
+        /// The index, value and fingerprint: 17 bytes.
+        pub fn to_bytes(&self) -> [u8; 17] {
+            let mut bytes = [0; 17];
+            bytes[0] = self.index;
+            bytes[1..9].copy_from_slice(&self.value);
+            bytes[9..].copy_from_slice(&self.fingerprint);
+            bytes
+        }
+
+        pub fn from_bytes(bytes: &[u8; 17]) -> Self {
+            SeedShare {
+                index: bytes[0],
+                value: bytes[1..9].try_into().expect("8 bytes"),
+                fingerprint: bytes[9..].try_into().expect("8 bytes"),
+            }
+        }
+    }
+
+    fn fingerprint(seed: u64) -> [u8; 8] {
+        let digest = Sha256::new_with_prefix(b"seed share fingerprint").chain_update(seed.to_le_bytes()).finalize();
+        digest[..8].try_into().expect("8 bytes")
+    }
+
+    /// Multiplication in GF(2^8) modulo the AES polynomial x^8 + x^4 + x^3 + x + 1.
+    fn mul(mut a: u8, mut b: u8) -> u8 {
+        let mut product = 0;
+        while b != 0 {
+            if b & 1 == 1 {
+                product ^= a;
+            }
+            a = (a << 1) ^ if a & 0x80 != 0 { 0x1b } else { 0 };
+            b >>= 1;
+        }
+        product
+    }
+
+    /// The inverse of a non-zero element, `a^254`.
+    fn inv(a: u8) -> u8 {
+        let (mut result, mut power, mut exponent) = (1, a, 254u8);
+        while exponent != 0 {
+            if exponent & 1 == 1 {
+                result = mul(result, power);
+            }
+            power = mul(power, power);
+            exponent >>= 1;
+        }
+        result
+    }
+
+    impl Seed {
+        /// Split the seed into `n` shares, any `t` of which recover it.
+        pub fn split(&self, t: u8, n: u8) -> Result<Vec<SeedShare>, SharingError> {
+            self.split_with(t, n, OsEntropy)
+        }
+
+        /// Like [`Seed::split`], drawing the polynomials' coefficients from `source`.
+        pub fn split_with<E: EntropySource>(&self, t: u8, n: u8, mut source: E) -> Result<Vec<SeedShare>, SharingError> {
+            if t == 0 || t > n {
+                return Err(SharingError::InvalidThreshold);
+            }
+            // coefficients[byte][0] is the seed byte, the others are random
+            let mut coefficients = [[0u8; 255]; 8];
+            for (byte, secret) in coefficients.iter_mut().zip(self.0.to_le_bytes()) {
+                byte[0] = secret;
+                source.fill(&mut byte[1..t as usize]).map_err(|_| SharingError::Randomness)?;
+            }
+            let fingerprint = fingerprint(self.0);
+            let shares = (1..=n).map(|x| {
+                let mut value = [0; 8];
+                for (out, byte) in value.iter_mut().zip(&coefficients) {
+                    // Horner's rule, from the highest coefficient down
+                    *out = byte[..t as usize].iter().rev().fold(0, |acc, c| mul(acc, x) ^ c);
+                }
+                SeedShare { index: x, value, fingerprint }
+            }).collect();
+            coefficients.iter_mut().for_each(|byte| byte.zeroize());
+            Ok(shares)
+        }
+
+        /// Recover a seed from at least the threshold of its shares.
+        pub fn combine(shares: &[SeedShare]) -> Result<Seed, SharingError> {
+            let Some(first) = shares.first() else {
+                return Err(SharingError::Mismatch);
+            };
+            for (i, share) in shares.iter().enumerate() {
+                if share.index == 0 || shares[..i].iter().any(|other| other.index == share.index) {
+                    return Err(SharingError::DuplicateShare);
+                }
+                if share.fingerprint != first.fingerprint {
+                    return Err(SharingError::Mismatch);
+                }
+            }
+            // Lagrange interpolation at zero; subtraction is xor in GF(2^8)
+            let mut bytes = [0u8; 8];
+            for (i, share) in shares.iter().enumerate() {
+                let basis = shares.iter().enumerate().filter(|(j, _)| *j != i)
+                    .fold(1, |acc, (_, other)| mul(acc, mul(other.index, inv(other.index ^ share.index))));
+                for (out, value) in bytes.iter_mut().zip(share.value) {
+                    *out ^= mul(basis, value);
+                }
+            }
+            let seed = Seed(u64::from_le_bytes(bytes));
+            bytes.zeroize();
+            if fingerprint(seed.0) != first.fingerprint {
+                return Err(SharingError::Mismatch);
+            }
+            Ok(seed)
+        }
+    }
+}
+
+#[test]
+#[cfg(feature = "std")]
+fn test_seed_from_reader() {
+    use std::io::Read;
+
+    let rolls = b"3 1 4 1 5 9 2 6 5 3 5 8 9 7 9 3 2 3 8 4 6 2 6 4 3 3 8 3 2 7 9 5";
+    let seed = Seed::from_reader(&rolls[..], 32).unwrap();
+    assert_eq!(Seed::from_reader(&rolls[..], 32).unwrap().expose(), seed.expose());
+    assert_ne!(Seed::from_reader_mixed(&rolls[..], 32, b"local").unwrap().expose(), seed.expose());
+    assert_eq!(Seed::from_reader(&rolls[..], 1000).unwrap_err().kind(), std::io::ErrorKind::UnexpectedEof);
+    // an endless device, bounded by the caller
+    assert!(Seed::from_reader(std::io::repeat(0x5a).take(64), 64).is_ok());
+}
+
+#[cfg(feature = "sha2")]
+#[test]
+fn test_multi_party_seed() {
+    use crate::HashChain;
+    use alloc::string::ToString;
+
+    let contribution = |party: &str, byte| Contribution { party: party.to_string(), secret: [byte; 32] };
+    let (seed, transcript) = Seed::combine_contributions(&[contribution("alice", 1), contribution("bob", 2)]);
+    let chain = HashChain::<Sha256>::new(16, seed.expose()).unwrap();
+    assert!(transcript.includes(&contribution("bob", 2)));
+    assert!(!transcript.includes(&contribution("bob", 3)));
+
+    // after the reveal anyone recomputes the seed and audits the chain
+    let opened = transcript.open(&[contribution("alice", 1), contribution("bob", 2)]).unwrap();
+    assert!(crate::audit_chain::<Sha256>(opened.expose(), 16, chain.anchor()).is_ok());
+    assert_eq!(transcript.open(&[contribution("alice", 1), contribution("bob", 3)]).unwrap_err(), TranscriptError::WrongContribution { party: "bob".to_string() });
+    assert_eq!(transcript.open(&[contribution("alice", 1)]).unwrap_err(), TranscriptError::WrongCount);
+    let (other, _) = Seed::combine_contributions(&[contribution("bob", 2), contribution("alice", 1)]);
+    assert_ne!(other.expose(), seed.expose());
+}
+
+#[test]
+#[cfg(feature = "vsss")]
+fn test_seed_sharing() {
+    let seed = Seed::new(0x0123_4567_89ab_cdef);
+    let shares = seed.split(3, 5).unwrap();
+    assert_eq!(format!("{:?}", seed), "Seed(..)");
+    for subset in [[0, 1, 2], [4, 2, 0], [1, 3, 4]] {
+        let chosen: alloc::vec::Vec<SeedShare> = subset.iter().map(|&i| shares[i].clone()).collect();
+        assert_eq!(Seed::combine(&chosen).unwrap().expose(), seed.expose());
+    }
+    let all: alloc::vec::Vec<SeedShare> = shares.iter().map(|share| SeedShare::from_bytes(&share.to_bytes())).collect();
+    assert_eq!(Seed::combine(&all).unwrap().expose(), seed.expose());
+
+    assert_eq!(Seed::combine(&shares[..2]).unwrap_err(), SharingError::Mismatch);
+    assert_eq!(Seed::combine(&[shares[0].clone(), shares[0].clone()]).unwrap_err(), SharingError::DuplicateShare);
+    let other = Seed::new(7).split(2, 2).unwrap();
+    assert_eq!(Seed::combine(&[shares[0].clone(), other[1].clone(), shares[2].clone()]).unwrap_err(), SharingError::Mismatch);
+    assert_eq!(seed.split(0, 2).unwrap_err(), SharingError::InvalidThreshold);
+    assert_eq!(seed.split(3, 2).unwrap_err(), SharingError::InvalidThreshold);
+
+    let reproducible = |source| seed.split_with(2, 3, crate::entropy::DeterministicEntropy::new(source)).unwrap();
+    assert!(reproducible(b"test") == reproducible(b"test") && reproducible(b"test") != reproducible(b"other"));
+}
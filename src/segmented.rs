@@ -0,0 +1,290 @@
+//! One logical chain made of independently seeded segments, set up on all cores at once and
+//! committed to by a single Merkle root.
+//!
+//! Segment `k` of `segments` is an ordinary chain of `segment_length` values from a seed derived
+//! from the master seed and `k`, and global index `i` is local index `(i - 1) % segment_length +
+//! 1` of segment `(i - 1) / segment_length`, as in [`sharded`](crate::sharded). Each segment's
+//! [`Boundary`] is its anchor together with its last value, and leaf `k` of the Merkle tree
+//! commits to segment `k`'s boundary. The root is the chain's anchor: a verifier given a
+//! [`SegmentOpening`] for a segment checks it against the root once and then checks the
+//! segment's values against the opened anchor like any other chain.
+//!
+//! A [`ContinuityProof`] shows that segment `k` picks up where segment `k - 1` ends: both
+//! boundaries sit side by side under the root, and segment `k`'s first value hashes to its
+//! anchor. Auditors check the stitching of all segments with [`verify_continuity`], with one
+//! hash and two paths per seam instead of a walk over every segment.
+//!
+//! The number of segments must be a power of two, as for every Merkle tree in this crate.
+
+use crate::merkle::{self, auth_path, AuthPath};
+use crate::{verify, ChainInitError, HashChain};
+use crate::value::ChainValue;
+use digest::{Digest, FixedOutputReset, Output, OutputSizeUser};
+use std::error::Error;
+use std::fmt::{self, Display};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ContinuityError {
+    /// The proofs do not cover every seam between segments, in order.
+    MissingSeam { segment: u64 },
+    /// A boundary does not sit under the root at its segment's position.
+    BadOpening { segment: u64 },
+    /// The first value does not hash to the anchor of its segment.
+    Unchained { segment: u64 },
+}
+
+impl Display for ContinuityError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ContinuityError::MissingSeam { segment } => write!(f, "no proof for the seam before segment {}", segment),
+            ContinuityError::BadOpening { segment } => write!(f, "boundary of segment {} not under the root", segment),
+            ContinuityError::Unchained { segment } => write!(f, "first value of segment {} does not hash to its anchor", segment),
+        }
+    }
+}
+
+impl Error for ContinuityError {}
+
+/// The seed of segment `segment` of the chain from `master_seed`.
+pub fn segment_seed<H: Digest>(master_seed: u64, segment: u64) -> u64 {
+    let digest = H::new_with_prefix(b"segmented chain seed")
+        .chain_update(master_seed.to_le_bytes())
+        .chain_update(segment.to_le_bytes())
+        .finalize();
+    u64::from_le_bytes(digest[..8].try_into().expect("digest output is at least 8 bytes"))
+}
+
+/// The two ends of a segment: its anchor, and the value at its last local index.
+pub struct Boundary<H: OutputSizeUser> {
+    pub anchor: Output<H>,
+    pub last: Output<H>,
+}
+
+impl<H: OutputSizeUser> Clone for Boundary<H> {
+    fn clone(&self) -> Self {
+        Boundary { anchor: self.anchor.clone(), last: self.last.clone() }
+    }
+}
+
+impl<H: Digest> Boundary<H> {
+    /// The Merkle leaf of segment `segment` with this boundary: the leaf hash of the segment
+    /// number as u64 big endian, the anchor and the last value.
+    pub fn leaf(&self, segment: u64) -> Output<H> {
+        let mut data = segment.to_be_bytes().to_vec();
+        data.extend_from_slice(&self.anchor);
+        data.extend_from_slice(&self.last);
+        merkle::leaf::<H>(&data)
+    }
+}
+
+/// A segment's boundary with its path under the root.
+pub struct SegmentOpening<H: OutputSizeUser> {
+    pub segment: u64,
+    pub boundary: Boundary<H>,
+    pub path: AuthPath<H>,
+}
+
+impl<H: OutputSizeUser> Clone for SegmentOpening<H> {
+    fn clone(&self) -> Self {
+        SegmentOpening { segment: self.segment, boundary: self.boundary.clone(), path: self.path.clone() }
+    }
+}
+
+impl<H: Digest> SegmentOpening<H> {
+    /// Check the opening against the root of a chain of `segments` segments.
+    pub fn verify(&self, root: &Output<H>, segments: u64) -> bool {
+        self.segment < segments && self.path.index == self.segment && self.path.height() == segments.trailing_zeros() && self.path.verify(root, &self.boundary.leaf(self.segment))
+    }
+}
+
+/// Check that `value` is the value at global `index`, given the opening of its segment.
+pub fn verify_segmented<H: Digest + FixedOutputReset>(root: &Output<H>, segments: u64, segment_length: u64, opening: &SegmentOpening<H>, index: u64, value: &Output<H>) -> bool {
+    let Some(segment) = index.checked_sub(1).map(|i| i / segment_length) else {
+        return false;
+    };
+    segment == opening.segment && opening.verify(root, segments) && verify::<H>(0, &opening.boundary.anchor, index - segment * segment_length, value)
+}
+
+/// Evidence that segment `next.segment` carries on from the segment before it.
+pub struct ContinuityProof<H: OutputSizeUser> {
+    pub previous: SegmentOpening<H>,
+    pub next: SegmentOpening<H>,
+    /// The value at local index 1 of the next segment.
+    pub first: Output<H>,
+}
+
+impl<H: OutputSizeUser> Clone for ContinuityProof<H> {
+    fn clone(&self) -> Self {
+        ContinuityProof { previous: self.previous.clone(), next: self.next.clone(), first: self.first.clone() }
+    }
+}
+
+impl<H: Digest + FixedOutputReset> ContinuityProof<H> {
+    /// Check the seam against the root of a chain of `segments` segments.
+    pub fn verify(&self, root: &Output<H>, segments: u64) -> Result<(), ContinuityError> {
+        let segment = self.next.segment;
+        if segment == 0 || self.previous.segment != segment - 1 {
+            return Err(ContinuityError::MissingSeam { segment });
+        }
+        for opening in [&self.previous, &self.next] {
+            if !opening.verify(root, segments) {
+                return Err(ContinuityError::BadOpening { segment: opening.segment });
+            }
+        }
+        if !verify::<H>(0, &self.next.boundary.anchor, 1, &self.first) {
+            return Err(ContinuityError::Unchained { segment });
+        }
+        Ok(())
+    }
+}
+
+/// Check that `proofs` stitch all `segments` segments under `root` together, the seam before
+/// segment 1 first.
+pub fn verify_continuity<H: Digest + FixedOutputReset>(root: &Output<H>, segments: u64, proofs: &[ContinuityProof<H>]) -> Result<(), ContinuityError> {
+    for segment in 1..segments {
+        let proof = proofs.get(segment as usize - 1).filter(|proof| proof.next.segment == segment).ok_or(ContinuityError::MissingSeam { segment })?;
+        proof.verify(root, segments)?;
+    }
+    Ok(())
+}
+
+pub struct SegmentedChain<H: Digest + FixedOutputReset> {
+    segment_length: u64,
+    segments: Vec<HashChain<H>>,
+    boundaries: Vec<Boundary<H>>,
+    /// The value at local index 1 of every segment, kept for continuity proofs.
+    firsts: Vec<Output<H>>,
+    leaves: Vec<Output<H>>,
+    root: Output<H>,
+    /// The segment disclosing next.
+    current: usize,
+}
+
+impl<H: Digest + FixedOutputReset> SegmentedChain<H> {
+    /// Set up `segments` segments (a power of two) of `segment_length` values (a power of two, at
+    /// least 2) from `master_seed`, each in its own thread.
+    pub fn new(segments: u64, segment_length: usize, master_seed: u64) -> Result<Self, ChainInitError>
+    where
+        H: Send + Sync,
+    {
+        if !segments.is_power_of_two() {
+            return Err(ChainInitError::new("number of segments must be a power of two"));
+        }
+        let chains = std::thread::scope(|scope| {
+            let setups: Vec<_> = (0..segments).map(|k| {
+                scope.spawn(move || {
+                    let seed = segment_seed::<H>(master_seed, k);
+                    let chain = HashChain::<H>::new(segment_length, seed)?;
+                    let first = chain.clone().disclose().expect("a fresh chain has values").1.to_array();
+                    Ok::<_, ChainInitError>((chain, first, H::digest(seed.to_le_bytes())))
+                })
+            }).collect();
+            setups.into_iter().map(|setup| setup.join().expect("setups do not panic")).collect::<Result<Vec<_>, _>>()
+        })?;
+        let mut firsts = Vec::with_capacity(chains.len());
+        let (segments, boundaries): (Vec<_>, Vec<_>) = chains.into_iter().map(|(chain, first, last)| {
+            firsts.push(first);
+            let boundary = Boundary { anchor: chain.anchor().clone(), last };
+            (chain, boundary)
+        }).unzip();
+        let leaves: Vec<Output<H>> = boundaries.iter().enumerate().map(|(k, boundary)| boundary.leaf(k as u64)).collect();
+        let root = merkle::root::<H>(&leaves);
+        Ok(SegmentedChain { segment_length: segment_length as u64, segments, boundaries, firsts, leaves, root, current: 0 })
+    }
+
+    /// The anchor of the whole chain.
+    pub fn root(&self) -> &Output<H> {
+        &self.root
+    }
+
+    pub fn segments(&self) -> u64 {
+        self.segments.len() as u64
+    }
+
+    pub fn segment_length(&self) -> u64 {
+        self.segment_length
+    }
+
+    pub fn length(&self) -> u64 {
+        self.segment_length * self.segments()
+    }
+
+    /// The opening verifiers need before the values of segment `segment`.
+    pub fn opening(&self, segment: u64) -> Option<SegmentOpening<H>> {
+        let boundary = self.boundaries.get(segment as usize)?.clone();
+        Some(SegmentOpening { segment, boundary, path: auth_path::<H>(&self.leaves, segment) })
+    }
+
+    /// The proof for the seam between segment `segment - 1` and segment `segment`.
+    pub fn continuity_proof(&self, segment: u64) -> Option<ContinuityProof<H>> {
+        let previous = self.opening(segment.checked_sub(1)?)?;
+        let next = self.opening(segment)?;
+        Some(ContinuityProof { previous, next, first: self.firsts[segment as usize].clone() })
+    }
+
+    /// The proofs for every seam, as [`verify_continuity`] takes them.
+    pub fn continuity_proofs(&self) -> Vec<ContinuityProof<H>> {
+        (1..self.segments()).map(|segment| self.continuity_proof(segment).expect("segment in range")).collect()
+    }
+
+    /// Disclose the next value with its global index, or `None` once every segment is exhausted.
+    pub fn disclose(&mut self) -> Option<(u64, ChainValue<H>)> {
+        while self.current < self.segments.len() {
+            if let Some((index, value)) = self.segments[self.current].disclose() {
+                return Some((self.current as u64 * self.segment_length + index, value));
+            }
+            self.current += 1;
+        }
+        None
+    }
+}
+
+#[test]
+fn test_segmented_chain() {
+    use sha2::Sha256;
+
+    assert!(SegmentedChain::<Sha256>::new(3, 8, 1).is_err());
+    let mut chain = SegmentedChain::<Sha256>::new(4, 8, 1).unwrap();
+    let root = *chain.root();
+    let openings: Vec<_> = (0..4).map(|k| chain.opening(k).unwrap()).collect();
+    let mut count = 0;
+    while let Some((index, value)) = chain.disclose() {
+        count += 1;
+        assert_eq!(index, count);
+        let segment = (index as usize - 1) / 8;
+        let opening = &openings[segment];
+        assert!(verify_segmented::<Sha256>(&root, 4, 8, opening, index, &value));
+        assert!(!verify_segmented::<Sha256>(&root, 4, 8, &openings[(segment + 1) % 4], index, &value));
+        if index.is_multiple_of(8) {
+            assert_eq!(value, opening.boundary.last);
+        }
+    }
+    assert_eq!(count, 32);
+
+    // an opening does not pass for another segment's
+    let mut moved = openings[1].clone();
+    moved.segment = 2;
+    moved.path.index = 2;
+    assert!(!moved.verify(&root, 4));
+}
+
+#[test]
+fn test_continuity_proofs() {
+    use sha2::Sha256;
+
+    let chain = SegmentedChain::<Sha256>::new(4, 8, 2).unwrap();
+    let proofs = chain.continuity_proofs();
+    assert!(chain.continuity_proof(0).is_none());
+    assert_eq!(verify_continuity::<Sha256>(chain.root(), 4, &proofs), Ok(()));
+    assert_eq!(verify_continuity::<Sha256>(chain.root(), 4, &proofs[..2]), Err(ContinuityError::MissingSeam { segment: 3 }));
+    assert_eq!(verify_continuity::<Sha256>(chain.root(), 4, &[proofs[0].clone(), proofs[2].clone(), proofs[1].clone()]), Err(ContinuityError::MissingSeam { segment: 2 }));
+
+    // a segment from another chain does not fit in
+    let other = SegmentedChain::<Sha256>::new(4, 8, 3).unwrap();
+    let mut spliced = proofs.clone();
+    spliced[1].next = other.opening(2).unwrap();
+    assert_eq!(verify_continuity::<Sha256>(chain.root(), 4, &spliced), Err(ContinuityError::BadOpening { segment: 2 }));
+    let mut spliced = proofs;
+    spliced[1].first = other.continuity_proof(2).unwrap().first;
+    assert_eq!(verify_continuity::<Sha256>(chain.root(), 4, &spliced), Err(ContinuityError::Unchained { segment: 2 }));
+}
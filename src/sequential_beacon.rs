@@ -0,0 +1,166 @@
+//! A beacon driven by one long sequential walk instead of a precomputed chain: every round
+//! hashes `params.steps` times from the last round's value, and the round's value is where the
+//! walk ends up.
+//!
+//! Each round carries a [`PoswProof`] with the previous round's value as its challenge, plus the
+//! path tying the round's value to the proof's root as its last checkpoint, so a relying party
+//! that verified round `r` knows round `r + 1` took another `params.steps` hashes after it, and
+//! [`SequentialVerifier::elapsed`] bounds the sequential work since genesis from below. The
+//! operator cannot run ahead of the rounds it has published without showing its hand, since
+//! the next walk depends on the value it would publish. The spot checks make the bound
+//! probabilistic, as [`crate::posw`] explains, and rounds have to be verified one after another.
+//!
+//! The public randomness of a round is the whitened [`beacon::output`] of its value.
+
+use crate::beacon;
+use crate::merkle::{leaf, AuthPath};
+use crate::posw::{self, PoswError, PoswParams, PoswProof, PoswProver};
+use crate::ChainInitError;
+use digest::generic_array::GenericArray;
+use digest::{Digest, FixedOutputReset, Output, OutputSizeUser};
+use std::error::Error;
+use std::fmt::{self, Display};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SequentialBeaconError {
+    /// The round is not newer than the last verified one.
+    Stale,
+    /// The round does not follow the last verified one; the rounds in between are needed.
+    Gap,
+    /// The proof of the work since the last round does not hold.
+    Work(PoswError),
+    /// The round's value is not the last checkpoint under the proof's root.
+    BadOutput,
+}
+
+impl Display for SequentialBeaconError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            SequentialBeaconError::Stale => write!(f, "round already verified"),
+            SequentialBeaconError::Gap => write!(f, "round does not follow the last verified one"),
+            SequentialBeaconError::Work(error) => write!(f, "invalid proof of work since the last round: {}", error),
+            SequentialBeaconError::BadOutput => write!(f, "round value is not the end of the walk"),
+        }
+    }
+}
+
+impl Error for SequentialBeaconError {}
+
+fn genesis_value<H: Digest>(genesis: &[u8]) -> Output<H> {
+    H::new_with_prefix(b"sequential beacon").chain_update(genesis).finalize()
+}
+
+pub struct SequentialRound<H: OutputSizeUser> {
+    pub number: u64,
+    pub value: Output<H>,
+    pub proof: PoswProof<H>,
+    /// The path of `value` as the last checkpoint under `proof.root`.
+    pub output_path: AuthPath<H>,
+}
+
+impl<H: OutputSizeUser> Clone for SequentialRound<H> {
+    fn clone(&self) -> Self {
+        SequentialRound { number: self.number, value: self.value.clone(), proof: self.proof.clone(), output_path: self.output_path.clone() }
+    }
+}
+
+impl<H: Digest> SequentialRound<H> {
+    pub fn output(&self) -> GenericArray<u8, H::OutputSize> {
+        beacon::output::<H>(self.number, &self.value)
+    }
+}
+
+/// The operator's side, holding only the last round's value.
+pub struct SequentialBeacon<H: OutputSizeUser> {
+    params: PoswParams,
+    number: u64,
+    value: Output<H>,
+}
+
+impl<H: Digest + FixedOutputReset> SequentialBeacon<H> {
+    /// A beacon walking on from `genesis`, e.g. a value nobody knew before the beacon started.
+    pub fn new(genesis: &[u8], params: PoswParams) -> Result<Self, ChainInitError> {
+        params.check()?;
+        Ok(SequentialBeacon { params, number: 0, value: genesis_value::<H>(genesis) })
+    }
+
+    /// The number of rounds published so far.
+    pub fn round(&self) -> u64 {
+        self.number
+    }
+
+    /// Walk the next round's steps and return the round to publish.
+    pub fn next_round(&mut self) -> SequentialRound<H> {
+        let prover = PoswProver::<H>::compute(&self.value, &self.params).expect("parameters checked in new");
+        let proof = prover.prove(&self.value, &self.params);
+        self.number += 1;
+        self.value = prover.output().clone();
+        SequentialRound { number: self.number, value: self.value.clone(), proof, output_path: prover.output_path() }
+    }
+}
+
+pub struct SequentialVerifier<H: OutputSizeUser> {
+    params: PoswParams,
+    number: u64,
+    value: Output<H>,
+}
+
+impl<H: Digest + FixedOutputReset> SequentialVerifier<H> {
+    pub fn new(genesis: &[u8], params: PoswParams) -> Result<Self, ChainInitError> {
+        params.check()?;
+        Ok(SequentialVerifier { params, number: 0, value: genesis_value::<H>(genesis) })
+    }
+
+    /// The last verified round, 0 before any.
+    pub fn round(&self) -> u64 {
+        self.number
+    }
+
+    /// The sequential hashes the verified rounds show, at least.
+    pub fn elapsed(&self) -> u64 {
+        self.number * self.params.steps
+    }
+
+    /// Check the round after the last verified one and return its public randomness.
+    pub fn verify(&mut self, round: &SequentialRound<H>) -> Result<GenericArray<u8, H::OutputSize>, SequentialBeaconError> {
+        if round.number <= self.number {
+            return Err(SequentialBeaconError::Stale);
+        }
+        if round.number != self.number + 1 {
+            return Err(SequentialBeaconError::Gap);
+        }
+        posw::verify::<H>(&self.value, &self.params, &round.proof).map_err(SequentialBeaconError::Work)?;
+        let segments = self.params.steps / self.params.stride;
+        let path = &round.output_path;
+        if path.index != segments - 1 || path.height() != segments.trailing_zeros() || !path.verify(&round.proof.root, &leaf::<H>(&round.value)) {
+            return Err(SequentialBeaconError::BadOutput);
+        }
+        (self.number, self.value) = (round.number, round.value.clone());
+        Ok(round.output())
+    }
+}
+
+#[test]
+fn test_sequential_beacon() {
+    use sha2::Sha256;
+
+    let params = PoswParams { steps: 1 << 10, stride: 32, queries: 6 };
+    assert!(SequentialBeacon::<Sha256>::new(b"genesis", PoswParams { stride: 0, ..params }).is_err());
+    let mut operator = SequentialBeacon::<Sha256>::new(b"genesis", params).unwrap();
+    let mut verifier = SequentialVerifier::<Sha256>::new(b"genesis", params).unwrap();
+    let first = operator.next_round();
+    let second = operator.next_round();
+    assert_eq!(verifier.verify(&second), Err(SequentialBeaconError::Gap));
+    assert_eq!(verifier.verify(&first), Ok(first.output()));
+    assert_eq!(verifier.verify(&first), Err(SequentialBeaconError::Stale));
+
+    // the value has to be where the proven walk ends
+    let mut swapped = second.clone();
+    swapped.value = first.value;
+    assert_eq!(verifier.verify(&swapped), Err(SequentialBeaconError::BadOutput));
+    // and the walk has to start at the last round's value
+    let mut elsewhere = SequentialVerifier::<Sha256>::new(b"other genesis", params).unwrap();
+    assert_eq!(elsewhere.verify(&first), Err(SequentialBeaconError::Work(PoswError::WrongQueries)));
+    assert_eq!(verifier.verify(&second), Ok(second.output()));
+    assert_eq!(verifier.elapsed(), 2 << 10);
+}
@@ -0,0 +1,103 @@
+//! The byte encoding of a traversal state, for storing a chain between runs.
+
+use crate::error::ChainInitError;
+use crate::pebble::{log_2, Pebble};
+use crate::HashChain;
+use alloc::sync::Arc;
+use alloc::vec::Vec;
+use digest::generic_array::GenericArray;
+use digest::{Digest, FixedOutputReset};
+
+impl<H: Digest + FixedOutputReset> HashChain<H> {
+    /// The traversal state: length, position, anchor, pebbles and personalization. Anyone
+    /// holding it can compute the remaining values, so it must be stored as carefully as the seed.
+    ///
+    /// The encoding is the length and position as u64 big endian, the anchor, the pebble count
+    /// as a byte and for each pebble its four counters as u64 big endian and its value, and last
+    /// the personalization after a length byte if the chain has one.
+    pub fn export_state(&self) -> Vec<u8> {
+        #[cfg(feature = "tracing")]
+        tracing::debug!(length = self.length, position = self.current, "chain state exported");
+        let n = <H as Digest>::output_size();
+        let mut bytes = Vec::with_capacity(17 + n + self.pebbles.len() * (32 + n));
+        bytes.extend_from_slice(&self.length.to_be_bytes());
+        bytes.extend_from_slice(&self.current.to_be_bytes());
+        bytes.extend_from_slice(&self.anchor);
+        bytes.push(self.pebbles.len() as u8);
+        for pebble in self.pebbles.iter() {
+            for counter in [pebble.start_incr, pebble.dest_incr, pebble.position, pebble.destination] {
+                bytes.extend_from_slice(&counter.to_be_bytes());
+            }
+            bytes.extend_from_slice(&pebble.value);
+        }
+        if let Some(personalization) = &self.personalization {
+            bytes.push(personalization.len() as u8);
+            bytes.extend_from_slice(personalization);
+        }
+        bytes
+    }
+
+    /// Restore a chain from [`HashChain::export_state`]. States whose pebbles fail
+    /// [`HashChain::check_invariants`] are rejected.
+    pub fn import_state(bytes: &[u8]) -> Result<Self, ChainInitError> {
+        let n = <H as Digest>::output_size();
+        let counter = |at: usize| u64::from_be_bytes(bytes[at..at + 8].try_into().expect("8 bytes"));
+        if bytes.len() < 17 + n {
+            return Err(ChainInitError::new("chain state truncated"));
+        }
+        let count = bytes[16 + n] as usize;
+        let end = 17 + n + count * (32 + n);
+        let personalization = match bytes.get(end..) {
+            Some([]) => None,
+            Some([len, personalization @ ..]) if *len > 0 && personalization.len() == *len as usize => Some(Arc::from(personalization)),
+            _ => return Err(ChainInitError::new("wrong length for a chain state")),
+        };
+        let (length, current) = (counter(0), counter(8));
+        if length < 2 || !length.is_power_of_two() || current > length || count > log_2(length) as usize {
+            return Err(ChainInitError::new("inconsistent chain state"));
+        }
+        let pebbles: Vec<_> = (0..count).map(|i| {
+            let at = 17 + n + i * (32 + n);
+            Pebble { start_incr: counter(at), dest_incr: counter(at + 8), position: counter(at + 16), destination: counter(at + 24), value: GenericArray::clone_from_slice(&bytes[at + 32..at + 32 + n]) }
+        }).collect();
+        let chain = HashChain { length, current, anchor: GenericArray::clone_from_slice(&bytes[16..16 + n]), pebbles: Arc::new(pebbles.into()), personalization, hasher: H::new() };
+        let report = chain.check_invariants();
+        if !report.is_ok() {
+            return Err(ChainInitError::new(&alloc::format!("inconsistent chain state: {}", report)));
+        }
+        #[cfg(feature = "tracing")]
+        tracing::debug!(length, position = current, "chain state imported");
+        Ok(chain)
+    }
+}
+
+impl<H: Digest + FixedOutputReset> crate::version::Versioned for HashChain<H> {
+    type Error = ChainInitError;
+
+    fn encode_body(&self, _version: u8) -> Vec<u8> {
+        self.export_state()
+    }
+
+    fn decode_body(_version: u8, body: &[u8]) -> Result<Self, ChainInitError> {
+        Self::import_state(body)
+    }
+}
+
+#[test]
+fn test_state_roundtrip() {
+    use sha2::Sha256;
+
+    let mut chain = HashChain::<Sha256>::new(64, 2).unwrap();
+    chain.nth(10);
+    let mut restored = HashChain::<Sha256>::import_state(&chain.export_state()).unwrap();
+    assert!(restored.audit(2).is_ok());
+    assert!(restored.by_ref().eq(chain));
+    assert!(HashChain::<Sha256>::import_state(&restored.export_state()[1..]).is_err());
+
+    let mut personalized = HashChain::<Sha256>::new_personalized(64, 2, b"acme-meterd v2").unwrap();
+    personalized.nth(10);
+    let mut restored = HashChain::<Sha256>::import_state(&personalized.export_state()).unwrap();
+    assert_eq!(restored.personalization(), b"acme-meterd v2");
+    assert!(restored.audit(2).is_ok());
+    assert!(restored.by_ref().eq(personalized));
+}
@@ -0,0 +1,133 @@
+//! A [tonic](https://docs.rs/tonic) gRPC service for enrolling chains and verifying
+//! disclosures against a shared [`Registry`], so the registry can be deployed as a verification
+//! microservice. The protocol is defined in `proto/chain_service.proto`; serve it with
+//! `tonic::transport::Server::builder().add_service(ChainServer::new(registry).into_service())`.
+//! Chains are enrolled only under the id derived from their anchor and length, and never over
+//! an enrolled one, while verification runs on tokio's blocking pool, bounded by the registry's
+//! [`GapPolicy`](crate::registry::GapPolicy).
+
+use crate::registry::{ChainRecord, Registry, VerifyError};
+use crate::store::StateStore;
+use crate::ChainId;
+use digest::generic_array::GenericArray;
+use digest::{Digest, FixedOutputReset};
+use std::sync::Arc;
+use tonic::{Request, Response, Status};
+
+/// The messages and service traits generated from the proto definitions.
+pub mod proto {
+    tonic::include_proto!("fractal_hash_traversal.v1");
+}
+
+use proto::chain_service_server::{ChainService, ChainServiceServer};
+use proto::{EnrollRequest, EnrollResponse, StateRequest, StateResponse, VerifyRequest, VerifyResponse};
+
+fn status(error: VerifyError) -> Status {
+    match error {
+        VerifyError::UnknownChain => Status::not_found(error.to_string()),
+        VerifyError::Replay | VerifyError::NotNext | VerifyError::GapTooLarge | VerifyError::Mismatch | VerifyError::WrongDomain => Status::permission_denied(error.to_string()),
+        VerifyError::Conflict => Status::aborted(error.to_string()),
+        VerifyError::AlreadyEnrolled => Status::already_exists(error.to_string()),
+        VerifyError::Retired | VerifyError::ProofRequired => Status::failed_precondition(error.to_string()),
+        VerifyError::DomainNotAllowed(_) => Status::invalid_argument(error.to_string()),
+        VerifyError::Store(_) => Status::unavailable(error.to_string()),
+    }
+}
+
+fn chain_id(bytes: &[u8]) -> Result<ChainId, Status> {
+    bytes.try_into().map(ChainId).map_err(|_| Status::invalid_argument("chain id must be 16 bytes"))
+}
+
+/// Implements [`ChainService`] over a [`Registry`].
+pub struct ChainServer<H, S> {
+    registry: Arc<Registry<H, S>>,
+}
+
+impl<H, S> ChainServer<H, S>
+where
+    H: Digest + FixedOutputReset + Send + Sync + 'static,
+    S: StateStore<Key = ChainId, State = ChainRecord<H>> + Send + Sync + 'static,
+{
+    pub fn new(registry: Arc<Registry<H, S>>) -> Self {
+        ChainServer { registry }
+    }
+
+    /// Wrap the server for `tonic::transport::Server::add_service`.
+    pub fn into_service(self) -> ChainServiceServer<Self> {
+        ChainServiceServer::new(self)
+    }
+}
+
+#[tonic::async_trait]
+impl<H, S> ChainService for ChainServer<H, S>
+where
+    H: Digest + FixedOutputReset + Send + Sync + 'static,
+    S: StateStore<Key = ChainId, State = ChainRecord<H>> + Send + Sync + 'static,
+{
+    async fn enroll(&self, request: Request<EnrollRequest>) -> Result<Response<EnrollResponse>, Status> {
+        let request = request.into_inner();
+        if request.anchor.len() != <H as Digest>::output_size() {
+            return Err(Status::invalid_argument("anchor has the wrong length"));
+        }
+        if request.length == 0 {
+            return Err(Status::invalid_argument("the chain length is required"));
+        }
+        if !request.chain_id.is_empty() && chain_id(&request.chain_id)? != ChainId::derive(&request.anchor, request.length) {
+            return Err(Status::invalid_argument("chain id does not match the anchor"));
+        }
+        let id = self.registry.enroll(GenericArray::clone_from_slice(&request.anchor), request.length).map_err(status)?;
+        Ok(Response::new(EnrollResponse { chain_id: id.0.to_vec() }))
+    }
+
+    async fn verify(&self, request: Request<VerifyRequest>) -> Result<Response<VerifyResponse>, Status> {
+        let request = request.into_inner();
+        let id = chain_id(&request.chain_id)?;
+        let registry = Arc::clone(&self.registry);
+        // up to a maximum gap of hashing, off the async workers
+        let advanced = tokio::task::spawn_blocking(move || registry.advance(&id, request.index, &request.value))
+            .await
+            .map_err(|e| Status::internal(e.to_string()))?
+            .map_err(status)?;
+        Ok(Response::new(VerifyResponse { advanced }))
+    }
+
+    async fn get_state(&self, request: Request<StateRequest>) -> Result<Response<StateResponse>, Status> {
+        let record = self.registry.record(&chain_id(&request.into_inner().chain_id)?).map_err(status)?;
+        Ok(Response::new(StateResponse { index: record.index, value: record.value.to_vec() }))
+    }
+}
+
+#[test]
+fn test_chain_service() {
+    use crate::store::MemoryStore;
+    use crate::HashChain;
+    use sha2::Sha256;
+
+    let runtime = tokio::runtime::Builder::new_current_thread().build().unwrap();
+    let server = ChainServer::new(Arc::new(Registry::<Sha256, _>::new(MemoryStore::new()).with_max_gap(4)));
+    let mut chain = HashChain::<Sha256>::new(16, 3).unwrap();
+    let anchor = chain.anchor().to_vec();
+    let enroll = |chain_id: Vec<u8>, length| EnrollRequest { chain_id, anchor: anchor.clone(), length };
+    let arbitrary = runtime.block_on(server.enroll(Request::new(enroll(vec![7; 16], 0)))).unwrap_err();
+    assert_eq!(arbitrary.code(), tonic::Code::InvalidArgument);
+    let mismatched = runtime.block_on(server.enroll(Request::new(enroll(vec![7; 16], 16)))).unwrap_err();
+    assert_eq!(mismatched.code(), tonic::Code::InvalidArgument);
+    let id = runtime.block_on(server.enroll(Request::new(enroll(vec![], 16)))).unwrap().into_inner().chain_id;
+    assert_eq!(id, chain.chain_id().0.to_vec());
+
+    chain.next();
+    let (index, value) = chain.disclose().unwrap();
+    let verify = |index, value: Vec<u8>| runtime.block_on(server.verify(Request::new(VerifyRequest { chain_id: id.clone(), index, value })));
+    assert_eq!(verify(index, value.to_vec()).unwrap().into_inner().advanced, 2);
+    assert_eq!(verify(index, value.to_vec()).unwrap_err().code(), tonic::Code::PermissionDenied);
+    assert_eq!(verify(u64::MAX, value.to_vec()).unwrap_err().code(), tonic::Code::PermissionDenied);
+
+    // enrolling the live chain again would reset it
+    let again = runtime.block_on(server.enroll(Request::new(enroll(id.clone(), 16)))).unwrap_err();
+    assert_eq!(again.code(), tonic::Code::AlreadyExists);
+
+    let state = runtime.block_on(server.get_state(Request::new(StateRequest { chain_id: id.clone() }))).unwrap().into_inner();
+    assert_eq!((state.index, state.value), (2, value.to_vec()));
+    let unknown = runtime.block_on(server.get_state(Request::new(StateRequest { chain_id: vec![0; 16] }))).unwrap_err();
+    assert_eq!(unknown.code(), tonic::Code::NotFound);
+}
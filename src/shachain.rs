@@ -0,0 +1,195 @@
+//! The BOLT-3 per-commitment secrets of Lightning channels, the `shachain`.
+//!
+//! The sender derives the secret for commitment number `index` (48 bits, counting down from
+//! `2^48 - 1`) from a 32-byte seed with [`per_commitment_secret`]: for every bit of the index from
+//! bit 47 down that is set, flip that bit of the value and hash it. The secret for an index with
+//! trailing zeros lets anyone derive the secrets of every index that only differs in those bits,
+//! so a [`Receiver`] taking the secrets in order keeps one per trailing-zero count, 49 in all,
+//! and re-derives any earlier secret from them, as a chain traversal keeps `log2(length)`
+//! pebbles.
+//!
+//! [`Receiver::to_bytes`] writes the 49 slots as the secret followed by the index as u64 big
+//! endian, with an unused slot holding zeros and the index `2^48`, the format rust-lightning
+//! stores its counterparty commitment secrets in.
+
+use alloc::vec::Vec;
+use core::error::Error;
+use core::fmt::{self, Display};
+use digest::Digest;
+use sha2::Sha256;
+
+/// The first commitment number of a channel; later ones count down from it.
+pub const MAX_INDEX: u64 = (1 << 48) - 1;
+/// The length of a [`Receiver`] encoding.
+pub const ENCODED_LEN: usize = SLOTS * 40;
+
+const SLOTS: usize = 49;
+const UNUSED: u64 = 1 << 48;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ShachainError {
+    /// The index is not one below the last secret taken, or past [`MAX_INDEX`] for the first.
+    OutOfOrder { expected: u64 },
+    /// The secret does not derive the secrets taken before it.
+    Mismatch { index: u64 },
+    /// No secret taken so far derives the one asked for.
+    Unknown { index: u64 },
+    /// The encoding is not [`ENCODED_LEN`] bytes or holds an index past `2^48`.
+    Malformed,
+}
+
+impl Display for ShachainError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ShachainError::OutOfOrder { expected } => write!(f, "expected the secret for commitment {}", expected),
+            ShachainError::Mismatch { index } => write!(f, "secret for commitment {} does not derive the earlier ones", index),
+            ShachainError::Unknown { index } => write!(f, "secret for commitment {} not received", index),
+            ShachainError::Malformed => write!(f, "malformed shachain encoding"),
+        }
+    }
+}
+
+impl Error for ShachainError {}
+
+/// Flip and hash for the lowest `bits` bits of `index` that are set, highest first.
+fn derive(base: &[u8; 32], bits: u32, index: u64) -> [u8; 32] {
+    let mut value = *base;
+    for bit in (0..bits).rev() {
+        if index >> bit & 1 == 1 {
+            value[bit as usize / 8] ^= 1 << (bit % 8);
+            value = Sha256::digest(value).into();
+        }
+    }
+    value
+}
+
+/// The sender's secret for commitment number `index`, which must not exceed [`MAX_INDEX`].
+pub fn per_commitment_secret(seed: &[u8; 32], index: u64) -> [u8; 32] {
+    assert!(index <= MAX_INDEX, "commitment numbers have 48 bits");
+    derive(seed, 48, index)
+}
+
+/// The secrets a channel peer revealed, in 49 slots.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Receiver {
+    /// Slot `b` holds the last secret taken whose index has `b` trailing zeros.
+    slots: [([u8; 32], u64); SLOTS],
+}
+
+impl Default for Receiver {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Receiver {
+    pub fn new() -> Self {
+        Receiver { slots: [([0; 32], UNUSED); SLOTS] }
+    }
+
+    /// The index of the last secret taken, `2^48` before the first.
+    pub fn min_index(&self) -> u64 {
+        self.slots.iter().map(|&(_, index)| index).min().expect("49 slots")
+    }
+
+    /// Take the secret for commitment `index`, the one below the last, after checking that it
+    /// derives every secret kept so far.
+    pub fn insert(&mut self, index: u64, secret: [u8; 32]) -> Result<(), ShachainError> {
+        let expected = self.min_index() - 1;
+        if index != expected {
+            return Err(ShachainError::OutOfOrder { expected });
+        }
+        let slot = index.trailing_zeros().min(48);
+        for (known, known_index) in &self.slots[..slot as usize] {
+            if derive(&secret, slot, *known_index) != *known {
+                return Err(ShachainError::Mismatch { index });
+            }
+        }
+        self.slots[slot as usize] = (secret, index);
+        Ok(())
+    }
+
+    /// The secret for commitment `index`, if it was taken or derives from one that was.
+    pub fn secret(&self, index: u64) -> Result<[u8; 32], ShachainError> {
+        for (bits, (known, known_index)) in self.slots.iter().enumerate() {
+            let mask = !((1u64 << bits) - 1);
+            if *known_index != UNUSED && index & mask == known_index & mask {
+                return Ok(derive(known, bits as u32, index));
+            }
+        }
+        Err(ShachainError::Unknown { index })
+    }
+
+    pub fn to_bytes(&self) -> [u8; ENCODED_LEN] {
+        let mut bytes = [0; ENCODED_LEN];
+        for (chunk, (secret, index)) in bytes.chunks_mut(40).zip(&self.slots) {
+            chunk[..32].copy_from_slice(secret);
+            chunk[32..].copy_from_slice(&index.to_be_bytes());
+        }
+        bytes
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, ShachainError> {
+        if bytes.len() != ENCODED_LEN {
+            return Err(ShachainError::Malformed);
+        }
+        let mut receiver = Self::new();
+        for (slot, chunk) in receiver.slots.iter_mut().zip(bytes.chunks(40)) {
+            let index = u64::from_be_bytes(chunk[32..].try_into().expect("8 bytes"));
+            if index > UNUSED {
+                return Err(ShachainError::Malformed);
+            }
+            *slot = (chunk[..32].try_into().expect("32 bytes"), index);
+        }
+        Ok(receiver)
+    }
+}
+
+impl crate::version::Versioned for Receiver {
+    type Error = ShachainError;
+
+    fn encode_body(&self, _version: u8) -> Vec<u8> {
+        self.to_bytes().to_vec()
+    }
+
+    fn decode_body(_version: u8, body: &[u8]) -> Result<Self, ShachainError> {
+        Self::from_bytes(body)
+    }
+}
+
+#[test]
+fn test_bolt3_generation_vectors() {
+    let cases: [([u8; 32], u64, &str); 5] = [
+        ([0; 32], MAX_INDEX, "02a40c85b6f28da08dfdbe0926c53fab2de6d28c10301f8f7c4073d5e42e3148"),
+        ([0xff; 32], MAX_INDEX, "7cc854b54e3e0dcdb010d7a3fee464a9687be6e8db3be6854c475621e007a5dc"),
+        ([0xff; 32], 0xaaaaaaaaaaa, "56f4008fb007ca9acf0e15b054d5c9fd12ee06cea347914ddbaed70d1c13a528"),
+        ([0xff; 32], 0x555555555555, "9015daaeb06dba4ccc05b91b2f73bd54405f2be9f217fbacd3c5ac2e62327d31"),
+        ([0x01; 32], 1, "915c75942a26bb3a433a8ce2cb0427c29ec6c1775cfc78328b57f6ba7bfeaa9c"),
+    ];
+    for (seed, index, expected) in cases {
+        assert_eq!(hex::encode(per_commitment_secret(&seed, index)), expected);
+    }
+}
+
+#[test]
+fn test_receiver() {
+    let seed = [7; 32];
+    let mut receiver = Receiver::new();
+    for index in (MAX_INDEX - 200..=MAX_INDEX).rev() {
+        receiver.insert(index, per_commitment_secret(&seed, index)).unwrap();
+    }
+    assert_eq!(receiver.min_index(), MAX_INDEX - 200);
+    assert!((MAX_INDEX - 200..=MAX_INDEX).all(|index| receiver.secret(index) == Ok(per_commitment_secret(&seed, index))));
+    assert_eq!(receiver.secret(MAX_INDEX - 201), Err(ShachainError::Unknown { index: MAX_INDEX - 201 }));
+
+    // a secret from another seed is caught once its slot covers an earlier one
+    let mut forged = receiver.clone();
+    let index = MAX_INDEX - 201;
+    assert_eq!(forged.insert(index, per_commitment_secret(&[8; 32], index)), Err(ShachainError::Mismatch { index }));
+    assert_eq!(forged.insert(index - 1, [0; 32]), Err(ShachainError::OutOfOrder { expected: index }));
+
+    let loaded = Receiver::from_bytes(&receiver.to_bytes()).unwrap();
+    assert_eq!(loaded, receiver);
+    assert_eq!(Receiver::new().to_bytes()[32..40], (1u64 << 48).to_be_bytes());
+    assert_eq!(Receiver::from_bytes(&[0; 40]), Err(ShachainError::Malformed));
+}
@@ -0,0 +1,225 @@
+//! Setup of one enormous chain split across worker processes.
+//!
+//! Every chain value is the hash of the one above it, so a single chain can only be set up in
+//! one sequential pass. A sharded chain is instead made of `segments` ordinary chains of
+//! `segment_length` values each, segment `k` from a mid-seed derived from the master seed and
+//! `k`, and global index `i` is local index `(i - 1) % segment_length + 1` of segment
+//! `(i - 1) / segment_length`. Workers each set up a segment with [`compute_segment`] and send
+//! back its state; the coordinator [`stitch`]es the states into a [`ShardedChain`], whose
+//! commitment is the hash of all segment anchors in order. Verifiers get the anchors along with
+//! the commitment and check every value against its segment's anchor with [`verify_sharded`].
+//!
+//! Stitching checks the shape of every segment and that its lowest pebble hashes to its anchor.
+//! A coordinator that does not trust its workers audits the stitched chain against the master
+//! seed as well, which costs as much as the setup but again runs the segments in parallel.
+
+use crate::{verify, ChainAuditError, HashChain};
+use crate::value::ChainValue;
+use digest::{Digest, FixedOutputReset, Output};
+use std::error::Error;
+use std::fmt::{self, Display};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ShardError {
+    details: String,
+}
+
+impl ShardError {
+    fn new(msg: &str) -> ShardError {
+        ShardError { details: msg.to_string() }
+    }
+}
+
+impl Display for ShardError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.details)
+    }
+}
+
+impl Error for ShardError {}
+
+/// How a sharded chain is cut up; coordinator and workers must agree on it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ShardPlan {
+    pub segments: u64,
+    /// A power of two, at least 2.
+    pub segment_length: usize,
+}
+
+impl ShardPlan {
+    pub fn length(&self) -> u64 {
+        self.segments * self.segment_length as u64
+    }
+}
+
+/// The seed of segment `segment` of the chain from `master_seed`.
+pub fn mid_seed<H: Digest>(master_seed: u64, segment: u64) -> u64 {
+    let digest = H::new_with_prefix(b"sharded chain mid seed")
+        .chain_update(master_seed.to_le_bytes())
+        .chain_update(segment.to_le_bytes())
+        .finalize();
+    u64::from_le_bytes(digest[..8].try_into().expect("digest output is at least 8 bytes"))
+}
+
+/// What a worker sends back: the segment number and the state of its fresh segment chain, in
+/// [`HashChain::export_state`] encoding.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SegmentResult {
+    pub segment: u64,
+    pub state: Vec<u8>,
+}
+
+impl SegmentResult {
+    /// The segment number as u64 big endian, followed by the state.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = self.segment.to_be_bytes().to_vec();
+        bytes.extend_from_slice(&self.state);
+        bytes
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, ShardError> {
+        if bytes.len() < 8 {
+            return Err(ShardError::new("segment result truncated"));
+        }
+        let segment = u64::from_be_bytes(bytes[..8].try_into().expect("8 bytes"));
+        Ok(SegmentResult { segment, state: bytes[8..].to_vec() })
+    }
+}
+
+/// A worker's share: set up segment `segment` of `plan`.
+pub fn compute_segment<H: Digest + FixedOutputReset>(plan: &ShardPlan, master_seed: u64, segment: u64) -> Result<SegmentResult, ShardError> {
+    if segment >= plan.segments {
+        return Err(ShardError::new("segment out of range"));
+    }
+    let chain = HashChain::<H>::new(plan.segment_length, mid_seed::<H>(master_seed, segment)).map_err(|e| ShardError::new(&e.to_string()))?;
+    Ok(SegmentResult { segment, state: chain.export_state() })
+}
+
+/// Assemble the workers' results, in any order, into the full chain.
+pub fn stitch<H: Digest + FixedOutputReset>(plan: &ShardPlan, results: Vec<SegmentResult>) -> Result<ShardedChain<H>, ShardError> {
+    let mut segments: Vec<Option<HashChain<H>>> = (0..plan.segments).map(|_| None).collect();
+    for result in results {
+        let slot = segments.get_mut(result.segment as usize).ok_or_else(|| ShardError::new("segment out of range"))?;
+        if slot.is_some() {
+            return Err(ShardError::new(&format!("segment {} returned twice", result.segment)));
+        }
+        let chain = HashChain::<H>::import_state(&result.state).map_err(|e| ShardError::new(&e.to_string()))?;
+        if chain.length() != plan.segment_length as u64 || HashChain::position(&chain) != 0 {
+            return Err(ShardError::new(&format!("segment {} is not a fresh segment of the plan", result.segment)));
+        }
+        // a fresh chain's lowest pebble rests at position 2
+        let lowest = chain.pebbles().iter().min_by_key(|p| p.position()).expect("a fresh chain has pebbles");
+        if lowest.position() != 2 || !verify::<H>(0, chain.anchor(), 2, &lowest.value) {
+            return Err(ShardError::new(&format!("segment {} does not hash to its anchor", result.segment)));
+        }
+        *slot = Some(chain);
+    }
+    let segments = segments.into_iter().enumerate()
+        .map(|(k, chain)| chain.ok_or_else(|| ShardError::new(&format!("segment {} missing", k))))
+        .collect::<Result<Vec<_>, _>>()?;
+    Ok(ShardedChain { segment_length: plan.segment_length as u64, segments, current: 0 })
+}
+
+/// The commitment to a sharded chain with the given segment anchors.
+pub fn commitment<H: Digest>(anchors: &[Output<H>]) -> Output<H> {
+    anchors.iter().fold(H::new_with_prefix(b"sharded chain"), |hasher, anchor| hasher.chain_update(anchor)).finalize()
+}
+
+/// Check that `value` is the value at global `index` of the sharded chain with `anchors`.
+pub fn verify_sharded<H: Digest + FixedOutputReset>(anchors: &[Output<H>], segment_length: u64, index: u64, value: &Output<H>) -> bool {
+    let Some(segment) = index.checked_sub(1).map(|i| i / segment_length) else {
+        return false;
+    };
+    anchors.get(segment as usize).is_some_and(|anchor| verify::<H>(0, anchor, index - segment * segment_length, value))
+}
+
+pub struct ShardedChain<H: Digest + FixedOutputReset> {
+    segment_length: u64,
+    segments: Vec<HashChain<H>>,
+    /// The segment disclosing next.
+    current: usize,
+}
+
+impl<H: Digest + FixedOutputReset> ShardedChain<H> {
+    pub fn anchors(&self) -> Vec<Output<H>> {
+        self.segments.iter().map(|chain| chain.anchor().clone()).collect()
+    }
+
+    pub fn commitment(&self) -> Output<H> {
+        commitment::<H>(&self.anchors())
+    }
+
+    pub fn length(&self) -> u64 {
+        self.segment_length * self.segments.len() as u64
+    }
+
+    /// Recompute every segment from `master_seed`, each in its own thread, returning the first
+    /// segment that does not match.
+    pub fn audit(&self, master_seed: u64) -> Result<(), (u64, ChainAuditError)>
+    where
+        H: Send + Sync,
+    {
+        std::thread::scope(|scope| {
+            let audits: Vec<_> = self.segments.iter().enumerate().map(|(k, chain)| {
+                scope.spawn(move || chain.audit(mid_seed::<H>(master_seed, k as u64)).map_err(|e| (k as u64, e)))
+            }).collect();
+            audits.into_iter().try_for_each(|audit| audit.join().expect("audits do not panic"))
+        })
+    }
+
+    /// Disclose the next value with its global index, or `None` once every segment is exhausted.
+    pub fn disclose(&mut self) -> Option<(u64, ChainValue<H>)> {
+        while self.current < self.segments.len() {
+            if let Some((index, value)) = self.segments[self.current].disclose() {
+                return Some((self.current as u64 * self.segment_length + index, value));
+            }
+            self.current += 1;
+        }
+        None
+    }
+}
+
+impl crate::version::Versioned for SegmentResult {
+    type Error = ShardError;
+
+    fn encode_body(&self, _version: u8) -> Vec<u8> {
+        self.to_bytes()
+    }
+
+    fn decode_body(_version: u8, body: &[u8]) -> Result<Self, ShardError> {
+        Self::from_bytes(body)
+    }
+}
+
+#[test]
+fn test_sharded_setup() {
+    use sha2::Sha256;
+
+    let plan = ShardPlan { segments: 4, segment_length: 16 };
+    // the workers run in threads here, talking to the coordinator in bytes
+    let mut results: Vec<SegmentResult> = std::thread::scope(|scope| {
+        let workers: Vec<_> = (0..plan.segments).rev().map(|k| scope.spawn(move || compute_segment::<Sha256>(&plan, 5, k).unwrap().to_bytes())).collect();
+        workers.into_iter().map(|worker| SegmentResult::from_bytes(&worker.join().unwrap()).unwrap()).collect()
+    });
+    let mut sharded = stitch::<Sha256>(&plan, results.clone()).unwrap();
+    assert!(sharded.audit(5).is_ok());
+    assert_eq!(sharded.audit(6).map_err(|(segment, _)| segment), Err(0));
+
+    let anchors = sharded.anchors();
+    assert_eq!(commitment::<Sha256>(&anchors), sharded.commitment());
+    let mut count = 0;
+    while let Some((index, value)) = sharded.disclose() {
+        count += 1;
+        assert_eq!(index, count);
+        assert!(verify_sharded::<Sha256>(&anchors, 16, index, &value));
+        assert!(!verify_sharded::<Sha256>(&anchors, 16, index % 64 + 1, &value));
+    }
+    assert_eq!(count, 64);
+
+    results.pop();
+    assert!(stitch::<Sha256>(&plan, results.clone()).is_err());
+    let mut tampered = compute_segment::<Sha256>(&plan, 5, 0).unwrap();
+    tampered.state[16] ^= 1; // the anchor's first byte
+    results.push(tampered);
+    assert!(stitch::<Sha256>(&plan, results).is_err());
+}
@@ -0,0 +1,258 @@
+//! One prover shared between threads, e.g. the request handlers of a web server.
+//!
+//! [`SharedTraverser`] holds its chain behind a mutex that is only taken for the disclosure
+//! itself. The anchor and length never change and live outside the lock, and the position is
+//! mirrored in an atomic, so none of them wait for a disclosure in progress. Every index is
+//! handed out exactly once, in the order callers get the lock.
+//!
+//! [`ReservingTraverser`] goes further for high-throughput issuance: a thread reserves its index
+//! with an atomic increment and hashes its value down from the nearest pebble above it without
+//! any lock held. The pebbles themselves still have to move in order, so whichever thread
+//! finishes finds the chain's lock free and catches the traversal up to the indices already
+//! reserved; threads that find it taken leave that work to the holder's successors.
+
+use crate::{hash_forward, HashChain};
+use crate::value::ChainValue;
+use digest::generic_array::GenericArray;
+use digest::{Digest, FixedOutputReset};
+use std::collections::BTreeSet;
+use std::error::Error;
+use std::fmt::{self, Display};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Mutex, MutexGuard, TryLockError};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TryNextError {
+    /// Another thread is disclosing right now.
+    Busy,
+    /// Every value has been disclosed.
+    Exhausted,
+}
+
+impl Display for TryNextError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            TryNextError::Busy => write!(f, "another disclosure is in progress"),
+            TryNextError::Exhausted => write!(f, "chain is exhausted"),
+        }
+    }
+}
+
+impl Error for TryNextError {}
+
+pub struct SharedTraverser<H: Digest + FixedOutputReset> {
+    anchor: GenericArray<u8, H::OutputSize>,
+    length: u64,
+    position: AtomicU64,
+    chain: Mutex<HashChain<H>>,
+}
+
+impl<H: Digest + FixedOutputReset> SharedTraverser<H> {
+    pub fn new(chain: HashChain<H>) -> Self {
+        SharedTraverser {
+            anchor: chain.anchor().clone(),
+            length: chain.length(),
+            position: AtomicU64::new(HashChain::position(&chain)),
+            chain: Mutex::new(chain),
+        }
+    }
+
+    pub fn anchor(&self) -> &GenericArray<u8, H::OutputSize> {
+        &self.anchor
+    }
+
+    pub fn length(&self) -> u64 {
+        self.length
+    }
+
+    /// The position of the last disclosed value. Another thread may move it on at any time.
+    pub fn position(&self) -> u64 {
+        self.position.load(Ordering::Acquire)
+    }
+
+    pub fn remaining(&self) -> u64 {
+        self.length - self.position()
+    }
+
+    /// Disclose the next value, waiting for any disclosure in progress, or `None` once the chain
+    /// is exhausted.
+    pub fn next(&self) -> Option<(u64, ChainValue<H>)> {
+        if self.remaining() == 0 {
+            return None;
+        }
+        // disclosing never panics half way, so a poisoned lock still guards a consistent chain
+        let chain = self.chain.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+        self.disclose(chain)
+    }
+
+    /// Disclose the next value if no other thread is disclosing, without waiting.
+    pub fn try_next(&self) -> Result<(u64, ChainValue<H>), TryNextError> {
+        if self.remaining() == 0 {
+            return Err(TryNextError::Exhausted);
+        }
+        let chain = match self.chain.try_lock() {
+            Ok(chain) => chain,
+            Err(TryLockError::Poisoned(poisoned)) => poisoned.into_inner(),
+            Err(TryLockError::WouldBlock) => return Err(TryNextError::Busy),
+        };
+        self.disclose(chain).ok_or(TryNextError::Exhausted)
+    }
+
+    fn disclose(&self, mut chain: MutexGuard<HashChain<H>>) -> Option<(u64, ChainValue<H>)> {
+        let disclosed = chain.disclose()?;
+        self.position.store(disclosed.0, Ordering::Release);
+        Some(disclosed)
+    }
+
+    pub fn into_inner(self) -> HashChain<H> {
+        self.chain.into_inner().unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+}
+
+/// The traversal behind a [`ReservingTraverser`] and how far it may advance.
+struct Reconciliation<H: Digest + FixedOutputReset> {
+    chain: HashChain<H>,
+    /// Every index up to here has taken its pebble, so the chain may move past it.
+    settled: u64,
+    /// Indices above `settled` that have taken their pebble out of order.
+    taken: BTreeSet<u64>,
+}
+
+pub struct ReservingTraverser<H: Digest + FixedOutputReset> {
+    anchor: GenericArray<u8, H::OutputSize>,
+    length: u64,
+    reserved: AtomicU64,
+    state: Mutex<Reconciliation<H>>,
+}
+
+impl<H: Digest + FixedOutputReset> ReservingTraverser<H> {
+    pub fn new(chain: HashChain<H>) -> Self {
+        let position = HashChain::position(&chain);
+        ReservingTraverser {
+            anchor: chain.anchor().clone(),
+            length: chain.length(),
+            reserved: AtomicU64::new(position),
+            state: Mutex::new(Reconciliation { chain, settled: position, taken: BTreeSet::new() }),
+        }
+    }
+
+    pub fn anchor(&self) -> &GenericArray<u8, H::OutputSize> {
+        &self.anchor
+    }
+
+    pub fn length(&self) -> u64 {
+        self.length
+    }
+
+    /// The highest index reserved so far. Its value may still be being computed.
+    pub fn reserved(&self) -> u64 {
+        self.reserved.load(Ordering::Acquire).min(self.length)
+    }
+
+    fn lock(&self) -> MutexGuard<'_, Reconciliation<H>> {
+        self.state.lock().unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+
+    /// Reserve the next index and compute its value, or `None` once every index is reserved.
+    /// Concurrent callers get distinct indices, but may return them out of order.
+    pub fn next(&self) -> Option<(u64, ChainValue<H>)> {
+        let index = self.reserved.fetch_add(1, Ordering::AcqRel) + 1;
+        if index > self.length {
+            return None;
+        }
+
+        // copy the closest value above `index`; the chain cannot have passed it, since `index`
+        // is not settled yet, and the top of the chain is only ever hashed down from
+        let (position, value) = {
+            let mut guard = self.lock();
+            let state = &mut *guard;
+            let pebble = state.chain.pebbles.iter().filter(|p| p.position >= index).min_by_key(|p| p.position)
+                .expect("a pebble holds a value above every undisclosed index");
+            let hint = (pebble.position, pebble.value.clone());
+            state.taken.insert(index);
+            while state.taken.remove(&(state.settled + 1)) {
+                state.settled += 1;
+            }
+            hint
+        };
+        let value = hash_forward::<H>(&value, position - index);
+        self.reconcile();
+        Some((index, ChainValue::new(value)))
+    }
+
+    /// Move the pebbles up to the settled indices, unless another thread is already doing so.
+    fn reconcile(&self) {
+        let mut state = match self.state.try_lock() {
+            Ok(state) => state,
+            Err(TryLockError::Poisoned(poisoned)) => poisoned.into_inner(),
+            Err(TryLockError::WouldBlock) => return,
+        };
+        while HashChain::position(&state.chain) < state.settled {
+            state.chain.disclose();
+        }
+    }
+
+    /// The traversal, caught up with every reserved index.
+    pub fn into_inner(self) -> HashChain<H> {
+        let mut state = self.state.into_inner().unwrap_or_else(|poisoned| poisoned.into_inner());
+        while HashChain::position(&state.chain) < state.settled {
+            state.chain.disclose();
+        }
+        state.chain
+    }
+}
+
+#[test]
+fn test_shared_traverser_is_sequential() {
+    use crate::verify;
+    use sha2::Sha256;
+
+    fn assert_send_sync<T: Send + Sync>() {}
+    assert_send_sync::<SharedTraverser<Sha256>>();
+
+    let shared = SharedTraverser::new(HashChain::<Sha256>::new(256, 11).unwrap());
+    let mut disclosed: Vec<_> = std::thread::scope(|scope| {
+        let workers: Vec<_> = (0..4).map(|_| scope.spawn(|| {
+            let mut mine = Vec::new();
+            while let Some(next) = shared.next() {
+                mine.push(next);
+            }
+            mine
+        })).collect();
+        workers.into_iter().flat_map(|worker| {
+            let mine = worker.join().unwrap();
+            assert!(mine.windows(2).all(|pair| pair[0].0 < pair[1].0));
+            mine
+        }).collect()
+    });
+    disclosed.sort_by_key(|(index, _)| *index);
+    assert_eq!(disclosed.iter().map(|(index, _)| *index).collect::<Vec<_>>(), (1..=256).collect::<Vec<_>>());
+    assert!(disclosed.windows(2).all(|pair| verify::<Sha256>(pair[0].0, &pair[0].1, pair[1].0, &pair[1].1)));
+    assert_eq!(shared.try_next(), Err(TryNextError::Exhausted));
+
+    let shared = SharedTraverser::new(HashChain::<Sha256>::new(4, 11).unwrap());
+    let guard = shared.chain.lock().unwrap();
+    assert_eq!(shared.try_next(), Err(TryNextError::Busy));
+    drop(guard);
+    assert_eq!(shared.try_next().map(|(index, _)| index), Ok(1));
+    assert_eq!(shared.position(), 1);
+}
+
+#[test]
+fn test_reserving_traverser() {
+    use crate::verify;
+    use sha2::Sha256;
+
+    let reserving = ReservingTraverser::new(HashChain::<Sha256>::new(512, 12).unwrap());
+    let anchor = *reserving.anchor();
+    let mut disclosed: Vec<_> = std::thread::scope(|scope| {
+        let workers: Vec<_> = (0..4).map(|_| scope.spawn(|| std::iter::from_fn(|| reserving.next()).collect::<Vec<_>>())).collect();
+        workers.into_iter().flat_map(|worker| worker.join().unwrap()).collect()
+    });
+    disclosed.sort_by_key(|(index, _)| *index);
+    assert_eq!(disclosed.iter().map(|(index, _)| *index).collect::<Vec<_>>(), (1..=512).collect::<Vec<_>>());
+    assert!(disclosed.iter().all(|(index, value)| verify::<Sha256>(0, &anchor, *index, value)));
+    assert_eq!(reserving.next(), None);
+    assert_eq!(reserving.reserved(), 512);
+    assert_eq!(reserving.into_inner().remaining(), 0);
+}
@@ -0,0 +1,93 @@
+//! Implementations of the [`signature`] crate's traits, so the hash-based schemes can be used
+//! wherever code is generic over signature algorithms.
+//!
+//! Stateful signers implement [`SignerMut`], since every signature moves their index forward.
+//! One-time keys are consumed by signing and fit neither signer trait, so only their verifying
+//! keys are covered.
+
+use crate::hypertree::{HypertreePublicKey, HypertreeSignature, HypertreeSigner};
+use crate::ots::{horst, lamport, winternitz};
+use crate::store::StateStore;
+use crate::xmss::{XmssPublicKey, XmssSignature, XmssSigner};
+use crate::ChainStep;
+use digest::Digest;
+use signature::{Error, SignerMut, Verifier};
+
+fn check(valid: bool) -> Result<(), Error> {
+    if valid {
+        Ok(())
+    } else {
+        Err(Error::new())
+    }
+}
+
+impl<H: Digest + Send + 'static, S: StateStore<State = u64>> SignerMut<XmssSignature<H>> for XmssSigner<H, S> {
+    fn try_sign(&mut self, msg: &[u8]) -> Result<XmssSignature<H>, Error> {
+        self.sign(msg).map_err(Error::from_source)
+    }
+}
+
+impl<H: Digest> Verifier<XmssSignature<H>> for XmssPublicKey<H> {
+    fn verify(&self, msg: &[u8], signature: &XmssSignature<H>) -> Result<(), Error> {
+        check(XmssPublicKey::verify(self, msg, signature))
+    }
+}
+
+impl<H: Digest + Send + 'static, S: StateStore<State = u64>> SignerMut<HypertreeSignature<H>> for HypertreeSigner<H, S> {
+    fn try_sign(&mut self, msg: &[u8]) -> Result<HypertreeSignature<H>, Error> {
+        self.sign(msg).map_err(Error::from_source)
+    }
+}
+
+impl<H: Digest> Verifier<HypertreeSignature<H>> for HypertreePublicKey<H> {
+    fn verify(&self, msg: &[u8], signature: &HypertreeSignature<H>) -> Result<(), Error> {
+        check(HypertreePublicKey::verify(self, msg, signature))
+    }
+}
+
+impl<H: Digest> SignerMut<horst::Signature<H>> for horst::SigningKey<H> {
+    fn try_sign(&mut self, msg: &[u8]) -> Result<horst::Signature<H>, Error> {
+        Ok(self.sign_message(msg))
+    }
+}
+
+impl<H: Digest> Verifier<horst::Signature<H>> for horst::VerifyingKey<H> {
+    fn verify(&self, msg: &[u8], signature: &horst::Signature<H>) -> Result<(), Error> {
+        check(self.verify_message(msg, signature))
+    }
+}
+
+impl<H: Digest> Verifier<lamport::Signature<H>> for lamport::VerifyingKey<H> {
+    fn verify(&self, msg: &[u8], signature: &lamport::Signature<H>) -> Result<(), Error> {
+        check(self.verify_message(msg, signature))
+    }
+}
+
+impl<H: Digest, S: ChainStep<H>> Verifier<winternitz::Signature<H>> for winternitz::VerifyingKey<H, S> {
+    fn verify(&self, msg: &[u8], signature: &winternitz::Signature<H>) -> Result<(), Error> {
+        check(self.verify_message(msg, signature))
+    }
+}
+
+#[test]
+fn test_generic_over_signature_traits() {
+    use crate::store::MemoryStore;
+    use crate::xmss::XmssParams;
+    use sha2::Sha256;
+
+    fn sign_and_verify<Sig, K: SignerMut<Sig>, V: Verifier<Sig>>(signer: &mut K, verifier: &V) -> bool {
+        let signature = signer.sign(b"generic");
+        verifier.verify(b"generic", &signature).is_ok() && verifier.verify(b"other", &signature).is_err()
+    }
+
+    let params = XmssParams { height: 2, wots: winternitz::Params::new(16).unwrap(), bds_k: 0 };
+    let mut xmss = XmssSigner::<Sha256, _>::new(params, b"secret", b"public", MemoryStore::new(), 0u8).unwrap();
+    let public = xmss.public_key().clone();
+    assert!(sign_and_verify(&mut xmss, &public));
+
+    let (mut horst, horst_public) = horst::generate::<Sha256>(horst::Params::new(8, 8, 32).unwrap(), b"seed");
+    assert!(sign_and_verify(&mut horst, &horst_public));
+
+    let (lamport, lamport_public) = lamport::generate::<Sha256>(b"seed");
+    assert!(Verifier::verify(&lamport_public, b"once", &lamport.sign_message(b"once")).is_ok());
+}
@@ -0,0 +1,191 @@
+//! A chain whose elements also commit to the elements `2^k` positions further from the anchor,
+//! so a verifier that missed many values catches up in `O(log gap)` hashes instead of `O(gap)`.
+//!
+//! Element `m` is the ordinary chain value at position `m` together with its links, the digests
+//! of elements `m + 1, m + 2, m + 4, ...` as far as the chain goes, and its digest is
+//! `H("skip element" || value || link_0 || link_1 || ...)`. The elements are computed down from
+//! the end of the chain, and element 0 is published in place of the anchor. A disclosure carries
+//! the elements on a path of power-of-two hops from the last index the receiver accepted, each
+//! checked against a link of the element before it. A digest reveals nothing of the value it
+//! covers, so the links of disclosed elements only ever vouch for values still to come.
+//!
+//! The prover keeps every element, `O(n log n)` digests, so skip chains suit modest lengths.
+
+use crate::{setup_chain, ChainInitError};
+use alloc::vec::Vec;
+use core::error::Error;
+use core::fmt::{self, Display};
+use digest::{Digest, FixedOutputReset, Output, OutputSizeUser};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SkipError {
+    /// The disclosure does not lead past the last accepted index.
+    Replay,
+    /// A hop of the path is not a power of two the element before it links.
+    InvalidHop,
+    /// An element of the path does not match the link to it.
+    Mismatch,
+}
+
+impl Display for SkipError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            SkipError::Replay => write!(f, "index already accepted"),
+            SkipError::InvalidHop => write!(f, "path hop without a link"),
+            SkipError::Mismatch => write!(f, "element does not match its link"),
+        }
+    }
+}
+
+impl Error for SkipError {}
+
+pub struct SkipElement<H: OutputSizeUser> {
+    pub value: Output<H>,
+    /// `links[k]` is the digest of the element `2^k` positions further from the anchor.
+    pub links: Vec<Output<H>>,
+}
+
+impl<H: OutputSizeUser> Clone for SkipElement<H> {
+    fn clone(&self) -> Self {
+        SkipElement { value: self.value.clone(), links: self.links.clone() }
+    }
+}
+
+impl<H: Digest> SkipElement<H> {
+    pub fn digest(&self) -> Output<H> {
+        let mut hasher = H::new_with_prefix(b"skip element");
+        hasher.update(&self.value);
+        for link in &self.links {
+            hasher.update(link);
+        }
+        hasher.finalize()
+    }
+}
+
+pub struct SkipDisclosure<H: OutputSizeUser> {
+    pub index: u64,
+    /// The elements from just past the receiver's index to `index`, with their indices.
+    pub path: Vec<(u64, SkipElement<H>)>,
+}
+
+impl<H: OutputSizeUser> Clone for SkipDisclosure<H> {
+    fn clone(&self) -> Self {
+        SkipDisclosure { index: self.index, path: self.path.clone() }
+    }
+}
+
+pub struct SkipChain<H: Digest + FixedOutputReset> {
+    /// Element `m` at offset `m`, the anchor's first.
+    elements: Vec<SkipElement<H>>,
+    position: u64,
+}
+
+impl<H: Digest + FixedOutputReset> SkipChain<H> {
+    pub fn new(length: usize, seed: u64) -> Result<Self, ChainInitError> {
+        let mut values = Vec::from_iter(core::iter::repeat_n(Output::<H>::default(), length + 1));
+        let (_, anchor) = setup_chain::<H, _>(length, seed, |position, value| values[position as usize] = value.clone())?;
+        values[0] = anchor;
+        let mut elements = Vec::<SkipElement<H>>::with_capacity(length + 1);
+        // built from the end of the chain, the element `2^k` further on already done
+        for (m, value) in values.into_iter().enumerate().rev() {
+            let links = (0..).map(|k| 1usize << k).take_while(|hop| m + hop <= length)
+                .map(|hop| elements[length - m - hop].digest()).collect();
+            elements.push(SkipElement { value, links });
+        }
+        elements.reverse();
+        Ok(SkipChain { elements, position: 0 })
+    }
+
+    /// Element 0, which verifiers start from.
+    pub fn anchor(&self) -> &SkipElement<H> {
+        &self.elements[0]
+    }
+
+    pub fn length(&self) -> u64 {
+        self.elements.len() as u64 - 1
+    }
+
+    pub fn position(&self) -> u64 {
+        self.position
+    }
+
+    /// Disclose the next value to a receiver that last accepted index `known`, 0 if it only has
+    /// the anchor. `None` once the chain is exhausted, or if `known` has not been disclosed.
+    pub fn disclose(&mut self, known: u64) -> Option<SkipDisclosure<H>> {
+        let index = self.position + 1;
+        if index > self.length() || known > self.position {
+            return None;
+        }
+        self.position = index;
+        let mut path = Vec::new();
+        let mut at = known;
+        while at < index {
+            // the longest hop that does not overshoot
+            at += 1 << (index - at).ilog2();
+            path.push((at, self.elements[at as usize].clone()));
+        }
+        Some(SkipDisclosure { index, path })
+    }
+}
+
+pub struct SkipVerifier<H: Digest> {
+    index: u64,
+    element: SkipElement<H>,
+}
+
+impl<H: Digest> SkipVerifier<H> {
+    pub fn new(anchor: SkipElement<H>) -> Self {
+        SkipVerifier { index: 0, element: anchor }
+    }
+
+    /// The last accepted index, the one to pass to [`SkipChain::disclose`].
+    pub fn index(&self) -> u64 {
+        self.index
+    }
+
+    /// Follow the path of a disclosure, one hash per hop, and accept its value.
+    pub fn accept(&mut self, disclosure: &SkipDisclosure<H>) -> Result<&Output<H>, SkipError> {
+        if disclosure.index <= self.index || disclosure.path.last().map(|(index, _)| *index) != Some(disclosure.index) {
+            return Err(SkipError::Replay);
+        }
+        let (mut index, mut element) = (self.index, &self.element);
+        for (next, next_element) in &disclosure.path {
+            let hop = next.checked_sub(index).filter(|hop| hop.is_power_of_two()).ok_or(SkipError::InvalidHop)?;
+            let link = element.links.get(hop.ilog2() as usize).ok_or(SkipError::InvalidHop)?;
+            if next_element.digest() != *link {
+                return Err(SkipError::Mismatch);
+            }
+            (index, element) = (*next, next_element);
+        }
+        self.element = element.clone();
+        self.index = index;
+        Ok(&self.element.value)
+    }
+}
+
+#[test]
+fn test_skip_chain() {
+    use crate::create_hash_chain_nopebble;
+    use sha2::Sha256;
+
+    let mut prover = SkipChain::<Sha256>::new(64, 5).unwrap();
+    let mut verifier = SkipVerifier::new(prover.anchor().clone());
+    let values = create_hash_chain_nopebble::<Sha256>(64, 5);
+    let first = prover.disclose(verifier.index()).unwrap();
+    assert_eq!(*verifier.accept(&first).unwrap(), values[63]);
+    assert_eq!(verifier.accept(&first), Err(SkipError::Replay));
+
+    // a verifier at 1 catches up to 46 in hops of 32, 8, 4 and 1
+    for _ in 2..46 {
+        prover.disclose(0).unwrap();
+    }
+    let mut late = prover.disclose(verifier.index()).unwrap();
+    assert_eq!(late.path.iter().map(|(index, _)| *index).collect::<Vec<_>>(), vec![33, 41, 45, 46]);
+    let mut tampered = late.clone();
+    tampered.path[1].1.value[0] ^= 1;
+    assert_eq!(verifier.accept(&tampered), Err(SkipError::Mismatch));
+    late.path.remove(1);
+    assert_eq!(verifier.accept(&late), Err(SkipError::InvalidHop));
+    let caught_up = prover.disclose(1).unwrap();
+    assert_eq!(*verifier.accept(&caught_up).unwrap(), values[64 - 47]);
+}
@@ -0,0 +1,143 @@
+//! Out-of-order disclosure. The discloser publishes the [`chain_root`](merkle::chain_root) of
+//! its chain and later reveals any values it likes, in any order, each with a
+//! [`ChainProof`]. Verifiers check every value against the root instead of hashing it down to
+//! the last value they saw, which suits selective reveals where most values are never
+//! disclosed.
+//!
+//! A tree committing to values that do not form a chain would pass the Merkle checks alone, so
+//! a verifier given the anchor as well also checks that each value hashes down to it.
+
+use crate::merkle::{self, AuthPath, ChainProof};
+use crate::{create_hash_chain_nopebble, hash_forward, ChainInitError};
+use digest::generic_array::GenericArray;
+use digest::{Digest, FixedOutputReset, OutputSizeUser};
+use std::collections::BTreeMap;
+use std::error::Error;
+use std::fmt::{self, Display};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SparseError {
+    /// The position lies outside the chain.
+    OutOfRange,
+    /// The authentication path does not lead to the root.
+    BadProof,
+    /// The value does not hash down to the anchor.
+    NotOnChain,
+}
+
+impl Display for SparseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            SparseError::OutOfRange => write!(f, "position outside the chain"),
+            SparseError::BadProof => write!(f, "proof does not verify against the root"),
+            SparseError::NotOnChain => write!(f, "value does not hash to the anchor"),
+        }
+    }
+}
+
+impl Error for SparseError {}
+
+/// Holds the whole chain and its tree, about three values per position, to prove any position
+/// without recomputation. [`merkle::chain_proof`] trades that memory for a pass over the chain.
+pub struct SparseDiscloser<H: OutputSizeUser> {
+    values: Vec<GenericArray<u8, H::OutputSize>>,
+    tree: Vec<Vec<GenericArray<u8, H::OutputSize>>>,
+}
+
+impl<H: Digest + FixedOutputReset> SparseDiscloser<H> {
+    pub fn new(length: usize, seed: u64) -> Result<Self, ChainInitError> {
+        if length < 2 || !length.is_power_of_two() {
+            return Err(ChainInitError::new("length not a power of two"));
+        }
+        let values = create_hash_chain_nopebble::<H>(length, seed);
+        let leaves: Vec<_> = values.iter().map(|value| merkle::leaf::<H>(value)).collect();
+        Ok(SparseDiscloser { values, tree: merkle::levels::<H>(&leaves) })
+    }
+
+    pub fn length(&self) -> u64 {
+        self.values.len() as u64
+    }
+
+    /// The Merkle root to publish.
+    pub fn root(&self) -> &GenericArray<u8, H::OutputSize> {
+        &self.tree[self.tree.len() - 1][0]
+    }
+
+    pub fn anchor(&self) -> GenericArray<u8, H::OutputSize> {
+        hash_forward::<H>(&self.values[self.values.len() - 1], 1)
+    }
+
+    /// The value at `position` with its proof.
+    pub fn disclose(&self, position: u64) -> Option<ChainProof<H>> {
+        if !(1..=self.length()).contains(&position) {
+            return None;
+        }
+        let index = self.length() - position;
+        let path: AuthPath<H> = merkle::path_in(&self.tree, index);
+        Some(ChainProof { position, value: self.values[index as usize].clone(), path })
+    }
+}
+
+pub struct SparseVerifier<H: OutputSizeUser> {
+    root: GenericArray<u8, H::OutputSize>,
+    length: u64,
+    anchor: Option<GenericArray<u8, H::OutputSize>>,
+    disclosed: BTreeMap<u64, GenericArray<u8, H::OutputSize>>,
+}
+
+impl<H: Digest + FixedOutputReset> SparseVerifier<H> {
+    /// Verify against `root` for a chain of `length` values.
+    pub fn new(root: GenericArray<u8, H::OutputSize>, length: u64) -> Self {
+        SparseVerifier { root, length, anchor: None, disclosed: BTreeMap::new() }
+    }
+
+    /// Also check every value against `anchor`, at a cost of `position` hashes each.
+    pub fn with_anchor(self, anchor: GenericArray<u8, H::OutputSize>) -> Self {
+        SparseVerifier { anchor: Some(anchor), ..self }
+    }
+
+    pub fn accept(&mut self, proof: &ChainProof<H>) -> Result<(), SparseError> {
+        if !(1..=self.length).contains(&proof.position) {
+            return Err(SparseError::OutOfRange);
+        }
+        if !proof.verify(&self.root, self.length) {
+            return Err(SparseError::BadProof);
+        }
+        if let Some(anchor) = &self.anchor {
+            if hash_forward::<H>(&proof.value, proof.position) != *anchor {
+                return Err(SparseError::NotOnChain);
+            }
+        }
+        self.disclosed.insert(proof.position, proof.value.clone());
+        Ok(())
+    }
+
+    /// The value accepted for `position`, if any.
+    pub fn get(&self, position: u64) -> Option<&GenericArray<u8, H::OutputSize>> {
+        self.disclosed.get(&position)
+    }
+
+    /// The accepted positions in ascending order.
+    pub fn positions(&self) -> impl Iterator<Item = u64> + '_ {
+        self.disclosed.keys().copied()
+    }
+}
+
+#[test]
+fn test_sparse_disclosure() {
+    use sha2::Sha256;
+
+    let discloser = SparseDiscloser::<Sha256>::new(32, 6).unwrap();
+    assert_eq!(*discloser.root(), merkle::chain_root::<Sha256>(32, 6).unwrap());
+    let mut verifier = SparseVerifier::<Sha256>::new(*discloser.root(), 32).with_anchor(discloser.anchor());
+    for position in [29, 3, 17] {
+        verifier.accept(&discloser.disclose(position).unwrap()).unwrap();
+    }
+    assert_eq!(verifier.positions().collect::<Vec<_>>(), vec![3, 17, 29]);
+    assert_eq!(hash_forward::<Sha256>(verifier.get(17).unwrap(), 14), *verifier.get(3).unwrap());
+
+    let mut forged = discloser.disclose(5).unwrap();
+    forged.value[0] ^= 1;
+    assert_eq!(verifier.accept(&forged), Err(SparseError::BadProof));
+    assert!(discloser.disclose(33).is_none());
+}
@@ -0,0 +1,298 @@
+//! Persistence of protocol state.
+//!
+//! Servers keep per-user or per-chain state (for example the last accepted index and value) in a
+//! [`StateStore`]. Every method takes `&self` so a store can be shared between request handlers;
+//! [`StateStore::compare_and_swap`] is what lets them advance state without losing updates.
+//! Backends reached over the network implement [`AsyncStateStore`] instead, so async servers
+//! wait for them without blocking their runtime.
+//!
+//! A store must not report a save or swap as successful before it is durable, or a crash can
+//! undo an accepted disclosure and let it verify again. The [`FileStore`] writes every state to a
+//! temporary file, syncs it and renames it into place, so a crash leaves either the old state or
+//! the new one, and [`FileStore::open`] clears the temporary files of writes that never finished.
+
+use crate::version::{Versioned, CURRENT};
+use std::collections::HashMap;
+use std::error::Error;
+use std::fmt::{self, Display};
+use std::fs::{self, File};
+use std::future::{self, Future};
+use std::hash::Hash;
+use std::io::{self, Write};
+use std::marker::PhantomData;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+
+#[derive(Debug, Clone)]
+pub struct StoreError {
+    details: String,
+}
+
+impl StoreError {
+    pub fn new(error_message: &str) -> StoreError {
+        StoreError { details: error_message.to_string() }
+    }
+}
+
+impl Display for StoreError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.details)
+    }
+}
+
+impl Error for StoreError {}
+
+/// A keyed store of protocol state.
+pub trait StateStore {
+    type Key;
+    type State;
+    type Error: Error;
+
+    /// Load the state stored under `key`, if any.
+    fn load(&self, key: &Self::Key) -> Result<Option<Self::State>, Self::Error>;
+
+    /// Unconditionally store `state` under `key`.
+    fn save(&self, key: &Self::Key, state: Self::State) -> Result<(), Self::Error>;
+
+    /// Store `new` under `key` only if the current state equals `expected` (`None` meaning no
+    /// state is stored yet). Returns whether the swap happened.
+    fn compare_and_swap(&self, key: &Self::Key, expected: Option<&Self::State>, new: Self::State) -> Result<bool, Self::Error>;
+}
+
+/// A shared store, so several servers can hold handles to one backend.
+impl<T: StateStore + ?Sized> StateStore for Arc<T> {
+    type Key = T::Key;
+    type State = T::State;
+    type Error = T::Error;
+
+    fn load(&self, key: &T::Key) -> Result<Option<T::State>, T::Error> {
+        (**self).load(key)
+    }
+
+    fn save(&self, key: &T::Key, state: T::State) -> Result<(), T::Error> {
+        (**self).save(key, state)
+    }
+
+    fn compare_and_swap(&self, key: &T::Key, expected: Option<&T::State>, new: T::State) -> Result<bool, T::Error> {
+        (**self).compare_and_swap(key, expected, new)
+    }
+}
+
+/// The async counterpart of [`StateStore`], with the same contract.
+pub trait AsyncStateStore {
+    type Key;
+    type State;
+    type Error: Error;
+
+    /// Load the state stored under `key`, if any.
+    fn load(&self, key: &Self::Key) -> impl Future<Output = Result<Option<Self::State>, Self::Error>> + Send;
+
+    /// Unconditionally store `state` under `key`.
+    fn save(&self, key: &Self::Key, state: Self::State) -> impl Future<Output = Result<(), Self::Error>> + Send;
+
+    /// Store `new` under `key` only if the current state equals `expected`. Returns whether the
+    /// swap happened.
+    fn compare_and_swap(&self, key: &Self::Key, expected: Option<&Self::State>, new: Self::State) -> impl Future<Output = Result<bool, Self::Error>> + Send;
+}
+
+impl<T: AsyncStateStore + Sync + ?Sized> AsyncStateStore for Arc<T> {
+    type Key = T::Key;
+    type State = T::State;
+    type Error = T::Error;
+
+    fn load(&self, key: &T::Key) -> impl Future<Output = Result<Option<T::State>, T::Error>> + Send {
+        (**self).load(key)
+    }
+
+    fn save(&self, key: &T::Key, state: T::State) -> impl Future<Output = Result<(), T::Error>> + Send {
+        (**self).save(key, state)
+    }
+
+    fn compare_and_swap(&self, key: &T::Key, expected: Option<&T::State>, new: T::State) -> impl Future<Output = Result<bool, T::Error>> + Send {
+        (**self).compare_and_swap(key, expected, new)
+    }
+}
+
+/// A [`StateStore`] kept in process memory, mostly useful for tests and single-process servers.
+#[derive(Debug, Default)]
+pub struct MemoryStore<K, V> {
+    entries: Mutex<HashMap<K, V>>,
+}
+
+impl<K, V> MemoryStore<K, V> {
+    pub fn new() -> Self {
+        MemoryStore { entries: Mutex::new(HashMap::new()) }
+    }
+}
+
+impl<K: Eq + Hash + Clone, V: Clone + PartialEq> StateStore for MemoryStore<K, V> {
+    type Key = K;
+    type State = V;
+    type Error = StoreError;
+
+    fn load(&self, key: &K) -> Result<Option<V>, StoreError> {
+        let entries = self.entries.lock().map_err(|_| StoreError::new("store lock poisoned"))?;
+        Ok(entries.get(key).cloned())
+    }
+
+    fn save(&self, key: &K, state: V) -> Result<(), StoreError> {
+        let mut entries = self.entries.lock().map_err(|_| StoreError::new("store lock poisoned"))?;
+        entries.insert(key.clone(), state);
+        Ok(())
+    }
+
+    fn compare_and_swap(&self, key: &K, expected: Option<&V>, new: V) -> Result<bool, StoreError> {
+        let mut entries = self.entries.lock().map_err(|_| StoreError::new("store lock poisoned"))?;
+        if entries.get(key) != expected {
+            return Ok(false);
+        }
+        entries.insert(key.clone(), new);
+        Ok(true)
+    }
+}
+
+/// The memory store never waits, so its futures are ready at once.
+impl<K: Eq + Hash + Clone, V: Clone + PartialEq + Send> AsyncStateStore for MemoryStore<K, V> {
+    type Key = K;
+    type State = V;
+    type Error = StoreError;
+
+    fn load(&self, key: &K) -> impl Future<Output = Result<Option<V>, StoreError>> + Send {
+        future::ready(StateStore::load(self, key))
+    }
+
+    fn save(&self, key: &K, state: V) -> impl Future<Output = Result<(), StoreError>> + Send {
+        future::ready(StateStore::save(self, key, state))
+    }
+
+    fn compare_and_swap(&self, key: &K, expected: Option<&V>, new: V) -> impl Future<Output = Result<bool, StoreError>> + Send {
+        future::ready(StateStore::compare_and_swap(self, key, expected, new))
+    }
+}
+
+/// A [`StateStore`] keeping each state in its own file of a directory, named after its key, in
+/// the state's current [`Versioned`] encoding. Keys must be non-empty and made of ASCII
+/// letters, digits, `-` and `_`, as chain ids in hex are.
+#[derive(Debug)]
+pub struct FileStore<K, V> {
+    directory: PathBuf,
+    /// Swaps within the process are serialized; the store is not meant to be shared between
+    /// processes.
+    lock: Mutex<()>,
+    _entries: PhantomData<fn(K) -> V>,
+}
+
+impl<K, V> FileStore<K, V> {
+    /// Open the store in `directory`, creating it if needed and removing what writes interrupted
+    /// by a crash left behind. Such a write never reported success, so its key keeps the state
+    /// from before it.
+    pub fn open<P: AsRef<Path>>(directory: P) -> io::Result<Self> {
+        let directory = directory.as_ref().to_path_buf();
+        fs::create_dir_all(&directory)?;
+        for entry in fs::read_dir(&directory)? {
+            let path = entry?.path();
+            if path.extension().is_some_and(|extension| extension == "tmp") {
+                fs::remove_file(path)?;
+            }
+        }
+        Ok(FileStore { directory, lock: Mutex::new(()), _entries: PhantomData })
+    }
+}
+
+impl<K: Display, V: Versioned + PartialEq> FileStore<K, V>
+where
+    V::Error: Display,
+{
+    fn path(&self, key: &K, extension: &str) -> Result<PathBuf, StoreError> {
+        let name = key.to_string();
+        if name.is_empty() || !name.bytes().all(|byte| byte.is_ascii_alphanumeric() || byte == b'-' || byte == b'_') {
+            return Err(StoreError::new("key not usable as a file name"));
+        }
+        Ok(self.directory.join(name).with_extension(extension))
+    }
+
+    fn read(&self, key: &K) -> Result<Option<V>, StoreError> {
+        let bytes = match fs::read(self.path(key, "state")?) {
+            Ok(bytes) => bytes,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(None),
+            Err(e) => return Err(StoreError::new(&e.to_string())),
+        };
+        V::from_versioned_bytes(&bytes, CURRENT).map(Some).map_err(|e| StoreError::new(&e.to_string()))
+    }
+
+    /// Write the state durably: to a temporary file, synced, then renamed into place, with the
+    /// directory synced so the rename survives a crash too.
+    fn write(&self, key: &K, state: &V) -> Result<(), StoreError> {
+        let (temporary, path) = (self.path(key, "tmp")?, self.path(key, "state")?);
+        let bytes = state.to_versioned_bytes(CURRENT).map_err(|e| StoreError::new(&e.to_string()))?;
+        let written = File::create(&temporary)
+            .and_then(|mut file| file.write_all(&bytes).and_then(|_| file.sync_all()))
+            .and_then(|_| fs::rename(&temporary, &path));
+        #[cfg(unix)]
+        let written = written.and_then(|_| File::open(&self.directory)?.sync_all());
+        written.map_err(|e| StoreError::new(&e.to_string()))
+    }
+}
+
+impl<K: Display, V: Versioned + PartialEq> StateStore for FileStore<K, V>
+where
+    V::Error: Display,
+{
+    type Key = K;
+    type State = V;
+    type Error = StoreError;
+
+    fn load(&self, key: &K) -> Result<Option<V>, StoreError> {
+        self.read(key)
+    }
+
+    fn save(&self, key: &K, state: V) -> Result<(), StoreError> {
+        let _lock = self.lock.lock().map_err(|_| StoreError::new("store lock poisoned"))?;
+        self.write(key, &state)
+    }
+
+    fn compare_and_swap(&self, key: &K, expected: Option<&V>, new: V) -> Result<bool, StoreError> {
+        let _lock = self.lock.lock().map_err(|_| StoreError::new("store lock poisoned"))?;
+        if self.read(key)?.as_ref() != expected {
+            return Ok(false);
+        }
+        self.write(key, &new)?;
+        Ok(true)
+    }
+}
+
+#[test]
+fn test_memory_store_compare_and_swap() {
+    let store = MemoryStore::<&str, u64>::new();
+    assert!(StateStore::compare_and_swap(&store, &"a", None, 1).unwrap());
+    assert!(!StateStore::compare_and_swap(&store, &"a", None, 2).unwrap());
+    assert!(!StateStore::compare_and_swap(&store, &"a", Some(&5), 2).unwrap());
+    assert!(StateStore::compare_and_swap(&store, &"a", Some(&1), 2).unwrap());
+    assert_eq!(StateStore::load(&store, &"a").unwrap(), Some(2));
+}
+
+#[test]
+fn test_file_store_survives_crashes() {
+    use crate::registry::{ChainRecord, Verifier, VerifyError};
+    use crate::HashChain;
+    use sha2::Sha256;
+
+    let directory = std::env::temp_dir().join(format!("fht-store-test-{}", std::process::id()));
+    let mut chain = HashChain::<Sha256>::new(16, 4).unwrap();
+    let verifier = Verifier::<Sha256, _>::new(FileStore::open(&directory).unwrap(), "meter", *chain.anchor()).unwrap();
+    let (index, value) = chain.disclose().unwrap();
+    assert_eq!(verifier.accept(index, &value), Ok(1));
+
+    // a crash while writing the next state leaves its temporary file and the old state
+    let (next, next_value) = chain.disclose().unwrap();
+    fs::write(directory.join("meter.tmp"), b"torn").unwrap();
+    let store = FileStore::<&str, ChainRecord<Sha256>>::open(&directory).unwrap();
+    assert!(!directory.join("meter.tmp").exists());
+    let restarted = Verifier::<Sha256, _>::new(store, "meter", *chain.anchor()).unwrap();
+    assert!(restarted.confirm(index, &value).unwrap());
+    assert!(!restarted.confirm(next, &next_value).unwrap());
+    assert_eq!(restarted.accept(index, &value), Err(VerifyError::Replay));
+    assert_eq!(restarted.accept(next, &next_value), Ok(1));
+    assert_eq!(restarted.accept(3, &[0; 16]), Err(VerifyError::Mismatch));
+    fs::remove_dir_all(&directory).unwrap();
+}
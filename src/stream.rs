@@ -0,0 +1,142 @@
+//! Disclosing a chain on a timer, as a [`Stream`] of `(index, value)` pairs.
+//!
+//! A [`ChainStream`] only hashes when it is polled, so a consumer that stops polling, e.g.
+//! because the socket it forwards values to is full, pauses the chain instead of letting values
+//! pile up. How it catches up on resuming depends on how it was built:
+//!
+//! - [`ChainStream::new`] yields one value per tick of the supplied interval. Missed ticks are
+//!   not replayed; the interval restarts a full period after the late tick.
+//! - [`ChainStream::with_schedule`] follows a TESLA [`Schedule`] and always yields the value of
+//!   the interval in progress, skipping the values of intervals the consumer slept through.
+//!   Those are the hashes of later values, so nothing a verifier needs is lost.
+
+use crate::tesla::Schedule;
+use crate::HashChain;
+use crate::value::ChainValue;
+use core::pin::Pin;
+use core::task::{Context, Poll};
+use digest::{Digest, FixedOutputReset};
+use futures_core::Stream;
+use std::time::Duration;
+use tokio::time::{Instant, Interval, MissedTickBehavior};
+
+/// Where the ticks of a scheduled stream sit in the schedule: the tick at `start` begins
+/// interval `first`, and every `period` after it begins the next one.
+struct Alignment {
+    start: Instant,
+    first: u64,
+    period: Duration,
+}
+
+pub struct ChainStream<H: Digest + FixedOutputReset> {
+    chain: HashChain<H>,
+    interval: Interval,
+    alignment: Option<Alignment>,
+}
+
+impl<H: Digest + FixedOutputReset> ChainStream<H> {
+    /// Disclose the next value of `chain` on every tick of `interval`.
+    pub fn new(chain: HashChain<H>, mut interval: Interval) -> Self {
+        interval.set_missed_tick_behavior(MissedTickBehavior::Delay);
+        ChainStream { chain, interval, alignment: None }
+    }
+
+    /// Disclose the value of each of `schedule`'s intervals as it begins, where `now` is the
+    /// current time on the schedule's clock. The stream ends with the chain, i.e. after interval
+    /// `chain.length()`.
+    pub fn with_schedule(chain: HashChain<H>, schedule: &Schedule, now: Duration) -> Self {
+        let clock = Instant::now();
+        let (start, first) = match schedule.interval_at(now) {
+            None => (clock + (schedule.start - now), 1),
+            Some(current) => {
+                let elapsed = now - schedule.start;
+                let into = Duration::from_nanos((elapsed.as_nanos() % schedule.interval.as_nanos()) as u64);
+                (clock.checked_sub(into).unwrap_or(clock), current)
+            }
+        };
+        let mut interval = tokio::time::interval_at(start, schedule.interval);
+        interval.set_missed_tick_behavior(MissedTickBehavior::Skip);
+        ChainStream { chain, interval, alignment: Some(Alignment { start, first, period: schedule.interval }) }
+    }
+
+    pub fn chain(&self) -> &HashChain<H> {
+        &self.chain
+    }
+
+    pub fn into_inner(self) -> HashChain<H> {
+        self.chain
+    }
+}
+
+// nothing is pinned structurally, the chain is only ever reached through `get_mut`
+impl<H: Digest + FixedOutputReset> Unpin for ChainStream<H> {}
+
+impl<H: Digest + FixedOutputReset> Stream for ChainStream<H> {
+    type Item = (u64, ChainValue<H>);
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let this = self.get_mut();
+        if this.chain.remaining() == 0 {
+            return Poll::Ready(None);
+        }
+        if this.interval.poll_tick(cx).is_pending() {
+            return Poll::Pending;
+        }
+        let Some(alignment) = &this.alignment else {
+            return Poll::Ready(this.chain.disclose());
+        };
+
+        // a late tick reports its missed deadline, so place it by the time it was actually taken
+        let target = alignment.first + (Instant::now().duration_since(alignment.start).as_nanos() / alignment.period.as_nanos()) as u64;
+        if target > this.chain.length() {
+            return Poll::Ready(None);
+        }
+        while HashChain::position(&this.chain) + 1 < target {
+            this.chain.disclose();
+        }
+        Poll::Ready(this.chain.disclose())
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let remaining = self.chain.remaining() as usize;
+        match self.alignment {
+            None => (remaining, Some(remaining)),
+            Some(_) => (0, Some(remaining)),
+        }
+    }
+}
+
+#[cfg(test)]
+fn next<S: Stream + Unpin>(stream: &mut S) -> impl core::future::Future<Output = Option<S::Item>> + '_ {
+    core::future::poll_fn(move |cx| Pin::new(&mut *stream).poll_next(cx))
+}
+
+#[test]
+fn test_chain_stream() {
+    use sha2::Sha256;
+
+    let runtime = tokio::runtime::Builder::new_current_thread().enable_time().start_paused(true).build().unwrap();
+    runtime.block_on(async {
+        let period = Duration::from_secs(1);
+        let chain = HashChain::<Sha256>::new(4, 9).unwrap();
+        let expected = crate::create_hash_chain_nopebble::<Sha256>(4, 9);
+        let mut stream = ChainStream::new(chain, tokio::time::interval(period));
+        let started = Instant::now();
+        for index in 1..=4u64 {
+            assert_eq!(next(&mut stream).await, Some((index, expected[4 - index as usize].into())));
+        }
+        assert_eq!(started.elapsed(), 3 * period);
+        assert_eq!(next(&mut stream).await, None);
+
+        // half a second into interval 2, sleeping through intervals 3 and 4
+        let schedule = Schedule { start: Duration::from_secs(100), interval: period };
+        let chain = HashChain::<Sha256>::new(8, 9).unwrap();
+        let expected = crate::create_hash_chain_nopebble::<Sha256>(8, 9);
+        let mut stream = ChainStream::with_schedule(chain, &schedule, Duration::from_millis(101_500));
+        assert_eq!(next(&mut stream).await, Some((2, expected[6].into())));
+        tokio::time::sleep(Duration::from_millis(2_200)).await;
+        assert_eq!(next(&mut stream).await, Some((4, expected[4].into())));
+        assert_eq!(next(&mut stream).await, Some((5, expected[3].into())));
+        assert_eq!(Instant::now() - started, 3 * period + Duration::from_millis(2_500));
+    });
+}
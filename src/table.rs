@@ -0,0 +1,267 @@
+//! An in-memory verification table for servers authenticating many users.
+//!
+//! Verifying a chain takes only its last accepted position, starting from the anchor, never its
+//! seed: a [`VerifierTable`] holds one `(index, value)` pair per chain and nothing an attacker
+//! could log in with if the table leaked. The pairs are kept in three flat arrays sorted by chain
+//! id, 24 bytes plus one hash value per chain with no per-entry allocation, so a table of a
+//! million SHA-256 chains takes 56 MB.
+//!
+//! [`VerifierTable::to_bytes`] saves the table as the record count as u64 big endian followed by
+//! every record, the chain id (16 bytes), the index as u64 big endian and the value, in id order,
+//! and [`VerifierTable::from_bytes`] loads it back. [`VerifierTable::extend_anchors`] enrolls a
+//! batch of chains with one sort instead of an insertion each.
+//!
+//! The table stores no chain lengths, so it refuses disclosures more than [`DEFAULT_MAX_GAP`]
+//! positions past the last accepted one before hashing, or as many as
+//! [`VerifierTable::with_max_gap`] sets.
+
+use crate::verify::DEFAULT_MAX_GAP;
+use crate::{verify, ChainId};
+use alloc::vec::Vec;
+use core::error::Error;
+use core::fmt::{self, Display};
+use core::marker::PhantomData;
+use digest::generic_array::GenericArray;
+use digest::{Digest, FixedOutputReset, Output};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TableError {
+    /// The chain is not in the table.
+    UnknownChain,
+    /// The chain is in the table already.
+    Duplicate(ChainId),
+    /// The index is not greater than the last accepted index.
+    Replay,
+    /// The index skips further ahead of the last accepted index than the table allows.
+    GapTooLarge,
+    /// The value does not hash forward to the last accepted value.
+    Mismatch,
+    /// The encoding ends early or runs on past the records.
+    WrongLength,
+    /// The encoded records are not in id order.
+    Unordered,
+}
+
+impl Display for TableError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            TableError::UnknownChain => write!(f, "unknown chain"),
+            TableError::Duplicate(chain_id) => write!(f, "chain {} enrolled twice", chain_id),
+            TableError::Replay => write!(f, "index already used"),
+            TableError::GapTooLarge => write!(f, "index too far ahead of the last accepted index"),
+            TableError::Mismatch => write!(f, "value does not match the chain"),
+            TableError::WrongLength => write!(f, "wrong length for a verifier table"),
+            TableError::Unordered => write!(f, "verifier table records out of order"),
+        }
+    }
+}
+
+impl Error for TableError {}
+
+pub struct VerifierTable<H: Digest> {
+    ids: Vec<ChainId>,
+    indices: Vec<u64>,
+    /// The last accepted values, one hash output after another in the order of `ids`.
+    values: Vec<u8>,
+    max_gap: u64,
+    _hash: PhantomData<H>,
+}
+
+impl<H: Digest> Clone for VerifierTable<H> {
+    fn clone(&self) -> Self {
+        VerifierTable { ids: self.ids.clone(), indices: self.indices.clone(), values: self.values.clone(), max_gap: self.max_gap, _hash: PhantomData }
+    }
+}
+
+impl<H: Digest> Default for VerifierTable<H> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<H: Digest> VerifierTable<H> {
+    pub fn new() -> Self {
+        Self::with_capacity(0)
+    }
+
+    pub fn with_capacity(chains: usize) -> Self {
+        let values = Vec::with_capacity(chains * <H as Digest>::output_size());
+        VerifierTable { ids: Vec::with_capacity(chains), indices: Vec::with_capacity(chains), values, max_gap: DEFAULT_MAX_GAP, _hash: PhantomData }
+    }
+
+    /// Refuse disclosures more than `max_gap` positions past the last accepted one with
+    /// [`TableError::GapTooLarge`] before hashing. Not saved by [`VerifierTable::to_bytes`].
+    pub fn with_max_gap(self, max_gap: u64) -> Self {
+        VerifierTable { max_gap, ..self }
+    }
+
+    pub fn len(&self) -> usize {
+        self.ids.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.ids.is_empty()
+    }
+
+    fn value(&self, slot: usize) -> &Output<H> {
+        let n = <H as Digest>::output_size();
+        GenericArray::from_slice(&self.values[slot * n..(slot + 1) * n])
+    }
+
+    /// The last accepted index and value of the chain, its anchor at index 0 until a value is
+    /// accepted.
+    pub fn get(&self, chain_id: &ChainId) -> Option<(u64, &Output<H>)> {
+        let slot = self.ids.binary_search(chain_id).ok()?;
+        Some((self.indices[slot], self.value(slot)))
+    }
+
+    /// Enroll a chain by its anchor.
+    pub fn insert(&mut self, chain_id: ChainId, anchor: &Output<H>) -> Result<(), TableError> {
+        let slot = match self.ids.binary_search(&chain_id) {
+            Ok(_) => return Err(TableError::Duplicate(chain_id)),
+            Err(slot) => slot,
+        };
+        let n = <H as Digest>::output_size();
+        self.ids.insert(slot, chain_id);
+        self.indices.insert(slot, 0);
+        self.values.splice(slot * n..slot * n, anchor.iter().copied());
+        Ok(())
+    }
+
+    /// Enroll every chain in `anchors`, sorting once. Nothing is enrolled if a chain is in the
+    /// table already or twice in `anchors`.
+    pub fn extend_anchors<I: IntoIterator<Item = (ChainId, Output<H>)>>(&mut self, anchors: I) -> Result<(), TableError> {
+        let mut records: Vec<_> = self.records().map(|(chain_id, index, value)| (chain_id, index, value.clone())).collect();
+        records.extend(anchors.into_iter().map(|(chain_id, anchor)| (chain_id, 0, anchor)));
+        records.sort_unstable_by_key(|(chain_id, _, _)| *chain_id);
+        if let Some(pair) = records.windows(2).find(|pair| pair[0].0 == pair[1].0) {
+            return Err(TableError::Duplicate(pair[0].0));
+        }
+        *self = Self::with_capacity(records.len()).with_max_gap(self.max_gap);
+        for (chain_id, index, value) in records {
+            self.ids.push(chain_id);
+            self.indices.push(index);
+            self.values.extend_from_slice(&value);
+        }
+        Ok(())
+    }
+
+    /// Remove the chain, returning whether it was in the table.
+    pub fn remove(&mut self, chain_id: &ChainId) -> bool {
+        let Ok(slot) = self.ids.binary_search(chain_id) else {
+            return false;
+        };
+        let n = <H as Digest>::output_size();
+        self.ids.remove(slot);
+        self.indices.remove(slot);
+        self.values.drain(slot * n..(slot + 1) * n);
+        true
+    }
+
+    /// Every chain's id, last accepted index and value, in id order.
+    pub fn records(&self) -> impl Iterator<Item = (ChainId, u64, &Output<H>)> + '_ {
+        (0..self.len()).map(|slot| (self.ids[slot], self.indices[slot], self.value(slot)))
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = Vec::with_capacity(8 + self.len() * 24 + self.values.len());
+        bytes.extend_from_slice(&(self.len() as u64).to_be_bytes());
+        for (chain_id, index, value) in self.records() {
+            bytes.extend_from_slice(&chain_id.0);
+            bytes.extend_from_slice(&index.to_be_bytes());
+            bytes.extend_from_slice(value);
+        }
+        bytes
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, TableError> {
+        let n = <H as Digest>::output_size();
+        let (count, records) = bytes.split_first_chunk::<8>().ok_or(TableError::WrongLength)?;
+        let count = usize::try_from(u64::from_be_bytes(*count)).map_err(|_| TableError::WrongLength)?;
+        if count.checked_mul(24 + n) != Some(records.len()) {
+            return Err(TableError::WrongLength);
+        }
+        let mut table = Self::with_capacity(count);
+        for record in records.chunks(24 + n) {
+            let chain_id = ChainId(record[..16].try_into().expect("16 bytes"));
+            match table.ids.last() {
+                Some(last) if *last == chain_id => return Err(TableError::Duplicate(chain_id)),
+                Some(last) if *last > chain_id => return Err(TableError::Unordered),
+                _ => {}
+            }
+            table.ids.push(chain_id);
+            table.indices.push(u64::from_be_bytes(record[16..24].try_into().expect("8 bytes")));
+            table.values.extend_from_slice(&record[24..]);
+        }
+        Ok(table)
+    }
+}
+
+impl<H: Digest + FixedOutputReset> VerifierTable<H> {
+    /// Accept `value` at `index` if it hashes forward to the chain's last accepted value, and
+    /// store it in its place. Returns how many positions the chain advanced.
+    pub fn verify(&mut self, chain_id: &ChainId, index: u64, value: &Output<H>) -> Result<u64, TableError> {
+        let slot = self.ids.binary_search(chain_id).map_err(|_| TableError::UnknownChain)?;
+        let known = self.indices[slot];
+        if index <= known {
+            return Err(TableError::Replay);
+        }
+        if index - known > self.max_gap {
+            return Err(TableError::GapTooLarge);
+        }
+        if !verify::<H>(known, self.value(slot), index, value) {
+            return Err(TableError::Mismatch);
+        }
+        let n = <H as Digest>::output_size();
+        self.indices[slot] = index;
+        self.values[slot * n..(slot + 1) * n].copy_from_slice(value);
+        Ok(index - known)
+    }
+}
+
+impl<H: Digest> crate::version::Versioned for VerifierTable<H> {
+    type Error = TableError;
+
+    fn encode_body(&self, _version: u8) -> Vec<u8> {
+        self.to_bytes()
+    }
+
+    fn decode_body(_version: u8, body: &[u8]) -> Result<Self, TableError> {
+        Self::from_bytes(body)
+    }
+}
+
+#[test]
+fn test_verifier_table() {
+    use crate::HashChain;
+    use sha2::Sha256;
+
+    let mut chains: Vec<_> = (0..4u8).map(|n| (ChainId([3 - n; 16]), HashChain::<Sha256>::new(8, n as u64).unwrap())).collect();
+    let mut table = VerifierTable::<Sha256>::new();
+    table.insert(chains[0].0, chains[0].1.anchor()).unwrap();
+    assert_eq!(table.insert(chains[0].0, chains[0].1.anchor()), Err(TableError::Duplicate(chains[0].0)));
+    table.extend_anchors(chains[1..].iter().map(|(id, chain)| (*id, *chain.anchor()))).unwrap();
+    assert_eq!(table.len(), 4);
+    assert!(table.records().map(|(id, _, _)| id).eq((0..4u8).map(|n| ChainId([n; 16]))));
+
+    let (id, chain) = &mut chains[2];
+    let (_, first) = chain.next().unwrap();
+    let (index, value) = chain.nth(1).map(|(index, value)| (index, value.to_array())).unwrap();
+    assert_eq!(table.verify(id, index, &value), Ok(3));
+    assert_eq!(table.verify(id, 1, &first.to_array()), Err(TableError::Replay));
+    assert_eq!(table.verify(id, u64::MAX, &value), Err(TableError::GapTooLarge));
+    let mut strict = table.clone().with_max_gap(2);
+    let (far, far_value) = chain.nth(2).map(|(index, value)| (index, value.to_array())).unwrap();
+    assert_eq!(strict.verify(id, far, &far_value), Err(TableError::GapTooLarge));
+    assert_eq!(strict.get(id).unwrap().0, 3);
+    assert_eq!(table.verify(&ChainId([9; 16]), index, &value), Err(TableError::UnknownChain));
+    assert_eq!(table.verify(&chains[1].0, index, &value), Err(TableError::Mismatch));
+
+    let saved = table.to_bytes();
+    assert_eq!(saved.len(), 8 + 4 * (24 + 32));
+    let mut loaded = VerifierTable::<Sha256>::from_bytes(&saved).unwrap();
+    assert_eq!(loaded.get(&chains[2].0), Some((3, &value)));
+    assert_eq!(VerifierTable::<Sha256>::from_bytes(&saved[..saved.len() - 1]).err(), Some(TableError::WrongLength));
+    assert!(loaded.remove(&chains[2].0) && !loaded.remove(&chains[2].0));
+    assert_eq!(loaded.get(&chains[3].0), Some((0, chains[3].1.anchor())));
+}
@@ -0,0 +1,87 @@
+//! A process-wide hook called on hash evaluations, for power profiling on embedded targets and
+//! cost attribution in profilers.
+//!
+//! [`set_hook`] registers a plain function that is called on every `every`-th hash of each
+//! [`Phase`] with the number of hashes of that phase so far. Without a hook each hash costs one
+//! relaxed atomic increment and load. The counters are `usize` and wrap, which on 32 bit targets
+//! happens after about four billion hashes.
+
+use core::sync::atomic::{AtomicPtr, AtomicUsize, Ordering};
+
+/// What a hash was evaluated for.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Phase {
+    /// Chain setup, from the seed down to the anchor.
+    Setup,
+    /// Moving pebbles while disclosing.
+    Traversal,
+    /// Hashing disclosed values forward, in [`verify`](crate::verify),
+    /// [`verify_batch`](crate::verify_batch) and [`hash_forward`](crate::hash_forward).
+    Verification,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct HashEvent {
+    pub phase: Phase,
+    /// The hashes of `phase` since the hook was set, this one included.
+    pub count: usize,
+}
+
+static HOOK: AtomicPtr<()> = AtomicPtr::new(core::ptr::null_mut());
+static EVERY: AtomicUsize = AtomicUsize::new(1);
+static COUNTS: [AtomicUsize; 3] = [AtomicUsize::new(0), AtomicUsize::new(0), AtomicUsize::new(0)];
+
+/// Call `hook` on every `every`-th hash of each phase, replacing any previous hook and resetting
+/// the counters. The hook runs on the hashing thread, in the middle of the operation, so it
+/// should only record what it is given.
+pub fn set_hook(hook: fn(HashEvent), every: usize) {
+    EVERY.store(every.max(1), Ordering::Relaxed);
+    for count in &COUNTS {
+        count.store(0, Ordering::Relaxed);
+    }
+    HOOK.store(hook as *mut (), Ordering::Release);
+}
+
+/// Stop calling the hook. The counters keep counting.
+pub fn clear_hook() {
+    HOOK.store(core::ptr::null_mut(), Ordering::Release);
+}
+
+/// The hashes of `phase` since the hook was last set.
+pub fn count(phase: Phase) -> usize {
+    COUNTS[phase as usize].load(Ordering::Relaxed)
+}
+
+pub(crate) fn record(phase: Phase) {
+    let count = COUNTS[phase as usize].fetch_add(1, Ordering::Relaxed).wrapping_add(1);
+    let hook = HOOK.load(Ordering::Acquire);
+    if !hook.is_null() && count.is_multiple_of(EVERY.load(Ordering::Relaxed)) {
+        // SAFETY: the only non-null values ever stored are `fn(HashEvent)` pointers
+        let hook = unsafe { core::mem::transmute::<*mut (), fn(HashEvent)>(hook) };
+        hook(HashEvent { phase, count });
+    }
+}
+
+#[test]
+fn test_telemetry_hook() {
+    use crate::{verify, HashChain};
+    use sha2::Sha256;
+
+    // other tests hash concurrently, so only lower bounds hold
+    static CALLS: [AtomicUsize; 3] = [AtomicUsize::new(0), AtomicUsize::new(0), AtomicUsize::new(0)];
+    fn hook(event: HashEvent) {
+        assert!(event.count.is_multiple_of(4));
+        CALLS[event.phase as usize].fetch_add(1, Ordering::Relaxed);
+    }
+
+    set_hook(hook, 4);
+    let mut chain = HashChain::<Sha256>::new(64, 1).unwrap();
+    let values: Vec<_> = chain.by_ref().take(8).collect();
+    assert!(verify::<Sha256>(0, chain.anchor(), 8, &values[7].1));
+    clear_hook();
+    assert!(count(Phase::Setup) >= 64);
+    assert!(count(Phase::Traversal) >= 8);
+    assert!(count(Phase::Verification) >= 8);
+    assert!(CALLS[Phase::Setup as usize].load(Ordering::Relaxed) >= 16);
+    assert!(CALLS[Phase::Verification as usize].load(Ordering::Relaxed) >= 2);
+}
@@ -0,0 +1,550 @@
+//! TESLA broadcast authentication (Perrig et al., RFC 4082).
+//!
+//! Time is split into intervals, and interval `i` is keyed by the chain value at position `i`.
+//! The sender MACs each packet with a key derived from its interval's chain value and discloses
+//! the chain value from `lag` intervals earlier in every packet. Receivers buffer packets until
+//! their key is disclosed, check the disclosed key against the chain, and only then release the
+//! packets. The security condition makes sure a packet only gets buffered if its key cannot have
+//! been disclosed yet when it arrived, which is what keeps an attacker from forging MACs with
+//! already public keys. The interval and disclosure bookkeeping is a [`KeySchedule`].
+//!
+//! Times are given as [`Duration`]s since an epoch shared by sender and receiver (typically the
+//! Unix epoch).
+
+use crate::keyschedule::{KeySchedule, KeyScheduleError};
+use crate::{hash_forward, HashChain};
+use crate::value::ChainValue;
+use digest::core_api::BlockSizeUser;
+use digest::generic_array::GenericArray;
+use digest::{Digest, FixedOutputReset, Mac, OutputSizeUser};
+use hmac::SimpleHmac;
+use std::collections::VecDeque;
+use std::error::Error;
+use std::fmt::{self, Display};
+use std::time::Duration;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum TeslaError {
+    /// The current time lies before the first interval.
+    NotStarted,
+    /// The chain has no key for the current interval.
+    Exhausted,
+    /// The packet's key may already have been disclosed when it arrived.
+    Unsafe,
+    /// The packet claims an interval the sender cannot have reached yet.
+    FromTheFuture,
+    /// The disclosed key does not belong to the chain.
+    InvalidKey,
+    /// A bootstrap message failed authentication.
+    BadBootstrap,
+    /// The receive buffer is full and the eviction policy rejected the packet.
+    BufferFull,
+    /// A time synchronization reply failed authentication or took too long.
+    BadTimeSync,
+}
+
+impl Display for TeslaError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            TeslaError::NotStarted => write!(f, "first interval has not started"),
+            TeslaError::Exhausted => write!(f, "no key left for the current interval"),
+            TeslaError::Unsafe => write!(f, "packet key may already be disclosed"),
+            TeslaError::FromTheFuture => write!(f, "packet interval lies in the future"),
+            TeslaError::InvalidKey => write!(f, "disclosed key does not verify against the chain"),
+            TeslaError::BadBootstrap => write!(f, "bootstrap message failed authentication"),
+            TeslaError::BufferFull => write!(f, "receive buffer full"),
+            TeslaError::BadTimeSync => write!(f, "time synchronization failed"),
+        }
+    }
+}
+
+impl Error for TeslaError {}
+
+impl From<KeyScheduleError> for TeslaError {
+    fn from(error: KeyScheduleError) -> Self {
+        match error {
+            KeyScheduleError::FromTheFuture => TeslaError::FromTheFuture,
+            KeyScheduleError::Disclosed => TeslaError::Unsafe,
+        }
+    }
+}
+
+/// The division of time into intervals: interval 1 starts at `start`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Schedule {
+    pub start: Duration,
+    pub interval: Duration,
+}
+
+impl Schedule {
+    /// The interval containing `time`, or `None` before the start.
+    pub fn interval_at(&self, time: Duration) -> Option<u64> {
+        let elapsed = time.checked_sub(self.start)?;
+        Some((elapsed.as_nanos() / self.interval.as_nanos()) as u64 + 1)
+    }
+}
+
+/// A signed clock difference: how far the sender's clock is ahead of ours (negative when it is
+/// behind).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct ClockOffset {
+    nanos: i128,
+}
+
+impl ClockOffset {
+    pub fn ahead(by: Duration) -> Self {
+        ClockOffset { nanos: by.as_nanos() as i128 }
+    }
+
+    pub fn behind(by: Duration) -> Self {
+        ClockOffset { nanos: -(by.as_nanos() as i128) }
+    }
+
+    /// Shift a local time by the offset, saturating at zero.
+    pub fn apply(&self, local: Duration) -> Duration {
+        let nanos = (local.as_nanos() as i128 + self.nanos).max(0);
+        Duration::from_nanos(nanos as u64)
+    }
+}
+
+/// What a receiver knows about the sender's clock.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct Timing {
+    /// Upper bound on the sender's clock relative to ours, e.g. from a [`TimeSync`] handshake.
+    pub offset: ClockOffset,
+    /// Additional tolerance for clock drift since the offset was measured.
+    pub max_skew: Duration,
+    /// The longest round trip accepted for a time synchronization.
+    pub max_delay: Duration,
+}
+
+impl Timing {
+    /// The latest time the sender's clock can show when ours shows `now`.
+    pub fn sender_upper_bound(&self, now: Duration) -> Duration {
+        self.offset.apply(now) + self.max_skew
+    }
+}
+
+/// A receiver's time synchronization request: a fresh nonce, sent at local time `sent_at`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TimeSync {
+    pub nonce: Vec<u8>,
+    pub sent_at: Duration,
+}
+
+/// The sender's answer to a [`TimeSync`]: its clock when the request arrived, authenticated
+/// together with the nonce.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TimeSyncReply {
+    pub sender_time: Duration,
+    pub signature: Vec<u8>,
+}
+
+fn time_sync_message(nonce: &[u8], sender_time: Duration) -> Vec<u8> {
+    let mut message = b"tesla time sync".to_vec();
+    message.extend_from_slice(&(nonce.len() as u32).to_be_bytes());
+    message.extend_from_slice(nonce);
+    message.extend_from_slice(&(sender_time.as_nanos() as u64).to_be_bytes());
+    message
+}
+
+impl TimeSyncReply {
+    /// The sender side: answer `request` at sender time `now`, signing with `sign`.
+    pub fn new<F: FnOnce(&[u8]) -> Vec<u8>>(request_nonce: &[u8], now: Duration, sign: F) -> Self {
+        TimeSyncReply { sender_time: now, signature: sign(&time_sync_message(request_nonce, now)) }
+    }
+}
+
+impl TimeSync {
+    pub fn new(nonce: &[u8], sent_at: Duration) -> Self {
+        TimeSync { nonce: nonce.to_vec(), sent_at }
+    }
+
+    /// Complete the handshake when `reply` arrives at local time `now`. The sender's clock read
+    /// `sender_time` no earlier than our `sent_at`, so `sender_time - sent_at` bounds how far
+    /// ahead it can be. Replies slower than `timing.max_delay` are rejected, as are replies
+    /// failing `verify(message, signature)`. Returns `timing` with the measured offset.
+    pub fn complete<F: Fn(&[u8], &[u8]) -> bool>(&self, reply: &TimeSyncReply, now: Duration, timing: Timing, verify: F) -> Result<Timing, TeslaError> {
+        if !verify(&time_sync_message(&self.nonce, reply.sender_time), &reply.signature) {
+            return Err(TeslaError::BadTimeSync);
+        }
+        if now < self.sent_at || now - self.sent_at > timing.max_delay {
+            return Err(TeslaError::BadTimeSync);
+        }
+        let offset = ClockOffset { nanos: reply.sender_time.as_nanos() as i128 - self.sent_at.as_nanos() as i128 };
+        Ok(Timing { offset, ..timing })
+    }
+}
+
+/// A broadcast packet.
+pub struct Packet<H: OutputSizeUser> {
+    pub interval: u64,
+    pub payload: Vec<u8>,
+    pub mac: Vec<u8>,
+    /// The chain value of an earlier interval, once there is one to disclose.
+    pub disclosed: Option<(u64, GenericArray<u8, H::OutputSize>)>,
+}
+
+/// Why a [`Packet`] could not be decoded from the wire.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WireError {
+    /// A reserved flag bit is set.
+    UnknownFlags(u8),
+    /// The payload is longer than the decoder accepts.
+    TooLarge(usize),
+}
+
+impl Display for WireError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            WireError::UnknownFlags(flags) => write!(f, "unknown flags {:#04x}", flags),
+            WireError::TooLarge(len) => write!(f, "payload of {} bytes too large", len),
+        }
+    }
+}
+
+impl Error for WireError {}
+
+const FLAG_DISCLOSED: u8 = 0x01;
+
+impl<H: OutputSizeUser> Packet<H> {
+    /// Append the wire encoding: a flags byte, the interval (u64 big-endian), the MAC length (u8)
+    /// and MAC, the disclosed key index (u64 big-endian) and key if present, then the payload
+    /// length (u32 big-endian) and payload.
+    pub fn encode(&self, out: &mut Vec<u8>) {
+        out.push(if self.disclosed.is_some() { FLAG_DISCLOSED } else { 0 });
+        out.extend_from_slice(&self.interval.to_be_bytes());
+        out.push(self.mac.len() as u8);
+        out.extend_from_slice(&self.mac);
+        if let Some((index, key)) = &self.disclosed {
+            out.extend_from_slice(&index.to_be_bytes());
+            out.extend_from_slice(key);
+        }
+        out.extend_from_slice(&(self.payload.len() as u32).to_be_bytes());
+        out.extend_from_slice(&self.payload);
+    }
+
+    /// Decode one packet from the front of `buf`, returning it with the number of bytes used, or
+    /// `None` if `buf` does not hold a whole packet yet.
+    pub fn decode(buf: &[u8], max_payload: usize) -> Result<Option<(Self, usize)>, WireError> {
+        fn take<'a>(buf: &'a [u8], at: &mut usize, len: usize) -> Option<&'a [u8]> {
+            let bytes = buf.get(*at..*at + len)?;
+            *at += len;
+            Some(bytes)
+        }
+        let mut at = 0;
+        let Some(&[flags]) = take(buf, &mut at, 1) else { return Ok(None) };
+        if flags & !FLAG_DISCLOSED != 0 {
+            return Err(WireError::UnknownFlags(flags));
+        }
+        let Some(interval) = take(buf, &mut at, 8) else { return Ok(None) };
+        let interval = u64::from_be_bytes(interval.try_into().expect("8 bytes"));
+        let Some(&[mac_len]) = take(buf, &mut at, 1) else { return Ok(None) };
+        let Some(mac) = take(buf, &mut at, mac_len as usize) else { return Ok(None) };
+        let mac = mac.to_vec();
+        let disclosed = if flags & FLAG_DISCLOSED != 0 {
+            let Some(index) = take(buf, &mut at, 8) else { return Ok(None) };
+            let index = u64::from_be_bytes(index.try_into().expect("8 bytes"));
+            let Some(key) = take(buf, &mut at, H::output_size()) else { return Ok(None) };
+            Some((index, GenericArray::clone_from_slice(key)))
+        } else {
+            None
+        };
+        let Some(len) = take(buf, &mut at, 4) else { return Ok(None) };
+        let len = u32::from_be_bytes(len.try_into().expect("4 bytes")) as usize;
+        if len > max_payload {
+            return Err(WireError::TooLarge(len));
+        }
+        let Some(payload) = take(buf, &mut at, len) else { return Ok(None) };
+        Ok(Some((Packet { interval, payload: payload.to_vec(), mac, disclosed }, at)))
+    }
+}
+
+impl<H: OutputSizeUser> Clone for Packet<H> {
+    fn clone(&self) -> Self {
+        Packet { interval: self.interval, payload: self.payload.clone(), mac: self.mac.clone(), disclosed: self.disclosed.clone() }
+    }
+}
+
+/// A packet whose MAC was checked against a disclosed key.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Authenticated {
+    pub interval: u64,
+    pub payload: Vec<u8>,
+}
+
+/// The per-interval MAC key, derived from the chain value so the value itself is never used
+/// both as a chain element and as a MAC key.
+pub(crate) fn mac_key<H: Digest + BlockSizeUser>(key: &[u8]) -> Vec<u8> {
+    let mut prf = <SimpleHmac<H> as Mac>::new_from_slice(key).expect("HMAC accepts any key length");
+    prf.update(b"tesla mac key");
+    prf.finalize().into_bytes().to_vec()
+}
+
+pub(crate) fn packet_mac<H: Digest + BlockSizeUser>(key: &[u8], interval: u64, payload: &[u8]) -> SimpleHmac<H> {
+    let mut mac = <SimpleHmac<H> as Mac>::new_from_slice(&mac_key::<H>(key)).expect("HMAC accepts any key length");
+    mac.update(&interval.to_be_bytes());
+    mac.update(payload);
+    mac
+}
+
+/// The broadcasting side.
+pub struct Sender<H: Digest + FixedOutputReset> {
+    chain: HashChain<H>,
+    keys: KeySchedule,
+    /// The keys of the current interval and the `lag` intervals before it, oldest first.
+    recent: VecDeque<(u64, ChainValue<H>)>,
+}
+
+impl<H: Digest + FixedOutputReset + BlockSizeUser> Sender<H> {
+    /// Broadcast with keys from `chain`, disclosing each key `lag` intervals after its interval.
+    pub fn new(chain: HashChain<H>, schedule: Schedule, lag: u64) -> Self {
+        Self::with_key_schedule(chain, KeySchedule::new(schedule, lag))
+    }
+
+    pub fn with_key_schedule(chain: HashChain<H>, keys: KeySchedule) -> Self {
+        Sender { chain, keys, recent: VecDeque::new() }
+    }
+
+    pub fn key_schedule(&self) -> &KeySchedule {
+        &self.keys
+    }
+
+    /// The anchor receivers must be bootstrapped with.
+    pub fn anchor(&self) -> &GenericArray<u8, H::OutputSize> {
+        self.chain.anchor()
+    }
+
+    /// Authenticate `payload` for broadcast at time `now`.
+    pub fn send(&mut self, payload: &[u8], now: Duration) -> Result<Packet<H>, TeslaError> {
+        let interval = self.keys.interval_at(now).ok_or(TeslaError::NotStarted)?;
+        let index = self.keys.key_index(interval);
+        if index > self.chain.length() {
+            return Err(TeslaError::Exhausted);
+        }
+        while self.chain.position() < index {
+            let key = self.chain.next().ok_or(TeslaError::Exhausted)?;
+            self.recent.push_back(key);
+            if self.recent.len() > self.keys.retained() {
+                self.recent.pop_front();
+            }
+        }
+        let (_, key) = self.recent.back().expect("current key present");
+        let mac = packet_mac::<H>(key, interval, payload).finalize().into_bytes().to_vec();
+        let due = self.keys.disclosed_in(interval);
+        let disclosed = self.recent.iter().find(|(i, _)| Some(*i) == due).map(|(i, key)| (*i, key.to_array()));
+        Ok(Packet { interval, payload: payload.to_vec(), mac, disclosed })
+    }
+}
+
+/// The receiving side.
+pub struct Receiver<H: Digest + FixedOutputReset> {
+    keys: KeySchedule,
+    timing: Timing,
+    key_index: u64,
+    key: GenericArray<u8, H::OutputSize>,
+    buffer: Vec<Packet<H>>,
+}
+
+impl<H: Digest + FixedOutputReset + BlockSizeUser> Receiver<H> {
+    /// Bootstrap a receiver with the sender's anchor and parameters. `max_offset` bounds how far
+    /// the sender's clock may be ahead of ours.
+    pub fn new(anchor: GenericArray<u8, H::OutputSize>, schedule: Schedule, lag: u64, max_offset: Duration) -> Self {
+        Self::with_timing(anchor, schedule, lag, Timing { offset: ClockOffset::ahead(max_offset), ..Timing::default() })
+    }
+
+    /// Bootstrap a receiver whose knowledge of the sender's clock is described by `timing`.
+    pub fn with_timing(anchor: GenericArray<u8, H::OutputSize>, schedule: Schedule, lag: u64, timing: Timing) -> Self {
+        Self::with_key_schedule(anchor, KeySchedule::new(schedule, lag), timing)
+    }
+
+    pub fn with_key_schedule(anchor: GenericArray<u8, H::OutputSize>, keys: KeySchedule, timing: Timing) -> Self {
+        Receiver { keys, timing, key_index: 0, key: anchor, buffer: Vec::new() }
+    }
+
+    /// Replace the clock bounds, e.g. after a new [`TimeSync`].
+    pub fn set_timing(&mut self, timing: Timing) {
+        self.timing = timing;
+    }
+
+    /// The highest interval whose key has been verified.
+    pub fn key_index(&self) -> u64 {
+        self.key_index
+    }
+
+    /// The number of packets waiting for their key.
+    pub fn buffered(&self) -> usize {
+        self.buffer.len()
+    }
+
+    /// The security condition: the latest interval the sender may be in when we receive at `now`
+    /// must not yet be the one disclosing the packet's key.
+    pub fn is_safe(&self, interval: u64, now: Duration) -> Result<(), TeslaError> {
+        Ok(self.keys.is_safe(interval, self.timing.sender_upper_bound(now))?)
+    }
+
+    /// Process a packet received at `now`, returning every packet that became authenticated.
+    /// Packets failing the security condition, or claiming an interval whose key is already
+    /// verified, are dropped with an error; a disclosed key is still used if it verifies.
+    pub fn receive(&mut self, packet: Packet<H>, now: Duration) -> Result<Vec<Authenticated>, TeslaError> {
+        let safe = self.is_safe(packet.interval, now);
+        let mut released = Vec::new();
+        if let Some((index, key)) = &packet.disclosed {
+            released = self.receive_key(*index, key, now)?;
+        }
+        safe?;
+        // the anchor, or any key already verified, is public and authenticates nothing
+        if packet.interval == 0 || self.keys.key_index(packet.interval) <= self.key_index {
+            return Err(TeslaError::Unsafe);
+        }
+        self.buffer.push(packet);
+        released.extend(self.release());
+        Ok(released)
+    }
+
+    /// Process a key received at `now` outside of a packet, returning every packet that became
+    /// authenticated. Keys older than the last verified one are ignored, and keys the sender
+    /// cannot have disclosed yet are refused before hashing.
+    pub fn receive_key(&mut self, index: u64, key: &GenericArray<u8, H::OutputSize>, now: Duration) -> Result<Vec<Authenticated>, TeslaError> {
+        if index <= self.key_index {
+            return Ok(Vec::new());
+        }
+        let latest = self.keys.interval_at(self.timing.sender_upper_bound(now)).and_then(|interval| self.keys.disclosed_in(interval));
+        if latest.is_none_or(|latest| index > latest) {
+            return Err(TeslaError::FromTheFuture);
+        }
+        if hash_forward::<H>(key, index - self.key_index) != self.key {
+            return Err(TeslaError::InvalidKey);
+        }
+        self.key_index = index;
+        self.key = key.clone();
+        Ok(self.release())
+    }
+
+    /// Authenticate and remove the buffered packets whose key is now known, dropping forgeries.
+    fn release(&mut self) -> Vec<Authenticated> {
+        let (ready, waiting): (Vec<_>, Vec<_>) = self.buffer.drain(..).partition(|p| self.keys.key_index(p.interval) <= self.key_index);
+        self.buffer = waiting;
+        ready.into_iter().filter_map(|packet| {
+            let key = hash_forward::<H>(&self.key, self.key_index - self.keys.key_index(packet.interval));
+            packet_mac::<H>(&key, packet.interval, &packet.payload).verify_slice(&packet.mac).ok()?;
+            Some(Authenticated { interval: packet.interval, payload: packet.payload })
+        }).collect()
+    }
+}
+
+#[test]
+fn test_tesla_broadcast() {
+    use sha2::Sha256;
+
+    let schedule = Schedule { start: Duration::from_secs(100), interval: Duration::from_secs(1) };
+    let at = |ms: u64| Duration::from_millis(100_000 + ms);
+    let mut sender = Sender::new(HashChain::<Sha256>::new(16, 3).unwrap(), schedule, 2);
+    let mut receiver = Receiver::<Sha256>::new(*sender.anchor(), schedule, 2, Duration::from_millis(100));
+
+    // interval 1: nothing disclosed yet, the packet is buffered
+    let first = sender.send(b"one", at(100)).unwrap();
+    assert!(first.disclosed.is_none());
+    assert_eq!(receiver.receive(first, at(150)), Ok(vec![]));
+
+    // a forgery for interval 2, and a genuine packet
+    let mut forged = sender.send(b"two", at(1100)).unwrap();
+    forged.payload = b"evil".to_vec();
+    assert_eq!(receiver.receive(forged, at(1150)), Ok(vec![]));
+    assert_eq!(receiver.receive(sender.send(b"two", at(1200)).unwrap(), at(1250)), Ok(vec![]));
+    assert_eq!(receiver.buffered(), 3);
+
+    // interval 4 discloses key 2, releasing the packets of intervals 1 and 2
+    let fourth = sender.send(b"four", at(3100)).unwrap();
+    assert_eq!(fourth.disclosed.as_ref().unwrap().0, 2);
+    let released = receiver.receive(fourth.clone(), at(3150)).unwrap();
+    assert_eq!(released, vec![
+        Authenticated { interval: 1, payload: b"one".to_vec() },
+        Authenticated { interval: 2, payload: b"two".to_vec() },
+    ]);
+    assert_eq!(receiver.key_index(), 2);
+    assert_eq!(receiver.buffered(), 1);
+
+    // replaying the interval 4 packet once its key is public fails the security condition
+    assert_eq!(receiver.receive(fourth, at(6000)), Err(TeslaError::Unsafe));
+}
+
+#[test]
+fn test_tesla_rejects_public_keys() {
+    use sha2::Sha256;
+
+    let schedule = Schedule { start: Duration::from_secs(100), interval: Duration::from_secs(1) };
+    let at = |ms: u64| Duration::from_millis(100_000 + ms);
+    let mut sender = Sender::new(HashChain::<Sha256>::new(16, 3).unwrap(), schedule, 2);
+    let anchor = *sender.anchor();
+    let mut receiver = Receiver::<Sha256>::new(anchor, schedule, 2, Duration::from_millis(100));
+
+    // anyone can MAC interval 0 with the public anchor
+    let mac = packet_mac::<Sha256>(&anchor, 0, b"evil").finalize().into_bytes().to_vec();
+    let forged = Packet::<Sha256> { interval: 0, payload: b"evil".to_vec(), mac, disclosed: None };
+    assert_eq!(receiver.receive(forged, at(150)), Err(TeslaError::Unsafe));
+    assert_eq!(receiver.buffered(), 0);
+
+    // a key index far past what the sender can have disclosed is refused without hashing
+    assert_eq!(receiver.receive_key(u64::MAX, &anchor, at(3150)), Err(TeslaError::FromTheFuture));
+    assert_eq!(receiver.receive_key(3, &anchor, at(3150)), Err(TeslaError::FromTheFuture));
+
+    // once key 2 is verified, packets claiming intervals 1 or 2 are refused
+    sender.send(b"one", at(100)).unwrap();
+    sender.send(b"two", at(1100)).unwrap();
+    let fourth = sender.send(b"four", at(3100)).unwrap();
+    let (index, key) = fourth.disclosed.unwrap();
+    assert_eq!(receiver.receive_key(index, &key, at(3150)), Ok(vec![]));
+    let mac = packet_mac::<Sha256>(&key, 2, b"late").finalize().into_bytes().to_vec();
+    let late = Packet::<Sha256> { interval: 2, payload: b"late".to_vec(), mac, disclosed: None };
+    assert_eq!(receiver.receive(late, at(1150)), Err(TeslaError::Unsafe));
+}
+
+#[test]
+fn test_time_sync_and_skew() {
+    use sha2::Sha256;
+
+    let sign = |message: &[u8]| Sha256::new_with_prefix(b"sender key").chain_update(message).finalize().to_vec();
+    let check = |message: &[u8], signature: &[u8]| sign(message) == signature;
+    let tolerance = Timing { offset: ClockOffset::default(), max_skew: Duration::from_millis(50), max_delay: Duration::from_millis(500) };
+
+    // our clock is 300ms ahead of the sender's; the reply takes 200ms round trip
+    let request = TimeSync::new(b"n1", Duration::from_millis(10_300));
+    let reply = TimeSyncReply::new(&request.nonce, Duration::from_millis(10_100), sign);
+    let timing = request.complete(&reply, Duration::from_millis(10_500), tolerance, check).unwrap();
+    assert_eq!(timing.offset, ClockOffset::behind(Duration::from_millis(200)));
+    assert_eq!(request.complete(&reply, Duration::from_millis(11_000), tolerance, check), Err(TeslaError::BadTimeSync));
+    assert_eq!(TimeSync::new(b"n2", request.sent_at).complete(&reply, Duration::from_millis(10_500), tolerance, check), Err(TeslaError::BadTimeSync));
+
+    let schedule = Schedule { start: Duration::ZERO, interval: Duration::from_secs(1) };
+    let mut sender = Sender::new(HashChain::<Sha256>::new(32, 1).unwrap(), schedule, 1);
+    let mut receiver = Receiver::<Sha256>::with_timing(*sender.anchor(), schedule, 1, timing);
+
+    // sent at sender time 11.9s (interval 12), received at our 12.1s: the sender's clock shows
+    // at most 11.95s, so interval 12's key is not yet out
+    let packet = sender.send(b"x", Duration::from_millis(11_900)).unwrap();
+    assert_eq!(receiver.receive(packet.clone(), Duration::from_millis(12_100)), Ok(vec![]));
+    // 60ms later the sender may have reached interval 13 and disclosed the key
+    assert_eq!(receiver.is_safe(packet.interval, Duration::from_millis(12_160)), Err(TeslaError::Unsafe));
+}
+
+#[test]
+fn test_packet_wire_roundtrip() {
+    use sha2::Sha256;
+
+    let schedule = Schedule { start: Duration::ZERO, interval: Duration::from_secs(1) };
+    let mut sender = Sender::new(HashChain::<Sha256>::new(8, 2).unwrap(), schedule, 1);
+    let mut wire = Vec::new();
+    sender.send(b"first", Duration::from_millis(100)).unwrap().encode(&mut wire);
+    sender.send(b"second", Duration::from_millis(1100)).unwrap().encode(&mut wire);
+
+    let (first, used) = Packet::<Sha256>::decode(&wire, 1024).unwrap().unwrap();
+    assert!(first.disclosed.is_none());
+    let (second, rest) = Packet::<Sha256>::decode(&wire[used..], 1024).unwrap().unwrap();
+    assert_eq!(used + rest, wire.len());
+    assert_eq!((second.interval, second.payload.as_slice()), (2, &b"second"[..]));
+    assert_eq!(second.disclosed.unwrap().0, 1);
+
+    assert!(Packet::<Sha256>::decode(&wire[..used - 1], 1024).unwrap().is_none());
+    assert_eq!(Packet::<Sha256>::decode(&wire, 4).err(), Some(WireError::TooLarge(5)));
+}
@@ -0,0 +1,134 @@
+//! Time-lock puzzles: a payload encrypted under a key that takes about `steps` sequential hashes
+//! of a public challenge to reach.
+//!
+//! The key is `H^steps(H("timelock" || challenge))`. From it HKDF-SHA-256 derives an encryption
+//! key, whose HMAC-SHA-256 blocks over a counter are the keystream, and a MAC key, whose
+//! HMAC-SHA-256 over the ciphertext is the tag. Anyone can open the puzzle once they have walked
+//! the chain, and no amount of parallel hardware helps, but unlike RSA time-lock puzzles there
+//! is no trapdoor: locking costs the sender the same walk, so puzzles are prepared ahead of time.
+//!
+//! [`calibrate`] turns a delay into a number of steps by measuring how fast this host hashes.
+//! The delay then holds against a recipient with about the same single-core hash rate; a faster
+//! one opens the puzzle sooner.
+
+use crate::hash_forward;
+use digest::{Digest, FixedOutputReset, Mac, Output};
+use hkdf::Hkdf;
+use hmac::Hmac;
+use sha2::Sha256;
+use std::error::Error;
+use std::fmt::{self, Display};
+use std::time::{Duration, Instant};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TimelockError {
+    /// The ciphertext, challenge or step count was changed, or the puzzle was locked with
+    /// another hash function.
+    Tampered,
+}
+
+impl Display for TimelockError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            TimelockError::Tampered => write!(f, "time-lock puzzle does not authenticate"),
+        }
+    }
+}
+
+impl Error for TimelockError {}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TimelockPuzzle {
+    pub challenge: Vec<u8>,
+    pub steps: u64,
+    pub ciphertext: Vec<u8>,
+    pub tag: [u8; 32],
+}
+
+/// The key at the end of the walk, what [`lock`] and [`open`] spend their time on.
+pub fn unlock_key<H: Digest + FixedOutputReset>(challenge: &[u8], steps: u64) -> Output<H> {
+    hash_forward::<H>(&H::new_with_prefix(b"timelock").chain_update(challenge).finalize(), steps)
+}
+
+/// XOR the keystream into `data` and return the tag over the result, or over `data` as it
+/// was when decrypting.
+fn seal(key: &[u8], data: &mut [u8], encrypt: bool) -> [u8; 32] {
+    let hkdf = Hkdf::<Sha256>::new(None, key);
+    let (mut encryption_key, mut mac_key) = ([0; 32], [0; 32]);
+    hkdf.expand(b"timelock encryption", &mut encryption_key).expect("32 bytes is a valid output length");
+    hkdf.expand(b"timelock mac", &mut mac_key).expect("32 bytes is a valid output length");
+    let mut mac = <Hmac<Sha256> as Mac>::new_from_slice(&mac_key).expect("HMAC takes any key length");
+    if !encrypt {
+        mac.update(data);
+    }
+    for (counter, chunk) in data.chunks_mut(32).enumerate() {
+        let mut block = <Hmac<Sha256> as Mac>::new_from_slice(&encryption_key).expect("HMAC takes any key length");
+        block.update(&(counter as u64).to_be_bytes());
+        for (byte, key) in chunk.iter_mut().zip(block.finalize().into_bytes()) {
+            *byte ^= key;
+        }
+    }
+    if encrypt {
+        mac.update(data);
+    }
+    mac.finalize().into_bytes().into()
+}
+
+/// Encrypt `payload` so that opening it takes `steps` hashes of `challenge`, which this walks
+/// once itself.
+pub fn lock<H: Digest + FixedOutputReset>(payload: &[u8], challenge: &[u8], steps: u64) -> TimelockPuzzle {
+    let mut ciphertext = payload.to_vec();
+    let tag = seal(&unlock_key::<H>(challenge, steps), &mut ciphertext, true);
+    TimelockPuzzle { challenge: challenge.to_vec(), steps, ciphertext, tag }
+}
+
+/// Walk the puzzle's chain and decrypt the payload.
+pub fn open<H: Digest + FixedOutputReset>(puzzle: &TimelockPuzzle) -> Result<Vec<u8>, TimelockError> {
+    let mut payload = puzzle.ciphertext.clone();
+    let tag = seal(&unlock_key::<H>(&puzzle.challenge, puzzle.steps), &mut payload, false);
+    // the tag is public, so comparing it in variable time reveals nothing
+    if tag != puzzle.tag {
+        return Err(TimelockError::Tampered);
+    }
+    Ok(payload)
+}
+
+/// The sequential hashes per second this host manages with `H`, measured for about `sample`.
+pub fn measure_hash_rate<H: Digest + FixedOutputReset>(sample: Duration) -> f64 {
+    const BATCH: u64 = 4096;
+    let started = Instant::now();
+    let mut value = Output::<H>::default();
+    let mut hashes = 0;
+    while hashes == 0 || started.elapsed() < sample {
+        value = hash_forward::<H>(&value, BATCH);
+        hashes += BATCH;
+    }
+    std::hint::black_box(value);
+    hashes as f64 / started.elapsed().as_secs_f64()
+}
+
+/// The steps that take `delay` at `hashes_per_second`, at least 1.
+pub fn steps_for(delay: Duration, hashes_per_second: f64) -> u64 {
+    ((delay.as_secs_f64() * hashes_per_second) as u64).max(1)
+}
+
+/// The steps that take `delay` on this host, measuring its hash rate for `sample` first.
+pub fn calibrate<H: Digest + FixedOutputReset>(delay: Duration, sample: Duration) -> u64 {
+    steps_for(delay, measure_hash_rate::<H>(sample))
+}
+
+#[test]
+fn test_timelock_roundtrip() {
+    let puzzle = lock::<Sha256>(b"the launch code is 0000, and much longer than one block", b"round 7", 1000);
+    assert_eq!(open::<Sha256>(&puzzle).unwrap(), b"the launch code is 0000, and much longer than one block");
+    assert_ne!(puzzle.ciphertext, b"the launch code is 0000, and much longer than one block");
+    let mut shortened = puzzle.clone();
+    shortened.steps = 999;
+    assert_eq!(open::<Sha256>(&shortened), Err(TimelockError::Tampered));
+    let mut flipped = puzzle;
+    flipped.ciphertext[3] ^= 1;
+    assert_eq!(open::<Sha256>(&flipped), Err(TimelockError::Tampered));
+
+    assert!(measure_hash_rate::<Sha256>(Duration::from_millis(10)) > 0.0);
+    assert_eq!(steps_for(Duration::from_secs(2), 1e6), 2_000_000);
+}
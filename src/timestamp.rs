@@ -0,0 +1,145 @@
+//! Linked timestamping: document digests folded one after another into a chain of links.
+//!
+//! Link `i` is `H("timestamp link" || link_(i-1) || d_i)` over the digest `d_i` of the `i`-th
+//! submitted document, link 0 being an agreed genesis value. A [`Receipt`] holds a document's
+//! index, the link before it, its digest and its own link, so it can be checked on its own. Two
+//! receipts are ordered by the digests submitted between them: folding those into the earlier
+//! receipt's link has to reach the link before the later one, which no one can arrange after the
+//! fact without a hash collision. Publishing the latest link now and then, e.g. in a newspaper,
+//! pins every earlier receipt down against the service itself.
+
+use alloc::vec::Vec;
+use core::error::Error;
+use core::fmt::{self, Display};
+use digest::{Digest, Output, OutputSizeUser};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TimestampError {
+    /// A receipt's link is not the hash of its previous link and digest.
+    InvalidReceipt,
+    /// The receipt said to be earlier does not have a lower index.
+    NotBefore,
+    /// The proof does not hold a digest for every index between the receipts.
+    ProofLength,
+    /// The proof does not link the earlier receipt to the later one.
+    Broken,
+}
+
+impl Display for TimestampError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            TimestampError::InvalidReceipt => write!(f, "receipt is not self-consistent"),
+            TimestampError::NotBefore => write!(f, "receipts are not in the claimed order"),
+            TimestampError::ProofLength => write!(f, "proof has the wrong number of digests"),
+            TimestampError::Broken => write!(f, "proof does not link the receipts"),
+        }
+    }
+}
+
+impl Error for TimestampError {}
+
+fn link<H: Digest>(previous: &Output<H>, document: &Output<H>) -> Output<H> {
+    H::new_with_prefix(b"timestamp link").chain_update(previous).chain_update(document).finalize()
+}
+
+pub struct Receipt<H: OutputSizeUser> {
+    /// The document's position, counting from 1.
+    pub index: u64,
+    pub previous: Output<H>,
+    pub document: Output<H>,
+    pub link: Output<H>,
+}
+
+impl<H: OutputSizeUser> Clone for Receipt<H> {
+    fn clone(&self) -> Self {
+        Receipt { index: self.index, previous: self.previous.clone(), document: self.document.clone(), link: self.link.clone() }
+    }
+}
+
+impl<H: Digest> Receipt<H> {
+    /// Whether the link follows from the previous link and the digest.
+    pub fn is_consistent(&self) -> bool {
+        link::<H>(&self.previous, &self.document) == self.link
+    }
+}
+
+/// The timestamping service's side: every digest submitted and the links over them.
+pub struct TimestampChain<H: OutputSizeUser> {
+    documents: Vec<Output<H>>,
+    /// Link `i` at offset `i`, the genesis value's first.
+    links: Vec<Output<H>>,
+}
+
+impl<H: Digest> TimestampChain<H> {
+    pub fn new(genesis: Output<H>) -> Self {
+        TimestampChain { documents: Vec::new(), links: alloc::vec![genesis] }
+    }
+
+    /// The number of documents submitted.
+    pub fn len(&self) -> u64 {
+        self.documents.len() as u64
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.documents.is_empty()
+    }
+
+    /// The latest link, the one to publish.
+    pub fn head(&self) -> &Output<H> {
+        self.links.last().expect("the genesis value is always there")
+    }
+
+    /// Fold the digest of a document into the chain and return its receipt.
+    pub fn submit(&mut self, document: Output<H>) -> Receipt<H> {
+        let previous = self.head().clone();
+        let next = link::<H>(&previous, &document);
+        self.documents.push(document.clone());
+        self.links.push(next.clone());
+        Receipt { index: self.len(), previous, document, link: next }
+    }
+
+    /// The digests submitted after index `earlier` and before index `later`, which
+    /// [`verify_order`] needs to order their receipts. `None` unless `earlier < later <= len`.
+    pub fn link_proof(&self, earlier: u64, later: u64) -> Option<Vec<Output<H>>> {
+        (earlier < later && later <= self.len()).then(|| self.documents[earlier as usize..later as usize - 1].to_vec())
+    }
+}
+
+/// Check that `earlier` was issued before `later`, given the digests submitted in between.
+pub fn verify_order<H: Digest>(earlier: &Receipt<H>, later: &Receipt<H>, proof: &[Output<H>]) -> Result<(), TimestampError> {
+    if !earlier.is_consistent() || !later.is_consistent() {
+        return Err(TimestampError::InvalidReceipt);
+    }
+    if earlier.index >= later.index {
+        return Err(TimestampError::NotBefore);
+    }
+    if proof.len() as u64 != later.index - earlier.index - 1 {
+        return Err(TimestampError::ProofLength);
+    }
+    let reached = proof.iter().fold(earlier.link.clone(), |previous, document| link::<H>(&previous, document));
+    if reached != later.previous {
+        return Err(TimestampError::Broken);
+    }
+    Ok(())
+}
+
+#[test]
+fn test_linked_timestamps() {
+    use sha2::Sha256;
+
+    let mut service = TimestampChain::<Sha256>::new(Sha256::digest(b"genesis"));
+    let receipts: Vec<_> = ["contract", "invoice", "memo", "patent"].iter().map(|document| service.submit(Sha256::digest(document))).collect();
+    assert_eq!(receipts[3].link, *service.head());
+    let proof = service.link_proof(1, 4).unwrap();
+    assert_eq!(verify_order(&receipts[0], &receipts[3], &proof), Ok(()));
+    assert_eq!(verify_order(&receipts[1], &receipts[2], &service.link_proof(2, 3).unwrap()), Ok(()));
+    assert_eq!(verify_order(&receipts[3], &receipts[0], &proof), Err(TimestampError::NotBefore));
+    assert_eq!(verify_order(&receipts[0], &receipts[3], &proof[1..]), Err(TimestampError::ProofLength));
+
+    // a backdated document does not link up with what came after it
+    let mut backdated = receipts[1].clone();
+    backdated.document = Sha256::digest(b"forged");
+    assert_eq!(verify_order(&receipts[0], &backdated, &[]), Err(TimestampError::InvalidReceipt));
+    backdated.link = link::<Sha256>(&backdated.previous, &backdated.document);
+    assert_eq!(verify_order(&backdated, &receipts[3], &service.link_proof(2, 4).unwrap()), Err(TimestampError::Broken));
+}
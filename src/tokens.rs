@@ -0,0 +1,106 @@
+//! Signature-free session continuation with per-request chain tokens.
+//!
+//! After a session is established (by whatever means) the client holds a [`HashChain`] and the
+//! server remembers its anchor. Every request carries the next chain value as a [`ChainToken`];
+//! the server accepts it only if it is the immediate successor of the previous one, which costs a
+//! single hash. A stolen token is useless because the following request needs a value that has
+//! not been disclosed yet.
+
+use crate::registry::{ChainToken, VerifyError};
+use crate::{verify, ChainId, HashChain};
+use digest::generic_array::GenericArray;
+use digest::{Digest, FixedOutputReset};
+
+/// The client half: issues one token per request.
+pub struct ClientSession<H: Digest + FixedOutputReset> {
+    chain_id: ChainId,
+    chain: HashChain<H>,
+}
+
+impl<H: Digest + FixedOutputReset> ClientSession<H> {
+    pub fn new(chain_id: ChainId, chain: HashChain<H>) -> Self {
+        ClientSession { chain_id, chain }
+    }
+
+    /// The anchor to hand to the server when establishing the session.
+    pub fn anchor(&self) -> &GenericArray<u8, H::OutputSize> {
+        self.chain.anchor()
+    }
+
+    /// The token to attach to the next request, or `None` once the chain is used up.
+    pub fn next_token(&mut self) -> Option<ChainToken> {
+        let (index, value) = self.chain.next()?;
+        Some(ChainToken { chain_id: self.chain_id, index, value: value.to_vec() })
+    }
+
+    /// The number of requests left before a new session is needed.
+    pub fn remaining(&self) -> u64 {
+        self.chain.remaining()
+    }
+}
+
+/// The server half: remembers only the previous value of the session's chain.
+pub struct ServerSession<H: Digest + FixedOutputReset> {
+    chain_id: ChainId,
+    index: u64,
+    value: GenericArray<u8, H::OutputSize>,
+}
+
+impl<H: Digest + FixedOutputReset> ServerSession<H> {
+    pub fn new(chain_id: ChainId, anchor: GenericArray<u8, H::OutputSize>) -> Self {
+        ServerSession { chain_id, index: 0, value: anchor }
+    }
+
+    /// The index of the last accepted token.
+    pub fn index(&self) -> u64 {
+        self.index
+    }
+
+    /// Accept `token` if it continues the session, i.e. it is exactly one step past the last one.
+    pub fn verify(&mut self, token: &ChainToken) -> Result<(), VerifyError> {
+        if token.chain_id != self.chain_id {
+            return Err(VerifyError::UnknownChain);
+        }
+        if token.index <= self.index {
+            return Err(VerifyError::Replay);
+        }
+        if token.index != self.index + 1 {
+            return Err(VerifyError::NotNext);
+        }
+        if token.value.len() != self.value.len() {
+            return Err(VerifyError::Mismatch);
+        }
+        let value = GenericArray::clone_from_slice(&token.value);
+        if !verify::<H>(self.index, &self.value, token.index, &value) {
+            return Err(VerifyError::Mismatch);
+        }
+        self.index = token.index;
+        self.value = value;
+        Ok(())
+    }
+}
+
+#[test]
+fn test_session_continuation() {
+    use sha2::Sha256;
+
+    let id = ChainId([4; 16]);
+    let mut client = ClientSession::new(id, HashChain::<Sha256>::new(8, 21).unwrap());
+    let mut server = ServerSession::<Sha256>::new(id, *client.anchor());
+
+    let first = client.next_token().unwrap();
+    server.verify(&first).unwrap();
+    assert_eq!(server.verify(&first), Err(VerifyError::Replay));
+
+    let skipped = client.next_token().unwrap();
+    let third = client.next_token().unwrap();
+    assert_eq!(server.verify(&third), Err(VerifyError::NotNext));
+    server.verify(&skipped).unwrap();
+    server.verify(&third).unwrap();
+
+    let mut forged = client.next_token().unwrap();
+    forged.value[0] ^= 1;
+    assert_eq!(server.verify(&forged), Err(VerifyError::Mismatch));
+    assert_eq!(server.index(), 3);
+    assert_eq!(client.remaining(), 4);
+}
@@ -0,0 +1,457 @@
+//! Traversing a chain: [`HashChain`] and its disclosures.
+
+#[cfg(feature = "sha2")]
+use crate::chain::ChainId;
+use crate::chain::{audit_personalized, setup_chain, setup_chain_personalized};
+use crate::error::{ChainAuditError, ChainInitError};
+use crate::merkle;
+use crate::pebble::{log_2, Pebble, PebbleBuf};
+use crate::value::ChainValue;
+use alloc::sync::Arc;
+use alloc::vec::Vec;
+use digest::generic_array::GenericArray;
+use digest::{Digest, FixedOutputReset};
+
+/// A hash chain traversed in reverse with Jakobsson's fractal algorithm: only `log_2(length)`
+/// pebbles are stored, and each disclosure costs about `log_2(length)` hash evaluations.
+///
+/// Values are disclosed from position 1 up to position `length`, each of them hashing forward to
+/// the [anchor](HashChain::anchor) and to every value disclosed before it.
+pub struct HashChain<H: Digest + FixedOutputReset> {
+    pub(crate) length: u64,
+    pub(crate) current: u64,
+    pub(crate) anchor: GenericArray<u8, H::OutputSize>,
+    /// Shared between clones until one of them discloses.
+    pub(crate) pebbles: Arc<PebbleBuf<H>>,
+    /// Mixed into every step, see [`HashChain::new_personalized`].
+    pub(crate) personalization: Option<Arc<[u8]>>,
+    pub(crate) hasher: H,
+}
+
+/// Cloning is cheap: the clone shares the pebbles and copies them only when it or the original
+/// first discloses, so a speculative path (clone, disclose a few values, then keep or drop the
+/// clone) pays one copy at most and the untouched side none.
+impl<H: Digest + FixedOutputReset> Clone for HashChain<H> {
+    fn clone(&self) -> Self {
+        HashChain { length: self.length, current: self.current, anchor: self.anchor.clone(), pebbles: self.pebbles.clone(), personalization: self.personalization.clone(), hasher: H::new() }
+    }
+}
+
+impl<H: Digest + FixedOutputReset> HashChain<H> {
+    /// Set up a chain of `length` values (a power of two, at least 2) from `seed`.
+    pub fn new(length: usize, seed: u64) -> Result<Self, ChainInitError> {
+        let (pebbles, anchor) = setup_chain::<H, _>(length, seed, |_, _| {})?;
+        let chain = HashChain { length: length as u64, current: 0, anchor, pebbles: Arc::new(pebbles.into()), personalization: None, hasher: H::new() };
+        debug_assert_invariants!(chain);
+        Ok(chain)
+    }
+
+    /// Set up a chain whose every step, the one from the seed included, hashes `personalization`
+    /// ahead of the value, e.g. `b"acme-meterd v2"`: its length as u64 big endian, then the
+    /// string. Values generated for one protocol then never verify under another, even if both
+    /// use the same seed; verify them with [`verify_personalized`](crate::verify_personalized).
+    /// The personalization must be shorter than 256 bytes, and an empty one gives the plain
+    /// chain [`HashChain::new`] sets up.
+    pub fn new_personalized(length: usize, seed: u64, personalization: &[u8]) -> Result<Self, ChainInitError> {
+        crate::params::check_personalization(personalization.len()).map_err(ChainInitError::new)?;
+        let (pebbles, anchor) = setup_chain_personalized::<H, _>(length, seed, personalization, |_, _| {})?;
+        let personalization = (!personalization.is_empty()).then(|| Arc::from(personalization));
+        let chain = HashChain { length: length as u64, current: 0, anchor, pebbles: Arc::new(pebbles.into()), personalization, hasher: H::new() };
+        debug_assert_invariants!(chain);
+        Ok(chain)
+    }
+
+    /// Like [`HashChain::new`] with the length as a const parameter, checked when compiling:
+    /// a length that is not a power of two of at least 2 fails the build instead of returning
+    /// a [`ChainInitError`].
+    pub fn with_length<const LENGTH: usize>(seed: u64) -> Self {
+        const { assert!(LENGTH >= 2 && LENGTH.is_power_of_two(), "chain length must be a power of two, at least 2") };
+        Self::new(LENGTH, seed).expect("length checked at compile time")
+    }
+
+    /// Set up the chain and, in the same pass, the root of the Merkle tree over all its values
+    /// (see [`merkle::chain_root`]), which lets verifiers check any position with a
+    /// [`merkle::ChainProof`].
+    pub fn new_with_merkle_root(length: usize, seed: u64) -> Result<(Self, GenericArray<u8, H::OutputSize>), ChainInitError> {
+        let mut tree = merkle::TreeHash::<H>::new(length.trailing_zeros());
+        let (pebbles, anchor) = setup_chain::<H, _>(length, seed, |_, value| tree.push(merkle::leaf::<H>(value)))?;
+        let root = tree.root().expect("one leaf per position").clone();
+        Ok((HashChain { length: length as u64, current: 0, anchor, pebbles: Arc::new(pebbles.into()), personalization: None, hasher: H::new() }, root))
+    }
+
+    /// Set up a chain of `length` values from `seed` that has disclosed its first `position`
+    /// values already, e.g. on a device restored from a seed backup and the last position it
+    /// used. Where the pebbles sit after `position` disclosures depends only on the length, so
+    /// it is worked out without hashing, and their values are picked up in the one pass from
+    /// the seed [`HashChain::new`] makes instead of replaying the disclosures.
+    pub fn resume_from_seed(seed: u64, length: usize, position: u64) -> Result<Self, ChainInitError> {
+        crate::params::check_chain_length(length as u64).map_err(ChainInitError::new)?;
+        if position > length as u64 {
+            return Err(ChainInitError::new("position past the end of the chain"));
+        }
+        let mut pebbles = schedule::<H>(length as u64, position);
+        let (_, anchor) = setup_chain::<H, _>(length, seed, |i, value| {
+            for pebble in pebbles.iter_mut().filter(|p| p.position == i) {
+                pebble.value = value.clone();
+            }
+        })?;
+        let chain = HashChain { length: length as u64, current: position, anchor, pebbles: Arc::new(pebbles.into()), personalization: None, hasher: H::new() };
+        debug_assert_invariants!(chain);
+        Ok(chain)
+    }
+
+    /// The public commitment at position 0.
+    pub fn anchor(&self) -> &GenericArray<u8, H::OutputSize> {
+        &self.anchor
+    }
+
+    /// The personalization mixed into every step, empty for a plain chain.
+    pub fn personalization(&self) -> &[u8] {
+        self.personalization.as_deref().unwrap_or_default()
+    }
+
+    /// The number of disclosable values.
+    pub fn length(&self) -> u64 {
+        self.length
+    }
+
+    /// The identifier derived from the anchor and length, see [`ChainId::derive`].
+    #[cfg(feature = "sha2")]
+    pub fn chain_id(&self) -> ChainId {
+        ChainId::derive(&self.anchor, self.length)
+    }
+
+    /// The position of the last disclosed value, 0 before the first disclosure.
+    pub fn position(&self) -> u64 {
+        self.current
+    }
+
+    /// The number of values left to disclose.
+    pub fn remaining(&self) -> u64 {
+        self.length - self.current
+    }
+    /// Check the anchor and the current pebbles against a recomputation from `seed`, see
+    /// [`audit_chain_with_pebbles`](crate::audit_chain_with_pebbles).
+    pub fn audit(&self, seed: u64) -> Result<(), ChainAuditError> {
+        audit_personalized::<H>(seed, self.length as usize, &self.anchor, &self.pebbles, self.personalization())
+    }
+
+    /// The pebbles, ordered by destination.
+    pub fn pebbles(&self) -> &[Pebble<H>] {
+        &self.pebbles
+    }
+
+    /// Whether the pebbles are locked into RAM, or locking failed and they can be swapped out,
+    /// see [`locked`](crate::locked).
+    #[cfg(feature = "mlock")]
+    pub fn memory_locked(&self) -> bool {
+        self.pebbles.is_locked()
+    }
+
+    /// The number of hash evaluations the next [`disclose`](HashChain::disclose) takes.
+    pub fn step_cost(&self) -> u64 {
+        let next = self.current + 1;
+        if next > self.length {
+            return 0;
+        }
+        let moving = |p: &Pebble<H>| p.position != p.destination;
+        if next % 2 == 1 {
+            return 1 + 2 * self.pebbles.iter().filter(|p| moving(p)).count() as u64;
+        }
+        // the first pebble is relocated, or dropped if its next destination lies past the end
+        let first = &self.pebbles[0];
+        let relocated = first.destination + first.dest_incr <= self.length && first.position + first.start_incr != first.destination + first.dest_incr;
+        2 * (self.pebbles[1..].iter().filter(|p| moving(p)).count() as u64 + relocated as u64)
+    }
+
+    /// Disclose the next value, returning its position and value, or `None` once exhausted.
+    pub fn disclose(&mut self) -> Option<(u64, ChainValue<H>)> {
+        if self.current == self.length {
+            #[cfg(feature = "tracing")]
+            tracing::debug!(length = self.length, "chain exhausted");
+            return None;
+        }
+        #[cfg(feature = "tracing")]
+        let hashes = self.step_cost();
+        self.current += 1;
+        let pebbles = Arc::make_mut(&mut self.pebbles);
+        let hasher = &mut self.hasher;
+        let personalization = self.personalization.as_deref().unwrap_or_default();
+        let mut hash = |value: &GenericArray<u8, H::OutputSize>| {
+            #[cfg(feature = "telemetry")]
+            crate::telemetry::record(crate::telemetry::Phase::Traversal);
+            crate::verify::personalize(hasher, personalization);
+            digest::Digest::update(hasher, value.as_slice());
+            hasher.finalize_reset()
+        };
+
+        // the first pebble always sits at the current position or just above it
+        let output = if self.current % 2 == 1 {
+            hash(&pebbles[0].value)
+        } else {
+            let output = pebbles[0].value.clone();
+            let pebble = &mut pebbles[0];
+            pebble.position += pebble.start_incr;
+            pebble.destination += pebble.dest_incr;
+            if pebble.destination > self.length {
+                pebbles.remove(0);
+            } else {
+                // the new position is always occupied by another pebble, whose value we copy
+                let position = pebble.position;
+                let value = pebbles.iter().find(|p| p.position == position && p.destination == p.position)
+                    .expect("relocated pebble must land on a resting pebble").value.clone();
+                pebbles[0].value = value;
+                pebbles.sort_by_key(|p| p.destination);
+            }
+            output
+        };
+
+        // every pebble that has not reached its destination moves two positions towards it
+        for pebble in pebbles.iter_mut().filter(|p| p.position != p.destination) {
+            let once = hash(&pebble.value);
+            pebble.value = hash(&once);
+            pebble.position -= 2;
+        }
+
+        debug_assert_invariants!(self);
+        #[cfg(feature = "tracing")]
+        tracing::trace!(position = self.current, hashes, "value disclosed");
+        Some((self.current, ChainValue::new(output)))
+    }
+
+    /// Compute the next value without disclosing it yet, or `None` once exhausted. The chain
+    /// only moves on when the [`Reservation`] is committed, so a value whose sending failed can
+    /// be aborted and handed out again instead of being burnt.
+    pub fn reserve_next(&mut self) -> Option<Reservation<'_, H>> {
+        let mut next = self.clone();
+        let disclosed = next.disclose()?;
+        Some(Reservation { chain: self, next, disclosed })
+    }
+}
+
+/// A value computed by [`HashChain::reserve_next`] and not yet disclosed. Dropping it aborts.
+pub struct Reservation<'a, H: Digest + FixedOutputReset> {
+    chain: &'a mut HashChain<H>,
+    /// The chain as it will be after the commit.
+    next: HashChain<H>,
+    disclosed: (u64, ChainValue<H>),
+}
+
+impl<H: Digest + FixedOutputReset> Reservation<'_, H> {
+    pub fn index(&self) -> u64 {
+        self.disclosed.0
+    }
+
+    /// The value to send. It counts as disclosed once it has left, whether or not the
+    /// reservation is committed, so only abort if it certainly did not go out.
+    pub fn value(&self) -> &ChainValue<H> {
+        &self.disclosed.1
+    }
+
+    /// Move the chain past the reserved value and return it.
+    pub fn commit(self) -> (u64, ChainValue<H>) {
+        *self.chain = self.next;
+        self.disclosed
+    }
+
+    /// Persist the state after the reserved value with `save`, e.g. to flash or a file, and
+    /// move the chain on only if that succeeds.
+    pub fn commit_with<E, F: FnOnce(&[u8]) -> Result<(), E>>(self, save: F) -> Result<(u64, ChainValue<H>), E> {
+        save(&self.next.export_state())?;
+        Ok(self.commit())
+    }
+
+    /// Leave the chain where it was, so the same value is reserved next time.
+    pub fn abort(self) {}
+}
+
+/// The pebbles of a chain of `length` values after `current` disclosures, with their values
+/// left zero: the moves [`HashChain::disclose`] makes, without the hashing.
+fn schedule<H: Digest + FixedOutputReset>(length: u64, current: u64) -> Vec<Pebble<H>> {
+    let mut pebbles: Vec<Pebble<H>> = (1..=log_2(length)).map(|j| {
+        let i = 1u64 << j;
+        Pebble { start_incr: 3 * i, dest_incr: 2 * i, position: i, destination: i, value: GenericArray::default() }
+    }).collect();
+    for disclosed in 1..=current {
+        if disclosed % 2 == 0 {
+            let pebble = &mut pebbles[0];
+            pebble.position += pebble.start_incr;
+            pebble.destination += pebble.dest_incr;
+            if pebble.destination > length {
+                pebbles.remove(0);
+            } else {
+                pebbles.sort_by_key(|p| p.destination);
+            }
+        }
+        for pebble in pebbles.iter_mut().filter(|p| p.position != p.destination) {
+            pebble.position -= 2;
+        }
+    }
+    pebbles
+}
+
+impl<H: Digest + FixedOutputReset> Iterator for HashChain<H> {
+    type Item = (u64, ChainValue<H>);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.disclose()
+    }
+}
+
+#[test]
+fn test_traversal_matches_full_chain() {
+    use crate::{create_hash_chain_nopebble, hash_forward};
+    use sha2::Sha256;
+
+    for log_len in 1..=10 {
+        let len = 1usize << log_len;
+        let expected = create_hash_chain_nopebble::<Sha256>(len, 11);
+        let mut chain = HashChain::<Sha256>::new(len, 11).unwrap();
+        assert_eq!(*chain.anchor(), hash_forward::<Sha256>(&expected[len - 1], 1));
+        for i in 1..=len as u64 {
+            let (position, value) = chain.next().unwrap();
+            assert_eq!(position, i);
+            assert_eq!(value, expected[len - i as usize], "length {} position {}", len, i);
+        }
+        assert_eq!(chain.remaining(), 0);
+        assert!(chain.next().is_none());
+    }
+}
+
+#[test]
+fn test_const_length() {
+    use sha2::Sha256;
+
+    let chain = HashChain::<Sha256>::with_length::<64>(8);
+    assert_eq!(chain.length(), 64);
+    assert_eq!(chain.anchor(), HashChain::<Sha256>::new(64, 8).unwrap().anchor());
+}
+
+#[test]
+fn test_step_cost() {
+    use sha2::Sha256;
+
+    let mut chain = HashChain::<Sha256>::new(1024, 3).unwrap();
+    assert_eq!(chain.pebbles().len(), 10);
+    assert!(chain.pebbles().iter().all(|p| p.position() == p.destination()));
+    let mut costs = Vec::new();
+    while chain.remaining() > 0 {
+        costs.push(chain.step_cost());
+        chain.disclose().unwrap();
+    }
+    assert_eq!(chain.step_cost(), 0);
+    assert!(costs.iter().all(|&cost| cost <= 10));
+    assert_eq!(costs.iter().sum::<u64>(), 4098);
+}
+
+#[test]
+fn test_speculative_clone() {
+    use sha2::Sha256;
+
+    let mut chain = HashChain::<Sha256>::new(64, 6).unwrap();
+    chain.disclose();
+    let mut speculative = chain.clone();
+    assert!(Arc::ptr_eq(&chain.pebbles, &speculative.pebbles));
+    let ahead: Vec<_> = speculative.by_ref().take(5).collect();
+    assert!(!Arc::ptr_eq(&chain.pebbles, &speculative.pebbles));
+
+    // discarding the speculation leaves the original where it was
+    drop(speculative);
+    assert_eq!(HashChain::position(&chain), 1);
+    assert_eq!(chain.by_ref().take(5).collect::<Vec<_>>(), ahead);
+}
+
+#[test]
+fn test_reserve_and_commit() {
+    use sha2::Sha256;
+
+    let mut chain = HashChain::<Sha256>::new(8, 6).unwrap();
+    let expected: Vec<_> = chain.clone().collect();
+    let reservation = chain.reserve_next().unwrap();
+    assert_eq!((reservation.index(), reservation.value().clone()), expected[0]);
+    reservation.abort();
+    assert_eq!(chain.reserve_next().unwrap().commit(), expected[0]);
+
+    // a failed save leaves the chain where it was
+    assert_eq!(chain.reserve_next().unwrap().commit_with(|_| Err("disk full")), Err("disk full"));
+    let mut saved = Vec::new();
+    let committed = chain.reserve_next().unwrap().commit_with(|state| {
+        saved = state.to_vec();
+        Ok::<_, ()>(())
+    });
+    assert_eq!(committed, Ok(expected[1].clone()));
+    assert_eq!(HashChain::<Sha256>::import_state(&saved).unwrap().next(), Some(expected[2].clone()));
+    assert_eq!(HashChain::position(&chain), 2);
+}
+
+#[cfg(feature = "tracing")]
+#[test]
+fn test_tracing_never_records_values() {
+    use crate::verify;
+    use core::fmt::Debug;
+    use sha2::Sha256;
+    use std::sync::Mutex;
+    use tracing::field::{Field, Visit};
+    use tracing::span::{Attributes, Id, Record};
+    use tracing::{Event, Metadata};
+
+    /// Collects every recorded field as text.
+    struct Recorder(Mutex<Vec<String>>);
+
+    impl Visit for &Recorder {
+        fn record_debug(&mut self, field: &Field, value: &dyn Debug) {
+            self.0.lock().unwrap().push(format!("{}={:?}", field.name(), value));
+        }
+    }
+
+    impl tracing::Subscriber for &'static Recorder {
+        fn enabled(&self, _: &Metadata<'_>) -> bool {
+            true
+        }
+        fn new_span(&self, span: &Attributes<'_>) -> Id {
+            span.record(&mut &**self);
+            Id::from_u64(1)
+        }
+        fn record(&self, _: &Id, values: &Record<'_>) {
+            values.record(&mut &**self);
+        }
+        fn record_follows_from(&self, _: &Id, _: &Id) {}
+        fn event(&self, event: &Event<'_>) {
+            event.record(&mut &**self);
+        }
+        fn enter(&self, _: &Id) {}
+        fn exit(&self, _: &Id) {}
+    }
+
+    let recorder: &'static Recorder = Box::leak(Box::new(Recorder(Mutex::new(Vec::new()))));
+    let values = tracing::subscriber::with_default(recorder, || {
+        let mut chain = HashChain::<Sha256>::new(16, 5).unwrap();
+        let values: Vec<_> = chain.by_ref().take(3).collect();
+        assert!(verify::<Sha256>(0, chain.anchor(), values[0].0, &values[0].1));
+        HashChain::<Sha256>::import_state(&chain.export_state()).unwrap();
+        values
+    });
+    let recorded = recorder.0.lock().unwrap();
+    assert!(recorded.windows(2).any(|pair| pair == ["position=2", "hashes=2"]));
+    for (_, value) in values {
+        assert!(recorded.iter().all(|field| !field.contains(&value.to_hex())));
+    }
+}
+
+#[test]
+fn test_resume_from_seed() {
+    use sha2::Sha256;
+
+    for length in [2, 64, 256] {
+        for position in 0..=length as u64 {
+            let mut replayed = HashChain::<Sha256>::new(length, 9).unwrap();
+            replayed.by_ref().take(position as usize).for_each(drop);
+            let mut resumed = HashChain::<Sha256>::resume_from_seed(9, length, position).unwrap();
+            assert_eq!((resumed.position(), resumed.anchor()), (position, replayed.anchor()));
+            assert_eq!(resumed.pebbles(), replayed.pebbles(), "length {} position {}", length, position);
+            assert!(resumed.by_ref().eq(replayed.by_ref()));
+        }
+    }
+    assert!(HashChain::<Sha256>::resume_from_seed(9, 64, 65).is_err());
+    assert!(HashChain::<Sha256>::resume_from_seed(9, 48, 0).is_err());
+}
@@ -0,0 +1,361 @@
+//! Chain values as a type of their own, so every value handed out by a chain compares in
+//! constant time, formats the same way and is wiped from memory when dropped.
+//!
+//! A [`ChainValue`] dereferences to the underlying [`GenericArray`], so it goes wherever a
+//! borrowed hash output goes, e.g. into [`hash_forward`](crate::hash_forward) and
+//! [`verify`](crate::verify).
+//!
+//! [`DisplayOptions`] choose how values, anchors included, are shown to operators: hex in
+//! either case, with or without `0x`, base58, and optionally cut down to a short prefix.
+
+use alloc::string::{String, ToString};
+use alloc::vec::Vec;
+use core::error::Error;
+use core::fmt::{self, Debug, Display};
+use core::ops::Deref;
+#[cfg(feature = "hex")]
+use core::str::FromStr;
+use digest::generic_array::GenericArray;
+use digest::OutputSizeUser;
+#[cfg(feature = "sha2")]
+use {digest::Digest, sha2::Sha256};
+use zeroize::Zeroize;
+
+/// Why a string is not a chain value.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ValueParseError {
+    /// A character outside the encoding's alphabet, at this byte offset.
+    InvalidCharacter { character: char, index: usize },
+    /// Hex with an odd number of digits.
+    OddLength,
+    /// Base64 that does not decode.
+    InvalidBase64,
+    /// The value has the wrong number of bytes for the hash.
+    Length { expected: usize, found: usize },
+}
+
+impl Display for ValueParseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ValueParseError::InvalidCharacter { character, index } => write!(f, "invalid character {:?} at offset {}", character, index),
+            ValueParseError::OddLength => write!(f, "odd number of hex digits"),
+            ValueParseError::InvalidBase64 => write!(f, "invalid base64"),
+            ValueParseError::Length { expected, found } => write!(f, "expected {} bytes, found {}", expected, found),
+        }
+    }
+}
+
+impl Error for ValueParseError {}
+
+pub struct ChainValue<H: OutputSizeUser>(GenericArray<u8, H::OutputSize>);
+
+impl<H: OutputSizeUser> ChainValue<H> {
+    pub fn new(value: GenericArray<u8, H::OutputSize>) -> Self {
+        ChainValue(value)
+    }
+
+    /// Parse lowercase or uppercase hex, with or without `0x`. Surrounding whitespace is ignored.
+    #[cfg(feature = "hex")]
+    pub fn from_hex(text: &str) -> Result<Self, ValueParseError> {
+        let text = text.trim();
+        let digits = text.strip_prefix("0x").unwrap_or(text);
+        let bytes = hex::decode(digits).map_err(|error| match error {
+            hex::FromHexError::InvalidHexCharacter { c, index } => ValueParseError::InvalidCharacter { character: c, index: index + text.len() - digits.len() },
+            _ => ValueParseError::OddLength,
+        })?;
+        Self::from_slice(&bytes)
+    }
+
+    /// Parse standard base64 with padding, as [`ChainValue::to_base64`] writes it.
+    #[cfg(feature = "base64")]
+    pub fn from_base64(text: &str) -> Result<Self, ValueParseError> {
+        use base64::Engine;
+        let bytes = base64::engine::general_purpose::STANDARD.decode(text.trim()).map_err(|error| match error {
+            base64::DecodeError::InvalidByte(index, byte) => ValueParseError::InvalidCharacter { character: byte as char, index },
+            _ => ValueParseError::InvalidBase64,
+        })?;
+        Self::from_slice(&bytes)
+    }
+
+    #[cfg(any(feature = "hex", feature = "base64"))]
+    fn from_slice(bytes: &[u8]) -> Result<Self, ValueParseError> {
+        let expected = <H::OutputSize as digest::generic_array::typenum::Unsigned>::USIZE;
+        if bytes.len() != expected {
+            return Err(ValueParseError::Length { expected, found: bytes.len() });
+        }
+        Ok(ChainValue(GenericArray::clone_from_slice(bytes)))
+    }
+
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.0
+    }
+
+    /// A copy of the raw value, which is not wiped when dropped.
+    pub fn to_array(&self) -> GenericArray<u8, H::OutputSize> {
+        self.0.clone()
+    }
+
+    pub fn to_hex(&self) -> String {
+        alloc::format!("{:x}", Hex(&self.0))
+    }
+
+    /// Standard base64 with padding.
+    #[cfg(feature = "base64")]
+    pub fn to_base64(&self) -> String {
+        use base64::Engine;
+        base64::engine::general_purpose::STANDARD.encode(&self.0)
+    }
+
+    /// Compare with `other` in time depending only on the lengths, e.g. against a value an
+    /// attacker is guessing at byte by byte.
+    pub fn ct_eq(&self, other: &[u8]) -> bool {
+        if self.0.len() != other.len() {
+            return false;
+        }
+        let difference = self.0.iter().zip(other).fold(0u8, |difference, (a, b)| difference | (a ^ b));
+        core::hint::black_box(difference) == 0
+    }
+
+    /// The value formatted with `options`, e.g. `value.display(DisplayOptions::new().prefixed())`.
+    pub fn display(&self, options: DisplayOptions) -> Displayed<'_> {
+        Displayed { bytes: &self.0, options }
+    }
+
+    /// A short identifier of the value: the first 4 bytes of its SHA-256 hash. It tells values
+    /// apart in logs without revealing them.
+    #[cfg(feature = "sha2")]
+    pub fn fingerprint(&self) -> [u8; 4] {
+        let digest = Sha256::new_with_prefix(b"chain value fingerprint").chain_update(&self.0).finalize();
+        digest[..4].try_into().expect("SHA-256 output is 32 bytes")
+    }
+}
+
+impl<H: OutputSizeUser> Clone for ChainValue<H> {
+    fn clone(&self) -> Self {
+        ChainValue(self.0.clone())
+    }
+}
+
+impl<H: OutputSizeUser> Deref for ChainValue<H> {
+    type Target = GenericArray<u8, H::OutputSize>;
+
+    fn deref(&self) -> &Self::Target {
+        &self.0
+    }
+}
+
+impl<H: OutputSizeUser> AsRef<[u8]> for ChainValue<H> {
+    fn as_ref(&self) -> &[u8] {
+        &self.0
+    }
+}
+
+impl<H: OutputSizeUser> From<GenericArray<u8, H::OutputSize>> for ChainValue<H> {
+    fn from(value: GenericArray<u8, H::OutputSize>) -> Self {
+        ChainValue(value)
+    }
+}
+
+/// Constant time, see [`ChainValue::ct_eq`].
+impl<H: OutputSizeUser> PartialEq for ChainValue<H> {
+    fn eq(&self, other: &Self) -> bool {
+        self.ct_eq(&other.0)
+    }
+}
+
+impl<H: OutputSizeUser> Eq for ChainValue<H> {}
+
+impl<H: OutputSizeUser> PartialEq<GenericArray<u8, H::OutputSize>> for ChainValue<H> {
+    fn eq(&self, other: &GenericArray<u8, H::OutputSize>) -> bool {
+        self.ct_eq(other)
+    }
+}
+
+impl<H: OutputSizeUser> Zeroize for ChainValue<H> {
+    fn zeroize(&mut self) {
+        self.0.as_mut_slice().zeroize();
+    }
+}
+
+impl<H: OutputSizeUser> Drop for ChainValue<H> {
+    fn drop(&mut self) {
+        self.zeroize();
+    }
+}
+
+/// Hex, see [`ChainValue::from_hex`].
+#[cfg(feature = "hex")]
+impl<H: OutputSizeUser> FromStr for ChainValue<H> {
+    type Err = ValueParseError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Self::from_hex(s)
+    }
+}
+
+/// Lowercase hex, as with the default [`DisplayOptions`].
+impl<H: OutputSizeUser> Display for ChainValue<H> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        Display::fmt(&self.display(DisplayOptions::new()), f)
+    }
+}
+
+impl<H: OutputSizeUser> Debug for ChainValue<H> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "ChainValue({:x})", Hex(&self.0))
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Encoding {
+    #[default]
+    Hex,
+    UpperHex,
+    /// The Bitcoin alphabet, with a leading `1` for every leading zero byte.
+    Base58,
+}
+
+/// How to show a value. The default is the full value in lowercase hex.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct DisplayOptions {
+    pub encoding: Encoding,
+    /// Put `0x` in front of hex.
+    pub prefix: bool,
+    /// Show only the first this many bytes, followed by `...`.
+    pub truncate: Option<usize>,
+}
+
+impl DisplayOptions {
+    pub const fn new() -> Self {
+        DisplayOptions { encoding: Encoding::Hex, prefix: false, truncate: None }
+    }
+
+    pub const fn with_encoding(mut self, encoding: Encoding) -> Self {
+        self.encoding = encoding;
+        self
+    }
+
+    pub const fn prefixed(mut self) -> Self {
+        self.prefix = true;
+        self
+    }
+
+    pub const fn truncated(mut self, bytes: usize) -> Self {
+        self.truncate = Some(bytes);
+        self
+    }
+
+    /// `bytes` formatted with these options, e.g. a chain's anchor.
+    pub fn format(&self, bytes: &[u8]) -> String {
+        Displayed { bytes, options: *self }.to_string()
+    }
+}
+
+/// Bytes formatted with [`DisplayOptions`], from [`ChainValue::display`].
+pub struct Displayed<'a> {
+    bytes: &'a [u8],
+    options: DisplayOptions,
+}
+
+impl Display for Displayed<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let shown = &self.bytes[..self.options.truncate.map_or(self.bytes.len(), |n| n.min(self.bytes.len()))];
+        match self.options.encoding {
+            Encoding::Hex | Encoding::UpperHex if self.options.prefix => f.write_str("0x")?,
+            _ => {}
+        }
+        match self.options.encoding {
+            Encoding::Hex => write!(f, "{:x}", Hex(shown))?,
+            Encoding::UpperHex => write!(f, "{:X}", Hex(shown))?,
+            Encoding::Base58 => f.write_str(&base58(shown))?,
+        }
+        if shown.len() < self.bytes.len() {
+            f.write_str("...")?;
+        }
+        Ok(())
+    }
+}
+
+/// Bytes written as hex with `{:x}` or `{:X}`, for formatting without the `hex` crate.
+pub(crate) struct Hex<'a>(pub(crate) &'a [u8]);
+
+impl fmt::LowerHex for Hex<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.0.iter().try_for_each(|byte| write!(f, "{:02x}", byte))
+    }
+}
+
+impl fmt::UpperHex for Hex<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.0.iter().try_for_each(|byte| write!(f, "{:02X}", byte))
+    }
+}
+
+fn base58(bytes: &[u8]) -> String {
+    const ALPHABET: &[u8; 58] = b"123456789ABCDEFGHJKLMNPQRSTUVWXYZabcdefghijkmnopqrstuvwxyz";
+    // little endian base 58 digits, updated for every byte as in long multiplication
+    let mut digits: Vec<u8> = Vec::with_capacity(bytes.len() * 138 / 100 + 1);
+    for &byte in bytes {
+        let mut carry = byte as u32;
+        for digit in digits.iter_mut() {
+            carry += (*digit as u32) << 8;
+            *digit = (carry % 58) as u8;
+            carry /= 58;
+        }
+        while carry > 0 {
+            digits.push((carry % 58) as u8);
+            carry /= 58;
+        }
+    }
+    let zeros = bytes.iter().take_while(|&&byte| byte == 0).count();
+    core::iter::repeat_n('1', zeros).chain(digits.iter().rev().map(|&digit| ALPHABET[digit as usize] as char)).collect()
+}
+
+#[cfg(all(feature = "sha2", feature = "base64"))]
+#[test]
+fn test_chain_value() {
+    let value = ChainValue::<Sha256>::new(Sha256::digest(b"value"));
+    assert_eq!(value.to_hex(), hex::encode(Sha256::digest(b"value")));
+    assert_eq!(value.to_base64().len(), 44);
+    assert!(value.ct_eq(&Sha256::digest(b"value")));
+    assert!(!value.ct_eq(&Sha256::digest(b"value")[..31]));
+    assert_ne!(value, ChainValue::new(Sha256::digest(b"other")));
+    assert_eq!(value, Sha256::digest(b"value"));
+    assert_ne!(value.fingerprint(), ChainValue::<Sha256>::new(Sha256::digest(b"other")).fingerprint());
+
+    let mut wiped = value.clone();
+    wiped.zeroize();
+    assert_eq!(wiped.as_bytes(), [0; 32]);
+}
+
+#[test]
+fn test_display_options() {
+    use sha2::{Digest, Sha256};
+
+    let value = ChainValue::<Sha256>::new(Sha256::digest(b"value"));
+    let hex = value.to_hex();
+    assert_eq!(value.to_string(), hex);
+    assert_eq!(value.display(DisplayOptions::new().prefixed().truncated(4)).to_string(), alloc::format!("0x{}...", &hex[..8]));
+    assert_eq!(value.display(DisplayOptions::new().with_encoding(Encoding::UpperHex)).to_string(), hex.to_uppercase());
+    assert_eq!(DisplayOptions::new().truncated(64).format(&value), hex);
+
+    let base58 = DisplayOptions::new().with_encoding(Encoding::Base58);
+    assert_eq!(base58.format(b"hello world"), "StV1DL6CwTryKyV");
+    assert_eq!(base58.format(&[0, 0, 1]), "112");
+    assert_eq!(base58.prefixed().format(&[]), "");
+}
+
+#[cfg(all(feature = "hex", feature = "base64"))]
+#[test]
+fn test_parse_chain_value() {
+    use sha2::{Digest, Sha256};
+
+    let value = ChainValue::<Sha256>::new(Sha256::digest(b"value"));
+    assert_eq!(value.to_hex().parse::<ChainValue<Sha256>>(), Ok(value.clone()));
+    assert_eq!(ChainValue::<Sha256>::from_hex(&alloc::format!(" 0x{} ", value.to_hex().to_uppercase())), Ok(value.clone()));
+    assert_eq!(ChainValue::<Sha256>::from_base64(&value.to_base64()), Ok(value.clone()));
+    assert_eq!(ChainValue::<Sha256>::from_hex("0xabc"), Err(ValueParseError::OddLength));
+    assert_eq!(ChainValue::<Sha256>::from_hex("0xabcg"), Err(ValueParseError::InvalidCharacter { character: 'g', index: 5 }));
+    assert_eq!(ChainValue::<Sha256>::from_hex("abcd"), Err(ValueParseError::Length { expected: 32, found: 2 }));
+    assert_eq!(ChainValue::<Sha256>::from_hex("abcd").unwrap_err().to_string(), "expected 32 bytes, found 2");
+}
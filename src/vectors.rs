@@ -0,0 +1,173 @@
+//! Test vectors in a JSON format other implementations can check themselves against.
+//!
+//! A vector describes one chain and looks like this, with all byte strings in lowercase hex:
+//!
+//! ```json
+//! {
+//!   "hash": "sha256",
+//!   "seed": 7,
+//!   "length": 16,
+//!   "anchor": "…",
+//!   "values": [{ "index": 1, "value": "…" }],
+//!   "snapshots": [{ "step": 4, "pebbles": [{ "start_incr": 24, "dest_incr": 16, "position": 8, "destination": 8, "value": "…" }, …] }]
+//! }
+//! ```
+//!
+//! The value at index `length` is the hash of the seed's 8 little-endian bytes, the value at
+//! index `i - 1` is the hash of the value at `i`, and the anchor is the value at index 0. The
+//! seed is an unsigned 64-bit integer. A snapshot lists the pebbles, ordered by destination,
+//! after `step` values have been disclosed; pebble `j` of a fresh chain starts with
+//! `start_incr = 3 * 2^(j+1)`, `dest_incr = 2 * 2^(j+1)` and rests at `2^(j+1)`.
+
+use crate::{hash_forward, HashChain, Pebble};
+use digest::{Digest, FixedOutputReset};
+use serde::{Deserialize, Serialize};
+use std::error::Error;
+use std::fmt::{self, Display};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct VectorError {
+    details: String,
+}
+
+impl VectorError {
+    fn new(msg: &str) -> VectorError {
+        VectorError { details: msg.to_string() }
+    }
+}
+
+impl Display for VectorError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.details)
+    }
+}
+
+impl Error for VectorError {}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct IndexedValue {
+    pub index: u64,
+    pub value: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PebbleSnapshot {
+    pub start_incr: u64,
+    pub dest_incr: u64,
+    pub position: u64,
+    pub destination: u64,
+    pub value: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Snapshot {
+    pub step: u64,
+    pub pebbles: Vec<PebbleSnapshot>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TestVector {
+    /// The hash function's name, e.g. `"sha256"`.
+    pub hash: String,
+    pub seed: u64,
+    pub length: u64,
+    pub anchor: String,
+    pub values: Vec<IndexedValue>,
+    pub snapshots: Vec<Snapshot>,
+}
+
+impl<H: Digest + FixedOutputReset> From<&Pebble<H>> for PebbleSnapshot {
+    fn from(pebble: &Pebble<H>) -> Self {
+        PebbleSnapshot {
+            start_incr: pebble.start_incr,
+            dest_incr: pebble.dest_incr,
+            position: pebble.position,
+            destination: pebble.destination,
+            value: hex::encode(&pebble.value),
+        }
+    }
+}
+
+impl TestVector {
+    /// The vector for the chain of `length` values from `seed` under `H`, named `hash`, with the
+    /// values at `indices` and pebble snapshots after each of `steps` disclosures.
+    pub fn generate<H: Digest + FixedOutputReset>(hash: &str, seed: u64, length: usize, indices: &[u64], steps: &[u64]) -> Result<Self, VectorError> {
+        let mut chain = HashChain::<H>::new(length, seed).map_err(|e| VectorError::new(&e.to_string()))?;
+        let length = chain.length();
+        if indices.iter().chain(steps).any(|&i| i > length) {
+            return Err(VectorError::new("index past the end of the chain"));
+        }
+
+        // walk down from the hash of the seed once, collecting the requested values on the way
+        let mut wanted = indices.to_vec();
+        wanted.sort_unstable_by(|a, b| b.cmp(a));
+        wanted.dedup();
+        let mut values = Vec::with_capacity(wanted.len());
+        let (mut index, mut value) = (length, H::digest(seed.to_le_bytes()));
+        for target in wanted {
+            value = hash_forward::<H>(&value, index - target);
+            index = target;
+            values.push(IndexedValue { index, value: hex::encode(&value) });
+        }
+        values.reverse();
+
+        let mut steps = steps.to_vec();
+        steps.sort_unstable();
+        steps.dedup();
+        let mut snapshots = Vec::with_capacity(steps.len());
+        for step in steps {
+            while HashChain::position(&chain) < step {
+                chain.disclose();
+            }
+            snapshots.push(Snapshot { step, pebbles: chain.pebbles.iter().map(PebbleSnapshot::from).collect() });
+        }
+
+        Ok(TestVector { hash: hash.to_string(), seed, length, anchor: hex::encode(chain.anchor()), values, snapshots })
+    }
+
+    /// Check that this crate, hashing with `H`, reproduces the vector.
+    pub fn check<H: Digest + FixedOutputReset>(&self) -> Result<(), VectorError> {
+        let length = usize::try_from(self.length).map_err(|_| VectorError::new("length too large"))?;
+        let indices: Vec<u64> = self.values.iter().map(|v| v.index).collect();
+        let steps: Vec<u64> = self.snapshots.iter().map(|s| s.step).collect();
+        let expected = TestVector::generate::<H>(&self.hash, self.seed, length, &indices, &steps)?;
+        if expected.anchor != self.anchor {
+            return Err(VectorError::new("anchor differs"));
+        }
+        if let Some(value) = self.values.iter().find(|v| !expected.values.contains(v)) {
+            return Err(VectorError::new(&format!("value {} differs", value.index)));
+        }
+        if let Some(snapshot) = self.snapshots.iter().find(|s| !expected.snapshots.contains(s)) {
+            return Err(VectorError::new(&format!("pebbles after step {} differ", snapshot.step)));
+        }
+        Ok(())
+    }
+
+    pub fn to_json(&self) -> String {
+        serde_json::to_string_pretty(self).expect("vectors serialize")
+    }
+
+    pub fn from_json(json: &str) -> Result<Self, VectorError> {
+        serde_json::from_str(json).map_err(|e| VectorError::new(&e.to_string()))
+    }
+}
+
+#[test]
+fn test_vectors_roundtrip_and_check() {
+    use sha2::Sha256;
+
+    let vector = TestVector::generate::<Sha256>("sha256", 7, 16, &[16, 1, 9], &[0, 4, 15]).unwrap();
+    let nopebble = crate::create_hash_chain_nopebble::<Sha256>(16, 7);
+    assert_eq!(vector.values[0], IndexedValue { index: 1, value: hex::encode(nopebble[15]) });
+    assert_eq!(vector.values[2], IndexedValue { index: 16, value: hex::encode(nopebble[0]) });
+    assert_eq!(vector.snapshots[0].pebbles.len(), 4);
+
+    let parsed = TestVector::from_json(&vector.to_json()).unwrap();
+    assert_eq!(parsed, vector);
+    assert_eq!(parsed.check::<Sha256>(), Ok(()));
+
+    let mut tampered = parsed.clone();
+    tampered.snapshots[1].pebbles[0].position += 2;
+    assert!(tampered.check::<Sha256>().is_err());
+    assert!(TestVector::generate::<Sha256>("sha256", 7, 16, &[17], &[]).is_err());
+}
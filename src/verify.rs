@@ -0,0 +1,330 @@
+//! Checking disclosed values against earlier ones, and the steps a chain is built from. With
+//! `std`, a [`Pool`] of worker threads verifies tokens against a registry.
+//!
+//! Verifying never allocates: each value is hashed forward in place, in one buffer of the hash's
+//! output size on the stack, so checks on a request path cost no heap traffic.
+
+use alloc::vec::Vec;
+use digest::generic_array::GenericArray;
+use digest::{Digest, FixedOutputReset, OutputSizeUser};
+
+#[cfg(feature = "std")]
+mod pool;
+#[cfg(feature = "std")]
+pub use pool::{Pending, Pool, PoolError, Submitter};
+
+/// The largest gap the verifiers that keep no chain lengths, the registry's [`GapPolicy`] and
+/// [`ReplayWindow`] among them, allow unless configured otherwise. Without a limit one
+/// disclosure can ask for up to `u64::MAX` hashes.
+///
+/// [`GapPolicy`]: crate::registry::GapPolicy
+/// [`ReplayWindow`]: crate::window::ReplayWindow
+pub const DEFAULT_MAX_GAP: u64 = 1 << 16;
+
+/// Apply the hash `steps` times to `value`. Applied to the value at position `i` this yields the
+/// value at position `i - steps`, position 0 being the anchor.
+pub fn hash_forward<H: Digest + FixedOutputReset>(value: &GenericArray<u8, H::OutputSize>, steps: u64) -> GenericArray<u8, H::OutputSize> {
+    hash_forward_personalized::<H>(&[], value, steps)
+}
+
+/// [`hash_forward`] along a chain set up with
+/// [`HashChain::new_personalized`](crate::HashChain::new_personalized).
+pub fn hash_forward_personalized<H: Digest + FixedOutputReset>(personalization: &[u8], value: &GenericArray<u8, H::OutputSize>, steps: u64) -> GenericArray<u8, H::OutputSize> {
+    let mut hasher = H::new();
+    let mut output = value.clone();
+    for _ in 0..steps {
+        #[cfg(feature = "telemetry")]
+        crate::telemetry::record(crate::telemetry::Phase::Verification);
+        personalize(&mut hasher, personalization);
+        digest::Digest::update(&mut hasher, output.as_slice());
+        FixedOutputReset::finalize_into_reset(&mut hasher, &mut output);
+    }
+    output
+}
+
+/// Feed the personalization into `hasher` ahead of the value of a chain step: its length as u64
+/// big endian and the string itself, or nothing for an empty one, which leaves the plain step
+/// `H(value)`. Prefixed inputs are longer than a value, so no step of one protocol is a step of
+/// another.
+pub(crate) fn personalize<H: Digest>(hasher: &mut H, personalization: &[u8]) {
+    if !personalization.is_empty() {
+        digest::Digest::update(hasher, (personalization.len() as u64).to_be_bytes());
+        digest::Digest::update(hasher, personalization);
+    }
+}
+
+/// A single step of a hash chain, mapping a value to the next one along the walk. `index` counts
+/// the steps from the start of the walk, so keyed or domain-separated steps can vary per step.
+pub trait ChainStep<H: OutputSizeUser> {
+    fn step(&self, value: &GenericArray<u8, H::OutputSize>, index: u64) -> GenericArray<u8, H::OutputSize>;
+
+    /// Apply the steps `start`, `start + 1`, ... to `value`, `steps` of them in total.
+    fn walk(&self, value: &GenericArray<u8, H::OutputSize>, start: u64, steps: u64) -> GenericArray<u8, H::OutputSize> {
+        (start..start + steps).fold(value.clone(), |value, index| self.step(&value, index))
+    }
+}
+
+/// The plain step `H(value)` used by [`HashChain`](crate::HashChain) and [`hash_forward`].
+pub struct PlainStep<H>(core::marker::PhantomData<H>);
+
+impl<H> PlainStep<H> {
+    pub fn new() -> Self {
+        PlainStep(core::marker::PhantomData)
+    }
+}
+
+impl<H> Default for PlainStep<H> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<H> Clone for PlainStep<H> {
+    fn clone(&self) -> Self {
+        Self::new()
+    }
+}
+
+impl<H: Digest> ChainStep<H> for PlainStep<H> {
+    fn step(&self, value: &GenericArray<u8, H::OutputSize>, _index: u64) -> GenericArray<u8, H::OutputSize> {
+        H::digest(value)
+    }
+}
+
+/// A keyed step: the MAC of the value under a fixed key. Any [`Mac`](digest::Mac), e.g. HMAC or
+/// CMAC, then drives the same machinery as a plain hash, as a step for any `H` with the MAC's
+/// output size. Like [`PlainStep`] it ignores the step index.
+pub struct MacStep<M> {
+    mac: M,
+}
+
+impl<M: digest::Mac + Clone> MacStep<M> {
+    /// The step computing `mac`, already keyed, over each value.
+    pub fn new(mac: M) -> Self {
+        MacStep { mac }
+    }
+}
+
+impl<M: digest::Mac + digest::KeyInit + Clone> MacStep<M> {
+    pub fn new_from_slice(key: &[u8]) -> Result<Self, digest::InvalidLength> {
+        Ok(MacStep { mac: <M as digest::KeyInit>::new_from_slice(key)? })
+    }
+}
+
+impl<M: Clone> Clone for MacStep<M> {
+    fn clone(&self) -> Self {
+        MacStep { mac: self.mac.clone() }
+    }
+}
+
+impl<H: OutputSizeUser<OutputSize = M::OutputSize>, M: digest::Mac + Clone> ChainStep<H> for MacStep<M> {
+    fn step(&self, value: &GenericArray<u8, H::OutputSize>, _index: u64) -> GenericArray<u8, H::OutputSize> {
+        self.mac.clone().chain_update(value).finalize().into_bytes()
+    }
+}
+
+/// The step of a personalized chain, `H(len || personalization || value)` with the length as
+/// u64 big endian, see [`HashChain::new_personalized`](crate::HashChain::new_personalized).
+pub struct PersonalizedStep<H> {
+    personalization: Vec<u8>,
+    hash: core::marker::PhantomData<H>,
+}
+
+impl<H> PersonalizedStep<H> {
+    pub fn new(personalization: &[u8]) -> Self {
+        PersonalizedStep { personalization: personalization.to_vec(), hash: core::marker::PhantomData }
+    }
+}
+
+impl<H> Clone for PersonalizedStep<H> {
+    fn clone(&self) -> Self {
+        Self::new(&self.personalization)
+    }
+}
+
+impl<H: Digest> ChainStep<H> for PersonalizedStep<H> {
+    fn step(&self, value: &GenericArray<u8, H::OutputSize>, _index: u64) -> GenericArray<u8, H::OutputSize> {
+        let mut hasher = H::new();
+        personalize(&mut hasher, &self.personalization);
+        hasher.chain_update(value).finalize()
+    }
+}
+
+/// Check that `value` is the chain value at `index`, given the value at an earlier `known_index`
+/// (the anchor at index 0, or the last accepted disclosure).
+pub fn verify<H: Digest + FixedOutputReset>(known_index: u64, known_value: &GenericArray<u8, H::OutputSize>, index: u64, value: &GenericArray<u8, H::OutputSize>) -> bool {
+    verify_personalized::<H>(&[], known_index, known_value, index, value)
+}
+
+/// [`verify`] for a chain set up with
+/// [`HashChain::new_personalized`](crate::HashChain::new_personalized). A value of a chain with
+/// another personalization, or none, does not verify even if both chains share a seed.
+pub fn verify_personalized<H: Digest + FixedOutputReset>(personalization: &[u8], known_index: u64, known_value: &GenericArray<u8, H::OutputSize>, index: u64, value: &GenericArray<u8, H::OutputSize>) -> bool {
+    let valid = index > known_index && hash_forward_personalized::<H>(personalization, value, index - known_index) == *known_value;
+    #[cfg(feature = "tracing")]
+    tracing::trace!(known_index, index, valid, "disclosure verified");
+    valid
+}
+
+/// Check several disclosures at once against the value at `known_index`, e.g. to catch up after
+/// a disconnection. The indices must be strictly increasing and above `known_index`. The values
+/// are checked in a single pass down from the highest one, and every comparison is folded into
+/// one result, so the time taken does not reveal which value was wrong.
+pub fn verify_batch<H: Digest + FixedOutputReset>(known_index: u64, known_value: &GenericArray<u8, H::OutputSize>, batch: &[(u64, GenericArray<u8, H::OutputSize>)]) -> bool {
+    let Some((top_index, top_value)) = batch.last() else {
+        return false;
+    };
+    if batch.iter().try_fold(known_index, |last, &(index, _)| (index > last).then_some(index)).is_none() {
+        return false;
+    }
+    let mut hasher = H::new();
+    let mut current = top_value.clone();
+    let mut difference = 0u8;
+    let mut compare = |a: &GenericArray<u8, H::OutputSize>, b: &GenericArray<u8, H::OutputSize>| {
+        difference |= a.iter().zip(b.iter()).fold(0, |acc, (x, y)| acc | (x ^ y));
+    };
+    let mut expected = batch.iter().rev().skip(1).peekable();
+    for index in (known_index..*top_index).rev() {
+        #[cfg(feature = "telemetry")]
+        crate::telemetry::record(crate::telemetry::Phase::Verification);
+        digest::Digest::update(&mut hasher, current.as_slice());
+        FixedOutputReset::finalize_into_reset(&mut hasher, &mut current);
+        if let Some((_, value)) = expected.next_if(|(at, _)| *at == index) {
+            compare(&current, value);
+        }
+    }
+    compare(&current, known_value);
+    difference == 0
+}
+
+#[test]
+fn test_verify_against_full_chain() {
+    use crate::create_hash_chain_nopebble;
+    use sha2::Sha256;
+
+    let len = 16;
+    // the unpebbled chain starts at the seed end, so element k sits at position len - k
+    let chain = create_hash_chain_nopebble::<Sha256>(len, 7);
+    let anchor = hash_forward::<Sha256>(&chain[len - 1], 1);
+    assert!(verify::<Sha256>(0, &anchor, 1, &chain[len - 1]));
+    assert!(verify::<Sha256>(0, &anchor, 16, &chain[0]));
+    assert!(verify::<Sha256>(3, &chain[len - 3], 9, &chain[len - 9]));
+    assert!(!verify::<Sha256>(3, &chain[len - 3], 3, &chain[len - 3]));
+    assert!(!verify::<Sha256>(0, &anchor, 2, &chain[len - 1]));
+}
+
+#[test]
+fn test_verify_batch() {
+    use crate::HashChain;
+    use sha2::Sha256;
+
+    let mut chain = HashChain::<Sha256>::new(32, 4).unwrap();
+    let batch: Vec<_> = chain.by_ref().take(6).map(|(i, v)| (i, v.to_array())).collect();
+    assert!(verify_batch::<Sha256>(0, chain.anchor(), &batch));
+    assert!(verify_batch::<Sha256>(2, &batch[1].1, &batch[2..]));
+
+    let mut forged = batch.clone();
+    forged[3].1[0] ^= 1;
+    assert!(!verify_batch::<Sha256>(0, chain.anchor(), &forged));
+    let mut reordered = batch.clone();
+    reordered.swap(1, 2);
+    assert!(!verify_batch::<Sha256>(0, chain.anchor(), &reordered));
+    assert!(!verify_batch::<Sha256>(0, chain.anchor(), &[]));
+}
+
+#[test]
+fn test_plain_step_matches_hash_forward() {
+    use sha2::Sha256;
+
+    let value = Sha256::digest(b"start");
+    assert_eq!(PlainStep::<Sha256>::new().walk(&value, 5, 7), hash_forward::<Sha256>(&value, 7));
+}
+
+#[test]
+fn test_mac_step() {
+    use digest::Mac;
+    use hmac::SimpleHmac;
+    use sha2::Sha256;
+
+    let step = MacStep::<SimpleHmac<Sha256>>::new_from_slice(b"chain key").unwrap();
+    let start = Sha256::digest(b"start");
+    let expected = (0..3).fold(start, |value, _| SimpleHmac::<Sha256>::new_from_slice(b"chain key").unwrap().chain_update(value).finalize().into_bytes());
+    assert_eq!(ChainStep::<Sha256>::walk(&step, &start, 0, 3), expected);
+}
+
+#[test]
+fn test_personalized_chain() {
+    use crate::HashChain;
+    use sha2::Sha256;
+
+    let mut plain = HashChain::<Sha256>::new(16, 5).unwrap();
+    let mut meterd = HashChain::<Sha256>::new_personalized(16, 5, b"acme-meterd v2").unwrap();
+    assert_ne!(plain.anchor(), meterd.anchor());
+    let (index, value) = meterd.nth(3).unwrap();
+    assert!(verify_personalized::<Sha256>(b"acme-meterd v2", 0, meterd.anchor(), index, &value));
+    assert!(!verify_personalized::<Sha256>(b"acme-meterd v1", 0, meterd.anchor(), index, &value));
+    assert!(!verify::<Sha256>(0, plain.anchor(), index, &value));
+    assert_eq!(plain.nth(3), HashChain::<Sha256>::new_personalized(16, 5, b"").unwrap().nth(3));
+    assert!(meterd.audit(5).is_ok());
+
+    let step = PersonalizedStep::<Sha256>::new(b"acme-meterd v2");
+    assert_eq!(ChainStep::<Sha256>::walk(&step, &value, 0, 4), *meterd.anchor());
+}
+
+
+/// Counts the heap allocations of each test thread, for tests of allocation-free paths.
+#[cfg(test)]
+struct CountingAllocator;
+
+#[cfg(test)]
+std::thread_local! {
+    static ALLOCATIONS: core::cell::Cell<usize> = const { core::cell::Cell::new(0) };
+}
+
+#[cfg(test)]
+unsafe impl std::alloc::GlobalAlloc for CountingAllocator {
+    unsafe fn alloc(&self, layout: std::alloc::Layout) -> *mut u8 {
+        // the counter is gone while the thread's locals are torn down
+        let _ = ALLOCATIONS.try_with(|count| count.set(count.get() + 1));
+        // SAFETY: forwarded unchanged
+        unsafe { std::alloc::System.alloc(layout) }
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: std::alloc::Layout) {
+        // SAFETY: forwarded unchanged
+        unsafe { std::alloc::System.dealloc(ptr, layout) }
+    }
+}
+
+#[cfg(test)]
+#[global_allocator]
+static COUNTING_ALLOCATOR: CountingAllocator = CountingAllocator;
+
+/// The result of `f` and the heap allocations it made on this thread.
+#[cfg(test)]
+pub(crate) fn count_allocations<T>(f: impl FnOnce() -> T) -> (T, usize) {
+    let before = ALLOCATIONS.with(|count| count.get());
+    let result = f();
+    (result, ALLOCATIONS.with(|count| count.get()) - before)
+}
+
+#[test]
+fn test_verify_does_not_allocate() {
+    use crate::HashChain;
+    use sha2::{Sha256, Sha512};
+
+    let mut chain = HashChain::<Sha512>::new(64, 2).unwrap();
+    let batch: Vec<_> = chain.by_ref().take(9).map(|(i, v)| (i, v.to_array())).collect();
+    let (index, value) = batch[8];
+    let anchor = *chain.anchor();
+    assert_eq!(count_allocations(|| verify::<Sha512>(0, &anchor, index, &value)), (true, 0));
+    assert_eq!(count_allocations(|| verify::<Sha512>(0, &anchor, index, &anchor)), (false, 0));
+    assert_eq!(count_allocations(|| verify_batch::<Sha512>(0, &anchor, &batch)), (true, 0));
+
+    let mut personalized = HashChain::<Sha256>::new_personalized(16, 2, b"acme-meterd v2").unwrap();
+    let (index, value) = personalized.nth(4).unwrap();
+    let (value, anchor) = (value.to_array(), *personalized.anchor());
+    assert_eq!(count_allocations(|| verify_personalized::<Sha256>(b"acme-meterd v2", 0, &anchor, index, &value)), (true, 0));
+    let step = PersonalizedStep::<Sha256>::new(b"acme-meterd v2");
+    assert_eq!(count_allocations(|| ChainStep::<Sha256>::walk(&step, &value, 0, index)), (anchor, 0));
+}
@@ -0,0 +1,169 @@
+//! A fixed set of worker threads verifying tokens off the request path.
+//!
+//! A [`Pool`] takes [`ChainToken`]s on a channel, from [`Pool::submit`] or any [`Submitter`]
+//! cloned from it, and hands each one to a worker chosen by its chain id, so the tokens of one
+//! chain are verified one after the other in the order submitted while other chains proceed on
+//! the other workers. Every submission answers on its own single-use channel, the [`Pending`]
+//! returned, which the caller may wait on, poll, or drop.
+
+use crate::registry::{ChainToken, TokenVerifier, VerifyError};
+use std::collections::hash_map::DefaultHasher;
+use std::error::Error;
+use std::fmt::{self, Display};
+use std::hash::{Hash, Hasher};
+use std::sync::mpsc::{self, Receiver, Sender, SyncSender, TryRecvError};
+use std::sync::Arc;
+use std::thread::{self, JoinHandle};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PoolError {
+    /// The token was verified and not accepted.
+    Rejected(VerifyError),
+    /// The worker was gone before it verified the token, e.g. because the verifier panicked.
+    Closed,
+}
+
+impl Display for PoolError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            PoolError::Rejected(e) => write!(f, "token rejected: {}", e),
+            PoolError::Closed => write!(f, "verification pool closed"),
+        }
+    }
+}
+
+impl Error for PoolError {}
+
+struct Job {
+    token: ChainToken,
+    reply: SyncSender<Result<(), VerifyError>>,
+}
+
+/// Worker threads verifying tokens against a shared verifier.
+pub struct Pool {
+    submitter: Submitter,
+    workers: Vec<JoinHandle<()>>,
+}
+
+impl Pool {
+    /// Start `workers` threads, at least one, verifying with `verifier`.
+    pub fn new(verifier: Arc<dyn TokenVerifier>, workers: usize) -> Self {
+        let (senders, workers) = (0..workers.max(1))
+            .map(|_| {
+                let (sender, jobs) = mpsc::channel::<Job>();
+                let verifier = Arc::clone(&verifier);
+                let worker = thread::spawn(move || {
+                    for job in jobs {
+                        // the caller may have stopped waiting
+                        let _ = job.reply.send(verifier.verify_token(&job.token));
+                    }
+                });
+                (sender, worker)
+            })
+            .unzip();
+        Pool { submitter: Submitter { senders: Arc::new(senders) }, workers }
+    }
+
+    pub fn workers(&self) -> usize {
+        self.workers.len()
+    }
+
+    /// Queue `token` behind the tokens submitted earlier for its chain.
+    pub fn submit(&self, token: ChainToken) -> Pending {
+        self.submitter.submit(token)
+    }
+
+    /// A handle submitting to this pool from other threads.
+    pub fn submitter(&self) -> Submitter {
+        self.submitter.clone()
+    }
+
+    /// Verify the tokens queued so far and stop the workers. Waits for every [`Submitter`] to be
+    /// dropped too, since the workers take jobs until then; dropping the pool instead leaves them
+    /// to finish on their own.
+    pub fn shutdown(self) {
+        drop(self.submitter);
+        for worker in self.workers {
+            let _ = worker.join();
+        }
+    }
+}
+
+/// Submits tokens to a [`Pool`], cheap to clone and send to other threads.
+#[derive(Clone)]
+pub struct Submitter {
+    senders: Arc<Vec<Sender<Job>>>,
+}
+
+impl Submitter {
+    /// See [`Pool::submit`].
+    pub fn submit(&self, token: ChainToken) -> Pending {
+        let mut hasher = DefaultHasher::new();
+        token.chain_id.hash(&mut hasher);
+        let shard = (hasher.finish() % self.senders.len() as u64) as usize;
+        let (reply, result) = mpsc::sync_channel(1);
+        // a failed send drops the reply sender, and the result reads as closed
+        let _ = self.senders[shard].send(Job { token, reply });
+        Pending(result)
+    }
+}
+
+/// The answer to one submission.
+pub struct Pending(Receiver<Result<(), VerifyError>>);
+
+impl Pending {
+    /// Block until the token is verified.
+    pub fn wait(self) -> Result<(), PoolError> {
+        match self.0.recv() {
+            Ok(result) => result.map_err(PoolError::Rejected),
+            Err(_) => Err(PoolError::Closed),
+        }
+    }
+
+    /// The result if the token has been verified, without blocking.
+    pub fn try_wait(&self) -> Option<Result<(), PoolError>> {
+        match self.0.try_recv() {
+            Ok(result) => Some(result.map_err(PoolError::Rejected)),
+            Err(TryRecvError::Empty) => None,
+            Err(TryRecvError::Disconnected) => Some(Err(PoolError::Closed)),
+        }
+    }
+}
+
+#[test]
+fn test_pool_orders_chains() {
+    use crate::registry::Registry;
+    use crate::store::MemoryStore;
+    use crate::{ChainId, HashChain};
+    use sha2::Sha256;
+
+    let registry = Registry::<Sha256, _>::new(MemoryStore::new());
+    let mut chains: Vec<_> = (0..8u8)
+        .map(|i| {
+            let chain = HashChain::<Sha256>::new(64, i as u64).unwrap();
+            registry.register(ChainId([i; 16]), *chain.anchor()).unwrap();
+            chain
+        })
+        .collect();
+    let pool = Pool::new(Arc::new(registry), 3);
+    let submitter = pool.submitter();
+
+    // every chain's tokens in order, interleaved across chains and submitters
+    let mut pending = Vec::new();
+    for round in 0..10 {
+        for (i, chain) in chains.iter_mut().enumerate() {
+            let (index, value) = chain.next().unwrap();
+            let token = ChainToken { chain_id: ChainId([i as u8; 16]), index, value: value.as_bytes().to_vec() };
+            let replay = token.clone();
+            pending.push((if round % 2 == 0 { pool.submit(token) } else { submitter.submit(token) }, Ok(())));
+            if round == 9 {
+                pending.push((pool.submit(replay), Err(PoolError::Rejected(VerifyError::Replay))));
+            }
+        }
+    }
+    for (pending, expected) in pending {
+        assert_eq!(pending.wait(), expected);
+    }
+    drop(submitter);
+    pool.shutdown();
+}
@@ -0,0 +1,120 @@
+//! Versioned encodings of the serialized artifacts, so fleets mixing builds, e.g. old sensors
+//! and new collectors, keep talking while the formats evolve.
+//!
+//! Every artifact with a byte encoding implements [`Versioned`]: its versioned encoding is a
+//! version byte followed by the body for that version. Version 0 is the body alone, as written
+//! before the formats were versioned. Each side announces its [`supported_versions`], an empty
+//! list for a peer that predates versioning, and both use the version [`negotiate`] picks, the
+//! newest they share. A newer build writes the negotiated version for an older peer and reads
+//! whatever version its peer wrote, refusing versions it does not know rather than misreading
+//! them.
+//!
+//! So far every version shares one body: version 1 only adds the version byte.
+
+use alloc::vec::Vec;
+use core::error::Error;
+use core::fmt::{self, Display};
+
+/// The unversioned encoding of builds that predate versioning.
+pub const LEGACY: u8 = 0;
+/// The version this build writes when the peer supports it.
+pub const CURRENT: u8 = 1;
+
+/// The versions this build reads and writes, newest first.
+pub fn supported_versions() -> &'static [u8] {
+    &[CURRENT, LEGACY]
+}
+
+/// The newest version in both `ours` and `theirs`, or `None` if they share none. A peer that
+/// announces no versions predates versioning and is taken to speak [`LEGACY`].
+pub fn negotiate(ours: &[u8], theirs: &[u8]) -> Option<u8> {
+    let theirs = if theirs.is_empty() { &[LEGACY][..] } else { theirs };
+    ours.iter().copied().filter(|version| theirs.contains(version)).max()
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum VersionError<E> {
+    /// The version is not one this build supports.
+    Unsupported(u8),
+    /// The encoding has no version byte.
+    Missing,
+    /// The body does not decode.
+    Body(E),
+}
+
+impl<E: Display> Display for VersionError<E> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            VersionError::Unsupported(version) => write!(f, "unsupported format version {}", version),
+            VersionError::Missing => write!(f, "missing format version"),
+            VersionError::Body(error) => write!(f, "{}", error),
+        }
+    }
+}
+
+impl<E: Error> Error for VersionError<E> {}
+
+/// An artifact with a versioned byte encoding.
+pub trait Versioned: Sized {
+    type Error;
+
+    /// The body for `version`, one of [`supported_versions`].
+    fn encode_body(&self, version: u8) -> Vec<u8>;
+
+    /// Decode the body written for `version`, one of [`supported_versions`].
+    fn decode_body(version: u8, body: &[u8]) -> Result<Self, Self::Error>;
+
+    /// The encoding for a peer that negotiated `version`: the version byte and the body, or the
+    /// body alone for [`LEGACY`].
+    fn to_versioned_bytes(&self, version: u8) -> Result<Vec<u8>, VersionError<Self::Error>> {
+        if !supported_versions().contains(&version) {
+            return Err(VersionError::Unsupported(version));
+        }
+        let body = self.encode_body(version);
+        if version == LEGACY {
+            return Ok(body);
+        }
+        let mut bytes = Vec::with_capacity(1 + body.len());
+        bytes.push(version);
+        bytes.extend_from_slice(&body);
+        Ok(bytes)
+    }
+
+    /// Decode what a peer that negotiated `version` wrote. Anything newer than [`LEGACY`] carries
+    /// its own version byte, which must be a supported version.
+    fn from_versioned_bytes(bytes: &[u8], version: u8) -> Result<Self, VersionError<Self::Error>> {
+        if version == LEGACY {
+            return Self::decode_body(LEGACY, bytes).map_err(VersionError::Body);
+        }
+        let (&written, body) = bytes.split_first().ok_or(VersionError::Missing)?;
+        if written == LEGACY || !supported_versions().contains(&written) {
+            return Err(VersionError::Unsupported(written));
+        }
+        Self::decode_body(written, body).map_err(VersionError::Body)
+    }
+}
+
+#[test]
+fn test_negotiate_versions() {
+    assert_eq!(negotiate(supported_versions(), &[3, 1, 0]), Some(CURRENT));
+    assert_eq!(negotiate(supported_versions(), &[]), Some(LEGACY));
+    assert_eq!(negotiate(&[CURRENT], &[]), None);
+    assert_eq!(negotiate(supported_versions(), &[2]), None);
+}
+
+#[test]
+fn test_versioned_chain_state() {
+    use crate::HashChain;
+    use sha2::Sha256;
+
+    let chain = HashChain::<Sha256>::new(16, 3).unwrap();
+    let current = chain.to_versioned_bytes(CURRENT).unwrap();
+    assert_eq!((current[0], &current[1..]), (CURRENT, &chain.export_state()[..]));
+    assert!(HashChain::<Sha256>::from_versioned_bytes(&current, CURRENT).unwrap().eq(chain.clone()));
+
+    // an old sensor writes the bare state, and a collector that negotiated LEGACY reads it
+    let legacy = chain.to_versioned_bytes(negotiate(supported_versions(), &[]).unwrap()).unwrap();
+    assert!(HashChain::<Sha256>::from_versioned_bytes(&legacy, LEGACY).unwrap().eq(chain.clone()));
+    assert!(matches!(HashChain::<Sha256>::from_versioned_bytes(&legacy, CURRENT), Err(VersionError::Unsupported(LEGACY))));
+    assert!(matches!(chain.to_versioned_bytes(2), Err(VersionError::Unsupported(2))));
+}
@@ -0,0 +1,128 @@
+//! JavaScript bindings for the prover side, built with `wasm-bindgen`. Values cross the
+//! boundary as `Uint8Array`s and the chain is SHA-256. A chain's traversal state can be
+//! exported as JSON, e.g. to keep it in `localStorage` between page loads, and imported again.
+
+use crate::value::ChainValue;
+use crate::{verify, HashChain, Pebble};
+use digest::generic_array::GenericArray;
+use serde::{Deserialize, Serialize};
+use sha2::Sha256;
+use std::sync::Arc;
+use wasm_bindgen::prelude::*;
+
+#[derive(Serialize, Deserialize)]
+struct PebbleState {
+    start_incr: u64,
+    dest_incr: u64,
+    position: u64,
+    destination: u64,
+    value: String,
+}
+
+/// The exported state; values are hex encoded.
+#[derive(Serialize, Deserialize)]
+struct ChainState {
+    length: u64,
+    current: u64,
+    anchor: String,
+    pebbles: Vec<PebbleState>,
+}
+
+fn decode_value(hex_value: &str) -> Result<GenericArray<u8, <Sha256 as digest::OutputSizeUser>::OutputSize>, JsError> {
+    let value = ChainValue::<Sha256>::from_hex(hex_value).map_err(|e| JsError::new(&format!("invalid chain value: {}", e)))?;
+    Ok(value.to_array())
+}
+
+#[wasm_bindgen]
+pub struct JsHashChain {
+    chain: HashChain<Sha256>,
+}
+
+#[wasm_bindgen]
+impl JsHashChain {
+    /// Set up a chain of `length` values from `seed`.
+    #[wasm_bindgen(constructor)]
+    pub fn new(length: usize, seed: u64) -> Result<JsHashChain, JsError> {
+        let chain = HashChain::new(length, seed).map_err(|e| JsError::new(&e.to_string()))?;
+        Ok(JsHashChain { chain })
+    }
+
+    pub fn anchor(&self) -> Vec<u8> {
+        self.chain.anchor().to_vec()
+    }
+
+    pub fn position(&self) -> u64 {
+        self.chain.position()
+    }
+
+    pub fn remaining(&self) -> u64 {
+        self.chain.remaining()
+    }
+
+    /// The next value, or `undefined` once the chain is exhausted. Its index is the new
+    /// [`position`](JsHashChain::position).
+    #[allow(clippy::should_implement_trait)]
+    pub fn next(&mut self) -> Option<Vec<u8>> {
+        self.chain.disclose().map(|(_, value)| value.to_vec())
+    }
+
+    /// The traversal state as JSON. It lets anyone compute the remaining values, so store it
+    /// like a secret key.
+    #[wasm_bindgen(js_name = exportState)]
+    pub fn export_state(&self) -> String {
+        let chain = &self.chain;
+        let state = ChainState {
+            length: chain.length,
+            current: chain.current,
+            anchor: hex::encode(chain.anchor),
+            pebbles: chain.pebbles.iter().map(|p| PebbleState {
+                start_incr: p.start_incr,
+                dest_incr: p.dest_incr,
+                position: p.position,
+                destination: p.destination,
+                value: hex::encode(p.value),
+            }).collect(),
+        };
+        serde_json::to_string(&state).expect("state serializes")
+    }
+
+    #[wasm_bindgen(js_name = importState)]
+    pub fn import_state(json: &str) -> Result<JsHashChain, JsError> {
+        let state: ChainState = serde_json::from_str(json).map_err(|e| JsError::new(&e.to_string()))?;
+        if state.current > state.length {
+            return Err(JsError::new("position beyond the chain length"));
+        }
+        let pebbles = state.pebbles.iter().map(|p| Ok(Pebble {
+            start_incr: p.start_incr,
+            dest_incr: p.dest_incr,
+            position: p.position,
+            destination: p.destination,
+            value: decode_value(&p.value)?,
+        })).collect::<Result<Vec<_>, JsError>>()?;
+        let chain = HashChain { length: state.length, current: state.current, anchor: decode_value(&state.anchor)?, pebbles: Arc::new(pebbles.into()), personalization: None, hasher: Sha256::default() };
+        Ok(JsHashChain { chain })
+    }
+}
+
+/// Check `value` at `index` against the value at `known_index`, the anchor being at 0.
+#[wasm_bindgen(js_name = verifyChainValue)]
+pub fn verify_chain_value(known_index: u64, known_value: &[u8], index: u64, value: &[u8]) -> bool {
+    if known_value.len() != 32 || value.len() != 32 {
+        return false;
+    }
+    verify::<Sha256>(known_index, GenericArray::from_slice(known_value), index, GenericArray::from_slice(value))
+}
+
+#[test]
+fn test_js_chain_state_roundtrip() {
+    let mut chain = JsHashChain::new(32, 11).unwrap_or_else(|_| unreachable!());
+    let anchor = chain.anchor();
+    let first = chain.next().unwrap();
+    assert!(verify_chain_value(0, &anchor, 1, &first));
+
+    let mut restored = JsHashChain::import_state(&chain.export_state()).unwrap_or_else(|_| unreachable!());
+    assert_eq!(restored.position(), 1);
+    let expected = chain.next().unwrap();
+    assert_eq!(restored.next(), Some(expected.clone()));
+    assert!(verify_chain_value(1, &first, 2, &expected));
+}
@@ -0,0 +1,185 @@
+//! Replay protection for disclosures arriving over a network that duplicates, delays or
+//! reorders messages.
+//!
+//! A [`ReplayWindow`] keeps the newest accepted position of a chain and, besides it, which of
+//! the indices just below it were accepted. Under [`WindowPolicy::Strict`] only indices above
+//! the newest one are accepted, as the [`Registry`](crate::registry::Registry) does. Under
+//! [`WindowPolicy::Window`] a value delivered late is still accepted, once, as long as its index
+//! lies within the window below the newest one, and a duplicate is refused either way. Either
+//! way a jump more than [`DEFAULT_MAX_GAP`] past the newest index, or as many as
+//! [`ReplayWindow::with_max_gap`] sets, is refused before hashing.
+//!
+//! A late value proves nothing about its sender: anyone who has seen a later value can compute
+//! it. The window only sorts out which late messages to process once, so take late values only
+//! where their messages are authenticated otherwise, e.g. by a MAC checked on arrival.
+
+use crate::verify::DEFAULT_MAX_GAP;
+use crate::{hash_forward, verify, ChainInitError};
+use core::error::Error;
+use core::fmt::{self, Display};
+use digest::{Digest, FixedOutputReset, Output};
+
+/// The widest window a [`ReplayWindow`] tracks.
+pub const MAX_WINDOW: u8 = 64;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WindowPolicy {
+    /// Only indices above the newest accepted one.
+    Strict,
+    /// Also indices this far below the newest accepted one, at most [`MAX_WINDOW`].
+    Window(u8),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WindowError {
+    /// The index was accepted already, or is the anchor's.
+    Replay,
+    /// The index lies below the window, or below the newest index under a strict policy.
+    TooOld,
+    /// The index skips further ahead of the newest accepted index than the window allows.
+    GapTooLarge,
+    /// The value does not verify against the newest accepted one.
+    Mismatch,
+}
+
+impl Display for WindowError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            WindowError::Replay => write!(f, "index already accepted"),
+            WindowError::TooOld => write!(f, "index too far behind the newest accepted index"),
+            WindowError::GapTooLarge => write!(f, "index too far ahead of the newest accepted index"),
+            WindowError::Mismatch => write!(f, "value does not verify against the chain"),
+        }
+    }
+}
+
+impl Error for WindowError {}
+
+/// How an accepted disclosure relates to the ones before it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Delivery {
+    /// The index is the newest so far, this many positions past the one before.
+    Advanced(u64),
+    /// The index lies within the window below the newest one.
+    Late,
+}
+
+pub struct ReplayWindow<H: Digest + FixedOutputReset> {
+    policy: WindowPolicy,
+    /// The newest accepted index and value, the anchor at index 0.
+    newest: (u64, Output<H>),
+    /// Bit `k` is set once index `newest - 1 - k` is accepted.
+    seen: u64,
+    max_gap: u64,
+}
+
+impl<H: Digest + FixedOutputReset> ReplayWindow<H> {
+    pub fn new(anchor: Output<H>, policy: WindowPolicy) -> Result<Self, ChainInitError> {
+        if matches!(policy, WindowPolicy::Window(size) if size > MAX_WINDOW) {
+            return Err(ChainInitError::new("window wider than MAX_WINDOW"));
+        }
+        Ok(ReplayWindow { policy, newest: (0, anchor), seen: 0, max_gap: DEFAULT_MAX_GAP })
+    }
+
+    /// Refuse indices more than `max_gap` past the newest accepted one with
+    /// [`WindowError::GapTooLarge`] before hashing, instead of [`DEFAULT_MAX_GAP`].
+    pub fn with_max_gap(self, max_gap: u64) -> Self {
+        ReplayWindow { max_gap, ..self }
+    }
+
+    pub fn policy(&self) -> WindowPolicy {
+        self.policy
+    }
+
+    /// The newest accepted index.
+    pub fn newest(&self) -> u64 {
+        self.newest.0
+    }
+
+    /// Whether `index` was accepted, as far as the window remembers. Indices below the window
+    /// count as accepted, since they can no longer be.
+    pub fn is_accepted(&self, index: u64) -> bool {
+        match self.newest.0.checked_sub(index) {
+            None => false,
+            Some(0) => true,
+            Some(behind) => behind > self.size() as u64 || self.seen & (1 << (behind - 1)) != 0,
+        }
+    }
+
+    fn size(&self) -> u8 {
+        match self.policy {
+            WindowPolicy::Strict => 0,
+            WindowPolicy::Window(size) => size,
+        }
+    }
+
+    pub fn accept(&mut self, index: u64, value: &Output<H>) -> Result<Delivery, WindowError> {
+        let (newest, newest_value) = &self.newest;
+        if index > *newest {
+            if index - newest > self.max_gap {
+                return Err(WindowError::GapTooLarge);
+            }
+            if !verify::<H>(*newest, newest_value, index, value) {
+                return Err(WindowError::Mismatch);
+            }
+            // the previous newest index, the anchor's included, moves into the window
+            let shift = index - newest;
+            let kept = u32::try_from(shift).ok().and_then(|shift| self.seen.checked_shl(shift)).unwrap_or(0);
+            let previous = u32::try_from(shift - 1).ok().and_then(|shift| 1u64.checked_shl(shift)).unwrap_or(0);
+            self.seen = kept | previous;
+            self.newest = (index, value.clone());
+            return Ok(Delivery::Advanced(shift));
+        }
+        let behind = newest - index;
+        if behind == 0 {
+            return Err(WindowError::Replay);
+        }
+        if behind > self.size() as u64 {
+            return Err(WindowError::TooOld);
+        }
+        let bit = 1 << (behind - 1);
+        if self.seen & bit != 0 {
+            return Err(WindowError::Replay);
+        }
+        if hash_forward::<H>(newest_value, behind) != *value {
+            return Err(WindowError::Mismatch);
+        }
+        self.seen |= bit;
+        Ok(Delivery::Late)
+    }
+}
+
+#[test]
+fn test_replay_window() {
+    use crate::HashChain;
+    use sha2::Sha256;
+
+    let values: Vec<_> = HashChain::<Sha256>::new(128, 1).unwrap().map(|(_, value)| value.to_array()).collect();
+    let anchor = *HashChain::<Sha256>::new(128, 1).unwrap().anchor();
+    let value_at = |index: u64| &values[index as usize - 1];
+
+    let mut strict = ReplayWindow::<Sha256>::new(anchor, WindowPolicy::Strict).unwrap();
+    assert_eq!(strict.accept(2, value_at(2)), Ok(Delivery::Advanced(2)));
+    assert_eq!(strict.accept(1, value_at(1)), Err(WindowError::TooOld));
+    assert_eq!(strict.accept(2, value_at(2)), Err(WindowError::Replay));
+
+    let mut window = ReplayWindow::<Sha256>::new(anchor, WindowPolicy::Window(4)).unwrap();
+    assert_eq!(window.accept(3, value_at(3)), Ok(Delivery::Advanced(3)));
+    assert_eq!(window.accept(0, &anchor), Err(WindowError::Replay));
+    assert_eq!(window.accept(1, value_at(1)), Ok(Delivery::Late));
+    assert_eq!(window.accept(1, value_at(1)), Err(WindowError::Replay));
+    assert_eq!(window.accept(2, value_at(3)), Err(WindowError::Mismatch));
+    assert_eq!(window.accept(6, value_at(6)), Ok(Delivery::Advanced(3)));
+    assert!(window.is_accepted(3) && window.is_accepted(1) && !window.is_accepted(4));
+    assert_eq!(window.accept(1, value_at(1)), Err(WindowError::TooOld));
+    assert_eq!(window.accept(2, value_at(2)), Ok(Delivery::Late));
+    assert_eq!(window.accept(72, value_at(72)), Ok(Delivery::Advanced(66)));
+    assert_eq!(window.accept(71, value_at(71)), Ok(Delivery::Late));
+
+    assert_eq!(window.accept(u64::MAX, value_at(72)), Err(WindowError::GapTooLarge));
+    let mut bounded = ReplayWindow::<Sha256>::new(anchor, WindowPolicy::Strict).unwrap().with_max_gap(8);
+    assert_eq!(bounded.accept(9, value_at(9)), Err(WindowError::GapTooLarge));
+    assert_eq!(bounded.accept(8, value_at(8)), Ok(Delivery::Advanced(8)));
+
+    assert!(ReplayWindow::<Sha256>::new(anchor, WindowPolicy::Window(MAX_WINDOW + 1)).is_err());
+}
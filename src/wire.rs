@@ -0,0 +1,129 @@
+//! Protobuf messages for anchor commitments, disclosures and verification results, generated
+//! with prost from `proto/messages.proto`, so systems in other languages share one wire schema.
+//! Encode and decode them with [`prost::Message`].
+
+use crate::anchor::AnchorCommitment;
+use crate::registry::{ChainToken, VerifyError};
+use crate::ChainId;
+use std::error::Error;
+use std::fmt::{self, Display};
+
+/// The messages generated from the proto definitions.
+pub mod proto {
+    include!(concat!(env!("OUT_DIR"), "/fractal_hash_traversal.wire.v1.rs"));
+}
+
+use proto::Failure;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct WireError {
+    details: String,
+}
+
+impl WireError {
+    fn new(msg: &str) -> WireError {
+        WireError { details: msg.to_string() }
+    }
+}
+
+impl Display for WireError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.details)
+    }
+}
+
+impl Error for WireError {}
+
+impl From<AnchorCommitment> for proto::AnchorCommitment {
+    fn from(commitment: AnchorCommitment) -> Self {
+        let AnchorCommitment { anchor, length, hash, valid_from, valid_until, kill_commitment, personalization, provider } = commitment;
+        proto::AnchorCommitment { anchor, length, hash, valid_from, valid_until, kill_commitment, personalization, provider }
+    }
+}
+
+impl From<proto::AnchorCommitment> for AnchorCommitment {
+    fn from(message: proto::AnchorCommitment) -> Self {
+        let proto::AnchorCommitment { anchor, length, hash, valid_from, valid_until, kill_commitment, personalization, provider } = message;
+        AnchorCommitment { anchor, length, hash, valid_from, valid_until, kill_commitment, personalization, provider }
+    }
+}
+
+impl From<ChainToken> for proto::Disclosure {
+    fn from(token: ChainToken) -> Self {
+        proto::Disclosure { chain_id: token.chain_id.0.to_vec(), index: token.index, value: token.value }
+    }
+}
+
+impl TryFrom<proto::Disclosure> for ChainToken {
+    type Error = WireError;
+
+    fn try_from(message: proto::Disclosure) -> Result<Self, WireError> {
+        let chain_id = message.chain_id.as_slice().try_into().map_err(|_| WireError::new("chain id must be 16 bytes"))?;
+        Ok(ChainToken { chain_id: ChainId(chain_id), index: message.index, value: message.value })
+    }
+}
+
+impl From<Result<u64, VerifyError>> for proto::VerificationResult {
+    fn from(result: Result<u64, VerifyError>) -> Self {
+        let (failure, details) = match result {
+            Ok(advanced) => return proto::VerificationResult { accepted: true, advanced, ..Default::default() },
+            Err(VerifyError::UnknownChain) => (Failure::UnknownChain, String::new()),
+            Err(VerifyError::Replay) => (Failure::Replay, String::new()),
+            Err(VerifyError::NotNext) => (Failure::NotNext, String::new()),
+            Err(VerifyError::GapTooLarge) => (Failure::GapTooLarge, String::new()),
+            Err(VerifyError::ProofRequired) => (Failure::ProofRequired, String::new()),
+            Err(VerifyError::Mismatch) => (Failure::Mismatch, String::new()),
+            Err(VerifyError::Conflict) => (Failure::Conflict, String::new()),
+            Err(VerifyError::AlreadyEnrolled) => (Failure::AlreadyEnrolled, String::new()),
+            Err(VerifyError::Retired) => (Failure::Retired, String::new()),
+            Err(VerifyError::DomainNotAllowed(domain)) => (Failure::DomainNotAllowed, domain),
+            Err(VerifyError::WrongDomain) => (Failure::WrongDomain, String::new()),
+            Err(VerifyError::Store(details)) => (Failure::Store, details),
+        };
+        proto::VerificationResult { accepted: false, advanced: 0, failure: failure.into(), details }
+    }
+}
+
+impl TryFrom<proto::VerificationResult> for Result<u64, VerifyError> {
+    type Error = WireError;
+
+    fn try_from(message: proto::VerificationResult) -> Result<Self, WireError> {
+        if message.accepted {
+            return Ok(Ok(message.advanced));
+        }
+        Ok(Err(match message.failure() {
+            Failure::Unspecified => return Err(WireError::new("rejected without a reason")),
+            Failure::UnknownChain => VerifyError::UnknownChain,
+            Failure::Replay => VerifyError::Replay,
+            Failure::NotNext => VerifyError::NotNext,
+            Failure::GapTooLarge => VerifyError::GapTooLarge,
+            Failure::ProofRequired => VerifyError::ProofRequired,
+            Failure::Mismatch => VerifyError::Mismatch,
+            Failure::Conflict => VerifyError::Conflict,
+            Failure::AlreadyEnrolled => VerifyError::AlreadyEnrolled,
+            Failure::Retired => VerifyError::Retired,
+            Failure::DomainNotAllowed => VerifyError::DomainNotAllowed(message.details),
+            Failure::WrongDomain => VerifyError::WrongDomain,
+            Failure::Store => VerifyError::Store(message.details),
+        }))
+    }
+}
+
+#[test]
+fn test_wire_roundtrip() {
+    use prost::Message;
+
+    let commitment = AnchorCommitment { anchor: vec![1; 32], length: 1024, hash: "sha256".to_string(), valid_from: 10, valid_until: 20, kill_commitment: Some(vec![2; 32]), personalization: Some(b"acme-meterd v2".to_vec()), provider: Some("openssl-fips".to_string()) };
+    let bytes = proto::AnchorCommitment::from(commitment.clone()).encode_to_vec();
+    assert_eq!(AnchorCommitment::from(proto::AnchorCommitment::decode(bytes.as_slice()).unwrap()), commitment);
+
+    let token = ChainToken { chain_id: ChainId([3; 16]), index: 5, value: vec![9; 32] };
+    let bytes = proto::Disclosure::from(token.clone()).encode_to_vec();
+    assert_eq!(ChainToken::try_from(proto::Disclosure::decode(bytes.as_slice()).unwrap()), Ok(token));
+    assert!(ChainToken::try_from(proto::Disclosure { chain_id: vec![3; 4], index: 5, value: vec![] }).is_err());
+
+    for result in [Ok(3), Err(VerifyError::Replay), Err(VerifyError::NotNext), Err(VerifyError::AlreadyEnrolled), Err(VerifyError::Store("disk full".to_string())), Err(VerifyError::DomainNotAllowed("otp".to_string()))] {
+        let bytes = proto::VerificationResult::from(result.clone()).encode_to_vec();
+        assert_eq!(Result::try_from(proto::VerificationResult::decode(bytes.as_slice()).unwrap()), Ok(result));
+    }
+}
@@ -0,0 +1,257 @@
+//! A stateful many-time signature scheme in the style of XMSS (RFC 8391).
+//!
+//! Every leaf of a Merkle tree is the fingerprint of a WOTS+ key derived from the secret seed,
+//! and the root is the public key. A signature is a WOTS+ signature under the next unused leaf
+//! and the leaf's authentication path, which a [`BdsTraverser`] produces in order.
+//!
+//! Signing twice with one leaf breaks the scheme, so the index of the next unused leaf lives in a
+//! [`StateStore`], and it is advanced there with a compare-and-swap before any signature leaves
+//! the signer. A crash can then waste indices but never reuse one, and a second signer working
+//! from the same store fails with [`XmssError::IndexReused`] instead of signing.
+//!
+//! This is not wire compatible with RFC 8391: addresses, PRFs and message hashing are
+//! simplified.
+
+use crate::merkle::{self, AuthPath, BdsTraverser, TreeTraversal};
+use crate::ots::winternitz::{self, MaskedStep, Params};
+use crate::store::StateStore;
+use digest::generic_array::GenericArray;
+use digest::{Digest, OutputSizeUser};
+use std::error::Error;
+use std::fmt::{self, Display};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum XmssError {
+    /// Every leaf has been used.
+    Exhausted,
+    /// The stored index has moved on without us, so this signer's next leaf may have been
+    /// used already.
+    IndexReused,
+    /// The parameters are not supported.
+    BadParams,
+    Store(String),
+}
+
+impl Display for XmssError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            XmssError::Exhausted => write!(f, "all signing indices used"),
+            XmssError::IndexReused => write!(f, "signing index already used by another signer"),
+            XmssError::BadParams => write!(f, "unsupported parameters"),
+            XmssError::Store(details) => write!(f, "state store error: {}", details),
+        }
+    }
+}
+
+impl Error for XmssError {}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct XmssParams {
+    /// The tree height; a key pair makes `2^height` signatures.
+    pub height: u32,
+    pub wots: Params,
+    /// The number of top tree levels the traversal retains, see [`BdsTraverser`].
+    pub bds_k: u32,
+}
+
+pub struct XmssPublicKey<H: OutputSizeUser> {
+    pub params: XmssParams,
+    pub root: GenericArray<u8, H::OutputSize>,
+    pub public_seed: Vec<u8>,
+}
+
+pub struct XmssSignature<H: OutputSizeUser> {
+    pub index: u64,
+    pub wots: winternitz::Signature<H>,
+    pub path: AuthPath<H>,
+}
+
+impl<H: OutputSizeUser> Clone for XmssPublicKey<H> {
+    fn clone(&self) -> Self {
+        XmssPublicKey { params: self.params, root: self.root.clone(), public_seed: self.public_seed.clone() }
+    }
+}
+
+impl<H: OutputSizeUser> Clone for XmssSignature<H> {
+    fn clone(&self) -> Self {
+        XmssSignature { index: self.index, wots: self.wots.clone(), path: self.path.clone() }
+    }
+}
+
+fn wots_seed<H: Digest>(secret_seed: &[u8], index: u64) -> GenericArray<u8, H::OutputSize> {
+    let mut hasher = H::new_with_prefix(b"xmss wots seed");
+    hasher.update((secret_seed.len() as u64).to_be_bytes());
+    hasher.update(secret_seed);
+    hasher.update(index.to_be_bytes());
+    hasher.finalize()
+}
+
+/// The digest a leaf signs: the message bound to the public root, the signing tree's `context`
+/// and the leaf index.
+fn message_digest<H: Digest>(root: &[u8], context: &[u8], index: u64, message: &[u8]) -> GenericArray<u8, H::OutputSize> {
+    let mut hasher = H::new_with_prefix(b"xmss message");
+    hasher.update(root);
+    hasher.update((context.len() as u64).to_be_bytes());
+    hasher.update(context);
+    hasher.update(index.to_be_bytes());
+    hasher.update(message);
+    hasher.finalize()
+}
+
+type LeafFn<H> = Box<dyn FnMut(u64) -> digest::Output<H> + Send>;
+
+/// A single tree of WOTS+ leaves with its traversal, not tied to any persistent state.
+pub(crate) struct Tree<H: Digest> {
+    params: XmssParams,
+    secret_seed: Vec<u8>,
+    step: MaskedStep<H>,
+    traversal: BdsTraverser<H, LeafFn<H>>,
+}
+
+impl<H: Digest + Send + 'static> Tree<H> {
+    /// Generate the tree, computing all `2^height` WOTS+ keys, positioned at leaf `index`.
+    pub(crate) fn new(params: XmssParams, secret_seed: &[u8], public_seed: &[u8], index: u64) -> Result<Self, XmssError> {
+        let step = MaskedStep::<H>::new(params.wots, public_seed);
+        let leaf: LeafFn<H> = {
+            let (step, secret_seed) = (step.clone(), secret_seed.to_vec());
+            Box::new(move |i| {
+                let (_, verifying) = winternitz::generate_with::<H, _>(params.wots, step.clone(), &wots_seed::<H>(&secret_seed, i));
+                merkle::leaf::<H>(&verifying.fingerprint())
+            })
+        };
+        let mut traversal = BdsTraverser::new(params.height, params.bds_k, leaf).ok_or(XmssError::BadParams)?;
+        for _ in 0..index {
+            traversal.next();
+        }
+        Ok(Tree { params, secret_seed: secret_seed.to_vec(), step, traversal })
+    }
+
+    pub(crate) fn root(&self) -> &GenericArray<u8, H::OutputSize> {
+        self.traversal.root()
+    }
+
+    /// The next unused leaf.
+    pub(crate) fn position(&self) -> u64 {
+        self.traversal.position()
+    }
+
+    pub(crate) fn is_exhausted(&self) -> bool {
+        self.position() >= 1 << self.params.height
+    }
+
+    /// Sign `message` with the next leaf. The caller guards against reuse.
+    pub(crate) fn sign(&mut self, public_root: &[u8], context: &[u8], message: &[u8]) -> Result<XmssSignature<H>, XmssError> {
+        let index = self.position();
+        let path = self.traversal.next().ok_or(XmssError::Exhausted)?;
+        let (signing, _) = winternitz::generate_with::<H, _>(self.params.wots, self.step.clone(), &wots_seed::<H>(&self.secret_seed, index));
+        let wots = signing.sign(&message_digest::<H>(public_root, context, index, message));
+        Ok(XmssSignature { index, wots, path })
+    }
+}
+
+/// The root of the tree `signature` was made in, if it is well formed. The signature is valid
+/// exactly when this is the expected root.
+pub(crate) fn signed_root<H: Digest>(params: XmssParams, step: &MaskedStep<H>, public_root: &[u8], context: &[u8], message: &[u8], signature: &XmssSignature<H>) -> Option<GenericArray<u8, H::OutputSize>> {
+    if signature.path.index != signature.index || signature.path.height() != params.height || signature.index >> params.height != 0 {
+        return None;
+    }
+    let digest = message_digest::<H>(public_root, context, signature.index, message);
+    let ends = winternitz::recover(params.wots, step, &digest, &signature.wots)?;
+    Some(signature.path.root(&merkle::leaf::<H>(&winternitz::fingerprint::<H>(&ends))))
+}
+
+/// The signing side, keeping its next index under `key` in `store`.
+pub struct XmssSigner<H: Digest, S: StateStore<State = u64>> {
+    tree: Tree<H>,
+    public_key: XmssPublicKey<H>,
+    store: S,
+    key: S::Key,
+}
+
+/// Load the next index under `key`, storing 0 if there is none yet.
+pub(crate) fn load_index<S: StateStore<State = u64>>(store: &S, key: &S::Key) -> Result<u64, XmssError> {
+    match store.load(key).map_err(|e| XmssError::Store(e.to_string()))? {
+        Some(index) => Ok(index),
+        None => {
+            store.compare_and_swap(key, None, 0).map_err(|e| XmssError::Store(e.to_string()))?;
+            Ok(0)
+        }
+    }
+}
+
+/// Move the stored index from `index` to `index + 1`, failing if someone else got there first.
+pub(crate) fn reserve_index<S: StateStore<State = u64>>(store: &S, key: &S::Key, index: u64) -> Result<(), XmssError> {
+    match store.compare_and_swap(key, Some(&index), index + 1) {
+        Ok(true) => Ok(()),
+        Ok(false) => Err(XmssError::IndexReused),
+        Err(e) => Err(XmssError::Store(e.to_string())),
+    }
+}
+
+impl<H, S> XmssSigner<H, S>
+where
+    H: Digest + Send + 'static,
+    S: StateStore<State = u64>,
+{
+    /// Set up the key pair for the seeds and resume from the index in `store`, starting at 0
+    /// if there is none. Key generation computes all `2^height` WOTS+ keys.
+    pub fn new(params: XmssParams, secret_seed: &[u8], public_seed: &[u8], store: S, key: S::Key) -> Result<Self, XmssError> {
+        let index = load_index(&store, &key)?;
+        let tree = Tree::new(params, secret_seed, public_seed, index)?;
+        let public_key = XmssPublicKey { params, root: tree.root().clone(), public_seed: public_seed.to_vec() };
+        Ok(XmssSigner { tree, public_key, store, key })
+    }
+
+    pub fn public_key(&self) -> &XmssPublicKey<H> {
+        &self.public_key
+    }
+
+    /// The number of signatures left.
+    pub fn remaining(&self) -> u64 {
+        (1 << self.public_key.params.height) - self.tree.position()
+    }
+
+    pub fn sign(&mut self, message: &[u8]) -> Result<XmssSignature<H>, XmssError> {
+        if self.tree.is_exhausted() {
+            return Err(XmssError::Exhausted);
+        }
+        reserve_index(&self.store, &self.key, self.tree.position())?;
+        self.tree.sign(&self.public_key.root, &[], message)
+    }
+}
+
+impl<H: Digest> XmssPublicKey<H> {
+    pub fn verify(&self, message: &[u8], signature: &XmssSignature<H>) -> bool {
+        let step = MaskedStep::<H>::new(self.params.wots, &self.public_seed);
+        signed_root(self.params, &step, &self.root, &[], message, signature).is_some_and(|root| root == self.root)
+    }
+}
+
+#[test]
+fn test_xmss_sign_verify_and_state() {
+    use crate::store::MemoryStore;
+    use sha2::Sha256;
+    use std::sync::Arc;
+
+    let params = XmssParams { height: 3, wots: Params::new(16).unwrap(), bds_k: 1 };
+    let store = Arc::new(MemoryStore::<&str, u64>::new());
+    let mut signer = XmssSigner::<Sha256, _>::new(params, b"secret", b"public", store.clone(), "key").unwrap();
+    let first = signer.sign(b"first").unwrap();
+    let second = signer.sign(b"second").unwrap();
+    assert!(signer.public_key().verify(b"first", &first));
+    assert!(signer.public_key().verify(b"second", &second));
+    assert!(!signer.public_key().verify(b"first", &second));
+    assert_eq!(store.load(&"key").unwrap(), Some(2));
+
+    // a signer restored from the store carries on; the old one may not sign any more
+    let mut restored = XmssSigner::<Sha256, _>::new(params, b"secret", b"public", store.clone(), "key").unwrap();
+    let third = restored.sign(b"third").unwrap();
+    assert_eq!(third.index, 2);
+    assert!(restored.public_key().verify(b"third", &third));
+    assert_eq!(signer.sign(b"forked").err(), Some(XmssError::IndexReused));
+
+    while restored.remaining() > 0 {
+        restored.sign(b"more").unwrap();
+    }
+    assert_eq!(restored.sign(b"too many").err(), Some(XmssError::Exhausted));
+}